@@ -0,0 +1,119 @@
+//! Apple Archive (.aar, and the older YAA .yaa) entry streams, after any pbz*
+//! compression has been removed: each entry is a "AA01"/"YAA1" header of typed fields
+//! (3-char key plus a type letter) followed by the blobs its fields declare.
+
+const HEADER_MIN: usize = 6;
+
+pub const AA_TYPE_FILE: u8 = b'F';
+pub const AA_TYPE_DIR: u8 = b'D';
+pub const AA_TYPE_SYMLINK: u8 = b'L';
+
+pub struct Entry<'a> {
+    pub kind: u8,        // TYP value: AA_TYPE_*, or another type letter
+    pub path: String,    // PAT, relative, "" for the archive root
+    pub link: String,    // LNK symlink target
+    pub mode: u32,       // MOD permission bits
+    pub uid: u32,
+    pub gid: u32,
+    pub mtime: i64,      // MTM, unix seconds
+    pub offset: usize,   // of the DAT blob within the archive
+    pub data: &'a [u8],  // DAT blob
+}
+
+/// True if `d` starts with an Apple Archive entry header.
+pub fn is_archive(d: &[u8]) -> bool {
+    d.starts_with(b"AA01") || d.starts_with(b"YAA1")
+}
+
+fn uint(v: &[u8]) -> u64 {
+    v.iter().rev().fold(0, |n, &b| n << 8 | b as u64)
+}
+
+/// Parse one header at `p`; returns the entry and the offset just past its blobs.
+fn entry(d: &[u8], p: usize) -> Option<(Entry<'_>, usize)> {
+    let h = d.get(p..)?;
+    if !is_archive(h) { return None; }
+    let size = u16::from_le_bytes([*h.get(4)?, *h.get(5)?]) as usize;
+    let fields = h.get(HEADER_MIN..size)?;
+    let mut e = Entry { kind: 0, path: String::new(), link: String::new(), mode: 0, uid: 0, gid: 0, mtime: 0, offset: 0, data: &[] };
+    let (mut blob_at, mut q) = (p + size, 0);
+    while q < fields.len() {
+        let key = fields.get(q..q + 3)?;
+        let ty = *fields.get(q + 3)?;
+        q += 4;
+        let width = match ty {
+            b'*' => 0,
+            b'1' | b'2' | b'4' | b'8' => (ty - b'0') as usize,
+            b'A' => 2,
+            b'B' | b'F' => 4,
+            b'C' | b'S' => 8,
+            b'T' => 12,
+            b'G' => 20,
+            b'H' => 32,
+            b'I' => 48,
+            b'J' => 64,
+            b'P' => 2 + u16::from_le_bytes([*fields.get(q)?, *fields.get(q + 1)?]) as usize,
+            _ => return None,
+        };
+        let v = fields.get(q..q + width)?;
+        q += width;
+        match (key, ty) {
+            (b"TYP", b'1'..=b'8') => e.kind = uint(v) as u8,
+            (b"MOD", b'1'..=b'8') => e.mode = uint(v) as u32,
+            (b"UID", b'1'..=b'8') => e.uid = uint(v) as u32,
+            (b"GID", b'1'..=b'8') => e.gid = uint(v) as u32,
+            (b"MTM", b'S' | b'T') => e.mtime = uint(&v[..8]) as i64,
+            (b"PAT", b'P') => e.path = String::from_utf8_lossy(&v[2..]).into_owned(),
+            (b"LNK", b'P') => e.link = String::from_utf8_lossy(&v[2..]).into_owned(),
+            _ => {}
+        }
+        if let b'A'..=b'C' = ty {
+            let len = usize::try_from(uint(v)).ok()?;
+            let blob = d.get(blob_at..blob_at.checked_add(len)?)?;
+            if key == b"DAT" { (e.offset, e.data) = (blob_at, blob); }
+            blob_at += len;
+        }
+    }
+    Some((e, blob_at))
+}
+
+/// Entries in order. Stops quietly at the first malformed or truncated header.
+pub fn entries(d: &[u8]) -> Vec<Entry<'_>> {
+    let mut out = Vec::new();
+    let mut p = 0;
+    while p < d.len() {
+        let Some((e, next)) = entry(d, p) else { break };
+        out.push(e);
+        p = next;
+    }
+    out
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    /// One AA01 entry with TYP, PAT, MOD, MTM and, for files, a DAT blob.
+    pub fn aa_entry(kind: u8, path: &str, data: &[u8]) -> Vec<u8> {
+        let mut f = [b"TYP1".as_slice(), &[kind], b"PATP", &(path.len() as u16).to_le_bytes(), path.as_bytes(),
+                     b"MOD2", &0o644u16.to_le_bytes(), b"MTMT", &1_700_000_000u64.to_le_bytes(), &[0; 4]].concat();
+        if kind == AA_TYPE_FILE {
+            f.extend_from_slice(b"DATB");
+            f.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        }
+        [b"AA01".as_slice(), &((HEADER_MIN + f.len()) as u16).to_le_bytes(), &f, data].concat()
+    }
+
+    #[test]
+    fn entries_and_blobs() {
+        let d = [aa_entry(AA_TYPE_DIR, "", b""), aa_entry(AA_TYPE_FILE, "bin/tool", b"\xcf\xfa\xed\xfe")].concat();
+        let e = entries(&d);
+        assert_eq!(e.len(), 2);
+        assert_eq!((e[0].kind, e[0].path.as_str(), e[0].data.len()), (AA_TYPE_DIR, "", 0));
+        assert_eq!((e[1].kind, e[1].path.as_str(), e[1].mode, e[1].mtime), (AA_TYPE_FILE, "bin/tool", 0o644, 1_700_000_000));
+        assert_eq!((e[1].data, &d[e[1].offset..][..4]), (&b"\xcf\xfa\xed\xfe"[..], &b"\xcf\xfa\xed\xfe"[..]));
+        // Truncated blob ends the listing
+        assert_eq!(entries(&d[..d.len() - 1]).len(), 1);
+        assert!(entries(b"AA01\x0a\0XYZQ").is_empty());
+    }
+}
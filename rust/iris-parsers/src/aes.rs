@@ -0,0 +1,181 @@
+//! AES-128 (FIPS 197) encryption and AES-GCM (NIST SP 800-38D) decryption, for
+//! protocols whose keys are public by design, such as QUIC Initial packets. Table
+//! lookups make this unsuitable for secrets, so nothing here is constant-time.
+
+const SBOX: [u8; 256] = [
+    0x63, 0x7c, 0x77, 0x7b, 0xf2, 0x6b, 0x6f, 0xc5, 0x30, 0x01, 0x67, 0x2b, 0xfe, 0xd7, 0xab, 0x76,
+    0xca, 0x82, 0xc9, 0x7d, 0xfa, 0x59, 0x47, 0xf0, 0xad, 0xd4, 0xa2, 0xaf, 0x9c, 0xa4, 0x72, 0xc0,
+    0xb7, 0xfd, 0x93, 0x26, 0x36, 0x3f, 0xf7, 0xcc, 0x34, 0xa5, 0xe5, 0xf1, 0x71, 0xd8, 0x31, 0x15,
+    0x04, 0xc7, 0x23, 0xc3, 0x18, 0x96, 0x05, 0x9a, 0x07, 0x12, 0x80, 0xe2, 0xeb, 0x27, 0xb2, 0x75,
+    0x09, 0x83, 0x2c, 0x1a, 0x1b, 0x6e, 0x5a, 0xa0, 0x52, 0x3b, 0xd6, 0xb3, 0x29, 0xe3, 0x2f, 0x84,
+    0x53, 0xd1, 0x00, 0xed, 0x20, 0xfc, 0xb1, 0x5b, 0x6a, 0xcb, 0xbe, 0x39, 0x4a, 0x4c, 0x58, 0xcf,
+    0xd0, 0xef, 0xaa, 0xfb, 0x43, 0x4d, 0x33, 0x85, 0x45, 0xf9, 0x02, 0x7f, 0x50, 0x3c, 0x9f, 0xa8,
+    0x51, 0xa3, 0x40, 0x8f, 0x92, 0x9d, 0x38, 0xf5, 0xbc, 0xb6, 0xda, 0x21, 0x10, 0xff, 0xf3, 0xd2,
+    0xcd, 0x0c, 0x13, 0xec, 0x5f, 0x97, 0x44, 0x17, 0xc4, 0xa7, 0x7e, 0x3d, 0x64, 0x5d, 0x19, 0x73,
+    0x60, 0x81, 0x4f, 0xdc, 0x22, 0x2a, 0x90, 0x88, 0x46, 0xee, 0xb8, 0x14, 0xde, 0x5e, 0x0b, 0xdb,
+    0xe0, 0x32, 0x3a, 0x0a, 0x49, 0x06, 0x24, 0x5c, 0xc2, 0xd3, 0xac, 0x62, 0x91, 0x95, 0xe4, 0x79,
+    0xe7, 0xc8, 0x37, 0x6d, 0x8d, 0xd5, 0x4e, 0xa9, 0x6c, 0x56, 0xf4, 0xea, 0x65, 0x7a, 0xae, 0x08,
+    0xba, 0x78, 0x25, 0x2e, 0x1c, 0xa6, 0xb4, 0xc6, 0xe8, 0xdd, 0x74, 0x1f, 0x4b, 0xbd, 0x8b, 0x8a,
+    0x70, 0x3e, 0xb5, 0x66, 0x48, 0x03, 0xf6, 0x0e, 0x61, 0x35, 0x57, 0xb9, 0x86, 0xc1, 0x1d, 0x9e,
+    0xe1, 0xf8, 0x98, 0x11, 0x69, 0xd9, 0x8e, 0x94, 0x9b, 0x1e, 0x87, 0xe9, 0xce, 0x55, 0x28, 0xdf,
+    0x8c, 0xa1, 0x89, 0x0d, 0xbf, 0xe6, 0x42, 0x68, 0x41, 0x99, 0x2d, 0x0f, 0xb0, 0x54, 0xbb, 0x16,
+];
+
+fn xtime(b: u8) -> u8 {
+    (b << 1) ^ if b & 0x80 != 0 { 0x1b } else { 0 }
+}
+
+/// An expanded AES-128 key. Bytes are column-major, as in FIPS 197.
+pub struct Aes128 {
+    round_keys: [[u8; 16]; 11],
+}
+
+impl Aes128 {
+    pub fn new(key: &[u8; 16]) -> Self {
+        let mut rk = [[0u8; 16]; 11];
+        rk[0] = *key;
+        let mut rcon = 1u8;
+        for r in 1..11 {
+            let prev = rk[r - 1];
+            let mut t = [SBOX[prev[13] as usize] ^ rcon, SBOX[prev[14] as usize], SBOX[prev[15] as usize], SBOX[prev[12] as usize]];
+            for w in 0..4 {
+                for i in 0..4 { t[i] ^= prev[4 * w + i]; }
+                rk[r][4 * w..4 * w + 4].copy_from_slice(&t);
+            }
+            rcon = xtime(rcon);
+        }
+        Aes128 { round_keys: rk }
+    }
+
+    pub fn encrypt_block(&self, b: &mut [u8; 16]) {
+        let add = |b: &mut [u8; 16], k: &[u8; 16]| b.iter_mut().zip(k).for_each(|(x, k)| *x ^= k);
+        add(b, &self.round_keys[0]);
+        for r in 1..11 {
+            let s = b.map(|x| SBOX[x as usize]);
+            // ShiftRows: row i of column c comes from column c + i
+            for c in 0..4 {
+                for i in 0..4 { b[4 * c + i] = s[4 * ((c + i) % 4) + i]; }
+            }
+            if r < 10 {
+                for col in b.chunks_exact_mut(4) {
+                    let [a0, a1, a2, a3] = [col[0], col[1], col[2], col[3]];
+                    let all = a0 ^ a1 ^ a2 ^ a3;
+                    col[0] ^= all ^ xtime(a0 ^ a1);
+                    col[1] ^= all ^ xtime(a1 ^ a2);
+                    col[2] ^= all ^ xtime(a2 ^ a3);
+                    col[3] ^= all ^ xtime(a3 ^ a0);
+                }
+            }
+            add(b, &self.round_keys[r]);
+        }
+    }
+}
+
+/// Multiplication in GCM's GF(2^128), bit-reflected as the standard specifies.
+fn gf_mul(x: u128, y: u128) -> u128 {
+    let (mut z, mut v) = (0u128, y);
+    for i in 0..128 {
+        if x >> (127 - i) & 1 == 1 { z ^= v; }
+        v = if v & 1 == 1 { (v >> 1) ^ (0xe1 << 120) } else { v >> 1 };
+    }
+    z
+}
+
+fn ghash(h: u128, aad: &[u8], ct: &[u8]) -> u128 {
+    let mut y = 0u128;
+    for data in [aad, ct] {
+        for chunk in data.chunks(16) {
+            let mut block = [0u8; 16];
+            block[..chunk.len()].copy_from_slice(chunk);
+            y = gf_mul(y ^ u128::from_be_bytes(block), h);
+        }
+    }
+    let lens = ((aad.len() as u128 * 8) << 64) | (ct.len() as u128 * 8);
+    gf_mul(y ^ lens, h)
+}
+
+/// CTR mode from the block after `j0`, in place.
+fn ctr(aes: &Aes128, j0: &[u8; 16], data: &mut [u8]) {
+    let mut counter = *j0;
+    for chunk in data.chunks_mut(16) {
+        let n = u32::from_be_bytes([counter[12], counter[13], counter[14], counter[15]]).wrapping_add(1);
+        counter[12..].copy_from_slice(&n.to_be_bytes());
+        let mut ks = counter;
+        aes.encrypt_block(&mut ks);
+        chunk.iter_mut().zip(ks).for_each(|(b, k)| *b ^= k);
+    }
+}
+
+fn tag(aes: &Aes128, j0: &[u8; 16], aad: &[u8], ct: &[u8]) -> [u8; 16] {
+    let mut h = [0u8; 16];
+    aes.encrypt_block(&mut h);
+    let mut ek = *j0;
+    aes.encrypt_block(&mut ek);
+    (ghash(u128::from_be_bytes(h), aad, ct) ^ u128::from_be_bytes(ek)).to_be_bytes()
+}
+
+fn j0(iv: &[u8; 12]) -> [u8; 16] {
+    let mut j = [0u8; 16];
+    j[..12].copy_from_slice(iv);
+    j[15] = 1;
+    j
+}
+
+/// Decrypt `data` (ciphertext followed by the 16-byte tag) with a 96-bit nonce.
+/// None if the tag does not verify.
+pub fn gcm_open(aes: &Aes128, iv: &[u8; 12], aad: &[u8], data: &[u8]) -> Option<Vec<u8>> {
+    let (ct, expected) = data.split_at(data.len().checked_sub(16)?);
+    let j0 = j0(iv);
+    if tag(aes, &j0, aad, ct) != expected { return None; }
+    let mut pt = ct.to_vec();
+    ctr(aes, &j0, &mut pt);
+    Some(pt)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::hash::to_hex;
+
+    /// GCM encryption, for building test packets.
+    pub fn gcm_seal(aes: &Aes128, iv: &[u8; 12], aad: &[u8], pt: &[u8]) -> Vec<u8> {
+        let j0 = j0(iv);
+        let mut ct = pt.to_vec();
+        ctr(aes, &j0, &mut ct);
+        let t = tag(aes, &j0, aad, &ct);
+        [ct, t.to_vec()].concat()
+    }
+
+    fn unhex(s: &str) -> Vec<u8> {
+        (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap()).collect()
+    }
+
+    #[test]
+    fn fips197_block() {
+        let aes = Aes128::new(&std::array::from_fn(|i| i as u8));
+        let mut b: [u8; 16] = std::array::from_fn(|i| (i as u8) << 4 | i as u8);
+        aes.encrypt_block(&mut b);
+        assert_eq!(to_hex(&b), "69c4e0d86a7b0430d8cdb78070b4c55a");
+    }
+
+    #[test]
+    fn gcm_test_vectors() {
+        // SP 800-38D test cases 2 and 4
+        let aes = Aes128::new(&[0; 16]);
+        let sealed = gcm_seal(&aes, &[0; 12], &[], &[0; 16]);
+        assert_eq!(to_hex(&sealed), "0388dace60b6a392f328c2b971b2fe78ab6e47d42cec13bdf53a67b21257bddf");
+
+        let aes = Aes128::new(&unhex("feffe9928665731c6d6a8f9467308308").try_into().unwrap());
+        let iv: [u8; 12] = unhex("cafebabefacedbaddecaf888").try_into().unwrap();
+        let pt = unhex("d9313225f88406e5a55909c5aff5269a86a7a9531534f7da2e4c303d8a318a721c3c0c95956809532fcf0e2449a6b525b16aedf5aa0de657ba637b39");
+        let aad = unhex("feedfacedeadbeeffeedfacedeadbeefabaddad2");
+        let sealed = gcm_seal(&aes, &iv, &aad, &pt);
+        assert_eq!(to_hex(&sealed[pt.len()..]), "5bc94fbc3221a5db94fae95ae7121a47");
+        assert_eq!(gcm_open(&aes, &iv, &aad, &sealed).unwrap(), pt);
+
+        let mut forged = sealed.clone();
+        forged[3] ^= 1;
+        assert!(gcm_open(&aes, &iv, &aad, &forged).is_none());
+        assert!(gcm_open(&aes, &iv, &aad, &sealed[..15]).is_none());
+    }
+}
@@ -0,0 +1,256 @@
+//! tar, cpio and Apple Archive listing (optionally gzip, pbzx or pbz* compressed), with
+//! an optional callback that receives each regular file's contents in memory for
+//! hashing or nested parsing.
+
+use crate::ffi::{alloc_array, free_array, free_cstr, to_cstr};
+use crate::inflate::gzip_decompress;
+use crate::zip::traversal;
+use crate::{aar, cpio, payload, tar};
+use std::ffi::{c_char, c_void};
+
+const MAX_DECOMPRESSED: usize = 1 << 30;
+const S_IFMT: u32 = 0o170000;
+
+pub const ARCHIVE_TAR_V7: u8 = 1;
+pub const ARCHIVE_TAR_USTAR: u8 = 2;
+pub const ARCHIVE_TAR_PAX: u8 = 3;
+pub const ARCHIVE_TAR_GNU: u8 = 4;
+pub const ARCHIVE_CPIO_ODC: u8 = 5;
+pub const ARCHIVE_CPIO_NEWC: u8 = 6;
+pub const ARCHIVE_APPLE_ARCHIVE: u8 = 7;
+
+pub const ARCHIVE_ENTRY_FILE: u8 = 1;
+pub const ARCHIVE_ENTRY_DIR: u8 = 2;
+pub const ARCHIVE_ENTRY_SYMLINK: u8 = 3;
+pub const ARCHIVE_ENTRY_HARDLINK: u8 = 4;
+pub const ARCHIVE_ENTRY_OTHER: u8 = 5;
+
+#[repr(C)]
+pub struct IrisArchiveEntry {
+    pub name: *mut c_char,
+    pub kind: u8,                  // ARCHIVE_ENTRY_*
+    pub mode: u32,                 // permission bits (and file type bits for cpio)
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u64,
+    pub mtime: i64,                // unix seconds
+    pub link_target: *mut c_char,  // symlink / hard link target, "" otherwise
+    pub data_offset: u64,          // within the (decompressed) archive
+    pub path_traversal: bool,      // absolute path or ".." component
+}
+
+#[repr(C)]
+pub struct IrisArchiveInfo {
+    pub format: u8,                // ARCHIVE_TAR_* / ARCHIVE_CPIO_* / ARCHIVE_APPLE_ARCHIVE
+    pub gzip: bool,                // archive was gzip-compressed
+    pub payload: u8,               // PAYLOAD_* stream the archive was wrapped in, 0 if none
+    pub entries: *mut IrisArchiveEntry,
+    pub entry_count: usize,
+    pub traversal_count: usize,
+}
+
+/// Called once per regular file with its contents; return nonzero to stop listing.
+pub type IrisArchiveCallback = Option<extern "C" fn(ctx: *mut c_void, entry: *const IrisArchiveEntry, data: *const u8, len: usize) -> i32>;
+
+struct Entry<'a> {
+    name: String,
+    kind: u8,
+    mode: u32,
+    uid: u32,
+    gid: u32,
+    mtime: i64,
+    link: String,
+    offset: usize,
+    data: &'a [u8],
+}
+
+fn from_tar(e: tar::Entry) -> Entry {
+    let kind = match e.typeflag {
+        b'0' | b'7' => ARCHIVE_ENTRY_FILE,
+        b'1' => ARCHIVE_ENTRY_HARDLINK,
+        b'2' => ARCHIVE_ENTRY_SYMLINK,
+        b'5' => ARCHIVE_ENTRY_DIR,
+        _ => ARCHIVE_ENTRY_OTHER,
+    };
+    Entry { name: e.name, kind, mode: e.mode, uid: e.uid, gid: e.gid, mtime: e.mtime, link: e.link, offset: e.offset, data: e.data }
+}
+
+fn from_cpio(e: cpio::Entry) -> Entry {
+    let kind = match e.mode & S_IFMT {
+        0o100000 => ARCHIVE_ENTRY_FILE,
+        0o040000 => ARCHIVE_ENTRY_DIR,
+        0o120000 => ARCHIVE_ENTRY_SYMLINK,
+        _ => ARCHIVE_ENTRY_OTHER,
+    };
+    let link = if kind == ARCHIVE_ENTRY_SYMLINK { String::from_utf8_lossy(e.data).into_owned() } else { String::new() };
+    Entry { name: e.name, kind, mode: e.mode, uid: e.uid, gid: e.gid, mtime: e.mtime, link, offset: e.offset, data: e.data }
+}
+
+fn from_aar(e: aar::Entry) -> Entry {
+    let kind = match e.kind {
+        aar::AA_TYPE_FILE => ARCHIVE_ENTRY_FILE,
+        aar::AA_TYPE_DIR => ARCHIVE_ENTRY_DIR,
+        aar::AA_TYPE_SYMLINK => ARCHIVE_ENTRY_SYMLINK,
+        _ => ARCHIVE_ENTRY_OTHER,
+    };
+    Entry { name: e.path, kind, mode: e.mode, uid: e.uid, gid: e.gid, mtime: e.mtime, link: e.link, offset: e.offset, data: e.data }
+}
+
+fn free_entry(e: &IrisArchiveEntry) {
+    free_cstr(e.name);
+    free_cstr(e.link_target);
+}
+
+// --- FFI entry points ---
+
+/// List a tar (v7/ustar/pax/GNU), cpio (odc/newc) or Apple Archive held in memory,
+/// first gunzipping it or unwrapping a pbzx / Apple Archive compressed stream if needed. When `callback` is non-null it is invoked for each regular file
+/// with its contents, which stay valid only for the duration of the call; a nonzero
+/// return stops the listing early (entries seen so far are still returned).
+/// Returns 0=ok, -2=arg error or corrupt/oversized compressed data, -3=not a supported archive.
+/// Free with iris_archive_free.
+#[no_mangle]
+pub extern "C" fn iris_archive_list(data: *const u8, len: usize, callback: IrisArchiveCallback, ctx: *mut c_void, out: *mut IrisArchiveInfo) -> i32 {
+    if data.is_null() || out.is_null() { return -2; }
+    let raw = unsafe { std::slice::from_raw_parts(data, len) };
+    let gzip = raw.starts_with(&[0x1f, 0x8b]);
+    let payload = payload::format(raw).unwrap_or(0);
+    let inflated;
+    let d = if gzip {
+        let Some(x) = gzip_decompress(raw, MAX_DECOMPRESSED) else { return -2 };
+        inflated = x;
+        &inflated[..]
+    } else if payload != 0 {
+        let Ok(x) = payload::decompress(raw, MAX_DECOMPRESSED) else { return -2 };
+        inflated = x;
+        &inflated[..]
+    } else {
+        raw
+    };
+    let (format, entries): (u8, Vec<Entry>) = if let Some(f) = cpio::format(d) {
+        (if f == cpio::CPIO_ODC { ARCHIVE_CPIO_ODC } else { ARCHIVE_CPIO_NEWC }, cpio::entries(d).into_iter().map(from_cpio).collect())
+    } else if tar::is_header(d) {
+        let f = match tar::format(d) {
+            tar::TAR_GNU => ARCHIVE_TAR_GNU,
+            tar::TAR_PAX => ARCHIVE_TAR_PAX,
+            tar::TAR_USTAR => ARCHIVE_TAR_USTAR,
+            _ => ARCHIVE_TAR_V7,
+        };
+        (f, tar::entries(d).into_iter().map(from_tar).collect())
+    } else if aar::is_archive(d) {
+        (ARCHIVE_APPLE_ARCHIVE, aar::entries(d).into_iter().map(from_aar).collect())
+    } else {
+        return -3;
+    };
+
+    let mut listed = Vec::with_capacity(entries.len());
+    let mut traversal_count = 0;
+    for e in entries {
+        let path_traversal = traversal(&e.name);
+        traversal_count += path_traversal as usize;
+        let size = if e.kind == ARCHIVE_ENTRY_FILE { e.data.len() as u64 } else { 0 };
+        let entry = IrisArchiveEntry {
+            name: to_cstr(&e.name),
+            kind: e.kind,
+            mode: e.mode, uid: e.uid, gid: e.gid,
+            size,
+            mtime: e.mtime,
+            link_target: to_cstr(&e.link),
+            data_offset: e.offset as u64,
+            path_traversal,
+        };
+        let stop = match callback {
+            Some(cb) if e.kind == ARCHIVE_ENTRY_FILE => cb(ctx, &entry, e.data.as_ptr(), e.data.len()) != 0,
+            _ => false,
+        };
+        listed.push(entry);
+        if stop { break; }
+    }
+    let (entries, entry_count) = alloc_array(listed);
+    unsafe { out.write(IrisArchiveInfo { format, gzip, payload, entries, entry_count, traversal_count }); }
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn iris_archive_free(info: *mut IrisArchiveInfo) {
+    if info.is_null() { return; }
+    let info = unsafe { &*info };
+    for i in 0..info.entry_count { free_entry(unsafe { &*info.entries.add(i) }); }
+    free_array(info.entries, info.entry_count);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aar::tests::aa_entry;
+    use crate::cpio::tests::odc;
+    use crate::hash::{sha256_digest, to_hex};
+    use crate::inflate::tests::gzip_stored;
+    use crate::payload::tests::pbz;
+    use crate::tar::tests::{end, member};
+
+    extern "C" fn hash_files(ctx: *mut c_void, entry: *const IrisArchiveEntry, data: *const u8, len: usize) -> i32 {
+        let seen = unsafe { &mut *(ctx as *mut Vec<(String, String)>) };
+        let name = unsafe { std::ffi::CStr::from_ptr((*entry).name) }.to_string_lossy().into_owned();
+        seen.push((name, to_hex(&sha256_digest(unsafe { std::slice::from_raw_parts(data, len) }))));
+        (seen.len() == 2) as i32
+    }
+
+    fn list(d: &[u8], callback: IrisArchiveCallback, seen: &mut Vec<(String, String)>) -> IrisArchiveInfo {
+        let mut out = std::mem::MaybeUninit::<IrisArchiveInfo>::uninit();
+        assert_eq!(iris_archive_list(d.as_ptr(), d.len(), callback, seen as *mut _ as *mut c_void, out.as_mut_ptr()), 0);
+        unsafe { out.assume_init() }
+    }
+
+    #[test]
+    fn tar_and_cpio() {
+        let t = [
+            member("app/", b'5', "", b""),
+            member("app/run.sh", b'0', "", b"#!/bin/sh\n"),
+            member("../../etc/periodic/daily/x", b'0', "", b"evil"),
+            member("app/third", b'0', "", b"never hashed"),
+            end(),
+        ].concat();
+        let mut seen = Vec::new();
+        let mut info = list(&gzip_stored(&t), Some(hash_files), &mut seen);
+        assert_eq!((info.format, info.gzip, info.entry_count, info.traversal_count), (ARCHIVE_TAR_USTAR, true, 3, 1));
+        let e = unsafe { std::slice::from_raw_parts(info.entries, info.entry_count) };
+        assert_eq!((e[0].kind, e[1].kind, e[1].size, e[2].path_traversal), (ARCHIVE_ENTRY_DIR, ARCHIVE_ENTRY_FILE, 10, true));
+        assert_eq!(&t[e[1].data_offset as usize..][..10], b"#!/bin/sh\n");
+        assert_eq!(seen, [("app/run.sh".to_string(), to_hex(&sha256_digest(b"#!/bin/sh\n"))), ("../../etc/periodic/daily/x".into(), to_hex(&sha256_digest(b"evil")))]);
+        iris_archive_free(&mut info);
+
+        let c = odc(&[("./preinstall", b"exit 0\n")]);
+        let mut info = list(&c, None, &mut seen);
+        assert_eq!((info.format, info.gzip, info.entry_count), (ARCHIVE_CPIO_ODC, false, 1));
+        let e = unsafe { &*info.entries };
+        assert_eq!((e.kind, e.size, e.mode), (ARCHIVE_ENTRY_FILE, 7, 0o100755));
+        iris_archive_free(&mut info);
+
+        let xz = crate::lzma::tests::hex(crate::lzma::tests::XZ_SAMPLE);
+        let x = pbz(b"pbzx", &[(c.len(), &c), (0, b"")]);
+        let mut info = list(&x, None, &mut seen);
+        assert_eq!((info.format, info.payload, info.entry_count), (ARCHIVE_CPIO_ODC, payload::PAYLOAD_PBZX, 1));
+        iris_archive_free(&mut info);
+        let mut out = std::mem::MaybeUninit::<IrisArchiveInfo>::uninit();
+        let x = pbz(b"pbzx", &[(xz.len() * 3, &xz)]);
+        assert_eq!(iris_archive_list(x.as_ptr(), x.len(), None, std::ptr::null_mut(), out.as_mut_ptr()), -2);
+
+        let mut out = std::mem::MaybeUninit::<IrisArchiveInfo>::uninit();
+        assert_eq!(iris_archive_list(b"PK\x03\x04".as_ptr(), 4, None, std::ptr::null_mut(), out.as_mut_ptr()), -3);
+    }
+
+    #[test]
+    fn apple_archive() {
+        let a = [aa_entry(aar::AA_TYPE_DIR, "", b""), aa_entry(aar::AA_TYPE_FILE, "Contents/MacOS/run", b"#!/bin/sh\n")].concat();
+        let lzfse = [b"bvx-".as_slice(), &(a.len() as u32).to_le_bytes(), &a, b"bvx$"].concat();
+        let mut seen = Vec::new();
+        let mut info = list(&pbz(b"pbze", &[(a.len(), &lzfse)]), Some(hash_files), &mut seen);
+        assert_eq!((info.format, info.payload, info.entry_count), (ARCHIVE_APPLE_ARCHIVE, payload::PAYLOAD_PBZE, 2));
+        let e = unsafe { std::slice::from_raw_parts(info.entries, info.entry_count) };
+        assert_eq!((e[0].kind, e[1].kind, e[1].size, e[1].mode), (ARCHIVE_ENTRY_DIR, ARCHIVE_ENTRY_FILE, 10, 0o644));
+        assert_eq!(&a[e[1].data_offset as usize..][..10], b"#!/bin/sh\n");
+        assert_eq!(seen, [("Contents/MacOS/run".to_string(), to_hex(&sha256_digest(b"#!/bin/sh\n")))]);
+        iris_archive_free(&mut info);
+    }
+}
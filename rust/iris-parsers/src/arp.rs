@@ -0,0 +1,335 @@
+//! ARP (RFC 826) parser and a cache tracker that flags gratuitous ARPs,
+//! IP→MAC rebinding, and unsolicited replies — the usual on-LAN MITM tells.
+
+use std::collections::HashMap;
+
+const MAX_BINDINGS: usize = 4096;
+const MAX_PENDING: usize = 1024;
+const REQUEST_WINDOW_SECS: i64 = 5;
+const MULTI_IP_THRESHOLD: usize = 8;
+
+pub const ARP_OP_REQUEST: u16 = 1;
+pub const ARP_OP_REPLY: u16 = 2;
+
+/// Observation flags returned by the cache tracker.
+pub const ARP_FLAG_GRATUITOUS: u32 = 1 << 0;
+pub const ARP_FLAG_MAC_CHANGED: u32 = 1 << 1;      // IP now claimed by a different MAC
+pub const ARP_FLAG_MAC_MULTI_IP: u32 = 1 << 2;     // one MAC claiming many IPs
+pub const ARP_FLAG_UNSOLICITED_REPLY: u32 = 1 << 3;
+pub const ARP_FLAG_PROBE: u32 = 1 << 4;            // RFC 5227 probe (sender IP 0.0.0.0)
+pub const ARP_FLAG_ETH_SRC_MISMATCH: u32 = 1 << 5; // Ethernet source != ARP sender MAC
+pub const ARP_FLAG_BROADCAST_SENDER: u32 = 1 << 6; // sender MAC is broadcast/multicast
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct IrisArpPacket {
+    pub hw_type: u16,
+    pub proto_type: u16,
+    pub op: u16,
+    pub sender_mac: [u8; 6],
+    pub sender_ip: [u8; 4],
+    pub target_mac: [u8; 6],
+    pub target_ip: [u8; 4],
+    pub is_gratuitous: bool,
+    pub is_probe: bool,
+}
+
+#[repr(C)]
+pub struct IrisArpObservation {
+    pub flags: u32,         // ARP_FLAG_*
+    pub previous_mac: [u8; 6], // prior binding when ARP_FLAG_MAC_CHANGED is set
+    pub previous_seen: i64,
+    pub sender_ip_count: u32,  // IPs currently bound to the sender MAC
+}
+
+/// Parse an Ethernet/IPv4 ARP packet (the payload after the Ethernet header).
+pub fn parse_arp(data: &[u8]) -> Option<IrisArpPacket> {
+    if data.len() < 28 { return None; }
+    let hw_type = u16::from_be_bytes([data[0], data[1]]);
+    let proto_type = u16::from_be_bytes([data[2], data[3]]);
+    // Only Ethernet/IPv4
+    if hw_type != 1 || proto_type != 0x0800 || data[4] != 6 || data[5] != 4 { return None; }
+    let op = u16::from_be_bytes([data[6], data[7]]);
+    let mut p = IrisArpPacket {
+        hw_type, proto_type, op,
+        sender_mac: [0; 6], sender_ip: [0; 4], target_mac: [0; 6], target_ip: [0; 4],
+        is_gratuitous: false, is_probe: false,
+    };
+    p.sender_mac.copy_from_slice(&data[8..14]);
+    p.sender_ip.copy_from_slice(&data[14..18]);
+    p.target_mac.copy_from_slice(&data[18..24]);
+    p.target_ip.copy_from_slice(&data[24..28]);
+    p.is_probe = op == ARP_OP_REQUEST && p.sender_ip == [0; 4];
+    p.is_gratuitous = !p.is_probe
+        && (p.sender_ip == p.target_ip || (op == ARP_OP_REPLY && p.target_mac == [0xFF; 6]));
+    Some(p)
+}
+
+// --- Cache tracker ---
+
+struct Binding { mac: [u8; 6], last_seen: i64 }
+
+/// Opaque IP→MAC tracking state. Create with iris_arp_cache_new.
+pub struct IrisArpCache {
+    bindings: HashMap<[u8; 4], Binding>,
+    // (requester IP, requested IP) → time of request
+    pending: HashMap<([u8; 4], [u8; 4]), i64>,
+}
+
+impl IrisArpCache {
+    fn new() -> Self {
+        IrisArpCache { bindings: HashMap::new(), pending: HashMap::new() }
+    }
+
+    fn ips_for_mac(&self, mac: &[u8; 6]) -> usize {
+        self.bindings.values().filter(|b| &b.mac == mac).count()
+    }
+
+    fn evict_oldest(&mut self) {
+        if let Some(ip) = self.bindings.iter().min_by_key(|(_, b)| b.last_seen).map(|(ip, _)| *ip) {
+            self.bindings.remove(&ip);
+        }
+    }
+
+    fn observe(&mut self, p: &IrisArpPacket, now: i64, eth_src: Option<[u8; 6]>) -> IrisArpObservation {
+        let mut obs = IrisArpObservation {
+            flags: 0, previous_mac: [0; 6], previous_seen: 0, sender_ip_count: 0,
+        };
+        if p.is_gratuitous { obs.flags |= ARP_FLAG_GRATUITOUS; }
+        if p.is_probe { obs.flags |= ARP_FLAG_PROBE; }
+        if p.sender_mac[0] & 0x01 != 0 { obs.flags |= ARP_FLAG_BROADCAST_SENDER; }
+        if let Some(src) = eth_src {
+            if src != p.sender_mac { obs.flags |= ARP_FLAG_ETH_SRC_MISMATCH; }
+        }
+
+        match p.op {
+            ARP_OP_REQUEST if !p.is_gratuitous => {
+                if self.pending.len() >= MAX_PENDING {
+                    self.pending.retain(|_, t| now - *t <= REQUEST_WINDOW_SECS);
+                    if self.pending.len() >= MAX_PENDING { self.pending.clear(); }
+                }
+                self.pending.insert((p.sender_ip, p.target_ip), now);
+            }
+            ARP_OP_REPLY if !p.is_gratuitous => {
+                let solicited = self.pending.remove(&(p.target_ip, p.sender_ip))
+                    .is_some_and(|t| now - t <= REQUEST_WINDOW_SECS);
+                if !solicited { obs.flags |= ARP_FLAG_UNSOLICITED_REPLY; }
+            }
+            _ => {}
+        }
+
+        if p.is_probe { return obs; } // probes carry no binding
+        match self.bindings.get_mut(&p.sender_ip) {
+            Some(b) => {
+                if b.mac != p.sender_mac {
+                    obs.flags |= ARP_FLAG_MAC_CHANGED;
+                    obs.previous_mac = b.mac;
+                    obs.previous_seen = b.last_seen;
+                    b.mac = p.sender_mac;
+                }
+                b.last_seen = now;
+            }
+            None => {
+                if self.bindings.len() >= MAX_BINDINGS { self.evict_oldest(); }
+                self.bindings.insert(p.sender_ip, Binding { mac: p.sender_mac, last_seen: now });
+            }
+        }
+        let count = self.ips_for_mac(&p.sender_mac);
+        if count >= MULTI_IP_THRESHOLD { obs.flags |= ARP_FLAG_MAC_MULTI_IP; }
+        obs.sender_ip_count = count as u32;
+        obs
+    }
+}
+
+// --- FFI entry points ---
+
+/// Parse an ARP packet. Returns 0=ok, -1=truncated/not Ethernet-IPv4, -2=arg error.
+#[no_mangle]
+pub extern "C" fn iris_arp_parse(data: *const u8, len: usize, out: *mut IrisArpPacket) -> i32 {
+    if data.is_null() || out.is_null() || len == 0 { return -2; }
+    let buf = unsafe { std::slice::from_raw_parts(data, len) };
+    match parse_arp(buf) {
+        Some(p) => { unsafe { out.write(p); } 0 }
+        None => -1,
+    }
+}
+
+/// Create an ARP cache tracker. Free with iris_arp_cache_free.
+#[no_mangle]
+pub extern "C" fn iris_arp_cache_new() -> *mut IrisArpCache {
+    Box::into_raw(Box::new(IrisArpCache::new()))
+}
+
+/// Feed a parsed ARP packet observed at `timestamp` (unix seconds).
+/// `eth_src` may be null; when given, it is compared against the ARP sender MAC.
+/// Returns 0=ok, -2=arg error.
+#[no_mangle]
+pub extern "C" fn iris_arp_cache_observe(
+    cache: *mut IrisArpCache, packet: *const IrisArpPacket, timestamp: i64,
+    eth_src: *const u8, out: *mut IrisArpObservation,
+) -> i32 {
+    if cache.is_null() || packet.is_null() || out.is_null() { return -2; }
+    let eth = if eth_src.is_null() { None } else {
+        let mut mac = [0u8; 6];
+        mac.copy_from_slice(unsafe { std::slice::from_raw_parts(eth_src, 6) });
+        Some(mac)
+    };
+    let obs = unsafe { (*cache).observe(&*packet, timestamp, eth) };
+    unsafe { out.write(obs); }
+    0
+}
+
+/// Look up the MAC currently bound to `ip` (4 bytes). Returns 0=found, -1=unknown, -2=arg error.
+#[no_mangle]
+pub extern "C" fn iris_arp_cache_lookup(
+    cache: *const IrisArpCache, ip: *const u8, out_mac: *mut u8,
+) -> i32 {
+    if cache.is_null() || ip.is_null() || out_mac.is_null() { return -2; }
+    let mut key = [0u8; 4];
+    key.copy_from_slice(unsafe { std::slice::from_raw_parts(ip, 4) });
+    match unsafe { (*cache).bindings.get(&key) } {
+        Some(b) => {
+            unsafe { std::ptr::copy_nonoverlapping(b.mac.as_ptr(), out_mac, 6); }
+            0
+        }
+        None => -1,
+    }
+}
+
+/// Free a tracker created by iris_arp_cache_new.
+#[no_mangle]
+pub extern "C" fn iris_arp_cache_free(cache: *mut IrisArpCache) {
+    if cache.is_null() { return; }
+    unsafe { drop(Box::from_raw(cache)); }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: [u8; 6] = [0x02, 0, 0, 0, 0, 0x0a];
+    const B: [u8; 6] = [0x02, 0, 0, 0, 0, 0x0b];
+    const EVIL: [u8; 6] = [0x02, 0, 0, 0, 0, 0x66];
+    const GW: [u8; 4] = [192, 168, 1, 1];
+    const HOST: [u8; 4] = [192, 168, 1, 20];
+
+    fn arp(op: u16, smac: [u8; 6], sip: [u8; 4], tmac: [u8; 6], tip: [u8; 4]) -> Vec<u8> {
+        [&[0, 1, 0x08, 0x00, 6, 4][..], &op.to_be_bytes(), &smac, &sip, &tmac, &tip].concat()
+    }
+
+    fn packet(op: u16, smac: [u8; 6], sip: [u8; 4], tmac: [u8; 6], tip: [u8; 4]) -> IrisArpPacket {
+        parse_arp(&arp(op, smac, sip, tmac, tip)).unwrap()
+    }
+
+    #[test]
+    fn parse_packets() {
+        let p = packet(ARP_OP_REQUEST, A, HOST, [0; 6], GW);
+        assert_eq!((p.hw_type, p.proto_type, p.op), (1, 0x0800, ARP_OP_REQUEST));
+        assert_eq!((p.sender_mac, p.sender_ip, p.target_ip), (A, HOST, GW));
+        assert!(!p.is_gratuitous && !p.is_probe);
+
+        // Gratuitous as a request (sender IP == target IP) and as a broadcast reply
+        assert!(packet(ARP_OP_REQUEST, B, GW, [0; 6], GW).is_gratuitous);
+        assert!(packet(ARP_OP_REPLY, B, GW, [0xff; 6], [192, 168, 1, 255]).is_gratuitous);
+        // RFC 5227 probe: sender IP unset, never gratuitous
+        let p = packet(ARP_OP_REQUEST, A, [0; 4], [0; 6], HOST);
+        assert!(p.is_probe && !p.is_gratuitous);
+
+        let mut out = std::mem::MaybeUninit::<IrisArpPacket>::uninit();
+        let d = arp(ARP_OP_REPLY, B, GW, A, HOST);
+        assert_eq!(iris_arp_parse(d.as_ptr(), d.len(), out.as_mut_ptr()), 0);
+        assert_eq!(unsafe { out.assume_init() }.op, ARP_OP_REPLY);
+        assert_eq!(iris_arp_parse(d.as_ptr(), 27, out.as_mut_ptr()), -1);
+        let mut ipv6ish = d.clone();
+        ipv6ish[5] = 16;
+        assert_eq!(iris_arp_parse(ipv6ish.as_ptr(), ipv6ish.len(), out.as_mut_ptr()), -1);
+        let mut ieee802 = d.clone();
+        ieee802[1] = 6;
+        assert_eq!(iris_arp_parse(ieee802.as_ptr(), ieee802.len(), out.as_mut_ptr()), -1);
+        let mut not_ipv4 = d.clone();
+        not_ipv4[2..4].copy_from_slice(&[0x86, 0xDD]);
+        assert_eq!(iris_arp_parse(not_ipv4.as_ptr(), not_ipv4.len(), out.as_mut_ptr()), -1);
+        assert_eq!(iris_arp_parse(d.as_ptr(), 0, out.as_mut_ptr()), -2);
+    }
+
+    #[test]
+    fn cache_flags() {
+        let mut c = IrisArpCache::new();
+        // Solicited exchange: no flags, binding learned
+        assert_eq!(c.observe(&packet(ARP_OP_REQUEST, A, HOST, [0; 6], GW), 100, Some(A)).flags, 0);
+        let o = c.observe(&packet(ARP_OP_REPLY, B, GW, A, HOST), 101, Some(B));
+        assert_eq!((o.flags, o.sender_ip_count), (0, 1));
+
+        // Spoofed reply nobody asked for, rebinding the gateway
+        let o = c.observe(&packet(ARP_OP_REPLY, EVIL, GW, A, HOST), 110, Some(EVIL));
+        assert_eq!(o.flags, ARP_FLAG_UNSOLICITED_REPLY | ARP_FLAG_MAC_CHANGED);
+        assert_eq!((o.previous_mac, o.previous_seen), (B, 101));
+
+        // A reply outside the request window is unsolicited too
+        c.observe(&packet(ARP_OP_REQUEST, A, HOST, [0; 6], GW), 200, None);
+        let o = c.observe(&packet(ARP_OP_REPLY, EVIL, GW, A, HOST), 200 + REQUEST_WINDOW_SECS + 1, None);
+        assert_eq!(o.flags, ARP_FLAG_UNSOLICITED_REPLY);
+
+        // Gratuitous announcement sent from a different Ethernet source
+        let o = c.observe(&packet(ARP_OP_REQUEST, B, GW, [0; 6], GW), 300, Some(EVIL));
+        assert_eq!(o.flags, ARP_FLAG_GRATUITOUS | ARP_FLAG_MAC_CHANGED | ARP_FLAG_ETH_SRC_MISMATCH);
+
+        // Probes bind nothing
+        let o = c.observe(&packet(ARP_OP_REQUEST, A, [0; 4], [0; 6], [192, 168, 1, 50]), 310, None);
+        assert_eq!((o.flags, o.sender_ip_count), (ARP_FLAG_PROBE, 0));
+        assert!(!c.bindings.contains_key(&[0; 4]));
+
+        let multicast = [0x01, 0, 0x5e, 0, 0, 1];
+        let o = c.observe(&packet(ARP_OP_REQUEST, multicast, [192, 168, 1, 9], [0; 6], GW), 320, None);
+        assert_eq!(o.flags, ARP_FLAG_BROADCAST_SENDER);
+
+        // One MAC answering for a whole subnet
+        let mut o = c.observe(&packet(ARP_OP_REQUEST, EVIL, [10, 0, 0, 1], [0; 6], GW), 400, None);
+        for i in 2..=MULTI_IP_THRESHOLD as u8 {
+            assert_eq!(o.flags & ARP_FLAG_MAC_MULTI_IP, 0);
+            o = c.observe(&packet(ARP_OP_REQUEST, EVIL, [10, 0, 0, i], [0; 6], GW), 400, None);
+        }
+        assert_eq!((o.flags, o.sender_ip_count as usize), (ARP_FLAG_MAC_MULTI_IP, MULTI_IP_THRESHOLD));
+    }
+
+    #[test]
+    fn cache_limits() {
+        let mut c = IrisArpCache::new();
+        for i in 0..MAX_BINDINGS {
+            let ip = [10, 1, (i >> 8) as u8, i as u8];
+            c.observe(&packet(ARP_OP_REQUEST, A, ip, [0; 6], GW), i as i64, None);
+        }
+        assert_eq!(c.bindings.len(), MAX_BINDINGS);
+        c.observe(&packet(ARP_OP_REQUEST, A, HOST, [0; 6], GW), 1_000_000, None);
+        assert_eq!(c.bindings.len(), MAX_BINDINGS);
+        assert!(!c.bindings.contains_key(&[10, 1, 0, 0]) && c.bindings.contains_key(&HOST));
+
+        // Full of stale requests: expired ones go
+        let mut c = IrisArpCache::new();
+        for i in 0..MAX_PENDING {
+            c.observe(&packet(ARP_OP_REQUEST, A, HOST, [0; 6], [10, 2, (i >> 8) as u8, i as u8]), 0, None);
+        }
+        c.observe(&packet(ARP_OP_REQUEST, A, HOST, [0; 6], GW), 100, None);
+        assert_eq!(c.pending.len(), 1);
+        // Full of fresh requests (a scan): everything is dropped
+        for i in 0..MAX_PENDING {
+            c.observe(&packet(ARP_OP_REQUEST, A, HOST, [0; 6], [10, 3, (i >> 8) as u8, i as u8]), 100, None);
+        }
+        assert_eq!(c.pending.len(), 1);
+    }
+
+    #[test]
+    fn ffi_cache() {
+        let c = iris_arp_cache_new();
+        let p = packet(ARP_OP_REPLY, B, GW, A, HOST);
+        let mut o = std::mem::MaybeUninit::<IrisArpObservation>::uninit();
+        assert_eq!(iris_arp_cache_observe(c, &p, 1, A.as_ptr(), o.as_mut_ptr()), 0);
+        assert_eq!(unsafe { o.assume_init_read() }.flags, ARP_FLAG_UNSOLICITED_REPLY | ARP_FLAG_ETH_SRC_MISMATCH);
+        let mut mac = [0u8; 6];
+        assert_eq!(iris_arp_cache_lookup(c, GW.as_ptr(), mac.as_mut_ptr()), 0);
+        assert_eq!(mac, B);
+        assert_eq!(iris_arp_cache_lookup(c, HOST.as_ptr(), mac.as_mut_ptr()), -1);
+        assert_eq!(iris_arp_cache_observe(c, std::ptr::null(), 1, std::ptr::null(), o.as_mut_ptr()), -2);
+        iris_arp_cache_free(c);
+    }
+}
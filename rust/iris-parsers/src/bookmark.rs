@@ -0,0 +1,377 @@
+//! Apple bookmark data ("book" blobs from CFURLCreateBookmarkData): a header, then a
+//! data area of typed items indexed by one or more tables of contents. Found in login
+//! items, sandbox container metadata, recent-items lists and Finder alias files.
+
+use crate::bytes::le32;
+use crate::ffi::{free_cstr, to_cstr};
+use crate::plist::CF_EPOCH;
+use std::collections::HashMap;
+use std::ffi::c_char;
+
+const HEADER_MIN: usize = 16;
+const TOC_MAGIC: u32 = 0xffff_fffe;
+const MAX_TOCS: usize = 16;
+const MAX_COMPONENTS: usize = 256;
+
+// Item types: high byte is the class, low byte the subtype
+const TYPE_STRING: u32 = 0x0101;
+const TYPE_DATA: u32 = 0x0201;
+const CLASS_NUMBER: u32 = 0x0300;
+const TYPE_DATE: u32 = 0x0400;
+const TYPE_TRUE: u32 = 0x0501;
+const TYPE_ARRAY: u32 = 0x0601;
+const TYPE_UUID: u32 = 0x0801;
+const TYPE_URL: u32 = 0x0901;
+
+const KEY_PATH: u32 = 0x1004;
+const KEY_FILE_IDS: u32 = 0x1005;
+const KEY_RESOURCE_PROPS: u32 = 0x1010;
+const KEY_CREATION_DATE: u32 = 0x1040;
+const KEY_VOLUME_PATH: u32 = 0x2002;
+const KEY_VOLUME_URL: u32 = 0x2005;
+const KEY_VOLUME_NAME: u32 = 0x2010;
+const KEY_VOLUME_UUID: u32 = 0x2011;
+const KEY_VOLUME_SIZE: u32 = 0x2012;
+const KEY_VOLUME_CREATION_DATE: u32 = 0x2013;
+const KEY_VOLUME_IS_BOOT: u32 = 0x2030;
+const KEY_USER_NAME: u32 = 0xc011;
+const KEY_UID: u32 = 0xc012;
+const KEY_CREATION_OPTIONS: u32 = 0xd010;
+const KEY_DISPLAY_NAME: u32 = 0xf017;
+const KEY_SANDBOX_EXTENSION: u32 = 0xf080;
+
+#[repr(C)]
+pub struct IrisBookmark {
+    pub path: *mut c_char,                 // target path on its volume
+    pub display_name: *mut c_char,
+    pub inode: u64,                        // target file ID, 0 if absent
+    pub resource_flags: u64,               // NSURL resource property bits (regular file, directory, ...)
+    pub creation_date: f64,                // target creation time, unix seconds, 0 if absent
+    pub volume_path: *mut c_char,          // mount point, "/" for the boot volume
+    pub volume_url: *mut c_char,
+    pub volume_name: *mut c_char,
+    pub volume_uuid: *mut c_char,
+    pub volume_size: u64,
+    pub volume_creation_date: f64,         // unix seconds, 0 if absent
+    pub volume_is_boot: bool,
+    pub user_name: *mut c_char,            // user that created the bookmark
+    pub uid: u32,
+    pub creation_options: u32,             // NSURLBookmarkCreationOptions
+    pub sandbox_extension: *mut c_char,    // security-scope extension token, "" if none
+}
+
+pub struct Bookmark {
+    pub path: String,
+    pub display_name: String,
+    pub inode: u64,
+    pub resource_flags: u64,
+    pub creation_date: f64,
+    pub volume_path: String,
+    pub volume_url: String,
+    pub volume_name: String,
+    pub volume_uuid: String,
+    pub volume_size: u64,
+    pub volume_creation_date: f64,
+    pub volume_is_boot: bool,
+    pub user_name: String,
+    pub uid: u32,
+    pub creation_options: u32,
+    pub sandbox_extension: String,
+}
+
+/// The data area and its key -> item offset index.
+struct Items<'a> {
+    data: &'a [u8],
+    toc: HashMap<u32, usize>,
+}
+
+impl<'a> Items<'a> {
+    fn new(data: &'a [u8]) -> Option<Self> {
+        let mut toc = HashMap::new();
+        let mut off = le32(data, 0)? as usize;
+        for _ in 0..MAX_TOCS {
+            if le32(data, off + 4)? != TOC_MAGIC { return None; }
+            let next = le32(data, off + 12)? as usize;
+            let count = le32(data, off + 16)? as usize;
+            for i in 0..count.min(data.len() / 12) {
+                let e = off + 20 + 12 * i;
+                let (Some(key), Some(item)) = (le32(data, e), le32(data, e + 4)) else { break };
+                // Keys with the high bit set are offsets of string keys; not used here
+                if key & 0x8000_0000 == 0 { toc.entry(key).or_insert(item as usize); }
+            }
+            if next == 0 { break; }
+            off = next;
+        }
+        Some(Items { data, toc })
+    }
+
+    fn item(&self, off: usize) -> Option<(u32, &'a [u8])> {
+        let len = le32(self.data, off)? as usize;
+        Some((le32(self.data, off + 4)?, self.data.get(off + 8..(off + 8).checked_add(len)?)?))
+    }
+
+    fn get(&self, key: u32) -> Option<(u32, &'a [u8])> {
+        self.item(*self.toc.get(&key)?)
+    }
+
+    fn string_at(&self, off: usize) -> Option<String> {
+        match self.item(off)? {
+            (TYPE_STRING | TYPE_URL, b) => Some(String::from_utf8_lossy(b).into_owned()),
+            (TYPE_UUID, b) if b.len() == 16 => Some(crate::plist::uuid_string(b)),
+            _ => None,
+        }
+    }
+
+    fn string(&self, key: u32) -> String {
+        self.toc.get(&key).and_then(|&o| self.string_at(o)).unwrap_or_default()
+    }
+
+    fn number_at(&self, off: usize) -> Option<u64> {
+        let (t, b) = self.item(off)?;
+        if t & 0xff00 != CLASS_NUMBER { return None; }
+        Some(match (t & 0xff, b.len()) {
+            (1, 1..) => b[0] as i8 as u64,
+            (2, 2..) => i16::from_le_bytes(b[..2].try_into().ok()?) as u64,
+            (3, 4..) => i32::from_le_bytes(b[..4].try_into().ok()?) as u64,
+            (4, 8..) => u64::from_le_bytes(b[..8].try_into().ok()?),
+            (5, 4..) => f32::from_le_bytes(b[..4].try_into().ok()?) as u64,
+            (6, 8..) => f64::from_le_bytes(b[..8].try_into().ok()?) as u64,
+            _ => return None,
+        })
+    }
+
+    fn number(&self, key: u32) -> u64 {
+        self.toc.get(&key).and_then(|&o| self.number_at(o)).unwrap_or(0)
+    }
+
+    /// Dates are big-endian CFAbsoluteTime.
+    fn date(&self, key: u32) -> f64 {
+        match self.get(key) {
+            Some((TYPE_DATE, b)) if b.len() >= 8 => f64::from_be_bytes(b[..8].try_into().unwrap()) + CF_EPOCH,
+            _ => 0.0,
+        }
+    }
+
+    fn array(&self, key: u32) -> Vec<usize> {
+        match self.get(key) {
+            Some((TYPE_ARRAY, b)) => b.chunks_exact(4).take(MAX_COMPONENTS)
+                .map(|c| u32::from_le_bytes(c.try_into().unwrap()) as usize).collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Parse a bookmark blob, or a Finder alias file ("book\0\0\0\0mark" header) wrapping one.
+pub fn parse(d: &[u8]) -> Option<Bookmark> {
+    if !d.starts_with(b"book") && !d.starts_with(b"alis") { return None; }
+    let (total, header) = if d.get(8..12) == Some(b"mark") {
+        (d.len(), le32(d, 16)? as usize)
+    } else {
+        (le32(d, 4)? as usize, le32(d, 12)? as usize)
+    };
+    if header < HEADER_MIN || header >= total { return None; }
+    let items = Items::new(d.get(header..total)?)?;
+    let components: Vec<String> = items.array(KEY_PATH).into_iter().filter_map(|o| items.string_at(o)).collect();
+    let resource_flags = match items.get(KEY_RESOURCE_PROPS) {
+        Some((TYPE_DATA, b)) if b.len() >= 8 => u64::from_le_bytes(b[..8].try_into().unwrap()),
+        _ => 0,
+    };
+    let sandbox_extension = match items.get(KEY_SANDBOX_EXTENSION) {
+        Some((TYPE_DATA, b)) => String::from_utf8_lossy(b).trim_end_matches('\0').to_string(),
+        _ => String::new(),
+    };
+    Some(Bookmark {
+        path: format!("/{}", components.join("/")),
+        display_name: items.string(KEY_DISPLAY_NAME),
+        inode: items.array(KEY_FILE_IDS).last().and_then(|&o| items.number_at(o)).unwrap_or(0),
+        resource_flags,
+        creation_date: items.date(KEY_CREATION_DATE),
+        volume_path: items.string(KEY_VOLUME_PATH),
+        volume_url: items.string(KEY_VOLUME_URL),
+        volume_name: items.string(KEY_VOLUME_NAME),
+        volume_uuid: items.string(KEY_VOLUME_UUID),
+        volume_size: items.number(KEY_VOLUME_SIZE),
+        volume_creation_date: items.date(KEY_VOLUME_CREATION_DATE),
+        volume_is_boot: matches!(items.get(KEY_VOLUME_IS_BOOT), Some((TYPE_TRUE, _))),
+        user_name: items.string(KEY_USER_NAME),
+        uid: items.number(KEY_UID) as u32,
+        creation_options: items.number(KEY_CREATION_OPTIONS) as u32,
+        sandbox_extension,
+    })
+}
+
+// --- FFI entry points ---
+
+/// Parse bookmark data or a Finder alias file.
+/// Returns 0=ok, -2=arg error or malformed, -3=not bookmark data.
+/// Free with iris_bookmark_free.
+#[no_mangle]
+pub extern "C" fn iris_bookmark_parse(data: *const u8, len: usize, out: *mut IrisBookmark) -> i32 {
+    if data.is_null() || out.is_null() { return -2; }
+    let d = unsafe { std::slice::from_raw_parts(data, len) };
+    if !d.starts_with(b"book") && !d.starts_with(b"alis") { return -3; }
+    let Some(b) = parse(d) else { return -2 };
+    unsafe {
+        out.write(IrisBookmark {
+            path: to_cstr(&b.path),
+            display_name: to_cstr(&b.display_name),
+            inode: b.inode,
+            resource_flags: b.resource_flags,
+            creation_date: b.creation_date,
+            volume_path: to_cstr(&b.volume_path),
+            volume_url: to_cstr(&b.volume_url),
+            volume_name: to_cstr(&b.volume_name),
+            volume_uuid: to_cstr(&b.volume_uuid),
+            volume_size: b.volume_size,
+            volume_creation_date: b.volume_creation_date,
+            volume_is_boot: b.volume_is_boot,
+            user_name: to_cstr(&b.user_name),
+            uid: b.uid,
+            creation_options: b.creation_options,
+            sandbox_extension: to_cstr(&b.sandbox_extension),
+        });
+    }
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn iris_bookmark_free(b: *mut IrisBookmark) {
+    if b.is_null() { return; }
+    let b = unsafe { &*b };
+    for p in [b.path, b.display_name, b.volume_path, b.volume_url, b.volume_name, b.volume_uuid, b.user_name, b.sandbox_extension] {
+        free_cstr(p);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A bookmark blob with one TOC holding `entries` (key, type, payload).
+    fn build(entries: &[(u32, u32, Vec<u8>)], strings: &[&str]) -> Vec<u8> {
+        // Data area: TOC offset, then the string items referenced by the path array
+        let mut area = vec![0u8; 4];
+        let item = |area: &mut Vec<u8>, t: u32, b: &[u8]| {
+            let off = area.len() as u32;
+            area.extend_from_slice(&(b.len() as u32).to_le_bytes());
+            area.extend_from_slice(&t.to_le_bytes());
+            area.extend_from_slice(b);
+            while !area.len().is_multiple_of(4) { area.push(0); }
+            off
+        };
+        let offs: Vec<u8> = strings.iter().flat_map(|s| item(&mut area, TYPE_STRING, s.as_bytes()).to_le_bytes()).collect();
+        let mut toc = vec![(KEY_PATH, item(&mut area, TYPE_ARRAY, &offs))];
+        for (k, t, b) in entries { toc.push((*k, item(&mut area, *t, b))); }
+        let toc_off = area.len() as u32;
+        area[..4].copy_from_slice(&toc_off.to_le_bytes());
+        area.extend_from_slice(&((20 + 12 * toc.len()) as u32).to_le_bytes());
+        area.extend_from_slice(&TOC_MAGIC.to_le_bytes());
+        area.extend_from_slice(&1u32.to_le_bytes());
+        area.extend_from_slice(&0u32.to_le_bytes());
+        area.extend_from_slice(&(toc.len() as u32).to_le_bytes());
+        for (k, o) in toc {
+            area.extend_from_slice(&k.to_le_bytes());
+            area.extend_from_slice(&o.to_le_bytes());
+            area.extend_from_slice(&0u32.to_le_bytes());
+        }
+        let mut header = vec![0u8; 48];
+        header[..4].copy_from_slice(b"book");
+        header[4..8].copy_from_slice(&((48 + area.len()) as u32).to_le_bytes());
+        header[8..12].copy_from_slice(&0x1004_0000u32.to_le_bytes());
+        header[12..16].copy_from_slice(&48u32.to_le_bytes());
+        [header, area].concat()
+    }
+
+    #[test]
+    fn bookmark_fields() {
+        let d = build(&[
+            (KEY_VOLUME_PATH, TYPE_URL, b"file:///".to_vec()),
+            (KEY_VOLUME_NAME, TYPE_STRING, b"Macintosh HD".to_vec()),
+            (KEY_VOLUME_UUID, TYPE_UUID, (0u8..16).collect()),
+            (KEY_VOLUME_SIZE, 0x0304, 500_000_000_000u64.to_le_bytes().to_vec()),
+            (KEY_VOLUME_IS_BOOT, TYPE_TRUE, Vec::new()),
+            (KEY_CREATION_DATE, TYPE_DATE, 0f64.to_be_bytes().to_vec()),
+            (KEY_UID, 0x0303, 501u32.to_le_bytes().to_vec()),
+            (KEY_RESOURCE_PROPS, TYPE_DATA, [2u64.to_le_bytes(), 0xfu64.to_le_bytes()].concat()),
+            (KEY_SANDBOX_EXTENSION, TYPE_DATA, b"abc;00;/Applications/Helper.app\0".to_vec()),
+            (KEY_VOLUME_CREATION_DATE, 0x0500, Vec::new()),
+        ], &["Applications", "Helper.app"]);
+        let b = parse(&d).unwrap();
+        assert_eq!(b.path, "/Applications/Helper.app");
+        assert_eq!((b.volume_path.as_str(), b.volume_name.as_str(), b.volume_size, b.volume_is_boot), ("file:///", "Macintosh HD", 500_000_000_000, true));
+        assert_eq!(b.volume_uuid, "00010203-0405-0607-0809-0A0B0C0D0E0F");
+        assert_eq!((b.creation_date, b.volume_creation_date, b.uid, b.resource_flags), (CF_EPOCH, 0.0, 501, 2));
+        assert_eq!(b.sandbox_extension, "abc;00;/Applications/Helper.app");
+
+        // Finder alias file: "book\0\0\0\0mark\0\0\0\0", header size at offset 16
+        let alias = [&b"book\0\0\0\0mark\0\0\0\0"[..], &56u32.to_le_bytes(), &56u32.to_le_bytes(), &[0; 32], &d[48..]].concat();
+        assert_eq!(parse(&alias).unwrap().path, "/Applications/Helper.app");
+
+        let mut out = std::mem::MaybeUninit::<IrisBookmark>::uninit();
+        assert_eq!(iris_bookmark_parse(b"bplist00".as_ptr(), 8, out.as_mut_ptr()), -3);
+        assert_eq!(iris_bookmark_parse(d.as_ptr(), 40, out.as_mut_ptr()), -2);
+        assert_eq!(iris_bookmark_parse(d.as_ptr(), d.len(), out.as_mut_ptr()), 0);
+        let mut b = unsafe { out.assume_init() };
+        assert_eq!(unsafe { std::ffi::CStr::from_ptr(b.path) }.to_str().unwrap(), "/Applications/Helper.app");
+        iris_bookmark_free(&mut b);
+    }
+
+    fn code(d: &[u8]) -> i32 {
+        let mut out = std::mem::MaybeUninit::<IrisBookmark>::uninit();
+        let rc = iris_bookmark_parse(d.as_ptr(), d.len(), out.as_mut_ptr());
+        if rc == 0 { iris_bookmark_free(out.as_mut_ptr()); }
+        rc
+    }
+
+    #[test]
+    fn truncated_headers_and_tocs() {
+        let d = build(&[(KEY_VOLUME_NAME, TYPE_STRING, b"Data".to_vec())], &["tmp"]);
+        assert_eq!(code(&d), 0);
+        let put = |at: usize, v: u32| { let mut x = d.clone(); x[at..at + 4].copy_from_slice(&v.to_le_bytes()); x };
+        assert_eq!(code(&put(12, 8)), -2); // header shorter than the fixed part
+        assert_eq!(code(&put(12, d.len() as u32)), -2); // header runs to the end
+        assert_eq!(code(&put(4, d.len() as u32 + 1)), -2); // declared size past the data
+        assert_eq!(code(&d[..d.len() - 12]), -2);
+        assert_eq!(code(&d[..4]), -2);
+        assert_eq!(code(b"alis"), -2);
+
+        let toc = 48 + u32::from_le_bytes(d[48..52].try_into().unwrap()) as usize;
+        assert_eq!(code(&put(48, 0x00ff_0000)), -2); // first TOC outside the data
+        assert_eq!(code(&put(toc + 4, 0)), -2); // TOC magic
+        assert_eq!(code(&put(toc + 12, 0x00ff_0000)), -2); // next TOC outside the data
+        // A TOC chain that loops, or a count past the data, stops without failing
+        let looped = put(toc + 12, (toc - 48) as u32);
+        assert_eq!(parse(&looped).unwrap().volume_name, "Data");
+        let many = put(toc + 16, u32::MAX);
+        assert_eq!(parse(&many).unwrap().path, "/tmp");
+
+        let mut out = std::mem::MaybeUninit::<IrisBookmark>::uninit();
+        assert_eq!(iris_bookmark_parse(std::ptr::null(), 0, out.as_mut_ptr()), -2);
+        assert_eq!(iris_bookmark_parse(d.as_ptr(), d.len(), std::ptr::null_mut()), -2);
+    }
+
+    #[test]
+    fn malformed_items() {
+        // Items of the wrong type or size read as absent rather than failing the parse
+        let d = build(&[
+            (KEY_VOLUME_UUID, TYPE_UUID, vec![1; 15]),
+            (KEY_VOLUME_SIZE, 0x0304, vec![1; 4]),
+            (KEY_CREATION_DATE, TYPE_DATE, vec![1; 4]),
+            (KEY_RESOURCE_PROPS, TYPE_DATA, vec![1; 4]),
+            (KEY_UID, TYPE_STRING, b"501".to_vec()),
+            (KEY_VOLUME_NAME, 0x0303, 7u32.to_le_bytes().to_vec()),
+            (KEY_FILE_IDS, TYPE_ARRAY, 0x00ff_0000u32.to_le_bytes().to_vec()),
+            (KEY_VOLUME_IS_BOOT, TYPE_STRING, Vec::new()),
+        ], &["a", "b"]);
+        let b = parse(&d).unwrap();
+        assert_eq!(b.path, "/a/b");
+        assert_eq!((b.volume_uuid.as_str(), b.volume_name.as_str()), ("", ""));
+        assert_eq!((b.volume_size, b.uid, b.inode, b.resource_flags, b.creation_date), (0, 0, 0, 0, 0.0));
+        assert!(!b.volume_is_boot);
+
+        // Path components whose items overrun the data are dropped
+        let mut d = build(&[], &["x", "y"]);
+        d[52..56].copy_from_slice(&0x00ff_0000u32.to_le_bytes()); // length of the "x" item
+        assert_eq!(parse(&d).unwrap().path, "/y");
+    }
+}
@@ -0,0 +1,472 @@
+//! OpenBSM audit trails (/var/audit/*): header-to-trailer records of tokens, read one
+//! record at a time. Subject, path, exec argument/environment, text and return tokens
+//! are decoded; other known tokens are skipped by size.
+
+use crate::ffi::{free_c_string_array, vec_to_c_string_array, IrisCStringArray};
+use crate::ip::IrisIpAddr;
+
+const AUT_OTHER_FILE32: u8 = 0x11;
+const AUT_TRAILER: u8 = 0x13;
+const AUT_HEADER32: u8 = 0x14;
+const AUT_HEADER32_EX: u8 = 0x15;
+const AUT_DATA: u8 = 0x21;
+const AUT_IPC: u8 = 0x22;
+const AUT_PATH: u8 = 0x23;
+const AUT_SUBJECT32: u8 = 0x24;
+const AUT_XATPATH: u8 = 0x25;
+const AUT_PROCESS32: u8 = 0x26;
+const AUT_RETURN32: u8 = 0x27;
+const AUT_TEXT: u8 = 0x28;
+const AUT_OPAQUE: u8 = 0x29;
+const AUT_IN_ADDR: u8 = 0x2a;
+const AUT_IP: u8 = 0x2b;
+const AUT_IPORT: u8 = 0x2c;
+const AUT_ARG32: u8 = 0x2d;
+const AUT_SOCKET: u8 = 0x2e;
+const AUT_SEQ: u8 = 0x2f;
+const AUT_IPC_PERM: u8 = 0x32;
+const AUT_NEWGROUPS: u8 = 0x3b;
+const AUT_EXEC_ARGS: u8 = 0x3c;
+const AUT_EXEC_ENV: u8 = 0x3d;
+const AUT_ATTR32: u8 = 0x3e;
+const AUT_EXIT: u8 = 0x52;
+const AUT_ZONENAME: u8 = 0x60;
+const AUT_ARG64: u8 = 0x71;
+const AUT_RETURN64: u8 = 0x72;
+const AUT_ATTR64: u8 = 0x73;
+const AUT_HEADER64: u8 = 0x74;
+const AUT_SUBJECT64: u8 = 0x75;
+const AUT_PROCESS64: u8 = 0x77;
+const AUT_HEADER64_EX: u8 = 0x79;
+const AUT_SUBJECT32_EX: u8 = 0x7a;
+const AUT_PROCESS32_EX: u8 = 0x7b;
+const AUT_SUBJECT64_EX: u8 = 0x7c;
+const AUT_PROCESS64_EX: u8 = 0x7d;
+const AUT_IN_ADDR_EX: u8 = 0x7e;
+const AUT_SOCKET_EX: u8 = 0x7f;
+const AUT_SOCKINET32: u8 = 0x80;
+const AUT_SOCKINET128: u8 = 0x81;
+const AUT_SOCKUNIX: u8 = 0x82;
+
+const TRAILER_MAGIC: u16 = 0xb105;
+const TRAILER_SIZE: usize = 7;
+const MAX_EXEC_STRINGS: u32 = 4096;
+
+#[repr(C)]
+pub struct IrisBsmRecord {
+    pub event_type: u16,                // AUE_* from /etc/security/audit_event
+    pub event_modifier: u16,
+    pub version: u8,
+    pub timestamp: f64,                 // unix seconds
+    pub has_subject: bool,
+    pub auid: u32,                      // audit (login) user ID, 0xffffffff if unset
+    pub euid: u32,
+    pub egid: u32,
+    pub ruid: u32,
+    pub rgid: u32,
+    pub pid: u32,
+    pub session_id: u32,
+    pub terminal_port: u64,
+    pub terminal_addr: IrisIpAddr,
+    pub paths: IrisCStringArray,
+    pub exec_args: IrisCStringArray,
+    pub exec_env: IrisCStringArray,
+    pub texts: IrisCStringArray,        // text tokens, e.g. authentication details
+    pub has_return: bool,
+    pub return_status: u8,              // errno-style status, 0 = success
+    pub return_value: i64,
+    pub complete: bool,                 // every token decoded and the trailer matched
+}
+
+pub struct IrisBsmIter {
+    data: Vec<u8>,
+    pos: usize,
+}
+
+#[derive(Default)]
+struct Record {
+    event_type: u16,
+    event_modifier: u16,
+    version: u8,
+    timestamp: f64,
+    subject: Option<Subject>,
+    paths: Vec<String>,
+    exec_args: Vec<String>,
+    exec_env: Vec<String>,
+    texts: Vec<String>,
+    ret: Option<(u8, i64)>,
+    complete: bool,
+}
+
+struct Subject {
+    ids: [u32; 7], // auid, euid, egid, ruid, rgid, pid, sid
+    port: u64,
+    addr: IrisIpAddr,
+}
+
+struct Reader<'a> {
+    d: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> Option<&'a [u8]> {
+        let v = self.d.get(self.pos..self.pos.checked_add(n)?)?;
+        self.pos += n;
+        Some(v)
+    }
+    fn u8(&mut self) -> Option<u8> { self.bytes(1).map(|b| b[0]) }
+    fn u16(&mut self) -> Option<u16> { self.bytes(2).map(|b| u16::from_be_bytes([b[0], b[1]])) }
+    fn u32(&mut self) -> Option<u32> { self.bytes(4).map(|b| u32::from_be_bytes(b.try_into().unwrap())) }
+    fn u64(&mut self) -> Option<u64> { self.bytes(8).map(|b| u64::from_be_bytes(b.try_into().unwrap())) }
+
+    /// u16 length-prefixed string; the length includes the trailing NUL.
+    fn string(&mut self) -> Option<String> {
+        let n = self.u16()? as usize;
+        Some(cstring(self.bytes(n)?))
+    }
+
+    fn nul_string(&mut self) -> Option<String> {
+        let rest = self.d.get(self.pos..)?;
+        let n = rest.iter().position(|&b| b == 0)?;
+        self.pos += n + 1;
+        Some(String::from_utf8_lossy(&rest[..n]).into_owned())
+    }
+
+    /// Address preceded by a u32 length (4 or 16).
+    fn addr_ex(&mut self) -> Option<IrisIpAddr> {
+        match self.u32()? {
+            4 => Some(IrisIpAddr::v4(self.bytes(4)?)),
+            16 => Some(IrisIpAddr::v6(self.bytes(16)?)),
+            _ => None,
+        }
+    }
+
+    fn subject(&mut self, port64: bool, ex: bool) -> Option<Subject> {
+        let mut ids = [0u32; 7];
+        for id in &mut ids { *id = self.u32()?; }
+        let port = if port64 { self.u64()? } else { self.u32()? as u64 };
+        let addr = if ex { self.addr_ex()? } else { IrisIpAddr::v4(self.bytes(4)?) };
+        Some(Subject { ids, port, addr })
+    }
+}
+
+fn cstring(b: &[u8]) -> String {
+    let end = b.iter().position(|&c| c == 0).unwrap_or(b.len());
+    String::from_utf8_lossy(&b[..end]).into_owned()
+}
+
+fn is_header(t: u8) -> bool {
+    matches!(t, AUT_HEADER32 | AUT_HEADER32_EX | AUT_HEADER64 | AUT_HEADER64_EX)
+}
+
+/// Decode one token into the record. None for an unknown or truncated token.
+fn token(r: &mut Reader, rec: &mut Record) -> Option<()> {
+    match r.u8()? {
+        AUT_PATH | AUT_XATPATH => { let p = r.string()?; rec.paths.push(p); }
+        AUT_TEXT => { let t = r.string()?; rec.texts.push(t); }
+        AUT_ZONENAME => { r.string()?; }
+        AUT_SUBJECT32 => rec.subject = Some(r.subject(false, false)?),
+        AUT_SUBJECT64 => rec.subject = Some(r.subject(true, false)?),
+        AUT_SUBJECT32_EX => rec.subject = Some(r.subject(false, true)?),
+        AUT_SUBJECT64_EX => rec.subject = Some(r.subject(true, true)?),
+        AUT_PROCESS32 => { r.subject(false, false)?; }
+        AUT_PROCESS64 => { r.subject(true, false)?; }
+        AUT_PROCESS32_EX => { r.subject(false, true)?; }
+        AUT_PROCESS64_EX => { r.subject(true, true)?; }
+        AUT_RETURN32 => rec.ret = Some((r.u8()?, r.u32()? as i32 as i64)),
+        AUT_RETURN64 => rec.ret = Some((r.u8()?, r.u64()? as i64)),
+        t @ (AUT_EXEC_ARGS | AUT_EXEC_ENV) => {
+            let n = r.u32()?;
+            if n > MAX_EXEC_STRINGS { return None; }
+            let mut v = Vec::with_capacity(n as usize);
+            for _ in 0..n { v.push(r.nul_string()?); }
+            if t == AUT_EXEC_ARGS { rec.exec_args = v; } else { rec.exec_env = v; }
+        }
+        AUT_ARG32 => { r.bytes(5)?; r.string()?; }
+        AUT_ARG64 => { r.bytes(9)?; r.string()?; }
+        AUT_ATTR32 => { r.bytes(28)?; }
+        AUT_ATTR64 => { r.bytes(32)?; }
+        AUT_EXIT => { r.bytes(8)?; }
+        AUT_SEQ | AUT_IN_ADDR => { r.bytes(4)?; }
+        AUT_IN_ADDR_EX => { r.addr_ex()?; }
+        AUT_IPORT => { r.bytes(2)?; }
+        AUT_IP => { r.bytes(20)?; }
+        AUT_IPC => { r.bytes(5)?; }
+        AUT_IPC_PERM => { r.bytes(28)?; }
+        AUT_SOCKET => { r.bytes(14)?; }
+        AUT_SOCKINET32 => { r.bytes(8)?; }
+        AUT_SOCKINET128 => { r.bytes(20)?; }
+        AUT_SOCKUNIX => { r.bytes(2)?; r.nul_string()?; }
+        AUT_SOCKET_EX => {
+            r.bytes(4)?;
+            let n = r.u16()? as usize;
+            if n != 4 && n != 16 { return None; }
+            r.bytes(2 * (2 + n))?;
+        }
+        AUT_OPAQUE => { let n = r.u16()? as usize; r.bytes(n)?; }
+        AUT_NEWGROUPS => { let n = r.u16()? as usize; r.bytes(n * 4)?; }
+        AUT_DATA => {
+            let _how_to_print = r.u8()?;
+            let size = match r.u8()? { 0 => 1, 1 => 2, 2 => 4, 3 => 8, _ => return None };
+            let n = r.u8()? as usize;
+            r.bytes(size * n)?;
+        }
+        _ => return None,
+    }
+    Some(())
+}
+
+/// Parse the record starting at a header token; returns it with its total length.
+fn record(d: &[u8]) -> Option<(Record, usize)> {
+    let mut r = Reader { d, pos: 0 };
+    let kind = r.u8()?;
+    if !is_header(kind) { return None; }
+    let len = r.u32()? as usize;
+    let body = d.get(..len)?;
+    let trailer = body.get(len.checked_sub(TRAILER_SIZE)?..)?;
+    if trailer[0] != AUT_TRAILER || u16::from_be_bytes([trailer[1], trailer[2]]) != TRAILER_MAGIC
+        || u32::from_be_bytes(trailer[3..7].try_into().unwrap()) as usize != len {
+        return None;
+    }
+    let mut rec = Record { version: r.u8()?, event_type: r.u16()?, event_modifier: r.u16()?, ..Default::default() };
+    if kind == AUT_HEADER32_EX || kind == AUT_HEADER64_EX { r.addr_ex()?; }
+    rec.timestamp = if kind == AUT_HEADER32 || kind == AUT_HEADER32_EX {
+        r.u32()? as f64 + r.u32()? as f64 / 1000.0
+    } else {
+        r.u64()? as f64 + r.u64()? as f64 / 1000.0
+    };
+    let mut tokens = Reader { d: &body[..len - TRAILER_SIZE], pos: r.pos };
+    rec.complete = loop {
+        if tokens.pos == tokens.d.len() { break true; }
+        if token(&mut tokens, &mut rec).is_none() { break false; }
+    };
+    Some((rec, len))
+}
+
+impl IrisBsmIter {
+    /// Next record, skipping file tokens and resynchronising past garbage.
+    fn next_record(&mut self) -> Option<Record> {
+        while self.pos < self.data.len() {
+            let rest = &self.data[self.pos..];
+            if rest[0] == AUT_OTHER_FILE32 {
+                let mut r = Reader { d: rest, pos: 9 };
+                if let Some(n) = r.u16() {
+                    if r.bytes(n as usize).is_some() { self.pos += r.pos; continue; }
+                }
+            }
+            if let Some((rec, len)) = record(rest) {
+                self.pos += len;
+                return Some(rec);
+            }
+            self.pos += 1;
+        }
+        None
+    }
+}
+
+// --- FFI entry points ---
+
+/// Start iterating an audit trail held in memory. Returns null on a null pointer.
+/// Free with iris_bsm_close.
+#[no_mangle]
+pub extern "C" fn iris_bsm_open(data: *const u8, len: usize) -> *mut IrisBsmIter {
+    if data.is_null() { return std::ptr::null_mut(); }
+    let data = unsafe { std::slice::from_raw_parts(data, len) }.to_vec();
+    Box::into_raw(Box::new(IrisBsmIter { data, pos: 0 }))
+}
+
+/// Fetch the next record. Returns 0=record written, -1=end of trail, -2=arg error.
+/// Free each record with iris_bsm_record_free.
+#[no_mangle]
+pub extern "C" fn iris_bsm_next(it: *mut IrisBsmIter, out: *mut IrisBsmRecord) -> i32 {
+    if it.is_null() || out.is_null() { return -2; }
+    let it = unsafe { &mut *it };
+    let Some(rec) = it.next_record() else { return -1 };
+    let s = rec.subject.as_ref();
+    let id = |i: usize| s.map_or(0, |s| s.ids[i]);
+    unsafe {
+        out.write(IrisBsmRecord {
+            event_type: rec.event_type,
+            event_modifier: rec.event_modifier,
+            version: rec.version,
+            timestamp: rec.timestamp,
+            has_subject: s.is_some(),
+            auid: id(0), euid: id(1), egid: id(2), ruid: id(3), rgid: id(4), pid: id(5), session_id: id(6),
+            terminal_port: s.map_or(0, |s| s.port),
+            terminal_addr: s.map_or_else(IrisIpAddr::default, |s| s.addr),
+            paths: vec_to_c_string_array(rec.paths),
+            exec_args: vec_to_c_string_array(rec.exec_args),
+            exec_env: vec_to_c_string_array(rec.exec_env),
+            texts: vec_to_c_string_array(rec.texts),
+            has_return: rec.ret.is_some(),
+            return_status: rec.ret.map_or(0, |r| r.0),
+            return_value: rec.ret.map_or(0, |r| r.1),
+            complete: rec.complete,
+        });
+    }
+    0
+}
+
+/// Free the arrays of a record returned by iris_bsm_next.
+#[no_mangle]
+pub extern "C" fn iris_bsm_record_free(rec: *mut IrisBsmRecord) {
+    if rec.is_null() { return; }
+    let r = unsafe { &*rec };
+    for a in [&r.paths, &r.exec_args, &r.exec_env, &r.texts] { free_c_string_array(a); }
+}
+
+/// Free an iterator from iris_bsm_open.
+#[no_mangle]
+pub extern "C" fn iris_bsm_close(it: *mut IrisBsmIter) {
+    if it.is_null() { return; }
+    unsafe { drop(Box::from_raw(it)); }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string_token(t: u8, s: &str) -> Vec<u8> {
+        let mut v = vec![t];
+        v.extend_from_slice(&(s.len() as u16 + 1).to_be_bytes());
+        v.extend_from_slice(s.as_bytes());
+        v.push(0);
+        v
+    }
+
+    fn header32(event: u16, sec: u32, ms: u32, tokens: &[u8]) -> Vec<u8> {
+        let len = 18 + tokens.len() + TRAILER_SIZE;
+        let mut v = vec![AUT_HEADER32];
+        v.extend_from_slice(&(len as u32).to_be_bytes());
+        v.push(11);
+        v.extend_from_slice(&event.to_be_bytes());
+        v.extend_from_slice(&0u16.to_be_bytes());
+        v.extend_from_slice(&sec.to_be_bytes());
+        v.extend_from_slice(&ms.to_be_bytes());
+        v.extend_from_slice(tokens);
+        v.push(AUT_TRAILER);
+        v.extend_from_slice(&TRAILER_MAGIC.to_be_bytes());
+        v.extend_from_slice(&(len as u32).to_be_bytes());
+        v
+    }
+
+    fn cs(a: &IrisCStringArray) -> Vec<String> {
+        (0..a.count).map(|i| unsafe { std::ffi::CStr::from_ptr(*a.items.add(i)) }.to_string_lossy().into_owned()).collect()
+    }
+
+    #[test]
+    fn exec_and_login_records() {
+        let mut execve = Vec::new();
+        execve.push(AUT_EXEC_ARGS);
+        execve.extend_from_slice(&2u32.to_be_bytes());
+        execve.extend_from_slice(b"/bin/sh\0-c\0");
+        execve.extend_from_slice(&string_token(AUT_PATH, "/bin/sh"));
+        execve.push(AUT_ATTR32);
+        execve.extend_from_slice(&[0; 28]);
+        execve.push(AUT_SUBJECT32_EX);
+        for id in [501u32, 0, 0, 501, 20, 4242, 100007, 0x0300_0000] { execve.extend_from_slice(&id.to_be_bytes()); }
+        execve.extend_from_slice(&4u32.to_be_bytes());
+        execve.extend_from_slice(&[10, 0, 0, 5]);
+        execve.push(AUT_RETURN32);
+        execve.push(0);
+        execve.extend_from_slice(&0u32.to_be_bytes());
+
+        let mut login = string_token(AUT_TEXT, "Authentication for user <admin>");
+        login.push(AUT_RETURN32);
+        login.push(1);
+        login.extend_from_slice(&u32::MAX.to_be_bytes());
+        login.push(0xee); // unknown token
+
+        // File token, a record, junk, then a record with an unknown token
+        let mut trail = vec![AUT_OTHER_FILE32];
+        trail.extend_from_slice(&[0; 8]);
+        trail.extend_from_slice(&1u16.to_be_bytes());
+        trail.push(0);
+        trail.extend_from_slice(&header32(23, 1_700_000_000, 250, &execve));
+        trail.extend_from_slice(b"junk");
+        trail.extend_from_slice(&header32(45023, 1_700_000_001, 0, &login));
+
+        let it = iris_bsm_open(trail.as_ptr(), trail.len());
+        let mut out = std::mem::MaybeUninit::<IrisBsmRecord>::uninit();
+        assert_eq!(iris_bsm_next(it, out.as_mut_ptr()), 0);
+        let mut rec = unsafe { out.assume_init() };
+        assert_eq!((rec.event_type, rec.version, rec.timestamp, rec.complete), (23, 11, 1_700_000_000.25, true));
+        assert_eq!((rec.has_subject, rec.auid, rec.euid, rec.pid, rec.session_id), (true, 501, 0, 4242, 100007));
+        assert_eq!((rec.terminal_port, rec.terminal_addr.as_slice()), (0x0300_0000, &[10, 0, 0, 5][..]));
+        assert_eq!((cs(&rec.exec_args), cs(&rec.paths)), (vec!["/bin/sh".to_string(), "-c".into()], vec!["/bin/sh".to_string()]));
+        assert_eq!((rec.has_return, rec.return_status, rec.return_value), (true, 0, 0));
+        iris_bsm_record_free(&mut rec);
+
+        let mut out = std::mem::MaybeUninit::<IrisBsmRecord>::uninit();
+        assert_eq!(iris_bsm_next(it, out.as_mut_ptr()), 0);
+        let mut rec = unsafe { out.assume_init() };
+        assert_eq!((rec.event_type, rec.has_subject, rec.complete), (45023, false, false));
+        assert_eq!(cs(&rec.texts), ["Authentication for user <admin>"]);
+        assert_eq!((rec.return_status, rec.return_value), (1, -1));
+        iris_bsm_record_free(&mut rec);
+
+        let mut out = std::mem::MaybeUninit::<IrisBsmRecord>::uninit();
+        assert_eq!(iris_bsm_next(it, out.as_mut_ptr()), -1);
+        iris_bsm_close(it);
+    }
+
+    /// Records decoded from a trail, as (event type, complete, paths).
+    fn records(trail: &[u8]) -> Vec<(u16, bool, Vec<String>)> {
+        let it = iris_bsm_open(trail.as_ptr(), trail.len());
+        let mut v = Vec::new();
+        let mut out = std::mem::MaybeUninit::<IrisBsmRecord>::uninit();
+        while iris_bsm_next(it, out.as_mut_ptr()) == 0 {
+            let mut rec = unsafe { out.assume_init_read() };
+            v.push((rec.event_type, rec.complete, cs(&rec.paths)));
+            iris_bsm_record_free(&mut rec);
+        }
+        assert_eq!(iris_bsm_next(it, out.as_mut_ptr()), -1); // stays at the end
+        iris_bsm_close(it);
+        v
+    }
+
+    #[test]
+    fn truncated_and_malformed_records() {
+        let good = header32(1, 0, 0, &string_token(AUT_PATH, "/etc/passwd"));
+        let ok = [(1, true, vec!["/etc/passwd".to_string()])];
+        assert_eq!(records(&good), ok);
+        assert!(records(&good[..good.len() - 1]).is_empty()); // trailer cut short
+        assert!(records(&good[..10]).is_empty());
+        assert!(records(&[]).is_empty());
+
+        // Trailer magic or length wrong: skipped, and the next record is found
+        let mut magic = good.clone();
+        let t = magic.len() - TRAILER_SIZE;
+        magic[t + 1] ^= 0xff;
+        let mut len = good.clone();
+        len[t + 6] ^= 1;
+        assert_eq!(records(&[magic, len, good.clone()].concat()), ok);
+
+        // Declared length shorter than a trailer
+        let mut tiny = good.clone();
+        tiny[1..5].copy_from_slice(&3u32.to_be_bytes());
+        assert_eq!(records(&[tiny, good.clone()].concat()), ok);
+
+        // Tokens that run past the record, or carry impossible counts, end it early
+        let mut overrun = string_token(AUT_PATH, "/a");
+        overrun.extend_from_slice(&[AUT_PATH, 0x01, 0x00, b'/']);
+        assert_eq!(records(&header32(2, 0, 0, &overrun)), [(2, false, vec!["/a".to_string()])]);
+        let mut args = vec![AUT_EXEC_ARGS];
+        args.extend_from_slice(&(MAX_EXEC_STRINGS + 1).to_be_bytes());
+        assert_eq!(records(&header32(3, 0, 0, &args)), [(3, false, vec![])]);
+        let unterminated = [&[AUT_EXEC_ARGS, 0, 0, 0, 1][..], b"no nul"].concat();
+        assert_eq!(records(&header32(4, 0, 0, &unterminated)), [(4, false, vec![])]);
+        assert_eq!(records(&header32(5, 0, 0, &[AUT_DATA, 0, 4, 1, 0])), [(5, false, vec![])]);
+        assert_eq!(records(&header32(6, 0, 0, &[AUT_IN_ADDR_EX, 0, 0, 0, 8, 1, 2, 3, 4])), [(6, false, vec![])]);
+        let short_subject = [&[AUT_SUBJECT32][..], &[0; 20]].concat();
+        assert_eq!(records(&header32(7, 0, 0, &short_subject)), [(7, false, vec![])]);
+
+        let mut out = std::mem::MaybeUninit::<IrisBsmRecord>::uninit();
+        assert!(iris_bsm_open(std::ptr::null(), 4).is_null());
+        assert_eq!(iris_bsm_next(std::ptr::null_mut(), out.as_mut_ptr()), -2);
+        let it = iris_bsm_open(good.as_ptr(), good.len());
+        assert_eq!(iris_bsm_next(it, std::ptr::null_mut()), -2);
+        iris_bsm_close(it);
+    }
+}
@@ -0,0 +1,24 @@
+//! Bounds-checked fixed-width integer reads at a byte offset, shared by the binary
+//! format parsers. Each returns None when the value would run past the end.
+
+pub fn be16(d: &[u8], o: usize) -> Option<u16> { d.get(o..)?.first_chunk().map(|b| u16::from_be_bytes(*b)) }
+pub fn be32(d: &[u8], o: usize) -> Option<u32> { d.get(o..)?.first_chunk().map(|b| u32::from_be_bytes(*b)) }
+pub fn be64(d: &[u8], o: usize) -> Option<u64> { d.get(o..)?.first_chunk().map(|b| u64::from_be_bytes(*b)) }
+pub fn le16(d: &[u8], o: usize) -> Option<u16> { d.get(o..)?.first_chunk().map(|b| u16::from_le_bytes(*b)) }
+pub fn le32(d: &[u8], o: usize) -> Option<u32> { d.get(o..)?.first_chunk().map(|b| u32::from_le_bytes(*b)) }
+pub fn le64(d: &[u8], o: usize) -> Option<u64> { d.get(o..)?.first_chunk().map(|b| u64::from_le_bytes(*b)) }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_and_bounds() {
+        let d = [1, 2, 3, 4, 5, 6, 7, 8, 9];
+        assert_eq!((be16(&d, 0), le16(&d, 0)), (Some(0x0102), Some(0x0201)));
+        assert_eq!((be32(&d, 5), le32(&d, 5)), (Some(0x0607_0809), Some(0x0908_0706)));
+        assert_eq!((be64(&d, 1), le64(&d, 0)), (Some(0x0203_0405_0607_0809), Some(0x0807_0605_0403_0201)));
+        assert_eq!((be16(&d, 8), be32(&d, 6), be64(&d, 2)), (None, None, None));
+        assert_eq!((le16(&d, 9), le32(&d, 10), le64(&d, usize::MAX)), (None, None, None));
+    }
+}
@@ -0,0 +1,329 @@
+//! Batch certificate audit for the periodic keychain scan: expiry status and days
+//! remaining against a reference time, plus public key and signature weaknesses.
+//! Certificates are checked on all cores, since a keychain can hold thousands.
+
+use crate::base64;
+use crate::der::read_tlv;
+use crate::ffi::{alloc_array, free_array, free_cstr, to_cstr, IrisSlice};
+use crate::hash::{sha256_digest, to_hex};
+use crate::x509::{self, Certificate};
+use std::ffi::{c_char, CStr};
+
+pub const CERT_EXPIRY_VALID: u8 = 0;
+pub const CERT_EXPIRY_EXPIRING: u8 = 1;      // expires within the warning window
+pub const CERT_EXPIRY_EXPIRED: u8 = 2;
+pub const CERT_EXPIRY_NOT_YET_VALID: u8 = 3;
+pub const CERT_EXPIRY_INVALID: u8 = 4;       // unreadable or not a certificate
+
+pub const CERT_KEY_UNKNOWN: u8 = 0;
+pub const CERT_KEY_RSA: u8 = 1;
+pub const CERT_KEY_EC: u8 = 2;
+pub const CERT_KEY_DSA: u8 = 3;
+pub const CERT_KEY_ED25519: u8 = 4;
+pub const CERT_KEY_ED448: u8 = 5;
+
+pub const CERT_WEAK_RSA_KEY: u32 = 0x01;       // modulus under 2048 bits
+pub const CERT_WEAK_EC_CURVE: u32 = 0x02;      // curve under 224 bits
+pub const CERT_WEAK_DSA_KEY: u32 = 0x04;       // DSA, deprecated for signing (FIPS 186-5)
+pub const CERT_WEAK_SIGNATURE_HASH: u32 = 0x08; // MD2, MD5 or SHA-1 signature on a non-root
+pub const CERT_WEAK_RSA_EXPONENT: u32 = 0x10;  // public exponent below 65537
+
+const MIN_RSA_BITS: u32 = 2048;
+const MIN_EC_BITS: u32 = 224;
+const MIN_RSA_EXPONENT: u32 = 65537;
+
+const OID_RSA: &str = "1.2.840.113549.1.1.1";
+const OID_RSA_PSS: &str = "1.2.840.113549.1.1.10";
+const OID_EC: &str = "1.2.840.10045.2.1";
+const OID_DSA: &str = "1.2.840.10040.4.1";
+const OID_ED25519: &str = "1.3.101.112";
+const OID_ED448: &str = "1.3.101.113";
+
+const CURVES: &[(&str, u32)] = &[
+    ("1.3.132.0.8", 160),               // secp160r1
+    ("1.2.840.10045.3.1.1", 192),       // P-192
+    ("1.3.132.0.33", 224),              // P-224
+    ("1.2.840.10045.3.1.7", 256),       // P-256
+    ("1.3.132.0.10", 256),              // secp256k1
+    ("1.3.132.0.34", 384),              // P-384
+    ("1.3.132.0.35", 521),              // P-521
+    ("1.3.36.3.3.2.8.1.1.7", 256),      // brainpoolP256r1
+    ("1.3.36.3.3.2.8.1.1.11", 384),     // brainpoolP384r1
+    ("1.3.36.3.3.2.8.1.1.13", 512),     // brainpoolP512r1
+];
+
+const WEAK_SIGNATURES: &[&str] = &[
+    "1.2.840.113549.1.1.2",  // md2WithRSAEncryption
+    "1.2.840.113549.1.1.4",  // md5WithRSAEncryption
+    "1.2.840.113549.1.1.5",  // sha1WithRSAEncryption
+    "1.3.14.3.2.29",         // sha1WithRSASignature (OIW)
+    "1.2.840.10045.4.1",     // ecdsa-with-SHA1
+    "1.2.840.10040.4.3",     // dsa-with-sha1
+];
+
+#[repr(C)]
+pub struct IrisCertAudit {
+    pub status: u8,             // CERT_EXPIRY_*
+    pub days_remaining: i64,    // whole days until notAfter, negative once expired
+    pub not_after: i64,         // unix seconds
+    pub key_type: u8,           // CERT_KEY_*
+    pub key_bits: u32,          // RSA modulus, EC curve or DSA prime size; 0 if unknown
+    pub weak: u32,              // CERT_WEAK_*
+    pub subject: *mut c_char,   // "CN=..., O=..."; "" when status is CERT_EXPIRY_INVALID
+    pub sha256: *mut c_char,    // fingerprint of the DER encoding, hex
+}
+
+#[repr(C)]
+pub struct IrisCertAudits {
+    pub items: *mut IrisCertAudit, // one per input, in input order
+    pub count: usize,
+}
+
+#[derive(Default)]
+pub struct Audit {
+    pub status: u8,
+    pub days_remaining: i64,
+    pub not_after: i64,
+    pub key_type: u8,
+    pub key_bits: u32,
+    pub weak: u32,
+    pub subject: String,
+    pub sha256: String,
+}
+
+fn bit_length(int: &[u8]) -> u32 {
+    let b = &int[int.iter().position(|&x| x != 0).unwrap_or(int.len())..];
+    b.first().map_or(0, |&hi| (b.len() as u32 - 1) * 8 + (8 - hi.leading_zeros()))
+}
+
+/// Key type, size and weaknesses of a SubjectPublicKeyInfo.
+fn key_strength(spki: &[u8]) -> Option<(u8, u32, u32)> {
+    let (spki, _) = read_tlv(spki)?;
+    let mut f = spki.children();
+    let mut alg = f.next()?.children();
+    let oid = alg.next()?.as_oid()?;
+    let params = alg.next();
+    let key = f.next()?.content.get(1..)?; // BIT STRING, unused-bits byte first
+    Some(match oid.as_str() {
+        OID_RSA | OID_RSA_PSS => {
+            let (rsa, _) = read_tlv(key)?;
+            let mut ints = rsa.children();
+            let bits = bit_length(ints.next()?.content);
+            let e = ints.next()?.content;
+            let small_e = bit_length(e) < 32 && e.iter().fold(0u32, |a, &b| a << 8 | b as u32) < MIN_RSA_EXPONENT;
+            let weak = if bits < MIN_RSA_BITS { CERT_WEAK_RSA_KEY } else { 0 } | if small_e { CERT_WEAK_RSA_EXPONENT } else { 0 };
+            (CERT_KEY_RSA, bits, weak)
+        }
+        OID_EC => {
+            let curve = params.and_then(|p| p.as_oid());
+            let bits = CURVES.iter().find(|(c, _)| Some(*c) == curve.as_deref()).map_or(0, |&(_, b)| b);
+            (CERT_KEY_EC, bits, if bits != 0 && bits < MIN_EC_BITS { CERT_WEAK_EC_CURVE } else { 0 })
+        }
+        OID_DSA => {
+            let bits = params.and_then(|p| p.children().next()).map_or(0, |p| bit_length(p.content));
+            (CERT_KEY_DSA, bits, CERT_WEAK_DSA_KEY)
+        }
+        OID_ED25519 => (CERT_KEY_ED25519, 256, 0),
+        OID_ED448 => (CERT_KEY_ED448, 456, 0),
+        _ => (CERT_KEY_UNKNOWN, 0, 0),
+    })
+}
+
+/// Audit one certificate at `now` (unix seconds), warning `warn_days` ahead of expiry.
+pub fn audit(c: &Certificate, now: i64, warn_days: u32) -> Audit {
+    let (key_type, key_bits, mut weak) = key_strength(c.spki).unwrap_or_default();
+    // A root's own signature is never checked, so its hash does not matter
+    if c.issuer.raw != c.subject.raw && WEAK_SIGNATURES.contains(&c.signature_algorithm.as_str()) {
+        weak |= CERT_WEAK_SIGNATURE_HASH;
+    }
+    let days_remaining = (c.not_after - now).div_euclid(86400);
+    let status = if now < c.not_before {
+        CERT_EXPIRY_NOT_YET_VALID
+    } else if now > c.not_after {
+        CERT_EXPIRY_EXPIRED
+    } else if days_remaining < warn_days as i64 {
+        CERT_EXPIRY_EXPIRING
+    } else {
+        CERT_EXPIRY_VALID
+    };
+    Audit {
+        status, days_remaining, not_after: c.not_after, key_type, key_bits, weak,
+        subject: c.subject.display(),
+        sha256: to_hex(&sha256_digest(c.raw)),
+    }
+}
+
+/// Audit a DER or PEM certificate (the first PEM block).
+fn audit_bytes(data: &[u8], now: i64, warn_days: u32) -> Audit {
+    let der = base64::pem_decode(data);
+    match x509::parse(der.as_deref().unwrap_or(data)) {
+        Some(c) => audit(&c, now, warn_days),
+        None => Audit { status: CERT_EXPIRY_INVALID, ..Default::default() },
+    }
+}
+
+/// Run `f` over every input on a scoped thread per core, keeping input order.
+fn parallel<T: Sync, R: Send>(inputs: &[T], f: impl Fn(&T) -> R + Sync) -> Vec<R> {
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get()).min(inputs.len()).max(1);
+    let chunk = inputs.len().div_ceil(threads).max(1);
+    std::thread::scope(|s| {
+        let handles: Vec<_> = inputs.chunks(chunk).map(|part| s.spawn(|| part.iter().map(&f).collect::<Vec<_>>())).collect();
+        handles.into_iter().flat_map(|h| h.join().unwrap_or_default()).collect()
+    })
+}
+
+fn write_audits(audits: Vec<Audit>, out: *mut IrisCertAudits) {
+    let (items, count) = alloc_array(audits.into_iter().map(|a| IrisCertAudit {
+        status: a.status, days_remaining: a.days_remaining, not_after: a.not_after,
+        key_type: a.key_type, key_bits: a.key_bits, weak: a.weak,
+        subject: to_cstr(&a.subject), sha256: to_cstr(&a.sha256),
+    }).collect());
+    unsafe { out.write(IrisCertAudits { items, count }); }
+}
+
+// --- FFI ---
+
+/// Audit `count` certificates (DER or PEM) at `now` (unix seconds), marking those
+/// that expire within `warn_days` as CERT_EXPIRY_EXPIRING. Entries that do not
+/// parse come back as CERT_EXPIRY_INVALID. Certificates are checked in
+/// parallel. Returns 0=ok, -2=arg error. Free with iris_cert_audits_free.
+#[no_mangle]
+pub extern "C" fn iris_cert_audit(
+    certs: *const IrisSlice, count: usize, now: i64, warn_days: u32, out: *mut IrisCertAudits,
+) -> i32 {
+    if (certs.is_null() && count > 0) || out.is_null() { return -2; }
+    let certs: &[IrisSlice] = if count == 0 { &[] } else { unsafe { std::slice::from_raw_parts(certs, count) } };
+    // Raw pointers are not Send; the caller's buffers outlive the scoped threads
+    let slices: Vec<&[u8]> = certs.iter()
+        .map(|s| if s.ptr.is_null() { &[][..] } else { unsafe { std::slice::from_raw_parts(s.ptr, s.len) } })
+        .collect();
+    write_audits(parallel(&slices, |d| audit_bytes(d, now, warn_days)), out);
+    0
+}
+
+/// As iris_cert_audit, reading each certificate from a file path (.cer, .crt,
+/// .pem; the first certificate of a PEM file). Unreadable files come back as
+/// CERT_EXPIRY_INVALID. Returns 0=ok, -2=arg error.
+#[no_mangle]
+pub extern "C" fn iris_cert_audit_files(
+    paths: *const *const c_char, count: usize, now: i64, warn_days: u32, out: *mut IrisCertAudits,
+) -> i32 {
+    if (paths.is_null() && count > 0) || out.is_null() { return -2; }
+    let paths: Vec<Option<String>> = (0..count).map(|i| {
+        let p = unsafe { *paths.add(i) };
+        if p.is_null() { None } else { unsafe { CStr::from_ptr(p) }.to_str().ok().map(String::from) }
+    }).collect();
+    let audits = parallel(&paths, |p| match p.as_ref().and_then(|p| std::fs::read(p).ok()) {
+        Some(d) => audit_bytes(&d, now, warn_days),
+        None => Audit { status: CERT_EXPIRY_INVALID, ..Default::default() },
+    });
+    write_audits(audits, out);
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn iris_cert_audits_free(a: *mut IrisCertAudits) {
+    if a.is_null() { return; }
+    let a = unsafe { &*a };
+    if !a.items.is_null() {
+        for c in unsafe { std::slice::from_raw_parts(a.items, a.count) } {
+            free_cstr(c.subject);
+            free_cstr(c.sha256);
+        }
+    }
+    free_array(a.items, a.count);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::x509::tests::{cert, cert_with, name, tlv, CN};
+
+    const JAN_2024: i64 = 1704067200;
+    const DAY: i64 = 86400;
+
+    fn rsa_spki(modulus_bytes: usize, e: &[u8]) -> Vec<u8> {
+        let alg = tlv(0x30, &[tlv(0x06, &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 1, 1, 1]), tlv(0x05, &[])].concat());
+        let n = [&[0x00, 0xc1][..], &vec![0x5a; modulus_bytes - 1]].concat();
+        let key = tlv(0x30, &[tlv(0x02, &n), tlv(0x02, e)].concat());
+        tlv(0x30, &[alg, tlv(0x03, &[&[0][..], &key].concat())].concat())
+    }
+
+    fn ec_spki(curve: &[u8]) -> Vec<u8> {
+        let alg = tlv(0x30, &[tlv(0x06, &[0x2a, 0x86, 0x48, 0xce, 0x3d, 2, 1]), tlv(0x06, curve)].concat());
+        tlv(0x30, &[alg, tlv(0x03, &[0, 4, 1, 2])].concat())
+    }
+
+    #[test]
+    fn key_strengths() {
+        assert_eq!(key_strength(&rsa_spki(256, &[1, 0, 1])), Some((CERT_KEY_RSA, 2048, 0)));
+        assert_eq!(key_strength(&rsa_spki(128, &[3])), Some((CERT_KEY_RSA, 1024, CERT_WEAK_RSA_KEY | CERT_WEAK_RSA_EXPONENT)));
+        // Past the 4096-bit cap of the signature-verification path
+        assert_eq!(key_strength(&rsa_spki(1024, &[1, 0, 1])), Some((CERT_KEY_RSA, 8192, 0)));
+        assert_eq!(key_strength(&ec_spki(&[0x2a, 0x86, 0x48, 0xce, 0x3d, 3, 1, 7])), Some((CERT_KEY_EC, 256, 0)));
+        assert_eq!(key_strength(&ec_spki(&[0x2a, 0x86, 0x48, 0xce, 0x3d, 3, 1, 1])), Some((CERT_KEY_EC, 192, CERT_WEAK_EC_CURVE)));
+        let ed = tlv(0x30, &[tlv(0x30, &tlv(0x06, &[0x2b, 101, 112])), tlv(0x03, &[0; 33])].concat());
+        assert_eq!(key_strength(&ed), Some((CERT_KEY_ED25519, 256, 0)));
+        assert_eq!(key_strength(&[0x30, 0]), None);
+    }
+
+    #[test]
+    fn expiry_status() {
+        let (subj, ca) = (name(&[(CN, "www.example.com")]), name(&[(CN, "CA")]));
+        let der = cert_with(1, &subj, &ca, b"20240301000000Z", &[]);
+        let c = x509::parse(&der).unwrap();
+        let a = audit(&c, JAN_2024 + 10 * DAY, 30);
+        assert_eq!((a.status, a.days_remaining), (CERT_EXPIRY_VALID, 50));
+        assert_eq!(a.subject, "CN=www.example.com");
+        assert_eq!(a.sha256, to_hex(&sha256_digest(c.raw)));
+        assert_eq!(audit(&c, JAN_2024 + 40 * DAY, 30).status, CERT_EXPIRY_EXPIRING);
+        let a = audit(&c, JAN_2024 + 62 * DAY, 30);
+        assert_eq!((a.status, a.days_remaining), (CERT_EXPIRY_EXPIRED, -2));
+        assert_eq!(audit(&c, JAN_2024 - DAY, 30).status, CERT_EXPIRY_NOT_YET_VALID);
+
+        // sha1WithRSAEncryption matters on a leaf, not on a self-signed root
+        let sha1 = |der: Vec<u8>| {
+            let alg = [0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 1, 1];
+            let mut der = der;
+            while let Some(i) = der.windows(9).position(|w| w[..8] == alg && w[8] == 11) { der[i + 8] = 5; }
+            der
+        };
+        let leaf = sha1(cert(1, &subj, &ca));
+        assert_eq!(audit(&x509::parse(&leaf).unwrap(), JAN_2024, 30).weak, CERT_WEAK_SIGNATURE_HASH);
+        let root = sha1(cert(1, &ca, &ca));
+        assert_eq!(audit(&x509::parse(&root).unwrap(), JAN_2024, 30).weak, 0);
+    }
+
+    #[test]
+    fn batch_ffi() {
+        let n = name(&[(CN, "a")]);
+        let ders: Vec<Vec<u8>> = (0..20).map(|i| cert_with(i, &n, &n, if i % 2 == 0 { b"20250101000000Z" } else { b"20240101120000Z" }, &[])).collect();
+        let mut slices: Vec<IrisSlice> = ders.iter().map(|d| IrisSlice { ptr: d.as_ptr(), len: d.len() }).collect();
+        slices.push(IrisSlice { ptr: b"junk".as_ptr(), len: 4 });
+        let mut out = std::mem::MaybeUninit::<IrisCertAudits>::uninit();
+        assert_eq!(iris_cert_audit(slices.as_ptr(), slices.len(), JAN_2024 + DAY, 30, out.as_mut_ptr()), 0);
+        let r = unsafe { &mut *out.as_mut_ptr() };
+        let items = unsafe { std::slice::from_raw_parts(r.items, r.count) };
+        assert_eq!(items.len(), 21);
+        for (i, a) in items[..20].iter().enumerate() {
+            assert_eq!(a.status, if i % 2 == 0 { CERT_EXPIRY_VALID } else { CERT_EXPIRY_EXPIRED }, "{i}");
+        }
+        assert_eq!(items[20].status, CERT_EXPIRY_INVALID);
+        iris_cert_audits_free(r);
+
+        let path = std::env::temp_dir().join(format!("iris-certaudit-{}.pem", std::process::id()));
+        let pem = format!("-----BEGIN CERTIFICATE-----\n{}\n-----END CERTIFICATE-----\n", base64::encode(&ders[0]));
+        std::fs::write(&path, pem).unwrap();
+        let p = std::ffi::CString::new(path.to_str().unwrap()).unwrap();
+        let paths = [p.as_ptr(), c"/nonexistent/iris.cer".as_ptr()];
+        assert_eq!(iris_cert_audit_files(paths.as_ptr(), 2, JAN_2024 + DAY, 30, out.as_mut_ptr()), 0);
+        std::fs::remove_file(&path).unwrap();
+        let r = unsafe { &mut *out.as_mut_ptr() };
+        let items = unsafe { std::slice::from_raw_parts(r.items, r.count) };
+        assert_eq!((items[0].status, items[0].days_remaining), (CERT_EXPIRY_VALID, 365));
+        assert_eq!(unsafe { CStr::from_ptr(items[0].subject) }, c"CN=a");
+        assert_eq!(items[1].status, CERT_EXPIRY_INVALID);
+        iris_cert_audits_free(r);
+        assert_eq!(iris_cert_audit(std::ptr::null(), 1, 0, 0, out.as_mut_ptr()), -2);
+    }
+}
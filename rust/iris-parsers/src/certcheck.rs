@@ -0,0 +1,199 @@
+//! Anomaly heuristics for a server certificate: self-signed leaves, validity far
+//! beyond what public CAs issue, no subjectAltName, machine-generated subjects and
+//! issuers naming a TLS interception product, rolled into a 0-100 score. Meant for
+//! triage; a private CA or a lab device trips several of these legitimately.
+
+use crate::base64;
+use crate::x509::{self, Certificate};
+use std::ffi::{c_char, CStr};
+
+pub const CERT_FLAG_SELF_SIGNED_LEAF: u32 = 0x01; // issuer == subject without basicConstraints cA
+pub const CERT_FLAG_LONG_VALIDITY: u32 = 0x02;    // leaf valid for more than 825 days
+pub const CERT_FLAG_NO_SAN: u32 = 0x04;           // leaf without DNS or IP subjectAltName
+pub const CERT_FLAG_RANDOM_SUBJECT: u32 = 0x08;   // subject CN or O looks machine-generated
+pub const CERT_FLAG_INTERCEPTION: u32 = 0x10;     // issuer names a TLS interception product
+
+/// Longest leaf lifetime the CA/Browser Forum allowed before 2020 (825 days).
+const MAX_LEAF_VALIDITY_DAYS: i64 = 825;
+
+const OID_CN: &str = "2.5.4.3";
+const OID_O: &str = "2.5.4.10";
+
+const WEIGHTS: &[(u32, u8)] = &[
+    (CERT_FLAG_SELF_SIGNED_LEAF, 30),
+    (CERT_FLAG_LONG_VALIDITY, 20),
+    (CERT_FLAG_NO_SAN, 15),
+    (CERT_FLAG_RANDOM_SUBJECT, 25),
+    (CERT_FLAG_INTERCEPTION, 50),
+];
+
+/// Issuer substrings (lowercase) of interception proxies, antivirus web shields and
+/// debugging proxies that mint certificates on the fly, with the product name.
+const INTERCEPTORS: &[(&str, &CStr)] = &[
+    ("zscaler", c"Zscaler"),
+    ("fortigate", c"Fortinet FortiGate"),
+    ("fortinet", c"Fortinet FortiGate"),
+    ("palo alto networks", c"Palo Alto Networks"),
+    ("blue coat", c"Symantec Blue Coat ProxySG"),
+    ("cisco umbrella", c"Cisco Umbrella"),
+    ("netskope", c"Netskope"),
+    ("forcepoint", c"Forcepoint"),
+    ("websense", c"Forcepoint"),
+    ("mcafee web gateway", c"McAfee Web Gateway"),
+    ("barracuda", c"Barracuda Web Security Gateway"),
+    ("sonicwall", c"SonicWall DPI-SSL"),
+    ("watchguard", c"WatchGuard"),
+    ("untangle", c"Untangle"),
+    ("smoothwall", c"Smoothwall"),
+    ("sophos", c"Sophos"),
+    ("kaspersky", c"Kaspersky"),
+    ("eset ssl filter", c"ESET"),
+    ("bitdefender", c"Bitdefender"),
+    ("avast", c"Avast"),
+    ("avg web/mail shield", c"AVG"),
+    ("superfish", c"Superfish"),
+    ("komodia", c"Komodia"),
+    ("mitmproxy", c"mitmproxy"),
+    ("portswigger", c"Burp Suite"),
+    ("charles proxy", c"Charles Proxy"),
+    ("fiddler", c"Fiddler"),
+];
+
+#[repr(C)]
+pub struct IrisCertAnomalies {
+    pub score: u8,                        // 0-100, higher is more suspicious
+    pub flags: u32,                       // CERT_FLAG_*
+    pub validity_days: i64,               // notAfter - notBefore
+    pub interception_product: *const c_char, // CERT_FLAG_INTERCEPTION match; static, NULL otherwise
+}
+
+pub struct Anomalies {
+    pub score: u8,
+    pub flags: u32,
+    pub validity_days: i64,
+    pub interception_product: Option<&'static CStr>,
+}
+
+fn is_vowel(c: u8) -> bool {
+    matches!(c, b'a' | b'e' | b'i' | b'o' | b'u' | b'y')
+}
+
+/// Whether a name component reads as generated rather than chosen: a long label
+/// with a six-consonant run, constant letter/digit alternation or almost no vowels
+/// ("xkqwzvbt", "a8f3k2x9q1", "kjdhfgskdjfhg").
+fn looks_random(s: &str) -> bool {
+    let s = s.trim_start_matches("*.");
+    // The longest label or word carries the generated part ("qzxkvbwt.example.net")
+    let label = s.split(['.', ' ']).max_by_key(|l| l.len()).unwrap_or("").to_ascii_lowercase();
+    let b = label.as_bytes();
+    if b.len() < 8 || !b.iter().all(u8::is_ascii_alphanumeric) { return false; }
+    let letters = b.iter().filter(|c| c.is_ascii_alphabetic()).count();
+    let vowels = b.iter().filter(|&&c| is_vowel(c)).count();
+    let (mut run, mut longest_run) = (0, 0);
+    for &c in b {
+        run = if c.is_ascii_alphabetic() && !is_vowel(c) { run + 1 } else { 0 };
+        longest_run = longest_run.max(run);
+    }
+    let switches = b.windows(2).filter(|w| w[0].is_ascii_digit() != w[1].is_ascii_digit()).count();
+    longest_run >= 6 || switches >= 4 || (letters >= 10 && vowels * 5 < letters)
+}
+
+/// Score one certificate. CA certificates are only checked for interception
+/// issuers and generated subjects, since roots legitimately live for decades.
+pub fn check(c: &Certificate) -> Anomalies {
+    let mut flags = 0;
+    let validity_days = (c.not_after - c.not_before) / 86400;
+    if !c.ca {
+        if c.issuer.raw == c.subject.raw { flags |= CERT_FLAG_SELF_SIGNED_LEAF; }
+        if validity_days > MAX_LEAF_VALIDITY_DAYS { flags |= CERT_FLAG_LONG_VALIDITY; }
+        if c.san.is_empty() { flags |= CERT_FLAG_NO_SAN; }
+    }
+    if [OID_CN, OID_O].iter().filter_map(|oid| c.subject.get(oid)).any(looks_random) {
+        flags |= CERT_FLAG_RANDOM_SUBJECT;
+    }
+    let issuer = c.issuer.display().to_ascii_lowercase();
+    let interception_product = INTERCEPTORS.iter().find(|(needle, _)| issuer.contains(needle)).map(|(_, name)| *name);
+    if interception_product.is_some() { flags |= CERT_FLAG_INTERCEPTION; }
+    let score = WEIGHTS.iter().filter(|(f, _)| flags & f != 0).map(|(_, w)| *w as u32).sum::<u32>().min(100) as u8;
+    Anomalies { score, flags, validity_days, interception_product }
+}
+
+// --- FFI ---
+
+/// Score a certificate (DER or PEM, e.g. the leaf of a TLS Certificate message)
+/// for signs of interception, malware infrastructure or misconfiguration.
+/// Returns 0=ok, -2=arg error or not a certificate.
+#[no_mangle]
+pub extern "C" fn iris_x509_check(data: *const u8, len: usize, out: *mut IrisCertAnomalies) -> i32 {
+    if data.is_null() || out.is_null() { return -2; }
+    let data = unsafe { std::slice::from_raw_parts(data, len) };
+    let der = base64::pem_decode(data);
+    let Some(c) = x509::parse(der.as_deref().unwrap_or(data)) else { return -2 };
+    let a = check(&c);
+    unsafe {
+        out.write(IrisCertAnomalies {
+            score: a.score,
+            flags: a.flags,
+            validity_days: a.validity_days,
+            interception_product: a.interception_product.map_or(std::ptr::null(), CStr::as_ptr),
+        });
+    }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::x509::tests::{ca_ext, cert, cert_with, name, san_ext, CN};
+
+    const O: &[u8] = &[0x55, 4, 10];
+
+    fn check_der(der: &[u8]) -> Anomalies {
+        check(&x509::parse(der).unwrap())
+    }
+
+    #[test]
+    fn clean_leaf() {
+        let der = cert_with(1, &name(&[(CN, "www.example.com")]), &name(&[(CN, "R11"), (O, "Let's Encrypt")]),
+                            b"20240331000000Z", &[san_ext(&["www.example.com"])]);
+        let a = check_der(&der);
+        assert_eq!((a.score, a.flags, a.validity_days), (0, 0, 90));
+    }
+
+    #[test]
+    fn self_signed_random_leaf() {
+        // Typical of malware C2: self-signed, ten years, no SAN, generated name
+        let n = name(&[(CN, "xkqwzvbtrm.com"), (O, "Pjgmb Ltd")]);
+        let a = check_der(&cert(1, &n, &n));
+        let all = CERT_FLAG_SELF_SIGNED_LEAF | CERT_FLAG_LONG_VALIDITY | CERT_FLAG_NO_SAN | CERT_FLAG_RANDOM_SUBJECT;
+        assert_eq!((a.flags, a.score), (all, 90));
+
+        // A long-lived self-signed root is normal for a CA
+        let a = check_der(&cert_with(1, &name(&[(CN, "Example Root CA")]), &name(&[(CN, "Example Root CA")]),
+                                     b"20440101000000Z", &[ca_ext()]));
+        assert_eq!(a.flags, 0);
+    }
+
+    #[test]
+    fn interception_issuer() {
+        let der = cert_with(1, &name(&[(CN, "www.example.com")]), &name(&[(CN, "Zscaler Intermediate Root CA"), (O, "Zscaler Inc.")]),
+                            b"20240401000000Z", &[san_ext(&["www.example.com"])]);
+        let pem = format!("-----BEGIN CERTIFICATE-----\n{}\n-----END CERTIFICATE-----\n", base64::encode(&der));
+        let mut out = std::mem::MaybeUninit::<IrisCertAnomalies>::uninit();
+        assert_eq!(iris_x509_check(b"junk".as_ptr(), 4, out.as_mut_ptr()), -2);
+        assert_eq!(iris_x509_check(pem.as_ptr(), pem.len(), out.as_mut_ptr()), 0);
+        let out = unsafe { out.assume_init() };
+        assert_eq!((out.flags, out.score), (CERT_FLAG_INTERCEPTION, 50));
+        assert_eq!(unsafe { CStr::from_ptr(out.interception_product) }, c"Zscaler");
+    }
+
+    #[test]
+    fn random_names() {
+        for s in ["xkqwzvbt", "a8f3k2x9q1.net", "*.kjdhfgskdjfhg.org", "3f9a0c7e1b2d"] {
+            assert!(looks_random(s), "{s}");
+        }
+        for s in ["www.example.com", "strengths.io", "Microsoft Corporation", "api2.github.com", "localhost", "login.microsoftonline.com"] {
+            assert!(!looks_random(s), "{s}");
+        }
+    }
+}
@@ -0,0 +1,361 @@
+//! CMS SignedData (RFC 5652) decoding: encapsulated content, certificate set and signer
+//! infos with their signed attributes. Covers S/MIME and PKCS#7 blobs as well as the
+//! detached signature in an Apple code signature. The messageDigest check ties the
+//! content to the signed attributes; the signature itself is not verified.
+
+use crate::base64;
+use crate::der::{read_tlv, Tlv};
+use crate::ffi::{alloc_array, alloc_bytes, free_array, free_cstr, iris_free_bytes, to_cstr};
+use crate::hash::{sha1_digest, sha256_digest, to_hex};
+use crate::x509::{self, alloc_cert_array, free_cert_array, Certificate, IrisX509Cert, Name};
+use std::ffi::{c_char, CStr};
+
+pub const OID_DATA: &str = "1.2.840.113549.1.7.1";
+const OID_SIGNED_DATA: &str = "1.2.840.113549.1.7.2";
+const OID_MESSAGE_DIGEST: &str = "1.2.840.113549.1.9.4";
+const OID_SIGNING_TIME: &str = "1.2.840.113549.1.9.5";
+const OID_SHA1: &str = "1.3.14.3.2.26";
+const OID_SHA256: &str = "2.16.840.1.101.3.4.2.1";
+const BLOB_WRAPPER_MAGIC: &[u8] = &[0xfa, 0xde, 0x0b, 0x01];
+
+/// Names of common signed attributes, for logs.
+const ATTRIBUTE_NAMES: &[(&str, &CStr)] = &[
+    ("1.2.840.113549.1.9.3", c"contentType"),
+    (OID_MESSAGE_DIGEST, c"messageDigest"),
+    (OID_SIGNING_TIME, c"signingTime"),
+    ("1.2.840.113549.1.9.15", c"smimeCapabilities"),
+    ("1.2.840.113549.1.9.16.2.11", c"smimeEncryptionKeyPreference"),
+    ("1.2.840.113549.1.9.16.2.12", c"signingCertificate"),
+    ("1.2.840.113549.1.9.16.2.47", c"signingCertificateV2"),
+    ("1.2.840.113549.1.9.52", c"cmsAlgorithmProtection"),
+    ("1.2.840.113635.100.9.1", c"appleCodeSigningHashAgility"),   // plist of cdhashes
+    ("1.2.840.113635.100.9.2", c"appleCodeSigningHashAgilityV2"), // (digest OID, cdhash) pairs
+];
+
+pub struct SignerInfo<'a> {
+    pub issuer: Option<Name<'a>>,     // issuerAndSerialNumber; None for subjectKeyIdentifier sids
+    pub serial: &'a [u8],
+    pub subject_key_id: &'a [u8],     // empty for issuerAndSerialNumber sids
+    pub digest_algorithm: String,     // OID
+    pub signature_algorithm: String,  // OID; empty if missing
+    pub attributes: Vec<(String, &'a [u8])>, // signed attributes: (OID, DER of the first value)
+    pub message_digest: Option<&'a [u8]>,
+    pub signing_time: Option<i64>,
+}
+
+pub struct SignedData<'a> {
+    pub content_type: String,         // eContentType OID
+    pub content: Vec<u8>,             // eContent, constructed OCTET STRING chunks joined
+    pub detached: bool,               // no eContent; the signed data travels separately
+    pub certificates: Vec<Certificate<'a>>,
+    pub signers: Vec<SignerInfo<'a>>,
+}
+
+/// OCTET STRING contents, concatenating BER constructed chunks.
+fn octets(t: &Tlv, out: &mut Vec<u8>, depth: usize) {
+    if t.constructed {
+        if depth >= 8 { return; }
+        for c in t.children() { octets(&c, out, depth + 1); }
+    } else {
+        out.extend_from_slice(t.content);
+    }
+}
+
+fn parse_signer<'a>(t: &Tlv<'a>) -> Option<SignerInfo<'a>> {
+    let mut f = t.children();
+    f.next()?; // version
+    let sid = f.next()?;
+    let (issuer, serial, subject_key_id) = if sid.is(0, 16) {
+        let mut ias = sid.children();
+        (Some(x509::parse_name(&ias.next()?)?), ias.next()?.content, &[][..])
+    } else {
+        (None, &[][..], sid.content)
+    };
+    let digest_algorithm = f.next()?.children().next()?.as_oid()?;
+    let mut s = SignerInfo {
+        issuer, serial, subject_key_id, digest_algorithm,
+        signature_algorithm: String::new(),
+        attributes: Vec::new(),
+        message_digest: None,
+        signing_time: None,
+    };
+    let mut next = f.next()?;
+    if next.is_context(0) {
+        for attr in next.children() {
+            let mut a = attr.children();
+            let oid = a.next()?.as_oid()?;
+            let value = a.next()?.children().next()?;
+            match oid.as_str() {
+                OID_MESSAGE_DIGEST => s.message_digest = Some(value.content),
+                OID_SIGNING_TIME => s.signing_time = value.as_time(),
+                _ => {}
+            }
+            s.attributes.push((oid, value.raw));
+        }
+        next = f.next()?;
+    }
+    s.signature_algorithm = next.children().next().and_then(|t| t.as_oid()).unwrap_or_default();
+    Some(s)
+}
+
+/// Decode a ContentInfo wrapping SignedData. None if it is not SignedData.
+pub fn parse_signed_data(data: &[u8]) -> Option<SignedData<'_>> {
+    let (ci, _) = read_tlv(data)?;
+    let mut f = ci.children();
+    if f.next()?.as_oid()? != OID_SIGNED_DATA { return None; }
+    let sd = f.next()?.inner()?;
+    let mut f = sd.children();
+    f.next()?; // version
+    f.next()?; // digestAlgorithms
+    let mut encap = f.next()?.children();
+    let content_type = encap.next()?.as_oid()?;
+    let mut content = Vec::new();
+    let ec = encap.next().filter(|e| e.is_context(0));
+    if let Some(ec) = &ec { octets(&ec.inner()?, &mut content, 0); }
+
+    let mut certificates = Vec::new();
+    let mut signers = Vec::new();
+    for t in f {
+        if t.is_context(0) {
+            certificates.extend(t.children().filter_map(|c| x509::parse(c.raw)));
+        } else if t.is(0, 17) {
+            signers = t.children().filter_map(|s| parse_signer(&s)).collect();
+        }
+    }
+    Some(SignedData { content_type, content, detached: ec.is_none(), certificates, signers })
+}
+
+impl SignedData<'_> {
+    /// True if there is at least one signer and every signer's messageDigest attribute
+    /// matches the content under its digest algorithm (SHA-1 or SHA-256).
+    pub fn digest_matches(&self) -> bool {
+        !self.signers.is_empty() && self.signers.iter().all(|s| {
+            let expected = match s.digest_algorithm.as_str() {
+                OID_SHA256 => sha256_digest(&self.content).to_vec(),
+                OID_SHA1 => sha1_digest(&self.content).to_vec(),
+                _ => return false,
+            };
+            s.message_digest == Some(&expected[..])
+        })
+    }
+
+    /// Index of a signer's certificate in the set, by issuer and serial number.
+    /// Subject key identifier sids are not matched.
+    pub fn signer_certificate(&self, s: &SignerInfo) -> Option<usize> {
+        let issuer = s.issuer.as_ref()?;
+        self.certificates.iter().position(|c| c.serial == s.serial && c.issuer.raw == issuer.raw)
+    }
+
+    /// The first signer's certificate followed by its issuers found in the set.
+    pub fn signer_chain(&self) -> Vec<&Certificate<'_>> {
+        let mut chain = Vec::new();
+        let Some(signer) = self.signers.first() else { return chain };
+        let mut cur = self.signer_certificate(signer).map(|i| &self.certificates[i]);
+        while let Some(c) = cur {
+            if chain.iter().any(|p: &&Certificate| std::ptr::eq(*p, c)) { break; }
+            chain.push(c);
+            if c.issuer.raw == c.subject.raw { break; }
+            cur = self.certificates.iter().find(|i| i.subject.raw == c.issuer.raw);
+        }
+        chain
+    }
+}
+
+#[repr(C)]
+pub struct IrisCmsAttribute {
+    pub oid: *mut c_char,
+    pub name: *const c_char,          // "signingTime"; NULL if not well known. Static, do not free
+    pub value: *mut u8,               // DER of the first attribute value
+    pub value_len: usize,
+}
+
+#[repr(C)]
+pub struct IrisCmsSigner {
+    pub issuer: *mut c_char,          // "CN=..., O=..."; empty for subject key identifier sids
+    pub serial: *mut c_char,          // hex
+    pub subject_key_id: *mut c_char,  // hex; empty for issuer and serial sids
+    pub digest_algorithm: *mut c_char,    // OID
+    pub signature_algorithm: *mut c_char, // OID
+    pub message_digest: *mut c_char,  // hex; empty if absent
+    pub signing_time: i64,            // unix seconds, 0 if absent
+    pub certificate: isize,           // index into IrisCmsSignedData.certificates, -1 if not included
+    pub attributes: *mut IrisCmsAttribute,
+    pub attribute_count: usize,
+}
+
+#[repr(C)]
+pub struct IrisCmsSignedData {
+    pub content_type: *mut c_char,    // eContentType OID
+    pub content: *mut u8,             // NULL when detached
+    pub content_len: usize,
+    pub detached: bool,               // content signed separately, as in code signatures
+    pub digest_valid: bool,           // every messageDigest matches the content; false when detached
+    pub certificates: *mut IrisX509Cert,
+    pub certificate_count: usize,
+    pub signers: *mut IrisCmsSigner,
+    pub signer_count: usize,
+}
+
+fn signer_summary(sd: &SignedData, s: &SignerInfo) -> IrisCmsSigner {
+    let attributes = s.attributes.iter().map(|(oid, value)| {
+        let (value, value_len) = alloc_bytes(value);
+        let name = ATTRIBUTE_NAMES.iter().find(|(o, _)| o == oid).map_or(std::ptr::null(), |(_, n)| n.as_ptr());
+        IrisCmsAttribute { oid: to_cstr(oid), name, value, value_len }
+    }).collect();
+    let (attributes, attribute_count) = alloc_array(attributes);
+    IrisCmsSigner {
+        issuer: to_cstr(&s.issuer.as_ref().map(Name::display).unwrap_or_default()),
+        serial: to_cstr(&to_hex(s.serial)),
+        subject_key_id: to_cstr(&to_hex(s.subject_key_id)),
+        digest_algorithm: to_cstr(&s.digest_algorithm),
+        signature_algorithm: to_cstr(&s.signature_algorithm),
+        message_digest: to_cstr(&to_hex(s.message_digest.unwrap_or_default())),
+        signing_time: s.signing_time.unwrap_or(0),
+        certificate: sd.signer_certificate(s).map_or(-1, |i| i as isize),
+        attributes,
+        attribute_count,
+    }
+}
+
+// --- FFI ---
+
+/// Parse a PKCS#7 / CMS SignedData blob: DER, PEM, or the CSSLOT_SIGNATURESLOT
+/// blob of a code signature (0xfade0b01 wrapper). Returns 0=ok, -2=arg error or
+/// not SignedData. Free with iris_cms_signed_data_free.
+#[no_mangle]
+pub extern "C" fn iris_cms_parse(data: *const u8, len: usize, out: *mut IrisCmsSignedData) -> i32 {
+    if data.is_null() || out.is_null() { return -2; }
+    let data = unsafe { std::slice::from_raw_parts(data, len) };
+    let pem = base64::pem_decode(data);
+    let mut der = pem.as_deref().unwrap_or(data);
+    if der.starts_with(BLOB_WRAPPER_MAGIC) { der = &der[8.min(der.len())..]; }
+    let Some(sd) = parse_signed_data(der) else { return -2 };
+    let (content, content_len) = alloc_bytes(&sd.content);
+    let (certificates, certificate_count) = alloc_cert_array(&sd.certificates.iter().collect::<Vec<_>>());
+    let (signers, signer_count) = alloc_array(sd.signers.iter().map(|s| signer_summary(&sd, s)).collect());
+    unsafe {
+        out.write(IrisCmsSignedData {
+            content_type: to_cstr(&sd.content_type),
+            content, content_len,
+            detached: sd.detached,
+            digest_valid: !sd.detached && sd.digest_matches(),
+            certificates, certificate_count,
+            signers, signer_count,
+        });
+    }
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn iris_cms_signed_data_free(sd: *mut IrisCmsSignedData) {
+    if sd.is_null() { return; }
+    let sd = unsafe { &*sd };
+    free_cstr(sd.content_type);
+    iris_free_bytes(sd.content, sd.content_len);
+    free_cert_array(sd.certificates, sd.certificate_count);
+    for i in 0..sd.signer_count {
+        let s = unsafe { &*sd.signers.add(i) };
+        for p in [s.issuer, s.serial, s.subject_key_id, s.digest_algorithm, s.signature_algorithm, s.message_digest] {
+            free_cstr(p);
+        }
+        for j in 0..s.attribute_count {
+            let a = unsafe { &*s.attributes.add(j) };
+            free_cstr(a.oid);
+            iris_free_bytes(a.value, a.value_len);
+        }
+        free_array(s.attributes, s.attribute_count);
+    }
+    free_array(sd.signers, sd.signer_count);
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::x509::tests::{cert, name, tlv, CN};
+
+    fn rsa() -> Vec<u8> {
+        tlv(0x30, &tlv(0x06, &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 1, 1, 1]))
+    }
+
+    fn content_info(sd: &[u8]) -> Vec<u8> {
+        tlv(0x30, &[tlv(0x06, &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 1, 7, 2]), tlv(0xa0, sd)].concat())
+    }
+
+    /// SignedData over `content` (eContentType id-data) with one signer whose
+    /// messageDigest and signingTime attributes are set; the signature is a placeholder.
+    pub fn signed_data(content: &[u8], signing_time: &[u8]) -> Vec<u8> {
+        let issuer = name(&[(CN, "Apple Root CA")]);
+        let signer_name = name(&[(CN, "Ticket Signing")]);
+        let sha256 = tlv(0x30, &tlv(0x06, &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01]));
+        let attrs = [
+            tlv(0x30, &[tlv(0x06, &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 1, 9, 5]), tlv(0x31, &tlv(0x17, signing_time))].concat()),
+            tlv(0x30, &[tlv(0x06, &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 1, 9, 4]), tlv(0x31, &tlv(0x04, &sha256_digest(content)))].concat()),
+        ].concat();
+        let sid = tlv(0x30, &[issuer.clone(), tlv(0x02, &[5])].concat());
+        let signer = tlv(0x30, &[tlv(0x02, &[1]), sid, sha256.clone(), tlv(0xa0, &attrs), rsa(), tlv(0x04, &[0; 4])].concat());
+        let encap = tlv(0x30, &[tlv(0x06, &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 1, 7, 1]), tlv(0xa0, &tlv(0x04, content))].concat());
+        let certs = [cert(5, &signer_name, &issuer), cert(1, &issuer, &issuer)].concat();
+        let sd = tlv(0x30, &[tlv(0x02, &[1]), tlv(0x31, &sha256), encap, tlv(0xa0, &certs), tlv(0x31, &signer)].concat());
+        content_info(&sd)
+    }
+
+    fn cs(p: *const c_char) -> String {
+        unsafe { CStr::from_ptr(p) }.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn signers_certificates_and_attributes() {
+        let blob = signed_data(b"hello", b"250301120000Z");
+        let mut out = std::mem::MaybeUninit::<IrisCmsSignedData>::uninit();
+        assert_eq!(iris_cms_parse(blob.as_ptr(), blob.len(), out.as_mut_ptr()), 0);
+        let sd = unsafe { &mut *out.as_mut_ptr() };
+        assert_eq!(cs(sd.content_type), OID_DATA);
+        assert!(!sd.detached && sd.digest_valid && sd.content_len == 5);
+        assert_eq!((sd.certificate_count, sd.signer_count), (2, 1));
+        let s = unsafe { &*sd.signers };
+        assert_eq!((cs(s.issuer), cs(s.serial), cs(s.subject_key_id)), ("CN=Apple Root CA".into(), "05".into(), String::new()));
+        assert_eq!((cs(s.digest_algorithm), cs(s.signature_algorithm)), (OID_SHA256.into(), "1.2.840.113549.1.1.1".into()));
+        assert_eq!(cs(s.message_digest), to_hex(&sha256_digest(b"hello")));
+        assert_eq!((s.signing_time, s.certificate), (1740830400, 0));
+        assert_eq!(cs(unsafe { &*sd.certificates.offset(s.certificate) }.common_name), "Ticket Signing");
+        let attrs = unsafe { std::slice::from_raw_parts(s.attributes, s.attribute_count) };
+        assert_eq!(attrs.iter().map(|a| cs(a.name)).collect::<Vec<_>>(), ["signingTime", "messageDigest"]);
+        let digest = unsafe { std::slice::from_raw_parts(attrs[1].value, attrs[1].value_len) };
+        assert_eq!(digest, tlv(0x04, &sha256_digest(b"hello")));
+        iris_cms_signed_data_free(sd);
+
+        // PEM, and the code signature blob wrapper
+        let pem = format!("-----BEGIN PKCS7-----\n{}\n-----END PKCS7-----\n", base64::encode(&blob));
+        let wrapped = [BLOB_WRAPPER_MAGIC, &(blob.len() as u32 + 8).to_be_bytes(), &blob].concat();
+        for input in [pem.as_bytes(), &wrapped] {
+            assert_eq!(iris_cms_parse(input.as_ptr(), input.len(), out.as_mut_ptr()), 0);
+            iris_cms_signed_data_free(out.as_mut_ptr());
+        }
+        assert_eq!(iris_cms_parse(b"junk".as_ptr(), 4, out.as_mut_ptr()), -2);
+    }
+
+    #[test]
+    fn detached_with_key_identifier() {
+        // Code signature style: no eContent, no certificates, signer named by key identifier
+        let sha256 = tlv(0x30, &tlv(0x06, &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01]));
+        let attrs = tlv(0x30, &[tlv(0x06, &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x63, 0x64, 9, 2]), tlv(0x31, &tlv(0x30, &[]))].concat());
+        let signer = tlv(0x30, &[tlv(0x02, &[3]), tlv(0x80, &[0xab; 4]), sha256.clone(), tlv(0xa0, &attrs), rsa(), tlv(0x04, &[0; 4])].concat());
+        let encap = tlv(0x30, &tlv(0x06, &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 1, 7, 1]));
+        let blob = content_info(&tlv(0x30, &[tlv(0x02, &[3]), tlv(0x31, &sha256), encap, tlv(0x31, &signer)].concat()));
+
+        let sd = parse_signed_data(&blob).unwrap();
+        assert!(sd.detached && sd.content.is_empty() && sd.certificates.is_empty());
+        let s = &sd.signers[0];
+        assert!(s.issuer.is_none() && s.message_digest.is_none());
+        assert_eq!((s.subject_key_id, sd.signer_certificate(s)), (&[0xab; 4][..], None));
+
+        let mut out = std::mem::MaybeUninit::<IrisCmsSignedData>::uninit();
+        assert_eq!(iris_cms_parse(blob.as_ptr(), blob.len(), out.as_mut_ptr()), 0);
+        let mut out = unsafe { out.assume_init() };
+        let s = unsafe { &*out.signers };
+        assert!(out.content.is_null() && !out.digest_valid && s.certificate == -1);
+        assert_eq!((cs(s.subject_key_id), cs(s.issuer)), ("abababab".into(), String::new()));
+        assert_eq!(cs(unsafe { &*s.attributes }.name), "appleCodeSigningHashAgilityV2");
+        iris_cms_signed_data_free(&mut out);
+    }
+}
@@ -0,0 +1,117 @@
+//! cpio archives in the portable odc ("070707", octal fields) and SVR4 newc/crc
+//! ("070701"/"070702", hex fields) formats, as used by installer payloads and scripts.
+
+const ODC_HEADER: usize = 76;
+const NEWC_HEADER: usize = 110;
+const TRAILER: &str = "TRAILER!!!";
+const S_IFMT: u32 = 0o170000;
+const S_IFREG: u32 = 0o100000;
+
+pub const CPIO_ODC: u8 = 1;
+pub const CPIO_NEWC: u8 = 2;
+
+pub struct Entry<'a> {
+    pub name: String,  // as stored, usually "./relative/path"
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub mtime: i64,
+    pub offset: usize, // of the entry data within the archive
+    pub data: &'a [u8], // file contents, or the target of a symlink
+}
+
+struct Header {
+    len: usize,
+    namesize: u64,
+    mode: u64,
+    uid: u64,
+    gid: u64,
+    mtime: u64,
+    size: u64,
+}
+
+impl Entry<'_> {
+    pub fn is_file(&self) -> bool { self.mode & S_IFMT == S_IFREG }
+}
+
+fn field(d: &[u8], radix: u32) -> Option<u64> {
+    u64::from_str_radix(std::str::from_utf8(d).ok()?, radix).ok()
+}
+
+fn align4(n: usize) -> usize { (n + 3) & !3 }
+
+/// Format of an archive starting at `d`: CPIO_*, or None.
+pub fn format(d: &[u8]) -> Option<u8> {
+    if d.starts_with(b"070707") { Some(CPIO_ODC) }
+    else if d.starts_with(b"070701") || d.starts_with(b"070702") { Some(CPIO_NEWC) }
+    else { None }
+}
+
+fn header(h: &[u8]) -> Option<Header> {
+    match format(h)? {
+        CPIO_ODC => {
+            let f = |r: std::ops::Range<usize>| field(h.get(r)?, 8);
+            Some(Header { len: ODC_HEADER, mode: f(18..24)?, uid: f(24..30)?, gid: f(30..36)?, mtime: f(48..59)?, namesize: f(59..65)?, size: f(65..76)? })
+        }
+        _ => {
+            let f = |o: usize| field(h.get(o..o + 8)?, 16);
+            Some(Header { len: NEWC_HEADER, mode: f(14)?, uid: f(22)?, gid: f(30)?, mtime: f(46)?, size: f(54)?, namesize: f(94)? })
+        }
+    }
+}
+
+/// Entries up to the trailer. Stops quietly at the first malformed or truncated header.
+pub fn entries(data: &[u8]) -> Vec<Entry<'_>> {
+    let mut out = Vec::new();
+    let mut p = 0;
+    while let Some(hdr) = data.get(p..) {
+        let Some(h) = header(hdr) else { break };
+        let newc = h.len == NEWC_HEADER;
+        let (hlen, namesize) = (h.len, h.namesize);
+        let Some(raw_name) = hdr.get(hlen..hlen + namesize as usize) else { break };
+        let name = String::from_utf8_lossy(raw_name.strip_suffix(&[0]).unwrap_or(raw_name)).into_owned();
+        if name == TRAILER { break; }
+        let mut start = hlen + namesize as usize;
+        if newc { start = align4(start); }
+        let Some(body) = usize::try_from(h.size).ok().and_then(|s| hdr.get(start..start.checked_add(s)?)) else { break };
+        let offset = p + start;
+        p += start + body.len();
+        if newc { p = align4(p); }
+        out.push(Entry { name, mode: h.mode as u32, uid: h.uid as u32, gid: h.gid as u32, mtime: h.mtime as i64, offset, data: body });
+    }
+    out
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    /// odc archive of regular files, with trailer.
+    pub fn odc(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut v = Vec::new();
+        for (name, data) in files.iter().copied().chain([(TRAILER, &b""[..])]) {
+            let mode = if name == TRAILER { 0 } else { 0o100755 };
+            v.extend_from_slice(format!("070707{:06o}{:06o}{:06o}{:06o}{:06o}{:06o}{:06o}{:011o}{:06o}{:011o}",
+                0, 1, mode, 0, 0, 1, 0, 0, name.len() + 1, data.len()).as_bytes());
+            v.extend_from_slice(name.as_bytes());
+            v.push(0);
+            v.extend_from_slice(data);
+        }
+        v
+    }
+
+    #[test]
+    fn odc_and_newc() {
+        let a = odc(&[("./postinstall", b"#!/bin/sh\nexit 0\n"), ("./bin", b"")]);
+        let e = entries(&a);
+        assert_eq!(e.len(), 2);
+        assert_eq!((e[0].name.as_str(), e[0].data, e[0].is_file()), ("./postinstall", &b"#!/bin/sh\nexit 0\n"[..], true));
+
+        let mut n = format!("070701{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}",
+            1, 0o40755, 0, 0, 2, 0, 0, 0, 0, 0, 0, 4, 0).into_bytes();
+        n.extend_from_slice(b"dir\0");
+        n.extend_from_slice(&[0; 2]);
+        let e = entries(&n);
+        assert_eq!((e.len(), e[0].name.as_str(), e[0].is_file()), (1, "dir", false));
+    }
+}
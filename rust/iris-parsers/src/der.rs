@@ -125,6 +125,16 @@ fn encode_oid_component(value: u32, out: &mut Vec<u8>) {
     }
 }
 
+/// Encoded OBJECT IDENTIFIER body for a dotted-decimal string ("1.2.840.113635.100.6.2.6").
+pub fn encode_oid(dotted: &str) -> Option<Vec<u8>> {
+    let c: Vec<u32> = dotted.split('.').map(|p| p.parse().ok()).collect::<Option<_>>()?;
+    if c.len() < 2 || c[0] > 2 || (c[0] < 2 && c[1] >= 40) { return None; }
+    let mut out = Vec::new();
+    encode_oid_component(c[1].checked_add(c[0] * 40)?, &mut out);
+    for &v in &c[2..] { encode_oid_component(v, &mut out); }
+    Some(out)
+}
+
 #[no_mangle]
 pub extern "C" fn iris_der_build_oid(
     components: *const u32, count: usize, out: *mut *mut u8, out_len: *mut usize,
@@ -214,3 +224,269 @@ pub extern "C" fn iris_der_build_generalized_time(
     let ts = format!("{:04}{:02}{:02}{:02}{:02}{:02}Z", y, mo, d, h, mi, s);
     write_result(&build_tlv(0x18, ts.as_bytes()), out, out_len)
 }
+
+// --- Decoding ---
+
+/// One decoded TLV. `content` borrows from the input buffer.
+#[derive(Clone, Copy)]
+pub struct Tlv<'a> {
+    pub class: u8,       // 0=universal, 1=application, 2=context, 3=private
+    pub constructed: bool,
+    pub number: u32,     // tag number (multi-byte form supported)
+    pub content: &'a [u8],
+    pub raw: &'a [u8],   // full encoding, header included
+}
+
+impl<'a> Tlv<'a> {
+    pub fn is(&self, class: u8, number: u32) -> bool { self.class == class && self.number == number }
+    pub fn is_context(&self, number: u32) -> bool { self.is(2, number) }
+    pub fn children(&self) -> DerReader<'a> { DerReader::new(self.content) }
+
+    /// First child of an EXPLICIT [n] wrapper.
+    pub fn inner(&self) -> Option<Tlv<'a>> { read_tlv(self.content).map(|(t, _)| t) }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        let c = self.content;
+        if c.is_empty() || c.len() > 8 { return None; }
+        let mut v: i64 = if c[0] & 0x80 != 0 { -1 } else { 0 };
+        for &b in c { v = (v << 8) | b as i64; }
+        Some(v)
+    }
+
+    pub fn as_string(&self) -> String { String::from_utf8_lossy(self.content).into_owned() }
+
+    pub fn as_oid(&self) -> Option<String> { decode_oid(self.content) }
+
+    /// UTCTime / GeneralizedTime → unix seconds.
+    pub fn as_time(&self) -> Option<i64> {
+        let s = std::str::from_utf8(self.content).ok()?;
+        match self.number {
+            23 => parse_time(s, false),
+            24 => parse_time(s, true),
+            _ => None,
+        }
+    }
+}
+
+/// Sequential reader over concatenated TLVs.
+pub struct DerReader<'a> { data: &'a [u8], pos: usize }
+
+impl<'a> DerReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self { DerReader { data, pos: 0 } }
+}
+
+impl<'a> Iterator for DerReader<'a> {
+    type Item = Tlv<'a>;
+    fn next(&mut self) -> Option<Tlv<'a>> {
+        let (tlv, used) = read_tlv(&self.data[self.pos..])?;
+        self.pos += used;
+        Some(tlv)
+    }
+}
+
+/// Decode one TLV. Returns the TLV and bytes consumed. BER indefinite lengths are
+/// accepted on constructed tags (CMS from Apple tooling uses them).
+pub fn read_tlv(data: &[u8]) -> Option<(Tlv<'_>, usize)> {
+    read_tlv_nested(data, 0)
+}
+
+fn read_tlv_nested(data: &[u8], depth: usize) -> Option<(Tlv<'_>, usize)> {
+    let first = *data.first()?;
+    let mut off = 1usize;
+    let mut number = (first & 0x1F) as u32;
+    if number == 0x1F {
+        number = 0;
+        loop {
+            let b = *data.get(off)?;
+            off += 1;
+            number = number.checked_mul(128)? | (b & 0x7F) as u32;
+            if b & 0x80 == 0 { break; }
+            if off > 5 { return None; }
+        }
+    }
+    let lb = *data.get(off)?;
+    off += 1;
+    let constructed = first & 0x20 != 0;
+    if lb == 0x80 {
+        // Indefinite: children run up to the 00 00 end-of-contents marker
+        if !constructed || depth >= 32 { return None; }
+        let mut end = off;
+        while data.get(end..end + 2)? != [0, 0] {
+            end += read_tlv_nested(&data[end..], depth + 1)?.1;
+        }
+        return Some((Tlv {
+            class: first >> 6, constructed, number, content: &data[off..end], raw: &data[..end + 2],
+        }, end + 2));
+    }
+    let len = if lb < 0x80 { lb as usize } else {
+        let n = (lb & 0x7F) as usize;
+        if n > 4 { return None; } // absurd lengths
+        let mut l = 0usize;
+        for _ in 0..n { l = (l << 8) | *data.get(off)? as usize; off += 1; }
+        l
+    };
+    let end = off.checked_add(len)?;
+    if end > data.len() { return None; }
+    Some((Tlv {
+        class: first >> 6, constructed, number, content: &data[off..end], raw: &data[..end],
+    }, end))
+}
+
+/// Dotted-decimal form of an encoded OBJECT IDENTIFIER body.
+pub fn decode_oid(c: &[u8]) -> Option<String> {
+    if c.is_empty() { return None; }
+    let mut parts: Vec<u64> = Vec::new();
+    let mut v = 0u64;
+    for (i, &b) in c.iter().enumerate() {
+        v = v.checked_mul(128)? | (b & 0x7F) as u64;
+        if b & 0x80 == 0 {
+            if parts.is_empty() {
+                let first = if v < 80 { v / 40 } else { 2 };
+                parts.push(first);
+                parts.push(v - first * 40);
+            } else {
+                parts.push(v);
+            }
+            v = 0;
+        } else if i == c.len() - 1 {
+            return None;
+        }
+    }
+    Some(parts.iter().map(|p| p.to_string()).collect::<Vec<_>>().join("."))
+}
+
+/// Inverse of unix_to_components (Howard Hinnant days_from_civil).
+pub fn components_to_unix(y: i64, m: u32, d: u32, hh: u32, mm: u32, ss: u32) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = (if y >= 0 { y } else { y - 399 }) / 400;
+    let yoe = y - era * 400;
+    let mp = (if m > 2 { m - 3 } else { m + 9 }) as i64;
+    let doy = (153 * mp + 2) / 5 + d as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+    days * 86400 + (hh * 3600 + mm * 60 + ss) as i64
+}
+
+/// Parse "YYMMDDHHMM[SS]Z" (UTCTime) or "YYYYMMDDHHMM[SS][.f]Z" (GeneralizedTime).
+/// Only UTC ("Z") is accepted; local times and offsets are rejected.
+pub fn parse_time(s: &str, four_digit_year: bool) -> Option<i64> {
+    let b = s.as_bytes();
+    let digits = |r: std::ops::Range<usize>| -> Option<u32> {
+        let f = b.get(r)?;
+        if !f.iter().all(u8::is_ascii_digit) { return None; }
+        Some(f.iter().fold(0, |v, &d| v * 10 + (d - b'0') as u32))
+    };
+    let (year, rest) = if four_digit_year {
+        (digits(0..4)? as i64, 4)
+    } else {
+        let yy = digits(0..2)? as i64;
+        (if yy >= 50 { 1900 + yy } else { 2000 + yy }, 2)
+    };
+    let mo = digits(rest..rest + 2)?;
+    let d = digits(rest + 2..rest + 4)?;
+    let h = digits(rest + 4..rest + 6)?;
+    let mi = digits(rest + 6..rest + 8)?;
+    let mut p = rest + 8;
+    let sec = match digits(p..p + 2) {
+        Some(sec) => { p += 2; sec }
+        None => 0,
+    };
+    if four_digit_year && matches!(b.get(p), Some(b'.' | b',')) {
+        let n = b[p + 1..].iter().take_while(|c| c.is_ascii_digit()).count();
+        if n == 0 { return None; }
+        p += 1 + n;
+    }
+    if &b[p..] != b"Z" { return None; }
+    if !(1..=12).contains(&mo) || !(1..=31).contains(&d) || h > 23 || mi > 59 || sec > 60 { return None; }
+    Some(components_to_unix(year, mo, d, h, mi, sec))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tlv_lengths() {
+        let (t, n) = read_tlv(&[0x04, 0x02, 0xAA, 0xBB, 0xFF]).unwrap();
+        assert_eq!((t.number, t.content, n), (4, &[0xAA, 0xBB][..], 4));
+
+        let mut long = vec![0x04, 0x82, 0x01, 0x00];
+        long.extend([7u8; 256]);
+        let (t, n) = read_tlv(&long).unwrap();
+        assert_eq!((t.content.len(), n), (256, 260));
+        assert!(read_tlv(&long[..259]).is_none()); // content runs past the input
+        assert!(read_tlv(&[0x04, 0x82, 0x01]).is_none()); // length bytes cut short
+        assert!(read_tlv(&[0x04, 0x85, 0, 0, 0, 0, 1, 0]).is_none()); // five length bytes
+        assert!(read_tlv(&[0x04]).is_none());
+        assert!(read_tlv(&[]).is_none());
+
+        // High tag number form
+        let (t, _) = read_tlv(&[0x9F, 0x81, 0x00, 0x00]).unwrap();
+        assert_eq!((t.class, t.number), (2, 128));
+        assert!(read_tlv(&[0x1F, 0x80, 0x80, 0x80, 0x80, 0x80, 0x01, 0x00]).is_none());
+    }
+
+    #[test]
+    fn tlv_indefinite() {
+        let d = [0x30, 0x80, 0x02, 0x01, 0x05, 0x30, 0x80, 0x05, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF];
+        let (t, n) = read_tlv(&d).unwrap();
+        assert_eq!(n, 13);
+        assert_eq!(t.content, &d[2..11]);
+        let kids: Vec<_> = t.children().collect();
+        assert_eq!(kids.len(), 2);
+        assert_eq!(kids[0].as_i64(), Some(5));
+        assert!(read_tlv(&d[..12]).is_none()); // end-of-contents missing
+        assert!(read_tlv(&[0x04, 0x80, 0x00, 0x00]).is_none()); // primitive
+
+        // Nesting is capped at 32 indefinite levels
+        let nested = |depth: usize| {
+            let mut v = [0x30, 0x80].repeat(depth);
+            v.extend([0x00, 0x00].repeat(depth));
+            v
+        };
+        assert!(read_tlv(&nested(32)).is_some());
+        assert!(read_tlv(&nested(33)).is_none());
+    }
+
+    #[test]
+    fn oids() {
+        assert_eq!(decode_oid(&[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x01, 0x0B]).as_deref(), Some("1.2.840.113549.1.1.11"));
+        assert_eq!(decode_oid(&[0x55, 0x1D, 0x11]).as_deref(), Some("2.5.29.17"));
+        assert_eq!(decode_oid(&[0x88, 0x37, 0x03]).as_deref(), Some("2.999.3"));
+        assert_eq!(encode_oid("1.2.840.113549.1.1.11").and_then(|e| decode_oid(&e)).as_deref(), Some("1.2.840.113549.1.1.11"));
+        assert!(decode_oid(&[]).is_none());
+        assert!(decode_oid(&[0x2A, 0x86]).is_none()); // last arc unterminated
+        let mut big = vec![0x2A];
+        big.extend([0xFF; 10]);
+        big.push(0x7F);
+        assert!(decode_oid(&big).is_none()); // arc over 64 bits
+    }
+
+    #[test]
+    fn times() {
+        assert_eq!(parse_time("700101000000Z", false), Some(0));
+        assert_eq!(parse_time("491231235959Z", false), Some(2524607999));
+        assert_eq!(parse_time("500101000000Z", false), Some(-631152000));
+        assert_eq!(parse_time("2401150830Z", false), Some(1705307400)); // no seconds
+        assert_eq!(parse_time("20240115083000Z", true), Some(1705307400));
+        assert_eq!(parse_time("20240115083000.123Z", true), Some(1705307400));
+        assert!(parse_time("20240115083000.Z", true).is_none());
+        assert!(parse_time("240115083000.5Z", false).is_none());
+        assert!(parse_time("20240115083000", true).is_none());
+        assert!(parse_time("20240115083000+0100", true).is_none());
+        assert!(parse_time("240115083000-0500", false).is_none());
+        assert!(parse_time("240115083000ZZ", false).is_none());
+        assert!(parse_time("+4011508300Z", false).is_none());
+        assert!(parse_time("24+115083000Z", false).is_none());
+        assert!(parse_time("241315083000Z", false).is_none());
+        assert!(parse_time("240115246000Z", false).is_none());
+        assert!(parse_time("2401", false).is_none());
+
+        let t = |tag: u8, s: &[u8]| { let mut v = vec![tag, s.len() as u8]; v.extend(s); v };
+        let utc = t(0x17, b"240115083000Z");
+        assert_eq!(read_tlv(&utc).unwrap().0.as_time(), Some(1705307400));
+        let gen = t(0x18, b"20240115083000Z");
+        assert_eq!(read_tlv(&gen).unwrap().0.as_time(), Some(1705307400));
+        assert!(read_tlv(&t(0x04, b"240115083000Z")).unwrap().0.as_time().is_none());
+    }
+}
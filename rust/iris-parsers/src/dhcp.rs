@@ -0,0 +1,306 @@
+//! DHCPv4 (RFC 2131/2132) and DHCPv6 (RFC 8415) parser with option-55 / ORO
+//! fingerprinting for passive device identification.
+
+use crate::ffi::{to_cstr, free_cstr};
+use crate::hash::to_hex;
+use std::ffi::c_char;
+
+const DHCP_MAGIC: [u8; 4] = [99, 130, 83, 99];
+const DHCP_FIXED_LEN: usize = 236;
+
+/// Well-known parameter-request-list fingerprints (Fingerbank-style comma lists).
+const KNOWN_FINGERPRINTS: &[(&str, &str)] = &[
+    ("1,121,3,6,15,108,114,119,252,95,44,46", "macOS"),
+    ("1,121,3,6,15,119,252,95,44,46", "macOS"),
+    ("1,121,3,6,15,108,114,119,252", "iOS"),
+    ("1,3,6,15,119,252", "iOS"),
+    ("1,3,6,15,31,33,43,44,46,47,119,121,249,252", "Windows"),
+    ("1,15,3,6,44,46,47,31,33,121,249,43", "Windows"),
+    ("1,3,6,15,26,28,51,58,59,43", "Android"),
+    ("1,3,6,15,26,28,51,58,59,43,114,108", "Android"),
+    ("1,28,2,3,15,6,119,12,44,47,26,121,42", "Linux (dhclient)"),
+    ("1,3,6,12,15,28,42", "Linux (udhcpc)"),
+    ("1,3,6,15,28,33,51,58,59,119,121", "Linux (systemd-networkd)"),
+    ("1,3,6,12,15,17,23,28,29,31,33,40,41,42,119", "Linux (dhcpcd)"),
+];
+
+#[repr(C)]
+pub struct IrisDhcpMessage {
+    pub is_v6: bool,
+    pub op: u8,                   // v4: 1=BOOTREQUEST, 2=BOOTREPLY; 0 for v6
+    pub message_type: u8,         // v4 option 53 / v6 msg-type
+    pub transaction_id: u32,      // v6 uses the low 24 bits
+    pub client_ip: [u8; 4],       // ciaddr
+    pub your_ip: [u8; 4],         // yiaddr
+    pub server_ip: [u8; 4],       // siaddr
+    pub relay_ip: [u8; 4],        // giaddr
+    pub client_mac: [u8; 6],      // chaddr (Ethernet only)
+    pub requested_ip: [u8; 4],    // option 50
+    pub server_id: [u8; 4],       // option 54
+    pub lease_time: u32,          // option 51, 0 = absent
+    pub max_message_size: u16,    // option 57, 0 = absent
+    pub enterprise_number: u32,   // v6 vendor class enterprise, 0 = absent
+    pub is_relayed: bool,         // v6 relay-forward/reply wrapper was unwrapped
+    pub hostname: *mut c_char,    // option 12
+    pub vendor_class: *mut c_char, // option 60 / v6 option 16
+    pub client_id: *mut c_char,   // hex: option 61 / v6 client DUID
+    pub fqdn: *mut c_char,        // option 81 / v6 option 39
+    pub param_request_list: *mut c_char, // "1,3,6,15" (option 55 / v6 ORO)
+    pub option_order: *mut c_char, // every option code, in wire order
+    pub device_hint: *mut c_char, // matched known fingerprint, "" if none
+}
+
+#[derive(Default)]
+struct Dhcp {
+    is_v6: bool,
+    op: u8,
+    msg_type: u8,
+    xid: u32,
+    ciaddr: [u8; 4],
+    yiaddr: [u8; 4],
+    siaddr: [u8; 4],
+    giaddr: [u8; 4],
+    chaddr: [u8; 6],
+    requested_ip: [u8; 4],
+    server_id: [u8; 4],
+    lease_time: u32,
+    max_size: u16,
+    enterprise: u32,
+    relayed: bool,
+    hostname: String,
+    vendor_class: String,
+    client_id: String,
+    fqdn: String,
+    prl: Vec<u16>,
+    options: Vec<u16>,
+}
+
+fn lossy(data: &[u8]) -> String {
+    String::from_utf8_lossy(data).trim_end_matches('\0').to_string()
+}
+
+fn ipv4(d: &[u8]) -> [u8; 4] {
+    let mut a = [0u8; 4];
+    if d.len() >= 4 { a.copy_from_slice(&d[..4]); }
+    a
+}
+
+/// Decode an RFC 1035 uncompressed name (used by FQDN options).
+fn wire_name(mut d: &[u8]) -> String {
+    let mut labels = Vec::new();
+    while let Some(&l) = d.first() {
+        let l = l as usize;
+        if l == 0 || l + 1 > d.len() { break; }
+        labels.push(lossy(&d[1..1 + l]));
+        d = &d[1 + l..];
+    }
+    labels.join(".")
+}
+
+fn join_codes(codes: &[u16]) -> String {
+    codes.iter().map(|c| c.to_string()).collect::<Vec<_>>().join(",")
+}
+
+fn parse_v4(data: &[u8]) -> Option<Dhcp> {
+    if data.len() < DHCP_FIXED_LEN + 4 || data[236..240] != DHCP_MAGIC { return None; }
+    let mut m = Dhcp {
+        op: data[0],
+        xid: u32::from_be_bytes([data[4], data[5], data[6], data[7]]),
+        ciaddr: ipv4(&data[12..16]),
+        yiaddr: ipv4(&data[16..20]),
+        siaddr: ipv4(&data[20..24]),
+        giaddr: ipv4(&data[24..28]),
+        ..Default::default()
+    };
+    if data[1] == 1 && data[2] == 6 { m.chaddr.copy_from_slice(&data[28..34]); }
+
+    let mut off = 240;
+    while off < data.len() {
+        let code = data[off];
+        if code == 0 { off += 1; continue; }
+        if code == 255 { break; }
+        if off + 2 > data.len() { break; }
+        let len = data[off + 1] as usize;
+        let start = off + 2;
+        if start + len > data.len() { break; }
+        let v = &data[start..start + len];
+        m.options.push(code as u16);
+        match code {
+            53 if len >= 1 => m.msg_type = v[0],
+            12 => m.hostname = lossy(v),
+            60 => m.vendor_class = lossy(v),
+            61 => m.client_id = to_hex(v),
+            50 => m.requested_ip = ipv4(v),
+            54 => m.server_id = ipv4(v),
+            51 if len >= 4 => m.lease_time = u32::from_be_bytes([v[0], v[1], v[2], v[3]]),
+            57 if len >= 2 => m.max_size = u16::from_be_bytes([v[0], v[1]]),
+            55 => m.prl = v.iter().map(|&c| c as u16).collect(),
+            // flags, rcode1, rcode2, then name (wire format when E bit set)
+            81 if len >= 3 => {
+                m.fqdn = if v[0] & 0x04 != 0 { wire_name(&v[3..]) } else { lossy(&v[3..]) };
+            }
+            _ => {}
+        }
+        off = start + len;
+    }
+    Some(m)
+}
+
+fn parse_v6_options(data: &[u8], m: &mut Dhcp, depth: u8) {
+    let mut off = 0;
+    while off + 4 <= data.len() {
+        let code = u16::from_be_bytes([data[off], data[off + 1]]);
+        let len = u16::from_be_bytes([data[off + 2], data[off + 3]]) as usize;
+        let start = off + 4;
+        if start + len > data.len() { break; }
+        let v = &data[start..start + len];
+        m.options.push(code);
+        match code {
+            1 => m.client_id = to_hex(v),
+            6 => m.prl = v.chunks_exact(2).map(|c| u16::from_be_bytes([c[0], c[1]])).collect(),
+            // Relay message: the client's message is nested inside
+            9 if depth < 4 => {
+                // Fingerprint fields describe the client, not the relay agent
+                if let Some(inner) = parse_v6_at(v, depth + 1) {
+                    *m = inner;
+                    m.relayed = true;
+                }
+                return;
+            }
+            16 if len >= 4 => {
+                m.enterprise = u32::from_be_bytes([v[0], v[1], v[2], v[3]]);
+                // vendor-class-data: repeated (u16 len, opaque)
+                let mut p = 4;
+                let mut parts = Vec::new();
+                while p + 2 <= v.len() {
+                    let l = u16::from_be_bytes([v[p], v[p + 1]]) as usize;
+                    if p + 2 + l > v.len() { break; }
+                    parts.push(lossy(&v[p + 2..p + 2 + l]));
+                    p += 2 + l;
+                }
+                m.vendor_class = parts.join(" ");
+            }
+            39 if len >= 1 => m.fqdn = wire_name(&v[1..]),
+            _ => {}
+        }
+        off = start + len;
+    }
+}
+
+fn parse_v6_at(data: &[u8], depth: u8) -> Option<Dhcp> {
+    if data.len() < 4 { return None; }
+    let mut m = Dhcp { is_v6: true, msg_type: data[0], ..Default::default() };
+    if data[0] == 12 || data[0] == 13 {
+        // Relay-forward / relay-reply: hop-count, link-address, peer-address
+        if data.len() < 34 { return None; }
+        parse_v6_options(&data[34..], &mut m, depth);
+    } else {
+        m.xid = u32::from_be_bytes([0, data[1], data[2], data[3]]);
+        parse_v6_options(&data[4..], &mut m, depth);
+    }
+    Some(m)
+}
+
+fn device_hint(prl: &str) -> &'static str {
+    KNOWN_FINGERPRINTS.iter().find(|(fp, _)| *fp == prl).map(|(_, name)| *name).unwrap_or("")
+}
+
+// --- FFI entry points ---
+
+/// Parse a DHCP message from a UDP payload. `is_v6` selects DHCPv6.
+/// Returns 0=ok, -1=not DHCP/truncated, -2=arg error. Free with iris_dhcp_free.
+#[no_mangle]
+pub extern "C" fn iris_dhcp_parse(
+    data: *const u8, len: usize, is_v6: bool, out: *mut IrisDhcpMessage,
+) -> i32 {
+    if data.is_null() || out.is_null() || len == 0 { return -2; }
+    let buf = unsafe { std::slice::from_raw_parts(data, len) };
+    let parsed = if is_v6 { parse_v6_at(buf, 0) } else { parse_v4(buf) };
+    let m = match parsed { Some(m) => m, None => return -1 };
+    let prl = join_codes(&m.prl);
+    let hint = device_hint(&prl);
+    unsafe {
+        out.write(IrisDhcpMessage {
+            is_v6: m.is_v6,
+            op: m.op,
+            message_type: m.msg_type,
+            transaction_id: m.xid,
+            client_ip: m.ciaddr,
+            your_ip: m.yiaddr,
+            server_ip: m.siaddr,
+            relay_ip: m.giaddr,
+            client_mac: m.chaddr,
+            requested_ip: m.requested_ip,
+            server_id: m.server_id,
+            lease_time: m.lease_time,
+            max_message_size: m.max_size,
+            enterprise_number: m.enterprise,
+            is_relayed: m.relayed,
+            hostname: to_cstr(&m.hostname),
+            vendor_class: to_cstr(&m.vendor_class),
+            client_id: to_cstr(&m.client_id),
+            fqdn: to_cstr(&m.fqdn),
+            param_request_list: to_cstr(&prl),
+            option_order: to_cstr(&join_codes(&m.options)),
+            device_hint: to_cstr(hint),
+        });
+    }
+    0
+}
+
+/// Free strings in an IrisDhcpMessage.
+#[no_mangle]
+pub extern "C" fn iris_dhcp_free(msg: *mut IrisDhcpMessage) {
+    if msg.is_null() { return; }
+    unsafe {
+        let m = &*msg;
+        free_cstr(m.hostname);
+        free_cstr(m.vendor_class);
+        free_cstr(m.client_id);
+        free_cstr(m.fqdn);
+        free_cstr(m.param_request_list);
+        free_cstr(m.option_order);
+        free_cstr(m.device_hint);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_v4_discover_fingerprint() {
+        let mut d = vec![0u8; 240];
+        d[0] = 1; d[1] = 1; d[2] = 6;
+        d[4..8].copy_from_slice(&0xdeadbeefu32.to_be_bytes());
+        d[28..34].copy_from_slice(&[0x3c, 0x22, 0xfb, 0x01, 0x02, 0x03]);
+        d[236..240].copy_from_slice(&DHCP_MAGIC);
+        d.extend_from_slice(&[53, 1, 1]);
+        d.extend_from_slice(&[55, 12, 1, 121, 3, 6, 15, 108, 114, 119, 252, 95, 44, 46]);
+        d.extend_from_slice(&[12, 4, b'm', b'a', b'c', b'1']);
+        d.push(255);
+        let m = parse_v4(&d).unwrap();
+        assert_eq!(m.msg_type, 1);
+        assert_eq!(m.xid, 0xdeadbeef);
+        assert_eq!(m.hostname, "mac1");
+        let prl = join_codes(&m.prl);
+        assert_eq!(prl, "1,121,3,6,15,108,114,119,252,95,44,46");
+        assert_eq!(device_hint(&prl), "macOS");
+        assert_eq!(join_codes(&m.options), "53,55,12");
+    }
+
+    #[test]
+    fn parse_v6_solicit_in_relay() {
+        let inner = [1u8, 0xaa, 0xbb, 0xcc, 0, 6, 0, 4, 0, 23, 0, 24, 0, 1, 0, 2, 0xde, 0xad];
+        let mut relay = vec![12u8, 0];
+        relay.extend_from_slice(&[0; 32]);
+        relay.extend_from_slice(&[0, 9, 0, inner.len() as u8]);
+        relay.extend_from_slice(&inner);
+        let m = parse_v6_at(&relay, 0).unwrap();
+        assert!(m.relayed);
+        assert_eq!(m.msg_type, 1);
+        assert_eq!(m.xid, 0xaabbcc);
+        assert_eq!(join_codes(&m.prl), "23,24");
+        assert_eq!(m.client_id, "dead");
+    }
+}
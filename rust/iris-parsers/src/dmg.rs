@@ -0,0 +1,285 @@
+//! Apple disk images (UDIF .dmg): the 512-byte "koly" trailer, the XML resource fork
+//! with its blkx partition table, and the encrypted-image (FileVault cdsa) containers.
+
+use crate::bytes::{be32, be64};
+use crate::ffi::{alloc_array, free_array, free_cstr, to_cstr};
+use crate::hash::to_hex;
+use crate::plist::{self, Plist};
+use std::ffi::c_char;
+
+const KOLY_MAGIC: &[u8] = b"koly";
+const KOLY_SIZE: usize = 512;
+const MISH_MAGIC: &[u8] = b"mish";
+const MISH_HEADER_SIZE: usize = 204;
+const MISH_CHUNK_SIZE: usize = 40;
+const ENCRYPTED_V1_MAGIC: &[u8] = b"cdsaencr"; // trailer
+const ENCRYPTED_V2_MAGIC: &[u8] = b"encrcdsa"; // header
+
+pub const DMG_COMP_ZERO: u32 = 0x01;  // zero-fill / ignored sectors
+pub const DMG_COMP_RAW: u32 = 0x02;
+pub const DMG_COMP_ADC: u32 = 0x04;
+pub const DMG_COMP_ZLIB: u32 = 0x08;
+pub const DMG_COMP_BZIP2: u32 = 0x10;
+pub const DMG_COMP_LZFSE: u32 = 0x20;
+pub const DMG_COMP_LZMA: u32 = 0x40;
+pub const DMG_COMP_UNKNOWN: u32 = 0x80;
+
+#[repr(C)]
+pub struct IrisDmgPartition {
+    pub name: *mut c_char,         // blkx "CFName", else "Name", e.g. "Apple_HFS : 4"
+    pub id: i64,
+    pub attributes: u32,
+    pub first_sector: u64,
+    pub sector_count: u64,
+    pub chunk_count: u32,          // block chunks, excluding comments and the terminator
+    pub compression: u32,          // DMG_COMP_* bits used by this partition
+    pub checksum_type: u32,        // 0=none, 2=CRC32
+    pub checksum: *mut c_char,     // hex
+}
+
+#[repr(C)]
+pub struct IrisDmgInfo {
+    pub encrypted: bool,           // cdsa container; nothing else is readable without the key
+    pub encryption_version: u8,    // 1 or 2, 0 if not encrypted
+    pub version: u32,
+    pub flags: u32,                // bit 0 flattened, bit 2 internet-enabled
+    pub image_variant: u32,
+    pub sector_count: u64,         // 512-byte sectors
+    pub data_fork_offset: u64,
+    pub data_fork_length: u64,
+    pub xml_offset: u64,
+    pub xml_length: u64,
+    pub segment_number: u32,
+    pub segment_count: u32,
+    pub data_checksum_type: u32,
+    pub data_checksum: *mut c_char,   // hex
+    pub master_checksum_type: u32,
+    pub master_checksum: *mut c_char, // hex, checksum of the partition checksums
+    pub compression: u32,          // DMG_COMP_* bits across all partitions
+    pub partitions: *mut IrisDmgPartition,
+    pub partition_count: usize,
+}
+
+/// UDIF checksum: type, size in bits, then up to 128 bytes of value.
+fn checksum(d: &[u8], o: usize) -> Option<(u32, String)> {
+    let bytes = (be32(d, o + 4)? as usize / 8).min(128);
+    Some((be32(d, o)?, to_hex(d.get(o + 8..o + 8 + bytes)?)))
+}
+
+fn chunk_compression(kind: u32) -> Option<u32> {
+    Some(match kind {
+        0x0000_0000 | 0x0000_0002 => DMG_COMP_ZERO,
+        0x0000_0001 => DMG_COMP_RAW,
+        0x8000_0004 => DMG_COMP_ADC,
+        0x8000_0005 => DMG_COMP_ZLIB,
+        0x8000_0006 => DMG_COMP_BZIP2,
+        0x8000_0007 => DMG_COMP_LZFSE,
+        0x8000_0008 => DMG_COMP_LZMA,
+        0x7fff_fffe | 0xffff_ffff => return None, // comment, terminator
+        _ => DMG_COMP_UNKNOWN,
+    })
+}
+
+fn parse_blkx(entry: &Plist) -> Option<IrisDmgPartition> {
+    let mish = entry.get("Data")?.as_data()?;
+    if mish.len() < MISH_HEADER_SIZE || &mish[..4] != MISH_MAGIC { return None; }
+    let n = be32(mish, 200)? as usize;
+    let chunks = mish.get(MISH_HEADER_SIZE..MISH_HEADER_SIZE.checked_add(n.checked_mul(MISH_CHUNK_SIZE)?)?)?;
+    let mut compression = 0;
+    let mut chunk_count = 0;
+    for c in chunks.chunks_exact(MISH_CHUNK_SIZE) {
+        if let Some(bit) = be32(c, 0).and_then(chunk_compression) {
+            compression |= bit;
+            chunk_count += 1;
+        }
+    }
+    let name = entry.get("CFName").or_else(|| entry.get("Name")).and_then(Plist::as_str).unwrap_or("");
+    let (checksum_type, sum) = checksum(mish, 64)?;
+    Some(IrisDmgPartition {
+        name: to_cstr(name),
+        id: entry.get("ID").and_then(|v| v.as_int().or_else(|| v.as_str()?.parse().ok())).unwrap_or(0),
+        attributes: entry.get("Attributes").and_then(|v| {
+            v.as_int().or_else(|| i64::from_str_radix(v.as_str()?.trim_start_matches("0x"), 16).ok())
+        }).unwrap_or(0) as u32,
+        first_sector: be64(mish, 8)?,
+        sector_count: be64(mish, 16)?,
+        chunk_count,
+        compression,
+        checksum_type,
+        checksum: to_cstr(&sum),
+    })
+}
+
+/// The fixed-offset fields of the trailing koly block.
+fn read_koly(k: &[u8], info: &mut IrisDmgInfo) -> Option<()> {
+    info.version = be32(k, 4)?;
+    info.flags = be32(k, 12)?;
+    info.data_fork_offset = be64(k, 24)?;
+    info.data_fork_length = be64(k, 32)?;
+    info.segment_number = be32(k, 56)?;
+    info.segment_count = be32(k, 60)?;
+    info.xml_offset = be64(k, 216)?;
+    info.xml_length = be64(k, 224)?;
+    info.image_variant = be32(k, 488)?;
+    info.sector_count = be64(k, 492)?;
+    Some(())
+}
+
+fn encryption_version(d: &[u8]) -> u8 {
+    if d.starts_with(ENCRYPTED_V2_MAGIC) { 2 } else if d.ends_with(ENCRYPTED_V1_MAGIC) { 1 } else { 0 }
+}
+
+// --- FFI entry points ---
+
+/// Parse a whole .dmg file. Returns 0=ok, -1=XML plist extends past the data,
+/// -2=arg error or malformed plist, -3=not a UDIF image. Encrypted images return 0 with
+/// only `encrypted`/`encryption_version` set. Free with iris_dmg_free.
+#[no_mangle]
+pub extern "C" fn iris_dmg_parse(data: *const u8, len: usize, out: *mut IrisDmgInfo) -> i32 {
+    if data.is_null() || out.is_null() { return -2; }
+    let d = unsafe { std::slice::from_raw_parts(data, len) };
+    let mut info = IrisDmgInfo {
+        encrypted: false, encryption_version: encryption_version(d),
+        version: 0, flags: 0, image_variant: 0, sector_count: 0,
+        data_fork_offset: 0, data_fork_length: 0, xml_offset: 0, xml_length: 0,
+        segment_number: 0, segment_count: 0,
+        data_checksum_type: 0, data_checksum: std::ptr::null_mut(),
+        master_checksum_type: 0, master_checksum: std::ptr::null_mut(),
+        compression: 0, partitions: std::ptr::null_mut(), partition_count: 0,
+    };
+    if info.encryption_version != 0 {
+        info.encrypted = true;
+        unsafe { out.write(info); }
+        return 0;
+    }
+    if len < KOLY_SIZE || &d[len - KOLY_SIZE..len - KOLY_SIZE + 4] != KOLY_MAGIC { return -3; }
+    let k = &d[len - KOLY_SIZE..];
+    if read_koly(k, &mut info).is_none() { return -3; }
+
+    let mut partitions = Vec::new();
+    if info.xml_length > 0 {
+        let Some(xml) = usize::try_from(info.xml_offset).ok()
+            .zip(usize::try_from(info.xml_length).ok())
+            .and_then(|(o, l)| d.get(o..o.checked_add(l)?)) else { return -1 };
+        let Some(root) = plist::parse(xml) else { return -2 };
+        let blkx = root.get("resource-fork").and_then(|r| r.get("blkx")).and_then(Plist::as_array).unwrap_or_default();
+        partitions.extend(blkx.iter().filter_map(parse_blkx));
+    }
+    info.compression = partitions.iter().fold(0, |m, p| m | p.compression);
+    let (data_type, data_sum) = checksum(k, 80).unwrap_or_default();
+    let (master_type, master_sum) = checksum(k, 352).unwrap_or_default();
+    info.data_checksum_type = data_type;
+    info.data_checksum = to_cstr(&data_sum);
+    info.master_checksum_type = master_type;
+    info.master_checksum = to_cstr(&master_sum);
+    (info.partitions, info.partition_count) = alloc_array(partitions);
+    unsafe { out.write(info); }
+    0
+}
+
+/// Free the strings and partitions of an IrisDmgInfo filled by iris_dmg_parse.
+#[no_mangle]
+pub extern "C" fn iris_dmg_free(info: *mut IrisDmgInfo) {
+    if info.is_null() { return; }
+    let info = unsafe { &*info };
+    free_cstr(info.data_checksum);
+    free_cstr(info.master_checksum);
+    for i in 0..info.partition_count {
+        let p = unsafe { &*info.partitions.add(i) };
+        free_cstr(p.name);
+        free_cstr(p.checksum);
+    }
+    free_array(info.partitions, info.partition_count);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base64::encode;
+
+    fn mish(first: u64, count: u64, kinds: &[u32]) -> Vec<u8> {
+        let mut m = vec![0u8; MISH_HEADER_SIZE];
+        m[..4].copy_from_slice(MISH_MAGIC);
+        m[8..16].copy_from_slice(&first.to_be_bytes());
+        m[16..24].copy_from_slice(&count.to_be_bytes());
+        m[64..68].copy_from_slice(&2u32.to_be_bytes());
+        m[68..72].copy_from_slice(&32u32.to_be_bytes());
+        m[72..76].copy_from_slice(&[0xde, 0xad, 0xbe, 0xef]);
+        m[200..204].copy_from_slice(&(kinds.len() as u32).to_be_bytes());
+        for &k in kinds {
+            let mut c = [0u8; MISH_CHUNK_SIZE];
+            c[..4].copy_from_slice(&k.to_be_bytes());
+            m.extend_from_slice(&c);
+        }
+        m
+    }
+
+    fn image() -> Vec<u8> {
+        let xml = format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0"><dict><key>resource-fork</key><dict><key>blkx</key><array>
+<dict><key>Attributes</key><string>0x0050</string><key>CFName</key><string>Protective Master Boot Record (MBR : 0)</string>
+<key>Data</key><data>{}</data><key>ID</key><string>-1</string></dict>
+<dict><key>Attributes</key><string>0x0050</string><key>Data</key><data>{}</data><key>ID</key><string>4</string>
+<key>Name</key><string>Apple_HFS : 4</string></dict>
+</array></dict></dict></plist>"#,
+            encode(&mish(0, 1, &[0x0000_0001, 0xffff_ffff])),
+            encode(&mish(1, 2048, &[0x8000_0007, 0x0000_0002, 0x7fff_fffe, 0x8000_0005, 0xffff_ffff])));
+        let mut d = vec![0xaa; 1024];
+        let xml_offset = d.len() as u64;
+        d.extend_from_slice(xml.as_bytes());
+        let mut k = [0u8; KOLY_SIZE];
+        k[..4].copy_from_slice(KOLY_MAGIC);
+        k[4..8].copy_from_slice(&4u32.to_be_bytes());
+        k[8..12].copy_from_slice(&(KOLY_SIZE as u32).to_be_bytes());
+        k[12..16].copy_from_slice(&1u32.to_be_bytes());
+        k[32..40].copy_from_slice(&1024u64.to_be_bytes());
+        k[80..84].copy_from_slice(&2u32.to_be_bytes());
+        k[84..88].copy_from_slice(&32u32.to_be_bytes());
+        k[88..92].copy_from_slice(&[0x12, 0x34, 0x56, 0x78]);
+        k[216..224].copy_from_slice(&xml_offset.to_be_bytes());
+        k[224..232].copy_from_slice(&(xml.len() as u64).to_be_bytes());
+        k[488..492].copy_from_slice(&1u32.to_be_bytes());
+        k[492..500].copy_from_slice(&2049u64.to_be_bytes());
+        d.extend_from_slice(&k);
+        d
+    }
+
+    fn cstr(p: *mut c_char) -> String {
+        unsafe { std::ffi::CStr::from_ptr(p) }.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn koly_and_blkx() {
+        let d = image();
+        let mut out = std::mem::MaybeUninit::<IrisDmgInfo>::uninit();
+        assert_eq!(iris_dmg_parse(d.as_ptr(), d.len(), out.as_mut_ptr()), 0);
+        let mut info = unsafe { out.assume_init() };
+        assert_eq!((info.encrypted, info.version, info.sector_count, info.data_fork_length), (false, 4, 2049, 1024));
+        assert_eq!((info.data_checksum_type, cstr(info.data_checksum)), (2, "12345678".into()));
+        assert_eq!(info.compression, DMG_COMP_RAW | DMG_COMP_LZFSE | DMG_COMP_ZERO | DMG_COMP_ZLIB);
+        assert_eq!(info.partition_count, 2);
+        let p = unsafe { &*info.partitions.add(1) };
+        assert_eq!((cstr(p.name), p.id, p.attributes), ("Apple_HFS : 4".into(), 4, 0x50));
+        assert_eq!((p.first_sector, p.sector_count, p.chunk_count), (1, 2048, 3));
+        assert_eq!(cstr(p.checksum), "deadbeef");
+        assert_eq!(unsafe { (*info.partitions).id }, -1);
+        iris_dmg_free(&mut info);
+
+        let mut bad = d.clone();
+        let k = bad.len() - KOLY_SIZE;
+        bad[k + 224..k + 232].copy_from_slice(&(1u64 << 40).to_be_bytes());
+        let mut out = std::mem::MaybeUninit::<IrisDmgInfo>::uninit();
+        assert_eq!(iris_dmg_parse(bad.as_ptr(), bad.len(), out.as_mut_ptr()), -1);
+        assert_eq!(iris_dmg_parse(d.as_ptr(), d.len() - 1, out.as_mut_ptr()), -3);
+    }
+
+    #[test]
+    fn encrypted_container() {
+        let d = [ENCRYPTED_V2_MAGIC, &[0; 64]].concat();
+        let mut out = std::mem::MaybeUninit::<IrisDmgInfo>::uninit();
+        assert_eq!(iris_dmg_parse(d.as_ptr(), d.len(), out.as_mut_ptr()), 0);
+        let mut info = unsafe { out.assume_init() };
+        assert_eq!((info.encrypted, info.encryption_version, info.partition_count), (true, 2, 0));
+        iris_dmg_free(&mut info);
+    }
+}
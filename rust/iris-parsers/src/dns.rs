@@ -1,6 +1,13 @@
 //! DNS wire format parser (RFC 1035) and query builder.
 
-use crate::ffi::alloc_bytes;
+use crate::ffi::{alloc_array, alloc_bytes, free_array, free_cstr, to_cstr, IrisSlice};
+use crate::idn;
+use crate::url::{self, IrisUrlParams};
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
 use std::ffi::{CString, CStr, c_char};
 
 // --- C FFI types ---
@@ -10,6 +17,7 @@ pub struct IrisDnsQuestion {
     pub name: *mut c_char,
     pub record_type: u16,
     pub qclass: u16,
+    pub unicast_response: bool, // mDNS QU bit, split off qclass (iris_dns_parse_mdns)
 }
 
 #[repr(C)]
@@ -18,9 +26,10 @@ pub struct IrisDnsRecord {
     pub record_type: u16,
     pub rrclass: u16,
     pub ttl: u32,
-    pub rdata: *mut u8,
+    pub rdata: *mut u8,         // names in NS, CNAME, PTR, MX, SOA and SRV data uncompressed
     pub rdata_len: usize,
     pub display_value: *mut c_char,
+    pub cache_flush: bool,      // mDNS cache-flush bit, split off rrclass (iris_dns_parse_mdns)
 }
 
 #[repr(C)]
@@ -41,53 +50,208 @@ pub struct IrisDnsMessage {
     pub authority_count: usize,
     pub additional: *mut IrisDnsRecord,
     pub additional_count: usize,
+    pub names_escaped: bool,    // a question or owner name has \DDD, \. or \\ escapes
+}
+
+/// A record for iris_dns_build_message, given either as text in the form the
+/// parser shows in display_value or as raw RDATA.
+#[repr(C)]
+pub struct IrisDnsBuildRecord {
+    pub section: u8,            // SECTION_ANSWER, SECTION_AUTHORITY or SECTION_ADDITIONAL
+    pub name: *const c_char,
+    pub record_type: u16,
+    pub rrclass: u16,
+    pub ttl: u32,
+    pub value: *const c_char,   // "192.0.2.1", "10 mx.example.com", "0 5 443 host."; NULL = use rdata
+    pub rdata: *const u8,
+    pub rdata_len: usize,
+}
+
+/// SVCB/HTTPS (types 64/65, RFC 9460) RDATA.
+#[repr(C)]
+pub struct IrisDnsSvcb {
+    pub priority: u16,          // 0 = AliasMode
+    pub target: *mut c_char,    // "." = the owner name itself
+    pub params: IrisUrlParams,  // SvcParams in wire order, presentation form: "alpn" -> "h2,h3",
+                                // "port" -> "443", "ech" -> base64, "no-default-alpn" without value
+    pub ech: *mut u8,           // ECHConfigList of the ech param, NULL if absent
+    pub ech_len: usize,
+}
+
+/// Header of a message walked by iris_dns_view.
+#[repr(C)]
+pub struct IrisDnsView {
+    pub id: u16,
+    pub flags: u16,             // raw header flags
+    pub counts: [u16; 4],       // QDCOUNT, ANCOUNT, NSCOUNT, ARCOUNT
+    pub count: usize,           // entries in the message; may exceed the caller's capacity
+}
+
+/// A question or record, pointing into the caller's buffer.
+#[repr(C)]
+pub struct IrisDnsEntry {
+    pub section: u8,            // SECTION_*
+    pub name_offset: usize,     // owner name, for iris_dns_view_name
+    pub record_type: u16,
+    pub rrclass: u16,           // qclass for questions
+    pub ttl: u32,               // 0 for questions
+    pub rdata: IrisSlice,       // empty for questions
+}
+
+impl Default for IrisDnsEntry {
+    fn default() -> Self {
+        IrisDnsEntry { section: 0, name_offset: 0, record_type: 0, rrclass: 0, ttl: 0, rdata: IrisSlice::from_bytes(&[]) }
+    }
+}
+
+#[repr(C)]
+pub struct IrisDnsMessages {
+    pub messages: *mut IrisDnsMessage,
+    pub count: usize,
+    pub malformed: usize,      // complete frames that did not parse or were over the limits, skipped
+}
+
+pub struct IrisDnsTcpStream {
+    inner: Mutex<DnsTcpStream>,
+}
+
+/// Per-call parser limits; a 0 field takes the default.
+#[repr(C)]
+pub struct IrisDnsLimits {
+    pub max_section_records: usize, // per section, default 256
+    pub max_total_records: usize,   // questions and records together, default 1024
+    pub max_message_len: usize,     // default 65535
 }
 
 // --- Internal types ---
 
-struct DnsQ { name: String, qtype: u16, qclass: u16 }
-struct DnsRR { name: String, rtype: u16, rclass: u16, ttl: u32, rdata: Vec<u8>, display: String }
+struct DnsQ { name: String, qtype: u16, qclass: u16, unicast_response: bool }
+
+pub enum RecordData { Text(String), Raw(Vec<u8>) }
+pub struct BuildRecord { pub section: usize, pub name: String, pub rtype: u16, pub rclass: u16, pub ttl: u32, pub data: RecordData }
+pub struct DnsRR {
+    pub name: String, pub rtype: u16, pub rclass: u16, pub ttl: u32, pub rdata: Vec<u8>, pub display: String,
+    pub cache_flush: bool,
+}
 
 // --- Parsing ---
 
-fn parse_dns(data: &[u8]) -> Option<(u16, bool, u8, bool, bool, bool, bool, u8,
-    Vec<DnsQ>, Vec<DnsRR>, Vec<DnsRR>, Vec<DnsRR>)>
-{
-    if data.len() < 12 { return None; }
+type ParsedDns = (u16, bool, u8, bool, bool, bool, bool, u8,
+    Vec<DnsQ>, Vec<DnsRR>, Vec<DnsRR>, Vec<DnsRR>);
+
+/// Section and size limits for parsing. The defaults suit ordinary queries and
+/// responses; zone transfers and other large TCP responses need more records.
+#[derive(Clone, Copy)]
+pub struct DnsLimits {
+    pub section_records: usize,
+    pub total_records: usize,
+    pub message_len: usize,
+}
+
+pub const DEFAULT_LIMITS: DnsLimits = DnsLimits { section_records: 256, total_records: 1024, message_len: 65535 };
+
+impl DnsLimits {
+    /// Limits from the C struct, 0 fields taking the default.
+    fn from_ffi(l: &IrisDnsLimits) -> Self {
+        let or = |v: usize, d: usize| if v == 0 { d } else { v };
+        DnsLimits {
+            section_records: or(l.max_section_records, DEFAULT_LIMITS.section_records),
+            total_records: or(l.max_total_records, DEFAULT_LIMITS.total_records),
+            message_len: or(l.max_message_len, DEFAULT_LIMITS.message_len),
+        }
+    }
+}
+
+fn parse_dns(data: &[u8]) -> Option<ParsedDns> {
+    parse_dns_limited(data, &DEFAULT_LIMITS).ok()
+}
+
+/// Err(-3) over `limits`, Err(-2) malformed.
+fn parse_dns_limited(data: &[u8], limits: &DnsLimits) -> Result<ParsedDns, i32> {
+    if data.len() < 12 { return Err(-2); }
+    if data.len() > limits.message_len { return Err(-3); }
     let id = u16::from_be_bytes([data[0], data[1]]);
     let flags = u16::from_be_bytes([data[2], data[3]]);
     let counts: Vec<usize> = (0..4).map(|i| {
         u16::from_be_bytes([data[4 + i * 2], data[5 + i * 2]]) as usize
     }).collect();
-    if counts.iter().any(|&c| c > 256) { return None; }
+    if counts.iter().any(|&c| c > limits.section_records) || counts.iter().sum::<usize>() > limits.total_records {
+        return Err(-3);
+    }
+    // Counts are attacker-controlled: size buffers by what the message can hold
+    // (5 bytes per question, 11 per record at least)
+    let cap = |n: usize, min: usize| n.min(data.len() / min);
 
     let mut off = 12usize;
-    let mut questions = Vec::with_capacity(counts[0]);
+    let mut questions = Vec::with_capacity(cap(counts[0], 5));
     for _ in 0..counts[0] {
-        let (name, new_off) = parse_name(data, off)?;
+        let (name, new_off) = parse_name(data, off).ok_or(-2)?;
         off = new_off;
-        if off + 4 > data.len() { return None; }
+        if off + 4 > data.len() { return Err(-2); }
         let qt = u16::from_be_bytes([data[off], data[off + 1]]);
         let qc = u16::from_be_bytes([data[off + 2], data[off + 3]]);
         off += 4;
-        questions.push(DnsQ { name, qtype: qt, qclass: qc });
+        questions.push(DnsQ { name, qtype: qt, qclass: qc, unicast_response: false });
     }
 
-    let mut answers = Vec::with_capacity(counts[1]);
+    let mut answers = Vec::with_capacity(cap(counts[1], 11));
     for _ in 0..counts[1] {
-        let (rr, new_off) = parse_rr(data, off)?;
+        let (rr, new_off) = parse_rr(data, off).ok_or(-2)?;
         off = new_off;
         answers.push(rr);
     }
     let authority = parse_rr_section(data, &mut off, counts[2]);
     let additional = parse_rr_section(data, &mut off, counts[3]);
 
-    Some((id, flags & 0x8000 != 0, ((flags >> 11) & 0xF) as u8,
+    Ok((id, flags & 0x8000 != 0, ((flags >> 11) & 0xF) as u8,
           flags & 0x0400 != 0, flags & 0x0200 != 0,
           flags & 0x0100 != 0, flags & 0x0080 != 0, (flags & 0xF) as u8,
           questions, answers, authority, additional))
 }
 
+/// mDNS (RFC 6762 §5.4, §10.2) reuses the top bit of the class: in questions it
+/// asks for a unicast response (QU), in records it flushes cached records of the
+/// same name and type. Split it off so the class reads as IN (1).
+fn split_mdns_class(p: &mut ParsedDns) {
+    for q in &mut p.8 {
+        q.unicast_response = q.qclass & 0x8000 != 0;
+        q.qclass &= 0x7FFF;
+    }
+    // OPT (41) uses the class for the UDP payload size
+    for rr in p.9.iter_mut().chain(&mut p.10).chain(&mut p.11).filter(|rr| rr.rtype != 41) {
+        rr.cache_flush = rr.rclass & 0x8000 != 0;
+        rr.rclass &= 0x7FFF;
+    }
+}
+
+/// A question: name, type, class.
+pub type Question = (String, u16, u16);
+
+/// Header id and flags and the first question, without
+/// parsing the records. The question is None for a message without one.
+pub fn header_question(data: &[u8]) -> Option<(u16, u16, Option<Question>)> {
+    if data.len() < 12 { return None; }
+    let be16 = |p: usize| data.get(p..p + 2).map(|b| u16::from_be_bytes([b[0], b[1]]));
+    let (id, flags) = (be16(0)?, be16(2)?);
+    if be16(4)? == 0 { return Some((id, flags, None)); }
+    let (name, p) = parse_name(data, 12)?;
+    Some((id, flags, Some((name, be16(p)?, be16(p + 2)?))))
+}
+
+/// Every resource record in a message (answer, authority, additional), for
+/// interpreters such as DNS-SD that correlate records across sections.
+pub fn parse_all_records(data: &[u8]) -> Option<Vec<DnsRR>> {
+    let [answers, authority, additional] = parse_record_sections(data)?;
+    Some(answers.into_iter().chain(authority).chain(additional).collect())
+}
+
+/// Answer, authority and additional records kept apart, for messages that give
+/// the sections other meanings (DNS UPDATE: prerequisite, update, additional).
+pub fn parse_record_sections(data: &[u8]) -> Option<[Vec<DnsRR>; 3]> {
+    let (.., answers, authority, additional) = parse_dns(data)?;
+    Some([answers, authority, additional])
+}
+
 fn parse_rr_section(data: &[u8], off: &mut usize, count: usize) -> Vec<DnsRR> {
     let mut rrs = Vec::new();
     for _ in 0..count {
@@ -99,8 +263,98 @@ fn parse_rr_section(data: &[u8], off: &mut usize, count: usize) -> Vec<DnsRR> {
     rrs
 }
 
-fn parse_name(data: &[u8], mut pos: usize) -> Option<(String, usize)> {
-    let mut labels: Vec<String> = Vec::new();
+fn parse_name(data: &[u8], pos: usize) -> Option<(String, usize)> {
+    let mut name = String::new();
+    let end_pos = walk_name(data, pos, |l| {
+        if !name.is_empty() { name.push('.'); }
+        label_text(l, &mut name);
+    })?;
+    if name.is_empty() { name.push('.'); }
+    Some((name, end_pos))
+}
+
+/// Append a label in presentation form. Valid UTF-8 is kept, except that `.` and
+/// `\` are escaped as `\.` and `\\` and control bytes as BIND-style `\DDD`; a
+/// label that is not valid UTF-8 also has its non-ASCII bytes as `\DDD`.
+fn label_text(l: &[u8], out: &mut String) {
+    let special = |b: u8| b == b'.' || b == b'\\' || b < 0x20 || b == 0x7f;
+    match std::str::from_utf8(l) {
+        Ok(s) if !l.iter().any(|&b| special(b)) => out.push_str(s),
+        Ok(s) => for c in s.chars() {
+            match c {
+                '.' | '\\' => { out.push('\\'); out.push(c); }
+                c if c.is_ascii() && special(c as u8) => out.push_str(&format!("\\{:03}", c as u32)),
+                c => out.push(c),
+            }
+        },
+        Err(_) => for &b in l {
+            match b {
+                b'.' | b'\\' => { out.push('\\'); out.push(b as char); }
+                0x20..=0x7e => out.push(b as char),
+                _ => out.push_str(&format!("\\{:03}", b)),
+            }
+        },
+    }
+}
+
+/// Decoded labels, each with the offset in the name text where it starts.
+type Labels = Vec<(usize, Vec<u8>)>;
+
+/// The labels of a presentation-form name, undoing `label_text` escapes, and
+/// where the name ends without the trailing root '.'.
+fn split_name(name: &str) -> Result<(Labels, usize), i32> {
+    let b = name.as_bytes();
+    let mut labels = Vec::new();
+    if name.is_empty() || name == "." { return Ok((labels, 0)); }
+    let (mut start, mut cur, mut i) = (0, Vec::new(), 0);
+    while i < b.len() {
+        match b[i] {
+            b'\\' => {
+                match b.get(i + 1..i + 4).filter(|d| d.iter().all(u8::is_ascii_digit)) {
+                    Some(d) => {
+                        let v = d.iter().fold(0u16, |v, &c| v * 10 + (c - b'0') as u16);
+                        cur.push(u8::try_from(v).map_err(|_| -2)?);
+                        i += 4;
+                    }
+                    None => {
+                        cur.push(*b.get(i + 1).ok_or(-2)?);
+                        i += 2;
+                    }
+                }
+            }
+            b'.' => {
+                labels.push((start, std::mem::take(&mut cur)));
+                i += 1;
+                start = i;
+                if i == b.len() { return Ok((labels, i - 1)); }
+            }
+            c => {
+                cur.push(c);
+                i += 1;
+            }
+        }
+    }
+    labels.push((start, cur));
+    Ok((labels, b.len()))
+}
+
+/// `split_name`, rejecting an empty inner label, a label over 63 bytes or a name
+/// over 255.
+fn checked_labels(name: &str) -> Result<(Labels, usize), i32> {
+    let (labels, end) = split_name(name)?;
+    if labels.iter().any(|(_, l)| l.is_empty() || l.len() > 63) { return Err(-2); }
+    if labels.iter().map(|(_, l)| l.len() + 1).sum::<usize>() + 1 > 255 { return Err(-2); }
+    Ok((labels, end))
+}
+
+/// The labels of a presentation-form name as they go on the wire, root excluded.
+pub fn wire_labels(name: &str) -> Result<Vec<Vec<u8>>, i32> {
+    Ok(checked_labels(name)?.0.into_iter().map(|(_, l)| l).collect())
+}
+
+/// Follow the (possibly compressed) name at `pos`, passing each label to `f`.
+/// Returns the offset just past the name as it appears at `pos`.
+pub fn walk_name(data: &[u8], mut pos: usize, mut f: impl FnMut(&[u8])) -> Option<usize> {
     let mut end_pos = 0usize;
     let mut jumped = false;
     let mut jumps = 0u8;
@@ -123,11 +377,10 @@ fn parse_name(data: &[u8], mut pos: usize) -> Option<(String, usize)> {
         if len > 63 { return None; }
         pos += 1;
         if pos + len > data.len() { return None; }
-        labels.push(std::str::from_utf8(&data[pos..pos + len]).ok()?.to_string());
+        f(&data[pos..pos + len]);
         pos += len;
     }
-    let name = if labels.is_empty() { ".".into() } else { labels.join(".") };
-    Some((name, if jumped { end_pos } else { end_pos }))
+    Some(end_pos)
 }
 
 fn parse_rr(data: &[u8], offset: usize) -> Option<(DnsRR, usize)> {
@@ -139,15 +392,226 @@ fn parse_rr(data: &[u8], offset: usize) -> Option<(DnsRR, usize)> {
     let rdlen = u16::from_be_bytes([data[pos + 8], data[pos + 9]]) as usize;
     pos += 10;
     if pos + rdlen > data.len() { return None; }
-    let rdata = data[pos..pos + rdlen].to_vec();
+    let rdata = expand_rdata(rtype, data, pos, pos + rdlen).unwrap_or_else(|| data[pos..pos + rdlen].to_vec());
     let display = format_rdata(rtype, &rdata, data, pos);
     pos += rdlen;
-    Some((DnsRR { name, rtype, rclass, ttl, rdata, display }, pos))
+    Some((DnsRR { name, rtype, rclass, ttl, rdata, display, cache_flush: false }, pos))
+}
+
+// --- Zero-copy view ---
+
+/// Walk a message without allocating: header and one entry per question and
+/// record, with RDATA borrowed from `data` and names left as offsets for
+/// `view_name`. Entries beyond `out.len()` are counted but not stored. Accepts
+/// the same messages as `parse_dns`.
+pub fn view(data: &[u8], out: &mut [IrisDnsEntry]) -> Option<IrisDnsView> {
+    if data.len() < 12 { return None; }
+    let be16 = |p: usize| u16::from_be_bytes([data[p], data[p + 1]]);
+    let counts = [be16(4), be16(6), be16(8), be16(10)];
+    if counts.iter().any(|&c| c > 256) { return None; }
+    let mut v = IrisDnsView { id: be16(0), flags: be16(2), counts, count: 0 };
+    let mut off = 12;
+    for (section, &n) in counts.iter().enumerate() {
+        for _ in 0..n {
+            let end = match walk_name(data, off, |_| {}) {
+                Some(end) if end + if section == SECTION_QUESTION { 4 } else { 10 } <= data.len() => end,
+                // Truncated authority/additional sections end the walk, as in parse_dns
+                _ if section >= SECTION_AUTHORITY => break,
+                _ => return None,
+            };
+            let mut e = IrisDnsEntry {
+                section: section as u8, name_offset: off, record_type: be16(end), rrclass: be16(end + 2),
+                ttl: 0, rdata: IrisSlice::from_bytes(&[]),
+            };
+            off = end + 4;
+            if section != SECTION_QUESTION {
+                let rdlen = be16(end + 8) as usize;
+                if end + 10 + rdlen > data.len() {
+                    if section >= SECTION_AUTHORITY { break; }
+                    return None;
+                }
+                e.ttl = u32::from_be_bytes([data[end + 4], data[end + 5], data[end + 6], data[end + 7]]);
+                e.rdata = IrisSlice::from_bytes(&data[end + 10..end + 10 + rdlen]);
+                off = end + 10 + rdlen;
+            }
+            if let Some(slot) = out.get_mut(v.count) { *slot = e; }
+            v.count += 1;
+        }
+    }
+    Some(v)
+}
+
+/// Decompress the name at `offset` into `buf` as dotted text ("." for the root),
+/// label bytes copied verbatim. Returns the length, or None if malformed or too
+/// long for `buf`.
+pub fn view_name(data: &[u8], offset: usize, buf: &mut [u8]) -> Option<usize> {
+    let (mut n, mut fits) = (0, true);
+    walk_name(data, offset, |l| {
+        let start = if n == 0 { 0 } else { 1 };
+        if n + start + l.len() > buf.len() { fits = false; return; }
+        if start == 1 { buf[n] = b'.'; }
+        buf[n + start..n + start + l.len()].copy_from_slice(l);
+        n += start + l.len();
+    })?;
+    if n == 0 {
+        if buf.is_empty() { return None; }
+        buf[0] = b'.';
+        n = 1;
+    }
+    fits.then_some(n)
+}
+
+/// RDATA of the name-bearing types (NS, CNAME, PTR, MX, SOA, SRV) with its names
+/// uncompressed, so it stays meaningful outside the message. None if malformed
+/// or not one of those types.
+fn expand_rdata(rtype: u16, msg: &[u8], start: usize, end: usize) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    let name_at = |out: &mut Vec<u8>, pos: usize| -> Option<usize> {
+        let next = walk_name(msg, pos, |l| { out.push(l.len() as u8); out.extend_from_slice(l); })?;
+        out.push(0);
+        (next <= end).then_some(next)
+    };
+    let fixed = |from: usize, len: usize| msg.get(from..from + len).filter(|_| from + len <= end);
+    let p = match rtype {
+        2 | 5 | 12 => name_at(&mut out, start)?,
+        15 => {
+            out.extend_from_slice(fixed(start, 2)?);
+            name_at(&mut out, start + 2)?
+        }
+        6 => {
+            let p = name_at(&mut out, start)?;
+            let p = name_at(&mut out, p)?;
+            out.extend_from_slice(fixed(p, 20)?);
+            p + 20
+        }
+        33 => {
+            out.extend_from_slice(fixed(start, 6)?);
+            name_at(&mut out, start + 6)?
+        }
+        _ => return None,
+    };
+    (p == end).then_some(out)
+}
+
+// --- Name tables ---
+
+/// IANA DNS RR TYPEs with mnemonics, including meta-types (OPT, TSIG, AXFR, ANY).
+const RR_TYPES: &[(u16, &CStr)] = &[
+    (1, c"A"), (2, c"NS"), (3, c"MD"), (4, c"MF"), (5, c"CNAME"), (6, c"SOA"), (7, c"MB"), (8, c"MG"),
+    (9, c"MR"), (10, c"NULL"), (11, c"WKS"), (12, c"PTR"), (13, c"HINFO"), (14, c"MINFO"), (15, c"MX"),
+    (16, c"TXT"), (17, c"RP"), (18, c"AFSDB"), (19, c"X25"), (20, c"ISDN"), (21, c"RT"), (22, c"NSAP"),
+    (23, c"NSAP-PTR"), (24, c"SIG"), (25, c"KEY"), (26, c"PX"), (27, c"GPOS"), (28, c"AAAA"), (29, c"LOC"),
+    (30, c"NXT"), (31, c"EID"), (32, c"NIMLOC"), (33, c"SRV"), (34, c"ATMA"), (35, c"NAPTR"), (36, c"KX"),
+    (37, c"CERT"), (38, c"A6"), (39, c"DNAME"), (40, c"SINK"), (41, c"OPT"), (42, c"APL"), (43, c"DS"),
+    (44, c"SSHFP"), (45, c"IPSECKEY"), (46, c"RRSIG"), (47, c"NSEC"), (48, c"DNSKEY"), (49, c"DHCID"),
+    (50, c"NSEC3"), (51, c"NSEC3PARAM"), (52, c"TLSA"), (53, c"SMIMEA"), (55, c"HIP"), (56, c"NINFO"),
+    (57, c"RKEY"), (58, c"TALINK"), (59, c"CDS"), (60, c"CDNSKEY"), (61, c"OPENPGPKEY"), (62, c"CSYNC"),
+    (63, c"ZONEMD"), (64, c"SVCB"), (65, c"HTTPS"), (66, c"DSYNC"), (99, c"SPF"), (100, c"UINFO"),
+    (101, c"UID"), (102, c"GID"), (103, c"UNSPEC"), (104, c"NID"), (105, c"L32"), (106, c"L64"),
+    (107, c"LP"), (108, c"EUI48"), (109, c"EUI64"), (249, c"TKEY"), (250, c"TSIG"), (251, c"IXFR"),
+    (252, c"AXFR"), (253, c"MAILB"), (254, c"MAILA"), (255, c"ANY"), (256, c"URI"), (257, c"CAA"),
+    (258, c"AVC"), (259, c"DOA"), (260, c"AMTRELAY"), (261, c"RESINFO"), (32768, c"TA"), (32769, c"DLV"),
+];
+
+/// RCODEs, including the extended ones carried in OPT (16 is BADSIG in TSIG).
+const RCODES: &[(u16, &CStr)] = &[
+    (0, c"NOERROR"), (1, c"FORMERR"), (2, c"SERVFAIL"), (3, c"NXDOMAIN"), (4, c"NOTIMP"), (5, c"REFUSED"),
+    (6, c"YXDOMAIN"), (7, c"YXRRSET"), (8, c"NXRRSET"), (9, c"NOTAUTH"), (10, c"NOTZONE"), (11, c"DSOTYPENI"),
+    (16, c"BADVERS"), (17, c"BADKEY"), (18, c"BADTIME"), (19, c"BADMODE"), (20, c"BADNAME"), (21, c"BADALG"),
+    (22, c"BADTRUNC"), (23, c"BADCOOKIE"),
+];
+
+fn table_name(table: &[(u16, &'static CStr)], v: u16) -> Option<&'static CStr> {
+    table.iter().find(|&&(n, _)| n == v).map(|&(_, name)| name)
+}
+
+/// Parse a type mnemonic (any case) or RFC 3597 "TYPE<n>".
+pub fn type_from_name(name: &str) -> Option<u16> {
+    if let Some(&(t, _)) = RR_TYPES.iter().find(|(_, n)| n.to_str().is_ok_and(|n| n.eq_ignore_ascii_case(name))) {
+        return Some(t);
+    }
+    let n = name.get(..4).filter(|p| p.eq_ignore_ascii_case("TYPE")).map(|_| &name[4..])?;
+    if n.starts_with('+') { return None; }
+    n.parse().ok()
+}
+
+// --- 0x20 case randomization ---
+
+/// 64 unpredictable bits: SipHash under `RandomState`'s per-process random keys,
+/// over a counter.
+fn random_u64() -> u64 {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let mut h = RandomState::new().build_hasher();
+    h.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+    h.finish()
+}
+
+/// Randomize the case of every ASCII letter (draft-vixie-dnsext-dns0x20), adding
+/// a bit of entropy per letter that an off-path spoofer must also guess.
+pub fn randomize_case(name: &str) -> String {
+    let (mut bits, mut left) = (0u64, 0);
+    name.chars().map(|c| {
+        if !c.is_ascii_alphabetic() { return c; }
+        if left == 0 { (bits, left) = (random_u64(), 64); }
+        let upper = bits & 1 == 1;
+        (bits, left) = (bits >> 1, left - 1);
+        if upper { c.to_ascii_uppercase() } else { c.to_ascii_lowercase() }
+    }).collect()
+}
+
+/// Whether a response echoes its query's question with the exact case.
+/// Err(-3) the questions differ beyond case or one is missing, Err(-2) malformed.
+pub fn case_echoed(query: &[u8], response: &[u8]) -> Result<bool, i32> {
+    let (_, _, q) = header_question(query).ok_or(-2)?;
+    let (_, _, r) = header_question(response).ok_or(-2)?;
+    let ((qn, qt, qc), (rn, rt, rc)) = (q.ok_or(-3)?, r.ok_or(-3)?);
+    if !qn.eq_ignore_ascii_case(&rn) || (qt, qc) != (rt, rc) { return Err(-3); }
+    Ok(qn == rn)
+}
+
+// --- TCP framing ---
+
+/// Reassembles DNS over TCP (RFC 1035 §4.2.2; also DoT once TLS is removed): each
+/// message is preceded by a two-byte length, and frames may be split or batched
+/// arbitrarily across reads. At most one frame (64 KiB) is buffered.
+pub struct DnsTcpStream {
+    buf: Vec<u8>,
+    limits: DnsLimits,
+}
+
+impl Default for DnsTcpStream {
+    fn default() -> Self {
+        DnsTcpStream { buf: Vec::new(), limits: DEFAULT_LIMITS }
+    }
+}
+
+impl DnsTcpStream {
+    /// Append stream bytes and return the complete frames, without their prefix.
+    pub fn feed(&mut self, d: &[u8]) -> Vec<Vec<u8>> {
+        self.buf.extend_from_slice(d);
+        let mut frames = Vec::new();
+        let mut p = 0;
+        while self.buf.len() - p >= 2 {
+            let len = u16::from_be_bytes([self.buf[p], self.buf[p + 1]]) as usize;
+            if self.buf.len() - p - 2 < len { break; }
+            frames.push(self.buf[p + 2..p + 2 + len].to_vec());
+            p += 2 + len;
+        }
+        self.buf.drain(..p);
+        frames
+    }
+
+    /// Bytes of an incomplete frame held for the next read.
+    pub fn pending(&self) -> usize {
+        self.buf.len()
+    }
 }
 
 // --- RDATA formatting ---
 
 fn format_rdata(rtype: u16, rd: &[u8], msg: &[u8], start: usize) -> String {
+    // Empty RDATA (UPDATE deletions and prerequisites): nothing to read a name from
+    if rd.is_empty() { return String::new(); }
     match rtype {
         1 if rd.len() == 4 => format!("{}.{}.{}.{}", rd[0], rd[1], rd[2], rd[3]),
         28 if rd.len() == 16 => (0..8)
@@ -178,50 +642,385 @@ fn format_rdata(rtype: u16, rd: &[u8], msg: &[u8], start: usize) -> String {
             let tgt = parse_name(msg, start + 6).map(|(n, _)| n).unwrap_or_default();
             format!("{} {} {} {}", pri, wt, port, tgt)
         }
+        6 => { // SOA
+            let (mname, p) = parse_name(msg, start).unwrap_or_default();
+            let (rname, p) = if p > 0 { parse_name(msg, p).unwrap_or_default() } else { (String::new(), 0) };
+            match msg.get(p..p + 20).filter(|_| p > 0 && p + 20 <= start + rd.len()) {
+                Some(t) => {
+                    let n = |i: usize| u32::from_be_bytes([t[i], t[i + 1], t[i + 2], t[i + 3]]);
+                    format!("{} {} {} {} {} {} {}", mname, rname, n(0), n(4), n(8), n(12), n(16))
+                }
+                None => hex(rd),
+            }
+        }
+        35 if rd.len() >= 7 => { // NAPTR: order, preference, flags, services, regexp, replacement
+            let order = u16::from_be_bytes([rd[0], rd[1]]);
+            let pref = u16::from_be_bytes([rd[2], rd[3]]);
+            let mut p = 4;
+            let mut strings = Vec::new();
+            for _ in 0..3 {
+                let Some(&len) = rd.get(p) else { return hex(rd) };
+                let Some(s) = rd.get(p + 1..p + 1 + len as usize) else { return hex(rd) };
+                strings.push(quoted(s));
+                p += 1 + len as usize;
+            }
+            let repl = parse_name(msg, start + p).map(|(n, _)| n).unwrap_or_default();
+            format!("{} {} {} {}", order, pref, strings.join(" "), repl)
+        }
+        257 if rd.len() >= 2 => { // CAA: flags, tag, value
+            let tag_len = rd[1] as usize;
+            match rd.get(2..2 + tag_len) {
+                Some(tag) => format!("{} {} {}", rd[0], String::from_utf8_lossy(tag), quoted(&rd[2 + tag_len..])),
+                None => hex(rd),
+            }
+        }
         64 | 65 if rd.len() >= 3 => { // SVCB / HTTPS
             let pri = u16::from_be_bytes([rd[0], rd[1]]);
             let tgt = parse_name(msg, start + 2).map(|(n, _)| n).unwrap_or_default();
-            if pri == 0 { format!("AliasMode {}", tgt) } else { format!("{} {}", pri, tgt) }
+            if pri == 0 { return format!("AliasMode {}", tgt); }
+            let params = parse_svcb(rd).map(|svcb| svc_params_display(&svcb.params)).unwrap_or_default();
+            format!("{} {}{}", pri, tgt, params)
         }
         _ => hex(rd),
     }
 }
 
+/// A character-string in zone-file form: quoted, with '"' and '\\' escaped and
+/// non-printable bytes as \DDD.
+fn quoted(s: &[u8]) -> String {
+    let mut out = String::from("\"");
+    for &b in s {
+        match b {
+            b'"' | b'\\' => { out.push('\\'); out.push(b as char); }
+            0x20..=0x7e => out.push(b as char),
+            _ => out.push_str(&format!("\\{:03}", b)),
+        }
+    }
+    out.push('"');
+    out
+}
+
+const SVC_PARAM_KEYS: [&str; 9] = [
+    "mandatory", "alpn", "no-default-alpn", "port", "ipv4hint", "ech", "ipv6hint", "dohpath", "ohttp",
+];
+const SVC_PARAM_ECH: u16 = 5;
+
+fn svc_param_name(key: u16) -> String {
+    SVC_PARAM_KEYS.get(key as usize).map_or_else(|| format!("key{}", key), |k| k.to_string())
+}
+
+/// Presentation form of a SvcParam value; None for keys that take no value.
+fn svc_param_value(key: u16, v: &[u8]) -> Option<String> {
+    let join = |parts: Vec<String>| parts.join(",");
+    Some(match key {
+        0 => join(v.chunks_exact(2).map(|c| svc_param_name(u16::from_be_bytes([c[0], c[1]]))).collect()),
+        1 => {
+            let mut ids = Vec::new();
+            let mut p = 0;
+            while let Some(&len) = v.get(p) {
+                ids.push(String::from_utf8_lossy(v.get(p + 1..p + 1 + len as usize)?).into_owned());
+                p += 1 + len as usize;
+            }
+            join(ids)
+        }
+        2 | 8 if v.is_empty() => return None,
+        3 if v.len() == 2 => u16::from_be_bytes([v[0], v[1]]).to_string(),
+        4 => join(v.chunks_exact(4).map(|c| std::net::Ipv4Addr::new(c[0], c[1], c[2], c[3]).to_string()).collect()),
+        5 => crate::base64::encode(v),
+        6 => join(v.chunks_exact(16).map(|c| std::net::Ipv6Addr::from(<[u8; 16]>::try_from(c).unwrap()).to_string()).collect()),
+        7 => String::from_utf8_lossy(v).into_owned(),
+        _ => quoted(v),
+    })
+}
+
+pub struct Svcb {
+    pub priority: u16,
+    pub target: String,
+    pub params: Vec<(u16, Vec<u8>)>,
+}
+
+/// Split SVCB/HTTPS RDATA. The target is never compressed (RFC 9460 §2.2).
+pub fn parse_svcb(rd: &[u8]) -> Option<Svcb> {
+    let priority = u16::from_be_bytes([*rd.first()?, *rd.get(1)?]);
+    let (target, mut p) = parse_name(rd, 2)?;
+    let mut params = Vec::new();
+    while p < rd.len() {
+        let key = u16::from_be_bytes([*rd.get(p)?, *rd.get(p + 1)?]);
+        let len = u16::from_be_bytes([*rd.get(p + 2)?, *rd.get(p + 3)?]) as usize;
+        params.push((key, rd.get(p + 4..p + 4 + len)?.to_vec()));
+        p += 4 + len;
+    }
+    Some(Svcb { priority, target, params })
+}
+
+fn svc_params_display(params: &[(u16, Vec<u8>)]) -> String {
+    params.iter().map(|(k, v)| match svc_param_value(*k, v) {
+        Some(val) => format!(" {}={}", svc_param_name(*k), val),
+        None => format!(" {}", svc_param_name(*k)),
+    }).collect()
+}
+
 fn hex(data: &[u8]) -> String {
     data.iter().map(|b| format!("{:02x}", b)).collect()
 }
 
 // --- Serialization ---
 
-fn serialize_name(name: &str) -> Vec<u8> {
-    let mut out = Vec::new();
-    for label in name.split('.').filter(|l| !l.is_empty()) {
-        let len = label.len().min(63);
-        out.push(len as u8);
-        out.extend_from_slice(&label.as_bytes()[..len]);
+pub const SECTION_QUESTION: usize = 0;
+pub const SECTION_ANSWER: usize = 1;
+pub const SECTION_AUTHORITY: usize = 2;
+pub const SECTION_ADDITIONAL: usize = 3;
+
+/// Largest offset a compression pointer can hold.
+const MAX_POINTER: usize = 0x3FFF;
+
+/// Serializes a DNS message section by section, compressing names against the
+/// names already written (RFC 1035 §4.1.4). Sections must be written in order;
+/// the header counts are filled in by `finish`.
+pub struct DnsWriter {
+    buf: Vec<u8>,
+    counts: [u16; 4],
+    /// Name suffix ("example.com") -> offset of its first label. Matching is exact, so
+    /// the case of every name (0x20 randomization) survives compression.
+    names: HashMap<String, u16>,
+}
+
+impl DnsWriter {
+    pub fn new(id: u16, flags: u16) -> Self {
+        let mut buf = Vec::with_capacity(512);
+        buf.extend_from_slice(&id.to_be_bytes());
+        buf.extend_from_slice(&flags.to_be_bytes());
+        buf.extend_from_slice(&[0; 8]);
+        DnsWriter { buf, counts: [0; 4], names: HashMap::new() }
+    }
+
+    /// Append a name, pointing at the longest suffix already in the message.
+    /// Err(-2) for an empty inner label, a label over 63 bytes or a name over 255.
+    pub fn name(&mut self, name: &str) -> Result<(), i32> {
+        self.write_name(name, true)
+    }
+
+    /// Append a name in full, for RDATA that must not be compressed (SRV, RFC 2782).
+    /// Its suffixes can still be pointed at by later names.
+    pub fn name_uncompressed(&mut self, name: &str) -> Result<(), i32> {
+        self.write_name(name, false)
+    }
+
+    fn write_name(&mut self, name: &str, compress: bool) -> Result<(), i32> {
+        let (labels, end) = checked_labels(name)?;
+        for (start, label) in labels {
+            let suffix = &name[start..end];
+            if let Some(&off) = self.names.get(suffix).filter(|_| compress) {
+                self.buf.extend_from_slice(&(0xC000 | off).to_be_bytes());
+                return Ok(());
+            }
+            if self.buf.len() <= MAX_POINTER { self.names.insert(suffix.to_string(), self.buf.len() as u16); }
+            self.buf.push(label.len() as u8);
+            self.buf.extend_from_slice(&label);
+        }
+        self.buf.push(0);
+        Ok(())
+    }
+
+    pub fn bytes(&mut self, b: &[u8]) {
+        self.buf.extend_from_slice(b);
+    }
+
+    pub fn question(&mut self, name: &str, qtype: u16, qclass: u16) -> Result<(), i32> {
+        self.name(name)?;
+        self.bytes(&qtype.to_be_bytes());
+        self.bytes(&qclass.to_be_bytes());
+        self.count(SECTION_QUESTION)
+    }
+
+    /// Append a record whose RDATA is written by `rdata`, which may use `name` for
+    /// the compressible names of RFC 1035 types (NS, CNAME, PTR, MX, SOA).
+    pub fn record<F>(&mut self, section: usize, name: &str, rtype: u16, rclass: u16, ttl: u32, rdata: F) -> Result<(), i32>
+    where
+        F: FnOnce(&mut Self) -> Result<(), i32>,
+    {
+        self.name(name)?;
+        self.bytes(&rtype.to_be_bytes());
+        self.bytes(&rclass.to_be_bytes());
+        self.bytes(&ttl.to_be_bytes());
+        let len_at = self.buf.len();
+        self.bytes(&[0, 0]);
+        rdata(self)?;
+        let rdlen = u16::try_from(self.buf.len() - len_at - 2).map_err(|_| -2)?;
+        self.buf[len_at..len_at + 2].copy_from_slice(&rdlen.to_be_bytes());
+        self.count(section)
+    }
+
+    fn count(&mut self, section: usize) -> Result<(), i32> {
+        let c = self.counts.get_mut(section).ok_or(-2)?;
+        *c = c.checked_add(1).ok_or(-2)?;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        for (i, c) in self.counts.iter().enumerate() {
+            self.buf[4 + i * 2..6 + i * 2].copy_from_slice(&c.to_be_bytes());
+        }
+        self.buf
     }
-    out.push(0);
-    out
 }
 
-fn build_query_bytes(domain: &str, rtype: u16, id: u16, rd: bool) -> Vec<u8> {
-    let mut buf = Vec::with_capacity(64);
-    buf.extend_from_slice(&id.to_be_bytes());
-    buf.extend_from_slice(&(if rd { 0x0100u16 } else { 0u16 }).to_be_bytes());
-    buf.extend_from_slice(&1u16.to_be_bytes()); // QDCOUNT
-    buf.extend_from_slice(&[0; 6]); // AN/NS/AR = 0
-    buf.extend(serialize_name(domain));
-    buf.extend_from_slice(&rtype.to_be_bytes());
-    buf.extend_from_slice(&1u16.to_be_bytes()); // QCLASS = IN
-    buf
+/// Copy the RDATA at msg[start..end] into `w`, expanding embedded names so they
+/// stay valid in the new message: compressed again for the RFC 1035 types, in full
+/// for SRV.
+fn copy_rdata(w: &mut DnsWriter, rtype: u16, msg: &[u8], start: usize, end: usize) -> Result<(), i32> {
+    let name_at = |w: &mut DnsWriter, pos: usize, compress: bool| -> Result<usize, i32> {
+        let (n, next) = parse_name(msg, pos).filter(|&(_, next)| next <= end).ok_or(-2)?;
+        w.write_name(&n, compress)?;
+        Ok(next)
+    };
+    let fixed = |from: usize, len: usize| msg.get(from..from + len).filter(|_| from + len <= end).ok_or(-2);
+    match rtype {
+        2 | 5 | 12 => { name_at(w, start, true)?; }
+        15 => {
+            w.bytes(fixed(start, 2)?);
+            name_at(w, start + 2, true)?;
+        }
+        6 => { // SOA: mname, rname, serial and four timers
+            let p = name_at(w, start, true)?;
+            let p = name_at(w, p, true)?;
+            w.bytes(fixed(p, 20)?);
+        }
+        33 => {
+            w.bytes(fixed(start, 6)?);
+            name_at(w, start + 6, false)?;
+        }
+        _ => w.bytes(&msg[start..end]),
+    }
+    Ok(())
 }
 
-// --- FFI helpers ---
+/// Write the RDATA of `rtype` from its display_value text form.
+/// Err(-2) malformed value, Err(-3) no text form for this type (pass raw RDATA).
+fn encode_rdata(w: &mut DnsWriter, rtype: u16, value: &str) -> Result<(), i32> {
+    let mut fields = value.split_ascii_whitespace();
+    let mut num = |w: &mut DnsWriter| -> Result<(), i32> {
+        let n: u16 = fields.next().and_then(|f| f.parse().ok()).ok_or(-2)?;
+        w.bytes(&n.to_be_bytes());
+        Ok(())
+    };
+    match rtype {
+        1 => w.bytes(&value.trim().parse::<std::net::Ipv4Addr>().map_err(|_| -2)?.octets()),
+        28 => w.bytes(&value.trim().parse::<std::net::Ipv6Addr>().map_err(|_| -2)?.octets()),
+        2 | 5 | 12 => w.name(value.trim())?,
+        15 => {
+            num(w)?;
+            w.name(fields.next().ok_or(-2)?)?;
+        }
+        16 => {
+            // Character-strings hold 255 bytes; the parser shows them joined
+            for chunk in value.as_bytes().chunks(255) {
+                w.bytes(&[chunk.len() as u8]);
+                w.bytes(chunk);
+            }
+            if value.is_empty() { w.bytes(&[0]); }
+        }
+        33 => {
+            for _ in 0..3 { num(w)?; }
+            w.name_uncompressed(fields.next().ok_or(-2)?)?;
+        }
+        _ => return Err(-3),
+    }
+    Ok(())
+}
 
-fn to_cstr(s: &str) -> *mut c_char {
-    CString::new(s).unwrap_or_else(|_| CString::new("").unwrap()).into_raw()
+/// Serialize a whole message. Records are written grouped by section, keeping their
+/// order within a section.
+pub fn build_message(id: u16, flags: u16, questions: &[Question], records: &[BuildRecord]) -> Result<Vec<u8>, i32> {
+    let mut w = DnsWriter::new(id, flags);
+    for (name, qtype, qclass) in questions {
+        w.question(name, *qtype, *qclass)?;
+    }
+    let mut records: Vec<&BuildRecord> = records.iter().collect();
+    records.sort_by_key(|r| r.section);
+    for r in records {
+        if !matches!(r.section, SECTION_ANSWER | SECTION_AUTHORITY | SECTION_ADDITIONAL) { return Err(-2); }
+        w.record(r.section, &r.name, r.rtype, r.rclass, r.ttl, |w| match &r.data {
+            RecordData::Text(v) => encode_rdata(w, r.rtype, v),
+            RecordData::Raw(b) => { w.bytes(b); Ok(()) }
+        })?;
+    }
+    Ok(w.finish())
 }
 
+fn c_slice<'a, T>(p: *const T, n: usize) -> Result<&'a [T], i32> {
+    match (p.is_null(), n) {
+        (_, 0) => Ok(&[]),
+        (true, _) => Err(-2),
+        (false, _) => Ok(unsafe { std::slice::from_raw_parts(p, n) }),
+    }
+}
+
+/// Serialize a parsed message, as edited by the caller, with names compressed.
+/// Header bits the parser does not report (Z, AD, CD) are written as 0.
+fn serialize(m: &IrisDnsMessage) -> Result<Vec<u8>, i32> {
+    let flags = (m.is_response as u16) << 15 | ((m.opcode & 0xF) as u16) << 11
+        | (m.is_authoritative as u16) << 10 | (m.is_truncated as u16) << 9
+        | (m.recursion_desired as u16) << 8 | (m.recursion_available as u16) << 7
+        | (m.response_code & 0xF) as u16;
+    let name = |p: *const c_char| {
+        if p.is_null() { return Err(-2); }
+        unsafe { CStr::from_ptr(p) }.to_str().map_err(|_| -2)
+    };
+    let mut w = DnsWriter::new(m.id, flags);
+    for q in c_slice(m.questions, m.questions_count)? {
+        w.question(name(q.name)?, q.record_type, q.qclass | if q.unicast_response { 0x8000 } else { 0 })?;
+    }
+    let sections = [
+        (SECTION_ANSWER, m.answers, m.answers_count),
+        (SECTION_AUTHORITY, m.authority, m.authority_count),
+        (SECTION_ADDITIONAL, m.additional, m.additional_count),
+    ];
+    for (section, rrs, count) in sections {
+        for r in c_slice(rrs, count)? {
+            let rdata = c_slice(r.rdata, r.rdata_len)?;
+            let class = r.rrclass | if r.cache_flush { 0x8000 } else { 0 };
+            // Parsed RDATA holds its names uncompressed, so it reads as its own buffer
+            w.record(section, name(r.name)?, r.record_type, class, r.ttl, |w| copy_rdata(w, r.record_type, rdata, 0, rdata.len()))?;
+        }
+    }
+    Ok(w.finish())
+}
+
+/// Re-serialize a message with its names compressed.
+pub fn compress_message(msg: &[u8]) -> Result<Vec<u8>, i32> {
+    let be16 = |o: usize| msg.get(o..o + 2).map(|b| u16::from_be_bytes([b[0], b[1]])).ok_or(-2);
+    let mut w = DnsWriter::new(be16(0)?, be16(2)?);
+    let mut off = 12;
+    for section in [SECTION_QUESTION, SECTION_ANSWER, SECTION_AUTHORITY, SECTION_ADDITIONAL] {
+        for _ in 0..be16(4 + section * 2)? {
+            let (name, p) = parse_name(msg, off).ok_or(-2)?;
+            let (rtype, class) = (be16(p)?, be16(p + 2)?);
+            if section == SECTION_QUESTION {
+                w.question(&name, rtype, class)?;
+                off = p + 4;
+                continue;
+            }
+            let ttl = (be16(p + 4)? as u32) << 16 | be16(p + 6)? as u32;
+            let start = p + 10;
+            let end = start + be16(p + 8)? as usize;
+            if end > msg.len() { return Err(-2); }
+            w.record(section, &name, rtype, class, ttl, |w| copy_rdata(w, rtype, msg, start, end))?;
+            off = end;
+        }
+    }
+    Ok(w.finish())
+}
+
+/// A query for `domain`, its Unicode labels IDNA-encoded.
+fn build_query_bytes(domain: &str, rtype: u16, id: u16, rd: bool) -> Result<Vec<u8>, i32> {
+    let mut w = DnsWriter::new(id, if rd { 0x0100 } else { 0 });
+    w.question(&idn::to_ascii(domain)?, rtype, 1)?; // QCLASS = IN
+    Ok(w.finish())
+}
+
+// --- FFI helpers ---
+
 fn alloc_questions(qs: Vec<DnsQ>) -> (*mut IrisDnsQuestion, usize) {
     let count = qs.len();
     if count == 0 { return (std::ptr::null_mut(), 0); }
@@ -232,13 +1031,14 @@ fn alloc_questions(qs: Vec<DnsQ>) -> (*mut IrisDnsQuestion, usize) {
         unsafe {
             ptr.add(i).write(IrisDnsQuestion {
                 name: to_cstr(&q.name), record_type: q.qtype, qclass: q.qclass,
+                unicast_response: q.unicast_response,
             });
         }
     }
     (ptr, count)
 }
 
-fn alloc_records(rrs: Vec<DnsRR>) -> (*mut IrisDnsRecord, usize) {
+pub fn alloc_records(rrs: Vec<DnsRR>) -> (*mut IrisDnsRecord, usize) {
     let count = rrs.len();
     if count == 0 { return (std::ptr::null_mut(), 0); }
     let layout = std::alloc::Layout::array::<IrisDnsRecord>(count).unwrap();
@@ -250,7 +1050,7 @@ fn alloc_records(rrs: Vec<DnsRR>) -> (*mut IrisDnsRecord, usize) {
             ptr.add(i).write(IrisDnsRecord {
                 name: to_cstr(&rr.name), record_type: rr.rtype, rrclass: rr.rclass,
                 ttl: rr.ttl, rdata: rdata_ptr, rdata_len,
-                display_value: to_cstr(&rr.display),
+                display_value: to_cstr(&rr.display), cache_flush: rr.cache_flush,
             });
         }
     }
@@ -259,35 +1059,188 @@ fn alloc_records(rrs: Vec<DnsRR>) -> (*mut IrisDnsRecord, usize) {
 
 // --- FFI entry points ---
 
+fn message(parsed: ParsedDns) -> IrisDnsMessage {
+    let (id, is_resp, opcode, aa, tc, rd, ra, rcode, qs, ans, auth, add) = parsed;
+    // label_text turns every literal backslash into an escape, so any backslash is one
+    let names_escaped = qs.iter().map(|q| &q.name).chain(ans.iter().chain(&auth).chain(&add).map(|r| &r.name))
+        .any(|n| n.contains('\\'));
+    let (qp, qc) = alloc_questions(qs);
+    let (ap, ac) = alloc_records(ans);
+    let (np, nc) = alloc_records(auth);
+    let (dp, dc) = alloc_records(add);
+    IrisDnsMessage {
+        id, is_response: is_resp, opcode, is_authoritative: aa,
+        is_truncated: tc, recursion_desired: rd, recursion_available: ra,
+        response_code: rcode,
+        questions: qp, questions_count: qc,
+        answers: ap, answers_count: ac,
+        authority: np, authority_count: nc,
+        additional: dp, additional_count: dc,
+        names_escaped,
+    }
+}
+
 /// Parse DNS wire format. Returns 0=ok, -2=error.
 #[no_mangle]
 pub extern "C" fn iris_dns_parse(data: *const u8, len: usize, out: *mut IrisDnsMessage) -> i32 {
     if data.is_null() || out.is_null() || len == 0 { return -2; }
     let buf = unsafe { std::slice::from_raw_parts(data, len) };
     match parse_dns(buf) {
-        Some((id, is_resp, opcode, aa, tc, rd, ra, rcode, qs, ans, auth, add)) => {
-            let (qp, qc) = alloc_questions(qs);
-            let (ap, ac) = alloc_records(ans);
-            let (np, nc) = alloc_records(auth);
-            let (dp, dc) = alloc_records(add);
-            unsafe {
-                out.write(IrisDnsMessage {
-                    id, is_response: is_resp, opcode, is_authoritative: aa,
-                    is_truncated: tc, recursion_desired: rd, recursion_available: ra,
-                    response_code: rcode,
-                    questions: qp, questions_count: qc,
-                    answers: ap, answers_count: ac,
-                    authority: np, authority_count: nc,
-                    additional: dp, additional_count: dc,
-                });
-            }
+        Some(parsed) => { unsafe { out.write(message(parsed)); } 0 }
+        None => -2,
+    }
+}
+
+/// Like iris_dns_parse with per-call limits (NULL = defaults).
+/// Returns 0=ok, -2=error, -3=over a limit. Free with iris_dns_free_message.
+#[no_mangle]
+pub extern "C" fn iris_dns_parse_limited(data: *const u8, len: usize, limits: *const IrisDnsLimits, out: *mut IrisDnsMessage) -> i32 {
+    if data.is_null() || out.is_null() || len == 0 { return -2; }
+    let buf = unsafe { std::slice::from_raw_parts(data, len) };
+    let limits = if limits.is_null() { DEFAULT_LIMITS } else { DnsLimits::from_ffi(unsafe { &*limits }) };
+    match parse_dns_limited(buf, &limits) {
+        Ok(parsed) => { unsafe { out.write(message(parsed)); } 0 }
+        Err(e) => e,
+    }
+}
+
+/// Parse an mDNS message (UDP 5353): like iris_dns_parse, but the top bit of each
+/// class is reported as unicast_response (questions) or cache_flush (records) and
+/// cleared from qclass/rrclass. Returns 0=ok, -2=error. Free with iris_dns_free_message.
+#[no_mangle]
+pub extern "C" fn iris_dns_parse_mdns(data: *const u8, len: usize, out: *mut IrisDnsMessage) -> i32 {
+    if data.is_null() || out.is_null() || len == 0 { return -2; }
+    let buf = unsafe { std::slice::from_raw_parts(data, len) };
+    match parse_dns(buf) {
+        Some(mut parsed) => {
+            split_mdns_class(&mut parsed);
+            unsafe { out.write(message(parsed)); }
             0
         }
         None => -2,
     }
 }
 
+/// Walk a message without allocating, for high packet rates: fills up to `cap`
+/// entries whose RDATA points into `data`; names are decoded on demand with
+/// iris_dns_view_name. Returns 0=ok, -1=more than `cap` entries (out->count has the
+/// total), -2=error. Nothing to free.
+#[no_mangle]
+pub extern "C" fn iris_dns_view(data: *const u8, len: usize, out: *mut IrisDnsView, entries: *mut IrisDnsEntry, cap: usize) -> i32 {
+    if data.is_null() || out.is_null() || (entries.is_null() && cap != 0) { return -2; }
+    let buf = unsafe { std::slice::from_raw_parts(data, len) };
+    let slots = if cap == 0 { &mut [][..] } else { unsafe { std::slice::from_raw_parts_mut(entries, cap) } };
+    match view(buf, slots) {
+        Some(v) => {
+            let rc = if v.count > cap { -1 } else { 0 };
+            unsafe { out.write(v); }
+            rc
+        }
+        None => -2,
+    }
+}
+
+/// Decode the name at `offset` (an IrisDnsEntry name_offset) into `buf` as a
+/// NUL-terminated dotted name. Returns 0=ok, -1=buf too small, -2=malformed.
+#[no_mangle]
+pub extern "C" fn iris_dns_view_name(data: *const u8, len: usize, offset: usize, buf: *mut c_char, cap: usize, out_len: *mut usize) -> i32 {
+    if data.is_null() || buf.is_null() || cap == 0 { return -2; }
+    let msg = unsafe { std::slice::from_raw_parts(data, len) };
+    let dst = unsafe { std::slice::from_raw_parts_mut(buf as *mut u8, cap) };
+    if walk_name(msg, offset, |_| {}).is_none() { return -2; }
+    match view_name(msg, offset, &mut dst[..cap - 1]) {
+        Some(n) => {
+            dst[n] = 0;
+            if !out_len.is_null() { unsafe { *out_len = n; } }
+            0
+        }
+        None => -1,
+    }
+}
+
+/// Mnemonic for an RR type ("A", "AAAA", "HTTPS", "ANY"). Returns a static string
+/// (do not free), or NULL for an unassigned type, conventionally shown as "TYPE<n>".
+#[no_mangle]
+pub extern "C" fn iris_dns_type_name(record_type: u16) -> *const c_char {
+    table_name(RR_TYPES, record_type).map_or(std::ptr::null(), CStr::as_ptr)
+}
+
+/// RR type for a mnemonic (case-insensitive) or "TYPE<n>". Returns the type, or
+/// -3 if unknown, -2 on a NULL or non-UTF-8 name.
+#[no_mangle]
+pub extern "C" fn iris_dns_type_from_name(name: *const c_char) -> i32 {
+    if name.is_null() { return -2; }
+    let Ok(name) = unsafe { CStr::from_ptr(name) }.to_str() else { return -2 };
+    type_from_name(name).map_or(-3, i32::from)
+}
+
+/// Name of an RCODE, including extended RCODEs up to BADCOOKIE (23): "NOERROR",
+/// "NXDOMAIN", "SERVFAIL". Returns a static string (do not free), or NULL if unassigned.
+#[no_mangle]
+pub extern "C" fn iris_dns_rcode_name(rcode: u16) -> *const c_char {
+    table_name(RCODES, rcode).map_or(std::ptr::null(), CStr::as_ptr)
+}
+
+/// Create a reassembler for one direction of a DNS-over-TCP (or decrypted DoT)
+/// connection. Free with iris_dns_tcp_free.
+#[no_mangle]
+pub extern "C" fn iris_dns_tcp_new() -> *mut IrisDnsTcpStream {
+    Box::into_raw(Box::new(IrisDnsTcpStream { inner: Mutex::new(DnsTcpStream::default()) }))
+}
+
+/// Feed stream bytes in order and parse every message they complete. Returns 0=ok,
+/// -2=arg error. Free with iris_dns_messages_free.
+#[no_mangle]
+pub extern "C" fn iris_dns_tcp_feed(s: *mut IrisDnsTcpStream, data: *const u8, len: usize, out: *mut IrisDnsMessages) -> i32 {
+    if s.is_null() || out.is_null() || (data.is_null() && len != 0) { return -2; }
+    let d = if len == 0 { &[][..] } else { unsafe { std::slice::from_raw_parts(data, len) } };
+    let (frames, limits) = {
+        let mut st = unsafe { &*s }.inner.lock().unwrap_or_else(|e| e.into_inner());
+        (st.feed(d), st.limits)
+    };
+    let total = frames.len();
+    let messages: Vec<IrisDnsMessage> = frames.iter().filter_map(|f| parse_dns_limited(f, &limits).ok()).map(message).collect();
+    let malformed = total - messages.len();
+    let (messages, count) = alloc_array(messages);
+    unsafe { out.write(IrisDnsMessages { messages, count, malformed }); }
+    0
+}
+
+/// Replace the limits the stream parses with (defaults otherwise); zone transfers
+/// need more than 256 records per message.
+#[no_mangle]
+pub extern "C" fn iris_dns_tcp_set_limits(s: *mut IrisDnsTcpStream, limits: *const IrisDnsLimits) {
+    if s.is_null() || limits.is_null() { return; }
+    unsafe { &*s }.inner.lock().unwrap_or_else(|e| e.into_inner()).limits = DnsLimits::from_ffi(unsafe { &*limits });
+}
+
+/// Bytes of a partial message waiting for more data.
+#[no_mangle]
+pub extern "C" fn iris_dns_tcp_pending(s: *const IrisDnsTcpStream) -> usize {
+    if s.is_null() { return 0; }
+    unsafe { &*s }.inner.lock().unwrap_or_else(|e| e.into_inner()).pending()
+}
+
+#[no_mangle]
+pub extern "C" fn iris_dns_messages_free(m: *mut IrisDnsMessages) {
+    if m.is_null() { return; }
+    let m = unsafe { &*m };
+    for i in 0..m.count {
+        iris_dns_free_message(unsafe { m.messages.add(i) });
+    }
+    free_array(m.messages, m.count);
+}
+
+#[no_mangle]
+pub extern "C" fn iris_dns_tcp_free(s: *mut IrisDnsTcpStream) {
+    if s.is_null() { return; }
+    drop(unsafe { Box::from_raw(s) });
+}
+
 /// Build a DNS query. Returns serialized bytes via out_data/out_len. Free with iris_free_bytes.
+/// Unicode labels are sent as IDNA2008 A-labels ("münchen" -> "xn--mnchen-3ya").
+/// Returns 0=ok, -2=arg error or invalid name (empty label, label over 63 bytes,
+/// misplaced hyphen), -3=code point not allowed in a domain name.
 #[no_mangle]
 pub extern "C" fn iris_dns_build_query(
     domain: *const c_char, record_type: u16, id: u16, recursion_desired: bool,
@@ -297,12 +1250,164 @@ pub extern "C" fn iris_dns_build_query(
     let domain_str = match unsafe { CStr::from_ptr(domain) }.to_str() {
         Ok(s) => s, Err(_) => return -2,
     };
-    let bytes = build_query_bytes(domain_str, record_type, id, recursion_desired);
+    let bytes = match build_query_bytes(domain_str, record_type, id, recursion_desired) {
+        Ok(b) => b, Err(e) => return e,
+    };
     let (ptr, len) = alloc_bytes(&bytes);
     unsafe { *out_data = ptr; *out_len = len; }
     0
 }
 
+/// Copy of `name` with the case of each letter randomized (DNS 0x20). Keep the query
+/// to check the response with iris_dns_0x20_check. Free with iris_free_string.
+/// Returns NULL on a NULL or non-UTF-8 name.
+#[no_mangle]
+pub extern "C" fn iris_dns_0x20_encode(name: *const c_char) -> *mut c_char {
+    if name.is_null() { return std::ptr::null_mut(); }
+    match unsafe { CStr::from_ptr(name) }.to_str() {
+        Ok(s) => to_cstr(&randomize_case(s)),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// iris_dns_build_query with the question name 0x20-encoded.
+/// Returns 0=ok, -2=arg error or invalid name, -3=disallowed code point.
+/// Free with iris_free_bytes.
+#[no_mangle]
+pub extern "C" fn iris_dns_build_query_0x20(
+    domain: *const c_char, record_type: u16, id: u16, recursion_desired: bool,
+    out_data: *mut *mut u8, out_len: *mut usize,
+) -> i32 {
+    if domain.is_null() || out_data.is_null() || out_len.is_null() { return -2; }
+    let Ok(domain) = unsafe { CStr::from_ptr(domain) }.to_str() else { return -2 };
+    let bytes = match build_query_bytes(&randomize_case(domain), record_type, id, recursion_desired) {
+        Ok(b) => b, Err(e) => return e,
+    };
+    let (ptr, len) = alloc_bytes(&bytes);
+    unsafe { *out_data = ptr; *out_len = len; }
+    0
+}
+
+/// Check that a response repeats its query's question with the exact letter case.
+/// Returns 1=echoed, 0=case changed (resolver does not preserve case, or a spoofed
+/// answer), -2=arg error or malformed, -3=different question or none.
+#[no_mangle]
+pub extern "C" fn iris_dns_0x20_check(query: *const u8, query_len: usize, response: *const u8, response_len: usize) -> i32 {
+    if query.is_null() || response.is_null() { return -2; }
+    let q = unsafe { std::slice::from_raw_parts(query, query_len) };
+    let r = unsafe { std::slice::from_raw_parts(response, response_len) };
+    case_echoed(q, r).map_or_else(|e| e, i32::from)
+}
+
+/// Re-serialize a DNS message with RFC 1035 name compression, e.g. to shrink a
+/// message built without it. Names inside NS, CNAME, PTR, MX and SOA data are
+/// compressed too. Returns 0=ok, -2=arg error or malformed message.
+/// Free *out_data with iris_free_bytes.
+#[no_mangle]
+pub extern "C" fn iris_dns_compress(data: *const u8, len: usize, out_data: *mut *mut u8, out_len: *mut usize) -> i32 {
+    if data.is_null() || out_data.is_null() || out_len.is_null() { return -2; }
+    let msg = match compress_message(unsafe { std::slice::from_raw_parts(data, len) }) {
+        Ok(m) => m, Err(e) => return e,
+    };
+    let (ptr, n) = alloc_bytes(&msg);
+    unsafe { *out_data = ptr; *out_len = n; }
+    0
+}
+
+/// Serialize an IrisDnsMessage from iris_dns_parse or iris_dns_parse_mdns after
+/// editing it in place (rewrite a TTL or rdata, reorder records, lower a count to
+/// drop trailing ones). display_value is ignored. Returns 0=ok, -2=arg error or
+/// invalid name/rdata. Free *out_data with iris_free_bytes.
+#[no_mangle]
+pub extern "C" fn iris_dns_serialize(msg: *const IrisDnsMessage, out_data: *mut *mut u8, out_len: *mut usize) -> i32 {
+    if msg.is_null() || out_data.is_null() || out_len.is_null() { return -2; }
+    let bytes = match serialize(unsafe { &*msg }) {
+        Ok(b) => b, Err(e) => return e,
+    };
+    let (ptr, n) = alloc_bytes(&bytes);
+    unsafe { *out_data = ptr; *out_len = n; }
+    0
+}
+
+/// Serialize a complete DNS message: header id and flags word (QR 0x8000, opcode
+/// << 11, AA 0x0400, TC 0x0200, RD 0x0100, RA 0x0080, rcode in the low 4 bits),
+/// questions, and records in any section order. Names are compressed.
+/// Returns 0=ok, -2=arg error or invalid name/value, -3=a record type with no text
+/// form was given a value instead of rdata. Free *out_data with iris_free_bytes.
+#[no_mangle]
+pub extern "C" fn iris_dns_build_message(
+    id: u16, flags: u16,
+    questions: *const IrisDnsQuestion, questions_count: usize,
+    records: *const IrisDnsBuildRecord, records_count: usize,
+    out_data: *mut *mut u8, out_len: *mut usize,
+) -> i32 {
+    if out_data.is_null() || out_len.is_null() { return -2; }
+    if (questions.is_null() && questions_count > 0) || (records.is_null() && records_count > 0) { return -2; }
+    let text = |p: *const c_char| unsafe { CStr::from_ptr(p) }.to_str().map(str::to_string).map_err(|_| -2);
+    let mut qs = Vec::with_capacity(questions_count);
+    for i in 0..questions_count {
+        let q = unsafe { &*questions.add(i) };
+        if q.name.is_null() { return -2; }
+        match text(q.name) {
+            Ok(name) => qs.push((name, q.record_type, q.qclass | if q.unicast_response { 0x8000 } else { 0 })),
+            Err(e) => return e,
+        }
+    }
+    let mut rrs = Vec::with_capacity(records_count);
+    for i in 0..records_count {
+        let r = unsafe { &*records.add(i) };
+        if r.name.is_null() || (r.value.is_null() && r.rdata.is_null() && r.rdata_len > 0) { return -2; }
+        let data = if !r.value.is_null() {
+            match text(r.value) { Ok(v) => RecordData::Text(v), Err(e) => return e }
+        } else if r.rdata_len == 0 {
+            RecordData::Raw(Vec::new())
+        } else {
+            RecordData::Raw(unsafe { std::slice::from_raw_parts(r.rdata, r.rdata_len) }.to_vec())
+        };
+        let name = match text(r.name) { Ok(n) => n, Err(e) => return e };
+        rrs.push(BuildRecord { section: r.section as usize, name, rtype: r.record_type, rclass: r.rrclass, ttl: r.ttl, data });
+    }
+    let msg = match build_message(id, flags, &qs, &rrs) { Ok(m) => m, Err(e) => return e };
+    let (ptr, len) = alloc_bytes(&msg);
+    unsafe { *out_data = ptr; *out_len = len; }
+    0
+}
+
+/// Decode the RDATA of an SVCB or HTTPS record (IrisDnsRecord.rdata) into its
+/// priority, target and SvcParams. Returns 0=ok, -2=arg error or malformed.
+/// Free with iris_dns_svcb_free.
+#[no_mangle]
+pub extern "C" fn iris_dns_parse_svcb(rdata: *const u8, len: usize, out: *mut IrisDnsSvcb) -> i32 {
+    if rdata.is_null() || out.is_null() { return -2; }
+    let Some(svcb) = parse_svcb(unsafe { std::slice::from_raw_parts(rdata, len) }) else { return -2 };
+    let (ech, ech_len) = match svcb.params.iter().find(|(k, _)| *k == SVC_PARAM_ECH) {
+        Some((_, v)) => alloc_bytes(v),
+        None => (std::ptr::null_mut(), 0),
+    };
+    let params = svcb.params.iter().map(|(k, v)| {
+        (svc_param_name(*k).into_bytes(), svc_param_value(*k, v).map(String::into_bytes))
+    }).collect();
+    unsafe {
+        out.write(IrisDnsSvcb {
+            priority: svcb.priority,
+            target: to_cstr(&svcb.target),
+            params: url::alloc_params(params),
+            ech,
+            ech_len,
+        });
+    }
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn iris_dns_svcb_free(s: *mut IrisDnsSvcb) {
+    if s.is_null() { return; }
+    let s = unsafe { &mut *s };
+    free_cstr(s.target);
+    url::iris_url_params_free(&mut s.params);
+    crate::ffi::iris_free_bytes(s.ech, s.ech_len);
+}
+
 fn free_questions(ptr: *mut IrisDnsQuestion, count: usize) {
     if ptr.is_null() || count == 0 { return; }
     for i in 0..count {
@@ -315,7 +1420,7 @@ fn free_questions(ptr: *mut IrisDnsQuestion, count: usize) {
     unsafe { std::alloc::dealloc(ptr as *mut u8, layout); }
 }
 
-fn free_records(ptr: *mut IrisDnsRecord, count: usize) {
+pub fn free_records(ptr: *mut IrisDnsRecord, count: usize) {
     if ptr.is_null() || count == 0 { return; }
     for i in 0..count {
         unsafe {
@@ -344,3 +1449,477 @@ pub extern "C" fn iris_dns_free_message(msg: *mut IrisDnsMessage) {
         free_records(m.additional, m.additional_count);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compressed_names_round_trip() {
+        let mut w = DnsWriter::new(0x1234, 0x8180);
+        w.question("www.example.com", 1, 1).unwrap();
+        w.record(SECTION_ANSWER, "www.example.com.", 5, 1, 300, |w| w.name("cdn.example.com")).unwrap();
+        w.record(SECTION_ANSWER, "cdn.example.com", 1, 1, 60, |w| { w.bytes(&[192, 0, 2, 1]); Ok(()) }).unwrap();
+        w.record(SECTION_AUTHORITY, "example.com", 2, 1, 3600, |w| w.name("ns1.example.net")).unwrap();
+        let msg = w.finish();
+        // Question name at 12; the CNAME owner is a bare pointer to it
+        assert_eq!(&msg[33..35], &[0xC0, 12]);
+        // "cdn" plus a pointer to "example.com" inside the question name
+        assert_eq!(&msg[45..51], &[3, b'c', b'd', b'n', 0xC0, 16]);
+        assert_eq!(msg.len(), 96);
+
+        let (id, resp, .., qs, ans, auth, add) = parse_dns(&msg).unwrap();
+        assert!(id == 0x1234 && resp && add.is_empty());
+        assert_eq!(qs[0].name, "www.example.com");
+        assert_eq!((ans[0].display.as_str(), ans[1].name.as_str()), ("cdn.example.com", "cdn.example.com"));
+        assert_eq!((ans[1].display.as_str(), auth[0].display.as_str()), ("192.0.2.1", "ns1.example.net"));
+    }
+
+    #[test]
+    fn compress_existing_message() {
+        let mut msg = vec![0, 1, 0x81, 0x80, 0, 1, 0, 3, 0, 0, 0, 0];
+        let name = b"\x03www\x07example\x03com\x00";
+        let target = b"\x03cdn\x07example\x03com\x00";
+        msg.extend_from_slice(name);
+        msg.extend_from_slice(&[0, 1, 0, 1]);
+        msg.extend_from_slice(name);
+        msg.extend_from_slice(&[0, 5, 0, 1, 0, 0, 1, 44, 0, target.len() as u8]);
+        msg.extend_from_slice(target);
+        msg.extend_from_slice(name);
+        msg.extend_from_slice(&[0, 15, 0, 1, 0, 0, 0, 60, 0, target.len() as u8 + 2, 0, 10]);
+        msg.extend_from_slice(target);
+        // SRV targets are expanded but never compressed
+        msg.extend_from_slice(&[0xC0, 12, 0, 33, 0, 1, 0, 0, 0, 60, 0, 8, 0, 1, 0, 2, 0, 80, 0xC0, 0x3c]);
+
+        let (mut out, mut n) = (std::ptr::null_mut(), 0);
+        assert_eq!(iris_dns_compress(msg.as_ptr(), msg.len(), &mut out, &mut n), 0);
+        let c = unsafe { std::slice::from_raw_parts(out, n) }.to_vec();
+        crate::ffi::iris_free_bytes(out, n);
+        assert!(c.len() < msg.len());
+        let (.., ans, _, _) = parse_dns(&c).unwrap();
+        let shown: Vec<&str> = ans.iter().map(|r| r.display.as_str()).collect();
+        assert_eq!(shown, ["cdn.example.com", "10 cdn.example.com", "1 2 80 cdn.example.com"]);
+        assert!(c.ends_with(b"\x00\x50\x03cdn\x07example\x03com\x00"));
+        assert_eq!(compress_message(&msg[..msg.len() - 1]), Err(-2));
+    }
+
+    #[test]
+    fn build_typed_response() {
+        let rec = |section: u8, name: &'static CStr, rtype: u16, value: Option<&'static CStr>, rdata: &'static [u8]| IrisDnsBuildRecord {
+            section, name: name.as_ptr(), record_type: rtype, rrclass: 1, ttl: 300,
+            value: value.map_or(std::ptr::null(), CStr::as_ptr), rdata: rdata.as_ptr(), rdata_len: rdata.len(),
+        };
+        let q = [IrisDnsQuestion { name: c"example.com".as_ptr() as *mut c_char, record_type: 255, qclass: 1, unicast_response: false }];
+        let long: &'static CStr = Box::leak(CString::new("x".repeat(300)).unwrap().into_boxed_c_str());
+        let records = [
+            rec(3, c"ns1.example.com", 1, Some(c"192.0.2.53"), b""),
+            rec(1, c"example.com", 1, Some(c"192.0.2.1"), b""),
+            rec(1, c"example.com", 28, Some(c"2001:db8::1"), b""),
+            rec(1, c"www.example.com", 5, Some(c"example.com."), b""),
+            rec(1, c"example.com", 15, Some(c"10 mail.example.com"), b""),
+            rec(1, c"example.com", 16, Some(long), b""),
+            rec(1, c"_sip._tcp.example.com", 33, Some(c"0 5 5060 sip.example.com"), b""),
+            rec(1, c"1.2.0.192.in-addr.arpa", 12, Some(c"example.com"), b""),
+            rec(2, c"example.com", 2, Some(c"ns1.example.com"), b""),
+            rec(1, c"example.com", 99, None, b"\x01\x02"),
+        ];
+        let (mut out, mut n) = (std::ptr::null_mut(), 0);
+        assert_eq!(iris_dns_build_message(0xbeef, 0x8580, q.as_ptr(), 1, records.as_ptr(), records.len(), &mut out, &mut n), 0);
+        let msg = unsafe { std::slice::from_raw_parts(out, n) }.to_vec();
+        crate::ffi::iris_free_bytes(out, n);
+
+        let (id, resp, _, aa, _, rd, _, _, qs, ans, auth, add) = parse_dns(&msg).unwrap();
+        assert!(id == 0xbeef && resp && aa && rd);
+        assert_eq!((qs[0].name.as_str(), qs[0].qtype), ("example.com", 255));
+        let shown: Vec<&str> = ans.iter().map(|r| r.display.as_str()).collect();
+        assert_eq!(shown, ["192.0.2.1", "2001:db8:0:0:0:0:0:1", "example.com", "10 mail.example.com",
+                           long.to_str().unwrap(), "0 5 5060 sip.example.com", "example.com", "0102"]);
+        assert_eq!(ans[4].rdata.len(), 302);
+        assert_eq!((auth[0].display.as_str(), add[0].name.as_str()), ("ns1.example.com", "ns1.example.com"));
+
+        let bad = [rec(1, c"a", 1, Some(c"not-an-ip"), b"")];
+        assert_eq!(iris_dns_build_message(0, 0, q.as_ptr(), 0, bad.as_ptr(), 1, &mut out, &mut n), -2);
+        let no_text = [rec(1, c"a", 6, Some(c"ns. admin. 1 2 3 4 5"), b"")];
+        assert_eq!(iris_dns_build_message(0, 0, q.as_ptr(), 0, no_text.as_ptr(), 1, &mut out, &mut n), -3);
+        let question_section = [rec(0, c"a", 1, Some(c"192.0.2.1"), b"")];
+        assert_eq!(iris_dns_build_message(0, 0, q.as_ptr(), 0, question_section.as_ptr(), 1, &mut out, &mut n), -2);
+    }
+
+    #[test]
+    fn soa_naptr_caa_display() {
+        let mut w = DnsWriter::new(1, 0x8400);
+        w.record(SECTION_ANSWER, "example.com", 6, 1, 3600, |w| {
+            w.name("ns1.example.com")?;
+            w.name("hostmaster.example.com")?;
+            for n in [2024010101u32, 7200, 3600, 1209600, 300] { w.bytes(&n.to_be_bytes()); }
+            Ok(())
+        }).unwrap();
+        w.record(SECTION_ANSWER, "example.com", 35, 1, 60, |w| {
+            w.bytes(&[0, 100, 0, 10, 1, b'S', 7]);
+            w.bytes(b"SIP+D2U");
+            w.bytes(&[0]);
+            w.name("_sip._udp.example.com")
+        }).unwrap();
+        w.record(SECTION_ANSWER, "example.com", 257, 1, 60, |w| { w.bytes(b"\x80\x05issueca.example.net; a=\"b\"\n"); Ok(()) }).unwrap();
+        w.record(SECTION_ANSWER, "example.com", 6, 1, 60, |w| { w.bytes(&[0, 0, 1]); Ok(()) }).unwrap();
+        let (.., ans, _, _) = parse_dns(&w.finish()).unwrap();
+        let shown: Vec<&str> = ans.iter().map(|r| r.display.as_str()).collect();
+        assert_eq!(shown, [
+            "ns1.example.com hostmaster.example.com 2024010101 7200 3600 1209600 300",
+            "100 10 \"S\" \"SIP+D2U\" \"\" _sip._udp.example.com",
+            "128 issue \"ca.example.net; a=\\\"b\\\"\\010\"",
+            "000001",
+        ]);
+    }
+
+    #[test]
+    fn svcb_params() {
+        let mut rd = vec![0, 1, 0];
+        rd.extend([0, 0, 0, 2, 0, 1]);                            // mandatory=alpn
+        rd.extend([0, 1, 0, 6, 2, b'h', b'2', 2, b'h', b'3']);    // alpn=h2,h3
+        rd.extend([0, 2, 0, 0]);                                  // no-default-alpn
+        rd.extend([0, 3, 0, 2, 0x01, 0xbb]);                      // port=443
+        rd.extend([0, 4, 0, 8, 192, 0, 2, 1, 192, 0, 2, 2]);      // ipv4hint
+        rd.extend([0, 5, 0, 3, 0xfe, 0x0d, 0x00]);                // ech
+        rd.extend([0, 6, 0, 16, 0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+        rd.extend([0, 99, 0, 1, b'x']);
+
+        let mut w = DnsWriter::new(1, 0x8000);
+        w.record(SECTION_ANSWER, "example.com", 65, 1, 300, |w| { w.bytes(&rd); Ok(()) }).unwrap();
+        let (.., ans, _, _) = parse_dns(&w.finish()).unwrap();
+        assert_eq!(ans[0].display, "1 . mandatory=alpn alpn=h2,h3 no-default-alpn port=443 \
+                                    ipv4hint=192.0.2.1,192.0.2.2 ech=/g0A ipv6hint=2001:db8::1 key99=\"x\"");
+
+        let mut out = std::mem::MaybeUninit::<IrisDnsSvcb>::uninit();
+        assert_eq!(iris_dns_parse_svcb(rd.as_ptr(), rd.len(), out.as_mut_ptr()), 0);
+        let mut s = unsafe { out.assume_init_read() };
+        assert_eq!((s.priority, s.params.count, s.ech_len), (1, 8, 3));
+        assert_eq!(unsafe { CStr::from_ptr(s.target) }.to_bytes(), b".");
+        let p = unsafe { std::slice::from_raw_parts(s.params.params, s.params.count) };
+        assert_eq!(unsafe { CStr::from_ptr(p[1].value) }.to_bytes(), b"h2,h3");
+        assert!(!p[2].has_value && p[3].has_value);
+        assert_eq!(unsafe { std::slice::from_raw_parts(s.ech, s.ech_len) }, [0xfe, 0x0d, 0]);
+        iris_dns_svcb_free(&mut s);
+        assert_eq!(iris_dns_parse_svcb(rd.as_ptr(), rd.len() - 1, out.as_mut_ptr()), -2);
+    }
+
+    #[test]
+    fn mdns_class_bits() {
+        let q = [IrisDnsQuestion { name: c"_ipp._tcp.local".as_ptr() as *mut c_char, record_type: 12, qclass: 1, unicast_response: true }];
+        let mut w = DnsWriter::new(0, 0x8400);
+        w.record(SECTION_ANSWER, "printer.local", 1, 0x8001, 120, |w| { w.bytes(&[192, 168, 1, 9]); Ok(()) }).unwrap();
+        w.record(SECTION_ADDITIONAL, ".", 41, 1440, 0, |_| Ok(())).unwrap();
+        let resp = w.finish();
+        let (mut out, mut n) = (std::ptr::null_mut(), 0);
+        assert_eq!(iris_dns_build_message(0, 0, q.as_ptr(), 1, std::ptr::null(), 0, &mut out, &mut n), 0);
+        let query = unsafe { std::slice::from_raw_parts(out, n) }.to_vec();
+        crate::ffi::iris_free_bytes(out, n);
+        assert_eq!(&query[query.len() - 2..], &[0x80, 0x01]);
+
+        let mut m = std::mem::MaybeUninit::<IrisDnsMessage>::uninit();
+        assert_eq!(iris_dns_parse_mdns(query.as_ptr(), query.len(), m.as_mut_ptr()), 0);
+        let mut msg = unsafe { m.assume_init_read() };
+        let q = unsafe { &*msg.questions };
+        assert!(q.unicast_response && q.qclass == 1);
+        iris_dns_free_message(&mut msg);
+
+        assert_eq!(iris_dns_parse_mdns(resp.as_ptr(), resp.len(), m.as_mut_ptr()), 0);
+        let mut msg = unsafe { m.assume_init_read() };
+        let (a, opt) = unsafe { (&*msg.answers, &*msg.additional) };
+        assert!(a.cache_flush && a.rrclass == 1);
+        assert!(!opt.cache_flush && opt.rrclass == 1440);
+        iris_dns_free_message(&mut msg);
+        // Plain DNS parsing leaves the class alone
+        assert_eq!(iris_dns_parse(resp.as_ptr(), resp.len(), m.as_mut_ptr()), 0);
+        let mut msg = unsafe { m.assume_init_read() };
+        assert!(unsafe { &*msg.answers }.rrclass == 0x8001);
+        iris_dns_free_message(&mut msg);
+    }
+
+    #[test]
+    fn zero_copy_view() {
+        let mut w = DnsWriter::new(7, 0x8180);
+        w.question("www.example.com", 1, 1).unwrap();
+        w.record(SECTION_ANSWER, "www.example.com", 5, 1, 300, |w| w.name("cdn.example.com")).unwrap();
+        w.record(SECTION_ANSWER, "cdn.example.com", 1, 1, 60, |w| { w.bytes(&[192, 0, 2, 1]); Ok(()) }).unwrap();
+        let msg = w.finish();
+
+        let mut entries: Vec<IrisDnsEntry> = (0..3).map(|_| IrisDnsEntry::default()).collect();
+        let mut v = std::mem::MaybeUninit::<IrisDnsView>::uninit();
+        assert_eq!(iris_dns_view(msg.as_ptr(), msg.len(), v.as_mut_ptr(), entries.as_mut_ptr(), 2), -1);
+        assert_eq!(unsafe { v.assume_init_read() }.count, 3);
+        assert_eq!(iris_dns_view(msg.as_ptr(), msg.len(), v.as_mut_ptr(), entries.as_mut_ptr(), 3), 0);
+        let v = unsafe { v.assume_init_read() };
+        assert_eq!((v.id, v.flags, v.counts), (7, 0x8180, [1, 2, 0, 0]));
+
+        let a = &entries[2];
+        assert_eq!((a.section as usize, a.record_type, a.ttl), (SECTION_ANSWER, 1, 60));
+        let rdata = unsafe { std::slice::from_raw_parts(a.rdata.ptr, a.rdata.len) };
+        assert_eq!(rdata, [192, 0, 2, 1]);
+        assert!(std::ptr::eq(a.rdata.ptr, msg[msg.len() - 4..].as_ptr()));
+
+        // Names decode lazily, following compression pointers
+        let mut buf = [0 as c_char; 32];
+        let mut n = 0;
+        assert_eq!(iris_dns_view_name(msg.as_ptr(), msg.len(), a.name_offset, buf.as_mut_ptr(), buf.len(), &mut n), 0);
+        assert_eq!(unsafe { CStr::from_ptr(buf.as_ptr()) }.to_str().unwrap(), "cdn.example.com");
+        assert_eq!(n, 15);
+        assert_eq!(iris_dns_view_name(msg.as_ptr(), msg.len(), a.name_offset, buf.as_mut_ptr(), 15, &mut n), -1);
+        assert_eq!(iris_dns_view_name(msg.as_ptr(), msg.len(), msg.len(), buf.as_mut_ptr(), 32, &mut n), -2);
+        let cname = &entries[1];
+        let mut tbuf = [0u8; 32];
+        let rd_off = cname.rdata.ptr as usize - msg.as_ptr() as usize;
+        assert_eq!(view_name(&msg, rd_off, &mut tbuf), Some(15));
+
+        // Same acceptance as the allocating parser
+        assert!(view(&msg[..msg.len() - 1], &mut []).is_none());
+        assert!(parse_dns(&msg[..msg.len() - 1]).is_none());
+        let root = build_message(1, 0, &[(".".into(), 2, 1)], &[]).unwrap();
+        let mut one = [0u8; 1];
+        assert_eq!(view_name(&root, 12, &mut one), Some(1));
+        assert_eq!(&one, b".");
+    }
+
+    #[test]
+    fn parse_edit_serialize() {
+        let mut soa = DnsWriter::new(0, 0);
+        soa.name_uncompressed("ns1.example.com").unwrap();
+        soa.name_uncompressed("hostmaster.example.com").unwrap();
+        soa.bytes(&[0; 20]);
+        let records = [
+            BuildRecord { section: SECTION_ANSWER, name: "www.example.com".into(), rtype: 5, rclass: 1, ttl: 300, data: RecordData::Text("cdn.example.com".into()) },
+            BuildRecord { section: SECTION_ANSWER, name: "cdn.example.com".into(), rtype: 1, rclass: 1, ttl: 60, data: RecordData::Text("192.0.2.1".into()) },
+            BuildRecord { section: SECTION_ANSWER, name: "cdn.example.com".into(), rtype: 1, rclass: 1, ttl: 60, data: RecordData::Text("192.0.2.2".into()) },
+            BuildRecord { section: SECTION_AUTHORITY, name: "example.com".into(), rtype: 6, rclass: 1, ttl: 900, data: RecordData::Raw(soa.finish()[12..].to_vec()) },
+        ];
+        let orig = build_message(0x4242, 0x8580, &[("www.example.com".into(), 1, 1)], &records[..3]).unwrap();
+
+        let mut mu = std::mem::MaybeUninit::<IrisDnsMessage>::uninit();
+        assert_eq!(iris_dns_parse(orig.as_ptr(), orig.len(), mu.as_mut_ptr()), 0);
+        let mut m = unsafe { mu.assume_init_read() };
+        // CNAME rdata no longer points into the message
+        let cname = unsafe { std::slice::from_raw_parts((*m.answers).rdata, (*m.answers).rdata_len) };
+        assert_eq!(cname, b"\x03cdn\x07example\x03com\x00");
+
+        // Unedited: byte-identical, since both sides compress the same names in order
+        let (mut out, mut n) = (std::ptr::null_mut(), 0);
+        assert_eq!(iris_dns_serialize(&m, &mut out, &mut n), 0);
+        assert_eq!(unsafe { std::slice::from_raw_parts(out, n) }, &orig[..]);
+        crate::ffi::iris_free_bytes(out, n);
+
+        // Rewrite a TTL and strip the last answer
+        unsafe { (*m.answers.add(1)).ttl = 5; }
+        m.answers_count -= 1;
+        assert_eq!(iris_dns_serialize(&m, &mut out, &mut n), 0);
+        m.answers_count += 1;
+        iris_dns_free_message(&mut m);
+        let edited = unsafe { std::slice::from_raw_parts(out, n) }.to_vec();
+        crate::ffi::iris_free_bytes(out, n);
+        let (id, .., qs, ans, _, _) = parse_dns(&edited).unwrap();
+        assert_eq!((id, qs.len(), ans.len()), (0x4242, 1, 2));
+        assert_eq!((ans[1].ttl, ans[1].display.as_str()), (5, "192.0.2.1"));
+        assert_eq!(ans[0].display, "cdn.example.com");
+
+        // SOA names survive the trip out of a compressed message
+        let orig = build_message(1, 0x8400, &[], &records[3..]).unwrap();
+        assert_eq!(iris_dns_parse(orig.as_ptr(), orig.len(), mu.as_mut_ptr()), 0);
+        let mut m = unsafe { mu.assume_init_read() };
+        m.id = 2;
+        assert_eq!(iris_dns_serialize(&m, &mut out, &mut n), 0);
+        let again = unsafe { std::slice::from_raw_parts(out, n) }.to_vec();
+        crate::ffi::iris_free_bytes(out, n);
+        iris_dns_free_message(&mut m);
+        let (id, .., auth, _) = parse_dns(&again).unwrap();
+        assert_eq!(id, 2);
+        assert_eq!(auth[0].display, "ns1.example.com hostmaster.example.com 0 0 0 0 0");
+
+        let bad = IrisDnsMessage { questions_count: 1, ..unsafe { std::mem::zeroed() } };
+        assert_eq!(iris_dns_serialize(&bad, &mut out, &mut n), -2);
+    }
+
+    #[test]
+    fn configurable_limits() {
+        // An AXFR-sized answer section: 300 A records
+        let records: Vec<BuildRecord> = (0..300u32).map(|i| BuildRecord {
+            section: SECTION_ANSWER, name: format!("h{}.example.com", i), rtype: 1, rclass: 1, ttl: 60,
+            data: RecordData::Raw(i.to_be_bytes().to_vec()),
+        }).collect();
+        let msg = build_message(9, 0x8400, &[("example.com".into(), 252, 1)], &records).unwrap();
+        assert!(parse_dns(&msg).is_none());
+
+        let mut out = std::mem::MaybeUninit::<IrisDnsMessage>::uninit();
+        assert_eq!(iris_dns_parse_limited(msg.as_ptr(), msg.len(), std::ptr::null(), out.as_mut_ptr()), -3);
+        let big = IrisDnsLimits { max_section_records: 1000, max_total_records: 0, max_message_len: 0 };
+        assert_eq!(iris_dns_parse_limited(msg.as_ptr(), msg.len(), &big, out.as_mut_ptr()), 0);
+        let mut m = unsafe { out.assume_init_read() };
+        assert_eq!(m.answers_count, 300);
+        iris_dns_free_message(&mut m);
+
+        let total = IrisDnsLimits { max_section_records: 1000, max_total_records: 300, max_message_len: 0 };
+        assert_eq!(iris_dns_parse_limited(msg.as_ptr(), msg.len(), &total, out.as_mut_ptr()), -3);
+        let small = IrisDnsLimits { max_section_records: 0, max_total_records: 0, max_message_len: 20 };
+        let q = build_query_bytes("example.com", 1, 1, true).unwrap();
+        assert_eq!(iris_dns_parse_limited(q.as_ptr(), q.len(), &small, out.as_mut_ptr()), -3);
+        // A huge count in a tiny message is still just malformed
+        let mut lie = q.clone();
+        lie[4..6].copy_from_slice(&[0xFF, 0xFF]);
+        let any = IrisDnsLimits { max_section_records: 65535, max_total_records: 1 << 20, max_message_len: 0 };
+        assert_eq!(iris_dns_parse_limited(lie.as_ptr(), lie.len(), &any, out.as_mut_ptr()), -2);
+
+        // Over TCP, where zone transfers arrive
+        let s = iris_dns_tcp_new();
+        let mut framed = (msg.len() as u16).to_be_bytes().to_vec();
+        framed.extend_from_slice(&msg);
+        let mut ms = std::mem::MaybeUninit::<IrisDnsMessages>::uninit();
+        assert_eq!(iris_dns_tcp_feed(s, framed.as_ptr(), framed.len(), ms.as_mut_ptr()), 0);
+        let mut m = unsafe { ms.assume_init_read() };
+        assert_eq!((m.count, m.malformed), (0, 1));
+        iris_dns_messages_free(&mut m);
+        iris_dns_tcp_set_limits(s, &big);
+        assert_eq!(iris_dns_tcp_feed(s, framed.as_ptr(), framed.len(), ms.as_mut_ptr()), 0);
+        let mut m = unsafe { ms.assume_init_read() };
+        assert_eq!((m.count, unsafe { &*m.messages }.answers_count), (1, 300));
+        iris_dns_messages_free(&mut m);
+        iris_dns_tcp_free(s);
+    }
+
+    #[test]
+    fn escaped_labels() {
+        // "caf\xe9" (Latin-1, not UTF-8), "a.b" as one label, a tab and a backslash
+        let mut msg = vec![0, 1, 0x81, 0x80, 0, 1, 0, 1, 0, 0, 0, 0];
+        msg.extend_from_slice(b"\x04caf\xe9\x03a.b\x03t\tx\x03c\\d\x00\x00\x01\x00\x01");
+        msg.extend_from_slice(b"\xc0\x0c\x00\x01\x00\x01\x00\x00\x00\x3c\x00\x04\xc0\x00\x02\x01");
+        let mut out = std::mem::MaybeUninit::<IrisDnsMessage>::uninit();
+        assert_eq!(iris_dns_parse(msg.as_ptr(), msg.len(), out.as_mut_ptr()), 0);
+        let mut m = unsafe { out.assume_init_read() };
+        let name = unsafe { CStr::from_ptr((*m.questions).name) }.to_str().unwrap();
+        assert_eq!(name, "caf\\233.a\\.b.t\\009x.c\\\\d");
+        assert!(m.names_escaped);
+
+        // Escapes are undone when writing, so the message round-trips
+        let (mut data, mut n) = (std::ptr::null_mut(), 0);
+        assert_eq!(iris_dns_serialize(&m, &mut data, &mut n), 0);
+        assert_eq!(unsafe { std::slice::from_raw_parts(data, n) }, &msg[..]);
+        crate::ffi::iris_free_bytes(data, n);
+        iris_dns_free_message(&mut m);
+
+        // Raw UTF-8 labels are left alone
+        let q = build_message(1, 0x0100, &[("münchen.de.".into(), 1, 1)], &[]).unwrap();
+        assert_eq!(iris_dns_parse(q.as_ptr(), q.len(), out.as_mut_ptr()), 0);
+        let mut m = unsafe { out.assume_init_read() };
+        assert_eq!(unsafe { CStr::from_ptr((*m.questions).name) }.to_str().unwrap(), "münchen.de");
+        assert!(!m.names_escaped);
+        iris_dns_free_message(&mut m);
+
+        assert_eq!(split_name("a\\.b.c.").map(|(l, end)| (l.len(), end)), Ok((2, 6)));
+        assert!(split_name("a\\256").is_err());
+        assert!(split_name("a\\").is_err());
+    }
+
+    #[test]
+    fn name_tables() {
+        let name = |p: *const c_char| unsafe { CStr::from_ptr(p) }.to_str().unwrap();
+        assert_eq!(name(iris_dns_type_name(28)), "AAAA");
+        assert_eq!(name(iris_dns_type_name(65)), "HTTPS");
+        assert_eq!(name(iris_dns_type_name(255)), "ANY");
+        assert!(iris_dns_type_name(54).is_null());
+        assert_eq!(name(iris_dns_rcode_name(3)), "NXDOMAIN");
+        assert_eq!(name(iris_dns_rcode_name(23)), "BADCOOKIE");
+        assert!(iris_dns_rcode_name(12).is_null());
+
+        assert_eq!(type_from_name("https"), Some(65));
+        assert_eq!(type_from_name("NSAP-PTR"), Some(23));
+        assert_eq!(type_from_name("TYPE65280"), Some(65280));
+        assert_eq!(type_from_name("type1"), Some(1));
+        assert_eq!(type_from_name("TYPE+1"), None);
+        assert_eq!(type_from_name("TYPE70000"), None);
+        assert_eq!(iris_dns_type_from_name(c"BOGUS".as_ptr()), -3);
+        assert_eq!(iris_dns_type_from_name(c"mx".as_ptr()), 15);
+        // Every mnemonic maps back to its number
+        assert!(RR_TYPES.iter().all(|&(t, n)| type_from_name(n.to_str().unwrap()) == Some(t)));
+    }
+
+    #[test]
+    fn idna_query() {
+        let q = build_query_bytes("bücher.example", 1, 9, true).unwrap();
+        assert_eq!(header_question(&q).unwrap().2.unwrap().0, "xn--bcher-kva.example");
+        let (mut data, mut n) = (std::ptr::null_mut(), 0);
+        assert_eq!(iris_dns_build_query(c"i\u{2665}.example".as_ptr(), 1, 9, true, &mut data, &mut n), -3);
+        assert_eq!(iris_dns_build_query_0x20(c"\u{3b5}\u{3bb}.example".as_ptr(), 1, 9, true, &mut data, &mut n), 0);
+        let q = unsafe { std::slice::from_raw_parts(data, n) };
+        assert!(header_question(q).unwrap().2.unwrap().0.eq_ignore_ascii_case("xn--qxam.example"));
+        crate::ffi::iris_free_bytes(data, n);
+    }
+
+    #[test]
+    fn case_randomization() {
+        let name = "www.example-domain.com";
+        let encoded: Vec<String> = (0..8).map(|_| randomize_case(name)).collect();
+        assert!(encoded.iter().all(|e| e.eq_ignore_ascii_case(name) && e.len() == name.len()));
+        // 17 letters: eight identical draws would be a 2^-119 event
+        assert!(encoded.iter().any(|e| e != &encoded[0]));
+        assert_eq!(randomize_case("1.2-3."), "1.2-3.");
+
+        let (mut data, mut n) = (std::ptr::null_mut(), 0);
+        assert_eq!(iris_dns_build_query_0x20(c"mail.example.com".as_ptr(), 15, 1, true, &mut data, &mut n), 0);
+        let query = unsafe { std::slice::from_raw_parts(data, n) }.to_vec();
+        crate::ffi::iris_free_bytes(data, n);
+        let (.., q) = header_question(&query).unwrap();
+        let qname = q.unwrap().0;
+        let answer = |name: &str| build_message(1, 0x8180, &[(name.into(), 15, 1)], &[]).unwrap();
+
+        let check = |r: &[u8]| iris_dns_0x20_check(query.as_ptr(), query.len(), r.as_ptr(), r.len());
+        assert_eq!(check(&answer(&qname)), 1);
+        let flipped: String = qname.chars().map(|c| if c.is_ascii_uppercase() { c.to_ascii_lowercase() } else { c.to_ascii_uppercase() }).collect();
+        assert_eq!(check(&answer(&flipped)), 0);
+        assert_eq!(check(&answer("mail.example.org")), -3);
+        assert_eq!(check(&build_message(1, 0x8180, &[], &[]).unwrap()), -3);
+        assert_eq!(check(&[0; 4]), -2);
+
+        let p = iris_dns_0x20_encode(c"Example.COM".as_ptr());
+        assert!(unsafe { CStr::from_ptr(p) }.to_str().unwrap().eq_ignore_ascii_case("example.com"));
+        crate::batch::iris_free_string(p);
+    }
+
+    #[test]
+    fn tcp_reassembly() {
+        let q1 = build_query_bytes("example.com", 1, 1, true).unwrap();
+        let q2 = build_query_bytes("example.org", 28, 2, true).unwrap();
+        let mut stream = Vec::new();
+        for q in [&q1, &q2] {
+            stream.extend((q.len() as u16).to_be_bytes());
+            stream.extend_from_slice(q);
+        }
+        stream.extend([0, 3, 1, 2, 3]); // complete but malformed
+        stream.extend([0, 40, 9]);      // partial
+
+        let s = iris_dns_tcp_new();
+        let mut out = std::mem::MaybeUninit::<IrisDnsMessages>::uninit();
+        // Split inside the first length prefix, then everything else
+        assert_eq!(iris_dns_tcp_feed(s, stream.as_ptr(), 1, out.as_mut_ptr()), 0);
+        let mut m = unsafe { out.assume_init_read() };
+        assert_eq!((m.count, iris_dns_tcp_pending(s)), (0, 1));
+        iris_dns_messages_free(&mut m);
+        assert_eq!(iris_dns_tcp_feed(s, stream[1..].as_ptr(), stream.len() - 1, out.as_mut_ptr()), 0);
+        let mut m = unsafe { out.assume_init_read() };
+        assert_eq!((m.count, m.malformed, iris_dns_tcp_pending(s)), (2, 1, 3));
+        let second = unsafe { &*m.messages.add(1) };
+        assert_eq!((second.id, unsafe { &*second.questions }.record_type), (2, 28));
+        iris_dns_messages_free(&mut m);
+        assert_eq!(iris_dns_tcp_feed(s, std::ptr::null(), 0, out.as_mut_ptr()), 0);
+        let mut m = unsafe { out.assume_init_read() };
+        iris_dns_messages_free(&mut m);
+        iris_dns_tcp_free(s);
+    }
+
+    #[test]
+    fn query_and_invalid_names() {
+        let q = build_query_bytes("example.com.", 28, 7, true).unwrap();
+        assert_eq!(&q[..12], &[0, 7, 1, 0, 0, 1, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(&q[12..], b"\x07example\x03com\x00\x00\x1c\x00\x01");
+        assert_eq!(build_query_bytes(".", 2, 0, false).unwrap()[12], 0);
+        assert!(build_query_bytes("a..b", 1, 0, false).is_err());
+        assert!(build_query_bytes(&"x".repeat(64), 1, 0, false).is_err());
+        assert!(build_query_bytes(&["a"; 128].join("."), 1, 0, false).is_err());
+    }
+}
@@ -0,0 +1,216 @@
+//! NAT64/DNS64 (RFC 6052, RFC 6147): AAAA answers a DNS64 resolver synthesized by
+//! embedding an IPv4 address in a NAT64 prefix. Flows to such addresses really
+//! go to the embedded IPv4 host, so correlating them with DNS needs both.
+
+use crate::dns;
+use crate::ffi::{alloc_array, free_array, free_cstr, to_cstr};
+use crate::ip::IrisIpAddr;
+use std::ffi::c_char;
+
+const TYPE_AAAA: u16 = 28;
+
+/// The Well-Known Prefix (RFC 6052 §2.1) and the local-use prefix (RFC 8215).
+const WELL_KNOWN: [([u8; 16], u8); 2] = [
+    ([0, 0x64, 0xff, 0x9b, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 96),
+    ([0, 0x64, 0xff, 0x9b, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 48),
+];
+
+/// A NAT64 prefix; `len` is one of the RFC 6052 lengths 32, 40, 48, 56, 64, 96.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct IrisNat64Prefix {
+    pub prefix: IrisIpAddr,
+    pub len: u8,
+}
+
+#[repr(C)]
+pub struct IrisDns64Answer {
+    pub name: *mut c_char,      // owner name of the AAAA record
+    pub address: IrisIpAddr,    // the synthesized IPv6 address
+    pub ipv4: IrisIpAddr,       // the IPv4 address embedded in it
+    pub prefix_len: u8,
+    pub well_known: bool,       // 64:ff9b::/96 or 64:ff9b:1::/48
+}
+
+#[repr(C)]
+pub struct IrisDns64Answers {
+    pub items: *mut IrisDns64Answer,
+    pub count: usize,
+    pub aaaa_count: usize,      // AAAA answers in the response
+    pub synthesized: bool,      // every AAAA answer lies in a NAT64 prefix
+}
+
+pub struct Dns64Answer {
+    pub name: String,
+    pub address: [u8; 16],
+    pub ipv4: [u8; 4],
+    pub prefix_len: u8,
+    pub well_known: bool,
+}
+
+/// The IPv4 address embedded in `addr` under a prefix (RFC 6052 §2.2): the four
+/// bytes after the prefix, skipping byte 8 (bits 64-71), which must be zero.
+pub fn extract_ipv4(addr: &[u8; 16], prefix: &[u8; 16], len: u8) -> Option<[u8; 4]> {
+    if ![32, 40, 48, 56, 64, 96].contains(&len) { return None; }
+    let n = len as usize / 8;
+    if addr[..n] != prefix[..n] || (n < 9 && addr[8] != 0) { return None; }
+    let mut v4 = [0u8; 4];
+    for (o, i) in v4.iter_mut().zip((n..16).filter(|&i| i != 8)) { *o = addr[i]; }
+    Some(v4)
+}
+
+/// The first prefix, well-known ones first, that `addr` is synthesized under.
+fn find(addr: &[u8; 16], prefixes: &[([u8; 16], u8)]) -> Option<([u8; 4], u8, bool)> {
+    WELL_KNOWN.iter().map(|p| (p, true)).chain(prefixes.iter().map(|p| (p, false)))
+        .find_map(|(&(prefix, len), wk)| extract_ipv4(addr, &prefix, len).map(|v4| (v4, len, wk)))
+}
+
+/// The synthesized AAAA answers of a response and how many AAAA answers it has.
+/// Err(-2) malformed.
+pub fn detect(data: &[u8], prefixes: &[([u8; 16], u8)]) -> Result<(Vec<Dns64Answer>, usize), i32> {
+    let [answers, ..] = dns::parse_record_sections(data).ok_or(-2)?;
+    let mut out = Vec::new();
+    let mut aaaa = 0;
+    for rr in answers.iter().filter(|rr| rr.rtype == TYPE_AAAA) {
+        let Ok(address) = <[u8; 16]>::try_from(&rr.rdata[..]) else { continue };
+        aaaa += 1;
+        if let Some((ipv4, prefix_len, well_known)) = find(&address, prefixes) {
+            out.push(Dns64Answer { name: rr.name.clone(), address, ipv4, prefix_len, well_known });
+        }
+    }
+    Ok((out, aaaa))
+}
+
+/// Prefixes from the C array. Err(-3) for a non-IPv6 prefix or an RFC 6052-invalid length.
+fn prefixes(p: *const IrisNat64Prefix, count: usize) -> Result<Vec<([u8; 16], u8)>, i32> {
+    if p.is_null() { return if count == 0 { Ok(Vec::new()) } else { Err(-2) }; }
+    unsafe { std::slice::from_raw_parts(p, count) }.iter().map(|p| {
+        if p.prefix.family != 6 || ![32, 40, 48, 56, 64, 96].contains(&p.len) { return Err(-3); }
+        Ok((p.prefix.bytes, p.len))
+    }).collect()
+}
+
+// --- FFI ---
+
+/// Find the AAAA answers of a DNS response synthesized under the well-known NAT64
+/// prefixes or the `count` given ones (NULL/0 for none). Returns 0=ok (count may
+/// be 0), -2=arg error or malformed, -3=invalid prefix. Free with iris_dns64_answers_free.
+#[no_mangle]
+pub extern "C" fn iris_dns64_detect(
+    data: *const u8, len: usize, prefixes_ptr: *const IrisNat64Prefix, prefixes_count: usize,
+    out: *mut IrisDns64Answers,
+) -> i32 {
+    if data.is_null() || out.is_null() { return -2; }
+    let prefixes = match prefixes(prefixes_ptr, prefixes_count) {
+        Ok(p) => p,
+        Err(e) => return e,
+    };
+    let (found, aaaa_count) = match detect(unsafe { std::slice::from_raw_parts(data, len) }, &prefixes) {
+        Ok(r) => r,
+        Err(e) => return e,
+    };
+    let synthesized = aaaa_count > 0 && found.len() == aaaa_count;
+    let (items, count) = alloc_array(found.into_iter().map(|a| IrisDns64Answer {
+        name: to_cstr(&a.name), address: IrisIpAddr::v6(&a.address), ipv4: IrisIpAddr::v4(&a.ipv4),
+        prefix_len: a.prefix_len, well_known: a.well_known,
+    }).collect());
+    unsafe { out.write(IrisDns64Answers { items, count, aaaa_count, synthesized }); }
+    0
+}
+
+/// The IPv4 address embedded in an IPv6 address (e.g. a flow destination) under
+/// the well-known or the given NAT64 prefixes. Returns 0=ok, -2=arg error,
+/// -3=invalid prefix or not a NAT64 address.
+#[no_mangle]
+pub extern "C" fn iris_nat64_extract(
+    addr: *const IrisIpAddr, prefixes_ptr: *const IrisNat64Prefix, prefixes_count: usize, out: *mut IrisIpAddr,
+) -> i32 {
+    if addr.is_null() || out.is_null() { return -2; }
+    let addr = unsafe { &*addr };
+    if addr.family != 6 { return -3; }
+    let prefixes = match prefixes(prefixes_ptr, prefixes_count) {
+        Ok(p) => p,
+        Err(e) => return e,
+    };
+    match find(&addr.bytes, &prefixes) {
+        Some((v4, ..)) => { unsafe { out.write(IrisIpAddr::v4(&v4)); } 0 }
+        None => -3,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn iris_dns64_answers_free(a: *mut IrisDns64Answers) {
+    if a.is_null() { return; }
+    let a = unsafe { &*a };
+    if !a.items.is_null() {
+        for item in unsafe { std::slice::from_raw_parts(a.items, a.count) } { free_cstr(item.name); }
+    }
+    free_array(a.items, a.count);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::{DnsWriter, SECTION_ANSWER};
+
+    #[test]
+    fn rfc6052_embedding() {
+        // RFC 6052 §2.4: 192.0.2.33 under 2001:db8::/N
+        let prefix = [0x20, 0x01, 0x0d, 0xb8, 0x01, 0x22, 0x03, 0x44, 0, 0, 0, 0, 0, 0, 0, 0];
+        let examples: [(u8, [u8; 16]); 6] = [
+            (32, [0x20, 0x01, 0x0d, 0xb8, 0xc0, 0x00, 0x02, 0x21, 0, 0, 0, 0, 0, 0, 0, 0]),
+            (40, [0x20, 0x01, 0x0d, 0xb8, 0x01, 0xc0, 0x00, 0x02, 0, 0x21, 0, 0, 0, 0, 0, 0]),
+            (48, [0x20, 0x01, 0x0d, 0xb8, 0x01, 0x22, 0xc0, 0x00, 0, 0x02, 0x21, 0, 0, 0, 0, 0]),
+            (56, [0x20, 0x01, 0x0d, 0xb8, 0x01, 0x22, 0x03, 0xc0, 0, 0x00, 0x02, 0x21, 0, 0, 0, 0]),
+            (64, [0x20, 0x01, 0x0d, 0xb8, 0x01, 0x22, 0x03, 0x44, 0, 0xc0, 0x00, 0x02, 0x21, 0, 0, 0]),
+            (96, [0x20, 0x01, 0x0d, 0xb8, 0x01, 0x22, 0x03, 0x44, 0, 0, 0, 0, 0xc0, 0x00, 0x02, 0x21]),
+        ];
+        for (len, addr) in examples {
+            assert_eq!(extract_ipv4(&addr, &prefix, len), Some([192, 0, 2, 33]), "/{}", len);
+        }
+        let mut bad_u = examples[0].1;
+        bad_u[8] = 1;
+        assert_eq!(extract_ipv4(&bad_u, &prefix, 32), None);
+        assert_eq!(extract_ipv4(&examples[5].1, &prefix, 33), None);
+
+        let flow = IrisIpAddr::v6(&[0, 0x64, 0xff, 0x9b, 0, 0, 0, 0, 0, 0, 0, 0, 198, 51, 100, 7]);
+        let mut out = IrisIpAddr::default();
+        assert_eq!(iris_nat64_extract(&flow, std::ptr::null(), 0, &mut out), 0);
+        assert_eq!(out.as_slice(), &[198, 51, 100, 7]);
+        assert_eq!(iris_nat64_extract(&IrisIpAddr::v6(&prefix), std::ptr::null(), 0, &mut out), -3);
+    }
+
+    #[test]
+    fn synthesized_responses() {
+        let custom = [0x20, 0x01, 0x0d, 0xb8, 0x00, 0x64, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        let response = |addrs: &[[u8; 16]]| {
+            let mut w = DnsWriter::new(1, 0x8180);
+            w.question("ipv4only.example", TYPE_AAAA, 1).unwrap();
+            for a in addrs {
+                w.record(SECTION_ANSWER, "ipv4only.example", TYPE_AAAA, 1, 60, |w| { w.bytes(a); Ok(()) }).unwrap();
+            }
+            w.finish()
+        };
+        let wkp = [0, 0x64, 0xff, 0x9b, 0, 0, 0, 0, 0, 0, 0, 0, 192, 0, 0, 170];
+        let mut in_custom = custom;
+        in_custom[12..].copy_from_slice(&[192, 0, 0, 171]);
+        let native = [0x20, 0x01, 0x0d, 0xb8, 0xff, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1];
+
+        let (found, aaaa) = detect(&response(&[wkp, in_custom, native]), &[(custom, 96)]).unwrap();
+        assert_eq!(aaaa, 3);
+        let got: Vec<([u8; 4], bool)> = found.iter().map(|a| (a.ipv4, a.well_known)).collect();
+        assert_eq!(got, [([192, 0, 0, 170], true), ([192, 0, 0, 171], false)]);
+
+        let msg = response(&[wkp]);
+        let prefix = IrisNat64Prefix { prefix: IrisIpAddr::v6(&custom), len: 96 };
+        let mut out = std::mem::MaybeUninit::<IrisDns64Answers>::uninit();
+        assert_eq!(iris_dns64_detect(msg.as_ptr(), msg.len(), &prefix, 1, out.as_mut_ptr()), 0);
+        let mut out = unsafe { out.assume_init() };
+        assert!(out.synthesized && out.count == 1);
+        assert_eq!(unsafe { &*out.items }.prefix_len, 96);
+        iris_dns64_answers_free(&mut out);
+
+        let bad = IrisNat64Prefix { prefix: IrisIpAddr::v6(&custom), len: 80 };
+        assert_eq!(iris_dns64_detect(msg.as_ptr(), msg.len(), &bad, 1, &mut out), -3);
+    }
+}
@@ -0,0 +1,203 @@
+//! DNSSEC canonical form and ordering (RFC 4034 §6): owner names lowercased and
+//! compared label by label from the root, records in wire form with the names in
+//! their RDATA lowercased, and RRsets sorted by RDATA with duplicates dropped.
+
+use crate::dns::{self, IrisDnsRecord};
+use crate::ffi::alloc_bytes;
+use std::cmp::Ordering;
+use std::ffi::{c_char, CStr};
+
+/// A piece of RDATA, in order.
+enum Field {
+    Fixed(usize),
+    Text,  // <character-string>: length byte and data
+    Name,
+}
+
+use Field::{Fixed, Name, Text};
+
+/// RDATA layouts up to the last name, for the types whose names are lowercased
+/// in canonical form (RFC 4034 §6.2 list, less NSEC per RFC 6840 §5.1 and the
+/// name-less HINFO; A6 is left out).
+fn layout(rtype: u16) -> &'static [Field] {
+    match rtype {
+        2 | 3 | 4 | 5 | 7 | 8 | 9 | 12 | 30 | 39 => &[Name], // NS MD MF CNAME MB MG MR PTR NXT DNAME
+        6 | 14 | 17 => &[Name, Name],                         // SOA MINFO RP
+        15 | 18 | 21 | 36 => &[Fixed(2), Name],               // MX AFSDB RT KX
+        26 => &[Fixed(2), Name, Name],                        // PX
+        33 => &[Fixed(6), Name],                              // SRV
+        35 => &[Fixed(4), Text, Text, Text, Name],            // NAPTR
+        24 | 46 => &[Fixed(18), Name],                        // SIG RRSIG: signer
+        _ => &[],
+    }
+}
+
+/// Lowercase a name in ASCII: the only case folding DNSSEC applies.
+fn lower(labels: Vec<Vec<u8>>) -> Vec<Vec<u8>> {
+    labels.into_iter().map(|l| l.to_ascii_lowercase()).collect()
+}
+
+/// A name in canonical wire form: uncompressed and lowercased.
+pub fn canonical_name(name: &str) -> Result<Vec<u8>, i32> {
+    let mut out = Vec::new();
+    for l in lower(dns::wire_labels(name)?) {
+        out.push(l.len() as u8);
+        out.extend_from_slice(&l);
+    }
+    out.push(0);
+    Ok(out)
+}
+
+/// Canonical name order (RFC 4034 §6.1): labels compared from the root as
+/// lowercased octet strings, a missing label sorting first.
+pub fn compare_names(a: &str, b: &str) -> Result<Ordering, i32> {
+    let (a, b) = (lower(dns::wire_labels(a)?), lower(dns::wire_labels(b)?));
+    Ok(a.iter().rev().cmp(b.iter().rev()))
+}
+
+/// RDATA with its embedded names lowercased. None if it is truncated or holds a
+/// compression pointer.
+pub fn canonical_rdata(rtype: u16, rd: &[u8]) -> Option<Vec<u8>> {
+    let mut out = rd.to_vec();
+    let mut p = 0;
+    for f in layout(rtype) {
+        match f {
+            Fixed(n) => p += n,
+            Text => p += 1 + *rd.get(p)? as usize,
+            Name => loop {
+                let len = *rd.get(p)? as usize;
+                if len > 63 { return None; }
+                out.get_mut(p + 1..p + 1 + len)?.make_ascii_lowercase();
+                p += 1 + len;
+                if len == 0 { break; }
+            },
+        }
+    }
+    (p <= rd.len()).then_some(out)
+}
+
+/// An RRset in canonical form and order (RFC 4034 §6.2-6.3), each record in wire
+/// form with `ttl` (for signatures, the RRSIG's Original TTL). Err(-2) if the
+/// records differ in owner, type or class, or RDATA is malformed.
+pub fn canonical_rrset(records: &[(&str, u16, u16, &[u8])], ttl: u32) -> Result<Vec<Vec<u8>>, i32> {
+    let Some(&(owner, rtype, class, _)) = records.first() else { return Ok(Vec::new()) };
+    let owner = canonical_name(owner)?;
+    let mut rdatas = Vec::with_capacity(records.len());
+    for &(name, t, c, rd) in records {
+        if (t, c) != (rtype, class) || canonical_name(name)? != owner { return Err(-2); }
+        rdatas.push(canonical_rdata(rtype, rd).ok_or(-2)?);
+    }
+    rdatas.sort();
+    rdatas.dedup();
+    rdatas.into_iter().map(|rd| {
+        let mut rr = owner.clone();
+        rr.extend_from_slice(&rtype.to_be_bytes());
+        rr.extend_from_slice(&class.to_be_bytes());
+        rr.extend_from_slice(&ttl.to_be_bytes());
+        rr.extend_from_slice(&u16::try_from(rd.len()).map_err(|_| -2)?.to_be_bytes());
+        rr.extend_from_slice(&rd);
+        Ok(rr)
+    }).collect()
+}
+
+fn c_str<'a>(p: *const c_char) -> Result<&'a str, i32> {
+    if p.is_null() { return Err(-2); }
+    unsafe { CStr::from_ptr(p) }.to_str().map_err(|_| -2)
+}
+
+// --- FFI ---
+
+/// Compare two names in canonical DNS order: *out is -1, 0 or 1.
+/// Returns 0=ok, -2=arg error or invalid name.
+#[no_mangle]
+pub extern "C" fn iris_dns_name_compare(a: *const c_char, b: *const c_char, out: *mut i32) -> i32 {
+    if out.is_null() { return -2; }
+    match c_str(a).and_then(|a| compare_names(a, c_str(b)?)) {
+        Ok(o) => { unsafe { *out = o as i32; } 0 }
+        Err(e) => e,
+    }
+}
+
+/// A name in canonical wire form. Returns 0=ok, -2=arg error or invalid name.
+/// Free with iris_free_bytes.
+#[no_mangle]
+pub extern "C" fn iris_dns_canonical_name(name: *const c_char, out_data: *mut *mut u8, out_len: *mut usize) -> i32 {
+    if out_data.is_null() || out_len.is_null() { return -2; }
+    let bytes = match c_str(name).and_then(canonical_name) {
+        Ok(b) => b,
+        Err(e) => return e,
+    };
+    let (ptr, len) = alloc_bytes(&bytes);
+    unsafe { *out_data = ptr; *out_len = len; }
+    0
+}
+
+/// Put the records of one RRset (e.g. from an IrisDnsMessage section) in canonical
+/// form and order, duplicates removed, concatenated as the RRSIG signature input
+/// expects; *out_count records remain. Returns 0=ok, -2=arg error, records not of
+/// one RRset, or RDATA with compressed names. Free with iris_free_bytes.
+#[no_mangle]
+pub extern "C" fn iris_dns_canonical_rrset(
+    records: *const IrisDnsRecord, count: usize, ttl: u32,
+    out_data: *mut *mut u8, out_len: *mut usize, out_count: *mut usize,
+) -> i32 {
+    if records.is_null() || out_data.is_null() || out_len.is_null() || out_count.is_null() { return -2; }
+    let records = unsafe { std::slice::from_raw_parts(records, count) };
+    let mut rrs = Vec::with_capacity(count);
+    for r in records {
+        let Ok(name) = c_str(r.name) else { return -2 };
+        let rd = if r.rdata.is_null() { &[][..] } else { unsafe { std::slice::from_raw_parts(r.rdata, r.rdata_len) } };
+        rrs.push((name, r.record_type, r.rrclass, rd));
+    }
+    let set = match canonical_rrset(&rrs, ttl) {
+        Ok(s) => s,
+        Err(e) => return e,
+    };
+    let (ptr, len) = alloc_bytes(&set.concat());
+    unsafe { *out_data = ptr; *out_len = len; *out_count = set.len(); }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rfc4034_name_order() {
+        // RFC 4034 §6.1, in canonical order
+        let names = [
+            "example", "a.example", "yljkjljk.a.example", "Z.a.example", "zABC.a.EXAMPLE",
+            "z.example", "\\001.z.example", "*.z.example", "\\200.z.example",
+        ];
+        let mut shuffled = names;
+        shuffled.reverse();
+        shuffled.swap(1, 6);
+        shuffled.sort_by(|a, b| compare_names(a, b).unwrap());
+        assert_eq!(shuffled, names);
+        assert_eq!(compare_names("WWW.Example.", "www.example"), Ok(Ordering::Equal));
+        assert_eq!(compare_names("a..b", "a"), Err(-2));
+
+        assert_eq!(canonical_name("Www.EXAMPLE.com.").unwrap(), b"\x03www\x07example\x03com\x00");
+        let mut out = 0;
+        assert_eq!(iris_dns_name_compare(c".".as_ptr(), c"com".as_ptr(), &mut out), 0);
+        assert_eq!(out, -1);
+    }
+
+    #[test]
+    fn rrset_form_and_order() {
+        let mx = |pref: u8, host: &[u8]| [&[0, pref][..], host].concat();
+        let (a, b) = (mx(10, b"\x04MAIL\x07Example\x00"), mx(5, b"\x02mx\x07example\x00"));
+        let dup = mx(10, b"\x04mail\x07example\x00");
+        let set = canonical_rrset(&[("Example.", 15, 1, &a), ("example", 15, 1, &b), ("EXAMPLE", 15, 1, &dup)], 3600).unwrap();
+        assert_eq!(set.len(), 2);
+        let head = b"\x07example\x00\x00\x0f\x00\x01\x00\x00\x0e\x10";
+        assert_eq!(set[0], [&head[..], &[0, 14], &b][..].concat());
+        assert_eq!(set[1], [&head[..], &[0, 16], &dup][..].concat());
+
+        assert_eq!(canonical_rrset(&[("a", 1, 1, &[1, 2, 3, 4]), ("b", 1, 1, &[1, 2, 3, 4])], 0), Err(-2));
+        // Compressed or truncated names in RDATA cannot be put in canonical form
+        assert_eq!(canonical_rdata(5, &[0xC0, 12]), None);
+        assert_eq!(canonical_rdata(5, &[3, b'A']), None);
+        assert_eq!(canonical_rdata(16, b"\x02AB").unwrap(), b"\x02AB");
+    }
+}
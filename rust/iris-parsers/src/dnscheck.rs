@@ -0,0 +1,299 @@
+//! Response sanity checks: a DNS response judged against its own question for
+//! records outside the answering zone's bailiwick, answers unrelated to the
+//! question, TTL anomalies, and private addresses handed out for public names —
+//! the marks of cache poisoning and DNS rebinding.
+
+use crate::dns::{self, DnsRR, SECTION_ANSWER};
+use crate::ffi::{alloc_array, free_array, free_cstr, to_cstr};
+use std::collections::HashMap;
+use std::ffi::{c_char, CStr};
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+pub const DNS_CHECK_OUT_OF_BAILIWICK: u8 = 1;  // authority/additional record outside the zone
+pub const DNS_CHECK_UNRELATED_ANSWER: u8 = 2;  // answer not for the question or its CNAME chain
+pub const DNS_CHECK_TTL_OVERFLOW: u8 = 3;      // TTL over 2^31-1 (RFC 2181 §8: read as 0)
+pub const DNS_CHECK_TTL_MISMATCH: u8 = 4;      // TTLs differ within one RRset (RFC 2181 §5.2)
+pub const DNS_CHECK_ZERO_TTL: u8 = 5;          // address answer that may not be cached
+pub const DNS_CHECK_PRIVATE_ADDRESS: u8 = 6;   // private, loopback or link-local address for a public name
+pub const DNS_CHECK_REBINDING: u8 = 7;         // private address with a short TTL, or mixed with public ones
+
+/// A private address with a TTL under this is a rebinding pattern: the name is
+/// meant to be looked up again soon, with a different answer.
+const REBIND_TTL: u32 = 60;
+/// Suffixes of names that legitimately resolve to private addresses.
+const PRIVATE_SUFFIXES: &[&str] = &[
+    "local", "localhost", "localdomain", "home.arpa", "internal", "lan", "home", "corp", "intranet",
+];
+
+const TYPE_A: u16 = 1;
+const TYPE_NS: u16 = 2;
+const TYPE_CNAME: u16 = 5;
+const TYPE_SOA: u16 = 6;
+const TYPE_AAAA: u16 = 28;
+const TYPE_DNAME: u16 = 39;
+const TYPE_OPT: u16 = 41;
+const TYPE_RRSIG: u16 = 46;
+const TYPE_TSIG: u16 = 250;
+
+#[repr(C)]
+pub struct IrisDnsFinding {
+    pub kind: u8,               // DNS_CHECK_*
+    pub section: u8,            // SECTION_ANSWER, SECTION_AUTHORITY or SECTION_ADDITIONAL
+    pub index: usize,           // record within its section
+    pub name: *mut c_char,      // owner name of the record
+    pub detail: *mut c_char,    // "10.0.0.5", "ttl 0", "outside example.com", ...
+}
+
+#[repr(C)]
+pub struct IrisDnsFindings {
+    pub items: *mut IrisDnsFinding,
+    pub count: usize,
+    pub bailiwick: *mut c_char, // the zone records were held to
+}
+
+pub struct Finding {
+    pub kind: u8,
+    pub section: usize,
+    pub index: usize,
+    pub name: String,
+    pub detail: String,
+}
+
+/// Lowercased wire labels, most specific first. None for an invalid name.
+fn labels(name: &str) -> Option<Vec<Vec<u8>>> {
+    dns::wire_labels(name).ok().map(|ls| ls.into_iter().map(|l| l.to_ascii_lowercase()).collect())
+}
+
+/// Whether `name` is `zone` or below it.
+fn within(name: &str, zone: &str) -> bool {
+    match (labels(name), labels(zone)) {
+        (Some(n), Some(z)) => n.ends_with(&z),
+        _ => false,
+    }
+}
+
+fn same_name(a: &str, b: &str) -> bool {
+    labels(a).is_some_and(|a| Some(a) == labels(b))
+}
+
+/// The name's parent, or the root for a single label.
+fn parent(name: &str) -> String {
+    let mut ls = labels(name).unwrap_or_default();
+    if !ls.is_empty() { ls.remove(0); }
+    if ls.is_empty() { return ".".into(); }
+    ls.iter().map(|l| String::from_utf8_lossy(l)).collect::<Vec<_>>().join(".")
+}
+
+/// Private-use, loopback, link-local, shared (CGNAT) and unspecified addresses.
+fn private_address(rr: &DnsRR) -> bool {
+    let v4 = |a: Ipv4Addr| a.is_private() || a.is_loopback() || a.is_link_local() || a.is_unspecified()
+        || (a.octets()[0] == 100 && a.octets()[1] & 0xC0 == 64);
+    match (rr.rtype, rr.rdata.len()) {
+        (TYPE_A, 4) => v4(Ipv4Addr::new(rr.rdata[0], rr.rdata[1], rr.rdata[2], rr.rdata[3])),
+        (TYPE_AAAA, 16) => {
+            let a = Ipv6Addr::from(<[u8; 16]>::try_from(&rr.rdata[..]).unwrap());
+            a.is_loopback() || a.is_unspecified() || a.is_unique_local() || a.is_unicast_link_local()
+                || a.to_ipv4_mapped().is_some_and(v4)
+        }
+        _ => false,
+    }
+}
+
+fn is_address(rr: &DnsRR) -> bool {
+    (rr.rtype == TYPE_A && rr.rdata.len() == 4) || (rr.rtype == TYPE_AAAA && rr.rdata.len() == 16)
+}
+
+/// The zone a response speaks for: the SOA or NS owner in its authority section
+/// that encloses the question name, else the question name's parent.
+fn default_bailiwick(qname: &str, authority: &[DnsRR]) -> String {
+    authority.iter()
+        .filter(|rr| matches!(rr.rtype, TYPE_SOA | TYPE_NS) && within(qname, &rr.name))
+        .max_by_key(|rr| labels(&rr.name).map_or(0, |l| l.len()))
+        .map_or_else(|| parent(qname), |rr| rr.name.clone())
+}
+
+/// Check a response against its question. `bailiwick` is the zone of the server
+/// that was asked, if known. Returns the findings and the bailiwick used.
+/// Err(-3) not a response or no question, Err(-2) malformed.
+pub fn check(data: &[u8], bailiwick: Option<&str>) -> Result<(Vec<Finding>, String), i32> {
+    let (_, flags, question) = dns::header_question(data).ok_or(-2)?;
+    if flags & 0x8000 == 0 { return Err(-3); }
+    let (qname, ..) = question.ok_or(-3)?;
+    let sections = dns::parse_record_sections(data).ok_or(-2)?;
+    let zone = bailiwick.map_or_else(|| default_bailiwick(&qname, &sections[1]), str::to_string);
+    let mut out = Vec::new();
+    let mut add = |kind, section, index, rr: &DnsRR, detail: String| {
+        out.push(Finding { kind, section, index, name: rr.name.clone(), detail });
+    };
+
+    // The question name and the CNAME chain from it, in any order
+    let answers = &sections[0];
+    let mut chain = vec![qname.clone()];
+    while let Some(target) = answers.iter()
+        .filter(|rr| rr.rtype == TYPE_CNAME && chain.iter().any(|n| same_name(n, &rr.name)))
+        .map(|rr| rr.display.clone())
+        .find(|t| !chain.iter().any(|n| same_name(n, t)))
+    {
+        chain.push(target);
+    }
+
+    let public = !PRIVATE_SUFFIXES.iter().any(|s| within(&qname, s)) && labels(&qname).is_some_and(|l| l.len() > 1);
+    let addresses: Vec<bool> = answers.iter().filter(|rr| is_address(rr)).map(private_address).collect();
+    let mixed = addresses.contains(&true) && addresses.contains(&false);
+    let mut ttls: HashMap<(String, u16, u16, usize), u32> = HashMap::new();
+
+    for (s, rrs) in sections.iter().enumerate() {
+        let section = SECTION_ANSWER + s;
+        for (i, rr) in rrs.iter().enumerate() {
+            if matches!(rr.rtype, TYPE_OPT | TYPE_TSIG) { continue; }
+            if section == SECTION_ANSWER {
+                let related = chain.iter().any(|n| same_name(n, &rr.name)
+                    || (rr.rtype == TYPE_DNAME && within(n, &rr.name)));
+                if !related { add(DNS_CHECK_UNRELATED_ANSWER, section, i, rr, format!("not for {}", qname)); }
+            } else if !within(&rr.name, &zone) {
+                add(DNS_CHECK_OUT_OF_BAILIWICK, section, i, rr, format!("outside {}", zone));
+            }
+
+            if rr.ttl > i32::MAX as u32 {
+                add(DNS_CHECK_TTL_OVERFLOW, section, i, rr, format!("ttl {}", rr.ttl));
+            } else if rr.rtype != TYPE_RRSIG {
+                let first = *ttls.entry((rr.name.to_ascii_lowercase(), rr.rtype, rr.rclass, section)).or_insert(rr.ttl);
+                if first != rr.ttl {
+                    add(DNS_CHECK_TTL_MISMATCH, section, i, rr, format!("ttl {} after {}", rr.ttl, first));
+                }
+            }
+
+            if section != SECTION_ANSWER || !is_address(rr) { continue; }
+            if rr.ttl == 0 { add(DNS_CHECK_ZERO_TTL, section, i, rr, "ttl 0".into()); }
+            if public && private_address(rr) {
+                add(DNS_CHECK_PRIVATE_ADDRESS, section, i, rr, rr.display.clone());
+                if rr.ttl < REBIND_TTL || mixed {
+                    let why = if mixed { "with public addresses" } else { "short ttl" };
+                    add(DNS_CHECK_REBINDING, section, i, rr, format!("{} {}", rr.display, why));
+                }
+            }
+        }
+    }
+    Ok((out, zone))
+}
+
+// --- FFI ---
+
+/// Check a DNS response against its question. `bailiwick` is the zone of the
+/// server asked, or NULL to infer it from the response. Returns 0=ok (possibly no
+/// findings), -2=arg error or malformed, -3=not a response or no question.
+/// Free with iris_dns_findings_free.
+#[no_mangle]
+pub extern "C" fn iris_dns_check_response(
+    data: *const u8, len: usize, bailiwick: *const c_char, out: *mut IrisDnsFindings,
+) -> i32 {
+    if data.is_null() || out.is_null() { return -2; }
+    let zone = if bailiwick.is_null() {
+        None
+    } else {
+        match unsafe { CStr::from_ptr(bailiwick) }.to_str() {
+            Ok(z) => Some(z),
+            Err(_) => return -2,
+        }
+    };
+    let (findings, zone) = match check(unsafe { std::slice::from_raw_parts(data, len) }, zone) {
+        Ok(r) => r,
+        Err(e) => return e,
+    };
+    let (items, count) = alloc_array(findings.into_iter().map(|f| IrisDnsFinding {
+        kind: f.kind, section: f.section as u8, index: f.index, name: to_cstr(&f.name), detail: to_cstr(&f.detail),
+    }).collect());
+    unsafe { out.write(IrisDnsFindings { items, count, bailiwick: to_cstr(&zone) }); }
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn iris_dns_findings_free(f: *mut IrisDnsFindings) {
+    if f.is_null() { return; }
+    let f = unsafe { &*f };
+    if !f.items.is_null() {
+        for item in unsafe { std::slice::from_raw_parts(f.items, f.count) } {
+            free_cstr(item.name);
+            free_cstr(item.detail);
+        }
+    }
+    free_array(f.items, f.count);
+    free_cstr(f.bailiwick);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::{DnsWriter, SECTION_ADDITIONAL, SECTION_AUTHORITY};
+
+    fn kinds(f: &[Finding]) -> Vec<(u8, usize, usize)> {
+        f.iter().map(|f| (f.kind, f.section, f.index)).collect()
+    }
+
+    #[test]
+    fn poisoning_patterns() {
+        // Kaminsky-style: an answer for a random name carrying a forged NS and glue
+        let mut w = DnsWriter::new(7, 0x8180);
+        w.question("x83hd.example.com", TYPE_A, 1).unwrap();
+        w.record(SECTION_ANSWER, "x83hd.example.com", TYPE_CNAME, 1, 300, |w| w.name("cdn.example.net")).unwrap();
+        w.record(SECTION_ANSWER, "CDN.example.net", TYPE_A, 1, 300, |w| { w.bytes(&[192, 0, 2, 1]); Ok(()) }).unwrap();
+        w.record(SECTION_ANSWER, "www.bank.test", TYPE_A, 1, 300, |w| { w.bytes(&[192, 0, 2, 66]); Ok(()) }).unwrap();
+        w.record(SECTION_AUTHORITY, "example.com", TYPE_NS, 1, 300, |w| w.name("ns.example.com")).unwrap();
+        w.record(SECTION_AUTHORITY, "bank.test", TYPE_NS, 1, 300, |w| w.name("ns.example.com")).unwrap();
+        w.record(SECTION_ADDITIONAL, "ns.example.com", TYPE_A, 1, 300, |w| { w.bytes(&[192, 0, 2, 53]); Ok(()) }).unwrap();
+        w.record(SECTION_ADDITIONAL, "ns.example.com", TYPE_A, 1, 0x8000_0000, |w| { w.bytes(&[192, 0, 2, 54]); Ok(()) }).unwrap();
+        let msg = w.finish();
+
+        let (f, zone) = check(&msg, None).unwrap();
+        assert_eq!(zone, "example.com");
+        assert_eq!(kinds(&f), [
+            (DNS_CHECK_UNRELATED_ANSWER, SECTION_ANSWER, 2),
+            (DNS_CHECK_OUT_OF_BAILIWICK, SECTION_AUTHORITY, 1),
+            (DNS_CHECK_TTL_OVERFLOW, SECTION_ADDITIONAL, 1),
+        ]);
+        assert_eq!(f[1].detail, "outside example.com");
+
+        // A narrower known bailiwick puts the NS and glue outside too
+        let (f, _) = check(&msg, Some("x83hd.example.com")).unwrap();
+        assert_eq!(f.iter().filter(|f| f.kind == DNS_CHECK_OUT_OF_BAILIWICK).count(), 4);
+
+        let mut out = std::mem::MaybeUninit::<IrisDnsFindings>::uninit();
+        assert_eq!(iris_dns_check_response(msg.as_ptr(), msg.len(), std::ptr::null(), out.as_mut_ptr()), 0);
+        let mut out = unsafe { out.assume_init() };
+        assert_eq!(out.count, 3);
+        assert_eq!(unsafe { CStr::from_ptr(out.bailiwick) }, c"example.com");
+        iris_dns_findings_free(&mut out);
+
+        let query = dns::build_message(7, 0x0100, &[("example.com".into(), 1, 1)], &[]).unwrap();
+        assert_eq!(check(&query, None).err(), Some(-3));
+    }
+
+    #[test]
+    fn rebinding_and_ttls() {
+        let response = |name: &str, addrs: &[(&[u8], u32)]| {
+            let mut w = DnsWriter::new(1, 0x8180);
+            w.question(name, TYPE_A, 1).unwrap();
+            for (a, ttl) in addrs {
+                let t = if a.len() == 4 { TYPE_A } else { TYPE_AAAA };
+                w.record(SECTION_ANSWER, name, t, 1, *ttl, |w| { w.bytes(a); Ok(()) }).unwrap();
+            }
+            w.finish()
+        };
+        let found = |msg: Vec<u8>| kinds(&check(&msg, None).unwrap().0).into_iter().map(|k| (k.0, k.2)).collect::<Vec<_>>();
+
+        assert_eq!(found(response("rebind.example.com", &[(&[127, 0, 0, 1], 0)])), [
+            (DNS_CHECK_ZERO_TTL, 0), (DNS_CHECK_PRIVATE_ADDRESS, 0), (DNS_CHECK_REBINDING, 0),
+        ]);
+        assert_eq!(found(response("app.example.com", &[(&[10, 1, 2, 3], 3600)])), [(DNS_CHECK_PRIVATE_ADDRESS, 0)]);
+        // A public and a private address for one name, also a TTL mismatch
+        assert_eq!(found(response("app.example.com", &[(&[192, 0, 2, 1], 300), (&[192, 168, 1, 1], 3600)])), [
+            (DNS_CHECK_TTL_MISMATCH, 1), (DNS_CHECK_PRIVATE_ADDRESS, 1), (DNS_CHECK_REBINDING, 1),
+        ]);
+        let ula = [0xfd, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1];
+        assert_eq!(found(response("app.example.com", &[(&ula, 30)])).len(), 2);
+        // Internal names may have private addresses
+        assert!(found(response("printer.home.arpa", &[(&[192, 168, 1, 9], 30)])).is_empty());
+        assert!(found(response("nas", &[(&[100, 64, 0, 1], 30)])).is_empty());
+        assert!(found(response("www.example.com", &[(&[198, 51, 100, 7], 300)])).is_empty());
+    }
+}
@@ -0,0 +1,294 @@
+//! DNS cookies (RFC 7873): the COOKIE option (10) of the EDNS OPT record. Reads
+//! and writes the client/server cookie pair, derives client cookies, and checks
+//! that a response echoes the cookie its query sent.
+
+use crate::dns::{self, IrisDnsEntry, SECTION_ADDITIONAL};
+use crate::ffi::alloc_bytes;
+use crate::hash::hmac_sha256;
+use crate::ip::IrisIpAddr;
+
+pub const COOKIE_NONE: i32 = 0;      // the query sent no cookie
+pub const COOKIE_OK: i32 = 1;        // client cookie echoed with a server cookie
+pub const COOKIE_MISSING: i32 = 2;   // the response has no cookie (server lacks support)
+pub const COOKIE_MISMATCH: i32 = 3;  // the response echoes a different client cookie
+pub const COOKIE_MALFORMED: i32 = 4; // bad option length, or no server cookie
+pub const COOKIE_BADCOOKIE: i32 = 5; // the server rejected it (extended RCODE 23)
+
+const TYPE_OPT: u16 = 41;
+const OPTION_COOKIE: u16 = 10;
+const RCODE_BADCOOKIE: u16 = 23;
+/// UDP payload size for an OPT record we add (the DNS Flag Day 2020 value).
+const DEFAULT_UDP_SIZE: u16 = 1232;
+
+#[repr(C)]
+pub struct IrisDnsCookie {
+    pub client: [u8; 8],
+    pub server: [u8; 32],
+    pub server_len: usize,      // 0 in a first query, otherwise 8-32
+    pub well_formed: bool,      // option length 8 or 16-40
+    pub extended_rcode: u16,    // header RCODE with the OPT upper bits; 23 = BADCOOKIE
+}
+
+pub struct Cookie {
+    pub client: [u8; 8],
+    pub server: Vec<u8>,
+    pub well_formed: bool,
+    pub extended_rcode: u16,
+}
+
+/// The OPT record of a message: its entry and the offset of its RDATA.
+fn opt_record(data: &[u8]) -> Result<Option<(IrisDnsEntry, usize)>, i32> {
+    let v = dns::view(data, &mut []).ok_or(-2)?;
+    let mut entries: Vec<IrisDnsEntry> = (0..v.count).map(|_| IrisDnsEntry::default()).collect();
+    dns::view(data, &mut entries).ok_or(-2)?;
+    let mut opts = entries.into_iter().filter(|e| e.record_type == TYPE_OPT && e.section as usize == SECTION_ADDITIONAL);
+    let opt = opts.next();
+    if opts.next().is_some() { return Err(-2); } // RFC 6891 §6.1.1: at most one
+    Ok(opt.map(|e| {
+        let start = e.rdata.ptr as usize - data.as_ptr() as usize;
+        (e, start)
+    }))
+}
+
+/// EDNS options as (code, value).
+fn options(rd: &[u8]) -> Option<Vec<(u16, &[u8])>> {
+    let mut out = Vec::new();
+    let mut p = 0;
+    while p < rd.len() {
+        let h = rd.get(p..p + 4)?;
+        let len = u16::from_be_bytes([h[2], h[3]]) as usize;
+        out.push((u16::from_be_bytes([h[0], h[1]]), rd.get(p + 4..p + 4 + len)?));
+        p += 4 + len;
+    }
+    Some(out)
+}
+
+/// The cookie of a message. Err(-3) no OPT record or no cookie, Err(-2) malformed.
+pub fn find(data: &[u8]) -> Result<Cookie, i32> {
+    let (opt, start) = opt_record(data)?.ok_or(-3)?;
+    let rd = &data[start..start + opt.rdata.len];
+    let (_, v) = options(rd).ok_or(-2)?.into_iter().find(|&(code, _)| code == OPTION_COOKIE).ok_or(-3)?;
+    let mut client = [0u8; 8];
+    let n = v.len().min(8);
+    client[..n].copy_from_slice(&v[..n]);
+    Ok(Cookie {
+        client,
+        server: v.get(8..).unwrap_or_default().iter().take(32).copied().collect(),
+        well_formed: v.len() == 8 || (16..=40).contains(&v.len()),
+        extended_rcode: ((opt.ttl >> 24) as u16) << 4 | (data[3] & 0xF) as u16,
+    })
+}
+
+/// Compare a response's cookie with the one its query sent (COOKIE_*).
+pub fn check(query: &[u8], response: &[u8]) -> Result<i32, i32> {
+    let sent = match find(query) {
+        Ok(c) => c,
+        Err(-3) => return Ok(COOKIE_NONE),
+        Err(e) => return Err(e),
+    };
+    let got = match find(response) {
+        Ok(c) => c,
+        Err(-3) => return Ok(COOKIE_MISSING),
+        Err(e) => return Err(e),
+    };
+    Ok(if !got.well_formed {
+        COOKIE_MALFORMED
+    } else if got.client != sent.client {
+        COOKIE_MISMATCH
+    } else if got.extended_rcode == RCODE_BADCOOKIE {
+        COOKIE_BADCOOKIE
+    } else if got.server.is_empty() {
+        COOKIE_MALFORMED
+    } else {
+        COOKIE_OK
+    })
+}
+
+/// Client cookie for one server (RFC 7873 Appendix A.2): HMAC-SHA256 of the client
+/// and server addresses under a client secret, truncated to 64 bits.
+pub fn client_cookie(secret: &[u8], client: &IrisIpAddr, server: &IrisIpAddr) -> [u8; 8] {
+    let mut input = client.as_slice().to_vec();
+    input.extend_from_slice(server.as_slice());
+    let mut c = [0u8; 8];
+    c.copy_from_slice(&hmac_sha256(secret, &input)[..8]);
+    c
+}
+
+/// Put a COOKIE option into the message's OPT record, replacing any existing one;
+/// a message without OPT gets one appended to the additional section.
+pub fn set(data: &[u8], client: &[u8; 8], server: &[u8]) -> Result<Vec<u8>, i32> {
+    if !server.is_empty() && !(8..=32).contains(&server.len()) { return Err(-2); }
+    let mut option = OPTION_COOKIE.to_be_bytes().to_vec();
+    option.extend_from_slice(&((8 + server.len()) as u16).to_be_bytes());
+    option.extend_from_slice(client);
+    option.extend_from_slice(server);
+
+    let mut out = Vec::with_capacity(data.len() + option.len() + 11);
+    match opt_record(data)? {
+        Some((opt, start)) => {
+            let end = start + opt.rdata.len;
+            let mut rd = Vec::new();
+            for (code, v) in options(&data[start..end]).ok_or(-2)? {
+                if code == OPTION_COOKIE { continue; }
+                rd.extend_from_slice(&code.to_be_bytes());
+                rd.extend_from_slice(&(v.len() as u16).to_be_bytes());
+                rd.extend_from_slice(v);
+            }
+            rd.extend_from_slice(&option);
+            out.extend_from_slice(&data[..start - 2]);
+            out.extend_from_slice(&u16::try_from(rd.len()).map_err(|_| -2)?.to_be_bytes());
+            out.extend_from_slice(&rd);
+            out.extend_from_slice(&data[end..]);
+        }
+        None => {
+            let arcount = u16::from_be_bytes([data[10], data[11]]).checked_add(1).ok_or(-2)?;
+            out.extend_from_slice(data);
+            out[10..12].copy_from_slice(&arcount.to_be_bytes());
+            out.push(0); // root owner
+            out.extend_from_slice(&TYPE_OPT.to_be_bytes());
+            out.extend_from_slice(&DEFAULT_UDP_SIZE.to_be_bytes());
+            out.extend_from_slice(&[0; 4]);
+            out.extend_from_slice(&(option.len() as u16).to_be_bytes());
+            out.extend_from_slice(&option);
+        }
+    }
+    Ok(out)
+}
+
+// --- FFI ---
+
+/// Read the COOKIE option of a DNS message's OPT record.
+/// Returns 0=ok, -2=arg error or malformed, -3=no cookie.
+#[no_mangle]
+pub extern "C" fn iris_dns_cookie(data: *const u8, len: usize, out: *mut IrisDnsCookie) -> i32 {
+    if data.is_null() || out.is_null() { return -2; }
+    let c = match find(unsafe { std::slice::from_raw_parts(data, len) }) {
+        Ok(c) => c,
+        Err(e) => return e,
+    };
+    let mut server = [0u8; 32];
+    server[..c.server.len()].copy_from_slice(&c.server);
+    unsafe {
+        out.write(IrisDnsCookie {
+            client: c.client, server, server_len: c.server.len(),
+            well_formed: c.well_formed, extended_rcode: c.extended_rcode,
+        });
+    }
+    0
+}
+
+/// Check that a response echoes its query's client cookie. Returns a COOKIE_* status,
+/// or -2=arg error or malformed message.
+#[no_mangle]
+pub extern "C" fn iris_dns_cookie_check(query: *const u8, query_len: usize, response: *const u8, response_len: usize) -> i32 {
+    if query.is_null() || response.is_null() { return -2; }
+    let q = unsafe { std::slice::from_raw_parts(query, query_len) };
+    let r = unsafe { std::slice::from_raw_parts(response, response_len) };
+    check(q, r).unwrap_or_else(|e| e)
+}
+
+/// Derive the 8-byte client cookie for a client/server address pair.
+/// Returns 0=ok, -2=arg error.
+#[no_mangle]
+pub extern "C" fn iris_dns_client_cookie(
+    secret: *const u8, secret_len: usize, client: *const IrisIpAddr, server: *const IrisIpAddr, out: *mut u8,
+) -> i32 {
+    if secret.is_null() || client.is_null() || server.is_null() || out.is_null() { return -2; }
+    let secret = unsafe { std::slice::from_raw_parts(secret, secret_len) };
+    let c = client_cookie(secret, unsafe { &*client }, unsafe { &*server });
+    unsafe { std::ptr::copy_nonoverlapping(c.as_ptr(), out, 8); }
+    0
+}
+
+/// Add or replace the cookie of a DNS message: an 8-byte client cookie and the
+/// server cookie from an earlier response (NULL/0 for none, else 8-32 bytes).
+/// Returns 0=ok, -2=arg error or malformed. Free *out_data with iris_free_bytes.
+#[no_mangle]
+pub extern "C" fn iris_dns_set_cookie(
+    data: *const u8, len: usize, client: *const u8, server: *const u8, server_len: usize,
+    out_data: *mut *mut u8, out_len: *mut usize,
+) -> i32 {
+    if data.is_null() || client.is_null() || out_data.is_null() || out_len.is_null() { return -2; }
+    if server.is_null() && server_len != 0 { return -2; }
+    let msg = unsafe { std::slice::from_raw_parts(data, len) };
+    let client: &[u8; 8] = unsafe { &*(client as *const [u8; 8]) };
+    let server = if server_len == 0 { &[][..] } else { unsafe { std::slice::from_raw_parts(server, server_len) } };
+    let bytes = match set(msg, client, server) {
+        Ok(b) => b,
+        Err(e) => return e,
+    };
+    let (ptr, n) = alloc_bytes(&bytes);
+    unsafe { *out_data = ptr; *out_len = n; }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::{build_message, DnsWriter};
+
+    fn query() -> Vec<u8> {
+        build_message(0x77, 0x0100, &[("example.com".into(), 1, 1)], &[]).unwrap()
+    }
+
+    #[test]
+    fn set_and_find() {
+        let client_ip = IrisIpAddr::v4(&[192, 0, 2, 1]);
+        let server_ip = IrisIpAddr::v4(&[198, 51, 100, 53]);
+        let cc = client_cookie(b"secret", &client_ip, &server_ip);
+        assert_ne!(cc, client_cookie(b"secret", &client_ip, &IrisIpAddr::v4(&[198, 51, 100, 54])));
+
+        // No OPT yet: one is appended
+        let q = set(&query(), &cc, &[]).unwrap();
+        assert_eq!(find(&query()).err(), Some(-3));
+        let c = find(&q).unwrap();
+        assert_eq!((c.client, c.server.len(), c.well_formed, c.extended_rcode), (cc, 0, true, 0));
+        assert_eq!(dns::parse_all_records(&q).unwrap()[0].rclass, DEFAULT_UDP_SIZE);
+
+        // Existing OPT: other options kept, cookie replaced
+        let mut w = DnsWriter::new(1, 0);
+        w.question("example.com", 1, 1).unwrap();
+        w.record(SECTION_ADDITIONAL, ".", TYPE_OPT, 4096, 0, |w| { w.bytes(&[0, 8, 0, 4, 0, 1, 0x18, 0]); Ok(()) }).unwrap();
+        let with_ecs = set(&w.finish(), &cc, &[]).unwrap();
+        let again = set(&with_ecs, &cc, &[9; 16]).unwrap();
+        assert_eq!(again.len(), with_ecs.len() + 16);
+        let c = find(&again).unwrap();
+        assert_eq!(c.server, [9; 16]);
+        let rr = &dns::parse_all_records(&again).unwrap()[0];
+        assert_eq!((rr.rclass, &rr.rdata[..8]), (4096, &[0, 8, 0, 4, 0, 1, 0x18, 0][..]));
+        assert_eq!(set(&again, &cc, &[1; 4]).err(), Some(-2));
+    }
+
+    #[test]
+    fn response_checks() {
+        let cc = [1, 2, 3, 4, 5, 6, 7, 8];
+        let q = set(&query(), &cc, &[]).unwrap();
+        let response = |client: &[u8; 8], server: &[u8], rcode: u8, ext: u8| {
+            let mut w = DnsWriter::new(0x77, 0x8180 | rcode as u16);
+            w.question("example.com", 1, 1).unwrap();
+            w.record(SECTION_ADDITIONAL, ".", TYPE_OPT, 1232, (ext as u32) << 24, |w| {
+                w.bytes(&OPTION_COOKIE.to_be_bytes());
+                w.bytes(&((8 + server.len()) as u16).to_be_bytes());
+                w.bytes(client);
+                w.bytes(server);
+                Ok(())
+            }).unwrap();
+            w.finish()
+        };
+        assert_eq!(check(&q, &response(&cc, &[7; 8], 0, 0)), Ok(COOKIE_OK));
+        assert_eq!(check(&q, &response(&[0; 8], &[7; 8], 0, 0)), Ok(COOKIE_MISMATCH));
+        assert_eq!(check(&q, &response(&cc, &[], 0, 0)), Ok(COOKIE_MALFORMED));
+        assert_eq!(check(&q, &response(&cc, &[7; 5], 0, 0)), Ok(COOKIE_MALFORMED));
+        // BADCOOKIE = 23: upper eight bits 1, header RCODE 7
+        assert_eq!(check(&q, &response(&cc, &[7; 8], 7, 1)), Ok(COOKIE_BADCOOKIE));
+        assert_eq!(check(&q, &query()), Ok(COOKIE_MISSING));
+        assert_eq!(check(&query(), &q), Ok(COOKIE_NONE));
+        assert_eq!(iris_dns_cookie_check(q.as_ptr(), q.len(), q.as_ptr(), 5), -2);
+
+        let r = response(&cc, &[7; 8], 0, 0);
+        let mut out = std::mem::MaybeUninit::<IrisDnsCookie>::uninit();
+        assert_eq!(iris_dns_cookie(r.as_ptr(), r.len(), out.as_mut_ptr()), 0);
+        let c = unsafe { out.assume_init() };
+        assert_eq!((c.client, &c.server[..c.server_len]), (cc, &[7u8; 8][..]));
+    }
+}
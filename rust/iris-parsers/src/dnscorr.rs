@@ -0,0 +1,319 @@
+//! DNS transaction correlation: pairs captured queries with their responses by
+//! transaction id, question and endpoints, measures latency, and flags responses
+//! nobody asked for, responses that do not fit the query they claim to answer,
+//! and second answers to one query — the traces of off-path spoofing attempts.
+
+use crate::dns;
+use crate::ffi::{alloc_array, free_array, free_cstr, to_cstr};
+use crate::ip::IrisIpAddr;
+use std::collections::HashMap;
+use std::ffi::c_char;
+use std::sync::Mutex;
+
+pub const DNS_TXN_QUERY: u8 = 1;       // query recorded, awaiting its response
+pub const DNS_TXN_ANSWERED: u8 = 2;    // response matched its query; latency set
+pub const DNS_TXN_UNSOLICITED: u8 = 3; // response with no outstanding query
+pub const DNS_TXN_MISMATCH: u8 = 4;    // id and client port match a query, but the
+                                       // question or server endpoint does not
+pub const DNS_TXN_DUPLICATE: u8 = 5;   // another response to an answered query
+pub const DNS_TXN_TIMEOUT: u8 = 6;     // no response within the timeout (expire)
+
+#[repr(C)]
+pub struct IrisDnsTransaction {
+    pub status: u8,              // DNS_TXN_*
+    pub id: u16,
+    pub ip_protocol: u8,         // PROTO_UDP or PROTO_TCP, as passed in
+    pub client: IrisIpAddr,
+    pub client_port: u16,
+    pub server: IrisIpAddr,
+    pub server_port: u16,
+    pub qname: *mut c_char,      // "" if the message has no question
+    pub qtype: u16,
+    pub query_us: i64,           // 0 if no query was seen
+    pub response_us: i64,        // 0 for QUERY and TIMEOUT
+    pub latency_us: i64,         // response_us - query_us, for ANSWERED and DUPLICATE
+    pub rcode: u8,               // of the response
+}
+
+#[repr(C)]
+pub struct IrisDnsTransactions {
+    pub items: *mut IrisDnsTransaction,
+    pub count: usize,
+}
+
+/// Queries are found by what a response must echo exactly: protocol, client
+/// endpoint and id. The server side and question are checked after the lookup so
+/// near misses can be reported as mismatches.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct Key { proto: u8, client: IrisIpAddr, client_port: u16, id: u16 }
+
+struct Pending {
+    server: IrisIpAddr,
+    server_port: u16,
+    qname: String,
+    qtype: u16,
+    qclass: u16,
+    query_us: i64,
+    answered_us: Option<i64>,
+}
+
+struct Correlator {
+    pending: HashMap<Key, Vec<Pending>>,
+    len: usize,
+    timeout_us: i64,
+    max_pending: usize,
+}
+
+/// One transaction event; converted to IrisDnsTransaction at the FFI boundary.
+pub struct Event {
+    pub status: u8,
+    pub id: u16,
+    pub proto: u8,
+    pub client: (IrisIpAddr, u16),
+    pub server: (IrisIpAddr, u16),
+    pub qname: String,
+    pub qtype: u16,
+    pub query_us: i64,
+    pub response_us: i64,
+    pub rcode: u8,
+}
+
+impl Correlator {
+    fn evict_oldest(&mut self) {
+        let oldest = self.pending.iter()
+            .flat_map(|(k, v)| v.iter().enumerate().map(move |(i, p)| (*k, i, p.query_us)))
+            .min_by_key(|&(_, _, t)| t);
+        if let Some((k, i, _)) = oldest {
+            let list = self.pending.get_mut(&k).unwrap();
+            list.remove(i);
+            if list.is_empty() { self.pending.remove(&k); }
+            self.len -= 1;
+        }
+    }
+
+    /// Account one DNS message sent from `src` to `dst` at `ts`. None if it is
+    /// not a DNS message.
+    fn observe(&mut self, msg: &[u8], proto: u8, src: (IrisIpAddr, u16), dst: (IrisIpAddr, u16), ts: i64) -> Option<Event> {
+        let (id, flags, question) = dns::header_question(msg)?;
+        let (qname, qtype, qclass) = question.unwrap_or_default();
+        let is_response = flags & 0x8000 != 0;
+        let (client, server) = if is_response { (dst, src) } else { (src, dst) };
+        let mut ev = Event {
+            status: DNS_TXN_QUERY, id, proto, client, server, qname, qtype,
+            query_us: 0, response_us: 0, rcode: (flags & 0xF) as u8,
+        };
+        let key = Key { proto, client: client.0, client_port: client.1, id };
+        if !is_response {
+            if self.len >= self.max_pending { self.evict_oldest(); }
+            self.pending.entry(key).or_default().push(Pending {
+                server: server.0, server_port: server.1, qname: ev.qname.clone(), qtype, qclass,
+                query_us: ts, answered_us: None,
+            });
+            self.len += 1;
+            ev.query_us = ts;
+            ev.rcode = 0;
+            return Some(ev);
+        }
+        ev.response_us = ts;
+        ev.status = DNS_TXN_UNSOLICITED;
+        let Some(list) = self.pending.get_mut(&key) else { return Some(ev) };
+        // A response without a question (FORMERR and the like) answers whichever query is open
+        let fits = |p: &Pending| (p.server, p.server_port) == server
+            && (ev.qname.is_empty() && qtype == 0
+                || p.qname.eq_ignore_ascii_case(&ev.qname) && p.qtype == qtype && p.qclass == qclass);
+        match list.iter_mut().filter(|p| fits(p)).min_by_key(|p| p.answered_us.is_some()) {
+            Some(p) => {
+                ev.status = if p.answered_us.is_some() { DNS_TXN_DUPLICATE } else { DNS_TXN_ANSWERED };
+                p.answered_us.get_or_insert(ts);
+                ev.query_us = p.query_us;
+                if ev.qname.is_empty() { (ev.qname, ev.qtype) = (p.qname.clone(), p.qtype); }
+            }
+            None => ev.status = DNS_TXN_MISMATCH,
+        }
+        Some(ev)
+    }
+
+    /// Drop queries older than the timeout, reporting the unanswered ones.
+    fn expire(&mut self, now: i64) -> Vec<Event> {
+        let timeout = self.timeout_us;
+        let mut done = Vec::new();
+        let mut removed = 0;
+        self.pending.retain(|k, list| {
+            list.retain(|p| {
+                let keep = now - p.query_us < timeout;
+                if !keep {
+                    removed += 1;
+                    if p.answered_us.is_none() {
+                        done.push(Event {
+                            status: DNS_TXN_TIMEOUT, id: k.id, proto: k.proto,
+                            client: (k.client, k.client_port), server: (p.server, p.server_port),
+                            qname: p.qname.clone(), qtype: p.qtype, query_us: p.query_us, response_us: 0, rcode: 0,
+                        });
+                    }
+                }
+                keep
+            });
+            !list.is_empty()
+        });
+        self.len -= removed;
+        done.sort_by_key(|e| e.query_us);
+        done
+    }
+}
+
+/// Opaque correlator. Create with iris_dns_correlator_new.
+pub struct IrisDnsCorrelator { inner: Mutex<Correlator> }
+
+fn transaction(e: Event) -> IrisDnsTransaction {
+    let answered = matches!(e.status, DNS_TXN_ANSWERED | DNS_TXN_DUPLICATE);
+    IrisDnsTransaction {
+        status: e.status, id: e.id, ip_protocol: e.proto,
+        client: e.client.0, client_port: e.client.1, server: e.server.0, server_port: e.server.1,
+        qname: to_cstr(&e.qname), qtype: e.qtype,
+        query_us: e.query_us, response_us: e.response_us,
+        latency_us: if answered { e.response_us - e.query_us } else { 0 },
+        rcode: e.rcode,
+    }
+}
+
+// --- FFI entry points ---
+
+/// Create a correlator. Queries are kept `timeout_ms` after they are sent (answered
+/// ones too, to catch late duplicate responses); beyond `max_pending` outstanding
+/// queries the oldest is dropped. Free with iris_dns_correlator_free.
+#[no_mangle]
+pub extern "C" fn iris_dns_correlator_new(timeout_ms: u32, max_pending: usize) -> *mut IrisDnsCorrelator {
+    let c = Correlator {
+        pending: HashMap::new(), len: 0,
+        timeout_us: timeout_ms as i64 * 1000, max_pending: max_pending.max(1),
+    };
+    Box::into_raw(Box::new(IrisDnsCorrelator { inner: Mutex::new(c) }))
+}
+
+/// Account one DNS message (UDP payload, or one message from iris_dns_tcp_feed) sent
+/// from src to dst at `timestamp_us`. The resulting event is written to `out`; free
+/// it with iris_dns_transaction_free. Returns 0=ok, -2=arg error or not DNS.
+#[no_mangle]
+pub extern "C" fn iris_dns_correlator_observe(
+    c: *mut IrisDnsCorrelator, data: *const u8, len: usize, ip_protocol: u8,
+    src: *const IrisIpAddr, src_port: u16, dst: *const IrisIpAddr, dst_port: u16,
+    timestamp_us: i64, out: *mut IrisDnsTransaction,
+) -> i32 {
+    if c.is_null() || data.is_null() || src.is_null() || dst.is_null() || out.is_null() { return -2; }
+    let msg = unsafe { std::slice::from_raw_parts(data, len) };
+    let (src, dst) = unsafe { ((*src, src_port), (*dst, dst_port)) };
+    let ev = unsafe { &*c }.inner.lock().unwrap_or_else(|e| e.into_inner()).observe(msg, ip_protocol, src, dst, timestamp_us);
+    match ev {
+        Some(ev) => { unsafe { out.write(transaction(ev)); } 0 }
+        None => -2,
+    }
+}
+
+/// Remove queries sent more than the timeout before `now_us`, returning the
+/// unanswered ones as DNS_TXN_TIMEOUT events in `out` (free with
+/// iris_dns_transactions_free). Returns 0=ok, -2=arg error.
+#[no_mangle]
+pub extern "C" fn iris_dns_correlator_expire(c: *mut IrisDnsCorrelator, now_us: i64, out: *mut IrisDnsTransactions) -> i32 {
+    if c.is_null() || out.is_null() { return -2; }
+    let done = unsafe { &*c }.inner.lock().unwrap_or_else(|e| e.into_inner()).expire(now_us);
+    let (items, count) = alloc_array(done.into_iter().map(transaction).collect());
+    unsafe { out.write(IrisDnsTransactions { items, count }); }
+    0
+}
+
+/// Number of queries held, answered or not.
+#[no_mangle]
+pub extern "C" fn iris_dns_correlator_len(c: *mut IrisDnsCorrelator) -> usize {
+    if c.is_null() { return 0; }
+    unsafe { &*c }.inner.lock().unwrap_or_else(|e| e.into_inner()).len
+}
+
+#[no_mangle]
+pub extern "C" fn iris_dns_transaction_free(t: *mut IrisDnsTransaction) {
+    if t.is_null() { return; }
+    free_cstr(unsafe { &*t }.qname);
+}
+
+#[no_mangle]
+pub extern "C" fn iris_dns_transactions_free(t: *mut IrisDnsTransactions) {
+    if t.is_null() { return; }
+    let t = unsafe { &*t };
+    for i in 0..t.count { iris_dns_transaction_free(unsafe { t.items.add(i) }); }
+    free_array(t.items, t.count);
+}
+
+#[no_mangle]
+pub extern "C" fn iris_dns_correlator_free(c: *mut IrisDnsCorrelator) {
+    if c.is_null() { return; }
+    unsafe { drop(Box::from_raw(c)); }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::build_message;
+    use crate::ip::PROTO_UDP;
+
+    const CLIENT: ([u8; 4], u16) = ([10, 0, 0, 2], 40000);
+    const SERVER: ([u8; 4], u16) = ([192, 0, 2, 53], 53);
+
+    fn msg(id: u16, flags: u16, name: &str) -> Vec<u8> {
+        build_message(id, flags, &[(name.into(), 1, 1)], &[]).unwrap()
+    }
+
+    fn observe(c: *mut IrisDnsCorrelator, m: &[u8], from: ([u8; 4], u16), to: ([u8; 4], u16), ts: i64) -> (u8, i64, String) {
+        let (src, dst) = (IrisIpAddr::v4(&from.0), IrisIpAddr::v4(&to.0));
+        let mut out = std::mem::MaybeUninit::<IrisDnsTransaction>::uninit();
+        assert_eq!(iris_dns_correlator_observe(c, m.as_ptr(), m.len(), PROTO_UDP, &src, from.1, &dst, to.1, ts, out.as_mut_ptr()), 0);
+        let mut t = unsafe { out.assume_init() };
+        let name = unsafe { std::ffi::CStr::from_ptr(t.qname) }.to_string_lossy().into_owned();
+        let r = (t.status, t.latency_us, name);
+        iris_dns_transaction_free(&mut t);
+        r
+    }
+
+    #[test]
+    fn pairs_and_flags_responses() {
+        let c = iris_dns_correlator_new(2000, 16);
+        assert_eq!(observe(c, &msg(7, 0x0100, "example.com"), CLIENT, SERVER, 1_000).0, DNS_TXN_QUERY);
+        // Wrong question first (a spoofing attempt guessing the id), then the real answer
+        assert_eq!(observe(c, &msg(7, 0x8180, "evil.example"), SERVER, CLIENT, 1_500).0, DNS_TXN_MISMATCH);
+        assert_eq!(observe(c, &msg(7, 0x8180, "EXAMPLE.com"), SERVER, CLIENT, 4_000), (DNS_TXN_ANSWERED, 3_000, "EXAMPLE.com".into()));
+        assert_eq!(observe(c, &msg(7, 0x8180, "example.com"), SERVER, CLIENT, 4_200).0, DNS_TXN_DUPLICATE);
+        // Right id and question from another address
+        assert_eq!(observe(c, &msg(7, 0x8180, "example.com"), ([198, 51, 100, 1], 53), CLIENT, 4_300).0, DNS_TXN_MISMATCH);
+        assert_eq!(observe(c, &msg(8, 0x8180, "example.com"), SERVER, CLIENT, 4_400).0, DNS_TXN_UNSOLICITED);
+        assert_eq!(observe(c, &msg(7, 0x8180, "example.com"), SERVER, (CLIENT.0, 40001), 4_500).0, DNS_TXN_UNSOLICITED);
+
+        // A question-less FORMERR answers the open query
+        observe(c, &msg(9, 0x0100, "example.org"), CLIENT, SERVER, 5_000);
+        let formerr = build_message(9, 0x8181, &[], &[]).unwrap();
+        assert_eq!(observe(c, &formerr, SERVER, CLIENT, 5_100), (DNS_TXN_ANSWERED, 100, "example.org".into()));
+        assert_eq!(iris_dns_correlator_len(c), 2);
+        iris_dns_correlator_free(c);
+    }
+
+    #[test]
+    fn timeouts_and_capacity() {
+        let c = iris_dns_correlator_new(1, 2); // 1 ms
+        observe(c, &msg(1, 0x0100, "a.example"), CLIENT, SERVER, 0);
+        observe(c, &msg(2, 0x0100, "b.example"), CLIENT, SERVER, 10);
+        observe(c, &msg(2, 0x8180, "b.example"), SERVER, CLIENT, 20);
+        observe(c, &msg(3, 0x0100, "c.example"), CLIENT, SERVER, 30); // evicts id 1
+        assert_eq!(iris_dns_correlator_len(c), 2);
+
+        let mut out = std::mem::MaybeUninit::<IrisDnsTransactions>::uninit();
+        assert_eq!(iris_dns_correlator_expire(c, 1_500, out.as_mut_ptr()), 0);
+        let mut t = unsafe { out.assume_init() };
+        assert_eq!(t.count, 1); // the answered query leaves silently
+        let e = unsafe { &*t.items };
+        assert_eq!((e.status, e.id, e.query_us, e.server_port), (DNS_TXN_TIMEOUT, 3, 30, 53));
+        iris_dns_transactions_free(&mut t);
+        assert_eq!(iris_dns_correlator_len(c), 0);
+
+        let (src, dst) = (IrisIpAddr::v4(&CLIENT.0), IrisIpAddr::v4(&SERVER.0));
+        let mut ev = std::mem::MaybeUninit::<IrisDnsTransaction>::uninit();
+        assert_eq!(iris_dns_correlator_observe(c, b"short".as_ptr(), 5, PROTO_UDP, &src, 1, &dst, 53, 0, ev.as_mut_ptr()), -2);
+        iris_dns_correlator_free(c);
+    }
+}
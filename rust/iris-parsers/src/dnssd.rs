@@ -0,0 +1,445 @@
+//! DNS-SD (RFC 6763) interpretation of mDNS/DNS messages: PTR/SRV/TXT records and
+//! host addresses are correlated into one advertisement per service instance, and
+//! a browser accumulates them across messages into a list of services on the LAN.
+
+use crate::dns::{self, DnsRR};
+use crate::ffi::{alloc_array, free_array, to_cstr, free_cstr, free_c_string_array, vec_to_c_string_array, IrisCStringArray};
+use crate::ip::IrisIpAddr;
+use std::collections::HashMap;
+use std::ffi::c_char;
+use std::sync::Mutex;
+
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_TXT: u16 = 16;
+const TYPE_AAAA: u16 = 28;
+const TYPE_SRV: u16 = 33;
+/// Owner of the service type enumeration PTRs (RFC 6763 §9), before the domain.
+const SERVICES_ENUM: &str = "_services._dns-sd._udp.";
+/// Instances and hosts a browser remembers; later ones are ignored.
+const BROWSER_MAX: usize = 1024;
+
+#[repr(C)]
+pub struct IrisDnssdTxt {
+    pub key: *mut c_char,
+    pub value: *mut c_char,     // empty for "key=" ...
+    pub has_value: bool,        // ... and false for a bare boolean "key"
+}
+
+#[repr(C)]
+pub struct IrisDnssdService {
+    pub instance: *mut c_char,      // "Living Room"
+    pub service_type: *mut c_char,  // "_airplay._tcp"
+    pub domain: *mut c_char,        // "local"
+    pub host: *mut c_char,          // SRV target, "" if no SRV in this message
+    pub port: u16,
+    pub priority: u16,
+    pub weight: u16,
+    pub ttl: u32,                   // PTR TTL, else SRV/TXT TTL
+    pub goodbye: bool,              // TTL 0: the service is being withdrawn
+    pub txt: *mut IrisDnssdTxt,
+    pub txt_count: usize,
+    pub addresses: *mut IrisIpAddr, // A/AAAA records for `host` in the same message
+    pub addresses_count: usize,
+}
+
+#[repr(C)]
+pub struct IrisDnssdServices {
+    pub services: *mut IrisDnssdService,
+    pub count: usize,
+    pub service_types: IrisCStringArray, // "_airplay._tcp.local", from _services._dns-sd._udp PTRs
+}
+
+pub struct IrisDnssdBrowser {
+    inner: Mutex<Browser>,
+}
+
+#[derive(Default, Clone)]
+struct Service {
+    full_name: String,
+    instance: String,
+    service_type: String,
+    domain: String,
+    host: String,
+    port: u16,
+    priority: u16,
+    weight: u16,
+    ttl: Option<u32>,
+    ttl_from_ptr: bool,
+    txt: Vec<(String, String, bool)>,
+    addresses: Vec<IrisIpAddr>,
+}
+
+/// Split "<instance>.<_svc>.<_tcp|_udp>.<domain>" at the service labels. The instance
+/// may itself contain dots, so the protocol label is located from the left.
+fn split_instance(name: &str) -> Option<(String, String, String)> {
+    let labels: Vec<&str> = name.split('.').collect();
+    let proto = (2..labels.len()).find(|&i| {
+        (labels[i].eq_ignore_ascii_case("_tcp") || labels[i].eq_ignore_ascii_case("_udp"))
+            && labels[i - 1].starts_with('_')
+    })?;
+    let instance = labels[..proto - 1].join(".");
+    let service_type = labels[proto - 1..=proto].join(".");
+    let domain = labels[proto + 1..].join(".");
+    Some((instance, service_type, domain))
+}
+
+fn txt_entries(rd: &[u8]) -> Vec<(String, String, bool)> {
+    let mut out = Vec::new();
+    let mut p = 0;
+    while p < rd.len() {
+        let len = rd[p] as usize;
+        p += 1;
+        if p + len > rd.len() { break; }
+        let s = &rd[p..p + len];
+        p += len;
+        if s.is_empty() { continue; }
+        let entry = match s.iter().position(|&b| b == b'=') {
+            Some(eq) => (String::from_utf8_lossy(&s[..eq]).into_owned(),
+                         String::from_utf8_lossy(&s[eq + 1..]).into_owned(), true),
+            None => (String::from_utf8_lossy(s).into_owned(), String::new(), false),
+        };
+        // RFC 6763 §6.4: keys are case-insensitive and only the first occurrence counts
+        if !out.iter().any(|(k, _, _): &(String, String, bool)| k.eq_ignore_ascii_case(&entry.0)) { out.push(entry); }
+    }
+    out
+}
+
+fn service_for<'a>(services: &'a mut Vec<Service>, full_name: &str) -> Option<&'a mut Service> {
+    if let Some(i) = services.iter().position(|s| s.full_name.eq_ignore_ascii_case(full_name)) {
+        return Some(&mut services[i]);
+    }
+    let (instance, service_type, domain) = split_instance(full_name)?;
+    if instance.is_empty() { return None; }
+    services.push(Service { full_name: full_name.to_string(), instance, service_type, domain, ..Default::default() });
+    services.last_mut()
+}
+
+fn address(rr: &DnsRR) -> Option<IrisIpAddr> {
+    match (rr.rtype, rr.rdata.len()) {
+        (TYPE_A, 4) => Some(IrisIpAddr::v4(&rr.rdata)),
+        (TYPE_AAAA, 16) => Some(IrisIpAddr::v6(&rr.rdata)),
+        _ => None,
+    }
+}
+
+/// Service types announced by enumeration PTRs, with their TTLs.
+fn service_types(rrs: &[DnsRR]) -> Vec<(String, u32)> {
+    rrs.iter()
+        .filter(|rr| rr.rtype == TYPE_PTR && rr.name.get(..SERVICES_ENUM.len()).is_some_and(|p| p.eq_ignore_ascii_case(SERVICES_ENUM)))
+        .map(|rr| (rr.display.clone(), rr.ttl))
+        .collect()
+}
+
+fn interpret(rrs: &[DnsRR]) -> Vec<Service> {
+    let mut services = Vec::new();
+    for rr in rrs.iter().filter(|r| r.rtype == TYPE_PTR) {
+        // Only "[<sub>._sub.]<_svc>.<_proto>.<domain>" PTRs; skips reverse-lookup and enumeration PTRs
+        let browse = rr.name.split_once("._sub.").map_or(rr.name.as_str(), |(_, t)| t);
+        let is_browse = split_instance(&format!("x.{}", browse)).is_some_and(|(i, _, _)| i == "x");
+        if !is_browse { continue; }
+        if let Some(s) = service_for(&mut services, &rr.display) {
+            (s.ttl, s.ttl_from_ptr) = (Some(rr.ttl), true);
+        }
+    }
+    for rr in rrs {
+        match rr.rtype {
+            TYPE_SRV if rr.rdata.len() >= 6 => {
+                let Some(s) = service_for(&mut services, &rr.name) else { continue };
+                s.priority = u16::from_be_bytes([rr.rdata[0], rr.rdata[1]]);
+                s.weight = u16::from_be_bytes([rr.rdata[2], rr.rdata[3]]);
+                s.port = u16::from_be_bytes([rr.rdata[4], rr.rdata[5]]);
+                s.host = rr.display.rsplit(' ').next().unwrap_or("").to_string();
+                s.ttl.get_or_insert(rr.ttl);
+            }
+            TYPE_TXT => {
+                let Some(s) = service_for(&mut services, &rr.name) else { continue };
+                s.txt = txt_entries(&rr.rdata);
+                s.ttl.get_or_insert(rr.ttl);
+            }
+            _ => {}
+        }
+    }
+    for rr in rrs {
+        let Some(addr) = address(rr) else { continue };
+        for s in services.iter_mut().filter(|s| s.host.eq_ignore_ascii_case(&rr.name)) {
+            if !s.addresses.contains(&addr) { s.addresses.push(addr); }
+        }
+    }
+    services
+}
+
+/// DNS-SD state gathered from many messages: instances keep what their latest
+/// PTR, SRV and TXT records said until a goodbye (TTL 0) withdraws them, and
+/// host addresses are attached when the list is taken.
+#[derive(Default)]
+struct Browser {
+    services: Vec<Service>,
+    types: Vec<String>,
+    hosts: HashMap<String, Vec<IrisIpAddr>>,
+}
+
+impl Browser {
+    fn feed(&mut self, rrs: &[DnsRR]) {
+        for (t, ttl) in service_types(rrs) {
+            let known = self.types.iter().position(|k| k.eq_ignore_ascii_case(&t));
+            match (known, ttl) {
+                (Some(i), 0) => { self.types.remove(i); }
+                (None, 1..) if self.types.len() < BROWSER_MAX => self.types.push(t),
+                _ => {}
+            }
+        }
+        for s in interpret(rrs) {
+            let known = self.services.iter().position(|k| k.full_name.eq_ignore_ascii_case(&s.full_name));
+            match known {
+                _ if s.ttl == Some(0) => { if let Some(i) = known { self.services.remove(i); } }
+                Some(i) => {
+                    let k = &mut self.services[i];
+                    if !s.host.is_empty() { (k.host, k.port, k.priority, k.weight) = (s.host, s.port, s.priority, s.weight); }
+                    if !s.txt.is_empty() { k.txt = s.txt; }
+                    // A PTR's TTL stands until the next PTR
+                    if s.ttl_from_ptr || !k.ttl_from_ptr { (k.ttl, k.ttl_from_ptr) = (s.ttl, s.ttl_from_ptr); }
+                }
+                None if self.services.len() < BROWSER_MAX => self.services.push(Service { addresses: Vec::new(), ..s }),
+                None => {}
+            }
+        }
+        for rr in rrs {
+            let Some(addr) = address(rr) else { continue };
+            let host = rr.name.to_ascii_lowercase();
+            if rr.ttl == 0 {
+                if let Some(a) = self.hosts.get_mut(&host) { a.retain(|x| *x != addr); }
+                continue;
+            }
+            if !self.hosts.contains_key(&host) && self.hosts.len() >= BROWSER_MAX { continue; }
+            let a = self.hosts.entry(host).or_default();
+            if !a.contains(&addr) { a.push(addr); }
+        }
+    }
+
+    fn list(&self) -> Vec<Service> {
+        self.services.iter().map(|s| Service {
+            addresses: self.hosts.get(&s.host.to_ascii_lowercase()).cloned().unwrap_or_default(),
+            ..s.clone()
+        }).collect()
+    }
+}
+
+fn write_services(services: Vec<Service>, types: Vec<String>, out: *mut IrisDnssdServices) {
+    let svcs: Vec<IrisDnssdService> = services.into_iter().map(|s| {
+        let txt = s.txt.iter().map(|(k, v, has)| IrisDnssdTxt {
+            key: to_cstr(k), value: to_cstr(v), has_value: *has,
+        }).collect();
+        let (txt, txt_count) = alloc_array(txt);
+        let (addresses, addresses_count) = alloc_array(s.addresses);
+        let ttl = s.ttl.unwrap_or(0);
+        IrisDnssdService {
+            instance: to_cstr(&s.instance),
+            service_type: to_cstr(&s.service_type),
+            domain: to_cstr(&s.domain),
+            host: to_cstr(&s.host),
+            port: s.port, priority: s.priority, weight: s.weight,
+            ttl, goodbye: ttl == 0,
+            txt, txt_count, addresses, addresses_count,
+        }
+    }).collect();
+    let (services, count) = alloc_array(svcs);
+    unsafe { out.write(IrisDnssdServices { services, count, service_types: vec_to_c_string_array(types) }); }
+}
+
+// --- FFI entry points ---
+
+/// Interpret a DNS/mDNS message as DNS-SD advertisements. A message with no
+/// service records yields count 0. Returns 0=ok, -2=parse/arg error.
+/// Free with iris_dnssd_free.
+#[no_mangle]
+pub extern "C" fn iris_dnssd_parse(data: *const u8, len: usize, out: *mut IrisDnssdServices) -> i32 {
+    if data.is_null() || out.is_null() || len == 0 { return -2; }
+    let buf = unsafe { std::slice::from_raw_parts(data, len) };
+    let rrs = match dns::parse_all_records(buf) { Some(r) => r, None => return -2 };
+    let types = service_types(&rrs).into_iter().map(|(t, _)| t).collect();
+    write_services(interpret(&rrs), types, out);
+    0
+}
+
+/// New browser accumulating DNS-SD service types and instances across messages.
+/// Free with iris_dnssd_browser_free.
+#[no_mangle]
+pub extern "C" fn iris_dnssd_browser_new() -> *mut IrisDnssdBrowser {
+    Box::into_raw(Box::new(IrisDnssdBrowser { inner: Mutex::new(Browser::default()) }))
+}
+
+/// Add the records of one DNS/mDNS message. Returns 0=ok, -2=parse/arg error.
+#[no_mangle]
+pub extern "C" fn iris_dnssd_browser_feed(b: *mut IrisDnssdBrowser, data: *const u8, len: usize) -> i32 {
+    if b.is_null() || data.is_null() || len == 0 { return -2; }
+    let Some(rrs) = dns::parse_all_records(unsafe { std::slice::from_raw_parts(data, len) }) else { return -2 };
+    unsafe { &*b }.inner.lock().unwrap_or_else(|e| e.into_inner()).feed(&rrs);
+    0
+}
+
+/// The service instances and types known so far, instances with the addresses
+/// last seen for their host. Returns 0=ok, -2=arg error. Free with iris_dnssd_free.
+#[no_mangle]
+pub extern "C" fn iris_dnssd_browser_list(b: *mut IrisDnssdBrowser, out: *mut IrisDnssdServices) -> i32 {
+    if b.is_null() || out.is_null() { return -2; }
+    let inner = unsafe { &*b }.inner.lock().unwrap_or_else(|e| e.into_inner());
+    write_services(inner.list(), inner.types.clone(), out);
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn iris_dnssd_browser_free(b: *mut IrisDnssdBrowser) {
+    if b.is_null() { return; }
+    unsafe { drop(Box::from_raw(b)); }
+}
+
+/// Free services returned by iris_dnssd_parse or iris_dnssd_browser_list.
+#[no_mangle]
+pub extern "C" fn iris_dnssd_free(svcs: *mut IrisDnssdServices) {
+    if svcs.is_null() { return; }
+    unsafe {
+        let l = &*svcs;
+        free_c_string_array(&l.service_types);
+        if l.services.is_null() { return; }
+        for i in 0..l.count {
+            let s = &*l.services.add(i);
+            for p in [s.instance, s.service_type, s.domain, s.host] { free_cstr(p); }
+            if !s.txt.is_null() {
+                for j in 0..s.txt_count {
+                    let t = &*s.txt.add(j);
+                    free_cstr(t.key);
+                    free_cstr(t.value);
+                }
+            }
+            free_array(s.txt, s.txt_count);
+            free_array(s.addresses, s.addresses_count);
+        }
+        free_array(l.services, l.count);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(n: &str) -> Vec<u8> {
+        let mut v = Vec::new();
+        for l in n.split('.') { v.push(l.len() as u8); v.extend_from_slice(l.as_bytes()); }
+        v.push(0);
+        v
+    }
+
+    fn rr(owner: &str, rtype: u16, ttl: u32, rdata: &[u8]) -> Vec<u8> {
+        let mut v = name(owner);
+        v.extend_from_slice(&rtype.to_be_bytes());
+        v.extend_from_slice(&0x8001u16.to_be_bytes()); // IN + cache-flush
+        v.extend_from_slice(&ttl.to_be_bytes());
+        v.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+        v.extend_from_slice(rdata);
+        v
+    }
+
+    fn cstr(p: *mut c_char) -> String {
+        unsafe { std::ffi::CStr::from_ptr(p) }.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn airplay_advertisement() {
+        let inst = "Living Room._airplay._tcp.local";
+        let mut srv = vec![0, 0, 0, 0, 0x1b, 0x58];
+        srv.extend(name("Living-Room.local"));
+        let txt: Vec<u8> = ["model=AppleTV14,1", "pk=abc", "flag", "Model=dup"].iter()
+            .flat_map(|s| std::iter::once(s.len() as u8).chain(s.bytes())).collect();
+        let records = [
+            rr("_airplay._tcp.local", TYPE_PTR, 4500, &name(inst)),
+            rr(inst, TYPE_SRV, 120, &srv),
+            rr(inst, TYPE_TXT, 4500, &txt),
+            rr("Living-Room.local", TYPE_A, 120, &[192, 168, 1, 20]),
+            rr("1.20.168.192.in-addr.arpa", TYPE_PTR, 120, &name("Living-Room.local")),
+        ];
+        let msg = message(&records);
+
+        let mut out = std::mem::MaybeUninit::<IrisDnssdServices>::uninit();
+        assert_eq!(iris_dnssd_parse(msg.as_ptr(), msg.len(), out.as_mut_ptr()), 0);
+        let mut out = unsafe { out.assume_init() };
+        assert_eq!(out.count, 1);
+        let s = unsafe { &*out.services };
+        assert_eq!(cstr(s.instance), "Living Room");
+        assert_eq!(cstr(s.service_type), "_airplay._tcp");
+        assert_eq!(cstr(s.domain), "local");
+        assert_eq!(cstr(s.host), "Living-Room.local");
+        assert_eq!((s.port, s.ttl, s.goodbye), (7000, 4500, false));
+        let txt = unsafe { std::slice::from_raw_parts(s.txt, s.txt_count) };
+        let kv: Vec<(String, String, bool)> = txt.iter().map(|t| (cstr(t.key), cstr(t.value), t.has_value)).collect();
+        assert_eq!(kv, [("model".into(), "AppleTV14,1".into(), true), ("pk".into(), "abc".into(), true),
+                        ("flag".into(), String::new(), false)]);
+        assert_eq!(s.addresses_count, 1);
+        assert_eq!(unsafe { (*s.addresses).as_slice() }, &[192, 168, 1, 20]);
+        iris_dnssd_free(&mut out);
+    }
+
+    fn message(records: &[Vec<u8>]) -> Vec<u8> {
+        let mut msg = vec![0, 0, 0x84, 0, 0, 0, 0, records.len() as u8, 0, 0, 0, 0];
+        for r in records { msg.extend_from_slice(r); }
+        msg
+    }
+
+    #[test]
+    fn browse_across_messages() {
+        let inst = "Office._ipp._tcp.local";
+        let mut srv = vec![0, 0, 0, 0, 0x02, 0x77];
+        srv.extend(name("printer.local"));
+        let b = iris_dnssd_browser_new();
+        let feed = |records: &[Vec<u8>]| {
+            let msg = message(records);
+            assert_eq!(iris_dnssd_browser_feed(b, msg.as_ptr(), msg.len()), 0);
+        };
+        let list = || {
+            let mut out = std::mem::MaybeUninit::<IrisDnssdServices>::uninit();
+            assert_eq!(iris_dnssd_browser_list(b, out.as_mut_ptr()), 0);
+            unsafe { out.assume_init() }
+        };
+
+        let enumeration = [
+            rr("_services._dns-sd._udp.local", TYPE_PTR, 4500, &name("_ipp._tcp.local")),
+            rr("_services._dns-sd._udp.local", TYPE_PTR, 4500, &name("_ssh._tcp.local")),
+        ];
+        // A single message reports the types it enumerates, and no instances
+        let msg = message(&enumeration);
+        let mut out = std::mem::MaybeUninit::<IrisDnssdServices>::uninit();
+        assert_eq!(iris_dnssd_parse(msg.as_ptr(), msg.len(), out.as_mut_ptr()), 0);
+        let mut out = unsafe { out.assume_init() };
+        assert_eq!((out.count, out.service_types.count), (0, 2));
+        assert_eq!(cstr(unsafe { *out.service_types.items }), "_ipp._tcp.local");
+        iris_dnssd_free(&mut out);
+
+        feed(&enumeration);
+        feed(&[rr("_ipp._tcp.local", TYPE_PTR, 4500, &name(inst))]);
+        feed(&[rr(inst, TYPE_SRV, 120, &srv), rr(inst, TYPE_TXT, 4500, &[6, b'r', b'p', b'=', b'i', b'p', b'p'])]);
+        feed(&[rr("printer.local", TYPE_A, 120, &[10, 0, 0, 9])]);
+        feed(&[rr("_services._dns-sd._udp.local", TYPE_PTR, 0, &name("_ssh._tcp.local"))]);
+
+        let mut out = list();
+        assert_eq!((out.count, out.service_types.count), (1, 1));
+        let s = unsafe { &*out.services };
+        assert_eq!((cstr(s.instance), cstr(s.host), s.port, s.ttl), ("Office".into(), "printer.local".into(), 631, 4500));
+        assert_eq!((s.txt_count, s.addresses_count), (1, 1));
+        assert_eq!(unsafe { (*s.addresses).as_slice() }, &[10, 0, 0, 9]);
+        iris_dnssd_free(&mut out);
+
+        // Goodbye withdraws the instance
+        feed(&[rr("_ipp._tcp.local", TYPE_PTR, 0, &name(inst))]);
+        let mut out = list();
+        assert_eq!(out.count, 0);
+        iris_dnssd_free(&mut out);
+        iris_dnssd_browser_free(b);
+    }
+
+    #[test]
+    fn split_dotted_instance() {
+        let (i, t, d) = split_instance("Bob's Mac 2.0._ssh._tcp.local").unwrap();
+        assert_eq!((i.as_str(), t.as_str(), d.as_str()), ("Bob's Mac 2.0", "_ssh._tcp", "local"));
+        assert!(split_instance("host.example.com").is_none());
+    }
+}
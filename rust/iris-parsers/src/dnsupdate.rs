@@ -0,0 +1,225 @@
+//! DNS UPDATE (RFC 2136, opcode 5). The four sections become zone, prerequisite,
+//! update and additional, and the class and TTL of a record select what it
+//! asserts or changes, so Active Directory registrations and other dynamic
+//! updates read as operations rather than as odd-looking answers.
+
+use crate::dns::{self, DnsRR, IrisDnsRecord};
+use crate::ffi::{alloc_array, alloc_bytes, free_array, free_cstr, iris_free_bytes, to_cstr};
+use std::ffi::c_char;
+
+pub const DNS_UPDATE_INVALID: u8 = 0;            // fits none of the RFC 2136 forms
+pub const DNS_PREREQ_NAME_IN_USE: u8 = 1;        // class ANY, type ANY
+pub const DNS_PREREQ_NAME_NOT_IN_USE: u8 = 2;    // class NONE, type ANY
+pub const DNS_PREREQ_RRSET_EXISTS: u8 = 3;       // class ANY, empty RDATA
+pub const DNS_PREREQ_RRSET_EXISTS_VALUE: u8 = 4; // zone class, with RDATA
+pub const DNS_PREREQ_RRSET_NOT_EXISTS: u8 = 5;   // class NONE, empty RDATA
+pub const DNS_UPDATE_ADD: u8 = 6;                // zone class
+pub const DNS_UPDATE_DELETE_RRSET: u8 = 7;       // class ANY
+pub const DNS_UPDATE_DELETE_NAME: u8 = 8;        // class ANY, type ANY: every RRset of the name
+pub const DNS_UPDATE_DELETE_RR: u8 = 9;          // class NONE: the one record
+
+const OPCODE_UPDATE: u16 = 5;
+const CLASS_NONE: u16 = 254;
+const CLASS_ANY: u16 = 255;
+const TYPE_ANY: u16 = 255;
+
+#[repr(C)]
+pub struct IrisDnsUpdateOp {
+    pub kind: u8,               // DNS_PREREQ_* or DNS_UPDATE_*
+    pub name: *mut c_char,
+    pub record_type: u16,       // 255 (ANY) for whole-name operations
+    pub rrclass: u16,           // as sent: zone class, 254 (NONE) or 255 (ANY)
+    pub ttl: u32,               // meaningful for DNS_UPDATE_ADD only
+    pub rdata: *mut u8,
+    pub rdata_len: usize,
+    pub display_value: *mut c_char,
+}
+
+#[repr(C)]
+pub struct IrisDnsUpdate {
+    pub id: u16,
+    pub is_response: bool,
+    pub response_code: u8,      // in responses: 6 YXDOMAIN, 7 YXRRSET, 8 NXRRSET, 9 NOTAUTH, 10 NOTZONE
+    pub zone: *mut c_char,      // NULL if the zone section is empty
+    pub zone_class: u16,
+    pub prerequisites: *mut IrisDnsUpdateOp,
+    pub prerequisites_count: usize,
+    pub updates: *mut IrisDnsUpdateOp,
+    pub updates_count: usize,
+    pub additional: *mut IrisDnsRecord, // e.g. the TSIG or GSS-TSIG signature
+    pub additional_count: usize,
+}
+
+pub struct Update {
+    pub id: u16,
+    pub flags: u16,
+    pub zone: Option<(String, u16)>,
+    pub prerequisites: Vec<(u8, DnsRR)>,
+    pub updates: Vec<(u8, DnsRR)>,
+    pub additional: Vec<DnsRR>,
+}
+
+/// RFC 2136 §2.4: what a prerequisite record asserts. `in_zone` tells whether a
+/// class is the zone's.
+fn prerequisite(rr: &DnsRR, in_zone: impl Fn(u16) -> bool) -> u8 {
+    if rr.ttl != 0 { return DNS_UPDATE_INVALID; }
+    match (rr.rclass, rr.rtype, rr.rdata.is_empty()) {
+        (CLASS_ANY, TYPE_ANY, true) => DNS_PREREQ_NAME_IN_USE,
+        (CLASS_ANY, _, true) => DNS_PREREQ_RRSET_EXISTS,
+        (CLASS_NONE, TYPE_ANY, true) => DNS_PREREQ_NAME_NOT_IN_USE,
+        (CLASS_NONE, _, true) => DNS_PREREQ_RRSET_NOT_EXISTS,
+        (c, t, _) if in_zone(c) && t != TYPE_ANY => DNS_PREREQ_RRSET_EXISTS_VALUE,
+        _ => DNS_UPDATE_INVALID,
+    }
+}
+
+/// RFC 2136 §2.5: what an update record changes.
+fn update(rr: &DnsRR, in_zone: impl Fn(u16) -> bool) -> u8 {
+    match (rr.rclass, rr.rtype, rr.ttl, rr.rdata.is_empty()) {
+        (c, t, ..) if in_zone(c) && t != TYPE_ANY => DNS_UPDATE_ADD,
+        (CLASS_ANY, TYPE_ANY, 0, true) => DNS_UPDATE_DELETE_NAME,
+        (CLASS_ANY, _, 0, true) => DNS_UPDATE_DELETE_RRSET,
+        (CLASS_NONE, t, 0, _) if t != TYPE_ANY => DNS_UPDATE_DELETE_RR,
+        _ => DNS_UPDATE_INVALID,
+    }
+}
+
+/// Parse an UPDATE message. Err(-3) another opcode, Err(-2) malformed.
+pub fn parse(data: &[u8]) -> Result<Update, i32> {
+    let (id, flags, zone) = dns::header_question(data).ok_or(-2)?;
+    if (flags >> 11) & 0xF != OPCODE_UPDATE { return Err(-3); }
+    let [prereqs, updates, additional] = dns::parse_record_sections(data).ok_or(-2)?;
+    let zone = zone.map(|(name, _, class)| (name, class));
+    // Without a zone (a FORMERR response may omit it) any real class is taken as the zone's
+    let zone_class = zone.as_ref().map(|z| z.1);
+    let in_zone = |c: u16| zone_class.map_or(c != CLASS_ANY && c != CLASS_NONE, |z| c == z);
+    Ok(Update {
+        id, flags, zone,
+        prerequisites: prereqs.into_iter().map(|rr| (prerequisite(&rr, in_zone), rr)).collect(),
+        updates: updates.into_iter().map(|rr| (update(&rr, in_zone), rr)).collect(),
+        additional,
+    })
+}
+
+fn ops(list: Vec<(u8, DnsRR)>) -> (*mut IrisDnsUpdateOp, usize) {
+    alloc_array(list.into_iter().map(|(kind, rr)| {
+        let (rdata, rdata_len) = alloc_bytes(&rr.rdata);
+        IrisDnsUpdateOp {
+            kind, name: to_cstr(&rr.name), record_type: rr.rtype, rrclass: rr.rclass, ttl: rr.ttl,
+            rdata, rdata_len, display_value: to_cstr(&rr.display),
+        }
+    }).collect())
+}
+
+fn free_ops(ptr: *mut IrisDnsUpdateOp, count: usize) {
+    if ptr.is_null() { return; }
+    for op in unsafe { std::slice::from_raw_parts(ptr, count) } {
+        free_cstr(op.name);
+        iris_free_bytes(op.rdata, op.rdata_len);
+        free_cstr(op.display_value);
+    }
+    free_array(ptr, count);
+}
+
+// --- FFI ---
+
+/// Parse a DNS UPDATE message. Returns 0=ok, -2=arg error or malformed,
+/// -3=not an UPDATE (opcode other than 5). Free with iris_dns_update_free.
+#[no_mangle]
+pub extern "C" fn iris_dns_parse_update(data: *const u8, len: usize, out: *mut IrisDnsUpdate) -> i32 {
+    if data.is_null() || out.is_null() { return -2; }
+    let u = match parse(unsafe { std::slice::from_raw_parts(data, len) }) {
+        Ok(u) => u,
+        Err(e) => return e,
+    };
+    let (zone, zone_class) = match &u.zone {
+        Some((name, class)) => (to_cstr(name), *class),
+        None => (std::ptr::null_mut(), 0),
+    };
+    let (prerequisites, prerequisites_count) = ops(u.prerequisites);
+    let (updates, updates_count) = ops(u.updates);
+    let (additional, additional_count) = dns::alloc_records(u.additional);
+    unsafe {
+        out.write(IrisDnsUpdate {
+            id: u.id, is_response: u.flags & 0x8000 != 0, response_code: (u.flags & 0xF) as u8,
+            zone, zone_class, prerequisites, prerequisites_count, updates, updates_count,
+            additional, additional_count,
+        });
+    }
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn iris_dns_update_free(u: *mut IrisDnsUpdate) {
+    if u.is_null() { return; }
+    let u = unsafe { &mut *u };
+    free_cstr(u.zone);
+    free_ops(u.prerequisites, u.prerequisites_count);
+    free_ops(u.updates, u.updates_count);
+    dns::free_records(u.additional, u.additional_count);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::{DnsWriter, SECTION_ADDITIONAL, SECTION_ANSWER, SECTION_AUTHORITY};
+
+    #[test]
+    fn ad_style_update() {
+        // What a Windows client sends to register its A record: the name must not
+        // be a CNAME, replace any A RRset, add the new address
+        let mut w = DnsWriter::new(0x1234, OPCODE_UPDATE << 11);
+        w.question("corp.example", 6, 1).unwrap();
+        w.record(SECTION_ANSWER, "host.corp.example", 5, CLASS_NONE, 0, |_| Ok(())).unwrap();
+        w.record(SECTION_ANSWER, "corp.example", 6, CLASS_ANY, 0, |_| Ok(())).unwrap();
+        w.record(SECTION_AUTHORITY, "host.corp.example", 1, CLASS_ANY, 0, |_| Ok(())).unwrap();
+        w.record(SECTION_AUTHORITY, "host.corp.example", 1, 1, 1200, |w| { w.bytes(&[10, 0, 0, 7]); Ok(()) }).unwrap();
+        w.record(SECTION_AUTHORITY, "old.corp.example", 12, CLASS_NONE, 0, |w| w.name("host.corp.example")).unwrap();
+        w.record(SECTION_AUTHORITY, "gone.corp.example", TYPE_ANY, CLASS_ANY, 0, |_| Ok(())).unwrap();
+        w.record(SECTION_AUTHORITY, "bad.corp.example", 1, CLASS_ANY, 60, |_| Ok(())).unwrap();
+        w.record(SECTION_ADDITIONAL, "key.", 250, CLASS_ANY, 0, |w| { w.bytes(&[0; 4]); Ok(()) }).unwrap();
+        let msg = w.finish();
+
+        let u = parse(&msg).unwrap();
+        assert_eq!(u.zone, Some(("corp.example".into(), 1)));
+        let kinds = |l: &[(u8, DnsRR)]| l.iter().map(|(k, _)| *k).collect::<Vec<_>>();
+        assert_eq!(kinds(&u.prerequisites), [DNS_PREREQ_RRSET_NOT_EXISTS, DNS_PREREQ_RRSET_EXISTS]);
+        assert_eq!(kinds(&u.updates), [
+            DNS_UPDATE_DELETE_RRSET, DNS_UPDATE_ADD, DNS_UPDATE_DELETE_RR, DNS_UPDATE_DELETE_NAME, DNS_UPDATE_INVALID,
+        ]);
+        // Empty RDATA is not read past; RDATA names are shown
+        assert_eq!(u.prerequisites[0].1.display, "");
+        assert_eq!(u.updates[2].1.display, "host.corp.example");
+        assert_eq!(u.additional.len(), 1);
+
+        let mut out = std::mem::MaybeUninit::<IrisDnsUpdate>::uninit();
+        assert_eq!(iris_dns_parse_update(msg.as_ptr(), msg.len(), out.as_mut_ptr()), 0);
+        let mut out = unsafe { out.assume_init() };
+        assert_eq!((out.updates_count, out.zone_class, out.is_response), (5, 1, false));
+        let add = unsafe { &*out.updates.add(1) };
+        assert_eq!((add.ttl, unsafe { std::slice::from_raw_parts(add.rdata, add.rdata_len) }), (1200, &[10, 0, 0, 7][..]));
+        iris_dns_update_free(&mut out);
+    }
+
+    #[test]
+    fn prerequisites_and_responses() {
+        let mut w = DnsWriter::new(1, OPCODE_UPDATE << 11 | 0x8000 | 8);
+        w.question("example.com", 6, 1).unwrap();
+        w.record(SECTION_ANSWER, "a.example.com", TYPE_ANY, CLASS_ANY, 0, |_| Ok(())).unwrap();
+        w.record(SECTION_ANSWER, "b.example.com", TYPE_ANY, CLASS_NONE, 0, |_| Ok(())).unwrap();
+        w.record(SECTION_ANSWER, "c.example.com", 1, 1, 0, |w| { w.bytes(&[192, 0, 2, 1]); Ok(()) }).unwrap();
+        w.record(SECTION_ANSWER, "d.example.com", 1, CLASS_ANY, 5, |_| Ok(())).unwrap();
+        w.record(SECTION_ANSWER, "e.example.com", 1, 3, 0, |w| { w.bytes(&[192, 0, 2, 1]); Ok(()) }).unwrap();
+        let u = parse(&w.finish()).unwrap();
+        let kinds: Vec<u8> = u.prerequisites.iter().map(|(k, _)| *k).collect();
+        assert_eq!(kinds, [
+            DNS_PREREQ_NAME_IN_USE, DNS_PREREQ_NAME_NOT_IN_USE, DNS_PREREQ_RRSET_EXISTS_VALUE,
+            DNS_UPDATE_INVALID, DNS_UPDATE_INVALID,
+        ]);
+        assert_eq!(u.flags & 0xF, 8); // NXRRSET
+
+        let query = dns::build_message(1, 0x0100, &[("example.com".into(), 1, 1)], &[]).unwrap();
+        assert_eq!(parse(&query).err(), Some(-3));
+        assert_eq!(parse(&[0; 5]).err(), Some(-2));
+    }
+}
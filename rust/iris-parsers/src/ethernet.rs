@@ -0,0 +1,243 @@
+//! Layer-2 frame parser: Ethernet II, 802.3/LLC/SNAP, 802.1Q and QinQ VLAN tags.
+//! Strips link-layer framing so packet-tap captures can be handed to L3 parsers.
+
+use crate::ffi::IrisSlice;
+use std::ffi::c_char;
+
+const ETH_HEADER_LEN: usize = 14;
+const MAX_VLAN_TAGS: usize = 2;
+
+pub const ETHERTYPE_IPV4: u16 = 0x0800;
+pub const ETHERTYPE_ARP: u16 = 0x0806;
+pub const ETHERTYPE_IPV6: u16 = 0x86DD;
+pub const ETHERTYPE_VLAN: u16 = 0x8100;
+pub const ETHERTYPE_QINQ: u16 = 0x88A8;
+pub const ETHERTYPE_QINQ_LEGACY: u16 = 0x9100;
+pub const ETHERTYPE_MPLS: u16 = 0x8847;
+pub const ETHERTYPE_MPLS_MCAST: u16 = 0x8848;
+pub const ETHERTYPE_PPPOE_DISC: u16 = 0x8863;
+pub const ETHERTYPE_PPPOE_SESS: u16 = 0x8864;
+pub const ETHERTYPE_EAPOL: u16 = 0x888E;
+pub const ETHERTYPE_LLDP: u16 = 0x88CC;
+
+/// Payload classification after EtherType dispatch.
+pub const L2_PAYLOAD_UNKNOWN: u8 = 0;
+pub const L2_PAYLOAD_IPV4: u8 = 1;
+pub const L2_PAYLOAD_IPV6: u8 = 2;
+pub const L2_PAYLOAD_ARP: u8 = 3;
+pub const L2_PAYLOAD_LLDP: u8 = 4;
+pub const L2_PAYLOAD_EAPOL: u8 = 5;
+pub const L2_PAYLOAD_MPLS: u8 = 6;
+pub const L2_PAYLOAD_PPPOE: u8 = 7;
+pub const L2_PAYLOAD_LLC: u8 = 8;
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct IrisVlanTag {
+    pub tpid: u16,
+    pub pcp: u8,  // priority code point (0-7)
+    pub dei: bool,
+    pub vid: u16, // 12-bit VLAN ID
+}
+
+#[repr(C)]
+pub struct IrisEthernetFrame {
+    pub dst_mac: [u8; 6],
+    pub src_mac: [u8; 6],
+    pub ethertype: u16,        // innermost EtherType (SNAP type for 802.3)
+    pub is_802_3: bool,        // length-framed 802.3 with LLC header
+    pub llc_dsap: u8,
+    pub llc_ssap: u8,
+    pub vlan_tags: [IrisVlanTag; MAX_VLAN_TAGS], // outermost first
+    pub vlan_count: u8,
+    pub vlan_overflow: bool,   // more than two stacked tags
+    pub dst_is_broadcast: bool,
+    pub dst_is_multicast: bool,
+    pub payload_kind: u8,      // L2_PAYLOAD_*
+    pub payload_offset: usize,
+    pub payload: IrisSlice,
+}
+
+/// Parsed frame with the payload as a byte range, for Rust-side callers.
+pub struct EthFrame {
+    pub dst: [u8; 6],
+    pub src: [u8; 6],
+    pub ethertype: u16,
+    pub is_802_3: bool,
+    pub llc: (u8, u8),
+    pub vlans: Vec<IrisVlanTag>,
+    pub payload_start: usize,
+    pub payload_end: usize,
+}
+
+pub fn payload_kind(ethertype: u16) -> u8 {
+    match ethertype {
+        ETHERTYPE_IPV4 => L2_PAYLOAD_IPV4,
+        ETHERTYPE_IPV6 => L2_PAYLOAD_IPV6,
+        ETHERTYPE_ARP => L2_PAYLOAD_ARP,
+        ETHERTYPE_LLDP => L2_PAYLOAD_LLDP,
+        ETHERTYPE_EAPOL => L2_PAYLOAD_EAPOL,
+        ETHERTYPE_MPLS | ETHERTYPE_MPLS_MCAST => L2_PAYLOAD_MPLS,
+        ETHERTYPE_PPPOE_DISC | ETHERTYPE_PPPOE_SESS => L2_PAYLOAD_PPPOE,
+        _ => L2_PAYLOAD_UNKNOWN,
+    }
+}
+
+fn is_vlan_tpid(t: u16) -> bool {
+    t == ETHERTYPE_VLAN || t == ETHERTYPE_QINQ || t == ETHERTYPE_QINQ_LEGACY
+}
+
+/// Parse an Ethernet frame (no preamble, FCS optional/ignored).
+pub fn parse_frame(data: &[u8]) -> Option<EthFrame> {
+    if data.len() < ETH_HEADER_LEN { return None; }
+    let mut dst = [0u8; 6];
+    let mut src = [0u8; 6];
+    dst.copy_from_slice(&data[0..6]);
+    src.copy_from_slice(&data[6..12]);
+
+    let mut off = 12usize;
+    let mut vlans = Vec::new();
+    let mut etype = u16::from_be_bytes([data[off], data[off + 1]]);
+    while is_vlan_tpid(etype) {
+        if off + 6 > data.len() { return None; }
+        let tci = u16::from_be_bytes([data[off + 2], data[off + 3]]);
+        vlans.push(IrisVlanTag {
+            tpid: etype, pcp: (tci >> 13) as u8, dei: tci & 0x1000 != 0, vid: tci & 0x0FFF,
+        });
+        off += 4;
+        etype = u16::from_be_bytes([data[off], data[off + 1]]);
+        if vlans.len() > 8 { return None; } // tag-stacking abuse
+    }
+    off += 2;
+
+    let mut end = data.len();
+    let mut is_802_3 = false;
+    let mut llc = (0u8, 0u8);
+    if etype <= 1500 {
+        // 802.3: EtherType field is a payload length, followed by LLC (and maybe SNAP)
+        is_802_3 = true;
+        end = (off + etype as usize).min(data.len());
+        if off + 3 > end { return None; }
+        llc = (data[off], data[off + 1]);
+        if llc == (0xAA, 0xAA) && data[off + 2] == 0x03 && off + 8 <= end {
+            etype = u16::from_be_bytes([data[off + 6], data[off + 7]]);
+            off += 8;
+        } else {
+            etype = 0;
+            off += 3;
+        }
+    }
+    Some(EthFrame { dst, src, ethertype: etype, is_802_3, llc, vlans,
+                    payload_start: off, payload_end: end })
+}
+
+// --- FFI entry points ---
+
+/// Parse an Ethernet frame. Returns 0=ok, -1=truncated, -2=arg error.
+/// `out.payload` points into `data` — keep it alive.
+#[no_mangle]
+pub extern "C" fn iris_eth_parse(data: *const u8, len: usize, out: *mut IrisEthernetFrame) -> i32 {
+    if data.is_null() || out.is_null() || len == 0 { return -2; }
+    let buf = unsafe { std::slice::from_raw_parts(data, len) };
+    let f = match parse_frame(buf) { Some(f) => f, None => return -1 };
+    let mut tags = [IrisVlanTag::default(); MAX_VLAN_TAGS];
+    for (slot, tag) in tags.iter_mut().zip(f.vlans.iter()) { *slot = *tag; }
+    let kind = if f.is_802_3 && f.ethertype == 0 { L2_PAYLOAD_LLC } else { payload_kind(f.ethertype) };
+    unsafe {
+        out.write(IrisEthernetFrame {
+            dst_mac: f.dst,
+            src_mac: f.src,
+            ethertype: f.ethertype,
+            is_802_3: f.is_802_3,
+            llc_dsap: f.llc.0,
+            llc_ssap: f.llc.1,
+            vlan_tags: tags,
+            vlan_count: f.vlans.len().min(MAX_VLAN_TAGS) as u8,
+            vlan_overflow: f.vlans.len() > MAX_VLAN_TAGS,
+            dst_is_broadcast: f.dst == [0xFF; 6],
+            dst_is_multicast: f.dst[0] & 0x01 != 0,
+            payload_kind: kind,
+            payload_offset: f.payload_start,
+            payload: IrisSlice::from_bytes(&buf[f.payload_start..f.payload_end]),
+        });
+    }
+    0
+}
+
+/// Canonical name for an EtherType. Returns a static string (do not free).
+#[no_mangle]
+pub extern "C" fn iris_ethertype_name(ethertype: u16) -> *const c_char {
+    let name: &'static [u8] = match ethertype {
+        ETHERTYPE_IPV4 => b"IPv4\0",
+        ETHERTYPE_ARP => b"ARP\0",
+        ETHERTYPE_IPV6 => b"IPv6\0",
+        ETHERTYPE_VLAN => b"802.1Q\0",
+        ETHERTYPE_QINQ | ETHERTYPE_QINQ_LEGACY => b"802.1ad\0",
+        ETHERTYPE_MPLS | ETHERTYPE_MPLS_MCAST => b"MPLS\0",
+        ETHERTYPE_PPPOE_DISC => b"PPPoE-Discovery\0",
+        ETHERTYPE_PPPOE_SESS => b"PPPoE-Session\0",
+        ETHERTYPE_EAPOL => b"EAPOL\0",
+        ETHERTYPE_LLDP => b"LLDP\0",
+        0x8035 => b"RARP\0",
+        0x88E5 => b"MACsec\0",
+        0x88F7 => b"PTP\0",
+        0x8809 => b"LACP\0",
+        _ => b"Unknown\0",
+    };
+    name.as_ptr() as *const c_char
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MACS: [u8; 12] = [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00, 0x11, 0x22, 0x33, 0x44, 0x55];
+
+    #[test]
+    fn parse_plain_ipv4_frame() {
+        let mut f = MACS.to_vec();
+        f.extend_from_slice(&[0x08, 0x00, 0x45, 0x00]);
+        let mut out = std::mem::MaybeUninit::<IrisEthernetFrame>::uninit();
+        assert_eq!(iris_eth_parse(f.as_ptr(), f.len(), out.as_mut_ptr()), 0);
+        let out = unsafe { out.assume_init() };
+        assert_eq!(out.ethertype, ETHERTYPE_IPV4);
+        assert_eq!(out.payload_kind, L2_PAYLOAD_IPV4);
+        assert_eq!(out.vlan_count, 0);
+        assert!(out.dst_is_broadcast);
+        assert_eq!(out.payload_offset, 14);
+        assert_eq!(out.payload.len, 2);
+    }
+
+    #[test]
+    fn parse_qinq_frame() {
+        let mut f = MACS.to_vec();
+        f.extend_from_slice(&[0x88, 0xA8, 0x00, 0x64]); // S-tag VID 100
+        f.extend_from_slice(&[0x81, 0x00, 0xA0, 0xC8]); // C-tag PCP 5, VID 200
+        f.extend_from_slice(&[0x86, 0xDD, 0x60]);
+        let r = parse_frame(&f).unwrap();
+        assert_eq!(r.vlans.len(), 2);
+        assert_eq!(r.vlans[0].vid, 100);
+        assert_eq!(r.vlans[1].vid, 200);
+        assert_eq!(r.vlans[1].pcp, 5);
+        assert_eq!(r.ethertype, ETHERTYPE_IPV6);
+        assert_eq!(r.payload_start, 22);
+    }
+
+    #[test]
+    fn parse_802_3_snap_frame() {
+        let mut f = MACS.to_vec();
+        f.extend_from_slice(&[0x00, 0x0A, 0xAA, 0xAA, 0x03, 0, 0, 0, 0x08, 0x06, 0x00, 0x01]);
+        f.extend_from_slice(&[0; 8]); // padding beyond the 802.3 length
+        let r = parse_frame(&f).unwrap();
+        assert!(r.is_802_3);
+        assert_eq!(r.ethertype, ETHERTYPE_ARP);
+        assert_eq!(r.payload_end - r.payload_start, 2);
+    }
+
+    #[test]
+    fn truncated_vlan_rejected() {
+        let mut f = MACS.to_vec();
+        f.extend_from_slice(&[0x81, 0x00, 0x00]);
+        assert!(parse_frame(&f).is_none());
+    }
+}
@@ -0,0 +1,409 @@
+//! Browser extensions on disk: WebExtensions (manifest.json, MV2 and MV3, as used by
+//! Chromium browsers, Firefox and Safari web extensions) and legacy Safari app
+//! extensions (.appex Info.plist). Lists the permissions and host access requested,
+//! content scripts and background code, hashes every bundled file, and scores the
+//! capabilities that matter for data theft or persistence.
+
+use crate::ffi::{alloc_array, free_array, free_c_string_array, free_cstr, to_cstr, vec_to_c_string_array, IrisCStringArray};
+use crate::hash::{sha256_digest, to_hex};
+use crate::json::{self, Value};
+use crate::plist::{self, Plist};
+use std::ffi::{c_char, CStr};
+use std::path::Path;
+
+const MAX_FILES: usize = 10_000;
+const MAX_HASHED_SIZE: u64 = 64 << 20;
+const MAX_DEPTH: usize = 16;
+
+const STORE_UPDATE_URLS: &[&str] = &[
+    "https://clients2.google.com/service/update2/crx",
+    "https://edge.microsoft.com/extensionwebstorebase/v1/crx",
+];
+const ALL_HOSTS: &[&str] = &["<all_urls>", "*://*/*", "http://*/*", "https://*/*", "file:///*"];
+
+pub const EXT_KIND_WEB: u8 = 1;         // manifest.json WebExtension
+pub const EXT_KIND_SAFARI_APP: u8 = 2;  // Safari app extension (com.apple.Safari.extension)
+
+pub const EXT_FLAG_ALL_URLS: u32 = 0x001;         // host or content-script access to every site
+pub const EXT_FLAG_NATIVE_MESSAGING: u32 = 0x002; // can exchange messages with a local binary
+pub const EXT_FLAG_COOKIES: u32 = 0x004;
+pub const EXT_FLAG_WEB_REQUEST: u32 = 0x008;      // can observe or rewrite requests
+pub const EXT_FLAG_DEBUGGER: u32 = 0x010;         // chrome.debugger: full DevTools protocol access
+pub const EXT_FLAG_PROXY: u32 = 0x020;
+pub const EXT_FLAG_BROWSING_DATA: u32 = 0x040;    // history, tabs, browsingData, downloads
+pub const EXT_FLAG_MANAGEMENT: u32 = 0x080;       // can disable or inspect other extensions
+pub const EXT_FLAG_REMOTE_CODE: u32 = 0x100;      // CSP allows eval or remote script sources
+pub const EXT_FLAG_EXTERNAL_UPDATE: u32 = 0x200;  // update_url outside the browser stores
+pub const EXT_FLAG_CLIPBOARD: u32 = 0x400;        // clipboardRead
+
+const FLAG_WEIGHTS: &[(u32, u8)] = &[
+    (EXT_FLAG_ALL_URLS, 25),
+    (EXT_FLAG_NATIVE_MESSAGING, 20),
+    (EXT_FLAG_COOKIES, 15),
+    (EXT_FLAG_WEB_REQUEST, 15),
+    (EXT_FLAG_DEBUGGER, 30),
+    (EXT_FLAG_PROXY, 20),
+    (EXT_FLAG_BROWSING_DATA, 10),
+    (EXT_FLAG_MANAGEMENT, 15),
+    (EXT_FLAG_REMOTE_CODE, 25),
+    (EXT_FLAG_EXTERNAL_UPDATE, 20),
+    (EXT_FLAG_CLIPBOARD, 10),
+];
+
+const PERMISSION_FLAGS: &[(&str, u32)] = &[
+    ("nativeMessaging", EXT_FLAG_NATIVE_MESSAGING),
+    ("cookies", EXT_FLAG_COOKIES),
+    ("webRequest", EXT_FLAG_WEB_REQUEST),
+    ("webRequestBlocking", EXT_FLAG_WEB_REQUEST),
+    ("declarativeNetRequest", EXT_FLAG_WEB_REQUEST),
+    ("debugger", EXT_FLAG_DEBUGGER),
+    ("proxy", EXT_FLAG_PROXY),
+    ("history", EXT_FLAG_BROWSING_DATA),
+    ("tabs", EXT_FLAG_BROWSING_DATA),
+    ("browsingData", EXT_FLAG_BROWSING_DATA),
+    ("downloads", EXT_FLAG_BROWSING_DATA),
+    ("management", EXT_FLAG_MANAGEMENT),
+    ("clipboardRead", EXT_FLAG_CLIPBOARD),
+];
+
+#[repr(C)]
+pub struct IrisExtensionFile {
+    pub path: *mut c_char,  // relative to the extension root
+    pub size: u64,
+    pub sha256: *mut c_char, // hex, "" if the file was too large or unreadable
+}
+
+#[repr(C)]
+pub struct IrisExtension {
+    pub kind: u8,                           // EXT_KIND_*
+    pub name: *mut c_char,                  // __MSG_*__ names resolved from _locales
+    pub version: *mut c_char,
+    pub identifier: *mut c_char,            // gecko id or bundle identifier, "" if none
+    pub manifest_version: u32,
+    pub permissions: IrisCStringArray,      // API permissions
+    pub host_permissions: IrisCStringArray, // match patterns / Safari allowed domains
+    pub content_matches: IrisCStringArray,  // content-script match patterns
+    pub content_scripts: IrisCStringArray,  // content-script files
+    pub background: IrisCStringArray,       // background scripts, page or service worker
+    pub update_url: *mut c_char,
+    pub files: *mut IrisExtensionFile,
+    pub file_count: usize,
+    pub flags: u32,                         // EXT_FLAG_*
+    pub risk: u8,                           // 0-100
+}
+
+#[derive(Default)]
+struct Ext {
+    kind: u8,
+    name: String,
+    version: String,
+    identifier: String,
+    manifest_version: u32,
+    permissions: Vec<String>,
+    hosts: Vec<String>,
+    matches: Vec<String>,
+    scripts: Vec<String>,
+    background: Vec<String>,
+    update_url: String,
+    flags: u32,
+}
+
+fn strs(v: Option<&Value>) -> Vec<String> {
+    v.and_then(Value::as_array).unwrap_or(&[]).iter().filter_map(Value::as_str).map(str::to_string).collect()
+}
+
+/// Resolve "__MSG_key__" from _locales/<default_locale>/messages.json (keys are case-insensitive).
+fn localized(root: &Path, locale: &str, s: &str) -> String {
+    let Some(key) = s.strip_prefix("__MSG_").and_then(|k| k.strip_suffix("__")) else { return s.to_string() };
+    let messages = std::fs::read(root.join("_locales").join(locale).join("messages.json")).ok().and_then(|d| json::parse(&d));
+    let found = match &messages {
+        Some(Value::Object(m)) => m.iter().find(|(k, _)| k.eq_ignore_ascii_case(key)).and_then(|(_, v)| v.get("message")?.as_str()),
+        _ => None,
+    };
+    found.unwrap_or(s).to_string()
+}
+
+fn csp_allows_remote(csp: &str) -> bool {
+    csp.split(';').filter(|d| d.trim_start().starts_with("script-src")).any(|d| {
+        d.split_whitespace().skip(1).any(|src| src == "'unsafe-eval'" || src.starts_with("http:") || src.starts_with("https:") || src == "*")
+    })
+}
+
+fn web_extension(root: &Path, m: &Value) -> Ext {
+    let s = |k: &str| m.get(k).and_then(Value::as_str).unwrap_or("").to_string();
+    let locale = m.get("default_locale").and_then(Value::as_str).unwrap_or("en");
+    let mut e = Ext {
+        kind: EXT_KIND_WEB,
+        name: localized(root, locale, &s("name")),
+        version: s("version"),
+        identifier: m.pointer("/browser_specific_settings/gecko/id").or_else(|| m.pointer("/applications/gecko/id"))
+            .and_then(Value::as_str).unwrap_or("").to_string(),
+        manifest_version: m.get("manifest_version").and_then(Value::as_f64).unwrap_or(0.0) as u32,
+        update_url: s("update_url"),
+        ..Ext::default()
+    };
+    // MV2 mixes host patterns into "permissions"
+    for p in strs(m.get("permissions")) {
+        if p.contains("://") || p == "<all_urls>" { e.hosts.push(p) } else { e.permissions.push(p) }
+    }
+    e.hosts.extend(strs(m.get("host_permissions")));
+    for cs in m.get("content_scripts").and_then(Value::as_array).unwrap_or(&[]) {
+        e.matches.extend(strs(cs.get("matches")));
+        e.scripts.extend(strs(cs.get("js")));
+    }
+    if let Some(bg) = m.get("background") {
+        e.background.extend(strs(bg.get("scripts")));
+        for k in ["page", "service_worker"] {
+            if let Some(p) = bg.get(k).and_then(Value::as_str) { e.background.push(p.to_string()); }
+        }
+    }
+    let csp = match m.get("content_security_policy") {
+        Some(Value::String(c)) => c.clone(),
+        Some(c) => c.get("extension_pages").and_then(Value::as_str).unwrap_or("").to_string(),
+        None => String::new(),
+    };
+    if csp_allows_remote(&csp) { e.flags |= EXT_FLAG_REMOTE_CODE; }
+    if !e.update_url.is_empty() && !STORE_UPDATE_URLS.contains(&e.update_url.as_str()) { e.flags |= EXT_FLAG_EXTERNAL_UPDATE; }
+    e
+}
+
+fn safari_app_extension(info: &Plist, ext: &Plist) -> Ext {
+    let s = |p: &Plist, k: &str| p.get(k).and_then(Plist::as_str).unwrap_or("").to_string();
+    let mut e = Ext {
+        kind: EXT_KIND_SAFARI_APP,
+        name: Some(s(info, "CFBundleDisplayName")).filter(|n| !n.is_empty()).unwrap_or_else(|| s(info, "CFBundleName")),
+        version: s(info, "CFBundleShortVersionString"),
+        identifier: s(info, "CFBundleIdentifier"),
+        ..Ext::default()
+    };
+    let arr = |k: &str| ext.get(k).and_then(Plist::as_array).unwrap_or(&[]);
+    e.scripts = arr("SFSafariContentScript").iter().map(|c| s(c, "Script")).filter(|c| !c.is_empty()).collect();
+    if let Some(access) = ext.get("SFSafariWebsiteAccess") {
+        if access.get("Level").and_then(Plist::as_str) == Some("All") { e.hosts.push("<all_urls>".into()); }
+        e.hosts.extend(access.get("Allowed Domains").and_then(Plist::as_array).unwrap_or(&[]).iter()
+            .filter_map(Plist::as_str).map(str::to_string));
+    }
+    e.matches = e.hosts.clone();
+    e
+}
+
+fn hash_files(root: &Path, dir: &Path, depth: usize, out: &mut Vec<IrisExtensionFile>) {
+    if depth > MAX_DEPTH { return; }
+    let Ok(rd) = std::fs::read_dir(dir) else { return };
+    let mut entries: Vec<_> = rd.flatten().collect();
+    entries.sort_by_key(|e| e.file_name());
+    for e in entries {
+        if out.len() >= MAX_FILES { return; }
+        let Ok(ft) = e.file_type() else { continue };
+        let path = e.path();
+        if ft.is_dir() {
+            hash_files(root, &path, depth + 1, out);
+        } else if ft.is_file() {
+            let size = e.metadata().map(|m| m.len()).unwrap_or(0);
+            let sha256 = if size <= MAX_HASHED_SIZE { std::fs::read(&path).map(|d| to_hex(&sha256_digest(&d))).unwrap_or_default() } else { String::new() };
+            let rel = path.strip_prefix(root).unwrap_or(&path).to_string_lossy().into_owned();
+            out.push(IrisExtensionFile { path: to_cstr(&rel), size, sha256: to_cstr(&sha256) });
+        }
+    }
+}
+
+fn risk(flags: u32) -> u8 {
+    FLAG_WEIGHTS.iter().filter(|(bit, _)| flags & bit != 0).fold(0u32, |r, (_, w)| r + *w as u32).min(100) as u8
+}
+
+/// Analyse the extension rooted at `root`: an unpacked WebExtension directory (e.g.
+/// a Chrome profile's Extensions/<id>/<version>), or an .appex bundle.
+fn analyze(root: &Path) -> Result<(Ext, Vec<IrisExtensionFile>), i32> {
+    let manifest = root.join("manifest.json");
+    let info_path = root.join("Contents/Info.plist");
+    let mut e = if manifest.is_file() {
+        let d = std::fs::read(&manifest).map_err(|_| -1)?;
+        web_extension(root, &json::parse(&d).ok_or(-2)?)
+    } else if info_path.is_file() {
+        let info = plist::parse(&std::fs::read(&info_path).map_err(|_| -1)?).ok_or(-2)?;
+        let ext = info.get("NSExtension").ok_or(-3)?;
+        match ext.get("NSExtensionPointIdentifier").and_then(Plist::as_str) {
+            Some("com.apple.Safari.web-extension") => {
+                let resources = root.join("Contents/Resources");
+                let d = std::fs::read(resources.join("manifest.json")).map_err(|_| -1)?;
+                let mut e = web_extension(&resources, &json::parse(&d).ok_or(-2)?);
+                e.identifier = info.get("CFBundleIdentifier").and_then(Plist::as_str).unwrap_or("").to_string();
+                e
+            }
+            Some("com.apple.Safari.extension") => safari_app_extension(&info, ext),
+            _ => return Err(-3),
+        }
+    } else {
+        return Err(-3);
+    };
+    for p in &e.permissions {
+        e.flags |= PERMISSION_FLAGS.iter().filter(|(name, _)| name == p).fold(0, |f, (_, bit)| f | bit);
+    }
+    if e.hosts.iter().chain(&e.matches).any(|h| ALL_HOSTS.contains(&h.as_str())) { e.flags |= EXT_FLAG_ALL_URLS; }
+    let mut files = Vec::new();
+    hash_files(root, root, 0, &mut files);
+    Ok((e, files))
+}
+
+// --- FFI entry points ---
+
+/// Analyse a browser extension directory: an unpacked WebExtension (manifest.json,
+/// e.g. a Chrome profile's Extensions/<id>/<version>) or a Safari .appex bundle.
+/// Returns 0=ok, -1=unreadable, -2=arg error or unparseable manifest, -3=no extension
+/// manifest found. Free with iris_extension_free.
+#[no_mangle]
+pub extern "C" fn iris_extension_analyze(path: *const c_char, out: *mut IrisExtension) -> i32 {
+    if path.is_null() || out.is_null() { return -2; }
+    let Ok(path) = unsafe { CStr::from_ptr(path) }.to_str() else { return -2 };
+    let (e, files) = match analyze(Path::new(path)) {
+        Ok(x) => x,
+        Err(rc) => return rc,
+    };
+    let (files, file_count) = alloc_array(files);
+    unsafe {
+        out.write(IrisExtension {
+            kind: e.kind,
+            name: to_cstr(&e.name),
+            version: to_cstr(&e.version),
+            identifier: to_cstr(&e.identifier),
+            manifest_version: e.manifest_version,
+            permissions: vec_to_c_string_array(e.permissions),
+            host_permissions: vec_to_c_string_array(e.hosts),
+            content_matches: vec_to_c_string_array(e.matches),
+            content_scripts: vec_to_c_string_array(e.scripts),
+            background: vec_to_c_string_array(e.background),
+            update_url: to_cstr(&e.update_url),
+            files,
+            file_count,
+            flags: e.flags,
+            risk: risk(e.flags),
+        });
+    }
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn iris_extension_free(e: *mut IrisExtension) {
+    if e.is_null() { return; }
+    let e = unsafe { &*e };
+    for p in [e.name, e.version, e.identifier, e.update_url] { free_cstr(p); }
+    for a in [&e.permissions, &e.host_permissions, &e.content_matches, &e.content_scripts, &e.background] { free_c_string_array(a); }
+    for i in 0..e.file_count {
+        let f = unsafe { &*e.files.add(i) };
+        free_cstr(f.path);
+        free_cstr(f.sha256);
+    }
+    free_array(e.files, e.file_count);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CString;
+
+    fn s(p: *mut c_char) -> String {
+        unsafe { CStr::from_ptr(p) }.to_string_lossy().into_owned()
+    }
+
+    fn strings(a: &IrisCStringArray) -> Vec<String> {
+        (0..a.count).map(|i| s(unsafe { *a.items.add(i) })).collect()
+    }
+
+    fn analyze_dir(dir: &Path) -> Result<IrisExtension, i32> {
+        let p = CString::new(dir.to_str().unwrap()).unwrap();
+        let mut out = std::mem::MaybeUninit::<IrisExtension>::uninit();
+        match iris_extension_analyze(p.as_ptr(), out.as_mut_ptr()) {
+            0 => Ok(unsafe { out.assume_init() }),
+            rc => Err(rc),
+        }
+    }
+
+    #[test]
+    fn web_and_safari_extensions() {
+        let root = std::env::temp_dir().join(format!("iris-ext-{}", std::process::id()));
+        let web = root.join("web");
+        std::fs::create_dir_all(web.join("_locales/en")).unwrap();
+        std::fs::write(web.join("manifest.json"), r#"{
+  "manifest_version": 2, "name": "__MSG_appName__", "version": "1.4.2", "default_locale": "en",
+  "permissions": ["cookies", "nativeMessaging", "storage", "<all_urls>"],
+  "background": {"scripts": ["bg.js"]},
+  "content_scripts": [{"matches": ["https://*.example.com/*"], "js": ["inject.js"]}],
+  "content_security_policy": "script-src 'self' https://cdn.example.net; object-src 'self'",
+  "update_url": "https://updates.example.net/crx"
+}"#).unwrap();
+        std::fs::write(web.join("_locales/en/messages.json"), r#"{"APPNAME": {"message": "Coupon Helper"}}"#).unwrap();
+        std::fs::write(web.join("bg.js"), "chrome.runtime.connectNative('x');").unwrap();
+
+        let mut e = analyze_dir(&web).unwrap();
+        assert_eq!((e.kind, s(e.name), s(e.version), e.manifest_version), (EXT_KIND_WEB, "Coupon Helper".into(), "1.4.2".into(), 2));
+        assert_eq!(strings(&e.permissions), ["cookies", "nativeMessaging", "storage"]);
+        assert_eq!((strings(&e.host_permissions), strings(&e.content_scripts), strings(&e.background)),
+                   (vec!["<all_urls>".to_string()], vec!["inject.js".to_string()], vec!["bg.js".to_string()]));
+        let all = EXT_FLAG_ALL_URLS | EXT_FLAG_COOKIES | EXT_FLAG_NATIVE_MESSAGING | EXT_FLAG_REMOTE_CODE | EXT_FLAG_EXTERNAL_UPDATE;
+        assert_eq!((e.flags, e.risk), (all, 100));
+        let files = unsafe { std::slice::from_raw_parts(e.files, e.file_count) };
+        let names: Vec<String> = files.iter().map(|f| s(f.path)).collect();
+        assert_eq!(names, ["_locales/en/messages.json", "bg.js", "manifest.json"]);
+        assert_eq!(s(files[1].sha256), to_hex(&sha256_digest(b"chrome.runtime.connectNative('x');")));
+        iris_extension_free(&mut e);
+
+        let appex = root.join("Blocker.appex");
+        std::fs::create_dir_all(appex.join("Contents/Resources")).unwrap();
+        std::fs::write(appex.join("Contents/Info.plist"), r#"<plist version="1.0"><dict>
+<key>CFBundleIdentifier</key><string>com.example.Blocker.Extension</string><key>CFBundleName</key><string>Blocker</string>
+<key>NSExtension</key><dict><key>NSExtensionPointIdentifier</key><string>com.apple.Safari.extension</string>
+<key>SFSafariContentScript</key><array><dict><key>Script</key><string>script.js</string></dict></array>
+<key>SFSafariWebsiteAccess</key><dict><key>Level</key><string>All</string></dict></dict></dict></plist>"#).unwrap();
+        let mut e = analyze_dir(&appex).unwrap();
+        assert_eq!((e.kind, s(e.identifier), e.flags, e.file_count), (EXT_KIND_SAFARI_APP, "com.example.Blocker.Extension".into(), EXT_FLAG_ALL_URLS, 1));
+        assert_eq!(strings(&e.content_scripts), ["script.js"]);
+        iris_extension_free(&mut e);
+
+        assert_eq!(analyze_dir(&root).err(), Some(-3));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn truncated_and_mistyped_manifests() {
+        let root = std::env::temp_dir().join(format!("iris-ext-bad-{}", std::process::id()));
+        let web = root.join("web");
+        std::fs::create_dir_all(&web).unwrap();
+        std::fs::write(web.join("manifest.json"), r#"{"manifest_version": 3, "name": "Cut"#).unwrap();
+        assert_eq!(analyze_dir(&web).err(), Some(-2));
+
+        // Fields of the wrong type read as absent
+        std::fs::write(web.join("manifest.json"), r#"{"manifest_version": "3", "name": 7, "version": ["1"],
+  "permissions": "cookies", "host_permissions": [1, "<all_urls>", null], "content_scripts": {"js": ["x.js"]},
+  "background": "bg.js", "content_security_policy": 5, "update_url": false}"#).unwrap();
+        let mut e = analyze_dir(&web).unwrap();
+        assert_eq!((s(e.name), s(e.version), e.manifest_version, s(e.update_url)), (String::new(), String::new(), 0, String::new()));
+        assert_eq!((e.permissions.count, e.content_scripts.count, e.background.count), (0, 0, 0));
+        assert_eq!(strings(&e.host_permissions), ["<all_urls>"]);
+        assert_eq!(e.flags, EXT_FLAG_ALL_URLS);
+        iris_extension_free(&mut e);
+
+        let appex = root.join("Bad.appex");
+        std::fs::create_dir_all(appex.join("Contents/Resources")).unwrap();
+        let info = appex.join("Contents/Info.plist");
+        std::fs::write(&info, r#"<plist version="1.0"><dict><key>NSExtension</key><dict>"#).unwrap();
+        assert_eq!(analyze_dir(&appex).err(), Some(-2));
+        std::fs::write(&info, r#"<plist version="1.0"><dict><key>CFBundleName</key><string>x</string></dict></plist>"#).unwrap();
+        assert_eq!(analyze_dir(&appex).err(), Some(-3));
+        std::fs::write(&info, r#"<plist version="1.0"><dict><key>NSExtension</key><dict><key>NSExtensionPointIdentifier</key>
+<string>com.apple.share-services</string></dict></dict></plist>"#).unwrap();
+        assert_eq!(analyze_dir(&appex).err(), Some(-3));
+        // Safari web extension whose bundled manifest is missing, then cut short
+        std::fs::write(&info, r#"<plist version="1.0"><dict><key>NSExtension</key><dict><key>NSExtensionPointIdentifier</key>
+<string>com.apple.Safari.web-extension</string></dict></dict></plist>"#).unwrap();
+        assert_eq!(analyze_dir(&appex).err(), Some(-1));
+        std::fs::write(appex.join("Contents/Resources/manifest.json"), "{\"name\": [").unwrap();
+        assert_eq!(analyze_dir(&appex).err(), Some(-2));
+
+        std::fs::remove_dir_all(&root).unwrap();
+        assert_eq!(analyze_dir(&web).err(), Some(-3));
+        let mut out = std::mem::MaybeUninit::<IrisExtension>::uninit();
+        assert_eq!(iris_extension_analyze(std::ptr::null(), out.as_mut_ptr()), -2);
+        assert_eq!(iris_extension_analyze(c"/tmp".as_ptr(), std::ptr::null_mut()), -2);
+        assert_eq!(iris_extension_analyze(c"/\xff".as_ptr(), out.as_mut_ptr()), -2);
+    }
+}
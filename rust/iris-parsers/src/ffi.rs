@@ -2,6 +2,20 @@
 
 use std::ffi::{CString, c_char};
 
+/// A borrowed slice (pointer + length) into the caller's buffer.
+/// Valid only while the original data buffer is alive.
+#[repr(C)]
+pub struct IrisSlice {
+    pub ptr: *const u8,
+    pub len: usize,
+}
+
+impl IrisSlice {
+    pub fn from_bytes(b: &[u8]) -> Self {
+        IrisSlice { ptr: b.as_ptr(), len: b.len() }
+    }
+}
+
 /// Array of owned null-terminated C strings, passed across FFI.
 #[repr(C)]
 pub struct IrisCStringArray {
@@ -9,6 +23,16 @@ pub struct IrisCStringArray {
     pub count: usize,
 }
 
+/// Allocate an owned C string. Interior NULs yield an empty string.
+pub fn to_cstr(s: &str) -> *mut c_char {
+    CString::new(s).unwrap_or_else(|_| CString::new("").unwrap()).into_raw()
+}
+
+/// Free a string produced by `to_cstr` (null-safe).
+pub fn free_cstr(p: *mut c_char) {
+    if !p.is_null() { unsafe { drop(CString::from_raw(p)); } }
+}
+
 pub fn vec_to_c_string_array(strings: Vec<String>) -> IrisCStringArray {
     let count = strings.len();
     if count == 0 {
@@ -38,6 +62,26 @@ pub fn free_c_string_array(arr: &IrisCStringArray) {
     unsafe { std::alloc::dealloc(arr.items as *mut u8, layout); }
 }
 
+/// Move a Vec of plain `#[repr(C)]` values into a malloc-style array for FFI.
+/// Free with `free_array::<T>` (element fields are not dropped).
+pub fn alloc_array<T>(items: Vec<T>) -> (*mut T, usize) {
+    let count = items.len();
+    if count == 0 { return (std::ptr::null_mut(), 0); }
+    let layout = std::alloc::Layout::array::<T>(count).unwrap();
+    let ptr = unsafe { std::alloc::alloc(layout) as *mut T };
+    if ptr.is_null() { return (std::ptr::null_mut(), 0); }
+    for (i, item) in items.into_iter().enumerate() {
+        unsafe { ptr.add(i).write(item); }
+    }
+    (ptr, count)
+}
+
+pub fn free_array<T>(ptr: *mut T, count: usize) {
+    if ptr.is_null() || count == 0 { return; }
+    let layout = std::alloc::Layout::array::<T>(count).unwrap();
+    unsafe { std::alloc::dealloc(ptr as *mut u8, layout); }
+}
+
 /// Allocate a copy of `data` on the heap. Caller frees with iris_free_bytes.
 pub fn alloc_bytes(data: &[u8]) -> (*mut u8, usize) {
     if data.is_empty() {
@@ -0,0 +1,351 @@
+//! Bidirectional 5-tuple flow table: packet/byte counters per direction, TCP
+//! connection state, and the application protocol from identify.rs. The table is
+//! internally locked, so it can be fed and snapshotted from different threads.
+
+use crate::ffi::{alloc_array, free_array};
+use crate::identify::{self, IrisProtocolMatch};
+use crate::ip::{self, IrisIpAddr, PROTO_TCP, PROTO_UDP};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+pub const FLOW_TCP_NONE: u8 = 0;        // not TCP
+pub const FLOW_TCP_SYN_SENT: u8 = 1;
+pub const FLOW_TCP_SYN_RECEIVED: u8 = 2;
+pub const FLOW_TCP_ESTABLISHED: u8 = 3;
+pub const FLOW_TCP_CLOSING: u8 = 4;     // FIN from one side
+pub const FLOW_TCP_CLOSED: u8 = 5;      // FIN from both sides
+pub const FLOW_TCP_RESET: u8 = 6;
+pub const FLOW_TCP_MIDSTREAM: u8 = 7;   // first packet seen was not a SYN
+
+const TCP_FIN: u8 = 0x01;
+const TCP_SYN: u8 = 0x02;
+const TCP_RST: u8 = 0x04;
+const TCP_ACK: u8 = 0x10;
+
+/// Payload packets inspected before settling on a port-only protocol guess.
+const MAX_IDENTIFY_ATTEMPTS: u8 = 4;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct IrisFlowRecord {
+    pub id: u64,                 // stable for the flow's lifetime
+    pub ip_protocol: u8,         // PROTO_TCP, PROTO_UDP, ...
+    pub client: IrisIpAddr,      // SYN sender, else sender of the first packet seen
+    pub client_port: u16,
+    pub server: IrisIpAddr,
+    pub server_port: u16,
+    pub first_seen_us: i64,
+    pub last_seen_us: i64,
+    pub packets_c2s: u64,
+    pub packets_s2c: u64,
+    pub bytes_c2s: u64,          // IP-layer bytes
+    pub bytes_s2c: u64,
+    pub payload_bytes_c2s: u64,  // transport payload only
+    pub payload_bytes_s2c: u64,
+    pub tcp_state: u8,           // FLOW_TCP_*
+    pub app: IrisProtocolMatch,  // from the first identifiable payload
+}
+
+#[repr(C)]
+pub struct IrisFlowSnapshot {
+    pub flows: *mut IrisFlowRecord,
+    pub count: usize,
+}
+
+/// Direction-independent key: the lower (addr, port) endpoint comes first.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct FlowKey { proto: u8, a: (IrisIpAddr, u16), b: (IrisIpAddr, u16) }
+
+impl FlowKey {
+    fn new(proto: u8, src: (IrisIpAddr, u16), dst: (IrisIpAddr, u16)) -> Self {
+        let ord = |e: &(IrisIpAddr, u16)| (e.0.family, e.0.bytes, e.1);
+        if ord(&src) <= ord(&dst) { FlowKey { proto, a: src, b: dst } } else { FlowKey { proto, a: dst, b: src } }
+    }
+}
+
+struct Flow {
+    rec: IrisFlowRecord,
+    fin_c2s: bool,
+    fin_s2c: bool,
+    identify_attempts: u8,
+}
+
+struct Table {
+    flows: HashMap<FlowKey, Flow>,
+    next_id: u64,
+    idle_timeout_us: i64,
+    max_flows: usize,
+}
+
+/// Opaque flow table. Create with iris_flow_table_new.
+pub struct IrisFlowTable { inner: Mutex<Table> }
+
+struct Segment<'a> { src_port: u16, dst_port: u16, tcp_flags: u8, payload: &'a [u8] }
+
+fn transport(proto: u8, seg: &[u8]) -> Option<Segment<'_>> {
+    match proto {
+        PROTO_TCP => {
+            if seg.len() < 20 { return None; }
+            let off = ((seg[12] >> 4) as usize * 4).clamp(20, seg.len());
+            Some(Segment {
+                src_port: u16::from_be_bytes([seg[0], seg[1]]),
+                dst_port: u16::from_be_bytes([seg[2], seg[3]]),
+                tcp_flags: seg[13],
+                payload: &seg[off..],
+            })
+        }
+        PROTO_UDP => {
+            if seg.len() < 8 { return None; }
+            Some(Segment {
+                src_port: u16::from_be_bytes([seg[0], seg[1]]),
+                dst_port: u16::from_be_bytes([seg[2], seg[3]]),
+                tcp_flags: 0,
+                payload: &seg[8..],
+            })
+        }
+        _ => Some(Segment { src_port: 0, dst_port: 0, tcp_flags: 0, payload: seg }),
+    }
+}
+
+fn advance_tcp(f: &mut Flow, from_client: bool, flags: u8) {
+    let s = &mut f.rec.tcp_state;
+    if flags & TCP_RST != 0 { *s = FLOW_TCP_RESET; return; }
+    match *s {
+        FLOW_TCP_SYN_SENT if !from_client && flags & (TCP_SYN | TCP_ACK) == TCP_SYN | TCP_ACK => *s = FLOW_TCP_SYN_RECEIVED,
+        FLOW_TCP_SYN_RECEIVED if from_client && flags & TCP_ACK != 0 => *s = FLOW_TCP_ESTABLISHED,
+        _ => {}
+    }
+    if flags & TCP_FIN != 0 {
+        if from_client { f.fin_c2s = true; } else { f.fin_s2c = true; }
+        if matches!(*s, FLOW_TCP_RESET | FLOW_TCP_CLOSED) { return; }
+        *s = if f.fin_c2s && f.fin_s2c { FLOW_TCP_CLOSED } else { FLOW_TCP_CLOSING };
+    }
+}
+
+impl Table {
+    fn evict_oldest(&mut self) {
+        let oldest = self.flows.iter().min_by_key(|(_, f)| f.rec.last_seen_us).map(|(k, _)| *k);
+        if let Some(k) = oldest { self.flows.remove(&k); }
+    }
+
+    fn observe(&mut self, pkt: &ip::IpPacket, seg: &Segment, ts: i64) -> IrisFlowRecord {
+        let src = (pkt.src, seg.src_port);
+        let dst = (pkt.dst, seg.dst_port);
+        let key = FlowKey::new(pkt.protocol, src, dst);
+        if !self.flows.contains_key(&key) {
+            if self.flows.len() >= self.max_flows { self.evict_oldest(); }
+            // A lone SYN-ACK means we missed the SYN: its sender is the server
+            let reversed = pkt.protocol == PROTO_TCP && seg.tcp_flags & (TCP_SYN | TCP_ACK) == TCP_SYN | TCP_ACK;
+            let (client, server) = if reversed { (dst, src) } else { (src, dst) };
+            let tcp_state = match pkt.protocol {
+                PROTO_TCP if seg.tcp_flags & (TCP_SYN | TCP_ACK) == TCP_SYN => FLOW_TCP_SYN_SENT,
+                PROTO_TCP if reversed => FLOW_TCP_SYN_RECEIVED,
+                PROTO_TCP => FLOW_TCP_MIDSTREAM,
+                _ => FLOW_TCP_NONE,
+            };
+            self.next_id += 1;
+            self.flows.insert(key, Flow {
+                rec: IrisFlowRecord {
+                    id: self.next_id, ip_protocol: pkt.protocol,
+                    client: client.0, client_port: client.1, server: server.0, server_port: server.1,
+                    first_seen_us: ts, last_seen_us: ts,
+                    packets_c2s: 0, packets_s2c: 0, bytes_c2s: 0, bytes_s2c: 0,
+                    payload_bytes_c2s: 0, payload_bytes_s2c: 0,
+                    tcp_state, app: IrisProtocolMatch::default(),
+                },
+                fin_c2s: false, fin_s2c: false, identify_attempts: 0,
+            });
+        }
+        let f = self.flows.get_mut(&key).unwrap();
+        let from_client = (f.rec.client, f.rec.client_port) == src;
+        let r = &mut f.rec;
+        r.last_seen_us = r.last_seen_us.max(ts);
+        let (packets, bytes, payload) = if from_client {
+            (&mut r.packets_c2s, &mut r.bytes_c2s, &mut r.payload_bytes_c2s)
+        } else {
+            (&mut r.packets_s2c, &mut r.bytes_s2c, &mut r.payload_bytes_s2c)
+        };
+        *packets += 1;
+        *bytes += pkt.total_len as u64;
+        *payload += seg.payload.len() as u64;
+        if pkt.protocol == PROTO_TCP { advance_tcp(f, from_client, seg.tcp_flags); }
+        if !seg.payload.is_empty() && !f.rec.app.payload_match && f.identify_attempts < MAX_IDENTIFY_ATTEMPTS {
+            f.identify_attempts += 1;
+            f.rec.app = identify::identify(seg.payload, f.rec.client_port, f.rec.server_port);
+        }
+        f.rec
+    }
+
+    fn expire(&mut self, now: i64) -> Vec<IrisFlowRecord> {
+        let timeout = self.idle_timeout_us;
+        let mut done = Vec::new();
+        self.flows.retain(|_, f| {
+            let finished = matches!(f.rec.tcp_state, FLOW_TCP_CLOSED | FLOW_TCP_RESET);
+            let keep = !finished && now - f.rec.last_seen_us < timeout;
+            if !keep { done.push(f.rec); }
+            keep
+        });
+        done
+    }
+}
+
+fn write_snapshot(mut recs: Vec<IrisFlowRecord>, out: *mut IrisFlowSnapshot) {
+    recs.sort_by_key(|r| r.id);
+    let (flows, count) = alloc_array(recs);
+    unsafe { out.write(IrisFlowSnapshot { flows, count }); }
+}
+
+// --- FFI entry points ---
+
+/// Create a flow table. Flows idle longer than `idle_timeout_secs` are removed by
+/// iris_flow_table_expire; when `max_flows` is reached the least recently seen flow
+/// is evicted. Free with iris_flow_table_free.
+#[no_mangle]
+pub extern "C" fn iris_flow_table_new(idle_timeout_secs: u32, max_flows: usize) -> *mut IrisFlowTable {
+    let table = Table {
+        flows: HashMap::new(), next_id: 0,
+        idle_timeout_us: idle_timeout_secs as i64 * 1_000_000, max_flows: max_flows.max(1),
+    };
+    Box::into_raw(Box::new(IrisFlowTable { inner: Mutex::new(table) }))
+}
+
+/// Account one IP packet observed at `timestamp_us` (unix microseconds). The updated
+/// flow is copied to `out` if non-null.
+/// Returns 0=ok, -1=truncated/not IP, -2=arg error, -3=non-initial fragment (not tracked).
+#[no_mangle]
+pub extern "C" fn iris_flow_table_observe(
+    table: *mut IrisFlowTable, packet: *const u8, len: usize, timestamp_us: i64,
+    out: *mut IrisFlowRecord,
+) -> i32 {
+    if table.is_null() || packet.is_null() || len == 0 { return -2; }
+    let buf = unsafe { std::slice::from_raw_parts(packet, len) };
+    let pkt = match ip::parse_packet(buf) { Some(p) => p, None => return -1 };
+    if pkt.fragment_offset != 0 { return -3; }
+    let seg = match transport(pkt.protocol, pkt.payload) { Some(s) => s, None => return -1 };
+    let t = unsafe { &*table };
+    let rec = t.inner.lock().unwrap_or_else(|e| e.into_inner()).observe(&pkt, &seg, timestamp_us);
+    if !out.is_null() { unsafe { out.write(rec); } }
+    0
+}
+
+/// Remove flows that closed or have been idle for the timeout as of `now_us`,
+/// returning their final records in `out` (free with iris_flow_snapshot_free).
+/// Returns 0=ok, -2=arg error.
+#[no_mangle]
+pub extern "C" fn iris_flow_table_expire(
+    table: *mut IrisFlowTable, now_us: i64, out: *mut IrisFlowSnapshot,
+) -> i32 {
+    if table.is_null() || out.is_null() { return -2; }
+    let t = unsafe { &*table };
+    let done = t.inner.lock().unwrap_or_else(|e| e.into_inner()).expire(now_us);
+    write_snapshot(done, out);
+    0
+}
+
+/// Copy every live flow, ordered by id. Free with iris_flow_snapshot_free.
+/// Returns 0=ok, -2=arg error.
+#[no_mangle]
+pub extern "C" fn iris_flow_table_snapshot(table: *mut IrisFlowTable, out: *mut IrisFlowSnapshot) -> i32 {
+    if table.is_null() || out.is_null() { return -2; }
+    let t = unsafe { &*table };
+    let recs: Vec<IrisFlowRecord> = t.inner.lock().unwrap_or_else(|e| e.into_inner())
+        .flows.values().map(|f| f.rec).collect();
+    write_snapshot(recs, out);
+    0
+}
+
+/// Number of live flows.
+#[no_mangle]
+pub extern "C" fn iris_flow_table_len(table: *mut IrisFlowTable) -> usize {
+    if table.is_null() { return 0; }
+    let t = unsafe { &*table };
+    t.inner.lock().unwrap_or_else(|e| e.into_inner()).flows.len()
+}
+
+/// Free a snapshot returned by iris_flow_table_snapshot/expire.
+#[no_mangle]
+pub extern "C" fn iris_flow_snapshot_free(snap: *mut IrisFlowSnapshot) {
+    if snap.is_null() { return; }
+    let s = unsafe { &*snap };
+    free_array(s.flows, s.count);
+}
+
+/// Free a table created by iris_flow_table_new.
+#[no_mangle]
+pub extern "C" fn iris_flow_table_free(table: *mut IrisFlowTable) {
+    if table.is_null() { return; }
+    unsafe { drop(Box::from_raw(table)); }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tcp(src: [u8; 4], sport: u16, dst: [u8; 4], dport: u16, flags: u8, payload: &[u8]) -> Vec<u8> {
+        let total = 20 + 20 + payload.len();
+        let mut p = vec![0x45, 0, (total >> 8) as u8, total as u8, 0, 0, 0x40, 0, 64, PROTO_TCP, 0, 0];
+        p.extend_from_slice(&src);
+        p.extend_from_slice(&dst);
+        let c = ip::internet_checksum(&[&p]);
+        p[10..12].copy_from_slice(&c.to_be_bytes());
+        p.extend_from_slice(&sport.to_be_bytes());
+        p.extend_from_slice(&dport.to_be_bytes());
+        p.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0, 0x50, flags, 0xff, 0xff, 0, 0, 0, 0]);
+        p.extend_from_slice(payload);
+        p
+    }
+
+    fn observe(t: *mut IrisFlowTable, p: &[u8], ts: i64) -> IrisFlowRecord {
+        let mut out = std::mem::MaybeUninit::<IrisFlowRecord>::uninit();
+        assert_eq!(iris_flow_table_observe(t, p.as_ptr(), p.len(), ts, out.as_mut_ptr()), 0);
+        unsafe { out.assume_init() }
+    }
+
+    #[test]
+    fn tcp_handshake_counters_and_close() {
+        let t = iris_flow_table_new(60, 16);
+        let (c, s) = ([10, 0, 0, 2], [93, 184, 216, 34]);
+        observe(t, &tcp(c, 50000, s, 80, TCP_SYN, b""), 1);
+        observe(t, &tcp(s, 80, c, 50000, TCP_SYN | TCP_ACK, b""), 2);
+        let r = observe(t, &tcp(c, 50000, s, 80, TCP_ACK, b""), 3);
+        assert_eq!(r.tcp_state, FLOW_TCP_ESTABLISHED);
+        let r = observe(t, &tcp(c, 50000, s, 80, TCP_ACK, b"GET / HTTP/1.1\r\n\r\n"), 4);
+        assert_eq!(r.app.protocol, identify::PROTO_HTTP);
+        assert_eq!((r.packets_c2s, r.packets_s2c, r.payload_bytes_c2s), (3, 1, 18));
+        assert_eq!(r.client.as_slice(), &c);
+        assert_eq!(r.server_port, 80);
+
+        observe(t, &tcp(s, 80, c, 50000, TCP_FIN | TCP_ACK, b""), 5);
+        let r = observe(t, &tcp(c, 50000, s, 80, TCP_FIN | TCP_ACK, b""), 6);
+        assert_eq!(r.tcp_state, FLOW_TCP_CLOSED);
+        assert_eq!(iris_flow_table_len(t), 1);
+
+        let mut snap = std::mem::MaybeUninit::<IrisFlowSnapshot>::uninit();
+        assert_eq!(iris_flow_table_expire(t, 7, snap.as_mut_ptr()), 0);
+        let mut snap = unsafe { snap.assume_init() };
+        assert_eq!(snap.count, 1);
+        assert_eq!(unsafe { (*snap.flows).id }, r.id);
+        iris_flow_snapshot_free(&mut snap);
+        assert_eq!(iris_flow_table_len(t), 0);
+        iris_flow_table_free(t);
+    }
+
+    #[test]
+    fn midstream_idle_expiry_and_eviction() {
+        let t = iris_flow_table_new(10, 2);
+        let r = observe(t, &tcp([10, 0, 0, 2], 50001, [10, 0, 0, 3], 22, TCP_ACK, b""), 0);
+        assert_eq!(r.tcp_state, FLOW_TCP_MIDSTREAM);
+        // SYN-ACK first: its sender is treated as the server
+        let r = observe(t, &tcp([10, 0, 0, 4], 443, [10, 0, 0, 2], 50002, TCP_SYN | TCP_ACK, b""), 5_000_000);
+        assert_eq!((r.server_port, r.tcp_state), (443, FLOW_TCP_SYN_RECEIVED));
+        observe(t, &tcp([10, 0, 0, 2], 50003, [10, 0, 0, 5], 25, TCP_SYN, b""), 6_000_000);
+        assert_eq!(iris_flow_table_len(t), 2); // first flow evicted
+
+        let mut snap = std::mem::MaybeUninit::<IrisFlowSnapshot>::uninit();
+        assert_eq!(iris_flow_table_expire(t, 15_500_000, snap.as_mut_ptr()), 0);
+        let mut snap = unsafe { snap.assume_init() };
+        assert_eq!(snap.count, 1);
+        iris_flow_snapshot_free(&mut snap);
+        iris_flow_table_free(t);
+    }
+}
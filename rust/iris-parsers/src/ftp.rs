@@ -0,0 +1,379 @@
+//! FTP control-channel parser (RFC 959, RFC 2428). Tracks commands and replies,
+//! logins, AUTH TLS, and the data-connection endpoints announced by
+//! PORT/EPRT/PASV/EPSV so they can be matched to observed flows.
+
+use crate::ffi::{to_cstr, free_cstr};
+use crate::ip::IrisIpAddr;
+use std::ffi::c_char;
+
+const MAX_LINE: usize = 8 * 1024;
+
+pub const FTP_EVENT_COMMAND: u8 = 1;
+pub const FTP_EVENT_REPLY: u8 = 2;
+pub const FTP_EVENT_LOGIN: u8 = 3;
+pub const FTP_EVENT_DATA_CHANNEL: u8 = 4;
+pub const FTP_EVENT_TLS_STARTED: u8 = 5;
+
+#[repr(C)]
+pub struct IrisFtpEvent {
+    pub kind: u8,               // FTP_EVENT_*
+    pub reply_code: u16,        // REPLY only
+    pub verb: *mut c_char,      // command verb; for REPLY, the command being answered
+    pub argument: *mut c_char,  // argument / reply text (PASS arguments are never copied)
+    pub username: *mut c_char,  // LOGIN only
+    pub anonymous: bool,        // LOGIN as anonymous/ftp
+    pub login_succeeded: bool,  // LOGIN only (230 after PASS)
+    pub passive: bool,          // DATA_CHANNEL: server listens (PASV/EPSV)
+    pub data_addr: IrisIpAddr,  // DATA_CHANNEL: family 0 = same host as control peer (EPSV)
+    pub data_port: u16,
+}
+
+#[repr(C)]
+pub struct IrisFtpEvents {
+    pub events: *mut IrisFtpEvent,
+    pub count: usize,
+}
+
+#[derive(Default)]
+struct Event {
+    kind: u8,
+    code: u16,
+    verb: String,
+    argument: String,
+    username: String,
+    anonymous: bool,
+    login_ok: bool,
+    passive: bool,
+    addr: IrisIpAddr,
+    port: u16,
+}
+
+/// Opaque per-connection state. Create with iris_ftp_new.
+pub struct IrisFtpSession {
+    client_buf: Vec<u8>,
+    server_buf: Vec<u8>,
+    reply_lines: Vec<String>,
+    multiline_code: Option<u16>,
+    last_verb: String,
+    user: String,
+    tls_pending: bool,
+    tls: bool,
+    events: Vec<Event>,
+}
+
+fn take_line(buf: &mut Vec<u8>) -> Option<Vec<u8>> {
+    let nl = buf.iter().position(|&b| b == b'\n')?;
+    let mut line: Vec<u8> = buf.drain(..=nl).collect();
+    line.pop();
+    if line.last() == Some(&b'\r') { line.pop(); }
+    Some(line)
+}
+
+/// "h1,h2,h3,h4,p1,p2" as used by PORT and the 227 reply.
+fn parse_host_port(s: &str) -> Option<(IrisIpAddr, u16)> {
+    let nums: Vec<u8> = s.split(',').map(|p| p.trim().parse::<u8>()).collect::<Result<_, _>>().ok()?;
+    if nums.len() != 6 { return None; }
+    Some((IrisIpAddr::v4(&nums[..4]), u16::from_be_bytes([nums[4], nums[5]])))
+}
+
+/// EPRT argument: "|proto|addr|port|" with an arbitrary delimiter.
+fn parse_eprt(s: &str) -> Option<(IrisIpAddr, u16)> {
+    let delim = s.chars().next()?;
+    let parts: Vec<&str> = s.split(delim).collect();
+    if parts.len() < 5 { return None; }
+    let port = parts[3].parse().ok()?;
+    let addr = match parts[1] {
+        "1" => IrisIpAddr::v4(&parts[2].parse::<std::net::Ipv4Addr>().ok()?.octets()),
+        "2" => IrisIpAddr::v6(&parts[2].parse::<std::net::Ipv6Addr>().ok()?.octets()),
+        _ => return None,
+    };
+    Some((addr, port))
+}
+
+/// EPSV reply text: "... (|||port|)".
+fn parse_epsv_reply(text: &str) -> Option<u16> {
+    let start = text.find('(')?;
+    let end = text[start..].find(')')? + start;
+    let inner = &text[start + 1..end];
+    let delim = inner.chars().next()?;
+    inner.split(delim).nth(3)?.parse().ok()
+}
+
+impl IrisFtpSession {
+    fn new() -> Self {
+        IrisFtpSession {
+            client_buf: Vec::new(), server_buf: Vec::new(), reply_lines: Vec::new(),
+            multiline_code: None, last_verb: String::new(), user: String::new(),
+            tls_pending: false, tls: false, events: Vec::new(),
+        }
+    }
+
+    fn data_event(&mut self, passive: bool, addr: IrisIpAddr, port: u16) {
+        self.events.push(Event {
+            kind: FTP_EVENT_DATA_CHANNEL, verb: self.last_verb.clone(), passive, addr, port,
+            ..Default::default()
+        });
+    }
+
+    fn client_line(&mut self, line: &[u8]) {
+        let text = String::from_utf8_lossy(line);
+        let (verb, arg) = match text.split_once(' ') {
+            Some((v, a)) => (v.to_ascii_uppercase(), a.trim().to_string()),
+            None => (text.trim().to_ascii_uppercase(), String::new()),
+        };
+        let mut ev = Event { kind: FTP_EVENT_COMMAND, verb: verb.clone(), ..Default::default() };
+        // Set first: PORT/EPRT data events are labelled with it
+        self.last_verb = verb.clone();
+        match verb.as_str() {
+            "USER" => self.user = arg.clone(),
+            "PORT" => if let Some((a, p)) = parse_host_port(&arg) { self.data_event(false, a, p); },
+            "EPRT" => if let Some((a, p)) = parse_eprt(&arg) { self.data_event(false, a, p); },
+            "AUTH" if arg.eq_ignore_ascii_case("TLS") || arg.eq_ignore_ascii_case("SSL") => {
+                self.tls_pending = true;
+            }
+            _ => {}
+        }
+        if verb != "PASS" { ev.argument = arg; }
+        self.events.push(ev);
+    }
+
+    fn feed_client(&mut self, data: &[u8]) {
+        self.client_buf.extend_from_slice(data);
+        while let Some(line) = take_line(&mut self.client_buf) { self.client_line(&line); }
+        if self.client_buf.len() > MAX_LINE { self.client_buf.clear(); }
+    }
+
+    fn reply(&mut self, code: u16, text: String) {
+        let verb = self.last_verb.clone();
+        match code {
+            227 => {
+                let hp = text.find('(')
+                    .and_then(|s| text[s + 1..].find(')').map(|e| &text[s + 1..s + 1 + e]))
+                    .or_else(|| text.split_whitespace().find(|w| w.matches(',').count() == 5));
+                if let Some((a, p)) = hp.and_then(parse_host_port) { self.data_event(true, a, p); }
+            }
+            229 => if let Some(p) = parse_epsv_reply(&text) {
+                self.data_event(true, IrisIpAddr::default(), p);
+            },
+            230 | 530 if verb == "PASS" || verb == "USER" => {
+                let anonymous = self.user.eq_ignore_ascii_case("anonymous") || self.user.eq_ignore_ascii_case("ftp");
+                self.events.push(Event {
+                    kind: FTP_EVENT_LOGIN, username: self.user.clone(), anonymous,
+                    login_ok: code == 230, ..Default::default()
+                });
+            }
+            _ => {}
+        }
+        self.events.push(Event { kind: FTP_EVENT_REPLY, code, verb, argument: text, ..Default::default() });
+        if self.tls_pending {
+            self.tls_pending = false;
+            if code == 234 {
+                self.tls = true;
+                self.events.push(Event { kind: FTP_EVENT_TLS_STARTED, ..Default::default() });
+            }
+        }
+    }
+
+    fn feed_server(&mut self, data: &[u8]) {
+        self.server_buf.extend_from_slice(data);
+        while let Some(line) = take_line(&mut self.server_buf) {
+            let code = line.get(..3)
+                .and_then(|c| std::str::from_utf8(c).ok())
+                .and_then(|c| c.parse::<u16>().ok());
+            let text = String::from_utf8_lossy(line.get(4..).unwrap_or(&[])).into_owned();
+            match (self.multiline_code, code) {
+                // RFC 959 multi-line: "123-first" ... "123 last"; other lines are free text
+                (Some(open), Some(c)) if c == open && line.get(3) == Some(&b' ') => {
+                    self.reply_lines.push(text);
+                    self.multiline_code = None;
+                    let all = std::mem::take(&mut self.reply_lines).join("\n");
+                    self.reply(c, all);
+                }
+                (Some(_), _) => self.reply_lines.push(String::from_utf8_lossy(&line).into_owned()),
+                (None, Some(c)) if line.get(3) == Some(&b'-') => {
+                    self.multiline_code = Some(c);
+                    self.reply_lines.push(text);
+                }
+                (None, Some(c)) => self.reply(c, text),
+                (None, None) => {}
+            }
+            if self.tls { self.server_buf.clear(); self.client_buf.clear(); return; }
+        }
+        if self.server_buf.len() > MAX_LINE { self.server_buf.clear(); }
+    }
+}
+
+fn alloc_events(evs: Vec<Event>) -> (*mut IrisFtpEvent, usize) {
+    let count = evs.len();
+    if count == 0 { return (std::ptr::null_mut(), 0); }
+    let layout = std::alloc::Layout::array::<IrisFtpEvent>(count).unwrap();
+    let ptr = unsafe { std::alloc::alloc(layout) as *mut IrisFtpEvent };
+    if ptr.is_null() { return (std::ptr::null_mut(), 0); }
+    for (i, e) in evs.into_iter().enumerate() {
+        unsafe {
+            ptr.add(i).write(IrisFtpEvent {
+                kind: e.kind, reply_code: e.code,
+                verb: to_cstr(&e.verb), argument: to_cstr(&e.argument),
+                username: to_cstr(&e.username), anonymous: e.anonymous,
+                login_succeeded: e.login_ok, passive: e.passive,
+                data_addr: e.addr, data_port: e.port,
+            });
+        }
+    }
+    (ptr, count)
+}
+
+// --- FFI entry points ---
+
+/// Create an FTP control-channel tracker. Free with iris_ftp_free.
+#[no_mangle]
+pub extern "C" fn iris_ftp_new() -> *mut IrisFtpSession {
+    Box::into_raw(Box::new(IrisFtpSession::new()))
+}
+
+/// Feed control-channel bytes from one direction. Completed events are returned
+/// in `out` (free with iris_ftp_free_events). Input after AUTH TLS is ignored.
+/// Returns 0=ok, -2=arg error.
+#[no_mangle]
+pub extern "C" fn iris_ftp_feed(
+    session: *mut IrisFtpSession, data: *const u8, len: usize, from_client: bool,
+    out: *mut IrisFtpEvents,
+) -> i32 {
+    if session.is_null() || out.is_null() || (data.is_null() && len > 0) { return -2; }
+    let s = unsafe { &mut *session };
+    if !s.tls && len > 0 {
+        let buf = unsafe { std::slice::from_raw_parts(data, len) };
+        if from_client { s.feed_client(buf); } else { s.feed_server(buf); }
+    }
+    let (events, count) = alloc_events(std::mem::take(&mut s.events));
+    unsafe { out.write(IrisFtpEvents { events, count }); }
+    0
+}
+
+/// Free events returned by iris_ftp_feed.
+#[no_mangle]
+pub extern "C" fn iris_ftp_free_events(evs: *mut IrisFtpEvents) {
+    if evs.is_null() { return; }
+    unsafe {
+        let e = &*evs;
+        if e.events.is_null() || e.count == 0 { return; }
+        for i in 0..e.count {
+            let ev = &*e.events.add(i);
+            free_cstr(ev.verb);
+            free_cstr(ev.argument);
+            free_cstr(ev.username);
+        }
+        let layout = std::alloc::Layout::array::<IrisFtpEvent>(e.count).unwrap();
+        std::alloc::dealloc(e.events as *mut u8, layout);
+    }
+}
+
+/// Free a session created by iris_ftp_new.
+#[no_mangle]
+pub extern "C" fn iris_ftp_free(session: *mut IrisFtpSession) {
+    if session.is_null() { return; }
+    unsafe { drop(Box::from_raw(session)); }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(dialogue: &[(bool, &str)]) -> IrisFtpSession {
+        let mut s = IrisFtpSession::new();
+        for (from_client, text) in dialogue {
+            if *from_client { s.feed_client(text.as_bytes()); } else { s.feed_server(text.as_bytes()); }
+        }
+        s
+    }
+
+    fn channels(s: &IrisFtpSession) -> Vec<(&str, bool, IrisIpAddr, u16)> {
+        s.events.iter().filter(|e| e.kind == FTP_EVENT_DATA_CHANNEL)
+            .map(|e| (e.verb.as_str(), e.passive, e.addr, e.port)).collect()
+    }
+
+    #[test]
+    fn data_channel_endpoints() {
+        let s = session(&[
+            (true, "PORT 10,0,0,5,4,1\r\n"),
+            (true, "EPRT |2|2001:db8::1|6000|\r\n"),
+            (true, "PASV\r\n"),
+            (false, "227 Entering Passive Mode (192,168,1,2,195,80).\r\n"),
+            (true, "EPSV\r\n"),
+            (false, "229 Entering Extended Passive Mode (|||6446|)\r\n"),
+            (true, "PORT 10,0,0,5,4\r\n"), // malformed: no event
+        ]);
+        let mut v6 = [0u8; 16];
+        v6[..4].copy_from_slice(&[0x20, 0x01, 0x0d, 0xb8]);
+        v6[15] = 1;
+        assert_eq!(channels(&s), [
+            ("PORT", false, IrisIpAddr::v4(&[10, 0, 0, 5]), 1025),
+            ("EPRT", false, IrisIpAddr::v6(&v6), 6000),
+            ("PASV", true, IrisIpAddr::v4(&[192, 168, 1, 2]), 50000),
+            ("EPSV", true, IrisIpAddr::default(), 6446),
+        ]);
+    }
+
+    #[test]
+    fn multiline_reply() {
+        let s = session(&[(false, "220-Welcome\r\n"), (false, " free text\r\n220-more\r\n220 ready\r\n")]);
+        assert_eq!(s.events.len(), 1);
+        let r = &s.events[0];
+        assert_eq!((r.kind, r.code), (FTP_EVENT_REPLY, 220));
+        assert_eq!(r.argument, "Welcome\n free text\n220-more\nready");
+    }
+
+    #[test]
+    fn login_redacts_password() {
+        let s = session(&[
+            (true, "USER alice\r\n"), (false, "331 Password required\r\n"),
+            (true, "PASS s3cret\r\n"), (false, "230 Logged in\r\n"),
+        ]);
+        assert!(s.events.iter().all(|e| !e.argument.contains("s3cret")));
+        let pass = s.events.iter().find(|e| e.verb == "PASS" && e.kind == FTP_EVENT_COMMAND).unwrap();
+        assert_eq!(pass.argument, "");
+        let login = s.events.iter().find(|e| e.kind == FTP_EVENT_LOGIN).unwrap();
+        assert_eq!(login.username, "alice");
+        assert!(login.login_ok && !login.anonymous);
+
+        let s = session(&[(true, "USER anonymous\r\n"), (true, "PASS guest@\r\n"), (false, "530 Login incorrect\r\n")]);
+        let login = s.events.iter().find(|e| e.kind == FTP_EVENT_LOGIN).unwrap();
+        assert!(login.anonymous && !login.login_ok);
+    }
+
+    #[test]
+    fn auth_tls_cutoff() {
+        // Refused: parsing carries on
+        let s = session(&[(true, "AUTH TLS\r\n"), (false, "504 Not supported\r\n"), (true, "USER bob\r\n")]);
+        assert!(!s.tls);
+        assert_eq!(s.events.last().unwrap().verb, "USER");
+
+        let f = iris_ftp_new();
+        let mut out = std::mem::MaybeUninit::<IrisFtpEvents>::uninit();
+        let feed = |d: &[u8], from_client: bool, out: *mut IrisFtpEvents| iris_ftp_feed(f, d.as_ptr(), d.len(), from_client, out);
+        assert_eq!(feed(b"AUTH TLS\r\n", true, out.as_mut_ptr()), 0);
+        iris_ftp_free_events(out.as_mut_ptr());
+        // Anything after the 234 in the same segment is the TLS handshake
+        assert_eq!(feed(b"234 Proceed\r\n\x16\x03\x01\x00\x05hello\n", false, out.as_mut_ptr()), 0);
+        let e = unsafe { out.assume_init_read() };
+        let kinds: Vec<u8> = unsafe { std::slice::from_raw_parts(e.events, e.count) }.iter().map(|e| e.kind).collect();
+        assert_eq!(kinds, [FTP_EVENT_REPLY, FTP_EVENT_TLS_STARTED]);
+        iris_ftp_free_events(out.as_mut_ptr());
+        assert_eq!(feed(b"USER hidden\r\n", true, out.as_mut_ptr()), 0);
+        assert_eq!(unsafe { out.assume_init_read() }.count, 0);
+        iris_ftp_free(f);
+    }
+
+    #[test]
+    fn max_line_reset() {
+        let mut s = IrisFtpSession::new();
+        s.feed_client(&[b'A'; MAX_LINE + 1]);
+        assert!(s.client_buf.is_empty() && s.events.is_empty());
+        s.feed_client(b"NOOP\r\n");
+        assert_eq!(s.events[0].verb, "NOOP");
+        s.feed_server(&[b'2'; MAX_LINE + 1]);
+        assert!(s.server_buf.is_empty());
+        s.feed_server(b"200 OK\r\n");
+        assert_eq!((s.events[1].code, s.events[1].argument.as_str()), (200, "OK"));
+    }
+}
@@ -0,0 +1,226 @@
+//! gRPC over HTTP/2 (and gRPC-Web over HTTP/1.1): content-type recognition, the
+//! 5-byte length-prefixed message framing inside DATA payloads, and the
+//! grpc-status / grpc-message trailers of a reassembled h2 response.
+
+use crate::ffi::{alloc_array, free_array, free_cstr, to_cstr};
+use crate::h2::IrisH2Message;
+use crate::hpack::{IrisHpackHeader, IrisHpackHeaders};
+use crate::url;
+use std::ffi::{c_char, CStr};
+use std::slice;
+
+pub const GRPC_CT_NONE: u8 = 0;
+pub const GRPC_CT_GRPC: u8 = 1;     // application/grpc[+proto|+json|...]
+pub const GRPC_CT_WEB: u8 = 2;      // application/grpc-web[+...]
+pub const GRPC_CT_WEB_TEXT: u8 = 3; // application/grpc-web-text: base64 body
+
+const PREFIX: usize = 5;
+const FLAG_COMPRESSED: u8 = 0x01;
+const FLAG_WEB_TRAILERS: u8 = 0x80;
+
+const STATUS_NAMES: [&str; 17] = [
+    "OK", "CANCELLED", "UNKNOWN", "INVALID_ARGUMENT", "DEADLINE_EXCEEDED", "NOT_FOUND",
+    "ALREADY_EXISTS", "PERMISSION_DENIED", "RESOURCE_EXHAUSTED", "FAILED_PRECONDITION",
+    "ABORTED", "OUT_OF_RANGE", "UNIMPLEMENTED", "INTERNAL", "UNAVAILABLE", "DATA_LOSS",
+    "UNAUTHENTICATED",
+];
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct IrisGrpcMessage {
+    pub offset: usize,     // of the payload, after the 5-byte prefix
+    pub length: usize,     // payload length
+    pub compressed: bool,  // payload uses the grpc-encoding codec
+    pub trailers: bool,    // gRPC-Web trailer frame: payload is "name: value\r\n" lines
+}
+
+#[repr(C)]
+pub struct IrisGrpcMessages {
+    pub messages: *mut IrisGrpcMessage,
+    pub count: usize,
+    pub consumed: usize,   // bytes covered by complete messages
+    pub truncated: bool,   // data ends inside a message
+}
+
+#[repr(C)]
+pub struct IrisGrpcStatus {
+    pub status: u32,
+    pub status_name: *mut c_char, // "UNAVAILABLE", "" for codes outside 0-16
+    pub message: *mut c_char,     // grpc-message, percent-decoded, "" if absent
+    pub trailers_only: bool,      // status arrived in the response headers
+}
+
+/// gRPC flavour of a Content-Type value (GRPC_CT_*).
+pub fn content_type(ct: &[u8]) -> u8 {
+    let ct = ct.split(|&b| b == b';').next().unwrap_or_default().trim_ascii().to_ascii_lowercase();
+    let base = ct.split(|&b| b == b'+').next().unwrap_or_default();
+    match base {
+        b"application/grpc" => GRPC_CT_GRPC,
+        b"application/grpc-web" => GRPC_CT_WEB,
+        b"application/grpc-web-text" => GRPC_CT_WEB_TEXT,
+        _ => GRPC_CT_NONE,
+    }
+}
+
+/// Split a de-framed request or response body into length-prefixed messages.
+/// Returns the messages and the bytes they cover.
+pub fn split_messages(d: &[u8]) -> (Vec<IrisGrpcMessage>, usize) {
+    let (mut out, mut p) = (Vec::new(), 0);
+    while d.len() - p >= PREFIX {
+        let flags = d[p];
+        let length = u32::from_be_bytes([d[p + 1], d[p + 2], d[p + 3], d[p + 4]]) as usize;
+        if d.len() - p - PREFIX < length { break; }
+        out.push(IrisGrpcMessage {
+            offset: p + PREFIX,
+            length,
+            compressed: flags & FLAG_COMPRESSED != 0,
+            trailers: flags & FLAG_WEB_TRAILERS != 0,
+        });
+        p += PREFIX + length;
+    }
+    (out, p)
+}
+
+fn hpack_headers(h: &IrisHpackHeaders) -> &[IrisHpackHeader] {
+    if h.headers.is_null() || h.count == 0 { return &[]; }
+    unsafe { slice::from_raw_parts(h.headers, h.count) }
+}
+
+fn hpack_value<'a>(h: &'a IrisHpackHeaders, name: &str) -> Option<&'a [u8]> {
+    hpack_headers(h).iter()
+        .find(|e| unsafe { CStr::from_ptr(e.name) }.to_bytes().eq_ignore_ascii_case(name.as_bytes()))
+        .map(|e| unsafe { CStr::from_ptr(e.value) }.to_bytes())
+}
+
+// --- FFI ---
+
+/// Classify a Content-Type value (NUL-terminated) as IRIS_GRPC_CT_*.
+#[no_mangle]
+pub extern "C" fn iris_grpc_content_type(ct: *const c_char) -> u8 {
+    if ct.is_null() { return GRPC_CT_NONE; }
+    content_type(unsafe { CStr::from_ptr(ct) }.to_bytes())
+}
+
+/// Find the length-prefixed messages in a gRPC or gRPC-Web body (already base64
+/// decoded for grpc-web-text). Offsets index into `data`.
+/// Returns 0=ok, -2=arg error. Free with iris_grpc_messages_free.
+#[no_mangle]
+pub extern "C" fn iris_grpc_parse_messages(data: *const u8, len: usize, out: *mut IrisGrpcMessages) -> i32 {
+    if out.is_null() || (data.is_null() && len != 0) { return -2; }
+    let d = if len == 0 { &[][..] } else { unsafe { slice::from_raw_parts(data, len) } };
+    let (messages, consumed) = split_messages(d);
+    let (messages, count) = alloc_array(messages);
+    unsafe { out.write(IrisGrpcMessages { messages, count, consumed, truncated: consumed < d.len() }); }
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn iris_grpc_messages_free(m: *mut IrisGrpcMessages) {
+    if m.is_null() { return; }
+    let m = unsafe { &*m };
+    free_array(m.messages, m.count);
+}
+
+/// grpc-status and grpc-message of an h2 response, from its trailers or, for
+/// trailers-only responses, its headers. Returns 0=ok, -2=arg error,
+/// -3=no grpc-status. Free with iris_grpc_status_free.
+#[no_mangle]
+pub extern "C" fn iris_grpc_status(response: *const IrisH2Message, out: *mut IrisGrpcStatus) -> i32 {
+    if response.is_null() || out.is_null() { return -2; }
+    let r = unsafe { &*response };
+    let (block, trailers_only) = match hpack_value(&r.trailers, "grpc-status") {
+        Some(_) => (&r.trailers, false),
+        None => (&r.headers, true),
+    };
+    let Some(status) = hpack_value(block, "grpc-status") else { return -3 };
+    let Some(status) = std::str::from_utf8(status).ok().and_then(|s| s.trim().parse::<u32>().ok()) else { return -3 };
+    let message = hpack_value(block, "grpc-message").map(|m| url::percent_decode(m, false)).unwrap_or_default();
+    unsafe {
+        out.write(IrisGrpcStatus {
+            status,
+            status_name: to_cstr(STATUS_NAMES.get(status as usize).copied().unwrap_or("")),
+            message: to_cstr(&String::from_utf8_lossy(&message)),
+            trailers_only,
+        });
+    }
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn iris_grpc_status_free(s: *mut IrisGrpcStatus) {
+    if s.is_null() { return; }
+    let s = unsafe { &*s };
+    free_cstr(s.status_name);
+    free_cstr(s.message);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hpack::{alloc_headers, free_headers, Header};
+
+    fn block(pairs: &[(&str, &str)]) -> IrisHpackHeaders {
+        alloc_headers(pairs.iter().map(|(n, v)| Header {
+            name: n.as_bytes().to_vec(), value: v.as_bytes().to_vec(), never_indexed: false,
+        }).collect())
+    }
+
+    #[test]
+    fn content_types() {
+        assert_eq!(content_type(b"application/grpc"), GRPC_CT_GRPC);
+        assert_eq!(content_type(b"Application/gRPC+proto; charset=utf-8"), GRPC_CT_GRPC);
+        assert_eq!(content_type(b"application/grpc-web+json"), GRPC_CT_WEB);
+        assert_eq!(content_type(b"application/grpc-web-text"), GRPC_CT_WEB_TEXT);
+        assert_eq!(content_type(b"application/grpcx"), GRPC_CT_NONE);
+        assert_eq!(iris_grpc_content_type(c"application/json".as_ptr()), GRPC_CT_NONE);
+    }
+
+    #[test]
+    fn message_framing() {
+        let body = [&[0u8, 0, 0, 0, 2][..], b"hi", &[1, 0, 0, 0, 0], &[0x80, 0, 0, 0, 15],
+                    b"grpc-status:0\r\n", &[0, 0, 0, 0, 9], b"part"].concat();
+        let mut out = std::mem::MaybeUninit::<IrisGrpcMessages>::uninit();
+        assert_eq!(iris_grpc_parse_messages(body.as_ptr(), body.len(), out.as_mut_ptr()), 0);
+        let mut m = unsafe { out.assume_init() };
+        let msgs = unsafe { slice::from_raw_parts(m.messages, m.count) };
+        assert_eq!(msgs.len(), 3);
+        assert_eq!((msgs[0].offset, msgs[0].length, msgs[0].compressed), (5, 2, false));
+        assert!(msgs[1].compressed && msgs[1].length == 0);
+        assert!(msgs[2].trailers);
+        assert_eq!(&body[msgs[2].offset..msgs[2].offset + 2], b"gr");
+        assert_eq!(m.consumed, body.len() - 9);
+        assert!(m.truncated);
+        iris_grpc_messages_free(&mut m);
+    }
+
+    #[test]
+    fn status_from_trailers() {
+        let mut resp = IrisH2Message {
+            headers: block(&[(":status", "200"), ("content-type", "application/grpc")]),
+            trailers: block(&[("grpc-status", "14"), ("grpc-message", "upstream%20down")]),
+            body: std::ptr::null_mut(), body_len: 0, body_truncated: false, complete: true,
+        };
+        let mut out = std::mem::MaybeUninit::<IrisGrpcStatus>::uninit();
+        assert_eq!(iris_grpc_status(&resp, out.as_mut_ptr()), 0);
+        let mut s = unsafe { out.assume_init_read() };
+        assert_eq!(s.status, 14);
+        assert_eq!(unsafe { CStr::from_ptr(s.status_name) }.to_bytes(), b"UNAVAILABLE");
+        assert_eq!(unsafe { CStr::from_ptr(s.message) }.to_bytes(), b"upstream down");
+        assert!(!s.trailers_only);
+        iris_grpc_status_free(&mut s);
+
+        // Trailers-only: status in the only HEADERS block
+        free_headers(&resp.headers);
+        free_headers(&resp.trailers);
+        resp.headers = block(&[(":status", "200"), ("grpc-status", "12")]);
+        resp.trailers = block(&[]);
+        assert_eq!(iris_grpc_status(&resp, out.as_mut_ptr()), 0);
+        let mut s = unsafe { out.assume_init_read() };
+        assert!(s.trailers_only && s.status == 12);
+        iris_grpc_status_free(&mut s);
+        free_headers(&resp.headers);
+        resp.headers = block(&[(":status", "200")]);
+        assert_eq!(iris_grpc_status(&resp, out.as_mut_ptr()), -3);
+        free_headers(&resp.headers);
+    }
+}
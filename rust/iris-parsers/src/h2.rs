@@ -0,0 +1,456 @@
+//! HTTP/2 connection tracking: reassembles frames from both directions of one
+//! connection (cleartext, or TLS after decryption), decodes header blocks with a
+//! per-direction HPACK decoder and pairs each stream's request with its response.
+//! Finished exchanges are queued until taken. The connection is internally locked.
+
+use crate::ffi::{alloc_array, alloc_bytes, free_array, iris_free_bytes};
+use crate::hpack::{self, Header, IrisHpackDecoder, IrisHpackHeaders};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+const FRAME_HEADER: usize = 9;
+const DEFAULT_BODY_LIMIT: usize = 1 << 20;
+const MAX_HEADER_BLOCK: usize = 1 << 20;
+const MAX_STREAMS: usize = 4096;
+
+const FRAME_DATA: u8 = 0;
+const FRAME_HEADERS: u8 = 1;
+const FRAME_RST_STREAM: u8 = 3;
+const FRAME_SETTINGS: u8 = 4;
+const FRAME_PUSH_PROMISE: u8 = 5;
+const FRAME_CONTINUATION: u8 = 9;
+
+const FLAG_END_STREAM: u8 = 0x01;
+const FLAG_ACK: u8 = 0x01;
+const FLAG_END_HEADERS: u8 = 0x04;
+const FLAG_PADDED: u8 = 0x08;
+const FLAG_PRIORITY: u8 = 0x20;
+
+const SETTINGS_HEADER_TABLE_SIZE: u16 = 1;
+
+#[repr(C)]
+pub struct IrisH2Message {
+    pub headers: IrisHpackHeaders,   // pseudo-headers first, as sent
+    pub trailers: IrisHpackHeaders,  // trailing HEADERS (gRPC status), empty if none
+    pub body: *mut u8,               // DATA payloads with padding removed
+    pub body_len: usize,
+    pub body_truncated: bool,        // body exceeded the connection's body limit
+    pub complete: bool,              // END_STREAM was seen
+}
+
+#[repr(C)]
+pub struct IrisH2Exchange {
+    pub stream_id: u32,
+    pub pushed: bool,           // server push: request headers came from PUSH_PROMISE
+    pub reset: bool,            // ended by RST_STREAM
+    pub error_code: u32,        // RST_STREAM error code (8 = CANCEL)
+    pub request: IrisH2Message,
+    pub response: IrisH2Message, // final (non-1xx) response
+}
+
+#[repr(C)]
+pub struct IrisH2Exchanges {
+    pub exchanges: *mut IrisH2Exchange,
+    pub count: usize,
+}
+
+#[derive(Default)]
+struct Message {
+    headers: Vec<Header>,
+    trailers: Vec<Header>,
+    body: Vec<u8>,
+    truncated: bool,
+    ended: bool,
+}
+
+#[derive(Default)]
+struct Stream {
+    id: u32,
+    pushed: bool,
+    reset: Option<u32>,
+    request: Message,
+    response: Message,
+}
+
+/// A header block being collected from HEADERS/PUSH_PROMISE and CONTINUATION frames.
+struct PendingBlock {
+    stream: u32,              // that the frames arrive on
+    promised: Option<u32>,    // PUSH_PROMISE: the stream the request belongs to
+    end_stream: bool,
+    block: Vec<u8>,
+}
+
+struct Direction {
+    buf: Vec<u8>,
+    decoder: IrisHpackDecoder,
+    pending: Option<PendingBlock>,
+    settings_table_size: Option<usize>,  // sent, awaiting the peer's ACK
+}
+
+struct Connection {
+    dirs: [Direction; 2],  // 0 = client to server, 1 = server to client
+    preface_checked: bool,
+    streams: HashMap<u32, Stream>,
+    done: VecDeque<Stream>,
+    body_limit: usize,
+    failed: bool,
+}
+
+/// Opaque per-connection state. Create with iris_h2_connection_new.
+pub struct IrisH2Connection { inner: Mutex<Connection> }
+
+impl Direction {
+    fn new() -> Self {
+        Direction { buf: Vec::new(), decoder: IrisHpackDecoder::new(hpack::DEFAULT_TABLE_SIZE), pending: None, settings_table_size: None }
+    }
+}
+
+/// Strip the padding of a PADDED frame (and the priority fields of HEADERS).
+fn unpad(flags: u8, ty: u8, p: &[u8]) -> Result<&[u8], i32> {
+    let (pad, mut p) = if flags & FLAG_PADDED != 0 {
+        let (&n, rest) = p.split_first().ok_or(-2)?;
+        (n as usize, rest)
+    } else {
+        (0, p)
+    };
+    if ty == FRAME_HEADERS && flags & FLAG_PRIORITY != 0 { p = p.get(5..).ok_or(-2)?; }
+    p.get(..p.len().checked_sub(pad).ok_or(-2)?).ok_or(-2)
+}
+
+impl Message {
+    fn append(&mut self, d: &[u8], limit: usize) {
+        let room = limit.saturating_sub(self.body.len());
+        self.body.extend_from_slice(&d[..d.len().min(room)]);
+        self.truncated |= d.len() > room;
+    }
+}
+
+impl Connection {
+    fn new(body_limit: usize) -> Self {
+        Connection {
+            dirs: [Direction::new(), Direction::new()], preface_checked: false, streams: HashMap::new(),
+            done: VecDeque::new(), body_limit, failed: false,
+        }
+    }
+
+    fn stream(&mut self, id: u32) -> Result<&mut Stream, i32> {
+        if id == 0 { return Err(-2); }
+        if !self.streams.contains_key(&id) && self.streams.len() >= MAX_STREAMS { return Err(-2); }
+        Ok(self.streams.entry(id).or_insert_with(|| Stream { id, ..Default::default() }))
+    }
+
+    /// Queue the stream if both sides have ended or it was reset.
+    fn settle(&mut self, id: u32) {
+        let Some(s) = self.streams.get(&id) else { return };
+        if s.reset.is_some() || (s.request.ended && s.response.ended) {
+            let s = self.streams.remove(&id).unwrap();
+            self.done.push_back(s);
+        }
+    }
+
+    fn feed(&mut self, dir: usize, d: &[u8]) -> Result<(), i32> {
+        self.dirs[dir].buf.extend_from_slice(d);
+        if dir == 0 && !self.preface_checked {
+            let buf = &self.dirs[0].buf;
+            if buf.len() < PREFACE.len() && PREFACE.starts_with(buf) { return Ok(()); }
+            // Tolerate captures that start after the preface
+            if buf.starts_with(PREFACE) { self.dirs[0].buf.drain(..PREFACE.len()); }
+            self.preface_checked = true;
+        }
+        let mut p = 0;
+        while let Some(h) = self.dirs[dir].buf.get(p..p + FRAME_HEADER) {
+            let len = (h[0] as usize) << 16 | (h[1] as usize) << 8 | h[2] as usize;
+            let (ty, flags) = (h[3], h[4]);
+            let id = u32::from_be_bytes([h[5], h[6], h[7], h[8]]) & 0x7fff_ffff;
+            let Some(payload) = self.dirs[dir].buf.get(p + FRAME_HEADER..p + FRAME_HEADER + len) else { break };
+            let payload = payload.to_vec();
+            p += FRAME_HEADER + len;
+            self.frame(dir, ty, flags, id, &payload)?;
+        }
+        self.dirs[dir].buf.drain(..p);
+        Ok(())
+    }
+
+    fn frame(&mut self, dir: usize, ty: u8, flags: u8, id: u32, p: &[u8]) -> Result<(), i32> {
+        // A header block must be finished before any other frame on the connection
+        if self.dirs[dir].pending.as_ref().is_some_and(|b| ty != FRAME_CONTINUATION || b.stream != id) { return Err(-2); }
+        match ty {
+            FRAME_DATA => {
+                let data = unpad(flags, ty, p)?;
+                let limit = self.body_limit;
+                let s = self.stream(id)?;
+                let m = if dir == 0 { &mut s.request } else { &mut s.response };
+                m.append(data, limit);
+                if flags & FLAG_END_STREAM != 0 { m.ended = true; }
+                self.settle(id);
+            }
+            FRAME_HEADERS | FRAME_PUSH_PROMISE => {
+                if id == 0 { return Err(-2); }
+                let mut frag = unpad(flags, ty, p)?;
+                let promised = if ty == FRAME_PUSH_PROMISE {
+                    if dir == 0 || frag.len() < 4 { return Err(-2); }
+                    let promised = u32::from_be_bytes([frag[0], frag[1], frag[2], frag[3]]) & 0x7fff_ffff;
+                    frag = &frag[4..];
+                    Some(promised)
+                } else {
+                    None
+                };
+                let block = PendingBlock { stream: id, promised, end_stream: flags & FLAG_END_STREAM != 0, block: frag.to_vec() };
+                self.dirs[dir].pending = Some(block);
+                if flags & FLAG_END_HEADERS != 0 { self.end_headers(dir)?; }
+            }
+            FRAME_CONTINUATION => {
+                let b = self.dirs[dir].pending.as_mut().ok_or(-2)?;
+                if b.block.len() + p.len() > MAX_HEADER_BLOCK { return Err(-2); }
+                b.block.extend_from_slice(p);
+                if flags & FLAG_END_HEADERS != 0 { self.end_headers(dir)?; }
+            }
+            FRAME_RST_STREAM => {
+                let code = u32::from_be_bytes(p.try_into().map_err(|_| -2)?);
+                // Resets of streams already finished or never seen carry nothing
+                if let Some(s) = self.streams.get_mut(&id) {
+                    s.reset = Some(code);
+                    self.settle(id);
+                }
+            }
+            FRAME_SETTINGS if flags & FLAG_ACK != 0 => {
+                // The peer acknowledged our settings: its encoder may now use our table size
+                if let Some(size) = self.dirs[1 - dir].settings_table_size.take() {
+                    self.dirs[1 - dir].decoder.set_protocol_max(size);
+                }
+            }
+            FRAME_SETTINGS => {
+                if !p.len().is_multiple_of(6) { return Err(-2); }
+                for s in p.chunks_exact(6) {
+                    if u16::from_be_bytes([s[0], s[1]]) == SETTINGS_HEADER_TABLE_SIZE {
+                        // Applies to the headers the other side encodes
+                        let size = u32::from_be_bytes([s[2], s[3], s[4], s[5]]) as usize;
+                        self.dirs[1 - dir].settings_table_size = Some(size);
+                    }
+                }
+            }
+            // PRIORITY, PING, GOAWAY, WINDOW_UPDATE and unknown types carry no exchange data
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn end_headers(&mut self, dir: usize) -> Result<(), i32> {
+        let b = self.dirs[dir].pending.take().ok_or(-2)?;
+        let headers = self.dirs[dir].decoder.decode(&b.block)?;
+        let id = b.promised.unwrap_or(b.stream);
+        let s = self.stream(id)?;
+        if b.promised.is_some() {
+            s.pushed = true;
+            s.request.headers = headers;
+            s.request.ended = true;
+            return Ok(());
+        }
+        let m = if dir == 0 { &mut s.request } else { &mut s.response };
+        let informational = dir == 1 && headers.iter().any(|h| h.name == b":status" && h.value.first() == Some(&b'1'));
+        if informational {
+            // 100 Continue / 103 Early Hints precede the final response
+        } else if m.headers.is_empty() {
+            m.headers = headers;
+        } else {
+            m.trailers = headers;
+        }
+        if b.end_stream { m.ended = true; }
+        self.settle(id);
+        Ok(())
+    }
+
+    /// Completed exchanges, then (with `include_open`) every stream still in progress.
+    fn take(&mut self, include_open: bool) -> Vec<Stream> {
+        let mut out: Vec<Stream> = self.done.drain(..).collect();
+        if include_open {
+            let mut open: Vec<Stream> = self.streams.drain().map(|(_, s)| s).collect();
+            open.sort_by_key(|s| s.id);
+            out.extend(open);
+        }
+        out
+    }
+}
+
+fn message(m: Message) -> IrisH2Message {
+    let (body, body_len) = alloc_bytes(&m.body);
+    IrisH2Message {
+        headers: hpack::alloc_headers(m.headers),
+        trailers: hpack::alloc_headers(m.trailers),
+        body, body_len,
+        body_truncated: m.truncated,
+        complete: m.ended,
+    }
+}
+
+fn free_message(m: &IrisH2Message) {
+    hpack::free_headers(&m.headers);
+    hpack::free_headers(&m.trailers);
+    iris_free_bytes(m.body, m.body_len);
+}
+
+// --- FFI entry points ---
+
+/// Create a tracker for one HTTP/2 connection. Each request and response body keeps
+/// at most `body_limit` bytes (0 = 1 MiB). Free with iris_h2_connection_free.
+#[no_mangle]
+pub extern "C" fn iris_h2_connection_new(body_limit: usize) -> *mut IrisH2Connection {
+    let limit = if body_limit == 0 { DEFAULT_BODY_LIMIT } else { body_limit };
+    Box::into_raw(Box::new(IrisH2Connection { inner: Mutex::new(Connection::new(limit)) }))
+}
+
+/// Feed bytes of one direction in stream order; partial frames are buffered. The
+/// client's first bytes may include the connection preface.
+/// Returns 0=ok, -2=arg error or protocol/HPACK error. After an error the connection
+/// can no longer be decoded and every later call returns -2.
+#[no_mangle]
+pub extern "C" fn iris_h2_connection_feed(conn: *mut IrisH2Connection, from_client: bool, data: *const u8, len: usize) -> i32 {
+    if conn.is_null() || (data.is_null() && len != 0) { return -2; }
+    let d = if len == 0 { &[][..] } else { unsafe { std::slice::from_raw_parts(data, len) } };
+    let mut c = unsafe { &*conn }.inner.lock().unwrap_or_else(|e| e.into_inner());
+    if c.failed { return -2; }
+    match c.feed(if from_client { 0 } else { 1 }, d) {
+        Ok(()) => 0,
+        Err(e) => {
+            c.failed = true;
+            e
+        }
+    }
+}
+
+/// Move finished exchanges (both sides ended, or reset) into `out`, in completion
+/// order. With `include_open`, streams still in progress follow, ordered by stream id;
+/// use this when the connection closes. Returns 0=ok, -2=arg error.
+/// Free with iris_h2_exchanges_free.
+#[no_mangle]
+pub extern "C" fn iris_h2_connection_take(conn: *mut IrisH2Connection, include_open: bool, out: *mut IrisH2Exchanges) -> i32 {
+    if conn.is_null() || out.is_null() { return -2; }
+    let streams = unsafe { &*conn }.inner.lock().unwrap_or_else(|e| e.into_inner()).take(include_open);
+    let exchanges: Vec<IrisH2Exchange> = streams.into_iter().map(|s| IrisH2Exchange {
+        stream_id: s.id,
+        pushed: s.pushed,
+        reset: s.reset.is_some(),
+        error_code: s.reset.unwrap_or(0),
+        request: message(s.request),
+        response: message(s.response),
+    }).collect();
+    let (exchanges, count) = alloc_array(exchanges);
+    unsafe { out.write(IrisH2Exchanges { exchanges, count }); }
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn iris_h2_exchanges_free(ex: *mut IrisH2Exchanges) {
+    if ex.is_null() { return; }
+    let ex = unsafe { &*ex };
+    for i in 0..ex.count {
+        let e = unsafe { &*ex.exchanges.add(i) };
+        free_message(&e.request);
+        free_message(&e.response);
+    }
+    free_array(ex.exchanges, ex.count);
+}
+
+#[no_mangle]
+pub extern "C" fn iris_h2_connection_free(conn: *mut IrisH2Connection) {
+    if conn.is_null() { return; }
+    drop(unsafe { Box::from_raw(conn) });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+
+    fn frame(ty: u8, flags: u8, id: u32, p: &[u8]) -> Vec<u8> {
+        let len = (p.len() as u32).to_be_bytes();
+        [&len[1..], &[ty, flags], &id.to_be_bytes(), p].concat()
+    }
+
+    fn take(c: *mut IrisH2Connection, include_open: bool) -> IrisH2Exchanges {
+        let mut out = std::mem::MaybeUninit::<IrisH2Exchanges>::uninit();
+        assert_eq!(iris_h2_connection_take(c, include_open, out.as_mut_ptr()), 0);
+        unsafe { out.assume_init() }
+    }
+
+    fn headers(h: &IrisHpackHeaders) -> Vec<(String, String)> {
+        (0..h.count).map(|i| {
+            let e = unsafe { &*h.headers.add(i) };
+            let s = |p| unsafe { CStr::from_ptr(p) }.to_string_lossy().into_owned();
+            (s(e.name), s(e.value))
+        }).collect()
+    }
+
+    fn body(m: &IrisH2Message) -> &[u8] {
+        if m.body.is_null() { &[] } else { unsafe { std::slice::from_raw_parts(m.body, m.body_len) } }
+    }
+
+    // :method GET, :scheme https, :path /, :authority example.com (added to the table)
+    const GET: &[u8] = b"\x82\x87\x84\x41\x0bexample.com";
+
+    #[test]
+    fn pairs_streams() {
+        let c = iris_h2_connection_new(8);
+        let client = [PREFACE, &frame(FRAME_SETTINGS, 0, 0, b""),
+                      &frame(FRAME_HEADERS, FLAG_END_STREAM, 1, &GET[..3]), &frame(FRAME_CONTINUATION, FLAG_END_HEADERS, 1, &GET[3..]),
+                      // second request reuses the dynamic table entry (index 62)
+                      &frame(FRAME_HEADERS, FLAG_END_HEADERS | FLAG_END_STREAM, 3, b"\x82\x87\x84\xbe")].concat();
+        for b in &client {
+            assert_eq!(iris_h2_connection_feed(c, true, b, 1), 0);
+        }
+        let server = [frame(FRAME_SETTINGS, FLAG_ACK, 0, b""),
+                      frame(FRAME_HEADERS, FLAG_END_HEADERS, 1, b"\x08\x03103"),
+                      frame(FRAME_HEADERS, FLAG_END_HEADERS, 1, b"\x88"),
+                      frame(FRAME_DATA, FLAG_PADDED, 1, b"\x02hello\0\0"),
+                      frame(FRAME_DATA, 0, 1, b" world"),
+                      frame(FRAME_HEADERS, FLAG_END_HEADERS | FLAG_END_STREAM, 1, b"\x40\x0bgrpc-status\x010"),
+                      frame(FRAME_RST_STREAM, 0, 3, &8u32.to_be_bytes())].concat();
+        assert_eq!(iris_h2_connection_feed(c, false, server.as_ptr(), server.len()), 0);
+
+        let mut ex = take(c, false);
+        assert_eq!(ex.count, 2);
+        let e = unsafe { &*ex.exchanges };
+        assert_eq!((e.stream_id, e.reset, e.request.complete, e.response.complete), (1, false, true, true));
+        assert_eq!(headers(&e.request.headers)[3], (":authority".into(), "example.com".into()));
+        assert_eq!(headers(&e.response.headers), [(":status".to_string(), "200".to_string())]);
+        assert_eq!(headers(&e.response.trailers), [("grpc-status".to_string(), "0".to_string())]);
+        assert_eq!((body(&e.response), e.response.body_truncated), (&b"hello wo"[..], true));
+        let e = unsafe { &*ex.exchanges.add(1) };
+        assert_eq!((e.stream_id, e.reset, e.error_code, e.response.headers.count), (3, true, 8, 0));
+        assert_eq!(headers(&e.request.headers)[3].1, "example.com");
+        iris_h2_exchanges_free(&mut ex);
+        iris_h2_connection_free(c);
+    }
+
+    #[test]
+    fn push_open_streams_and_errors() {
+        let c = iris_h2_connection_new(0);
+        let req = frame(FRAME_HEADERS, FLAG_END_HEADERS | FLAG_END_STREAM, 1, GET);
+        assert_eq!(iris_h2_connection_feed(c, true, req.as_ptr(), req.len()), 0);
+        // promised stream 2 carries GET /index.html
+        let push = [frame(FRAME_PUSH_PROMISE, 0, 1, b"\0\0\0\x02\x82\x87"),
+                    frame(FRAME_CONTINUATION, FLAG_END_HEADERS, 1, b"\x85"),
+                    frame(FRAME_HEADERS, FLAG_END_HEADERS, 2, b"\x88"),
+                    frame(FRAME_DATA, FLAG_END_STREAM, 2, b"<html>")].concat();
+        assert_eq!(iris_h2_connection_feed(c, false, push.as_ptr(), push.len()), 0);
+        let mut ex = take(c, true);
+        assert_eq!(ex.count, 2);
+        let (pushed, open) = unsafe { (&*ex.exchanges, &*ex.exchanges.add(1)) };
+        assert_eq!((pushed.stream_id, pushed.pushed, body(&pushed.response)), (2, true, &b"<html>"[..]));
+        assert_eq!(headers(&pushed.request.headers)[2].1, "/index.html");
+        assert_eq!((open.stream_id, open.request.complete, open.response.complete), (1, true, false));
+        iris_h2_exchanges_free(&mut ex);
+
+        // HEADERS interrupting an unfinished header block is a connection error
+        let bad = [frame(FRAME_HEADERS, 0, 3, b"\x82"), frame(FRAME_HEADERS, FLAG_END_HEADERS, 5, b"\x82")].concat();
+        assert_eq!(iris_h2_connection_feed(c, true, bad.as_ptr(), bad.len()), -2);
+        assert_eq!(iris_h2_connection_feed(c, false, b"".as_ptr(), 0), -2);
+        iris_h2_connection_free(c);
+
+        let c = iris_h2_connection_new(0);
+        let bad = frame(FRAME_DATA, 0, 0, b"x");
+        assert_eq!(iris_h2_connection_feed(c, true, bad.as_ptr(), bad.len()), -2);
+        iris_h2_connection_free(c);
+    }
+}
@@ -0,0 +1,350 @@
+//! Browser history databases: Safari History.db, Chrome/Chromium History and Firefox
+//! places.sqlite. Visits and downloads are returned with timestamps in unix seconds.
+
+use crate::ffi::{alloc_array, free_array, free_cstr, to_cstr};
+use crate::plist::CF_EPOCH;
+use crate::sqlite::{Database, Row, Table};
+use std::collections::HashMap;
+use std::ffi::{c_char, CStr};
+
+pub const BROWSER_SAFARI: u8 = 1;
+pub const BROWSER_CHROME: u8 = 2;
+pub const BROWSER_FIREFOX: u8 = 3;
+
+const WEBKIT_EPOCH: f64 = -11_644_473_600.0; // 1601-01-01 in unix seconds
+const FIREFOX_DOWNLOAD_ANNO: &str = "downloads/destinationFileURI";
+
+#[repr(C)]
+pub struct IrisHistoryVisit {
+    pub url: *mut c_char,
+    pub title: *mut c_char,
+    pub timestamp: f64,           // unix seconds
+    pub visit_count: u32,         // total visits recorded for the URL
+}
+
+#[repr(C)]
+pub struct IrisDownload {
+    pub url: *mut c_char,         // final URL of the redirect chain
+    pub referrer: *mut c_char,
+    pub tab_url: *mut c_char,     // page the download was started from, "" if unknown
+    pub target_path: *mut c_char,
+    pub mime_type: *mut c_char,
+    pub start_time: f64,          // unix seconds
+    pub end_time: f64,            // unix seconds, 0 if unknown
+    pub received_bytes: i64,
+    pub total_bytes: i64,         // -1 if unknown
+    pub state: i32,               // Chrome DownloadState (1=complete, 2=cancelled, 3=interrupted), -1 if unknown
+    pub danger_type: i32,         // Chrome DownloadDangerType, -1 if unknown
+}
+
+#[repr(C)]
+pub struct IrisBrowserHistory {
+    pub browser: u8,              // BROWSER_*
+    pub visits: *mut IrisHistoryVisit,
+    pub visit_count: usize,
+    pub downloads: *mut IrisDownload,
+    pub download_count: usize,
+}
+
+struct Visit {
+    url: String,
+    title: String,
+    timestamp: f64,
+    visit_count: u32,
+}
+
+#[derive(Default)]
+struct Download {
+    url: String,
+    referrer: String,
+    tab_url: String,
+    target_path: String,
+    mime_type: String,
+    start_time: f64,
+    end_time: f64,
+    received_bytes: i64,
+    total_bytes: i64,
+    state: i32,
+    danger_type: i32,
+}
+
+struct Cols<'a> {
+    t: &'a Table,
+    r: &'a Row,
+}
+
+impl<'a> Cols<'a> {
+    fn str(&self, col: &str) -> &'a str { self.r.get(self.t.column(col)).as_str() }
+    fn int(&self, col: &str) -> i64 { self.r.get(self.t.column(col)).as_int().unwrap_or(0) }
+    fn real(&self, col: &str) -> f64 { self.r.get(self.t.column(col)).as_real().unwrap_or(0.0) }
+}
+
+fn each<'a>(t: &'a Table, rows: &'a [Row]) -> impl Iterator<Item = Cols<'a>> {
+    rows.iter().map(move |r| Cols { t, r })
+}
+
+fn webkit_time(us: i64) -> f64 {
+    if us == 0 { 0.0 } else { us as f64 / 1e6 + WEBKIT_EPOCH }
+}
+
+fn unix_us(us: i64) -> f64 {
+    us as f64 / 1e6
+}
+
+/// Local path of a file:// URI, percent-decoded.
+fn file_uri_path(uri: &str) -> String {
+    let s = uri.strip_prefix("file://").unwrap_or(uri).as_bytes();
+    let hex = |b: u8| (b as char).to_digit(16).map(|v| v as u8);
+    let mut out = Vec::with_capacity(s.len());
+    let mut i = 0;
+    while i < s.len() {
+        if s[i] == b'%' && i + 2 < s.len() {
+            if let (Some(h), Some(l)) = (hex(s[i + 1]), hex(s[i + 2])) {
+                out.push(h << 4 | l);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(s[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn safari(db: &Database) -> Option<(Vec<Visit>, Vec<Download>)> {
+    let items_t = db.table("history_items")?;
+    let visits_t = db.table("history_visits")?;
+    let items_rows = db.rows(&items_t);
+    let items: HashMap<i64, (&str, u32)> = each(&items_t, &items_rows)
+        .map(|c| (c.int("id"), (c.str("url"), c.int("visit_count") as u32)))
+        .collect();
+    let visit_rows = db.rows(&visits_t);
+    let visits = each(&visits_t, &visit_rows).map(|c| {
+        let (url, visit_count) = items.get(&c.int("history_item")).copied().unwrap_or(("", 0));
+        Visit { url: url.into(), title: c.str("title").into(), timestamp: c.real("visit_time") + CF_EPOCH, visit_count }
+    }).collect();
+    // Safari records downloads in Downloads.plist rather than History.db
+    Some((visits, Vec::new()))
+}
+
+fn chrome(db: &Database) -> Option<(Vec<Visit>, Vec<Download>)> {
+    let urls_t = db.table("urls")?;
+    let visits_t = db.table("visits")?;
+    let url_rows = db.rows(&urls_t);
+    let urls: HashMap<i64, (&str, &str, u32)> = each(&urls_t, &url_rows)
+        .map(|c| (c.int("id"), (c.str("url"), c.str("title"), c.int("visit_count") as u32)))
+        .collect();
+    let visit_rows = db.rows(&visits_t);
+    let visits = each(&visits_t, &visit_rows).map(|c| {
+        let (url, title, visit_count) = urls.get(&c.int("url")).copied().unwrap_or(("", "", 0));
+        Visit { url: url.into(), title: title.into(), timestamp: webkit_time(c.int("visit_time")), visit_count }
+    }).collect();
+
+    let mut chains: HashMap<i64, (i64, String)> = HashMap::new();
+    if let Some(t) = db.table("downloads_url_chains") {
+        let rows = db.rows(&t);
+        for c in each(&t, &rows) {
+            let idx = c.int("chain_index");
+            let e = chains.entry(c.int("id")).or_insert((-1, String::new()));
+            if idx > e.0 { *e = (idx, c.str("url").into()); }
+        }
+    }
+    let mut downloads = Vec::new();
+    if let Some(t) = db.table("downloads") {
+        let rows = db.rows(&t);
+        for c in each(&t, &rows) {
+            let url = chains.remove(&c.int("id")).map(|(_, u)| u).unwrap_or_else(|| c.str("url").into());
+            let target = if c.str("target_path").is_empty() { c.str("current_path") } else { c.str("target_path") };
+            downloads.push(Download {
+                url,
+                referrer: c.str("referrer").into(),
+                tab_url: c.str("tab_url").into(),
+                target_path: target.into(),
+                mime_type: c.str("mime_type").into(),
+                start_time: webkit_time(c.int("start_time")),
+                end_time: webkit_time(c.int("end_time")),
+                received_bytes: c.int("received_bytes"),
+                total_bytes: c.int("total_bytes"),
+                state: c.int("state") as i32,
+                danger_type: c.int("danger_type") as i32,
+            });
+        }
+    }
+    Some((visits, downloads))
+}
+
+fn firefox(db: &Database) -> Option<(Vec<Visit>, Vec<Download>)> {
+    let places_t = db.table("moz_places")?;
+    let visits_t = db.table("moz_historyvisits")?;
+    let place_rows = db.rows(&places_t);
+    let places: HashMap<i64, (&str, &str, u32)> = each(&places_t, &place_rows)
+        .map(|c| (c.int("id"), (c.str("url"), c.str("title"), c.int("visit_count") as u32)))
+        .collect();
+    let visit_rows = db.rows(&visits_t);
+    let visits = each(&visits_t, &visit_rows).map(|c| {
+        let (url, title, visit_count) = places.get(&c.int("place_id")).copied().unwrap_or(("", "", 0));
+        Visit { url: url.into(), title: title.into(), timestamp: unix_us(c.int("visit_date")), visit_count }
+    }).collect();
+
+    // Downloads are page annotations: the destination file URI annotates the source URL's place
+    let mut downloads = Vec::new();
+    if let (Some(attrs_t), Some(annos_t)) = (db.table("moz_anno_attributes"), db.table("moz_annos")) {
+        let attr_rows = db.rows(&attrs_t);
+        let attr = each(&attrs_t, &attr_rows).find(|c| c.str("name") == FIREFOX_DOWNLOAD_ANNO).map(|c| c.int("id"));
+        let anno_rows = db.rows(&annos_t);
+        for c in each(&annos_t, &anno_rows).filter(|c| Some(c.int("anno_attribute_id")) == attr) {
+            let (url, _, _) = places.get(&c.int("place_id")).copied().unwrap_or(("", "", 0));
+            downloads.push(Download {
+                url: url.into(),
+                target_path: file_uri_path(c.str("content")),
+                start_time: unix_us(c.int("dateAdded")),
+                total_bytes: -1, state: -1, danger_type: -1,
+                ..Default::default()
+            });
+        }
+    }
+    Some((visits, downloads))
+}
+
+fn free_strings(ptrs: &[*mut c_char]) {
+    for &p in ptrs { free_cstr(p); }
+}
+
+// --- FFI entry points ---
+
+/// Read visits and downloads from a browser history database file, detecting Safari,
+/// Chrome or Firefox by schema. Returns 0=ok, -1=file error, -2=arg error or
+/// unrecognised schema. Free with iris_browser_history_free.
+#[no_mangle]
+pub extern "C" fn iris_browser_history_parse(path: *const c_char, out: *mut IrisBrowserHistory) -> i32 {
+    if path.is_null() || out.is_null() { return -2; }
+    let Ok(path) = unsafe { CStr::from_ptr(path) }.to_str() else { return -2 };
+    let db = match Database::open_path(path) { Ok(db) => db, Err(e) => return e };
+    let (browser, (visits, downloads)) = if let Some(h) = safari(&db) {
+        (BROWSER_SAFARI, h)
+    } else if let Some(h) = chrome(&db) {
+        (BROWSER_CHROME, h)
+    } else if let Some(h) = firefox(&db) {
+        (BROWSER_FIREFOX, h)
+    } else {
+        return -2;
+    };
+    let visits: Vec<_> = visits.into_iter().map(|v| IrisHistoryVisit {
+        url: to_cstr(&v.url), title: to_cstr(&v.title), timestamp: v.timestamp, visit_count: v.visit_count,
+    }).collect();
+    let downloads: Vec<_> = downloads.into_iter().map(|d| IrisDownload {
+        url: to_cstr(&d.url),
+        referrer: to_cstr(&d.referrer),
+        tab_url: to_cstr(&d.tab_url),
+        target_path: to_cstr(&d.target_path),
+        mime_type: to_cstr(&d.mime_type),
+        start_time: d.start_time,
+        end_time: d.end_time,
+        received_bytes: d.received_bytes,
+        total_bytes: d.total_bytes,
+        state: d.state,
+        danger_type: d.danger_type,
+    }).collect();
+    let (visits, visit_count) = alloc_array(visits);
+    let (downloads, download_count) = alloc_array(downloads);
+    unsafe { out.write(IrisBrowserHistory { browser, visits, visit_count, downloads, download_count }); }
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn iris_browser_history_free(h: *mut IrisBrowserHistory) {
+    if h.is_null() { return; }
+    let h = unsafe { &*h };
+    for i in 0..h.visit_count {
+        let v = unsafe { &*h.visits.add(i) };
+        free_strings(&[v.url, v.title]);
+    }
+    free_array(h.visits, h.visit_count);
+    for i in 0..h.download_count {
+        let d = unsafe { &*h.downloads.add(i) };
+        free_strings(&[d.url, d.referrer, d.tab_url, d.target_path, d.mime_type]);
+    }
+    free_array(h.downloads, h.download_count);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sqlite::tests::database;
+    use crate::sqlite::Value;
+
+    fn t(s: &str) -> Value { Value::Text(s.into()) }
+
+    fn parse(db: &[u8], name: &str) -> IrisBrowserHistory {
+        let path = std::env::temp_dir().join(format!("iris-history-{}-{}.db", name, std::process::id()));
+        std::fs::write(&path, db).unwrap();
+        let cpath = std::ffi::CString::new(path.to_str().unwrap()).unwrap();
+        let mut out = std::mem::MaybeUninit::<IrisBrowserHistory>::uninit();
+        assert_eq!(iris_browser_history_parse(cpath.as_ptr(), out.as_mut_ptr()), 0);
+        std::fs::remove_file(&path).unwrap();
+        unsafe { out.assume_init() }
+    }
+
+    fn cs(p: *mut c_char) -> String {
+        unsafe { CStr::from_ptr(p) }.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn safari_and_firefox() {
+        let db = database(&[
+            ("CREATE TABLE history_items (id INTEGER PRIMARY KEY AUTOINCREMENT, url TEXT NOT NULL UNIQUE, domain_expansion TEXT NULL, visit_count INTEGER NOT NULL)",
+             vec![vec![Value::Null, t("https://example.invalid/"), t("example"), Value::Int(4)]]),
+            ("CREATE TABLE history_visits (id INTEGER PRIMARY KEY AUTOINCREMENT, history_item INTEGER NOT NULL REFERENCES history_items(id) ON DELETE CASCADE, visit_time REAL NOT NULL, title TEXT NULL)",
+             vec![vec![Value::Null, Value::Int(1), Value::Real(700_000_000.0), t("Example")]]),
+        ]);
+        let mut h = parse(&db, "safari");
+        assert_eq!((h.browser, h.visit_count, h.download_count), (BROWSER_SAFARI, 1, 0));
+        let v = unsafe { &*h.visits };
+        assert_eq!((cs(v.url), cs(v.title), v.timestamp, v.visit_count), ("https://example.invalid/".into(), "Example".into(), 1_678_307_200.0, 4));
+        iris_browser_history_free(&mut h);
+
+        let db = database(&[
+            ("CREATE TABLE moz_places (id INTEGER PRIMARY KEY, url LONGVARCHAR, title LONGVARCHAR, rev_host LONGVARCHAR, visit_count INTEGER DEFAULT 0)",
+             vec![vec![Value::Null, t("https://dl.example.invalid/tool.dmg"), Value::Null, t("dilavni.elpmaxe.ld."), Value::Int(0)]]),
+            ("CREATE TABLE moz_historyvisits (id INTEGER PRIMARY KEY, from_visit INTEGER, place_id INTEGER, visit_date INTEGER, visit_type INTEGER, session INTEGER)",
+             vec![vec![Value::Null, Value::Int(0), Value::Int(1), Value::Int(1_700_000_000_250_000), Value::Int(7), Value::Int(0)]]),
+            ("CREATE TABLE moz_anno_attributes (id INTEGER PRIMARY KEY, name VARCHAR(32) UNIQUE NOT NULL)",
+             vec![vec![Value::Null, t("downloads/metaData")], vec![Value::Null, t(FIREFOX_DOWNLOAD_ANNO)]]),
+            ("CREATE TABLE moz_annos (id INTEGER PRIMARY KEY, place_id INTEGER NOT NULL, anno_attribute_id INTEGER, content LONGVARCHAR, flags INTEGER DEFAULT 0, expiration INTEGER DEFAULT 0, type INTEGER DEFAULT 0, dateAdded INTEGER DEFAULT 0, lastModified INTEGER DEFAULT 0)",
+             vec![vec![Value::Null, Value::Int(1), Value::Int(2), t("file:///Users/a/Downloads/my%20tool.dmg"), Value::Int(0), Value::Int(4), Value::Int(3), Value::Int(1_700_000_001_000_000), Value::Int(0)]]),
+        ]);
+        let mut h = parse(&db, "firefox");
+        assert_eq!((h.browser, h.visit_count, h.download_count), (BROWSER_FIREFOX, 1, 1));
+        assert_eq!(unsafe { (*h.visits).timestamp }, 1_700_000_000.25);
+        let d = unsafe { &*h.downloads };
+        assert_eq!((cs(d.url), cs(d.target_path), d.start_time), ("https://dl.example.invalid/tool.dmg".into(), "/Users/a/Downloads/my tool.dmg".into(), 1_700_000_001.0));
+        iris_browser_history_free(&mut h);
+    }
+
+    #[test]
+    fn chrome_downloads() {
+        let webkit = |unix: i64| Value::Int((unix + 11_644_473_600) * 1_000_000);
+        let db = database(&[
+            ("CREATE TABLE urls(id INTEGER PRIMARY KEY AUTOINCREMENT,url LONGVARCHAR,title LONGVARCHAR,visit_count INTEGER DEFAULT 0 NOT NULL,typed_count INTEGER DEFAULT 0 NOT NULL,last_visit_time INTEGER NOT NULL,hidden INTEGER DEFAULT 0 NOT NULL)",
+             vec![vec![Value::Null, t("https://example.invalid/get"), t("Get it"), Value::Int(2), Value::Int(0), webkit(1_700_000_000), Value::Int(0)]]),
+            ("CREATE TABLE visits(id INTEGER PRIMARY KEY,url INTEGER NOT NULL,visit_time INTEGER NOT NULL,from_visit INTEGER,transition INTEGER DEFAULT 0 NOT NULL)",
+             vec![vec![Value::Null, Value::Int(1), webkit(1_700_000_000), Value::Int(0), Value::Int(805_306_368)]]),
+            // Trimmed to the columns read here; the real table has ~30
+            ("CREATE TABLE downloads (id INTEGER PRIMARY KEY,guid VARCHAR NOT NULL,current_path LONGVARCHAR NOT NULL,target_path LONGVARCHAR NOT NULL,start_time INTEGER NOT NULL,received_bytes INTEGER NOT NULL,total_bytes INTEGER NOT NULL,state INTEGER NOT NULL,danger_type INTEGER NOT NULL,end_time INTEGER NOT NULL,referrer VARCHAR NOT NULL,tab_url VARCHAR NOT NULL,mime_type VARCHAR(255) NOT NULL)",
+             vec![vec![
+                 Value::Null, t("guid"), t("/Users/a/Downloads/x.pkg.crdownload"), t("/Users/a/Downloads/x.pkg"), webkit(1_700_000_010), Value::Int(5000), Value::Int(5000),
+                 Value::Int(1), Value::Int(0), webkit(1_700_000_012), t("https://example.invalid/get"), t("https://example.invalid/get"), t("application/octet-stream"),
+             ]]),
+            ("CREATE TABLE downloads_url_chains (id INTEGER NOT NULL,chain_index INTEGER NOT NULL,url LONGVARCHAR NOT NULL, PRIMARY KEY (id, chain_index) )",
+             vec![vec![Value::Int(1), Value::Int(1), t("https://cdn.example.invalid/x.pkg")], vec![Value::Int(1), Value::Int(0), t("https://example.invalid/dl?id=1")]]),
+        ]);
+        let mut h = parse(&db, "chrome");
+        assert_eq!((h.browser, h.visit_count, h.download_count), (BROWSER_CHROME, 1, 1));
+        let v = unsafe { &*h.visits };
+        assert_eq!((cs(v.title), v.timestamp, v.visit_count), ("Get it".into(), 1_700_000_000.0, 2));
+        let d = unsafe { &*h.downloads };
+        assert_eq!((cs(d.url), cs(d.referrer), cs(d.target_path)), ("https://cdn.example.invalid/x.pkg".into(), "https://example.invalid/get".into(), "/Users/a/Downloads/x.pkg".into()));
+        assert_eq!((d.start_time, d.end_time, d.total_bytes, d.state, d.danger_type), (1_700_000_010.0, 1_700_000_012.0, 5000, 1, 0));
+        iris_browser_history_free(&mut h);
+    }
+}
@@ -0,0 +1,345 @@
+//! HPACK (RFC 7541) header block decoding for HTTP/2. A decoder holds one
+//! direction's dynamic table, so keep one per connection and direction and feed it
+//! every header block (HEADERS/PUSH_PROMISE plus CONTINUATION payloads) in order.
+
+use crate::ffi::{alloc_array, free_array, free_cstr, to_cstr};
+use std::collections::VecDeque;
+use std::ffi::c_char;
+
+pub const DEFAULT_TABLE_SIZE: usize = 4096;
+const ENTRY_OVERHEAD: usize = 32;
+const MAX_HEADER_LIST: usize = 1 << 20;
+const MAX_CODE_LEN: usize = 30;
+const EOS: u16 = 256;
+
+const STATIC_TABLE: [(&str, &str); 61] = [
+    (":authority", ""), (":method", "GET"), (":method", "POST"), (":path", "/"), (":path", "/index.html"),
+    (":scheme", "http"), (":scheme", "https"), (":status", "200"), (":status", "204"), (":status", "206"),
+    (":status", "304"), (":status", "400"), (":status", "404"), (":status", "500"), ("accept-charset", ""),
+    ("accept-encoding", "gzip, deflate"), ("accept-language", ""), ("accept-ranges", ""), ("accept", ""),
+    ("access-control-allow-origin", ""), ("age", ""), ("allow", ""), ("authorization", ""), ("cache-control", ""),
+    ("content-disposition", ""), ("content-encoding", ""), ("content-language", ""), ("content-length", ""),
+    ("content-location", ""), ("content-range", ""), ("content-type", ""), ("cookie", ""), ("date", ""),
+    ("etag", ""), ("expect", ""), ("expires", ""), ("from", ""), ("host", ""), ("if-match", ""),
+    ("if-modified-since", ""), ("if-none-match", ""), ("if-range", ""), ("if-unmodified-since", ""),
+    ("last-modified", ""), ("link", ""), ("location", ""), ("max-forwards", ""), ("proxy-authenticate", ""),
+    ("proxy-authorization", ""), ("range", ""), ("referer", ""), ("refresh", ""), ("retry-after", ""),
+    ("server", ""), ("set-cookie", ""), ("strict-transport-security", ""), ("transfer-encoding", ""),
+    ("user-agent", ""), ("vary", ""), ("via", ""), ("www-authenticate", ""),
+];
+
+/// Huffman code length per symbol (256 = EOS). The code is canonical, so lengths
+/// alone determine it.
+const HUFFMAN_LENGTHS: [u8; 257] = [
+    13, 23, 28, 28, 28, 28, 28, 28, 28, 24, 30, 28, 28, 30, 28, 28, 28, 28, 28, 28, 28, 28, 30, 28, 28, 28, 28, 28, 28, 28, 28, 28,
+    6, 10, 10, 12, 13, 6, 8, 11, 10, 10, 8, 11, 8, 6, 6, 6, 5, 5, 5, 6, 6, 6, 6, 6, 6, 6, 7, 8, 15, 6, 12, 10,
+    13, 6, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 8, 7, 8, 13, 19, 13, 14, 6,
+    15, 5, 6, 5, 6, 5, 6, 6, 6, 5, 7, 7, 6, 6, 6, 5, 6, 7, 6, 5, 5, 6, 7, 7, 7, 7, 7, 15, 11, 14, 13, 28,
+    20, 22, 20, 20, 22, 22, 22, 23, 22, 23, 23, 23, 23, 23, 24, 23, 24, 24, 22, 23, 24, 23, 23, 23, 23, 21, 22, 23, 22, 23, 23, 24,
+    22, 21, 20, 22, 22, 23, 23, 21, 23, 22, 22, 24, 21, 22, 23, 23, 21, 21, 22, 21, 23, 22, 23, 23, 20, 22, 22, 22, 23, 22, 22, 23,
+    26, 26, 20, 19, 22, 23, 22, 25, 26, 26, 26, 27, 27, 26, 24, 25, 19, 21, 26, 27, 27, 26, 27, 24, 21, 21, 26, 26, 28, 27, 27, 27,
+    20, 24, 20, 21, 22, 21, 21, 23, 22, 22, 25, 25, 24, 24, 26, 23, 26, 27, 26, 26, 27, 27, 27, 27, 27, 28, 27, 27, 27, 27, 27, 26,
+    30,
+];
+
+#[repr(C)]
+pub struct IrisHpackHeader {
+    pub name: *mut c_char,
+    pub value: *mut c_char,
+    pub never_indexed: bool,  // sent as a never-indexed literal (credentials, cookies)
+}
+
+#[repr(C)]
+pub struct IrisHpackHeaders {
+    pub headers: *mut IrisHpackHeader,
+    pub count: usize,
+}
+
+pub struct Header {
+    pub name: Vec<u8>,
+    pub value: Vec<u8>,
+    pub never_indexed: bool,
+}
+
+/// Per-connection decoding state.
+pub struct IrisHpackDecoder {
+    table: VecDeque<(Vec<u8>, Vec<u8>)>, // newest first
+    size: usize,
+    max_size: usize,      // current limit, set by size updates
+    protocol_max: usize,  // SETTINGS_HEADER_TABLE_SIZE the peer may use
+    counts: [u16; MAX_CODE_LEN + 1],
+    symbols: Vec<u16>,    // ordered by (code length, symbol)
+}
+
+struct Input<'a> {
+    d: &'a [u8],
+    pos: usize,
+}
+
+impl Input<'_> {
+    fn byte(&mut self) -> Result<u8, i32> {
+        let b = *self.d.get(self.pos).ok_or(-1)?;
+        self.pos += 1;
+        Ok(b)
+    }
+
+    /// Integer with an N-bit prefix (RFC 7541 §5.1).
+    fn int(&mut self, prefix: u32) -> Result<usize, i32> {
+        let mask = (1u8 << prefix) - 1;
+        let mut v = (self.byte()? & mask) as usize;
+        if v < mask as usize { return Ok(v); }
+        for shift in (0..).step_by(7).take(5) {
+            let b = self.byte()?;
+            v += ((b & 0x7f) as usize) << shift;
+            if b & 0x80 == 0 { return Ok(v); }
+        }
+        Err(-2)
+    }
+}
+
+impl IrisHpackDecoder {
+    pub fn new(max_size: usize) -> Self {
+        let mut counts = [0u16; MAX_CODE_LEN + 1];
+        HUFFMAN_LENGTHS.iter().for_each(|&l| counts[l as usize] += 1);
+        let mut symbols: Vec<u16> = (0..=EOS).collect();
+        symbols.sort_by_key(|&s| HUFFMAN_LENGTHS[s as usize]);
+        IrisHpackDecoder { table: VecDeque::new(), size: 0, max_size, protocol_max: max_size, counts, symbols }
+    }
+
+    fn evict(&mut self) {
+        while self.size > self.max_size {
+            let Some((n, v)) = self.table.pop_back() else { break };
+            self.size -= ENTRY_OVERHEAD + n.len() + v.len();
+        }
+    }
+
+    fn insert(&mut self, name: Vec<u8>, value: Vec<u8>) {
+        let size = ENTRY_OVERHEAD + name.len() + value.len();
+        self.size += size;
+        self.table.push_front((name, value));
+        self.evict();
+    }
+
+    /// Acknowledged SETTINGS_HEADER_TABLE_SIZE from the peer's decoder settings.
+    pub fn set_protocol_max(&mut self, max: usize) {
+        self.protocol_max = max;
+        self.max_size = self.max_size.min(max);
+        self.evict();
+    }
+
+    fn entry(&self, index: usize) -> Result<(Vec<u8>, Vec<u8>), i32> {
+        match index {
+            0 => Err(-2),
+            1..=61 => {
+                let (n, v) = STATIC_TABLE[index - 1];
+                Ok((n.as_bytes().to_vec(), v.as_bytes().to_vec()))
+            }
+            _ => self.table.get(index - 62).cloned().ok_or(-2),
+        }
+    }
+
+    /// Canonical Huffman decode, most significant bit first. Padding must be the
+    /// high bits of EOS and shorter than a byte (§5.2).
+    fn huffman(&self, d: &[u8]) -> Result<Vec<u8>, i32> {
+        let mut out = Vec::with_capacity(d.len() * 8 / 5);
+        let (mut code, mut first, mut index, mut len) = (0u32, 0u32, 0u32, 0usize);
+        let mut ones = true;
+        for i in 0..d.len() * 8 {
+            let bit = (d[i / 8] >> (7 - i % 8)) as u32 & 1;
+            code |= bit;
+            ones &= bit == 1;
+            len += 1;
+            let count = self.counts[len] as u32;
+            if code < first + count {
+                let sym = self.symbols[(index + code - first) as usize];
+                if sym == EOS { return Err(-2); }
+                out.push(sym as u8);
+                (code, first, index, len, ones) = (0, 0, 0, 0, true);
+                continue;
+            }
+            if len == MAX_CODE_LEN { return Err(-2); }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        if len > 7 || !ones { return Err(-2); }
+        Ok(out)
+    }
+
+    fn string(&self, inp: &mut Input) -> Result<Vec<u8>, i32> {
+        let huffman = inp.d.get(inp.pos).ok_or(-1)? & 0x80 != 0;
+        let len = inp.int(7)?;
+        let raw = inp.d.get(inp.pos..inp.pos.checked_add(len).ok_or(-2)?).ok_or(-1)?;
+        inp.pos += len;
+        if huffman { self.huffman(raw) } else { Ok(raw.to_vec()) }
+    }
+
+    /// Decode one complete header block. Errors are HTTP/2 COMPRESSION_ERRORs: the
+    /// table may be left partly updated and the connection should not be decoded further.
+    /// Err(-1) truncated representation, Err(-2) malformed.
+    pub fn decode(&mut self, d: &[u8]) -> Result<Vec<Header>, i32> {
+        let mut inp = Input { d, pos: 0 };
+        let (mut out, mut list_size) = (Vec::new(), 0);
+        while inp.pos < d.len() {
+            let b = d[inp.pos];
+            let (name, value, never_indexed) = if b & 0x80 != 0 {
+                let (n, v) = self.entry(inp.int(7)?)?;
+                (n, v, false)
+            } else if b & 0xe0 == 0x20 {
+                // Size updates are only allowed before the first header
+                if !out.is_empty() { return Err(-2); }
+                let size = inp.int(5)?;
+                if size > self.protocol_max { return Err(-2); }
+                self.max_size = size;
+                self.evict();
+                continue;
+            } else {
+                let (prefix, indexed) = if b & 0x40 != 0 { (6, true) } else { (4, false) };
+                let name = match inp.int(prefix)? {
+                    0 => self.string(&mut inp)?,
+                    i => self.entry(i)?.0,
+                };
+                let value = self.string(&mut inp)?;
+                if indexed { self.insert(name.clone(), value.clone()); }
+                (name, value, b & 0xf0 == 0x10)
+            };
+            list_size += ENTRY_OVERHEAD + name.len() + value.len();
+            if list_size > MAX_HEADER_LIST { return Err(-2); }
+            out.push(Header { name, value, never_indexed });
+        }
+        Ok(out)
+    }
+}
+
+pub fn alloc_headers(headers: Vec<Header>) -> IrisHpackHeaders {
+    let headers: Vec<IrisHpackHeader> = headers.into_iter().map(|h| IrisHpackHeader {
+        name: to_cstr(&String::from_utf8_lossy(&h.name)),
+        value: to_cstr(&String::from_utf8_lossy(&h.value)),
+        never_indexed: h.never_indexed,
+    }).collect();
+    let (headers, count) = alloc_array(headers);
+    IrisHpackHeaders { headers, count }
+}
+
+pub fn free_headers(h: &IrisHpackHeaders) {
+    for i in 0..h.count {
+        let e = unsafe { &*h.headers.add(i) };
+        free_cstr(e.name);
+        free_cstr(e.value);
+    }
+    free_array(h.headers, h.count);
+}
+
+// --- FFI entry points ---
+
+/// Create a decoder for one direction of a connection. `max_table_size` is the
+/// SETTINGS_HEADER_TABLE_SIZE advertised by the decoding side (0 = the 4096 default).
+/// Free with iris_hpack_decoder_free.
+#[no_mangle]
+pub extern "C" fn iris_hpack_decoder_new(max_table_size: u32) -> *mut IrisHpackDecoder {
+    let size = if max_table_size == 0 { DEFAULT_TABLE_SIZE } else { max_table_size as usize };
+    Box::into_raw(Box::new(IrisHpackDecoder::new(size)))
+}
+
+/// Apply a new SETTINGS_HEADER_TABLE_SIZE once the encoder side has acknowledged it.
+#[no_mangle]
+pub extern "C" fn iris_hpack_decoder_set_max_table_size(dec: *mut IrisHpackDecoder, max_table_size: u32) {
+    if dec.is_null() { return; }
+    unsafe { &mut *dec }.set_protocol_max(max_table_size as usize);
+}
+
+/// Decode one complete header block, updating the dynamic table. Names and values
+/// are returned as C strings (invalid UTF-8 is replaced, embedded NULs give "").
+/// Returns 0=ok, -1=truncated block, -2=arg error or COMPRESSION_ERROR (stop decoding
+/// this connection). Free with iris_hpack_headers_free.
+#[no_mangle]
+pub extern "C" fn iris_hpack_decode(dec: *mut IrisHpackDecoder, data: *const u8, len: usize, out: *mut IrisHpackHeaders) -> i32 {
+    if dec.is_null() || out.is_null() || (data.is_null() && len != 0) { return -2; }
+    let d = if len == 0 { &[][..] } else { unsafe { std::slice::from_raw_parts(data, len) } };
+    match unsafe { &mut *dec }.decode(d) {
+        Ok(h) => {
+            unsafe { out.write(alloc_headers(h)); }
+            0
+        }
+        Err(e) => e,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn iris_hpack_headers_free(headers: *mut IrisHpackHeaders) {
+    if headers.is_null() { return; }
+    free_headers(unsafe { &*headers });
+}
+
+#[no_mangle]
+pub extern "C" fn iris_hpack_decoder_free(dec: *mut IrisHpackDecoder) {
+    if dec.is_null() { return; }
+    drop(unsafe { Box::from_raw(dec) });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(s: &str) -> Vec<u8> {
+        let s: String = s.split_whitespace().collect();
+        (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap()).collect()
+    }
+
+    fn pairs(h: &[Header]) -> Vec<(String, String)> {
+        h.iter().map(|h| (String::from_utf8_lossy(&h.name).into_owned(), String::from_utf8_lossy(&h.value).into_owned())).collect()
+    }
+
+    fn p(list: &[(&str, &str)]) -> Vec<(String, String)> {
+        list.iter().map(|(n, v)| (n.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn rfc7541_requests_with_huffman() {
+        // Appendix C.4
+        let mut dec = IrisHpackDecoder::new(DEFAULT_TABLE_SIZE);
+        let h = dec.decode(&hex("8286 8441 8cf1 e3c2 e5f2 3a6b a0ab 90f4 ff")).unwrap();
+        assert_eq!(pairs(&h), p(&[(":method", "GET"), (":scheme", "http"), (":path", "/"), (":authority", "www.example.com")]));
+        let h = dec.decode(&hex("8286 84be 5886 a8eb 1064 9cbf")).unwrap();
+        assert_eq!(pairs(&h)[3..], p(&[(":authority", "www.example.com"), ("cache-control", "no-cache")]));
+        let h = dec.decode(&hex("8287 85bf 4088 25a8 49e9 5ba9 7d7f 8925 a849 e95b b8e8 b4bf")).unwrap();
+        assert_eq!(pairs(&h), p(&[(":method", "GET"), (":scheme", "https"), (":path", "/index.html"),
+                                  (":authority", "www.example.com"), ("custom-key", "custom-value")]));
+        assert_eq!(dec.size, 164);
+    }
+
+    #[test]
+    fn rfc7541_responses_with_eviction() {
+        // Appendix C.6, with a 256-byte table
+        let mut dec = IrisHpackDecoder::new(256);
+        let h = dec.decode(&hex("4882 6402 5885 aec3 771a 4b61 96d0 7abe 9410 54d4 44a8 2005 9504 0b81 66e0 82a6
+                                 2d1b ff6e 919d 29ad 1718 63c7 8f0b 97c8 e9ae 82ae 43d3")).unwrap();
+        assert_eq!(pairs(&h), p(&[(":status", "302"), ("cache-control", "private"), ("date", "Mon, 21 Oct 2013 20:13:21 GMT"),
+                                  ("location", "https://www.example.com")]));
+        assert_eq!(dec.size, 222);
+        let h = dec.decode(&hex("4883 640e ffc1 c0bf")).unwrap();
+        assert_eq!(pairs(&h)[0], p(&[(":status", "307")])[0]);
+        assert_eq!((dec.table.len(), dec.size), (4, 222));
+    }
+
+    #[test]
+    fn errors_and_ffi() {
+        let dec = iris_hpack_decoder_new(0);
+        let mut out = std::mem::MaybeUninit::<IrisHpackHeaders>::uninit();
+        // never-indexed literal with a literal name
+        let block = [0x10, 0x06, b's', b'e', b'c', b'r', b'e', b't', 0x02, b'h', b'i'];
+        assert_eq!(iris_hpack_decode(dec, block.as_ptr(), block.len(), out.as_mut_ptr()), 0);
+        let mut h = unsafe { out.assume_init() };
+        let e = unsafe { &*h.headers };
+        assert_eq!((h.count, e.never_indexed), (1, true));
+        assert_eq!(unsafe { std::ffi::CStr::from_ptr(e.value) }.to_str().unwrap(), "hi");
+        iris_hpack_headers_free(&mut h);
+
+        let mut out = std::mem::MaybeUninit::<IrisHpackHeaders>::uninit();
+        for (bad, rc) in [(&[0x80][..], -2), (&[0xff, 0x80][..], -1), (&[0xc0][..], -2), (&[0x3f, 0xe2, 0x1f][..], -2),
+                          (&[0x82, 0x20][..], -2), (&[0x00, 0x81, 0x00][..], -2), (&[0x00, 0x05, b'a'][..], -1)] {
+            assert_eq!(iris_hpack_decode(dec, bad.as_ptr(), bad.len(), out.as_mut_ptr()), rc, "{:?}", bad);
+        }
+        iris_hpack_decoder_free(dec);
+    }
+}
@@ -0,0 +1,364 @@
+//! HTTP/1.x connection tracking: both directions of one TCP connection are read with
+//! httpstream.rs and each response is paired with the oldest unanswered request,
+//! giving the transactions of the connection in order. Interim 1xx responses
+//! (100 Continue, 103 Early Hints) are recorded on the transaction rather than
+//! ending it. Keep-alive state follows `should_close`; requests sent after either
+//! side asked to close are flagged. A 101 response, or a 2xx answer to CONNECT,
+//! ends HTTP on the connection: the transaction completes and both directions are
+//! passed over as a tunnel from then on. The connection is internally locked.
+
+use crate::ffi::{alloc_array, free_array};
+use crate::httpstream::{self, HttpStream, IrisHttpStreamMessage, Message};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+#[repr(C)]
+pub struct IrisHttpTransaction {
+    pub has_request: bool,           // false for a response with no request seen (midstream)
+    pub has_response: bool,          // false if the connection closed before one arrived
+    pub request: IrisHttpStreamMessage,
+    pub response: IrisHttpStreamMessage,
+    pub interim_status: u16,         // last 1xx before the final response (100 Continue), 0 if none
+    pub connection_close: bool,      // either side asked to close after this exchange
+    pub after_close: bool,           // request sent after the connection should have closed
+}
+
+#[repr(C)]
+pub struct IrisHttpTransactions {
+    pub transactions: *mut IrisHttpTransaction,
+    pub count: usize,
+}
+
+pub struct IrisHttpConnection {
+    inner: Mutex<Connection>,
+}
+
+pub struct Transaction {
+    pub request: Option<Message>,
+    pub response: Option<Message>,
+    pub interim_status: u16,
+    pub after_close: bool,
+}
+
+pub struct Connection {
+    requests: HttpStream,
+    responses: HttpStream,
+    /// Requests still waiting for their response, with their after_close flag.
+    pending: VecDeque<(Message, bool)>,
+    /// Final responses that arrived while their request's body was still being sent,
+    /// e.g. a refused Expect: 100-continue, with their interim status.
+    early: VecDeque<(Message, u16)>,
+    /// Last interim status for the oldest unanswered request.
+    interim: u16,
+    done: VecDeque<Transaction>,
+    closing: bool,
+    failed: bool,
+}
+
+impl Default for Connection {
+    fn default() -> Self { Self::new() }
+}
+
+impl Connection {
+    pub fn new() -> Self {
+        let mut requests = HttpStream::new(true);
+        requests.hold_upgrades();
+        Connection {
+            requests,
+            responses: HttpStream::new(false),
+            pending: VecDeque::new(),
+            early: VecDeque::new(),
+            interim: 0,
+            done: VecDeque::new(),
+            closing: false,
+            failed: false,
+        }
+    }
+
+    pub fn feed(&mut self, from_client: bool, data: &[u8]) -> Result<(), i32> {
+        if self.failed { return Err(-2); }
+        let r = if from_client { self.requests.feed(data) } else { self.responses.feed(data) };
+        let r = r.and(self.collect());
+        if r.is_err() { self.failed = true; }
+        r
+    }
+
+    /// The TCP connection ended: finish read-until-close responses and report
+    /// unanswered requests.
+    pub fn close(&mut self) {
+        self.requests.close();
+        self.responses.close();
+        let _ = self.collect();
+        for (req, after_close) in self.pending.drain(..) {
+            self.done.push_back(Transaction { request: Some(req), response: None, interim_status: 0, after_close });
+        }
+        for (resp, interim_status) in self.early.drain(..) {
+            self.done.push_back(Transaction { request: None, response: Some(resp), interim_status, after_close: false });
+        }
+    }
+
+    pub fn take(&mut self) -> Vec<Transaction> {
+        self.done.drain(..).collect()
+    }
+
+    fn collect(&mut self) -> Result<(), i32> {
+        loop {
+            let mut refused = false;
+            for req in self.requests.take() {
+                let after_close = self.closing;
+                self.closing |= req.should_close;
+                if let Some((resp, interim_status)) = self.early.pop_front() {
+                    refused |= self.answer(req, resp, interim_status, after_close);
+                    continue;
+                }
+                if req.method.eq_ignore_ascii_case("CONNECT") {
+                    self.responses.expect_connect();
+                } else {
+                    self.responses.expect_response(req.method.eq_ignore_ascii_case("HEAD"));
+                }
+                self.pending.push_back((req, after_close));
+            }
+            for resp in self.responses.take() {
+                if resp.interim {
+                    self.interim = resp.status;
+                    continue;
+                }
+                self.closing |= resp.should_close;
+                let interim_status = std::mem::take(&mut self.interim);
+                match self.pending.pop_front() {
+                    Some((req, after_close)) => refused |= self.answer(req, resp, interim_status, after_close),
+                    // Answered before the request finished: pair once it does, or at close
+                    None if self.requests.in_progress().is_some() => self.early.push_back((resp, interim_status)),
+                    None => {
+                        if resp.upgrade { self.requests.tunnel(); }
+                        self.done.push_back(Transaction { request: None, response: Some(resp), interim_status, after_close: false });
+                    }
+                }
+            }
+            // The requests held back behind a refused upgrade are HTTP after all
+            if !refused { return Ok(()); }
+            self.requests.resume()?;
+        }
+    }
+
+    /// Record a finished transaction; true if the request offered to switch
+    /// protocols and the response declined.
+    fn answer(&mut self, req: Message, resp: Message, interim_status: u16, after_close: bool) -> bool {
+        let refused = req.upgrade && !resp.upgrade;
+        if resp.upgrade { self.requests.tunnel(); }
+        self.done.push_back(Transaction { request: Some(req), response: Some(resp), interim_status, after_close });
+        refused
+    }
+}
+
+fn stream_message(m: Option<Message>) -> IrisHttpStreamMessage {
+    httpstream::message(m.unwrap_or(Message { content_length: -1, ..Default::default() }))
+}
+
+// --- FFI ---
+
+/// Create a tracker for one HTTP/1.x connection. Free with iris_http_connection_free.
+#[no_mangle]
+pub extern "C" fn iris_http_connection_new() -> *mut IrisHttpConnection {
+    Box::into_raw(Box::new(IrisHttpConnection { inner: Mutex::new(Connection::new()) }))
+}
+
+/// Feed bytes of one direction in stream order. Returns 0=ok, -2=arg error or
+/// malformed message, -3=head over the size limits. After an error every later
+/// call returns -2; transactions finished before it can still be taken.
+#[no_mangle]
+pub extern "C" fn iris_http_connection_feed(conn: *mut IrisHttpConnection, from_client: bool, data: *const u8, len: usize) -> i32 {
+    if conn.is_null() || (data.is_null() && len != 0) { return -2; }
+    let d = if len == 0 { &[][..] } else { unsafe { std::slice::from_raw_parts(data, len) } };
+    let mut c = unsafe { &*conn }.inner.lock().unwrap_or_else(|e| e.into_inner());
+    match c.feed(from_client, d) {
+        Ok(()) => 0,
+        Err(e) => e,
+    }
+}
+
+/// The TCP connection closed (FIN/RST or flow expiry): completes a response read
+/// until close and reports requests that never got a response.
+#[no_mangle]
+pub extern "C" fn iris_http_connection_close(conn: *mut IrisHttpConnection) {
+    if conn.is_null() { return; }
+    unsafe { &*conn }.inner.lock().unwrap_or_else(|e| e.into_inner()).close();
+}
+
+/// Take the transactions finished since the last call, in order.
+/// Returns 0=ok, -2=arg error. Free with iris_http_transactions_free.
+#[no_mangle]
+pub extern "C" fn iris_http_connection_take(conn: *mut IrisHttpConnection, out: *mut IrisHttpTransactions) -> i32 {
+    if conn.is_null() || out.is_null() { return -2; }
+    let done = unsafe { &*conn }.inner.lock().unwrap_or_else(|e| e.into_inner()).take();
+    let transactions: Vec<IrisHttpTransaction> = done.into_iter().map(|t| IrisHttpTransaction {
+        has_request: t.request.is_some(),
+        has_response: t.response.is_some(),
+        connection_close: t.request.as_ref().is_some_and(|m| m.should_close)
+            || t.response.as_ref().is_some_and(|m| m.should_close),
+        interim_status: t.interim_status,
+        after_close: t.after_close,
+        request: stream_message(t.request),
+        response: stream_message(t.response),
+    }).collect();
+    let (transactions, count) = alloc_array(transactions);
+    unsafe { out.write(IrisHttpTransactions { transactions, count }); }
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn iris_http_transactions_free(t: *mut IrisHttpTransactions) {
+    if t.is_null() { return; }
+    let t = unsafe { &*t };
+    for i in 0..t.count {
+        let x = unsafe { &*t.transactions.add(i) };
+        httpstream::free_message(&x.request);
+        httpstream::free_message(&x.response);
+    }
+    free_array(t.transactions, t.count);
+}
+
+#[no_mangle]
+pub extern "C" fn iris_http_connection_free(conn: *mut IrisHttpConnection) {
+    if conn.is_null() { return; }
+    drop(unsafe { Box::from_raw(conn) });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+
+    #[test]
+    fn pairs_pipelined_transactions() {
+        let mut c = Connection::new();
+        c.feed(true, b"HEAD /a HTTP/1.1\r\nHost: x\r\n\r\nGET /b HTTP/1.1\r\nHost: x\r\n\r\n").unwrap();
+        // The HEAD response advertises a length but carries no body
+        c.feed(false, b"HTTP/1.1 200 OK\r\nContent-Length: 1000\r\n\r\n").unwrap();
+        c.feed(false, b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nok\r\n").unwrap();
+        let t = c.take();
+        assert_eq!(t.len(), 1);
+        assert_eq!(t[0].request.as_ref().unwrap().method, "HEAD");
+        assert_eq!(t[0].response.as_ref().unwrap().body_len, 0);
+        c.feed(false, b"0\r\n\r\n").unwrap();
+        let t = c.take();
+        assert_eq!(t[0].request.as_ref().unwrap().target, "/b");
+        assert_eq!(t[0].response.as_ref().unwrap().body_len, 2);
+    }
+
+    #[test]
+    fn expect_continue() {
+        let mut c = Connection::new();
+        c.feed(true, b"PUT /f HTTP/1.1\r\nContent-Length: 4\r\nExpect: 100-continue\r\n\r\n").unwrap();
+        c.feed(false, b"HTTP/1.1 100 Continue\r\n\r\n").unwrap();
+        c.feed(true, b"data").unwrap();
+        assert!(c.take().is_empty()); // the interim response does not end the transaction
+        c.feed(false, b"HTTP/1.1 201 Created\r\nContent-Length: 0\r\n\r\n").unwrap();
+        let t = c.take();
+        assert_eq!(t.len(), 1);
+        assert_eq!(t[0].interim_status, 100);
+        assert!(t[0].request.as_ref().unwrap().expect_continue);
+        assert_eq!(t[0].response.as_ref().unwrap().status, 201);
+
+        // Refused without a 100: the client gives up on the body and closes
+        c.feed(true, b"PUT /big HTTP/1.1\r\nContent-Length: 99999\r\nExpect: 100-continue\r\n\r\n").unwrap();
+        c.feed(false, b"HTTP/1.1 413 Payload Too Large\r\nContent-Length: 0\r\n\r\n").unwrap();
+        assert!(c.take().is_empty());
+        c.close();
+        let t = c.take();
+        assert_eq!(t.len(), 1);
+        assert!(!t[0].request.as_ref().unwrap().complete);
+        assert_eq!((t[0].response.as_ref().unwrap().status, t[0].interim_status), (413, 0));
+    }
+
+    #[test]
+    fn close_and_after_close() {
+        let mut c = Connection::new();
+        c.feed(true, b"GET /1 HTTP/1.1\r\nConnection: close\r\n\r\nGET /2 HTTP/1.1\r\n\r\n").unwrap();
+        c.feed(false, b"HTTP/1.1 200 OK\r\n\r\nbody until close").unwrap();
+        assert!(c.take().is_empty());
+        c.close();
+        let t = c.take();
+        assert_eq!(t.len(), 2);
+        let resp = t[0].response.as_ref().unwrap();
+        assert!(resp.complete && resp.body_len == 16);
+        assert!(!t[0].after_close);
+        assert!(t[1].after_close && t[1].response.is_none());
+    }
+
+    #[test]
+    fn ffi_transactions() {
+        let c = iris_http_connection_new();
+        let req = b"POST /x HTTP/1.1\r\nContent-Length: 2\r\n\r\nhi";
+        let resp = b"HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n";
+        assert_eq!(iris_http_connection_feed(c, false, resp.as_ptr(), 0), 0);
+        assert_eq!(iris_http_connection_feed(c, true, req.as_ptr(), req.len()), 0);
+        assert_eq!(iris_http_connection_feed(c, false, resp.as_ptr(), resp.len()), 0);
+        let mut out = std::mem::MaybeUninit::<IrisHttpTransactions>::uninit();
+        assert_eq!(iris_http_connection_take(c, out.as_mut_ptr()), 0);
+        let mut t = unsafe { out.assume_init() };
+        assert_eq!(t.count, 1);
+        let x = unsafe { &*t.transactions };
+        assert!(x.has_request && x.has_response && x.connection_close);
+        assert_eq!(unsafe { CStr::from_ptr(x.request.target) }.to_bytes(), b"/x");
+        assert_eq!(x.response.status_code, 204);
+        iris_http_transactions_free(&mut t);
+        assert_eq!(iris_http_connection_feed(c, true, b"\x00\x01".as_ptr(), 2), -2);
+        iris_http_connection_free(c);
+    }
+
+    #[test]
+    fn connect_tunnel() {
+        let mut c = Connection::new();
+        // The client starts the TLS handshake without waiting for the answer
+        c.feed(true, b"CONNECT example.com:443 HTTP/1.1\r\nHost: example.com:443\r\n\r\n\x16\x03\x01\x02\x00").unwrap();
+        assert!(c.take().is_empty());
+        c.feed(false, b"HTTP/1.1 200 Connection Established\r\n\r\n\x16\x03\x03\x00\x7a").unwrap();
+        let t = c.take();
+        assert_eq!(t.len(), 1);
+        assert_eq!(t[0].request.as_ref().unwrap().method, "CONNECT");
+        let resp = t[0].response.as_ref().unwrap();
+        assert!(resp.complete && resp.upgrade && resp.body_len == 0);
+        c.feed(true, b"\x17\x03\x03\x00\x10 not http at all").unwrap();
+        c.feed(false, b"\x17\x03\x03\x00\x10 nor this").unwrap();
+        c.close();
+        assert!(c.take().is_empty());
+
+        // Refused: the next request was held back and is parsed as HTTP
+        let mut c = Connection::new();
+        c.feed(true, b"CONNECT a:443 HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\n\r\n").unwrap();
+        c.feed(false, b"HTTP/1.1 407 Proxy Authentication Required\r\nContent-Length: 0\r\n\r\n").unwrap();
+        let t = c.take();
+        assert_eq!(t.len(), 1);
+        assert!(!t[0].response.as_ref().unwrap().upgrade);
+        c.feed(false, b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok").unwrap();
+        let t = c.take();
+        assert_eq!(t[0].request.as_ref().unwrap().target, "/");
+        assert_eq!(t[0].response.as_ref().unwrap().body_len, 2);
+    }
+
+    #[test]
+    fn websocket_upgrade() {
+        let mut c = Connection::new();
+        c.feed(true, b"GET /ws HTTP/1.1\r\nHost: x\r\nConnection: Upgrade\r\nUpgrade: websocket\r\n\r\n").unwrap();
+        c.feed(false, b"HTTP/1.1 101 Switching Protocols\r\nConnection: Upgrade\r\nUpgrade: websocket\r\n\r\n\x81\x02hi").unwrap();
+        let t = c.take();
+        assert_eq!(t.len(), 1);
+        assert!(t[0].request.as_ref().unwrap().upgrade);
+        let resp = t[0].response.as_ref().unwrap();
+        assert_eq!((resp.status, resp.body_len), (101, 0));
+        assert!(resp.complete && t[0].interim_status == 0);
+        c.feed(true, b"\x81\x82mask\x00\x00").unwrap();
+        c.feed(false, b"\x88\x00").unwrap();
+        c.close();
+        assert!(c.take().is_empty());
+
+        // Declined: the server answers over HTTP and keeps the connection
+        let mut c = Connection::new();
+        c.feed(true, b"GET / HTTP/1.1\r\nUpgrade: h2c\r\n\r\n").unwrap();
+        c.feed(false, b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n").unwrap();
+        c.feed(true, b"GET /next HTTP/1.1\r\n\r\n").unwrap();
+        c.feed(false, b"HTTP/1.1 204 No Content\r\n\r\n").unwrap();
+        assert_eq!(c.take().len(), 2);
+    }
+}
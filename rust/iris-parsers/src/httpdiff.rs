@@ -0,0 +1,356 @@
+//! Differential parsing of HTTP/1.x request streams: the same bytes are split into
+//! requests under several interpretation profiles — strict RFC 9112, and the
+//! leniencies of nginx-like and Apache-like front ends — and the message boundaries
+//! are compared. When two profiles that both accept the bytes end a request at
+//! different offsets, a front end and a back end with those behaviours would
+//! disagree about where the next request starts: request smuggling.
+//!
+//! The profiles model the behaviour classes that matter for framing, not any one
+//! server version. smuggling_flags in http.rs names the constructs involved.
+
+use crate::ffi::{alloc_array, free_array, free_cstr, to_cstr};
+use std::ffi::c_char;
+use std::slice;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Colon {
+    Reject, // "Name : value" is a 400
+    Ignore, // the line is dropped (nginx ignore_invalid_headers)
+    Strip,  // the name is trimmed and the header used
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum TeMatch {
+    Last,      // the final coding is "chunked", else the request is refused
+    Exact,     // the whole value is "chunked", else the request is refused
+    Substring, // any value mentioning "chunked" is chunked; others are ignored
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum TeCl {
+    Reject,
+    TeWins,
+}
+
+pub struct Profile {
+    pub name: &'static str,
+    bare_lf: bool,          // LF alone ends a line
+    unfold: bool,           // obs-fold continuation lines join the previous value
+    colon: Colon,
+    te: TeMatch,
+    te_cl: TeCl,
+    te_http10: bool,        // Transfer-Encoding honoured in HTTP/1.0 requests
+    cl_first: bool,         // differing Content-Length values: first wins instead of 400
+    cl_lenient: bool,       // "+5", " 5 abc": leading digits after an optional sign
+}
+
+pub const PROFILES: [Profile; 3] = [
+    Profile {
+        name: "strict", bare_lf: false, unfold: false, colon: Colon::Reject, te: TeMatch::Last,
+        te_cl: TeCl::Reject, te_http10: false, cl_first: false, cl_lenient: false,
+    },
+    Profile {
+        name: "nginx", bare_lf: true, unfold: false, colon: Colon::Ignore, te: TeMatch::Exact,
+        te_cl: TeCl::TeWins, te_http10: true, cl_first: false, cl_lenient: false,
+    },
+    Profile {
+        name: "apache", bare_lf: true, unfold: true, colon: Colon::Strip, te: TeMatch::Substring,
+        te_cl: TeCl::TeWins, te_http10: true, cl_first: true, cl_lenient: true,
+    },
+];
+
+#[repr(C)]
+pub struct IrisHttpDiffProfile {
+    pub name: *mut c_char,       // "strict", "nginx", "apache"
+    pub boundaries: *mut usize,  // end offset of each complete request, in order
+    pub count: usize,
+    pub rest_status: i32,        // after the last boundary: 0=nothing, -1=incomplete, -2=rejected
+}
+
+#[repr(C)]
+pub struct IrisHttpDiff {
+    pub profiles: *mut IrisHttpDiffProfile,
+    pub count: usize,
+    pub boundaries_differ: bool,  // two profiles end a request at different offsets
+    pub acceptance_differs: bool, // a profile rejects a request another one accepts
+    pub divergence: usize,        // start of the first request the profiles disagree on
+}
+
+pub struct Split {
+    pub boundaries: Vec<usize>,
+    pub rest_status: i32,
+}
+
+pub struct Diff {
+    pub splits: Vec<Split>,
+    pub boundaries_differ: bool,
+    pub acceptance_differs: bool,
+    pub divergence: usize,
+}
+
+/// Next line of `d` from `p` under the profile's line endings: (line, next offset).
+/// Err(-1) no line end yet, Err(-2) bare LF where CRLF is required.
+fn line<'a>(d: &'a [u8], p: usize, pr: &Profile) -> Result<(&'a [u8], usize), i32> {
+    let lf = d[p..].iter().position(|&b| b == b'\n').ok_or(-1)?;
+    match d[p..p + lf].strip_suffix(b"\r") {
+        Some(l) => Ok((l, p + lf + 1)),
+        None if pr.bare_lf => Ok((&d[p..p + lf], p + lf + 1)),
+        None => Err(-2),
+    }
+}
+
+struct Head {
+    len: usize,
+    http10: bool,
+    headers: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+fn head(d: &[u8], pr: &Profile) -> Result<Head, i32> {
+    let (start, mut p) = line(d, 0, pr)?;
+    let parts: Vec<&[u8]> = start.split(|&b| b == b' ').collect();
+    let [method, target, version] = parts[..] else { return Err(-2) };
+    if method.is_empty() || target.is_empty() || !version.starts_with(b"HTTP/1.") { return Err(-2); }
+    let mut headers: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
+    loop {
+        let (l, next) = line(d, p, pr)?;
+        p = next;
+        if l.is_empty() { break; }
+        if l[0] == b' ' || l[0] == b'\t' {
+            let Some(last) = headers.last_mut().filter(|_| pr.unfold) else { return Err(-2) };
+            last.1.push(b' ');
+            last.1.extend_from_slice(l.trim_ascii());
+            continue;
+        }
+        let colon = l.iter().position(|&b| b == b':').ok_or(-2)?;
+        let name = &l[..colon];
+        let trimmed = name.trim_ascii_end();
+        if trimmed.is_empty() || trimmed.iter().any(|&b| b <= b' ' || b == 0x7f) { return Err(-2); }
+        if trimmed.len() != name.len() {
+            match pr.colon {
+                Colon::Reject => return Err(-2),
+                Colon::Ignore => continue,
+                Colon::Strip => {}
+            }
+        }
+        headers.push((trimmed.to_ascii_lowercase(), l[colon + 1..].trim_ascii().to_vec()));
+    }
+    Ok(Head { len: p, http10: version == b"HTTP/1.0", headers })
+}
+
+fn content_length(v: &[u8], pr: &Profile) -> Option<usize> {
+    let digits = if pr.cl_lenient {
+        let v = v.strip_prefix(b"+").unwrap_or(v);
+        &v[..v.iter().take_while(|b| b.is_ascii_digit()).count()]
+    } else {
+        v
+    };
+    if digits.is_empty() || !digits.iter().all(u8::is_ascii_digit) { return None; }
+    std::str::from_utf8(digits).ok()?.parse().ok()
+}
+
+/// Length of a chunked body starting at `p`, under the profile's line endings.
+fn chunked(d: &[u8], mut p: usize, pr: &Profile) -> Result<usize, i32> {
+    loop {
+        let (l, next) = line(d, p, pr)?;
+        let hex = l.split(|&b| b == b';').next().unwrap_or_default();
+        let hex = if pr.cl_lenient { hex.trim_ascii() } else { hex };
+        if hex.is_empty() || hex.len() > 16 || !hex.iter().all(u8::is_ascii_hexdigit) { return Err(-2); }
+        let size = usize::from_str_radix(std::str::from_utf8(hex).map_err(|_| -2)?, 16).map_err(|_| -2)?;
+        p = next;
+        if size == 0 { break; }
+        let end = p.checked_add(size).ok_or(-2)?;
+        if end >= d.len() { return Err(-1); }
+        let (l, next) = line(d, end, pr)?;
+        if !l.is_empty() { return Err(-2); }
+        p = next;
+    }
+    loop {
+        let (l, next) = line(d, p, pr)?;
+        p = next;
+        if l.is_empty() { return Ok(p); }
+    }
+}
+
+/// Length of the request at the start of `d` under one profile.
+/// Err(-1) incomplete, Err(-2) the profile rejects it.
+fn request_len(d: &[u8], pr: &Profile) -> Result<usize, i32> {
+    let h = head(d, pr)?;
+    let values = |n: &[u8]| h.headers.iter().filter(|(k, _)| k == n).map(|(_, v)| v.as_slice()).collect::<Vec<_>>();
+    let (tes, cls) = (values(b"transfer-encoding"), values(b"content-length"));
+
+    let te = tes.join(&b","[..]).to_ascii_lowercase();
+    let is_chunked = !tes.is_empty() && (pr.te_http10 || !h.http10) && match pr.te {
+        TeMatch::Last => {
+            if te.rsplit(|&b| b == b',').next().unwrap_or_default().trim_ascii() != b"chunked" { return Err(-2); }
+            true
+        }
+        TeMatch::Exact => {
+            if te.trim_ascii() != b"chunked" { return Err(-2); }
+            true
+        }
+        TeMatch::Substring => te.windows(7).any(|w| w == b"chunked"),
+    };
+    if !tes.is_empty() && !cls.is_empty() && pr.te_cl == TeCl::Reject { return Err(-2); }
+    if !tes.is_empty() && h.http10 && !pr.te_http10 { return Err(-2); }
+    if is_chunked { return chunked(d, h.len, pr); }
+
+    let mut lengths: Vec<usize> = Vec::new();
+    for v in cls {
+        let n = content_length(v, pr).ok_or(-2)?;
+        if !lengths.contains(&n) { lengths.push(n); }
+    }
+    if lengths.len() > 1 && !pr.cl_first { return Err(-2); }
+    let end = h.len.checked_add(lengths.first().copied().unwrap_or(0)).ok_or(-2)?;
+    if end > d.len() { Err(-1) } else { Ok(end) }
+}
+
+/// Split a request stream into requests under one profile.
+pub fn split(d: &[u8], pr: &Profile) -> Split {
+    let (mut boundaries, mut p) = (Vec::new(), 0);
+    while p < d.len() {
+        match request_len(&d[p..], pr) {
+            Ok(n) => {
+                p += n;
+                boundaries.push(p);
+            }
+            Err(e) => return Split { boundaries, rest_status: e },
+        }
+    }
+    Split { boundaries, rest_status: 0 }
+}
+
+/// Split `d` under every profile and compare. A request still incomplete under one
+/// profile ends after any boundary inside the buffer, so waiting for more body
+/// bytes where another profile already sees the next request counts as a
+/// difference; a rejection only differs in acceptance.
+pub fn differential(d: &[u8]) -> Diff {
+    let splits: Vec<Split> = PROFILES.iter().map(|pr| split(d, pr)).collect();
+    let ends = |s: &Split| {
+        let mut e = s.boundaries.clone();
+        if s.rest_status == -1 { e.push(usize::MAX); }
+        e
+    };
+    let mut first: Option<usize> = None;
+    let mut boundaries_differ = false;
+    for (i, a) in splits.iter().enumerate() {
+        for b in &splits[i + 1..] {
+            let (ea, eb) = (ends(a), ends(b));
+            if let Some(k) = ea.iter().zip(&eb).position(|(x, y)| x != y) {
+                boundaries_differ = true;
+                first = Some(first.map_or(k, |f| f.min(k)));
+            }
+        }
+    }
+    let rejected_at = |s: &Split| (s.rest_status == -2).then_some(s.boundaries.len());
+    let acceptance_differs = splits.iter().any(|s| rejected_at(s) != rejected_at(&splits[0]));
+    if let Some(k) = splits.iter().filter_map(rejected_at).min().filter(|_| acceptance_differs) {
+        first = Some(first.map_or(k, |f| f.min(k)));
+    }
+    // The divergence is where the disputed request starts, which every profile agrees on
+    let divergence = match first {
+        Some(0) | None => 0,
+        Some(k) => splits.iter().find_map(|s| s.boundaries.get(k - 1)).copied().unwrap_or(0),
+    };
+    Diff { splits, boundaries_differ, acceptance_differs, divergence }
+}
+
+// --- FFI ---
+
+/// Split a buffer of back-to-back HTTP/1.x requests under the strict, nginx-like and
+/// Apache-like profiles and report where their boundaries disagree.
+/// Returns 0=ok, -2=arg error. Free with iris_http_diff_free.
+#[no_mangle]
+pub extern "C" fn iris_http_differential(data: *const u8, len: usize, out: *mut IrisHttpDiff) -> i32 {
+    if out.is_null() || (data.is_null() && len != 0) { return -2; }
+    let d = if len == 0 { &[][..] } else { unsafe { slice::from_raw_parts(data, len) } };
+    let diff = differential(d);
+    let profiles = diff.splits.into_iter().zip(PROFILES.iter()).map(|(s, pr)| {
+        let (boundaries, count) = alloc_array(s.boundaries);
+        IrisHttpDiffProfile { name: to_cstr(pr.name), boundaries, count, rest_status: s.rest_status }
+    }).collect();
+    let (profiles, count) = alloc_array(profiles);
+    unsafe {
+        out.write(IrisHttpDiff {
+            profiles,
+            count,
+            boundaries_differ: diff.boundaries_differ,
+            acceptance_differs: diff.acceptance_differs,
+            divergence: diff.divergence,
+        });
+    }
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn iris_http_diff_free(d: *mut IrisHttpDiff) {
+    if d.is_null() { return; }
+    let d = unsafe { &*d };
+    for i in 0..d.count {
+        let p = unsafe { &*d.profiles.add(i) };
+        free_cstr(p.name);
+        free_array(p.boundaries, p.count);
+    }
+    free_array(d.profiles, d.count);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bounds(d: &[u8]) -> Vec<(Vec<usize>, i32)> {
+        differential(d).splits.into_iter().map(|s| (s.boundaries, s.rest_status)).collect()
+    }
+
+    #[test]
+    fn clean_pipeline_agrees() {
+        let d = b"GET /a HTTP/1.1\r\nHost: x\r\n\r\nPOST /b HTTP/1.1\r\nContent-Length: 2\r\n\r\nhi";
+        let diff = differential(d);
+        assert!(!diff.boundaries_differ && !diff.acceptance_differs);
+        assert!(diff.splits.iter().all(|s| s.boundaries == [28, d.len()] && s.rest_status == 0));
+    }
+
+    #[test]
+    fn space_before_colon_desync() {
+        // nginx drops the malformed TE and frames by Content-Length; Apache uses TE
+        let d = b"POST / HTTP/1.1\r\nContent-Length: 4\r\nTransfer-Encoding : chunked\r\n\r\n0\r\n\r\nGET /x HTTP/1.1\r\n\r\n";
+        let b = bounds(d);
+        assert_eq!(b[0], (vec![], -2));
+        assert_eq!(b[1].0[0], 71);
+        assert_eq!(b[2].0[0], 72);
+        let diff = differential(d);
+        assert!(diff.boundaries_differ && diff.acceptance_differs);
+        assert_eq!(diff.divergence, 0);
+    }
+
+    #[test]
+    fn obfuscated_te_and_cl() {
+        // Only the substring match reads "xchunked" as chunked
+        let d = b"GET /ok HTTP/1.1\r\n\r\nPOST / HTTP/1.1\r\nTransfer-Encoding: xchunked\r\n\r\n";
+        let diff = differential(d);
+        assert_eq!(bounds(d)[2], (vec![20], -1));
+        assert!(diff.acceptance_differs && !diff.boundaries_differ);
+        assert_eq!(diff.divergence, 20);
+
+        // Apache-like takes the first length and sees "bc" start the next request
+        let d = b"POST / HTTP/1.1\r\nContent-Length: 1\r\nContent-Length: 3\r\n\r\nabc";
+        assert_eq!(bounds(d).iter().map(|b| b.1).collect::<Vec<_>>(), [-2, -2, -1]);
+        // Folding and bare LF
+        let d = b"POST / HTTP/1.1\nContent-Length: 0\nX: a\n b\n\n";
+        assert_eq!(bounds(d).iter().map(|b| b.1).collect::<Vec<_>>(), [-2, -2, 0]);
+    }
+
+    #[test]
+    fn ffi_diff() {
+        let d = b"POST / HTTP/1.1\r\nContent-Length: +2\r\n\r\nhi";
+        let mut out = std::mem::MaybeUninit::<IrisHttpDiff>::uninit();
+        assert_eq!(iris_http_differential(d.as_ptr(), d.len(), out.as_mut_ptr()), 0);
+        let mut diff = unsafe { out.assume_init_read() };
+        assert_eq!(diff.count, 3);
+        let apache = unsafe { &*diff.profiles.add(2) };
+        assert_eq!(unsafe { std::ffi::CStr::from_ptr(apache.name) }.to_bytes(), b"apache");
+        assert_eq!((apache.count, unsafe { *apache.boundaries }), (1, d.len()));
+        assert!(diff.acceptance_differs);
+        iris_http_diff_free(&mut diff);
+        assert_eq!(iris_http_differential(std::ptr::null(), 1, out.as_mut_ptr()), -2);
+    }
+}
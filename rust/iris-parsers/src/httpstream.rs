@@ -0,0 +1,498 @@
+//! Incremental HTTP/1.x reader for one direction of a connection. Bytes are fed as
+//! they arrive; each message's end is found from its framing (Content-Length,
+//! chunked, or the connection closing) and the decoded body is hashed with SHA-256
+//! on the way through, so bodies never need to be buffered. Only message heads and
+//! chunk-size lines are held between calls. After a 101 response, or a 2xx answer
+//! to CONNECT, the connection carries another protocol and the rest is passed over.
+//! The stream is internally locked.
+
+use crate::ffi::{alloc_array, free_array, free_cstr, to_cstr};
+use crate::hash::Sha256;
+use crate::http;
+use std::collections::VecDeque;
+use std::ffi::c_char;
+use std::sync::Mutex;
+
+/// Largest message head or chunk line held while waiting for its end.
+const MAX_HEAD: usize = 1 << 20;
+const MAX_CHUNK_LINE: usize = 4096;
+
+#[repr(C)]
+pub struct IrisHttpStreamMessage {
+    pub is_request: bool,
+    pub method: *mut c_char,      // requests, "" for responses
+    pub target: *mut c_char,      // request-target, "" for responses
+    pub status_code: u16,         // responses, 0 for requests
+    pub version_minor: u8,
+    pub header_len: usize,
+    pub content_length: i64,      // -1 = absent
+    pub is_chunked: bool,
+    pub should_close: bool,       // Connection: close, or HTTP/1.0 without keep-alive
+    pub expect_continue: bool,    // request sent Expect: 100-continue
+    pub interim: bool,            // 1xx response other than 101; never has a body
+    pub body_len: u64,            // decoded (de-chunked) body bytes
+    pub body_sha256: [u8; 32],    // SHA-256 of the decoded body
+    pub complete: bool,           // false if the connection closed mid-message
+}
+
+#[repr(C)]
+pub struct IrisHttpStreamMessages {
+    pub messages: *mut IrisHttpStreamMessage,
+    pub count: usize,
+}
+
+pub struct IrisHttpStream {
+    inner: Mutex<HttpStream>,
+}
+
+#[derive(Clone, Default)]
+pub struct Message {
+    pub request: bool,
+    pub method: String,
+    pub target: String,
+    pub status: u16,
+    pub version_minor: u8,
+    pub header_len: usize,
+    pub content_length: i64,
+    pub chunked: bool,
+    pub should_close: bool,
+    pub expect_continue: bool,
+    pub interim: bool,
+    /// Requests: CONNECT or an Upgrade offer. Responses: the protocol switched.
+    pub upgrade: bool,
+    pub body_len: u64,
+    pub body_sha256: [u8; 32],
+    pub complete: bool,
+}
+
+enum Body {
+    Length(u64),
+    Chunked(Chunk),
+    UntilClose,
+}
+
+enum Chunk {
+    Size,
+    Data(u64),
+    DataEnd,
+    Trailers,
+}
+
+/// How the response to a request is framed.
+enum Expect {
+    Framed,
+    NoBody,
+    Connect,
+}
+
+struct Current {
+    msg: Message,
+    body: Body,
+    hash: Sha256,
+}
+
+pub struct HttpStream {
+    request: bool,
+    buf: Vec<u8>,
+    current: Option<Current>,
+    done: VecDeque<Message>,
+    /// Responses: per outstanding request, how its response is framed.
+    expected: VecDeque<Expect>,
+    /// Requests: stop after one that may switch protocols until resume or tunnel.
+    hold_upgrades: bool,
+    held: bool,
+    /// The connection switched protocols: everything after is passed over.
+    tunnel: bool,
+    failed: bool,
+}
+
+fn line_end(d: &[u8]) -> Option<usize> {
+    d.windows(2).position(|w| w == b"\r\n")
+}
+
+/// Parse a message head at the start of `d`: None if it is not complete yet.
+fn parse_head(request: bool, d: &[u8], expected: &mut VecDeque<Expect>) -> Result<Option<(Current, usize)>, i32> {
+    let mut hdr_buf = vec![httparse::EMPTY_HEADER; http::header_capacity()];
+    let mut msg = Message { request, content_length: -1, ..Default::default() };
+    let (parsed, headers) = if request {
+        let mut req = httparse::Request::new(&mut hdr_buf);
+        let r = req.parse(d);
+        msg.method = req.method.unwrap_or("").to_string();
+        msg.target = req.path.unwrap_or("").to_string();
+        msg.version_minor = req.version.unwrap_or(1);
+        (r, req.headers.len())
+    } else {
+        let mut resp = httparse::Response::new(&mut hdr_buf);
+        let r = resp.parse(d);
+        msg.status = resp.code.unwrap_or(0);
+        msg.version_minor = resp.version.unwrap_or(1);
+        (r, resp.headers.len())
+    };
+    let n = match parsed {
+        Ok(httparse::Status::Complete(n)) if http::head_too_large(n) => return Err(-3),
+        Ok(httparse::Status::Complete(n)) => n,
+        Ok(httparse::Status::Partial) if http::head_too_large(d.len()) || d.len() > MAX_HEAD => return Err(-3),
+        Ok(httparse::Status::Partial) => return Ok(None),
+        Err(httparse::Error::TooManyHeaders) => return Err(-3),
+        Err(_) => return Err(-2),
+    };
+    let headers = &hdr_buf[..headers];
+    msg.header_len = n;
+    msg.chunked = http::is_chunked(headers);
+    msg.content_length = http::parse_content_length(headers).map_err(|_| -2)?.unwrap_or(-1);
+    msg.should_close = http::should_close(msg.version_minor, headers);
+    msg.expect_continue = request && http::expects_continue(headers);
+    msg.interim = !request && http::is_interim(msg.status);
+
+    // Interim responses do not answer the request, so leave its framing queued
+    let expect = if request || msg.interim { Expect::Framed } else { expected.pop_front().unwrap_or(Expect::Framed) };
+    msg.upgrade = if request {
+        msg.method.eq_ignore_ascii_case("CONNECT") || http::requests_upgrade(headers)
+    } else {
+        msg.status == 101 || (matches!(expect, Expect::Connect) && (200..300).contains(&msg.status))
+    };
+    let bodiless = !request && !msg.interim
+        && (msg.upgrade || matches!(expect, Expect::NoBody) || msg.status == 204 || msg.status == 304);
+    let body = if msg.interim || bodiless {
+        Body::Length(0)
+    } else if msg.chunked {
+        Body::Chunked(Chunk::Size)
+    } else if msg.content_length >= 0 {
+        Body::Length(msg.content_length as u64)
+    } else if request {
+        Body::Length(0)
+    } else {
+        // Unframed responses run until the connection closes
+        Body::UntilClose
+    };
+    Ok(Some((Current { msg, body, hash: Sha256::new() }, n)))
+}
+
+impl HttpStream {
+    pub fn new(request: bool) -> Self {
+        HttpStream {
+            request,
+            buf: Vec::new(),
+            current: None,
+            done: VecDeque::new(),
+            expected: VecDeque::new(),
+            hold_upgrades: false,
+            held: false,
+            tunnel: false,
+            failed: false,
+        }
+    }
+
+    /// Responses only: note that a request was sent whose response carries no body
+    /// (`no_body`, e.g. HEAD) or the usual framed one. Call once per request, in order.
+    pub fn expect_response(&mut self, no_body: bool) {
+        self.expected.push_back(if no_body { Expect::NoBody } else { Expect::Framed });
+    }
+
+    /// Responses only: the request was CONNECT, so a 2xx answer opens a tunnel.
+    pub fn expect_connect(&mut self) {
+        self.expected.push_back(Expect::Connect);
+    }
+
+    /// Requests only: after CONNECT or an Upgrade offer, keep what follows unparsed
+    /// until the answer is known; `resume` if it was refused, `tunnel` if not.
+    pub fn hold_upgrades(&mut self) {
+        self.hold_upgrades = true;
+    }
+
+    pub fn resume(&mut self) -> Result<(), i32> {
+        if !self.held { return Ok(()); }
+        self.held = false;
+        self.feed(&[])
+    }
+
+    /// The other side switched protocols: pass over everything from here on.
+    pub fn tunnel(&mut self) {
+        self.tunnel = true;
+        self.held = false;
+        self.buf.clear();
+    }
+
+    pub fn feed(&mut self, data: &[u8]) -> Result<(), i32> {
+        if self.failed { return Err(-2); }
+        if self.tunnel { return Ok(()); }
+        self.buf.extend_from_slice(data);
+        let mut p = 0;
+        let r = self.advance(&mut p);
+        self.buf.drain(..p);
+        if r.is_err() { self.failed = true; }
+        r
+    }
+
+    /// The connection closed: a read-until-close body ends here, anything else
+    /// in progress is reported incomplete.
+    pub fn close(&mut self) {
+        if let Some(cur) = self.current.take() {
+            let complete = matches!(cur.body, Body::UntilClose);
+            self.finish(cur, complete);
+        }
+        self.buf.clear();
+    }
+
+    /// The message whose head has been read but whose body has not finished.
+    pub fn in_progress(&self) -> Option<&Message> {
+        self.current.as_ref().map(|c| &c.msg)
+    }
+
+    pub fn take(&mut self) -> Vec<Message> {
+        self.done.drain(..).collect()
+    }
+
+    fn finish(&mut self, cur: Current, complete: bool) {
+        let mut msg = cur.msg;
+        msg.body_sha256 = cur.hash.finalize();
+        msg.complete = complete;
+        if complete && msg.upgrade {
+            if self.request { self.held = self.hold_upgrades; } else { self.tunnel = true; }
+        }
+        self.done.push_back(msg);
+    }
+
+    /// Consume as much of `buf[p..]` as possible.
+    fn advance(&mut self, p: &mut usize) -> Result<(), i32> {
+        loop {
+            let rest = &self.buf[*p..];
+            let Some(cur) = self.current.as_mut() else {
+                if self.tunnel {
+                    *p = self.buf.len();
+                    return Ok(());
+                }
+                if self.held {
+                    return if rest.len() > MAX_HEAD { Err(-3) } else { Ok(()) };
+                }
+                if rest.is_empty() { return Ok(()); }
+                let Some((cur, n)) = parse_head(self.request, rest, &mut self.expected)? else { return Ok(()) };
+                *p += n;
+                self.current = Some(cur);
+                continue;
+            };
+            let mut take = |n: u64, hash: &mut Sha256, msg: &mut Message| -> u64 {
+                let k = n.min(rest.len() as u64);
+                hash.update(&rest[..k as usize]);
+                msg.body_len += k;
+                *p += k as usize;
+                k
+            };
+            match &mut cur.body {
+                Body::Length(0) => {}
+                Body::Length(n) => {
+                    let k = take(*n, &mut cur.hash, &mut cur.msg);
+                    *n -= k;
+                    if *n > 0 { return Ok(()); }
+                }
+                Body::UntilClose => {
+                    take(rest.len() as u64, &mut cur.hash, &mut cur.msg);
+                    return Ok(());
+                }
+                Body::Chunked(Chunk::Data(n)) => {
+                    let k = take(*n, &mut cur.hash, &mut cur.msg);
+                    *n -= k;
+                    if *n > 0 { return Ok(()); }
+                    cur.body = Body::Chunked(Chunk::DataEnd);
+                    continue;
+                }
+                Body::Chunked(Chunk::DataEnd) => {
+                    if rest.len() < 2 { return Ok(()); }
+                    if &rest[..2] != b"\r\n" { return Err(-2); }
+                    *p += 2;
+                    cur.body = Body::Chunked(Chunk::Size);
+                    continue;
+                }
+                Body::Chunked(Chunk::Size) => {
+                    let Some(end) = line_end(rest) else {
+                        return if rest.len() > MAX_CHUNK_LINE { Err(-2) } else { Ok(()) };
+                    };
+                    let size = rest[..end].split(|&b| b == b';').next().unwrap_or_default().trim_ascii();
+                    let size = std::str::from_utf8(size).ok()
+                        .and_then(|s| u64::from_str_radix(s, 16).ok()).ok_or(-2)?;
+                    *p += end + 2;
+                    cur.body = Body::Chunked(if size == 0 { Chunk::Trailers } else { Chunk::Data(size) });
+                    continue;
+                }
+                Body::Chunked(Chunk::Trailers) => {
+                    let Some(end) = line_end(rest) else {
+                        return if rest.len() > MAX_HEAD { Err(-3) } else { Ok(()) };
+                    };
+                    *p += end + 2;
+                    if end > 0 { continue; }
+                }
+            }
+            // The message body is done
+            let cur = self.current.take().expect("current message");
+            self.finish(cur, true);
+        }
+    }
+}
+
+pub fn message(m: Message) -> IrisHttpStreamMessage {
+    IrisHttpStreamMessage {
+        is_request: m.request,
+        method: to_cstr(&m.method),
+        target: to_cstr(&m.target),
+        status_code: m.status,
+        version_minor: m.version_minor,
+        header_len: m.header_len,
+        content_length: m.content_length,
+        is_chunked: m.chunked,
+        should_close: m.should_close,
+        expect_continue: m.expect_continue,
+        interim: m.interim,
+        body_len: m.body_len,
+        body_sha256: m.body_sha256,
+        complete: m.complete,
+    }
+}
+
+pub fn free_message(m: &IrisHttpStreamMessage) {
+    free_cstr(m.method);
+    free_cstr(m.target);
+}
+
+// --- FFI ---
+
+/// Create a reader for the requests (`is_request`) or responses of one connection.
+/// Free with iris_http_stream_free.
+#[no_mangle]
+pub extern "C" fn iris_http_stream_new(is_request: bool) -> *mut IrisHttpStream {
+    Box::into_raw(Box::new(IrisHttpStream { inner: Mutex::new(HttpStream::new(is_request)) }))
+}
+
+/// Feed bytes in stream order. Returns 0=ok, -2=arg error or malformed message,
+/// -3=head over the size limits. After an error every later call returns -2.
+#[no_mangle]
+pub extern "C" fn iris_http_stream_feed(stream: *mut IrisHttpStream, data: *const u8, len: usize) -> i32 {
+    if stream.is_null() || (data.is_null() && len != 0) { return -2; }
+    let d = if len == 0 { &[][..] } else { unsafe { std::slice::from_raw_parts(data, len) } };
+    let mut s = unsafe { &*stream }.inner.lock().unwrap_or_else(|e| e.into_inner());
+    match s.feed(d) {
+        Ok(()) => 0,
+        Err(e) => e,
+    }
+}
+
+/// Response streams: record that a request was sent, and whether its response has
+/// no body (HEAD). Call for every request in order, or never; without it every
+/// response is framed by its own headers.
+#[no_mangle]
+pub extern "C" fn iris_http_stream_expect_response(stream: *mut IrisHttpStream, no_body: bool) {
+    if stream.is_null() { return; }
+    unsafe { &*stream }.inner.lock().unwrap_or_else(|e| e.into_inner()).expect_response(no_body);
+}
+
+/// Response streams: as iris_http_stream_expect_response, for a CONNECT request. A
+/// 2xx answer has no body and the bytes after it are tunnelled, not parsed.
+#[no_mangle]
+pub extern "C" fn iris_http_stream_expect_connect(stream: *mut IrisHttpStream) {
+    if stream.is_null() { return; }
+    unsafe { &*stream }.inner.lock().unwrap_or_else(|e| e.into_inner()).expect_connect();
+}
+
+/// The connection closed: ends a read-until-close body, and reports any other
+/// message in progress with complete=false.
+#[no_mangle]
+pub extern "C" fn iris_http_stream_close(stream: *mut IrisHttpStream) {
+    if stream.is_null() { return; }
+    unsafe { &*stream }.inner.lock().unwrap_or_else(|e| e.into_inner()).close();
+}
+
+/// Take the messages finished since the last call, with their body digests.
+/// Returns 0=ok, -2=arg error. Free with iris_http_stream_messages_free.
+#[no_mangle]
+pub extern "C" fn iris_http_stream_take(stream: *mut IrisHttpStream, out: *mut IrisHttpStreamMessages) -> i32 {
+    if stream.is_null() || out.is_null() { return -2; }
+    let done = unsafe { &*stream }.inner.lock().unwrap_or_else(|e| e.into_inner()).take();
+    let (messages, count) = alloc_array(done.into_iter().map(message).collect());
+    unsafe { out.write(IrisHttpStreamMessages { messages, count }); }
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn iris_http_stream_messages_free(m: *mut IrisHttpStreamMessages) {
+    if m.is_null() { return; }
+    let m = unsafe { &*m };
+    for i in 0..m.count {
+        free_message(unsafe { &*m.messages.add(i) });
+    }
+    free_array(m.messages, m.count);
+}
+
+#[no_mangle]
+pub extern "C" fn iris_http_stream_free(stream: *mut IrisHttpStream) {
+    if stream.is_null() { return; }
+    drop(unsafe { Box::from_raw(stream) });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::sha256_digest;
+
+    #[test]
+    fn pipelined_requests_byte_by_byte() {
+        let data = b"GET /a HTTP/1.1\r\nHost: x\r\n\r\n\
+                     POST /b HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello\
+                     POST /c HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3;ext=1\r\nabc\r\nA\r\n0123456789\r\n0\r\nX-T: 1\r\n\r\n\
+                     GET /d HTTP/1.1\r\n";
+        let mut s = HttpStream::new(true);
+        for b in data.chunks(1) { s.feed(b).unwrap(); }
+        let m = s.take();
+        assert_eq!(m.len(), 3);
+        assert_eq!((m[0].method.as_str(), m[0].target.as_str(), m[0].body_len), ("GET", "/a", 0));
+        assert_eq!(m[0].body_sha256, sha256_digest(b""));
+        assert_eq!(m[1].body_sha256, sha256_digest(b"hello"));
+        assert!(m[2].chunked && m[2].complete);
+        assert_eq!(m[2].body_len, 13);
+        assert_eq!(m[2].body_sha256, sha256_digest(b"abc0123456789"));
+        s.close();
+        assert!(s.take().is_empty()); // the unfinished head is dropped
+    }
+
+    #[test]
+    fn responses_and_close() {
+        let mut s = HttpStream::new(false);
+        s.expect_response(true);
+        s.expect_response(false);
+        s.feed(b"HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 200 OK\r\nContent-Length: 100\r\n\r\n").unwrap();
+        s.feed(b"HTTP/1.0 200 OK\r\n\r\nstreamed ").unwrap();
+        s.feed(b"until close").unwrap();
+        let m = s.take(); // 100 Continue, then the HEAD response
+        assert!(m[0].interim && !m[1].interim);
+        assert_eq!(m[1].body_len, 0);
+        s.close();
+        let m = s.take();
+        assert!(m[0].complete && m[0].should_close);
+        assert_eq!(m[0].body_sha256, sha256_digest(b"streamed until close"));
+
+        let mut s = HttpStream::new(false);
+        s.feed(b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nshort").unwrap();
+        s.close();
+        let m = s.take();
+        assert!(!m[0].complete);
+        assert_eq!(m[0].body_len, 5);
+
+        let mut s = HttpStream::new(false);
+        assert_eq!(s.feed(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n"), Err(-2));
+        assert_eq!(s.feed(b""), Err(-2));
+    }
+
+    #[test]
+    fn ffi_take() {
+        let s = iris_http_stream_new(true);
+        let d = b"PUT /f HTTP/1.1\r\nContent-Length: 3\r\n\r\nabc";
+        assert_eq!(iris_http_stream_feed(s, d.as_ptr(), d.len()), 0);
+        let mut out = std::mem::MaybeUninit::<IrisHttpStreamMessages>::uninit();
+        assert_eq!(iris_http_stream_take(s, out.as_mut_ptr()), 0);
+        let mut m = unsafe { out.assume_init() };
+        assert_eq!(m.count, 1);
+        let msg = unsafe { &*m.messages };
+        assert_eq!(msg.body_sha256, sha256_digest(b"abc"));
+        assert_eq!(msg.header_len, d.len() - 3);
+        iris_http_stream_messages_free(&mut m);
+        iris_http_stream_free(s);
+    }
+}
@@ -0,0 +1,341 @@
+//! ICMP (RFC 792) and ICMPv6 (RFC 4443) parser, including NDP (RFC 4861)
+//! router/neighbor messages and the original datagram quoted in error messages.
+
+use crate::bytes::{be16, be32};
+use crate::ffi::IrisSlice;
+use crate::ip::{self, IrisIpAddr, PROTO_ICMP, PROTO_ICMPV6, PROTO_TCP, PROTO_UDP};
+
+/// Message classification shared by ICMP and ICMPv6.
+pub const ICMP_KIND_OTHER: u8 = 0;
+pub const ICMP_KIND_ECHO_REQUEST: u8 = 1;
+pub const ICMP_KIND_ECHO_REPLY: u8 = 2;
+pub const ICMP_KIND_DEST_UNREACHABLE: u8 = 3;
+pub const ICMP_KIND_PACKET_TOO_BIG: u8 = 4;
+pub const ICMP_KIND_TIME_EXCEEDED: u8 = 5;
+pub const ICMP_KIND_PARAMETER_PROBLEM: u8 = 6;
+pub const ICMP_KIND_REDIRECT: u8 = 7;
+pub const ICMP_KIND_ROUTER_SOLICIT: u8 = 8;
+pub const ICMP_KIND_ROUTER_ADVERT: u8 = 9;
+pub const ICMP_KIND_NEIGHBOR_SOLICIT: u8 = 10;
+pub const ICMP_KIND_NEIGHBOR_ADVERT: u8 = 11;
+pub const ICMP_KIND_TIMESTAMP: u8 = 12;
+
+/// Header of the datagram quoted inside an ICMP error.
+#[repr(C)]
+#[derive(Default)]
+pub struct IrisIcmpOriginal {
+    pub present: bool,
+    pub ip_version: u8,
+    pub protocol: u8,
+    pub src: IrisIpAddr,
+    pub dst: IrisIpAddr,
+    pub src_port: u16, // TCP/UDP only, else 0
+    pub dst_port: u16,
+    pub icmp_id: u16,  // quoted echo identifier, for traceroute/ping correlation
+}
+
+#[repr(C)]
+pub struct IrisIcmpMessage {
+    pub is_v6: bool,
+    pub icmp_type: u8,
+    pub code: u8,
+    pub kind: u8,               // ICMP_KIND_*
+    pub checksum: u16,
+    pub checksum_checked: bool, // false when no IP header was supplied for ICMPv6
+    pub checksum_valid: bool,
+    pub echo_id: u16,
+    pub echo_seq: u16,
+    pub mtu: u32,               // fragmentation needed / packet too big
+    pub gateway: IrisIpAddr,    // ICMPv4 redirect gateway
+    pub target: IrisIpAddr,     // NS/NA/redirect target
+    pub destination: IrisIpAddr, // ICMPv6 redirect destination
+    pub cur_hop_limit: u8,      // RA
+    pub ra_managed: bool,
+    pub ra_other: bool,
+    pub router_lifetime: u16,
+    pub na_router: bool,
+    pub na_solicited: bool,
+    pub na_override: bool,
+    pub has_source_lladdr: bool,
+    pub source_lladdr: [u8; 6],
+    pub has_target_lladdr: bool,
+    pub target_lladdr: [u8; 6],
+    pub prefix: IrisIpAddr,     // first RA prefix-information option
+    pub prefix_len: u8,
+    pub ndp_hop_limit_invalid: bool, // NDP not sent with hop limit 255 (off-link spoof)
+    pub unsolicited_override: bool,  // NA with O=1, S=0 — classic cache-poisoning shape
+    pub original: IrisIcmpOriginal,
+    pub payload: IrisSlice,          // echo data or quoted datagram
+}
+
+fn empty_message(is_v6: bool, data: &[u8]) -> Option<IrisIcmpMessage> {
+    Some(IrisIcmpMessage {
+        is_v6, icmp_type: data[0], code: data[1], kind: ICMP_KIND_OTHER,
+        checksum: be16(data, 2)?, checksum_checked: false, checksum_valid: false,
+        echo_id: 0, echo_seq: 0, mtu: 0,
+        gateway: IrisIpAddr::default(), target: IrisIpAddr::default(),
+        destination: IrisIpAddr::default(),
+        cur_hop_limit: 0, ra_managed: false, ra_other: false, router_lifetime: 0,
+        na_router: false, na_solicited: false, na_override: false,
+        has_source_lladdr: false, source_lladdr: [0; 6],
+        has_target_lladdr: false, target_lladdr: [0; 6],
+        prefix: IrisIpAddr::default(), prefix_len: 0,
+        ndp_hop_limit_invalid: false, unsolicited_override: false,
+        original: IrisIcmpOriginal::default(),
+        payload: IrisSlice::from_bytes(&data[data.len()..]),
+    })
+}
+
+fn parse_original(data: &[u8]) -> IrisIcmpOriginal {
+    let p = match ip::parse_packet(data) { Some(p) => p, None => return IrisIcmpOriginal::default() };
+    let mut o = IrisIcmpOriginal {
+        present: true, ip_version: p.version, protocol: p.protocol,
+        src: p.src, dst: p.dst, ..Default::default()
+    };
+    let l4 = p.payload;
+    match p.protocol {
+        PROTO_TCP | PROTO_UDP => {
+            if let (Some(src), Some(dst)) = (be16(l4, 0), be16(l4, 2)) { (o.src_port, o.dst_port) = (src, dst); }
+        }
+        PROTO_ICMP | PROTO_ICMPV6 => o.icmp_id = be16(l4, 4).unwrap_or(0),
+        _ => {}
+    }
+    o
+}
+
+/// Walk NDP options (type, length in 8-byte units).
+fn parse_ndp_options(msg: &mut IrisIcmpMessage, mut opts: &[u8]) {
+    while opts.len() >= 2 {
+        let olen = opts[1] as usize * 8;
+        if olen == 0 || olen > opts.len() { break; }
+        let o = &opts[..olen];
+        match o[0] {
+            1 if olen >= 8 => {
+                msg.has_source_lladdr = true;
+                msg.source_lladdr.copy_from_slice(&o[2..8]);
+            }
+            2 if olen >= 8 => {
+                msg.has_target_lladdr = true;
+                msg.target_lladdr.copy_from_slice(&o[2..8]);
+            }
+            3 if olen >= 32 && msg.prefix.family == 0 => {
+                msg.prefix_len = o[2];
+                msg.prefix = IrisIpAddr::v6(&o[16..32]);
+            }
+            5 => msg.mtu = be32(o, 4).unwrap_or(msg.mtu),
+            _ => {}
+        }
+        opts = &opts[olen..];
+    }
+}
+
+fn parse_v4(data: &[u8]) -> Option<IrisIcmpMessage> {
+    if data.len() < 8 { return None; }
+    let mut m = empty_message(false, data)?;
+    m.checksum_checked = true;
+    m.checksum_valid = ip::internet_checksum(&[data]) == 0;
+    let rest = &data[8..];
+    match data[0] {
+        0 | 8 => {
+            m.kind = if data[0] == 8 { ICMP_KIND_ECHO_REQUEST } else { ICMP_KIND_ECHO_REPLY };
+            m.echo_id = be16(data, 4)?;
+            m.echo_seq = be16(data, 6)?;
+            m.payload = IrisSlice::from_bytes(rest);
+            return Some(m);
+        }
+        3 => {
+            m.kind = ICMP_KIND_DEST_UNREACHABLE;
+            if data[1] == 4 { m.mtu = be16(data, 6)? as u32; }
+        }
+        5 => {
+            m.kind = ICMP_KIND_REDIRECT;
+            m.gateway = IrisIpAddr::v4(&data[4..8]);
+        }
+        11 => m.kind = ICMP_KIND_TIME_EXCEEDED,
+        12 => m.kind = ICMP_KIND_PARAMETER_PROBLEM,
+        9 => {
+            m.kind = ICMP_KIND_ROUTER_ADVERT;
+            m.router_lifetime = be16(data, 6)?;
+            return Some(m);
+        }
+        10 => { m.kind = ICMP_KIND_ROUTER_SOLICIT; return Some(m); }
+        13 | 14 => {
+            m.kind = ICMP_KIND_TIMESTAMP;
+            m.echo_id = be16(data, 4)?;
+            m.echo_seq = be16(data, 6)?;
+            return Some(m);
+        }
+        _ => return Some(m),
+    }
+    m.original = parse_original(rest);
+    m.payload = IrisSlice::from_bytes(rest);
+    Some(m)
+}
+
+fn parse_v6(data: &[u8]) -> Option<IrisIcmpMessage> {
+    if data.len() < 8 { return None; }
+    let mut m = empty_message(true, data)?;
+    match data[0] {
+        128 | 129 => {
+            m.kind = if data[0] == 128 { ICMP_KIND_ECHO_REQUEST } else { ICMP_KIND_ECHO_REPLY };
+            m.echo_id = be16(data, 4)?;
+            m.echo_seq = be16(data, 6)?;
+            m.payload = IrisSlice::from_bytes(&data[8..]);
+        }
+        1..=4 => {
+            m.kind = match data[0] {
+                1 => ICMP_KIND_DEST_UNREACHABLE,
+                2 => ICMP_KIND_PACKET_TOO_BIG,
+                3 => ICMP_KIND_TIME_EXCEEDED,
+                _ => ICMP_KIND_PARAMETER_PROBLEM,
+            };
+            if data[0] == 2 { m.mtu = be32(data, 4)?; }
+            m.original = parse_original(&data[8..]);
+            m.payload = IrisSlice::from_bytes(&data[8..]);
+        }
+        133 => {
+            m.kind = ICMP_KIND_ROUTER_SOLICIT;
+            parse_ndp_options(&mut m, &data[8..]);
+        }
+        134 => {
+            if data.len() < 16 { return None; }
+            m.kind = ICMP_KIND_ROUTER_ADVERT;
+            m.cur_hop_limit = data[4];
+            m.ra_managed = data[5] & 0x80 != 0;
+            m.ra_other = data[5] & 0x40 != 0;
+            m.router_lifetime = be16(data, 6)?;
+            parse_ndp_options(&mut m, &data[16..]);
+        }
+        135 | 136 => {
+            if data.len() < 24 { return None; }
+            m.kind = if data[0] == 135 { ICMP_KIND_NEIGHBOR_SOLICIT } else { ICMP_KIND_NEIGHBOR_ADVERT };
+            if data[0] == 136 {
+                m.na_router = data[4] & 0x80 != 0;
+                m.na_solicited = data[4] & 0x40 != 0;
+                m.na_override = data[4] & 0x20 != 0;
+                m.unsolicited_override = m.na_override && !m.na_solicited;
+            }
+            m.target = IrisIpAddr::v6(&data[8..24]);
+            parse_ndp_options(&mut m, &data[24..]);
+        }
+        137 => {
+            if data.len() < 40 { return None; }
+            m.kind = ICMP_KIND_REDIRECT;
+            m.target = IrisIpAddr::v6(&data[8..24]);
+            m.destination = IrisIpAddr::v6(&data[24..40]);
+            parse_ndp_options(&mut m, &data[40..]);
+        }
+        _ => {}
+    }
+    Some(m)
+}
+
+fn is_ndp(m: &IrisIcmpMessage) -> bool { m.is_v6 && (133..=137).contains(&m.icmp_type) }
+
+// --- FFI entry points ---
+
+/// Parse a bare ICMP/ICMPv6 message (no IP header). ICMPv6 checksums need the
+/// pseudo-header, so `checksum_checked` is false for v6 here.
+/// Returns 0=ok, -1=truncated, -2=arg error.
+#[no_mangle]
+pub extern "C" fn iris_icmp_parse(
+    data: *const u8, len: usize, is_v6: bool, out: *mut IrisIcmpMessage,
+) -> i32 {
+    if data.is_null() || out.is_null() || len == 0 { return -2; }
+    let buf = unsafe { std::slice::from_raw_parts(data, len) };
+    let parsed = if is_v6 { parse_v6(buf) } else { parse_v4(buf) };
+    match parsed {
+        Some(m) => { unsafe { out.write(m); } 0 }
+        None => -1,
+    }
+}
+
+/// Parse an IP packet carrying ICMP/ICMPv6. Verifies the ICMPv6 checksum over
+/// the pseudo-header and flags NDP messages not sent with hop limit 255.
+/// Returns 0=ok, -1=truncated, -2=arg error, -3=not ICMP.
+#[no_mangle]
+pub extern "C" fn iris_icmp_parse_packet(
+    data: *const u8, len: usize, out: *mut IrisIcmpMessage,
+) -> i32 {
+    if data.is_null() || out.is_null() || len == 0 { return -2; }
+    let buf = unsafe { std::slice::from_raw_parts(data, len) };
+    let pkt = match ip::parse_packet(buf) { Some(p) => p, None => return -1 };
+    let mut m = match pkt.protocol {
+        PROTO_ICMP if pkt.version == 4 => match parse_v4(pkt.payload) { Some(m) => m, None => return -1 },
+        PROTO_ICMPV6 if pkt.version == 6 => {
+            let mut m = match parse_v6(pkt.payload) { Some(m) => m, None => return -1 };
+            let ph = ip::pseudo_header(&pkt.src, &pkt.dst, PROTO_ICMPV6, pkt.payload.len());
+            m.checksum_checked = true;
+            m.checksum_valid = ip::internet_checksum(&[&ph, pkt.payload]) == 0;
+            m
+        }
+        _ => return -3,
+    };
+    m.ndp_hop_limit_invalid = is_ndp(&m) && pkt.ttl != 255;
+    unsafe { out.write(m); }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_checksum(mut msg: Vec<u8>) -> Vec<u8> {
+        let c = ip::internet_checksum(&[&msg]);
+        msg[2..4].copy_from_slice(&c.to_be_bytes());
+        msg
+    }
+
+    #[test]
+    fn parse_echo_request() {
+        let msg = with_checksum(vec![8, 0, 0, 0, 0x12, 0x34, 0x00, 0x07, b'p', b'i', b'n', b'g']);
+        let m = parse_v4(&msg).unwrap();
+        assert_eq!(m.kind, ICMP_KIND_ECHO_REQUEST);
+        assert_eq!(m.echo_id, 0x1234);
+        assert_eq!(m.echo_seq, 7);
+        assert!(m.checksum_valid);
+        assert_eq!(m.payload.len, 4);
+    }
+
+    #[test]
+    fn dest_unreachable_quotes_original_udp() {
+        let mut msg = vec![3, 3, 0, 0, 0, 0, 0, 0];
+        // Original IPv4/UDP header: 10.0.0.1 -> 10.0.0.2, 53000 -> 53
+        msg.extend_from_slice(&[0x45, 0, 0, 28, 0, 0, 0, 0, 64, 17, 0, 0,
+                                10, 0, 0, 1, 10, 0, 0, 2]);
+        msg.extend_from_slice(&[0xCF, 0x08, 0x00, 0x35, 0, 8, 0, 0]);
+        let m = parse_v4(&with_checksum(msg)).unwrap();
+        assert_eq!(m.kind, ICMP_KIND_DEST_UNREACHABLE);
+        assert!(m.original.present);
+        assert_eq!(m.original.protocol, PROTO_UDP);
+        assert_eq!(m.original.dst.as_slice(), &[10, 0, 0, 2]);
+        assert_eq!(m.original.src_port, 53000);
+        assert_eq!(m.original.dst_port, 53);
+    }
+
+    #[test]
+    fn neighbor_advert_in_packet() {
+        let src = [0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1];
+        let dst = [0xff, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1];
+        let mut icmp = vec![136, 0, 0, 0, 0x20, 0, 0, 0];
+        icmp.extend_from_slice(&src);
+        icmp.extend_from_slice(&[2, 1, 0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff]);
+        let ph = ip::pseudo_header(&IrisIpAddr::v6(&src), &IrisIpAddr::v6(&dst), PROTO_ICMPV6, icmp.len());
+        let c = ip::internet_checksum(&[&ph, &icmp]);
+        icmp[2..4].copy_from_slice(&c.to_be_bytes());
+
+        let mut pkt = vec![0x60, 0, 0, 0, 0, icmp.len() as u8, PROTO_ICMPV6, 64];
+        pkt.extend_from_slice(&src);
+        pkt.extend_from_slice(&dst);
+        pkt.extend_from_slice(&icmp);
+        let mut out = std::mem::MaybeUninit::<IrisIcmpMessage>::uninit();
+        assert_eq!(iris_icmp_parse_packet(pkt.as_ptr(), pkt.len(), out.as_mut_ptr()), 0);
+        let m = unsafe { out.assume_init() };
+        assert_eq!(m.kind, ICMP_KIND_NEIGHBOR_ADVERT);
+        assert!(m.checksum_valid);
+        assert!(m.unsolicited_override);
+        assert!(m.ndp_hop_limit_invalid); // hop limit 64, not 255
+        assert!(m.has_target_lladdr);
+        assert_eq!(m.target_lladdr, [0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff]);
+    }
+}
@@ -0,0 +1,369 @@
+//! Application-protocol identification from a flow's first payload bytes and ports.
+//! Payload signatures decide; a matching well-known port raises confidence and is
+//! used alone (at low confidence) only when no signature matches.
+
+use crate::{kerberos, snmp, tls, vpn};
+use std::ffi::c_char;
+
+pub const PROTO_UNKNOWN: u16 = 0;
+pub const PROTO_HTTP: u16 = 1;
+pub const PROTO_HTTP2: u16 = 2;
+pub const PROTO_TLS: u16 = 3;
+pub const PROTO_QUIC: u16 = 4;
+pub const PROTO_SSH: u16 = 5;
+pub const PROTO_DNS: u16 = 6;
+pub const PROTO_SMB: u16 = 7;
+pub const PROTO_RDP: u16 = 8;
+pub const PROTO_BITTORRENT: u16 = 9;
+pub const PROTO_SMTP: u16 = 10;
+pub const PROTO_FTP: u16 = 11;
+pub const PROTO_POP3: u16 = 12;
+pub const PROTO_IMAP: u16 = 13;
+pub const PROTO_KERBEROS: u16 = 14;
+pub const PROTO_SNMP: u16 = 15;
+pub const PROTO_DHCP: u16 = 16;
+pub const PROTO_WIREGUARD: u16 = 17;
+pub const PROTO_OPENVPN: u16 = 18;
+pub const PROTO_IKE: u16 = 19;
+pub const PROTO_SIP: u16 = 20;
+pub const PROTO_RTSP: u16 = 21;
+pub const PROTO_MQTT: u16 = 22;
+
+const PORT_ONLY_CONFIDENCE: u8 = 25;
+const PORT_BONUS: u8 = 10;
+const BARE_GREETING_CONFIDENCE: u8 = 40;
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct IrisProtocolMatch {
+    pub protocol: u16,      // PROTO_*
+    pub confidence: u8,     // 0-100
+    pub payload_match: bool,
+    pub port_match: bool,
+}
+
+/// What the client sent first inside an established CONNECT tunnel.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct IrisTunnelPayload {
+    pub protocol: u16,      // PROTO_TLS, PROTO_HTTP, PROTO_HTTP2, PROTO_SSH, ... or PROTO_UNKNOWN
+    pub confidence: u8,     // 0-100
+    pub tls_version: u16,   // TLS: highest version the ClientHello offers (0x0304 = TLS 1.3),
+                            // else the record version; 0 otherwise
+}
+
+const WELL_KNOWN_PORTS: &[(u16, u16)] = &[
+    (80, PROTO_HTTP), (8080, PROTO_HTTP), (8000, PROTO_HTTP), (443, PROTO_TLS), (8443, PROTO_TLS),
+    (22, PROTO_SSH), (53, PROTO_DNS), (5353, PROTO_DNS), (445, PROTO_SMB), (139, PROTO_SMB),
+    (3389, PROTO_RDP), (6881, PROTO_BITTORRENT), (25, PROTO_SMTP), (587, PROTO_SMTP),
+    (465, PROTO_SMTP), (21, PROTO_FTP), (110, PROTO_POP3), (143, PROTO_IMAP), (88, PROTO_KERBEROS),
+    (161, PROTO_SNMP), (162, PROTO_SNMP), (67, PROTO_DHCP), (68, PROTO_DHCP), (546, PROTO_DHCP),
+    (547, PROTO_DHCP), (51820, PROTO_WIREGUARD), (1194, PROTO_OPENVPN), (500, PROTO_IKE),
+    (4500, PROTO_IKE), (5060, PROTO_SIP), (554, PROTO_RTSP), (1883, PROTO_MQTT),
+];
+
+fn port_protocols(port: u16) -> impl Iterator<Item = u16> {
+    let torrent = (6881..=6889).contains(&port).then_some(PROTO_BITTORRENT);
+    WELL_KNOWN_PORTS.iter().filter(move |(p, _)| *p == port).map(|(_, proto)| *proto).chain(torrent)
+}
+
+fn port_matches(proto: u16, src: u16, dst: u16) -> bool {
+    // QUIC shares 443 with TLS
+    let proto = if proto == PROTO_QUIC { PROTO_TLS } else { proto };
+    port_protocols(src).chain(port_protocols(dst)).any(|p| p == proto)
+}
+
+fn first_line(d: &[u8]) -> &[u8] {
+    let end = d.iter().position(|&b| b == b'\r' || b == b'\n').unwrap_or(d.len());
+    &d[..end]
+}
+
+/// Request/status lines ending in or starting with a protocol version token.
+fn text_protocol(d: &[u8]) -> Option<(u16, u8)> {
+    if d.starts_with(b"PRI * HTTP/2.0") { return Some((PROTO_HTTP2, 100)); }
+    let line = first_line(d);
+    let last = line.rsplit(|&b| b == b' ').next().unwrap_or(b"");
+    let verb_ok = line.iter().take_while(|&&b| b != b' ').all(|b| b.is_ascii_uppercase() || *b == b'-');
+    for (token, proto) in [(&b"HTTP/1."[..], PROTO_HTTP), (b"SIP/2.0", PROTO_SIP), (b"RTSP/1.0", PROTO_RTSP)] {
+        // status line, or "METHOD target VERSION"
+        if line.starts_with(token) || (verb_ok && last.starts_with(token)) { return Some((proto, 95)); }
+    }
+    const METHODS: &[&[u8]] = &[b"GET ", b"POST ", b"HEAD ", b"PUT ", b"DELETE ", b"OPTIONS ", b"PATCH ", b"CONNECT "];
+    if METHODS.iter().any(|m| d.starts_with(m)) { return Some((PROTO_HTTP, 70)); } // line incomplete
+    None
+}
+
+fn mail_or_ftp(d: &[u8]) -> Option<(u16, u8)> {
+    let line = first_line(d).to_ascii_uppercase();
+    if line.starts_with(b"EHLO ") || line.starts_with(b"HELO ") { return Some((PROTO_SMTP, 95)); }
+    if line.starts_with(b"+OK") { return Some((PROTO_POP3, 85)); }
+    if line.starts_with(b"* OK") || line.starts_with(b"* PREAUTH") { return Some((PROTO_IMAP, 85)); }
+    if line.starts_with(b"220") && matches!(line.get(3), Some(b' ') | Some(b'-')) {
+        let has = |w: &[u8]| line.windows(w.len()).any(|x| x == w);
+        if has(b"SMTP") || has(b"MAIL") { return Some((PROTO_SMTP, 90)); }
+        if has(b"FTP") { return Some((PROTO_FTP, 90)); }
+        return Some((PROTO_SMTP, BARE_GREETING_CONFIDENCE));
+    }
+    None
+}
+
+fn tls(d: &[u8]) -> Option<(u16, u8)> {
+    if d.len() < 3 || d[1] != 0x03 || d[2] > 0x04 { return None; }
+    match d[0] {
+        0x16 if d.get(5).is_some_and(|&t| t == 1 || t == 2) => Some((PROTO_TLS, 95)),
+        0x16 => Some((PROTO_TLS, 70)),
+        0x14 | 0x15 | 0x17 => Some((PROTO_TLS, 50)),
+        _ => None,
+    }
+}
+
+fn quic(d: &[u8]) -> Option<(u16, u8)> {
+    if d.len() < 7 || d[0] & 0x80 == 0 { return None; }
+    let version = u32::from_be_bytes([d[1], d[2], d[3], d[4]]);
+    let known = version == 1 || version == 0x6b33_43cf || version >> 8 == 0x00ff_0000;
+    if !known { return if version == 0 { Some((PROTO_QUIC, 60)) } else { None }; }
+    if d[0] & 0x40 == 0 { return None; } // fixed bit
+    // Client Initials are padded to 1200 bytes
+    Some((PROTO_QUIC, if d.len() >= 1200 { 95 } else { 80 }))
+}
+
+fn smb(d: &[u8]) -> Option<(u16, u8)> {
+    let magic = |m: &[u8]| matches!(m, b"\xFESMB" | b"\xFFSMB" | b"\xFDSMB");
+    if d.len() >= 8 && d[0] == 0 && magic(&d[4..8]) { return Some((PROTO_SMB, 100)); }
+    if d.len() >= 4 && magic(&d[..4]) { return Some((PROTO_SMB, 90)); }
+    None
+}
+
+/// TPKT header followed by an X.224 Connection Request.
+fn rdp(d: &[u8]) -> Option<(u16, u8)> {
+    if d.len() < 11 || d[0] != 3 || d[1] != 0 { return None; }
+    if u16::from_be_bytes([d[2], d[3]]) as usize != d.len() || d[5] & 0xF0 != 0xE0 { return None; }
+    let cookie = d.windows(17).any(|w| w == b"Cookie: mstshash=");
+    Some((PROTO_RDP, if cookie { 100 } else { 85 }))
+}
+
+fn bittorrent(d: &[u8]) -> Option<(u16, u8)> {
+    if d.starts_with(b"\x13BitTorrent protocol") { return Some((PROTO_BITTORRENT, 100)); }
+    // Mainline DHT KRPC: bencoded dict with transaction id and message type keys
+    if d.starts_with(b"d1:") && d.windows(4).any(|w| w == b"1:y1") { return Some((PROTO_BITTORRENT, 85)); }
+    None
+}
+
+fn dns_question_ok(d: &[u8]) -> bool {
+    if d.len() < 17 { return false; }
+    let flags = u16::from_be_bytes([d[2], d[3]]);
+    let qd = u16::from_be_bytes([d[4], d[5]]);
+    if (flags >> 11) & 0xF > 6 || flags & 0x0040 != 0 || !(1..=4).contains(&qd) { return false; }
+    let mut off = 12;
+    loop {
+        let l = match d.get(off) { Some(&l) => l as usize, None => return false };
+        if l == 0 { return off + 5 <= d.len(); }
+        if l > 63 { return false; }
+        off += 1 + l;
+    }
+}
+
+fn dns(d: &[u8]) -> Option<(u16, u8)> {
+    if dns_question_ok(d) { return Some((PROTO_DNS, 75)); }
+    if d.len() > 2 && u16::from_be_bytes([d[0], d[1]]) as usize == d.len() - 2 && dns_question_ok(&d[2..]) {
+        return Some((PROTO_DNS, 80));
+    }
+    None
+}
+
+fn dhcp(d: &[u8]) -> Option<(u16, u8)> {
+    if d.len() >= 240 && matches!(d[0], 1 | 2) && d[236..240] == [0x63, 0x82, 0x53, 0x63] {
+        return Some((PROTO_DHCP, 100));
+    }
+    None
+}
+
+/// MQTT CONNECT: fixed header, variable-length remaining length, "MQTT"/"MQIsdp".
+fn mqtt(d: &[u8]) -> Option<(u16, u8)> {
+    if d.first() != Some(&0x10) { return None; }
+    let mut off = 1;
+    while off < 5 && d.get(off)? & 0x80 != 0 { off += 1; }
+    let rest = d.get(off + 1..)?;
+    (rest.starts_with(b"\x00\x04MQTT") || rest.starts_with(b"\x00\x06MQIsdp")).then_some((PROTO_MQTT, 100))
+}
+
+fn vpn_match(d: &[u8]) -> Option<(u16, u8)> {
+    let tcp = vpn::detect(d, true);
+    let v = tcp.or_else(|| vpn::detect(d, false))?;
+    Some(match v.protocol {
+        vpn::VPN_PROTO_WIREGUARD => (PROTO_WIREGUARD, 90),
+        vpn::VPN_PROTO_OPENVPN => (PROTO_OPENVPN, 70),
+        _ => (PROTO_IKE, 90),
+    })
+}
+
+fn asn1(d: &[u8]) -> Option<(u16, u8)> {
+    let body = if d.len() > 4 && u32::from_be_bytes([d[0], d[1], d[2], d[3]]) as usize == d.len() - 4 {
+        &d[4..]
+    } else {
+        d
+    };
+    if kerberos::is_kerberos(body) { return Some((PROTO_KERBEROS, 85)); }
+    if snmp::is_snmp(d) { return Some((PROTO_SNMP, 90)); }
+    None
+}
+
+type Detector = fn(&[u8]) -> Option<(u16, u8)>;
+
+const DETECTORS: &[Detector] = &[
+    text_protocol, mail_or_ftp, tls, smb, rdp, bittorrent, dhcp, mqtt, vpn_match, quic, asn1, dns,
+];
+
+pub fn identify(d: &[u8], src_port: u16, dst_port: u16) -> IrisProtocolMatch {
+    // Ports break ties between signatures of similar strength
+    let score = |(p, c): (u16, u8)| if port_matches(p, src_port, dst_port) { c.saturating_add(PORT_BONUS) } else { c };
+    let mut best = if d.starts_with(b"SSH-") {
+        Some((PROTO_SSH, 100))
+    } else {
+        DETECTORS.iter().filter_map(|det| det(d)).max_by_key(|&m| score(m))
+    };
+    // A bare "220" greeting is SMTP or FTP; only the port can tell
+    if best == Some((PROTO_SMTP, BARE_GREETING_CONFIDENCE)) && port_matches(PROTO_FTP, src_port, dst_port) {
+        best = Some((PROTO_FTP, BARE_GREETING_CONFIDENCE));
+    }
+    if let Some((protocol, conf)) = best {
+        let port_match = port_matches(protocol, src_port, dst_port);
+        let confidence = score((protocol, conf)).min(100);
+        return IrisProtocolMatch { protocol, confidence, payload_match: true, port_match };
+    }
+    match port_protocols(dst_port).next().or_else(|| port_protocols(src_port).next()) {
+        Some(protocol) => IrisProtocolMatch {
+            protocol, confidence: PORT_ONLY_CONFIDENCE, payload_match: false, port_match: true,
+        },
+        None => IrisProtocolMatch { protocol: PROTO_UNKNOWN, ..Default::default() },
+    }
+}
+
+/// Classify the first bytes inside a CONNECT tunnel. Only payload signatures count:
+/// the tunnel's `port` (0 if unknown) breaks ties but never names a protocol alone,
+/// since a proxy user picks the port freely.
+pub fn identify_tunnel(d: &[u8], port: u16) -> IrisTunnelPayload {
+    let m = identify(d, 0, port);
+    if !m.payload_match { return IrisTunnelPayload::default(); }
+    let tls_version = if m.protocol == PROTO_TLS {
+        tls::offered_version(d).unwrap_or_else(|| u16::from_be_bytes([d[1], d[2]]))
+    } else {
+        0
+    };
+    IrisTunnelPayload { protocol: m.protocol, confidence: m.confidence, tls_version }
+}
+
+// --- FFI entry points ---
+
+/// Classify a flow from its first payload bytes (either direction) and its ports.
+/// Returns 0=ok (protocol may be PROTO_UNKNOWN), -2=arg error.
+#[no_mangle]
+pub extern "C" fn iris_identify_protocol(
+    data: *const u8, len: usize, src_port: u16, dst_port: u16, out: *mut IrisProtocolMatch,
+) -> i32 {
+    if out.is_null() || (data.is_null() && len > 0) { return -2; }
+    let buf = if len == 0 { &[][..] } else { unsafe { std::slice::from_raw_parts(data, len) } };
+    unsafe { out.write(identify(buf, src_port, dst_port)); }
+    0
+}
+
+/// Classify the client's first bytes inside an established CONNECT tunnel (TLS with
+/// its version, plaintext HTTP, HTTP/2, SSH, ...). `port` is the CONNECT target's
+/// port, 0 if unknown. Returns 0=ok (protocol may be PROTO_UNKNOWN), -2=arg error.
+#[no_mangle]
+pub extern "C" fn iris_identify_tunnel(data: *const u8, len: usize, port: u16, out: *mut IrisTunnelPayload) -> i32 {
+    if out.is_null() || (data.is_null() && len > 0) { return -2; }
+    let buf = if len == 0 { &[][..] } else { unsafe { std::slice::from_raw_parts(data, len) } };
+    unsafe { out.write(identify_tunnel(buf, port)); }
+    0
+}
+
+/// Short name for a PROTO_* value. Returns a static string (do not free).
+#[no_mangle]
+pub extern "C" fn iris_protocol_name(protocol: u16) -> *const c_char {
+    let name: &'static [u8] = match protocol {
+        PROTO_HTTP => b"HTTP\0",
+        PROTO_HTTP2 => b"HTTP/2\0",
+        PROTO_TLS => b"TLS\0",
+        PROTO_QUIC => b"QUIC\0",
+        PROTO_SSH => b"SSH\0",
+        PROTO_DNS => b"DNS\0",
+        PROTO_SMB => b"SMB\0",
+        PROTO_RDP => b"RDP\0",
+        PROTO_BITTORRENT => b"BitTorrent\0",
+        PROTO_SMTP => b"SMTP\0",
+        PROTO_FTP => b"FTP\0",
+        PROTO_POP3 => b"POP3\0",
+        PROTO_IMAP => b"IMAP\0",
+        PROTO_KERBEROS => b"Kerberos\0",
+        PROTO_SNMP => b"SNMP\0",
+        PROTO_DHCP => b"DHCP\0",
+        PROTO_WIREGUARD => b"WireGuard\0",
+        PROTO_OPENVPN => b"OpenVPN\0",
+        PROTO_IKE => b"IKE\0",
+        PROTO_SIP => b"SIP\0",
+        PROTO_RTSP => b"RTSP\0",
+        PROTO_MQTT => b"MQTT\0",
+        _ => b"Unknown\0",
+    };
+    name.as_ptr() as *const c_char
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signatures_and_ports() {
+        let m = identify(b"GET / HTTP/1.1\r\nHost: x\r\n\r\n", 50000, 80);
+        assert_eq!((m.protocol, m.confidence, m.port_match), (PROTO_HTTP, 100, true));
+        assert_eq!(identify(b"INVITE sip:bob@example.com SIP/2.0\r\n", 5060, 5060).protocol, PROTO_SIP);
+        assert_eq!(identify(b"OPTIONS rtsp://cam/stream RTSP/1.0\r\n", 50000, 554).protocol, PROTO_RTSP);
+        assert_eq!(identify(b"SSH-2.0-OpenSSH_9.6\r\n", 22, 50000).confidence, 100);
+        assert_eq!(identify(b"\x16\x03\x01\x02\x00\x01\x00\x01\xfc", 50000, 8443).protocol, PROTO_TLS);
+        assert_eq!(identify(b"\x13BitTorrent protocol\0\0\0\0\0\0\0\0", 50000, 51413).protocol, PROTO_BITTORRENT);
+        let rdp = b"\x03\x00\x00\x13\x0e\xe0\x00\x00\x00\x00\x00\x01\x00\x08\x00\x03\x00\x00\x00";
+        assert_eq!(identify(rdp, 50000, 3389).protocol, PROTO_RDP);
+    }
+
+    #[test]
+    fn dns_and_greeting_disambiguation() {
+        let q = b"\x12\x34\x01\x00\x00\x01\x00\x00\x00\x00\x00\x00\x07example\x03com\x00\x00\x01\x00\x01";
+        let m = identify(q, 50000, 9999);
+        assert_eq!((m.protocol, m.port_match), (PROTO_DNS, false));
+        assert_eq!(identify(b"220 Welcome\r\n", 21, 50000).protocol, PROTO_FTP);
+        assert_eq!(identify(b"220 Welcome\r\n", 25, 50000).protocol, PROTO_SMTP);
+        assert_eq!(identify(b"220 mx.example.com ESMTP\r\n", 2525, 50000).protocol, PROTO_SMTP);
+    }
+
+    #[test]
+    fn tunnel_payloads() {
+        // ClientHello offering TLS 1.3 through supported_versions
+        let mut body = vec![0x03, 0x03];
+        body.extend([0u8; 32]);
+        body.extend([0, 0, 2, 0x13, 0x01, 1, 0, 0, 7, 0, 43, 0, 3, 2, 3, 4]);
+        let mut hello = vec![1, 0, 0, body.len() as u8];
+        hello.extend(body);
+        let mut record = vec![0x16, 0x03, 0x01, 0, hello.len() as u8];
+        record.extend(hello);
+        let t = identify_tunnel(&record, 443);
+        assert_eq!((t.protocol, t.tls_version), (PROTO_TLS, 0x0304));
+        assert_eq!(identify_tunnel(&record[..20], 443).tls_version, 0x0301);
+
+        let mut out = IrisTunnelPayload::default();
+        let get = b"GET / HTTP/1.1\r\nHost: a\r\n\r\n";
+        assert_eq!(iris_identify_tunnel(get.as_ptr(), get.len(), 443, &mut out), 0);
+        assert_eq!((out.protocol, out.tls_version), (PROTO_HTTP, 0));
+        assert_eq!(identify_tunnel(b"SSH-2.0-OpenSSH_9.6\r\n", 443).protocol, PROTO_SSH);
+        assert_eq!(identify_tunnel(b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n", 80).protocol, PROTO_HTTP2);
+        // The port alone says nothing about what a tunnel carries
+        assert_eq!(identify_tunnel(b"\x8f\x11\x02", 22).protocol, PROTO_UNKNOWN);
+    }
+
+    #[test]
+    fn port_fallback() {
+        let m = identify(b"\x8f\x11\x02", 50000, 445);
+        assert_eq!((m.protocol, m.confidence, m.payload_match), (PROTO_SMB, PORT_ONLY_CONFIDENCE, false));
+        assert_eq!(identify(b"", 50000, 60000).protocol, PROTO_UNKNOWN);
+    }
+}
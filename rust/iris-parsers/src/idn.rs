@@ -0,0 +1,322 @@
+//! Internationalized domain names: Punycode (RFC 3492) decoding of `xn--` labels
+//! and a coarse per-label script check, so homograph names such as
+//! `xn--80ak6aa92e.com` read as Unicode and Latin/Cyrillic mixes stand out; and
+//! the reverse, IDNA2008 encoding of Unicode names for queries.
+
+use crate::ffi::{free_cstr, to_cstr};
+use std::ffi::{c_char, CStr};
+
+#[repr(C)]
+pub struct IrisIdnName {
+    pub original: *mut c_char,    // the name as given
+    pub unicode: *mut c_char,     // xn-- labels decoded; undecodable labels kept as-is
+    pub has_idn: bool,            // at least one xn-- label
+    pub invalid: bool,            // an xn-- label was not valid Punycode
+    pub mixed_script: bool,       // a label mixes scripts (e.g. Latin + Cyrillic)
+    pub scripts: *mut c_char,     // scripts seen, comma separated: "Latin,Cyrillic"
+}
+
+const BASE: u32 = 36;
+const T_MIN: u32 = 1;
+const T_MAX: u32 = 26;
+const SKEW: u32 = 38;
+const DAMP: u32 = 700;
+const INITIAL_BIAS: u32 = 72;
+const INITIAL_N: u32 = 128;
+
+/// Label separators besides '.' (UTS #46): ideographic, fullwidth and halfwidth full stops.
+const DOTS: [char; 3] = ['\u{3002}', '\u{FF0E}', '\u{FF61}'];
+
+/// Compatibility forms that count as letters or digits but that IDNA2008 disallows:
+/// letterlike symbols, number forms, enclosed and fullwidth alphanumerics, math
+/// alphanumerics, superscripts and subscripts.
+const COMPAT: &[(u32, u32)] = &[
+    (0xB2, 0xB3), (0xB9, 0xB9), (0x2070, 0x209F), (0x2100, 0x218F), (0x2460, 0x24FF),
+    (0x3200, 0x33FF), (0xFF00, 0xFFEF), (0x1D400, 0x1D7FF), (0x1F100, 0x1F1FF),
+];
+
+/// Combining marks: valid in a label, but not first (RFC 5891 §4.2.3.2).
+const COMBINING: &[(u32, u32)] = &[
+    (0x300, 0x36F), (0x1AB0, 0x1AFF), (0x1DC0, 0x1DFF), (0x20D0, 0x20FF), (0xFE20, 0xFE2F),
+];
+
+/// Letter ranges by script; anything else (digits, hyphen, marks) is common.
+const SCRIPTS: &[(u32, u32, &str)] = &[
+    (0x41, 0x5A, "Latin"), (0x61, 0x7A, "Latin"), (0xC0, 0xD6, "Latin"), (0xD8, 0xF6, "Latin"),
+    (0xF8, 0x24F, "Latin"), (0x1E00, 0x1EFF, "Latin"), (0x2C60, 0x2C7F, "Latin"),
+    (0xA720, 0xA7FF, "Latin"), (0xFF21, 0xFF3A, "Latin"), (0xFF41, 0xFF5A, "Latin"),
+    (0x370, 0x3FF, "Greek"), (0x1F00, 0x1FFF, "Greek"),
+    (0x400, 0x52F, "Cyrillic"), (0x1C80, 0x1C8F, "Cyrillic"), (0x2DE0, 0x2DFF, "Cyrillic"),
+    (0xA640, 0xA69F, "Cyrillic"),
+    (0x530, 0x58F, "Armenian"), (0x590, 0x5FF, "Hebrew"),
+    (0x600, 0x6FF, "Arabic"), (0x750, 0x77F, "Arabic"), (0x8A0, 0x8FF, "Arabic"),
+    (0xFB50, 0xFDFF, "Arabic"), (0xFE70, 0xFEFF, "Arabic"),
+    (0x900, 0x97F, "Devanagari"), (0x980, 0x9FF, "Bengali"), (0xE00, 0xE7F, "Thai"),
+    (0x10A0, 0x10FF, "Georgian"),
+    (0x1100, 0x11FF, "Hangul"), (0x3130, 0x318F, "Hangul"), (0xAC00, 0xD7AF, "Hangul"),
+    (0x3040, 0x309F, "Hiragana"), (0x30A0, 0x30FF, "Katakana"), (0x3100, 0x312F, "Bopomofo"),
+    (0x3400, 0x4DBF, "Han"), (0x4E00, 0x9FFF, "Han"), (0x20000, 0x2FFFF, "Han"),
+];
+
+pub struct IdnName {
+    pub unicode: String,
+    pub has_idn: bool,
+    pub invalid: bool,
+    pub mixed_script: bool,
+    pub scripts: Vec<&'static str>,
+}
+
+fn adapt(delta: u32, points: u32, first: bool) -> u32 {
+    let mut delta = if first { delta / DAMP } else { delta / 2 };
+    delta += delta / points;
+    let mut k = 0;
+    while delta > ((BASE - T_MIN) * T_MAX) / 2 {
+        delta /= BASE - T_MIN;
+        k += BASE;
+    }
+    k + (BASE - T_MIN + 1) * delta / (delta + SKEW)
+}
+
+fn threshold(k: u32, bias: u32) -> u32 {
+    if k <= bias { T_MIN } else if k >= bias + T_MAX { T_MAX } else { k - bias }
+}
+
+fn digit(c: u8) -> Option<u32> {
+    match c {
+        b'a'..=b'z' => Some((c - b'a') as u32),
+        b'A'..=b'Z' => Some((c - b'A') as u32),
+        b'0'..=b'9' => Some((c - b'0') as u32 + 26),
+        _ => None,
+    }
+}
+
+/// Decode a Punycode string (the part after `xn--`).
+pub fn punycode_decode(input: &str) -> Option<String> {
+    if !input.is_ascii() { return None; }
+    let (basic, rest) = match input.rfind('-') {
+        Some(p) => (&input[..p], &input[p + 1..]),
+        None => ("", input),
+    };
+    let mut out: Vec<char> = basic.chars().collect();
+    let (mut n, mut i, mut bias) = (INITIAL_N, 0u32, INITIAL_BIAS);
+    let mut rest = rest.bytes().peekable();
+    while rest.peek().is_some() {
+        let (old, mut w) = (i, 1u32);
+        let mut k = BASE;
+        loop {
+            let d = digit(rest.next()?)?;
+            i = i.checked_add(d.checked_mul(w)?)?;
+            let t = threshold(k, bias);
+            if d < t { break; }
+            w = w.checked_mul(BASE - t)?;
+            k += BASE;
+        }
+        let points = out.len() as u32 + 1;
+        bias = adapt(i - old, points, old == 0);
+        n = n.checked_add(i / points)?;
+        i %= points;
+        out.insert(i as usize, char::from_u32(n)?);
+        i += 1;
+    }
+    Some(out.into_iter().collect())
+}
+
+fn encode_digit(d: u32) -> char {
+    if d < 26 { (b'a' + d as u8) as char } else { (b'0' + (d - 26) as u8) as char }
+}
+
+/// Encode a string as Punycode (without the `xn--` prefix). None on overflow.
+pub fn punycode_encode(input: &str) -> Option<String> {
+    let points: Vec<u32> = input.chars().map(|c| c as u32).collect();
+    let mut out: String = input.chars().filter(char::is_ascii).collect();
+    let basic = out.len() as u32;
+    if basic > 0 { out.push('-'); }
+    let (mut n, mut delta, mut bias, mut h) = (INITIAL_N, 0u32, INITIAL_BIAS, basic);
+    while (h as usize) < points.len() {
+        let m = points.iter().copied().filter(|&c| c >= n).min()?;
+        delta = delta.checked_add((m - n).checked_mul(h + 1)?)?;
+        n = m;
+        for &c in &points {
+            if c < n { delta = delta.checked_add(1)?; }
+            if c != n { continue; }
+            let (mut q, mut k) = (delta, BASE);
+            loop {
+                let t = threshold(k, bias);
+                if q < t { break; }
+                out.push(encode_digit(t + (q - t) % (BASE - t)));
+                q = (q - t) / (BASE - t);
+                k += BASE;
+            }
+            out.push(encode_digit(q));
+            bias = adapt(delta, h + 1, h == basic);
+            delta = 0;
+            h += 1;
+        }
+        delta += 1;
+        n += 1;
+    }
+    Some(out)
+}
+
+fn in_ranges(c: char, ranges: &[(u32, u32)]) -> bool {
+    ranges.iter().any(|&(lo, hi)| (lo..=hi).contains(&(c as u32)))
+}
+
+/// A coarse IDNA2008 PVALID check for lowercased text: letters, digits, combining
+/// marks and '-', less compatibility forms. Joiners (CONTEXTJ) are not accepted.
+fn allowed(c: char) -> bool {
+    c == '-' || (!in_ranges(c, COMPAT) && (c.is_alphabetic() || c.is_numeric() || in_ranges(c, COMBINING)))
+}
+
+/// Encode the Unicode labels of a dotted name as `xn--` A-labels (IDNA2008,
+/// lowercasing as UTS #46 maps; no normalization or bidi rules). ASCII labels
+/// pass through untouched, escapes included. Err(-3) for a disallowed code
+/// point, Err(-2) for a misplaced hyphen or combining mark.
+pub fn to_ascii(name: &str) -> Result<String, i32> {
+    if name.is_ascii() { return Ok(name.to_string()); }
+    let name: String = name.chars().map(|c| if DOTS.contains(&c) { '.' } else { c }).collect();
+    let mut labels = Vec::new();
+    for label in name.split('.') {
+        if label.is_ascii() {
+            labels.push(label.to_string());
+            continue;
+        }
+        let label: String = label.chars().flat_map(char::to_lowercase).collect();
+        if !label.chars().all(allowed) { return Err(-3); }
+        // RFC 5891 §4.2.3.1: no leading or trailing hyphen, none in positions 3-4
+        let hyphens = label.starts_with('-') || label.ends_with('-') || label.get(2..4) == Some("--");
+        if hyphens || label.chars().next().is_some_and(|c| in_ranges(c, COMBINING)) { return Err(-2); }
+        labels.push(format!("xn--{}", punycode_encode(&label).ok_or(-2)?));
+    }
+    Ok(labels.join("."))
+}
+
+fn script(c: char) -> Option<&'static str> {
+    let c = c as u32;
+    SCRIPTS.iter().find(|&&(lo, hi, _)| c >= lo && c <= hi).map(|&(_, _, s)| s)
+}
+
+/// Japanese and Korean legitimately mix Han with kana or Hangul in one label.
+fn script_group(s: &str) -> &str {
+    match s {
+        "Hiragana" | "Katakana" | "Hangul" | "Bopomofo" => "Han",
+        _ => s,
+    }
+}
+
+/// Decode every `xn--` label of `name` and check each label for mixed scripts.
+pub fn to_unicode(name: &str) -> IdnName {
+    let mut r = IdnName { unicode: String::new(), has_idn: false, invalid: false, mixed_script: false, scripts: Vec::new() };
+    let mut labels = Vec::new();
+    for label in name.split('.') {
+        let decoded = match label.get(..4) {
+            Some(p) if p.eq_ignore_ascii_case("xn--") => {
+                r.has_idn = true;
+                let d = punycode_decode(&label[4..]);
+                r.invalid |= d.is_none();
+                d.unwrap_or_else(|| label.to_string())
+            }
+            _ => label.to_string(),
+        };
+        let mut group: Option<&str> = None;
+        for s in decoded.chars().filter_map(script) {
+            if !r.scripts.contains(&s) { r.scripts.push(s); }
+            match group {
+                None => group = Some(script_group(s)),
+                Some(g) if g != script_group(s) => r.mixed_script = true,
+                _ => {}
+            }
+        }
+        labels.push(decoded);
+    }
+    r.unicode = labels.join(".");
+    r
+}
+
+// --- FFI ---
+
+/// Decode the IDN labels of a dotted name. Returns 0=ok, -2=arg error.
+/// Free with iris_idn_name_free.
+#[no_mangle]
+pub extern "C" fn iris_idn_decode(name: *const c_char, out: *mut IrisIdnName) -> i32 {
+    if name.is_null() || out.is_null() { return -2; }
+    let Ok(s) = unsafe { CStr::from_ptr(name) }.to_str() else { return -2; };
+    let r = to_unicode(s);
+    unsafe {
+        out.write(IrisIdnName {
+            original: to_cstr(s),
+            unicode: to_cstr(&r.unicode),
+            has_idn: r.has_idn,
+            invalid: r.invalid,
+            mixed_script: r.mixed_script,
+            scripts: to_cstr(&r.scripts.join(",")),
+        });
+    }
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn iris_idn_name_free(n: *mut IrisIdnName) {
+    if n.is_null() { return; }
+    let n = unsafe { &mut *n };
+    free_cstr(n.original);
+    free_cstr(n.unicode);
+    free_cstr(n.scripts);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rfc3492_samples() {
+        assert_eq!(punycode_decode("mnchen-3ya").as_deref(), Some("münchen"));
+        assert_eq!(punycode_decode("fsq").as_deref(), Some("例"));
+        // RFC 3492 §7.1 (L) Japanese
+        assert_eq!(punycode_decode("3B-ww4c5e180e575a65lsy2b").as_deref(), Some("3年B組金八先生"));
+        assert_eq!(punycode_decode("-> $1.00 <--").as_deref(), Some("-> $1.00 <-"));
+        assert!(punycode_decode("99999999999").is_none());
+        assert!(punycode_decode("a!b").is_none());
+    }
+
+    #[test]
+    fn encode_and_to_ascii() {
+        assert_eq!(punycode_encode("münchen").as_deref(), Some("mnchen-3ya"));
+        assert_eq!(punycode_encode("3年B組金八先生").as_deref(), Some("3B-ww4c5e180e575a65lsy2b"));
+        assert_eq!(punycode_encode("-> $1.00 <-").as_deref(), Some("-> $1.00 <--"));
+        for s in ["例", "аррӏе", "ليهمابتكلموشعربي؟", "Pročprostěnemluvíčesky"] {
+            assert_eq!(punycode_decode(&punycode_encode(s).unwrap()).as_deref(), Some(s));
+        }
+
+        assert_eq!(to_ascii("www.München.de").as_deref(), Ok("www.xn--mnchen-3ya.de"));
+        assert_eq!(to_ascii("例。jp").as_deref(), Ok("xn--fsq.jp"));
+        assert_eq!(to_ascii("plain.example.").as_deref(), Ok("plain.example."));
+        assert_eq!(to_ascii("snow☃.com"), Err(-3));
+        assert_eq!(to_ascii("ｅxample.com"), Err(-3));
+        assert_eq!(to_ascii("a_ü.com"), Err(-3));
+        assert_eq!(to_ascii("-ü.de"), Err(-2));
+        assert_eq!(to_ascii("\u{301}a.de"), Err(-2));
+    }
+
+    #[test]
+    fn homographs() {
+        // "аррӏе" in Cyrillic: one script, readable but not mixed
+        let r = to_unicode("xn--80ak6aa92e.com");
+        assert_eq!(r.unicode, "аррӏе.com");
+        assert!(r.has_idn && !r.mixed_script && !r.invalid);
+        assert_eq!(r.scripts, ["Cyrillic", "Latin"]);
+
+        // Latin "p" + Cyrillic "а" in one label
+        let r = to_unicode("xn--p-8sb.example");
+        assert_eq!(r.unicode, "pа.example");
+        assert!(r.mixed_script);
+
+        let r = to_unicode("XN--mnchen-3ya.de");
+        assert_eq!(r.unicode, "münchen.de");
+        assert!(!r.mixed_script);
+        let r = to_unicode("xn--zz!.com");
+        assert!(r.invalid && r.unicode == "xn--zz!.com");
+        let r = to_unicode("www.example.com");
+        assert!(!r.has_idn && r.unicode == "www.example.com");
+    }
+}
@@ -0,0 +1,237 @@
+//! DEFLATE (RFC 1951) decoder with zlib (RFC 1950) and gzip (RFC 1952) wrappers.
+//! Output is capped by the caller so hostile streams cannot balloon memory.
+
+const MAX_BITS: usize = 15;
+const LEN_BASE: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LEN_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DIST_BASE: [u16; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+                              2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
+const DIST_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+const CODE_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+struct Bits<'a> {
+    d: &'a [u8],
+    pos: usize,
+    buf: u64,
+    cnt: u32,
+}
+
+impl Bits<'_> {
+    fn get(&mut self, n: u32) -> Option<u32> {
+        while self.cnt < n {
+            self.buf |= (*self.d.get(self.pos)? as u64) << self.cnt;
+            self.pos += 1;
+            self.cnt += 8;
+        }
+        let v = (self.buf & ((1u64 << n) - 1)) as u32;
+        self.buf >>= n;
+        self.cnt -= n;
+        Some(v)
+    }
+
+    fn align(&mut self) {
+        self.buf = 0;
+        self.cnt = 0;
+    }
+}
+
+/// Canonical Huffman code: symbol counts per length and symbols in code order.
+struct Huffman {
+    count: [u16; MAX_BITS + 1],
+    symbol: Vec<u16>,
+}
+
+impl Huffman {
+    /// None for over-subscribed lengths; incomplete codes are allowed.
+    fn new(lengths: &[u8]) -> Option<Huffman> {
+        let mut count = [0u16; MAX_BITS + 1];
+        for &l in lengths { count[l as usize] += 1; }
+        let mut left = 1i32;
+        for &c in &count[1..] {
+            left = (left << 1) - c as i32;
+            if left < 0 { return None; }
+        }
+        let mut offs = [0u16; MAX_BITS + 1];
+        for l in 1..MAX_BITS { offs[l + 1] = offs[l] + count[l]; }
+        let mut symbol = vec![0u16; lengths.len()];
+        for (s, &l) in lengths.iter().enumerate() {
+            if l != 0 {
+                symbol[offs[l as usize] as usize] = s as u16;
+                offs[l as usize] += 1;
+            }
+        }
+        Some(Huffman { count, symbol })
+    }
+
+    fn decode(&self, b: &mut Bits) -> Option<u16> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for &count in &self.count[1..] {
+            code |= b.get(1)? as i32;
+            let count = count as i32;
+            if code - count < first { return self.symbol.get((index + code - first) as usize).copied(); }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        None
+    }
+}
+
+fn fixed_tables() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    (Huffman::new(&lengths).unwrap(), Huffman::new(&[5; 30]).unwrap())
+}
+
+fn dynamic_tables(b: &mut Bits) -> Option<(Huffman, Huffman)> {
+    let nlen = b.get(5)? as usize + 257;
+    let ndist = b.get(5)? as usize + 1;
+    let ncode = b.get(4)? as usize + 4;
+    if nlen > 286 || ndist > 30 { return None; }
+    let mut cl = [0u8; 19];
+    for &i in &CODE_ORDER[..ncode] { cl[i] = b.get(3)? as u8; }
+    let codes = Huffman::new(&cl)?;
+    let mut lengths = Vec::with_capacity(nlen + ndist);
+    while lengths.len() < nlen + ndist {
+        let sym = codes.decode(b)?;
+        let (value, repeat) = match sym {
+            0..=15 => { lengths.push(sym as u8); continue; }
+            16 => (*lengths.last()?, 3 + b.get(2)?),
+            17 => (0, 3 + b.get(3)?),
+            _ => (0, 11 + b.get(7)?),
+        };
+        if lengths.len() + repeat as usize > nlen + ndist { return None; }
+        lengths.extend(std::iter::repeat_n(value, repeat as usize));
+    }
+    if lengths[256] == 0 { return None; } // no end-of-block code
+    Some((Huffman::new(&lengths[..nlen])?, Huffman::new(&lengths[nlen..])?))
+}
+
+fn codes(b: &mut Bits, out: &mut Vec<u8>, lit: &Huffman, dist: &Huffman, limit: usize) -> Option<()> {
+    loop {
+        let sym = lit.decode(b)? as usize;
+        match sym {
+            0..=255 => {
+                if out.len() >= limit { return None; }
+                out.push(sym as u8);
+            }
+            256 => return Some(()),
+            _ => {
+                let i = sym - 257;
+                let len = *LEN_BASE.get(i)? as usize + b.get(LEN_EXTRA[i] as u32)? as usize;
+                let d = dist.decode(b)? as usize;
+                let off = *DIST_BASE.get(d)? as usize + b.get(DIST_EXTRA[d] as u32)? as usize;
+                if off > out.len() || out.len() + len > limit { return None; }
+                let start = out.len() - off;
+                for k in 0..len { out.push(out[start + k]); }
+            }
+        }
+    }
+}
+
+/// Decode a raw DEFLATE stream. Returns the output and the number of input bytes
+/// consumed; None if the stream is malformed, truncated or would exceed `limit`.
+pub fn inflate(data: &[u8], limit: usize) -> Option<(Vec<u8>, usize)> {
+    let mut b = Bits { d: data, pos: 0, buf: 0, cnt: 0 };
+    let mut out = Vec::new();
+    loop {
+        let last = b.get(1)?;
+        match b.get(2)? {
+            0 => {
+                b.align();
+                let hdr = data.get(b.pos..b.pos + 4)?;
+                let len = u16::from_le_bytes([hdr[0], hdr[1]]);
+                if len != !u16::from_le_bytes([hdr[2], hdr[3]]) { return None; }
+                let block = data.get(b.pos + 4..b.pos + 4 + len as usize)?;
+                if out.len() + block.len() > limit { return None; }
+                out.extend_from_slice(block);
+                b.pos += 4 + len as usize;
+            }
+            1 => { let (l, d) = fixed_tables(); codes(&mut b, &mut out, &l, &d, limit)?; }
+            2 => { let (l, d) = dynamic_tables(&mut b)?; codes(&mut b, &mut out, &l, &d, limit)?; }
+            _ => return None,
+        }
+        if last == 1 { return Some((out, b.pos)); }
+    }
+}
+
+/// zlib stream (CMF/FLG header, DEFLATE body). The Adler-32 trailer is not checked.
+pub fn zlib_decompress(data: &[u8], limit: usize) -> Option<Vec<u8>> {
+    let (cmf, flg) = (*data.first()?, *data.get(1)?);
+    if cmf & 0x0f != 8 || !(cmf as u16 * 256 + flg as u16).is_multiple_of(31) || flg & 0x20 != 0 { return None; }
+    inflate(&data[2..], limit).map(|(out, _)| out)
+}
+
+/// gzip member (header with optional extra/name/comment/CRC fields). Trailer not checked.
+pub fn gzip_decompress(data: &[u8], limit: usize) -> Option<Vec<u8>> {
+    if data.len() < 10 || data[..3] != [0x1f, 0x8b, 8] { return None; }
+    let flags = data[3];
+    let mut p = 10;
+    if flags & 0x04 != 0 { p += 2 + u16::from_le_bytes([*data.get(p)?, *data.get(p + 1)?]) as usize; }
+    for bit in [0x08, 0x10] {
+        if flags & bit != 0 { p += data.get(p..)?.iter().position(|&b| b == 0)? + 1; }
+    }
+    if flags & 0x02 != 0 { p += 2; }
+    inflate(data.get(p..)?, limit).map(|(out, _)| out)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    /// zlib stream of stored blocks, for building test inputs (Adler-32 left zero).
+    pub fn zlib_stored(data: &[u8]) -> Vec<u8> {
+        [&[0x78, 0x01][..], &deflate_stored(data), &[0; 4]].concat()
+    }
+
+    /// gzip member of stored blocks (CRC-32 and size left zero).
+    pub fn gzip_stored(data: &[u8]) -> Vec<u8> {
+        [&[0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 3][..], &deflate_stored(data), &[0; 8]].concat()
+    }
+
+    fn deflate_stored(data: &[u8]) -> Vec<u8> {
+        let mut v = Vec::new();
+        let mut chunks = data.chunks(0xffff).peekable();
+        if chunks.peek().is_none() { return vec![1, 0, 0, 0xff, 0xff]; }
+        while let Some(c) = chunks.next() {
+            v.push(chunks.peek().is_none() as u8);
+            v.extend_from_slice(&(c.len() as u16).to_le_bytes());
+            v.extend_from_slice(&(!(c.len() as u16)).to_le_bytes());
+            v.extend_from_slice(c);
+        }
+        v
+    }
+
+    #[test]
+    fn stored_fixed_and_dynamic_blocks() {
+        // zlib.compress(b"hello hello hello hello", 9)
+        let fixed = [0x78, 0xda, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0xc8, 0x40, 0x27, 0x01, 0x68, 0x03, 0x08, 0xb1];
+        assert_eq!(zlib_decompress(&fixed, 100).unwrap(), b"hello hello hello hello");
+        assert!(zlib_decompress(&fixed, 10).is_none());
+        assert_eq!(inflate(&[0x01, 3, 0, 0xfc, 0xff, b'a', b'b', b'c'], 10).unwrap(), (b"abc".to_vec(), 8));
+        let big: Vec<u8> = (0..70_000u32).map(|i| i as u8).collect();
+        assert_eq!(zlib_decompress(&zlib_stored(&big), big.len()).unwrap(), big);
+
+        // gzip.compress(text, mtime=0) for 200 LCG-picked letters: one dynamic Huffman block
+        let gz = [
+            0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0x15, 0x8e, 0x51, 0x0a, 0x00, 0x41,
+            0x08, 0x42, 0xaf, 0xd2, 0xd5, 0x82, 0x84, 0x82, 0x28, 0x28, 0xe7, 0xfe, 0xdb, 0xfe, 0x04, 0x26,
+            0x4f, 0x05, 0xbd, 0xb1, 0x1a, 0x6f, 0x1b, 0x42, 0x4c, 0x42, 0x42, 0x15, 0x84, 0x6b, 0xab, 0x80,
+            0x2d, 0x8a, 0x5e, 0xa0, 0x09, 0x88, 0xee, 0x70, 0x80, 0x73, 0x25, 0xb1, 0xd1, 0x85, 0x93, 0xe7,
+            0xf7, 0xdc, 0x4b, 0x0d, 0x24, 0x9e, 0x71, 0xf9, 0x04, 0xfb, 0xd4, 0xc1, 0x10, 0xce, 0x81, 0x63,
+            0x30, 0xd2, 0x47, 0x70, 0xd1, 0x3f, 0x60, 0xfa, 0xe7, 0xa4, 0x21, 0xeb, 0xa4, 0x13, 0xb4, 0xbc,
+            0x76, 0xfa, 0x4e, 0x14, 0x42, 0xca, 0xcc, 0xb5, 0x64, 0xca, 0x29, 0x4a, 0x3d, 0x5a, 0x8e, 0xb3,
+            0xbd, 0x5b, 0x57, 0x5e, 0xf1, 0x63, 0x37, 0xce, 0x3d, 0x89, 0xc2, 0x3c, 0x7c, 0xba, 0x5b, 0xc7,
+            0x46, 0xc8, 0x00, 0x00, 0x00,
+        ];
+        let mut x = 1u32;
+        let expected: Vec<u8> = (0..200).map(|_| {
+            x = x.wrapping_mul(1103515245).wrapping_add(12345) & 0x7fff_ffff;
+            b"eeeeeeetttaaoinshrdlu  "[(x >> 16) as usize % 23]
+        }).collect();
+        assert_eq!(gzip_decompress(&gz, 1 << 16).unwrap(), expected);
+    }
+}
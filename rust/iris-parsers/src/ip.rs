@@ -0,0 +1,364 @@
+//! IPv4/IPv6 header parser. Walks IPv6 extension headers to the upper-layer protocol.
+
+use crate::ffi::IrisSlice;
+
+pub const PROTO_ICMP: u8 = 1;
+pub const PROTO_TCP: u8 = 6;
+pub const PROTO_UDP: u8 = 17;
+pub const PROTO_ICMPV6: u8 = 58;
+
+/// IP address tagged with its family (4, 6, or 0 = absent).
+/// IPv4 addresses occupy the first four bytes.
+#[repr(C)]
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash, Debug)]
+pub struct IrisIpAddr {
+    pub family: u8,
+    pub bytes: [u8; 16],
+}
+
+impl IrisIpAddr {
+    pub fn v4(b: &[u8]) -> Self {
+        let mut bytes = [0u8; 16];
+        bytes[..4].copy_from_slice(&b[..4]);
+        IrisIpAddr { family: 4, bytes }
+    }
+
+    pub fn v6(b: &[u8]) -> Self {
+        let mut bytes = [0u8; 16];
+        bytes.copy_from_slice(&b[..16]);
+        IrisIpAddr { family: 6, bytes }
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        match self.family { 4 => &self.bytes[..4], 6 => &self.bytes[..], _ => &[] }
+    }
+}
+
+#[repr(C)]
+pub struct IrisIpHeader {
+    pub version: u8,
+    pub protocol: u8,        // upper-layer protocol (after IPv6 extension headers)
+    pub ttl: u8,             // TTL / hop limit
+    pub dscp: u8,
+    pub src: IrisIpAddr,
+    pub dst: IrisIpAddr,
+    pub header_len: usize,   // includes IPv4 options / IPv6 extension headers
+    pub total_len: usize,
+    pub identification: u32,
+    pub is_fragment: bool,
+    pub more_fragments: bool,
+    pub dont_fragment: bool,
+    pub fragment_offset: u16, // bytes
+    pub checksum_valid: bool, // IPv4 header checksum (always true for IPv6)
+    pub payload: IrisSlice,
+}
+
+pub struct IpPacket<'a> {
+    pub version: u8,
+    pub protocol: u8,
+    pub ttl: u8,
+    pub dscp: u8,
+    pub src: IrisIpAddr,
+    pub dst: IrisIpAddr,
+    pub header_len: usize,
+    pub total_len: usize,
+    pub identification: u32,
+    pub more_fragments: bool,
+    pub dont_fragment: bool,
+    pub fragment_offset: u16,
+    pub checksum_valid: bool,
+    pub payload: &'a [u8],
+}
+
+impl IpPacket<'_> {
+    pub fn is_fragment(&self) -> bool { self.more_fragments || self.fragment_offset != 0 }
+}
+
+/// RFC 1071 ones-complement checksum over the concatenation of `parts`.
+pub fn internet_checksum(parts: &[&[u8]]) -> u16 {
+    let mut sum = 0u32;
+    let mut carry: Option<u8> = None;
+    for part in parts {
+        for &b in part.iter() {
+            match carry.take() {
+                Some(hi) => sum += u16::from_be_bytes([hi, b]) as u32,
+                None => carry = Some(b),
+            }
+        }
+    }
+    if let Some(hi) = carry { sum += (hi as u32) << 8; }
+    while sum > 0xFFFF { sum = (sum & 0xFFFF) + (sum >> 16); }
+    !(sum as u16)
+}
+
+/// Pseudo-header for TCP/UDP/ICMPv6 checksums.
+pub fn pseudo_header(src: &IrisIpAddr, dst: &IrisIpAddr, protocol: u8, len: usize) -> Vec<u8> {
+    let mut ph = Vec::with_capacity(40);
+    ph.extend_from_slice(src.as_slice());
+    ph.extend_from_slice(dst.as_slice());
+    if src.family == 6 {
+        ph.extend_from_slice(&(len as u32).to_be_bytes());
+        ph.extend_from_slice(&[0, 0, 0, protocol]);
+    } else {
+        ph.extend_from_slice(&[0, protocol]);
+        ph.extend_from_slice(&(len as u16).to_be_bytes());
+    }
+    ph
+}
+
+fn parse_v4(data: &[u8]) -> Option<IpPacket<'_>> {
+    if data.len() < 20 { return None; }
+    let ihl = (data[0] & 0x0F) as usize * 4;
+    if ihl < 20 || ihl > data.len() { return None; }
+    let total = u16::from_be_bytes([data[2], data[3]]) as usize;
+    // Truncated captures (e.g. ICMP-embedded headers) keep whatever is present
+    let end = if total >= ihl { total.min(data.len()) } else { data.len() };
+    let frag = u16::from_be_bytes([data[6], data[7]]);
+    Some(IpPacket {
+        version: 4,
+        protocol: data[9],
+        ttl: data[8],
+        dscp: data[1] >> 2,
+        src: IrisIpAddr::v4(&data[12..16]),
+        dst: IrisIpAddr::v4(&data[16..20]),
+        header_len: ihl,
+        total_len: total,
+        identification: u16::from_be_bytes([data[4], data[5]]) as u32,
+        more_fragments: frag & 0x2000 != 0,
+        dont_fragment: frag & 0x4000 != 0,
+        fragment_offset: (frag & 0x1FFF) << 3,
+        checksum_valid: internet_checksum(&[&data[..ihl]]) == 0,
+        payload: &data[ihl..end],
+    })
+}
+
+fn parse_v6(data: &[u8]) -> Option<IpPacket<'_>> {
+    if data.len() < 40 { return None; }
+    let plen = u16::from_be_bytes([data[4], data[5]]) as usize;
+    let end = (40 + plen).min(data.len());
+    let mut next = data[6];
+    let mut off = 40usize;
+    let mut pkt = IpPacket {
+        version: 6,
+        protocol: 0,
+        ttl: data[7],
+        dscp: ((data[0] & 0x0F) << 2) | (data[1] >> 6),
+        src: IrisIpAddr::v6(&data[8..24]),
+        dst: IrisIpAddr::v6(&data[24..40]),
+        header_len: 40,
+        total_len: 40 + plen,
+        identification: 0,
+        more_fragments: false,
+        dont_fragment: false,
+        fragment_offset: 0,
+        checksum_valid: true,
+        payload: &[],
+    };
+    // Hop-by-hop, routing, fragment, destination options, AH
+    for _ in 0..8 {
+        match next {
+            0 | 43 | 60 => {
+                if off + 2 > end { return None; }
+                let hlen = (data[off + 1] as usize + 1) * 8;
+                next = data[off];
+                off += hlen;
+            }
+            44 => {
+                if off + 8 > end { return None; }
+                let fo = u16::from_be_bytes([data[off + 2], data[off + 3]]);
+                pkt.fragment_offset = fo & 0xFFF8;
+                pkt.more_fragments = fo & 1 != 0;
+                pkt.identification = u32::from_be_bytes([data[off + 4], data[off + 5], data[off + 6], data[off + 7]]);
+                next = data[off];
+                off += 8;
+                // A non-initial fragment carries the middle of the payload, not headers
+                if pkt.fragment_offset != 0 { break; }
+            }
+            51 => {
+                if off + 2 > end { return None; }
+                let hlen = (data[off + 1] as usize + 2) * 4;
+                next = data[off];
+                off += hlen;
+            }
+            _ => break,
+        }
+    }
+    if off > end { return None; }
+    pkt.protocol = next;
+    pkt.header_len = off;
+    pkt.payload = &data[off..end];
+    Some(pkt)
+}
+
+/// Parse an IPv4 or IPv6 header, dispatching on the version nibble.
+pub fn parse_packet(data: &[u8]) -> Option<IpPacket<'_>> {
+    match data.first()? >> 4 {
+        4 => parse_v4(data),
+        6 => parse_v6(data),
+        _ => None,
+    }
+}
+
+// --- FFI entry points ---
+
+/// Parse an IPv4/IPv6 packet header. Returns 0=ok, -1=truncated/invalid, -2=arg error.
+/// `out.payload` points into `data` — keep it alive.
+#[no_mangle]
+pub extern "C" fn iris_ip_parse(data: *const u8, len: usize, out: *mut IrisIpHeader) -> i32 {
+    if data.is_null() || out.is_null() || len == 0 { return -2; }
+    let buf = unsafe { std::slice::from_raw_parts(data, len) };
+    let p = match parse_packet(buf) { Some(p) => p, None => return -1 };
+    unsafe {
+        out.write(IrisIpHeader {
+            version: p.version,
+            protocol: p.protocol,
+            ttl: p.ttl,
+            dscp: p.dscp,
+            src: p.src,
+            dst: p.dst,
+            header_len: p.header_len,
+            total_len: p.total_len,
+            identification: p.identification,
+            is_fragment: p.is_fragment(),
+            more_fragments: p.more_fragments,
+            dont_fragment: p.dont_fragment,
+            fragment_offset: p.fragment_offset,
+            checksum_valid: p.checksum_valid,
+            payload: IrisSlice::from_bytes(p.payload),
+        });
+    }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v4(ihl_words: u8, total: u16, frag: u16, proto: u8, rest: &[u8]) -> Vec<u8> {
+        let mut h = vec![0x40 | ihl_words, 0xB8, 0, 0, 0x12, 0x34, 0, 0, 64, proto, 0, 0, 10, 0, 0, 1, 192, 168, 1, 2];
+        h[2..4].copy_from_slice(&total.to_be_bytes());
+        h[6..8].copy_from_slice(&frag.to_be_bytes());
+        h.resize(ihl_words as usize * 4, 1); // options
+        let sum = internet_checksum(&[&h]);
+        h[10..12].copy_from_slice(&sum.to_be_bytes());
+        h.extend_from_slice(rest);
+        h
+    }
+
+    fn v6(next: u8, rest: &[u8]) -> Vec<u8> {
+        let mut h = vec![0x60, 0x30, 0, 0, 0, 0, next, 255];
+        h[4..6].copy_from_slice(&(rest.len() as u16).to_be_bytes());
+        h.extend_from_slice(&[0x20, 0x01, 0x0d, 0xb8]);
+        h.extend_from_slice(&[0; 11]);
+        h.push(1);
+        h.extend_from_slice(&[0xfe, 0x80]);
+        h.extend_from_slice(&[0; 13]);
+        h.push(2);
+        h.extend_from_slice(rest);
+        h
+    }
+
+    #[test]
+    fn ipv4() {
+        let d = v4(5, 24, 0x4000, PROTO_TCP, b"abcdTRAILER");
+        let p = parse_packet(&d).unwrap();
+        assert_eq!((p.version, p.protocol, p.ttl, p.dscp), (4, PROTO_TCP, 64, 46));
+        assert_eq!(p.src.as_slice(), &[10, 0, 0, 1]);
+        assert_eq!(p.dst.as_slice(), &[192, 168, 1, 2]);
+        assert_eq!((p.header_len, p.total_len, p.identification), (20, 24, 0x1234));
+        assert!(p.checksum_valid && p.dont_fragment && !p.is_fragment());
+        assert_eq!(p.payload, b"abcd"); // link-layer padding dropped
+
+        // Options, and a fragment 1480 bytes in
+        let d = v4(6, 28, 0x2000 | 185, PROTO_UDP, b"abcd");
+        let p = parse_packet(&d).unwrap();
+        assert_eq!((p.header_len, p.fragment_offset), (24, 1480));
+        assert!(p.more_fragments && p.is_fragment());
+        assert_eq!(p.payload, b"abcd");
+
+        let mut bad = d.clone();
+        bad[8] = 1;
+        assert!(!parse_packet(&bad).unwrap().checksum_valid);
+    }
+
+    #[test]
+    fn ipv4_truncation() {
+        let d = v4(5, 1500, 0, PROTO_ICMP, b"only some");
+        assert_eq!(parse_packet(&d).unwrap().payload, b"only some"); // total past the capture
+        let d = v4(5, 4, 0, PROTO_ICMP, b"xy"); // total below the header: keep what is there
+        assert_eq!(parse_packet(&d).unwrap().payload, b"xy");
+        assert!(parse_packet(&d[..19]).is_none());
+        let mut d = v4(5, 20, 0, PROTO_ICMP, b"");
+        d[0] = 0x44; // IHL under 20 bytes
+        assert!(parse_packet(&d).is_none());
+        d[0] = 0x46; // IHL past the data
+        assert!(parse_packet(&d).is_none());
+        assert!(parse_packet(&[0x50; 40]).is_none());
+        assert!(parse_packet(&[]).is_none());
+    }
+
+    #[test]
+    fn ipv6_extension_headers() {
+        let d = v6(PROTO_UDP, b"payload");
+        let p = parse_packet(&d).unwrap();
+        assert_eq!((p.version, p.protocol, p.ttl, p.dscp, p.header_len), (6, PROTO_UDP, 255, 0, 40));
+        assert_eq!(p.src.as_slice()[..4], [0x20, 0x01, 0x0d, 0xb8]);
+        assert_eq!(p.dst.as_slice()[15], 2);
+        assert_eq!(p.payload, b"payload");
+
+        // Hop-by-hop (8) -> routing (24) -> AH (16) -> destination options (8) -> TCP
+        let mut ext = vec![43, 0, 5, 2, 1, 4, 0, 0];
+        ext.extend_from_slice(&[51, 2, 0, 0]);
+        ext.extend_from_slice(&[0; 20]);
+        ext.extend_from_slice(&[60, 2, 0, 0]);
+        ext.extend_from_slice(&[0; 12]);
+        ext.extend_from_slice(&[PROTO_TCP, 0, 1, 4, 0, 0, 0, 0]);
+        ext.extend_from_slice(b"tcp");
+        let d = v6(0, &ext);
+        let p = parse_packet(&d).unwrap();
+        assert_eq!((p.protocol, p.header_len), (PROTO_TCP, 96));
+        assert_eq!(p.payload, b"tcp");
+
+        // First fragment: the walk continues into the next header
+        let d = v6(44, &[PROTO_ICMPV6, 0, 0, 1, 0xde, 0xad, 0xbe, 0xef, 128, 0]);
+        let p = parse_packet(&d).unwrap();
+        assert_eq!((p.protocol, p.fragment_offset, p.identification), (PROTO_ICMPV6, 0, 0xdeadbeef));
+        assert!(p.more_fragments && p.is_fragment());
+        assert_eq!(p.payload, &[128, 0]);
+
+        // Later fragment whose data happens to look like a hop-by-hop header
+        let d = v6(44, &[0, 0, 0x05, 0xA8, 0, 0, 0, 7, 0, 200, 1, 2]);
+        let p = parse_packet(&d).unwrap();
+        assert_eq!((p.protocol, p.fragment_offset, p.header_len), (0, 1448, 48));
+        assert!(!p.more_fragments && p.is_fragment());
+        assert_eq!(p.payload, &[0, 200, 1, 2]);
+    }
+
+    #[test]
+    fn ipv6_truncation() {
+        assert!(parse_packet(&v6(PROTO_UDP, b"")[..39]).is_none());
+        assert!(parse_packet(&v6(0, &[PROTO_TCP])).is_none()); // extension header cut short
+        assert!(parse_packet(&v6(44, &[PROTO_TCP, 0, 0, 0])).is_none());
+        assert!(parse_packet(&v6(51, &[PROTO_TCP])).is_none());
+        assert!(parse_packet(&v6(0, &[PROTO_TCP, 1, 0, 0, 0, 0, 0, 0])).is_none()); // off > end
+
+        // Payload length past the capture keeps what is there
+        let mut d = v6(PROTO_UDP, b"abc");
+        d[4..6].copy_from_slice(&100u16.to_be_bytes());
+        let p = parse_packet(&d).unwrap();
+        assert_eq!((p.total_len, p.payload), (140, &b"abc"[..]));
+    }
+
+    #[test]
+    fn ffi_parse() {
+        let d = v4(5, 23, 0, PROTO_UDP, b"abc");
+        let mut out = std::mem::MaybeUninit::<IrisIpHeader>::uninit();
+        assert_eq!(iris_ip_parse(d.as_ptr(), d.len(), out.as_mut_ptr()), 0);
+        let h = unsafe { out.assume_init_read() };
+        assert_eq!((h.version, h.protocol, h.header_len, h.payload.len), (4, PROTO_UDP, 20, 3));
+        assert!(h.checksum_valid && !h.is_fragment);
+        assert_eq!(iris_ip_parse(d.as_ptr(), 10, out.as_mut_ptr()), -1);
+        assert_eq!(iris_ip_parse(d.as_ptr(), 0, out.as_mut_ptr()), -2);
+        assert_eq!(iris_ip_parse(std::ptr::null(), 1, out.as_mut_ptr()), -2);
+    }
+}
@@ -0,0 +1,340 @@
+//! JSON (RFC 8259) into an owned value tree. Object members keep their document
+//! order; duplicate keys are kept as-is and lookups return the first. The FFI exposes
+//! the tree as opaque node pointers owned by the root, like plist.rs.
+
+use crate::ffi::IrisSlice;
+use std::ffi::{c_char, CStr};
+
+pub const JSON_NULL: u8 = 1;
+pub const JSON_BOOL: u8 = 2;
+pub const JSON_NUMBER: u8 = 3;
+pub const JSON_STRING: u8 = 4;
+pub const JSON_ARRAY: u8 = 5;
+pub const JSON_OBJECT: u8 = 6;
+
+const MAX_DEPTH: usize = 128;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// Member of an object by key (first match).
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(m) => m.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        if let Value::String(s) = self { Some(s) } else { None }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        if let Value::Number(n) = self { Some(*n) } else { None }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        if let Value::Bool(b) = self { Some(*b) } else { None }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        if let Value::Array(a) = self { Some(a) } else { None }
+    }
+
+    pub fn type_code(&self) -> u8 {
+        match self {
+            Value::Null => JSON_NULL,
+            Value::Bool(_) => JSON_BOOL,
+            Value::Number(_) => JSON_NUMBER,
+            Value::String(_) => JSON_STRING,
+            Value::Array(_) => JSON_ARRAY,
+            Value::Object(_) => JSON_OBJECT,
+        }
+    }
+
+    /// RFC 6901 JSON Pointer lookup ("" is the value itself, "/a/0/b" descends).
+    pub fn pointer(&self, ptr: &str) -> Option<&Value> {
+        if ptr.is_empty() { return Some(self); }
+        ptr.strip_prefix('/')?.split('/').try_fold(self, |v, token| {
+            let token = token.replace("~1", "/").replace("~0", "~");
+            match v {
+                Value::Object(_) => v.get(&token),
+                Value::Array(a) if token == "0" || !token.starts_with('0') => a.get(token.parse::<usize>().ok()?),
+                _ => None,
+            }
+        })
+    }
+}
+
+struct Parser<'a> {
+    d: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn ws(&mut self) {
+        while self.d.get(self.pos).is_some_and(|c| matches!(c, b' ' | b'\t' | b'\n' | b'\r')) { self.pos += 1; }
+    }
+
+    fn eat(&mut self, c: u8) -> bool {
+        self.ws();
+        let hit = self.d.get(self.pos) == Some(&c);
+        self.pos += hit as usize;
+        hit
+    }
+
+    fn literal(&mut self, word: &[u8], v: Value) -> Option<Value> {
+        if !self.d[self.pos..].starts_with(word) { return None; }
+        self.pos += word.len();
+        Some(v)
+    }
+
+    fn value(&mut self, depth: usize) -> Option<Value> {
+        if depth > MAX_DEPTH { return None; }
+        self.ws();
+        match *self.d.get(self.pos)? {
+            b'{' => {
+                self.pos += 1;
+                let mut members = Vec::new();
+                if self.eat(b'}') { return Some(Value::Object(members)); }
+                loop {
+                    self.ws();
+                    let key = self.string()?;
+                    if !self.eat(b':') { return None; }
+                    members.push((key, self.value(depth + 1)?));
+                    if self.eat(b'}') { return Some(Value::Object(members)); }
+                    if !self.eat(b',') { return None; }
+                }
+            }
+            b'[' => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                if self.eat(b']') { return Some(Value::Array(items)); }
+                loop {
+                    items.push(self.value(depth + 1)?);
+                    if self.eat(b']') { return Some(Value::Array(items)); }
+                    if !self.eat(b',') { return None; }
+                }
+            }
+            b'"' => self.string().map(Value::String),
+            b't' => self.literal(b"true", Value::Bool(true)),
+            b'f' => self.literal(b"false", Value::Bool(false)),
+            b'n' => self.literal(b"null", Value::Null),
+            _ => self.number(),
+        }
+    }
+
+    fn number(&mut self) -> Option<Value> {
+        let start = self.pos;
+        while self.d.get(self.pos).is_some_and(|c| matches!(c, b'0'..=b'9' | b'-' | b'+' | b'.' | b'e' | b'E')) { self.pos += 1; }
+        let s = std::str::from_utf8(&self.d[start..self.pos]).ok()?;
+        // Rust's float parser accepts forms JSON does not ("1.", ".5", "+1", "inf")
+        let digits = s.strip_prefix('-').unwrap_or(s);
+        let int_end = digits.find(|c: char| !c.is_ascii_digit()).unwrap_or(digits.len());
+        if int_end == 0 || (int_end > 1 && digits.starts_with('0')) { return None; }
+        if digits[int_end..].starts_with('.') && !digits[int_end + 1..].starts_with(|c: char| c.is_ascii_digit()) { return None; }
+        s.parse().ok().map(Value::Number)
+    }
+
+    fn hex4(&mut self) -> Option<u32> {
+        let h = std::str::from_utf8(self.d.get(self.pos..self.pos + 4)?).ok()?;
+        self.pos += 4;
+        u32::from_str_radix(h, 16).ok()
+    }
+
+    fn string(&mut self) -> Option<String> {
+        if self.d.get(self.pos) != Some(&b'"') { return None; }
+        self.pos += 1;
+        let mut out = Vec::new();
+        loop {
+            let c = *self.d.get(self.pos)?;
+            self.pos += 1;
+            match c {
+                b'"' => return String::from_utf8(out).ok(),
+                b'\\' => {
+                    let e = *self.d.get(self.pos)?;
+                    self.pos += 1;
+                    let ch = match e {
+                        b'"' => '"', b'\\' => '\\', b'/' => '/',
+                        b'b' => '\u{8}', b'f' => '\u{c}', b'n' => '\n', b'r' => '\r', b't' => '\t',
+                        b'u' => {
+                            let mut cp = self.hex4()?;
+                            if (0xd800..0xdc00).contains(&cp) && self.d[self.pos..].starts_with(b"\\u") {
+                                self.pos += 2;
+                                let lo = self.hex4()?;
+                                if !(0xdc00..0xe000).contains(&lo) { return None; }
+                                cp = 0x10000 + ((cp - 0xd800) << 10) + (lo - 0xdc00);
+                            }
+                            char::from_u32(cp).unwrap_or('\u{fffd}')
+                        }
+                        _ => return None,
+                    };
+                    out.extend_from_slice(ch.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                0..=0x1f => return None,
+                _ => out.push(c),
+            }
+        }
+    }
+}
+
+/// Parse a complete document; None on syntax errors, trailing data or nesting deeper
+/// than MAX_DEPTH. A leading UTF-8 BOM is skipped.
+pub fn parse(data: &[u8]) -> Option<Value> {
+    let data = data.strip_prefix(b"\xef\xbb\xbf").unwrap_or(data);
+    let mut p = Parser { d: data, pos: 0 };
+    let v = p.value(0)?;
+    p.ws();
+    (p.pos == data.len()).then_some(v)
+}
+
+// --- FFI entry points ---
+
+fn node<'a>(n: *const Value) -> Option<&'a Value> {
+    if n.is_null() { None } else { Some(unsafe { &*n }) }
+}
+
+fn node_ptr(v: Option<&Value>) -> *const Value {
+    v.map_or(std::ptr::null(), |v| v as *const Value)
+}
+
+/// Parse a JSON document. Returns the root node (null on parse/arg error); every node
+/// pointer obtained from it stays valid until iris_json_free(root).
+#[no_mangle]
+pub extern "C" fn iris_json_parse(data: *const u8, len: usize) -> *mut Value {
+    if data.is_null() { return std::ptr::null_mut(); }
+    let buf = unsafe { std::slice::from_raw_parts(data, len) };
+    match parse(buf) {
+        Some(v) => Box::into_raw(Box::new(v)),
+        None => std::ptr::null_mut(),
+    }
+}
+
+/// Free a tree returned by iris_json_parse. Pass only the root.
+#[no_mangle]
+pub extern "C" fn iris_json_free(root: *mut Value) {
+    if root.is_null() { return; }
+    unsafe { drop(Box::from_raw(root)); }
+}
+
+/// JSON_* type of a node, 0 for null.
+#[no_mangle]
+pub extern "C" fn iris_json_type(n: *const Value) -> u8 {
+    node(n).map_or(0, Value::type_code)
+}
+
+/// Element count of an array or object, else 0.
+#[no_mangle]
+pub extern "C" fn iris_json_count(n: *const Value) -> usize {
+    match node(n) {
+        Some(Value::Array(a)) => a.len(),
+        Some(Value::Object(m)) => m.len(),
+        _ => 0,
+    }
+}
+
+/// Array element or object member value at `index`; null if out of range or not a container.
+#[no_mangle]
+pub extern "C" fn iris_json_item(n: *const Value, index: usize) -> *const Value {
+    node_ptr(match node(n) {
+        Some(Value::Object(m)) => m.get(index).map(|(_, v)| v),
+        Some(v) => v.as_array().and_then(|a| a.get(index)),
+        None => None,
+    })
+}
+
+/// Object key at `index` (UTF-8, not NUL-terminated); empty slice if absent.
+#[no_mangle]
+pub extern "C" fn iris_json_key(n: *const Value, index: usize) -> IrisSlice {
+    match node(n) {
+        Some(Value::Object(m)) => IrisSlice::from_bytes(m.get(index).map_or(&b""[..], |(k, _)| k.as_bytes())),
+        _ => IrisSlice::from_bytes(b""),
+    }
+}
+
+/// Object member for a NUL-terminated key (first match); null if missing or not an object.
+#[no_mangle]
+pub extern "C" fn iris_json_get(n: *const Value, key: *const c_char) -> *const Value {
+    if key.is_null() { return std::ptr::null(); }
+    let key = unsafe { CStr::from_ptr(key) }.to_string_lossy();
+    node_ptr(node(n).and_then(|v| v.get(&key)))
+}
+
+/// Descendant addressed by an RFC 6901 JSON Pointer such as "/items/0/name";
+/// null if any step is missing.
+#[no_mangle]
+pub extern "C" fn iris_json_pointer(n: *const Value, pointer: *const c_char) -> *const Value {
+    if pointer.is_null() { return std::ptr::null(); }
+    let Ok(pointer) = unsafe { CStr::from_ptr(pointer) }.to_str() else { return std::ptr::null() };
+    node_ptr(node(n).and_then(|v| v.pointer(pointer)))
+}
+
+/// String contents (UTF-8, escapes decoded); empty slice for other types.
+#[no_mangle]
+pub extern "C" fn iris_json_string(n: *const Value) -> IrisSlice {
+    IrisSlice::from_bytes(node(n).and_then(Value::as_str).map_or(&b""[..], str::as_bytes))
+}
+
+/// Number value; 0.0 for other types.
+#[no_mangle]
+pub extern "C" fn iris_json_number(n: *const Value) -> f64 {
+    node(n).and_then(Value::as_f64).unwrap_or(0.0)
+}
+
+/// Bool value; false for other types.
+#[no_mangle]
+pub extern "C" fn iris_json_bool(n: *const Value) -> bool {
+    node(n).and_then(Value::as_bool).unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn documents() {
+        let v = parse(br#" {"a": [1, -2.5e1, true, null], "s": "q\"\u00e9\ud83d\ude00", "a": 0} "#).unwrap();
+        assert_eq!(v.get("a").unwrap(), &Value::Array(vec![Value::Number(1.0), Value::Number(-25.0), Value::Bool(true), Value::Null]));
+        assert_eq!(v.get("s").unwrap().as_str(), Some("q\"é😀"));
+        assert_eq!(parse(b"\xef\xbb\xbf[]"), Some(Value::Array(vec![])));
+
+        for bad in [&b"{\"a\":1,}"[..], b"[01]", b"[1.]", b"\"\x01\"", b"{} x", b"tru", b"{\"a\" 1}", b""] {
+            assert_eq!(parse(bad), None, "{:?}", String::from_utf8_lossy(bad));
+        }
+        let deep = format!("{}{}", "[".repeat(MAX_DEPTH + 2), "]".repeat(MAX_DEPTH + 2));
+        assert_eq!(parse(deep.as_bytes()), None);
+    }
+
+    #[test]
+    fn ffi_tree() {
+        let doc = br#"{"items": [{"name": "a~/b", "ok": true}], "n": 7, "a/b": {"~": null}}"#;
+        let root = iris_json_parse(doc.as_ptr(), doc.len());
+        assert_eq!((iris_json_type(root), iris_json_count(root)), (JSON_OBJECT, 3));
+        let k = iris_json_key(root, 1);
+        assert_eq!(unsafe { std::slice::from_raw_parts(k.ptr, k.len) }, b"n");
+        assert_eq!(iris_json_number(iris_json_item(root, 1)), 7.0);
+
+        let ptr = |p: &str| iris_json_pointer(root, std::ffi::CString::new(p).unwrap().as_ptr());
+        let s = iris_json_string(ptr("/items/0/name"));
+        assert_eq!(unsafe { std::slice::from_raw_parts(s.ptr, s.len) }, b"a~/b");
+        assert!(iris_json_bool(ptr("/items/0/ok")));
+        assert_eq!(iris_json_type(ptr("/a~1b/~0")), JSON_NULL);
+        assert_eq!(ptr(""), root as *const Value);
+        assert!(ptr("/items/01").is_null() && ptr("/items/1").is_null() && ptr("items").is_null());
+
+        let items = iris_json_get(root, c"items".as_ptr());
+        assert_eq!((iris_json_type(items), iris_json_count(items)), (JSON_ARRAY, 1));
+        assert!(iris_json_item(items, 1).is_null() && iris_json_get(items, c"name".as_ptr()).is_null());
+        assert_eq!(iris_json_type(std::ptr::null()), 0);
+        iris_json_free(root);
+        assert!(iris_json_parse(b"{".as_ptr(), 1).is_null());
+    }
+}
@@ -0,0 +1,247 @@
+//! JSON Web Tokens (RFC 7519) in JWS compact form: header and claims decoding,
+//! risky-pattern flags, and optional HS256 / RS256 signature verification.
+
+use crate::base64;
+use crate::ffi::{free_c_string_array, free_cstr, to_cstr, vec_to_c_string_array, IrisCStringArray};
+use crate::hash::{ct_eq, hmac_sha256};
+use crate::json::{self, Value};
+use crate::rsa;
+use std::ffi::{c_char, CStr};
+
+const FAR_FUTURE_SECS: i64 = 365 * 86400;
+
+pub const JWT_SIG_UNCHECKED: u8 = 0;   // no key supplied
+pub const JWT_SIG_VALID: u8 = 1;
+pub const JWT_SIG_INVALID: u8 = 2;
+pub const JWT_SIG_UNSUPPORTED: u8 = 3; // alg other than none/HS256/RS256
+
+pub const JWT_FLAG_ALG_NONE: u32 = 0x01;        // unsigned token
+pub const JWT_FLAG_EXPIRED: u32 = 0x02;
+pub const JWT_FLAG_FAR_FUTURE_EXP: u32 = 0x04;  // exp more than a year ahead
+pub const JWT_FLAG_NO_EXP: u32 = 0x08;          // never expires
+pub const JWT_FLAG_NOT_YET_VALID: u32 = 0x10;   // nbf in the future
+pub const JWT_FLAG_EMPTY_SIGNATURE: u32 = 0x20; // signed alg but no signature bytes
+
+#[repr(C)]
+pub struct IrisJwt {
+    pub alg: *mut c_char,
+    pub typ: *mut c_char,           // "" if absent
+    pub kid: *mut c_char,           // "" if absent
+    pub iss: *mut c_char,           // "" if absent
+    pub sub: *mut c_char,           // "" if absent
+    pub aud: IrisCStringArray,      // string or array form
+    pub exp: i64,                   // unix seconds
+    pub nbf: i64,
+    pub iat: i64,
+    pub has_exp: bool,
+    pub has_nbf: bool,
+    pub has_iat: bool,
+    pub header_json: *mut c_char,   // decoded header
+    pub payload_json: *mut c_char,  // decoded claims
+    pub signature_status: u8,       // JWT_SIG_*
+    pub flags: u32,                 // JWT_FLAG_* bits
+}
+
+struct Jwt {
+    header: Value,
+    claims: Value,
+    header_json: String,
+    payload_json: String,
+    signature_status: u8,
+    flags: u32,
+}
+
+fn segment(s: &str) -> Option<Vec<u8>> {
+    // base64url without padding; anything outside that alphabet is malformed
+    if s.bytes().any(|c| !(c.is_ascii_alphanumeric() || c == b'-' || c == b'_')) { return None; }
+    base64::decode(s.as_bytes())
+}
+
+fn time_claim(claims: &Value, name: &str) -> Option<i64> {
+    claims.get(name)?.as_f64().map(|t| t as i64)
+}
+
+fn str_claim(v: &Value, name: &str) -> String {
+    v.get(name).and_then(Value::as_str).unwrap_or("").to_string()
+}
+
+/// Err(-3) when the token is not a JWS at all, Err(-2) when it is malformed or the
+/// key cannot be parsed.
+fn decode(token: &str, key: Option<&[u8]>, now: i64) -> Result<Jwt, i32> {
+    let token = token.trim();
+    let token = token.strip_prefix("Bearer ").unwrap_or(token).trim();
+    let parts: Vec<&str> = token.split('.').collect();
+    if parts.len() != 3 { return Err(-3); }
+    let header_raw = segment(parts[0]).ok_or(-3)?;
+    let header = json::parse(&header_raw).filter(|h| h.get("alg").and_then(Value::as_str).is_some()).ok_or(-3)?;
+    let payload_raw = segment(parts[1]).ok_or(-2)?;
+    let claims = json::parse(&payload_raw).filter(|c| matches!(c, Value::Object(_))).ok_or(-2)?;
+    let sig = segment(parts[2]).ok_or(-2)?;
+    let alg = str_claim(&header, "alg");
+
+    let mut flags = 0;
+    if alg.eq_ignore_ascii_case("none") { flags |= JWT_FLAG_ALG_NONE; } else if sig.is_empty() { flags |= JWT_FLAG_EMPTY_SIGNATURE; }
+    match time_claim(&claims, "exp") {
+        None => flags |= JWT_FLAG_NO_EXP,
+        Some(exp) if exp <= now => flags |= JWT_FLAG_EXPIRED,
+        Some(exp) if exp - now > FAR_FUTURE_SECS => flags |= JWT_FLAG_FAR_FUTURE_EXP,
+        _ => {}
+    }
+    if time_claim(&claims, "nbf").is_some_and(|nbf| nbf > now) { flags |= JWT_FLAG_NOT_YET_VALID; }
+
+    let signing_input = &token[..parts[0].len() + 1 + parts[1].len()];
+    let signature_status = match key {
+        None => JWT_SIG_UNCHECKED,
+        Some(_) if flags & JWT_FLAG_ALG_NONE != 0 => JWT_SIG_INVALID,
+        Some(k) => match alg.as_str() {
+            // An RSA public key is no HS256 secret: accepting it would let anyone who
+            // has the public key forge tokens (algorithm confusion)
+            "HS256" if rsa::public_key(k).is_some() => JWT_SIG_INVALID,
+            "HS256" => if ct_eq(&hmac_sha256(k, signing_input.as_bytes()), &sig) { JWT_SIG_VALID } else { JWT_SIG_INVALID },
+            "RS256" => {
+                let pk = rsa::public_key(k).ok_or(-2)?;
+                if rsa::verify_pkcs1_sha256(&pk, signing_input.as_bytes(), &sig) { JWT_SIG_VALID } else { JWT_SIG_INVALID }
+            }
+            _ => JWT_SIG_UNSUPPORTED,
+        },
+    };
+    Ok(Jwt {
+        header,
+        claims,
+        header_json: String::from_utf8_lossy(&header_raw).into_owned(),
+        payload_json: String::from_utf8_lossy(&payload_raw).into_owned(),
+        signature_status,
+        flags,
+    })
+}
+
+// --- FFI entry points ---
+
+/// Decode a compact JWS token (a "Bearer " prefix is tolerated) and check its claims
+/// against the current time. With a key, the signature is verified: the raw shared
+/// secret for HS256, a PEM or DER RSA public key for RS256. alg=none never verifies,
+/// nor does HS256 against an RSA public key.
+/// Returns 0=ok, -2=arg error, malformed token or unusable key, -3=not a JWT.
+/// Free with iris_jwt_free.
+#[no_mangle]
+pub extern "C" fn iris_jwt_decode(token: *const c_char, key: *const u8, key_len: usize, out: *mut IrisJwt) -> i32 {
+    if token.is_null() || out.is_null() { return -2; }
+    let Ok(token) = unsafe { CStr::from_ptr(token) }.to_str() else { return -2 };
+    let key = (!key.is_null()).then(|| unsafe { std::slice::from_raw_parts(key, key_len) });
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_secs() as i64);
+    let j = match decode(token, key, now) {
+        Ok(j) => j,
+        Err(e) => return e,
+    };
+    let aud = match j.claims.get("aud") {
+        Some(Value::String(s)) => vec![s.clone()],
+        Some(Value::Array(a)) => a.iter().filter_map(Value::as_str).map(str::to_string).collect(),
+        _ => Vec::new(),
+    };
+    let (exp, nbf, iat) = (time_claim(&j.claims, "exp"), time_claim(&j.claims, "nbf"), time_claim(&j.claims, "iat"));
+    unsafe {
+        out.write(IrisJwt {
+            alg: to_cstr(&str_claim(&j.header, "alg")),
+            typ: to_cstr(&str_claim(&j.header, "typ")),
+            kid: to_cstr(&str_claim(&j.header, "kid")),
+            iss: to_cstr(&str_claim(&j.claims, "iss")),
+            sub: to_cstr(&str_claim(&j.claims, "sub")),
+            aud: vec_to_c_string_array(aud),
+            exp: exp.unwrap_or(0),
+            nbf: nbf.unwrap_or(0),
+            iat: iat.unwrap_or(0),
+            has_exp: exp.is_some(),
+            has_nbf: nbf.is_some(),
+            has_iat: iat.is_some(),
+            header_json: to_cstr(&j.header_json),
+            payload_json: to_cstr(&j.payload_json),
+            signature_status: j.signature_status,
+            flags: j.flags,
+        });
+    }
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn iris_jwt_free(jwt: *mut IrisJwt) {
+    if jwt.is_null() { return; }
+    let j = unsafe { &*jwt };
+    for p in [j.alg, j.typ, j.kid, j.iss, j.sub, j.header_json, j.payload_json] { free_cstr(p); }
+    free_c_string_array(&j.aud);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base64::encode;
+
+    const NOW: i64 = 1_700_000_000;
+
+    fn b64url(d: &[u8]) -> String {
+        encode(d).trim_end_matches('=').replace('+', "-").replace('/', "_")
+    }
+
+    fn unsigned(header: &str, claims: &str) -> String {
+        format!("{}.{}", b64url(header.as_bytes()), b64url(claims.as_bytes()))
+    }
+
+    #[test]
+    fn claims_and_flags() {
+        let t = format!("{}.", unsigned(r#"{"alg":"none"}"#, r#"{"sub":"admin","nbf":1800000000}"#));
+        let j = decode(&t, None, NOW).unwrap();
+        assert_eq!(j.flags, JWT_FLAG_ALG_NONE | JWT_FLAG_NO_EXP | JWT_FLAG_NOT_YET_VALID);
+        assert_eq!(decode(&t, Some(b"secret"), NOW).unwrap().signature_status, JWT_SIG_INVALID);
+
+        let t = format!("{}.", unsigned(r#"{"alg":"HS256"}"#, &format!(r#"{{"exp":{}}}"#, NOW + 10 * 365 * 86400)));
+        assert_eq!(decode(&t, None, NOW).unwrap().flags, JWT_FLAG_EMPTY_SIGNATURE | JWT_FLAG_FAR_FUTURE_EXP);
+
+        let t = format!("{}.c2ln", unsigned(r#"{"alg":"ES256"}"#, r#"{"exp":1600000000}"#));
+        let j = decode(&t, Some(b"k"), NOW).unwrap();
+        assert_eq!((j.flags, j.signature_status), (JWT_FLAG_EXPIRED, JWT_SIG_UNSUPPORTED));
+
+        assert_eq!(decode("a.b", None, NOW).err(), Some(-3));
+        assert_eq!(decode(&format!("{}.e30.", b64url(b"{}")), None, NOW).err(), Some(-3));
+        assert_eq!(decode(&format!("{}.!!.", b64url(br#"{"alg":"none"}"#)), None, NOW).err(), Some(-2));
+    }
+
+    #[test]
+    fn signatures() {
+        let input = unsigned(r#"{"alg":"HS256","typ":"JWT"}"#, r#"{"iss":"iris","aud":["a","b"],"exp":1700000600}"#);
+        let t = format!("Bearer {}.{}", input, b64url(&hmac_sha256(b"secret", input.as_bytes())));
+        assert_eq!(decode(&t, Some(b"secret"), NOW).unwrap().signature_status, JWT_SIG_VALID);
+        assert_eq!(decode(&t, Some(b"Secret"), NOW).unwrap().signature_status, JWT_SIG_INVALID);
+
+        let input = unsigned(r#"{"alg":"RS256","kid":"k1"}"#, r#"{"sub":"user"}"#);
+        let t = format!("{}.{}", input, b64url(&rsa::tests::sign(input.as_bytes())));
+        let key = rsa::tests::spki();
+        assert_eq!(decode(&t, Some(&key), NOW).unwrap().signature_status, JWT_SIG_VALID);
+        let forged = format!("{}.{}", input, b64url(&rsa::tests::sign(b"other")));
+        assert_eq!(decode(&forged, Some(&key), NOW).unwrap().signature_status, JWT_SIG_INVALID);
+        assert_eq!(decode(&t, Some(b"not a key"), NOW).err(), Some(-2));
+
+        // Algorithm confusion: HS256 keyed with the public key the verifier holds
+        let pem = format!("-----BEGIN PUBLIC KEY-----\n{}\n-----END PUBLIC KEY-----\n", encode(&key));
+        let input = unsigned(r#"{"alg":"HS256"}"#, r#"{"sub":"admin"}"#);
+        for k in [pem.as_bytes(), &key[..]] {
+            let t = format!("{}.{}", input, b64url(&hmac_sha256(k, input.as_bytes())));
+            assert_eq!(decode(&t, Some(k), NOW).unwrap().signature_status, JWT_SIG_INVALID);
+            let c = std::ffi::CString::new(t).unwrap();
+            let mut out = std::mem::MaybeUninit::<IrisJwt>::uninit();
+            assert_eq!(iris_jwt_decode(c.as_ptr(), k.as_ptr(), k.len(), out.as_mut_ptr()), 0);
+            let mut j = unsafe { out.assume_init() };
+            assert_eq!(j.signature_status, JWT_SIG_INVALID);
+            iris_jwt_free(&mut j);
+        }
+
+        let input = unsigned(r#"{"alg":"HS256","typ":"JWT"}"#, r#"{"iss":"iris","aud":["a","b"],"exp":4000000000}"#);
+        let c = std::ffi::CString::new(format!("{}.{}", input, b64url(&hmac_sha256(b"k", input.as_bytes())))).unwrap();
+        let mut out = std::mem::MaybeUninit::<IrisJwt>::uninit();
+        assert_eq!(iris_jwt_decode(c.as_ptr(), b"k".as_ptr(), 1, out.as_mut_ptr()), 0);
+        let mut j = unsafe { out.assume_init() };
+        let s = |p| unsafe { CStr::from_ptr(p) }.to_str().unwrap().to_string();
+        assert_eq!((s(j.alg), s(j.typ), s(j.iss), s(j.sub)), ("HS256".into(), "JWT".into(), "iris".into(), String::new()));
+        assert_eq!((j.aud.count, j.exp, j.has_exp, j.has_iat), (2, 4_000_000_000, true, false));
+        assert_eq!((j.signature_status, j.flags), (JWT_SIG_VALID, JWT_FLAG_FAR_FUTURE_EXP));
+        iris_jwt_free(&mut j);
+    }
+}
@@ -0,0 +1,402 @@
+//! Kerberos v5 message parser (RFC 4120): AS-REQ/AS-REP, TGS-REQ/TGS-REP,
+//! AP-REQ and KRB-ERROR, with flags for AS-REP roasting and kerberoasting shapes.
+
+use crate::der::{read_tlv, Tlv};
+use crate::ffi::{alloc_array, free_array, to_cstr, free_cstr};
+use std::ffi::c_char;
+
+pub const KRB_AS_REQ: u8 = 10;
+pub const KRB_AS_REP: u8 = 11;
+pub const KRB_TGS_REQ: u8 = 12;
+pub const KRB_TGS_REP: u8 = 13;
+pub const KRB_AP_REQ: u8 = 14;
+pub const KRB_AP_REP: u8 = 15;
+pub const KRB_ERROR: u8 = 30;
+
+const ETYPE_RC4_HMAC: i32 = 23;
+const ETYPE_DES_CBC_MD5: i32 = 3;
+const ETYPE_DES_CBC_CRC: i32 = 1;
+const PA_TGS_REQ: i32 = 1;
+const PA_ENC_TIMESTAMP: i32 = 2;
+
+/// Detection flags.
+pub const KRB_FLAG_NO_PREAUTH: u32 = 1 << 0;        // AS-REQ without PA-ENC-TIMESTAMP
+pub const KRB_FLAG_RC4_ONLY: u32 = 1 << 1;          // only RC4/DES etypes requested
+pub const KRB_FLAG_WEAK_TICKET: u32 = 1 << 2;       // TGS-REP ticket encrypted with RC4/DES
+pub const KRB_FLAG_SERVICE_TICKET: u32 = 1 << 3;    // TGS for a non-krbtgt SPN
+pub const KRB_FLAG_PREAUTH_REQUIRED: u32 = 1 << 4;  // KDC_ERR_PREAUTH_REQUIRED (25)
+pub const KRB_FLAG_PRINCIPAL_UNKNOWN: u32 = 1 << 5; // KDC_ERR_C_PRINCIPAL_UNKNOWN (6) — enumeration
+pub const KRB_FLAG_ASREP_ROASTABLE: u32 = 1 << 6;   // AS-REP whose enc-part uses RC4/DES
+
+#[repr(C)]
+pub struct IrisKerberosMessage {
+    pub msg_type: u8,             // KRB_* (10..30)
+    pub realm: *mut c_char,       // request realm / crealm / error realm
+    pub cname: *mut c_char,       // components joined with '/'
+    pub cname_type: i32,
+    pub sname: *mut c_char,       // e.g. "krbtgt/CORP.EXAMPLE.COM"
+    pub sname_type: i32,
+    pub kdc_options: u32,
+    pub nonce: u32,
+    pub till: i64,                // unix seconds, 0 = absent
+    pub etypes: *mut i32,         // requested encryption types, in preference order
+    pub etypes_count: usize,
+    pub padata_types: *mut i32,
+    pub padata_count: usize,
+    pub ticket_realm: *mut c_char,
+    pub ticket_sname: *mut c_char,
+    pub ticket_etype: i32,        // -1 = no ticket
+    pub ticket_kvno: i32,         // -1 = absent
+    pub enc_part_etype: i32,      // reply enc-part / AP-REQ authenticator, -1 = absent
+    pub error_code: i32,          // KRB-ERROR only, -1 otherwise
+    pub error_text: *mut c_char,
+    pub flags: u32,               // KRB_FLAG_*
+}
+
+#[derive(Default)]
+struct Krb {
+    msg_type: u8,
+    realm: String,
+    cname: String,
+    cname_type: i32,
+    sname: String,
+    sname_type: i32,
+    kdc_options: u32,
+    nonce: u32,
+    till: i64,
+    etypes: Vec<i32>,
+    padata: Vec<i32>,
+    ticket_realm: String,
+    ticket_sname: String,
+    ticket_etype: i32,
+    ticket_kvno: i32,
+    enc_etype: i32,
+    error_code: i32,
+    error_text: String,
+}
+
+fn int(t: &Tlv) -> i32 { t.inner().and_then(|i| i.as_i64()).unwrap_or(-1) as i32 }
+
+fn string(t: &Tlv) -> String { t.inner().map(|i| i.as_string()).unwrap_or_default() }
+
+/// PrincipalName ::= SEQUENCE { name-type [0] Int32, name-string [1] SEQUENCE OF KerberosString }
+fn principal(t: &Tlv) -> (String, i32) {
+    let seq = match t.inner() { Some(s) => s, None => return (String::new(), 0) };
+    let mut name_type = 0;
+    let mut parts = Vec::new();
+    for f in seq.children() {
+        if f.is_context(0) { name_type = int(&f); }
+        if f.is_context(1) {
+            if let Some(list) = f.inner() { parts.extend(list.children().map(|s| s.as_string())); }
+        }
+    }
+    (parts.join("/"), name_type)
+}
+
+/// EncryptedData ::= SEQUENCE { etype [0], kvno [1] OPTIONAL, cipher [2] } → (etype, kvno)
+fn encrypted_data(t: &Tlv) -> (i32, i32) {
+    let mut r = (-1, -1);
+    if let Some(seq) = t.inner() {
+        for f in seq.children() {
+            if f.is_context(0) { r.0 = int(&f); }
+            if f.is_context(1) { r.1 = int(&f); }
+        }
+    }
+    r
+}
+
+/// Ticket ::= [APPLICATION 1] SEQUENCE { tkt-vno [0], realm [1], sname [2], enc-part [3] }
+fn ticket(t: &Tlv, k: &mut Krb) {
+    let app = match t.inner() { Some(a) if a.is(1, 1) => a, _ => return };
+    let seq = match app.inner() { Some(s) => s, None => return };
+    for f in seq.children() {
+        match f.number {
+            1 if f.class == 2 => k.ticket_realm = string(&f),
+            2 if f.class == 2 => k.ticket_sname = principal(&f).0,
+            3 if f.class == 2 => {
+                let (e, v) = encrypted_data(&f);
+                k.ticket_etype = e;
+                k.ticket_kvno = v;
+            }
+            _ => {}
+        }
+    }
+}
+
+fn ap_req(seq: &Tlv, k: &mut Krb) {
+    for f in seq.children() {
+        if f.is_context(3) { ticket(&f, k); }
+        if f.is_context(4) { k.enc_etype = encrypted_data(&f).0; }
+    }
+}
+
+fn padata(t: &Tlv, k: &mut Krb) {
+    let list = match t.inner() { Some(l) => l, None => return };
+    for pa in list.children() {
+        let mut ptype = -1;
+        for f in pa.children() {
+            if f.is_context(1) { ptype = int(&f); }
+            // PA-TGS-REQ carries an AP-REQ with the presented TGT
+            if f.is_context(2) && ptype == PA_TGS_REQ {
+                if let Some(app) = f.inner().and_then(|o| read_tlv(o.content).map(|(t, _)| t)) {
+                    if let Some(seq) = app.inner() { ap_req(&seq, k); }
+                }
+            }
+        }
+        k.padata.push(ptype);
+    }
+}
+
+fn kdc_req(seq: &Tlv, k: &mut Krb) {
+    for f in seq.children() {
+        if f.is_context(3) { padata(&f, k); }
+        if !f.is_context(4) { continue; }
+        let body = match f.inner() { Some(b) => b, None => continue };
+        for b in body.children() {
+            if b.class != 2 { continue; }
+            match b.number {
+                0 => if let Some(bits) = b.inner() {
+                    let c = bits.content;
+                    if c.len() >= 5 { k.kdc_options = u32::from_be_bytes([c[1], c[2], c[3], c[4]]); }
+                },
+                1 => (k.cname, k.cname_type) = principal(&b),
+                2 => k.realm = string(&b),
+                3 => (k.sname, k.sname_type) = principal(&b),
+                5 => k.till = b.inner().and_then(|t| t.as_time()).unwrap_or(0),
+                7 => k.nonce = int(&b) as u32,
+                8 => if let Some(list) = b.inner() {
+                    k.etypes = list.children().filter_map(|e| e.as_i64()).map(|e| e as i32).collect();
+                },
+                _ => {}
+            }
+        }
+    }
+}
+
+fn kdc_rep(seq: &Tlv, k: &mut Krb) {
+    for f in seq.children() {
+        if f.class != 2 { continue; }
+        match f.number {
+            2 => padata(&f, k),
+            3 => k.realm = string(&f),
+            4 => (k.cname, k.cname_type) = principal(&f),
+            5 => ticket(&f, k),
+            6 => k.enc_etype = encrypted_data(&f).0,
+            _ => {}
+        }
+    }
+}
+
+fn krb_error(seq: &Tlv, k: &mut Krb) {
+    for f in seq.children() {
+        if f.class != 2 { continue; }
+        match f.number {
+            6 => k.error_code = int(&f),
+            7 => k.realm = string(&f),
+            8 => (k.cname, k.cname_type) = principal(&f),
+            9 if k.realm.is_empty() => k.realm = string(&f),
+            10 => (k.sname, k.sname_type) = principal(&f),
+            11 => k.error_text = string(&f),
+            _ => {}
+        }
+    }
+}
+
+fn parse_krb(data: &[u8]) -> Option<Krb> {
+    let (app, _) = read_tlv(data)?;
+    if app.class != 1 { return None; }
+    // High tag numbers must not wrap onto real message types (266 is not AS-REQ)
+    let msg_type = u8::try_from(app.number).ok()?;
+    let seq = app.inner()?;
+    let mut k = Krb {
+        msg_type, ticket_etype: -1, ticket_kvno: -1,
+        enc_etype: -1, error_code: -1, ..Default::default()
+    };
+    match msg_type {
+        KRB_AS_REQ | KRB_TGS_REQ => kdc_req(&seq, &mut k),
+        KRB_AS_REP | KRB_TGS_REP => kdc_rep(&seq, &mut k),
+        KRB_AP_REQ => ap_req(&seq, &mut k),
+        KRB_ERROR => krb_error(&seq, &mut k),
+        KRB_AP_REP => {}
+        _ => return None,
+    }
+    Some(k)
+}
+
+/// True if `data` decodes as a Kerberos message (no TCP length prefix).
+pub fn is_kerberos(data: &[u8]) -> bool { parse_krb(data).is_some() }
+
+fn is_weak(etype: i32) -> bool {
+    matches!(etype, ETYPE_RC4_HMAC | ETYPE_DES_CBC_MD5 | ETYPE_DES_CBC_CRC | 24)
+}
+
+fn flags(k: &Krb) -> u32 {
+    let mut f = 0;
+    if k.msg_type == KRB_AS_REQ && !k.padata.contains(&PA_ENC_TIMESTAMP) { f |= KRB_FLAG_NO_PREAUTH; }
+    if !k.etypes.is_empty() && k.etypes.iter().all(|&e| is_weak(e)) { f |= KRB_FLAG_RC4_ONLY; }
+    if k.ticket_etype >= 0 && is_weak(k.ticket_etype) && k.msg_type == KRB_TGS_REP { f |= KRB_FLAG_WEAK_TICKET; }
+    if matches!(k.msg_type, KRB_TGS_REQ | KRB_TGS_REP) {
+        let sname = if k.msg_type == KRB_TGS_REQ { &k.sname } else { &k.ticket_sname };
+        if !sname.is_empty() && !sname.to_ascii_lowercase().starts_with("krbtgt/") {
+            f |= KRB_FLAG_SERVICE_TICKET;
+        }
+    }
+    if k.msg_type == KRB_AS_REP && is_weak(k.enc_etype) { f |= KRB_FLAG_ASREP_ROASTABLE; }
+    if k.error_code == 25 { f |= KRB_FLAG_PREAUTH_REQUIRED; }
+    if k.error_code == 6 { f |= KRB_FLAG_PRINCIPAL_UNKNOWN; }
+    f
+}
+
+// --- FFI entry points ---
+
+/// Parse a Kerberos message (UDP payload, or TCP with its 4-byte length prefix).
+/// Returns 0=ok, -1=truncated/not Kerberos, -2=arg error. Free with iris_kerberos_free.
+#[no_mangle]
+pub extern "C" fn iris_kerberos_parse(
+    data: *const u8, len: usize, out: *mut IrisKerberosMessage,
+) -> i32 {
+    if data.is_null() || out.is_null() || len == 0 { return -2; }
+    let mut buf = unsafe { std::slice::from_raw_parts(data, len) };
+    if buf.len() > 4 && buf[0] & 0xC0 != 0x40 {
+        let framed = u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]) as usize;
+        if framed == buf.len() - 4 { buf = &buf[4..]; }
+    }
+    let k = match parse_krb(buf) { Some(k) => k, None => return -1 };
+    let fl = flags(&k);
+    let (etypes, etypes_count) = alloc_array(k.etypes);
+    let (padata_types, padata_count) = alloc_array(k.padata);
+    unsafe {
+        out.write(IrisKerberosMessage {
+            msg_type: k.msg_type,
+            realm: to_cstr(&k.realm),
+            cname: to_cstr(&k.cname),
+            cname_type: k.cname_type,
+            sname: to_cstr(&k.sname),
+            sname_type: k.sname_type,
+            kdc_options: k.kdc_options,
+            nonce: k.nonce,
+            till: k.till,
+            etypes, etypes_count,
+            padata_types, padata_count,
+            ticket_realm: to_cstr(&k.ticket_realm),
+            ticket_sname: to_cstr(&k.ticket_sname),
+            ticket_etype: k.ticket_etype,
+            ticket_kvno: k.ticket_kvno,
+            enc_part_etype: k.enc_etype,
+            error_code: k.error_code,
+            error_text: to_cstr(&k.error_text),
+            flags: fl,
+        });
+    }
+    0
+}
+
+/// Free allocations in an IrisKerberosMessage.
+#[no_mangle]
+pub extern "C" fn iris_kerberos_free(msg: *mut IrisKerberosMessage) {
+    if msg.is_null() { return; }
+    unsafe {
+        let m = &*msg;
+        for p in [m.realm, m.cname, m.sname, m.ticket_realm, m.ticket_sname, m.error_text] {
+            free_cstr(p);
+        }
+        free_array(m.etypes, m.etypes_count);
+        free_array(m.padata_types, m.padata_count);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+        let mut v = vec![tag];
+        if content.len() < 0x80 { v.push(content.len() as u8); } else { v.extend([0x81, content.len() as u8]); }
+        v.extend_from_slice(content);
+        v
+    }
+    fn ctx(n: u8, inner: Vec<u8>) -> Vec<u8> { tlv(0xA0 | n, &inner) }
+    fn int(v: u8) -> Vec<u8> { tlv(0x02, &[v]) }
+    fn gstr(s: &str) -> Vec<u8> { tlv(0x1B, s.as_bytes()) }
+    fn seq(parts: &[Vec<u8>]) -> Vec<u8> { tlv(0x30, &parts.concat()) }
+    fn name(t: u8, parts: &[&str]) -> Vec<u8> {
+        let strs: Vec<Vec<u8>> = parts.iter().map(|p| gstr(p)).collect();
+        seq(&[ctx(0, int(t)), ctx(1, seq(&strs))])
+    }
+
+    fn parse(data: &[u8]) -> (i32, IrisKerberosMessage) {
+        let mut out = std::mem::MaybeUninit::<IrisKerberosMessage>::uninit();
+        let rc = iris_kerberos_parse(data.as_ptr(), data.len(), out.as_mut_ptr());
+        (rc, unsafe { out.assume_init() })
+    }
+
+    fn cstr(p: *mut c_char) -> String {
+        unsafe { std::ffi::CStr::from_ptr(p) }.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn as_req_without_preauth() {
+        let body = seq(&[
+            ctx(0, tlv(0x03, &[0, 0x40, 0x81, 0, 0x10])),
+            ctx(1, name(1, &["alice"])),
+            ctx(2, gstr("CORP.EXAMPLE.COM")),
+            ctx(3, name(2, &["krbtgt", "CORP.EXAMPLE.COM"])),
+            ctx(5, tlv(0x18, b"20370913024805Z")),
+            ctx(7, int(0x42)),
+            ctx(8, seq(&[int(23)])),
+        ]);
+        let req = tlv(0x6A, &seq(&[ctx(1, int(5)), ctx(2, int(10)), ctx(4, body)]));
+        let mut framed = (req.len() as u32).to_be_bytes().to_vec();
+        framed.extend_from_slice(&req);
+        let (rc, mut m) = parse(&framed);
+        assert_eq!(rc, 0);
+        assert_eq!(m.msg_type, KRB_AS_REQ);
+        assert_eq!(cstr(m.cname), "alice");
+        assert_eq!(cstr(m.realm), "CORP.EXAMPLE.COM");
+        assert_eq!(cstr(m.sname), "krbtgt/CORP.EXAMPLE.COM");
+        assert_eq!(m.kdc_options, 0x40810010);
+        assert_eq!(m.nonce, 0x42);
+        assert_eq!(m.till, 2136422885);
+        assert_eq!(m.etypes_count, 1);
+        assert_eq!(m.flags & (KRB_FLAG_NO_PREAUTH | KRB_FLAG_RC4_ONLY), KRB_FLAG_NO_PREAUTH | KRB_FLAG_RC4_ONLY);
+        iris_kerberos_free(&mut m);
+    }
+
+    #[test]
+    fn tgs_rep_rc4_service_ticket() {
+        let enc = |etype: u8| seq(&[ctx(0, int(etype)), ctx(1, int(2)), ctx(2, tlv(0x04, &[0; 8]))]);
+        let ticket = tlv(0x61, &seq(&[
+            ctx(0, int(5)), ctx(1, gstr("CORP")), ctx(2, name(2, &["MSSQLSvc", "db.corp:1433"])), ctx(3, enc(23)),
+        ]));
+        let rep = tlv(0x6D, &seq(&[
+            ctx(0, int(5)), ctx(1, int(13)), ctx(3, gstr("CORP")), ctx(4, name(1, &["bob"])),
+            ctx(5, ticket), ctx(6, enc(18)),
+        ]));
+        let (rc, mut m) = parse(&rep);
+        assert_eq!(rc, 0);
+        assert_eq!(m.msg_type, KRB_TGS_REP);
+        assert_eq!(cstr(m.ticket_sname), "MSSQLSvc/db.corp:1433");
+        assert_eq!((m.ticket_etype, m.ticket_kvno, m.enc_part_etype), (23, 2, 18));
+        assert_ne!(m.flags & KRB_FLAG_WEAK_TICKET, 0);
+        assert_ne!(m.flags & KRB_FLAG_SERVICE_TICKET, 0);
+        iris_kerberos_free(&mut m);
+    }
+
+    #[test]
+    fn error_and_garbage() {
+        let err = tlv(0x7E, &seq(&[ctx(0, int(5)), ctx(1, int(30)), ctx(6, int(25)), ctx(9, gstr("CORP"))]));
+        let (rc, mut m) = parse(&err);
+        assert_eq!(rc, 0);
+        assert_eq!(m.error_code, 25);
+        assert_eq!(cstr(m.realm), "CORP");
+        assert_ne!(m.flags & KRB_FLAG_PREAUTH_REQUIRED, 0);
+        iris_kerberos_free(&mut m);
+        assert_eq!(parse(b"\x30\x03\x02\x01\x05").0, -1);
+
+        // [APPLICATION 266] around a KRB-ERROR body: 266 & 0xff is 10 (AS-REQ)
+        let body = seq(&[ctx(0, int(5)), ctx(1, int(30)), ctx(6, int(25))]);
+        let high = [&[0x7f, 0x82, 0x0a, body.len() as u8][..], &body].concat();
+        assert_eq!(parse(&high).0, -1);
+        assert!(!is_kerberos(&high));
+    }
+}
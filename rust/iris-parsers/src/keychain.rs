@@ -0,0 +1,467 @@
+//! File-based keychains (login.keychain-db and legacy .keychain files, both "kych"
+//! AppleDatabase stores): item classes, labels, accounts, dates and the access-control
+//! metadata kept in clear next to each item's key. Secrets are never decrypted.
+//!
+//! Layout: header, schema (table offsets), then per table a header, record offsets
+//! and records. A record is a fixed header, one offset per schema attribute, its
+//! (encrypted) data, then the attribute values. Password data starts with "ssgp" and
+//! the label of the symmetric key that encrypts it; that key's blob carries the ACL.
+
+use crate::bytes::be32;
+use crate::der::parse_time;
+use crate::ffi::{alloc_array, free_array, free_c_string_array, free_cstr, to_cstr, vec_to_c_string_array, IrisCStringArray};
+use crate::plist::{self, Plist};
+use std::ffi::{c_char, CStr};
+
+const MAGIC: &[u8] = b"kych";
+const HEADER_SIZE: usize = 20;
+const TABLE_HEADER: usize = 28;
+const RECORD_HEADER: usize = 24;
+const KEY_BLOB_MAGIC: u32 = 0xfade0711;
+const SSGP_MAGIC: &[u8] = b"ssgp";
+const SSGP_LABEL: usize = 20;
+const MAX_ACL_STRING: usize = 4096;
+
+pub const KEYCHAIN_CLASS_PUBLIC_KEY: u32 = 0x0000000f;
+pub const KEYCHAIN_CLASS_PRIVATE_KEY: u32 = 0x00000010;
+pub const KEYCHAIN_CLASS_SYMMETRIC_KEY: u32 = 0x00000011;
+pub const KEYCHAIN_CLASS_GENERIC_PASSWORD: u32 = 0x80000000;
+pub const KEYCHAIN_CLASS_INTERNET_PASSWORD: u32 = 0x80000001;
+pub const KEYCHAIN_CLASS_APPLESHARE_PASSWORD: u32 = 0x80000002;
+pub const KEYCHAIN_CLASS_CERTIFICATE: u32 = 0x80001000;
+
+/// Attributes shared by the password classes, in schema order.
+const PASSWORD_COMMON: &[&str] = &["cdat", "mdat", "desc", "icmt", "crtr", "type", "scrp", "labl", "alis", "invi", "nega", "cusi", "prot", "acct"];
+const GENERIC_TAIL: &[&str] = &["svce", "gena"];
+const INTERNET_TAIL: &[&str] = &["sdmn", "srvr", "ptcl", "atyp", "port", "path"];
+const APPLESHARE_TAIL: &[&str] = &["vlme", "srvr", "ptcl", "atyp", "addr", "ssig"];
+const CERTIFICATE_ATTRS: &[&str] = &["ctyp", "cenc", "labl", "alis", "subj", "issu", "snbr", "skid", "hpky"];
+const KEY_ATTRS: &[&str] = &["kcls", "labl", "alis", "perm", "priv", "modi", "klbl", "atag", "crtr", "type", "bsiz",
+                             "esiz", "sdat", "edat", "sens", "asen", "extr", "next", "encr", "decr", "drve", "sign",
+                             "vrfy", "snrc", "vyrc", "wrap", "unwp"];
+
+#[repr(C)]
+pub struct IrisKeychainItem {
+    pub item_class: u32,                 // KEYCHAIN_CLASS_*
+    pub record_number: u32,
+    pub label: *mut c_char,              // print name shown in Keychain Access
+    pub account: *mut c_char,
+    pub service: *mut c_char,            // generic password service, or internet/AppleShare server
+    pub kind: *mut c_char,               // description ("application password", ...)
+    pub comment: *mut c_char,
+    pub creator: *mut c_char,            // four-char codes, "" if unset
+    pub item_type: *mut c_char,
+    pub protocol: *mut c_char,           // internet/AppleShare protocol ("htps", "smb ")
+    pub port: u32,
+    pub path: *mut c_char,
+    pub created: i64,                    // unix seconds, 0 if absent
+    pub modified: i64,
+    pub key_size: u32,                   // keys: size in bits
+    pub data_size: u32,                  // encrypted data length
+    pub acl_found: bool,                 // the protecting key and its ACL were located
+    pub acl_descriptions: IrisCStringArray,
+    pub trusted_apps: IrisCStringArray,  // application paths allowed without a prompt
+    pub partitions: IrisCStringArray,    // partition list ("apple-tool:", "teamid:ABCDE12345")
+}
+
+#[repr(C)]
+pub struct IrisKeychain {
+    pub version: u32,
+    pub table_count: u32,
+    pub items: *mut IrisKeychainItem,
+    pub count: usize,
+}
+
+fn schema(class: u32) -> Option<Vec<&'static str>> {
+    let tail = match class {
+        KEYCHAIN_CLASS_GENERIC_PASSWORD => GENERIC_TAIL,
+        KEYCHAIN_CLASS_INTERNET_PASSWORD => INTERNET_TAIL,
+        KEYCHAIN_CLASS_APPLESHARE_PASSWORD => APPLESHARE_TAIL,
+        KEYCHAIN_CLASS_CERTIFICATE => return Some(CERTIFICATE_ATTRS.to_vec()),
+        KEYCHAIN_CLASS_PUBLIC_KEY | KEYCHAIN_CLASS_PRIVATE_KEY | KEYCHAIN_CLASS_SYMMETRIC_KEY => return Some(KEY_ATTRS.to_vec()),
+        _ => return None,
+    };
+    Some([PASSWORD_COMMON, tail].concat())
+}
+
+struct Record<'a> {
+    class: u32,
+    number: u32,
+    r: &'a [u8],
+    attrs: Vec<(&'static str, usize)>, // name, offset within the record (0 = absent)
+    data: &'a [u8],
+}
+
+impl Record<'_> {
+    fn at(&self, name: &str) -> Option<usize> {
+        self.attrs.iter().find(|(n, o)| *n == name && *o != 0).map(|(_, o)| *o)
+    }
+    fn blob(&self, name: &str) -> Option<&[u8]> {
+        let o = self.at(name)?;
+        let len = be32(self.r, o)? as usize;
+        self.r.get(o + 4..o + 4 + len)
+    }
+    fn string(&self, name: &str) -> String {
+        self.blob(name).map(|b| String::from_utf8_lossy(b.strip_suffix(&[0]).unwrap_or(b)).into_owned()).unwrap_or_default()
+    }
+    fn int(&self, name: &str) -> u32 {
+        self.at(name).and_then(|o| be32(self.r, o)).unwrap_or(0)
+    }
+    fn fourcc(&self, name: &str) -> String {
+        match self.int(name) {
+            0 => String::new(),
+            v => String::from_utf8_lossy(&v.to_be_bytes()).into_owned(),
+        }
+    }
+    /// "YYYYMMDDhhmmssZ\0", stored without a length prefix.
+    fn time(&self, name: &str) -> i64 {
+        self.at(name).and_then(|o| std::str::from_utf8(self.r.get(o..o + 16)?).ok())
+            .and_then(|s| parse_time(s.trim_end_matches('\0'), true)).unwrap_or(0)
+    }
+}
+
+fn records(d: &[u8]) -> Option<(u32, u32, Vec<Record<'_>>)> {
+    if !d.starts_with(MAGIC) || d.len() < HEADER_SIZE { return None; }
+    let version = be32(d, 4)?;
+    let schema_at = be32(d, 12)? as usize;
+    let table_count = be32(d, schema_at + 4)?;
+    let mut out = Vec::new();
+    for t in 0..table_count as usize {
+        let base = schema_at + be32(d, schema_at + 8 + 4 * t)? as usize;
+        let (size, class, count) = (be32(d, base)? as usize, be32(d, base + 4)?, be32(d, base + 8)? as usize);
+        let Some(names) = schema(class) else { continue };
+        let table = d.get(base..base.checked_add(size)?)?;
+        let (mut found, mut slot) = (0, 0);
+        // Deleted records leave zero (or odd, free-list) slots behind
+        while found < count && TABLE_HEADER + 4 * slot + 4 <= size {
+            let off = be32(table, TABLE_HEADER + 4 * slot)? as usize;
+            slot += 1;
+            if off == 0 || !off.is_multiple_of(4) { continue; }
+            found += 1;
+            let Some(r) = be32(table, off).and_then(|len| table.get(off..off.checked_add(len as usize)?)) else { continue };
+            let Some(data_size) = be32(r, 16) else { continue };
+            let data_at = RECORD_HEADER + 4 * names.len();
+            let attrs = names.iter().enumerate().map(|(i, n)| (*n, be32(r, RECORD_HEADER + 4 * i).unwrap_or(0) as usize & !1)).collect();
+            let data = r.get(data_at..data_at + data_size as usize).unwrap_or(&[]);
+            out.push(Record { class, number: be32(r, 4)?, r, attrs, data });
+        }
+    }
+    Some((version, table_count, out))
+}
+
+fn hex_decode(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) || !s.bytes().all(|b| b.is_ascii_hexdigit()) { return None; }
+    (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok()).collect()
+}
+
+struct Acl {
+    descriptions: Vec<String>,
+    apps: Vec<String>,
+    partitions: Vec<String>,
+}
+
+/// Length-prefixed strings in the clear (public) part of a key blob, ahead of the
+/// encrypted key material. Entry descriptions are usually the item label; the
+/// partition list entry's description is a hex-encoded plist.
+fn acl(blob: &[u8]) -> Option<Acl> {
+    if be32(blob, 0)? != KEY_BLOB_MAGIC { return None; }
+    let public = blob.get(..be32(blob, 8)? as usize)?;
+    let mut acl = Acl { descriptions: Vec::new(), apps: Vec::new(), partitions: Vec::new() };
+    let mut p = 16;
+    while p + 4 <= public.len() {
+        let len = be32(public, p)? as usize;
+        let s = (1..=MAX_ACL_STRING).contains(&len).then(|| public.get(p + 4..p + 4 + len)).flatten()
+            .and_then(|b| std::str::from_utf8(b.strip_suffix(&[0]).unwrap_or(b)).ok())
+            .filter(|s| !s.is_empty() && !s.chars().any(char::is_control));
+        let Some(s) = s else { p += 4; continue };
+        let partitions = hex_decode(s).and_then(|x| plist::parse(&x)).and_then(|pl| {
+            Some(pl.get("Partitions")?.as_array()?.iter().filter_map(Plist::as_str).map(str::to_string).collect::<Vec<_>>())
+        });
+        match partitions {
+            Some(parts) => acl.partitions.extend(parts),
+            None if s.starts_with('/') => acl.apps.push(s.to_string()),
+            None => acl.descriptions.push(s.to_string()),
+        }
+        p += 4 + ((len + 3) & !3);
+    }
+    Some(acl)
+}
+
+fn item(r: &Record, keys: &[&Record]) -> IrisKeychainItem {
+    let password = r.class & 0x8000_0000 != 0 && r.class != KEYCHAIN_CLASS_CERTIFICATE;
+    let key_blob = if password {
+        r.data.starts_with(SSGP_MAGIC).then(|| r.data.get(..SSGP_LABEL)).flatten()
+            .and_then(|label| keys.iter().find(|k| k.blob("klbl") == Some(label))).map(|k| k.data)
+    } else if r.class == KEYCHAIN_CLASS_CERTIFICATE {
+        None
+    } else {
+        Some(r.data)
+    };
+    let acl = key_blob.and_then(acl);
+    let service = match r.class {
+        KEYCHAIN_CLASS_GENERIC_PASSWORD => r.string("svce"),
+        _ => r.string("srvr"),
+    };
+    let (acl_found, descriptions, apps, partitions) = match acl {
+        Some(a) => (true, a.descriptions, a.apps, a.partitions),
+        None => (false, Vec::new(), Vec::new(), Vec::new()),
+    };
+    IrisKeychainItem {
+        item_class: r.class,
+        record_number: r.number,
+        label: to_cstr(&r.string("labl")),
+        account: to_cstr(&r.string("acct")),
+        service: to_cstr(&service),
+        kind: to_cstr(&r.string("desc")),
+        comment: to_cstr(&r.string("icmt")),
+        creator: to_cstr(&r.fourcc("crtr")),
+        item_type: to_cstr(&if password { r.fourcc("type") } else { String::new() }),
+        protocol: to_cstr(&r.fourcc("ptcl")),
+        port: r.int("port"),
+        path: to_cstr(&r.string("path")),
+        created: r.time("cdat"),
+        modified: r.time("mdat"),
+        key_size: r.int("bsiz"),
+        data_size: r.data.len() as u32,
+        acl_found,
+        acl_descriptions: vec_to_c_string_array(descriptions),
+        trusted_apps: vec_to_c_string_array(apps),
+        partitions: vec_to_c_string_array(partitions),
+    }
+}
+
+fn parse(d: &[u8]) -> Option<IrisKeychain> {
+    let (version, table_count, records) = records(d)?;
+    let keys: Vec<&Record> = records.iter().filter(|r| r.class == KEYCHAIN_CLASS_SYMMETRIC_KEY).collect();
+    let items: Vec<IrisKeychainItem> = records.iter().map(|r| item(r, &keys)).collect();
+    let (items, count) = alloc_array(items);
+    Some(IrisKeychain { version, table_count, items, count })
+}
+
+fn write_keychain(d: &[u8], out: *mut IrisKeychain) -> i32 {
+    if !d.starts_with(MAGIC) { return -3; }
+    match parse(d) {
+        Some(k) => {
+            unsafe { out.write(k); }
+            0
+        }
+        None => -1,
+    }
+}
+
+// --- FFI entry points ---
+
+/// Inventory a keychain file (login.keychain-db or a legacy .keychain) held in memory.
+/// Returns 0=ok, -1=truncated schema, -2=arg error, -3=not a keychain.
+/// Free with iris_keychain_free.
+#[no_mangle]
+pub extern "C" fn iris_keychain_parse(data: *const u8, len: usize, out: *mut IrisKeychain) -> i32 {
+    if data.is_null() || out.is_null() { return -2; }
+    write_keychain(unsafe { std::slice::from_raw_parts(data, len) }, out)
+}
+
+/// Inventory a keychain file by path. Same return codes as iris_keychain_parse,
+/// with -1 also covering an unreadable file.
+#[no_mangle]
+pub extern "C" fn iris_keychain_parse_path(path: *const c_char, out: *mut IrisKeychain) -> i32 {
+    if path.is_null() || out.is_null() { return -2; }
+    let Ok(path) = unsafe { CStr::from_ptr(path) }.to_str() else { return -2 };
+    let Ok(d) = std::fs::read(path) else { return -1 };
+    write_keychain(&d, out)
+}
+
+#[no_mangle]
+pub extern "C" fn iris_keychain_free(k: *mut IrisKeychain) {
+    if k.is_null() { return; }
+    let k = unsafe { &*k };
+    for i in 0..k.count {
+        let i = unsafe { &*k.items.add(i) };
+        for p in [i.label, i.account, i.service, i.kind, i.comment, i.creator, i.item_type, i.protocol, i.path] { free_cstr(p); }
+        for a in [&i.acl_descriptions, &i.trusted_apps, &i.partitions] { free_c_string_array(a); }
+    }
+    free_array(k.items, k.count);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lv(s: &[u8]) -> Vec<u8> {
+        let mut v = (s.len() as u32).to_be_bytes().to_vec();
+        v.extend_from_slice(s);
+        v.resize((v.len() + 3) & !3, 0);
+        v
+    }
+
+    /// Record with the given attribute values (already encoded) in schema order.
+    fn record(class: u32, number: u32, data: &[u8], values: &[(&str, Vec<u8>)]) -> Vec<u8> {
+        let names = schema(class).unwrap();
+        let mut body = data.to_vec();
+        body.resize((body.len() + 3) & !3, 0);
+        let mut offsets = vec![0u32; names.len()];
+        let start = RECORD_HEADER + 4 * names.len();
+        for (name, v) in values {
+            let i = names.iter().position(|n| n == name).unwrap();
+            offsets[i] = (start + body.len()) as u32 + 1;
+            body.extend_from_slice(v);
+        }
+        let mut r = Vec::new();
+        for v in [(start + body.len()) as u32, number, 0, 0, data.len() as u32, 0] { r.extend_from_slice(&v.to_be_bytes()); }
+        offsets.iter().for_each(|o| r.extend_from_slice(&o.to_be_bytes()));
+        r.extend_from_slice(&body);
+        r
+    }
+
+    fn table(class: u32, records: &[Vec<u8>]) -> Vec<u8> {
+        // One deleted slot ahead of the live records
+        let mut offsets = vec![0u32];
+        let mut at = TABLE_HEADER + 4 * (records.len() + 1);
+        for r in records {
+            offsets.push(at as u32);
+            at += r.len();
+        }
+        let mut t = Vec::new();
+        for v in [at as u32, class, records.len() as u32, 0, 0, 0, offsets.len() as u32] { t.extend_from_slice(&v.to_be_bytes()); }
+        offsets.iter().for_each(|o| t.extend_from_slice(&o.to_be_bytes()));
+        records.iter().for_each(|r| t.extend_from_slice(r));
+        t
+    }
+
+    fn keychain(tables: &[Vec<u8>]) -> Vec<u8> {
+        let mut d = b"kych".to_vec();
+        for v in [0x0001_0000u32, HEADER_SIZE as u32, HEADER_SIZE as u32, 0] { d.extend_from_slice(&v.to_be_bytes()); }
+        let schema_len = 8 + 4 * tables.len();
+        d.extend_from_slice(&(schema_len as u32).to_be_bytes());
+        d.extend_from_slice(&(tables.len() as u32).to_be_bytes());
+        let mut at = schema_len;
+        for t in tables {
+            d.extend_from_slice(&(at as u32).to_be_bytes());
+            at += t.len();
+        }
+        tables.iter().for_each(|t| d.extend_from_slice(t));
+        d
+    }
+
+    fn s(p: *mut c_char) -> String {
+        unsafe { CStr::from_ptr(p) }.to_string_lossy().into_owned()
+    }
+
+    fn strings(a: &IrisCStringArray) -> Vec<String> {
+        (0..a.count).map(|i| s(unsafe { *a.items.add(i) })).collect()
+    }
+
+    #[test]
+    fn passwords_and_key_acls() {
+        let key_label = [b"ssgp".as_slice(), &[7; 16]].concat();
+        let partitions = r#"<plist version="1.0"><dict><key>Partitions</key><array><string>apple-tool:</string><string>teamid:ABCDE12345</string></array></dict></plist>"#;
+        let hex: String = partitions.bytes().map(|b| format!("{:02x}", b)).collect();
+        let public = [lv(b"Example Mail"), lv(b"/Applications/Mail.app"), lv(hex.as_bytes())].concat();
+        let blob = [KEY_BLOB_MAGIC.to_be_bytes().as_slice(), &0x100u32.to_be_bytes(), &(16 + public.len() as u32).to_be_bytes(),
+                    &0u32.to_be_bytes(), &public, &[0xaa; 32]].concat();
+        let key = record(KEYCHAIN_CLASS_SYMMETRIC_KEY, 3, &blob, &[("klbl", lv(&key_label)), ("bsiz", 192u32.to_be_bytes().to_vec())]);
+        let generic = record(KEYCHAIN_CLASS_GENERIC_PASSWORD, 1, &[key_label.as_slice(), &[0x55; 24]].concat(), &[
+            ("cdat", b"20240315120000Z\0".to_vec()),
+            ("labl", lv(b"Example Mail")),
+            ("acct", lv(b"alice@example.com")),
+            ("svce", lv(b"Example Mail")),
+            ("desc", lv(b"application password")),
+        ]);
+        let internet = record(KEYCHAIN_CLASS_INTERNET_PASSWORD, 2, b"ssgp-unmatched", &[
+            ("srvr", lv(b"git.example.com")),
+            ("ptcl", b"htps".to_vec()),
+            ("port", 443u32.to_be_bytes().to_vec()),
+        ]);
+        let d = keychain(&[table(KEYCHAIN_CLASS_GENERIC_PASSWORD, &[generic]), table(KEYCHAIN_CLASS_INTERNET_PASSWORD, &[internet]),
+                           table(KEYCHAIN_CLASS_SYMMETRIC_KEY, &[key]), table(0x0000_0002, &[])]);
+
+        let mut out = std::mem::MaybeUninit::<IrisKeychain>::uninit();
+        assert_eq!(iris_keychain_parse(d.as_ptr(), d.len(), out.as_mut_ptr()), 0);
+        let mut kc = unsafe { out.assume_init() };
+        assert_eq!((kc.version, kc.table_count, kc.count), (0x0001_0000, 4, 3));
+        let items = unsafe { std::slice::from_raw_parts(kc.items, kc.count) };
+
+        let g = &items[0];
+        assert_eq!((g.item_class, g.record_number, s(g.label), s(g.account)), (KEYCHAIN_CLASS_GENERIC_PASSWORD, 1, "Example Mail".into(), "alice@example.com".into()));
+        assert_eq!((s(g.service), s(g.kind), g.created, g.modified, g.data_size), ("Example Mail".into(), "application password".into(), 1710504000, 0, 44));
+        assert!(g.acl_found);
+        assert_eq!((strings(&g.acl_descriptions), strings(&g.trusted_apps)), (vec!["Example Mail".to_string()], vec!["/Applications/Mail.app".to_string()]));
+        assert_eq!(strings(&g.partitions), ["apple-tool:", "teamid:ABCDE12345"]);
+
+        let i = &items[1];
+        assert_eq!((s(i.service), s(i.protocol), i.port, i.acl_found), ("git.example.com".into(), "htps".into(), 443, false));
+        assert_eq!((items[2].item_class, items[2].key_size, items[2].acl_found), (KEYCHAIN_CLASS_SYMMETRIC_KEY, 192, true));
+        iris_keychain_free(&mut kc);
+
+        let mut out = std::mem::MaybeUninit::<IrisKeychain>::uninit();
+        assert_eq!(iris_keychain_parse(b"SQLite format 3".as_ptr(), 15, out.as_mut_ptr()), -3);
+        assert_eq!(iris_keychain_parse(d.as_ptr(), 24, out.as_mut_ptr()), -1);
+    }
+    fn parse_code(d: &[u8]) -> (i32, usize) {
+        let mut out = std::mem::MaybeUninit::<IrisKeychain>::uninit();
+        let rc = iris_keychain_parse(d.as_ptr(), d.len(), out.as_mut_ptr());
+        if rc != 0 { return (rc, 0); }
+        let mut kc = unsafe { out.assume_init() };
+        let count = kc.count;
+        iris_keychain_free(&mut kc);
+        (rc, count)
+    }
+
+    fn generic(number: u32) -> Vec<u8> {
+        record(KEYCHAIN_CLASS_GENERIC_PASSWORD, number, b"", &[("labl", lv(b"x"))])
+    }
+
+    #[test]
+    fn bad_magic() {
+        let d = keychain(&[table(KEYCHAIN_CLASS_GENERIC_PASSWORD, &[generic(1)])]);
+        assert_eq!(parse_code(&d), (0, 1));
+        let mut bad = d.clone();
+        bad[..4].copy_from_slice(b"KYCH");
+        assert_eq!(parse_code(&bad), (-3, 0));
+        assert_eq!(parse_code(&d[1..]), (-3, 0));
+        assert_eq!(parse_code(b""), (-3, 0));
+        assert_eq!(parse_code(b"kych"), (-1, 0)); // magic but no header
+        let mut out = std::mem::MaybeUninit::<IrisKeychain>::uninit();
+        assert_eq!(iris_keychain_parse(std::ptr::null(), 0, out.as_mut_ptr()), -2);
+        assert_eq!(iris_keychain_parse(d.as_ptr(), d.len(), std::ptr::null_mut()), -2);
+    }
+
+    #[test]
+    fn table_offsets_out_of_range() {
+        let d = keychain(&[table(KEYCHAIN_CLASS_GENERIC_PASSWORD, &[generic(1)])]);
+        let mut schema = d.clone();
+        schema[12..16].copy_from_slice(&0xffff_fff0u32.to_be_bytes());
+        assert_eq!(parse_code(&schema), (-1, 0));
+        let mut table_at = d.clone();
+        table_at[HEADER_SIZE + 8..HEADER_SIZE + 12].copy_from_slice(&(d.len() as u32).to_be_bytes());
+        assert_eq!(parse_code(&table_at), (-1, 0));
+        let mut tables = d.clone();
+        tables[HEADER_SIZE + 4..HEADER_SIZE + 8].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert_eq!(parse_code(&tables), (-1, 0)); // offsets for the extra tables run off the end
+        let base = HEADER_SIZE + 12;
+        let mut size = d.clone();
+        size[base..base + 4].copy_from_slice(&0x0010_0000u32.to_be_bytes());
+        assert_eq!(parse_code(&size), (-1, 0));
+        assert_eq!(parse_code(&d[..d.len() - 1]), (-1, 0)); // table cut short
+
+        // A record slot pointing outside its table is skipped, not fatal
+        let mut slot = d.clone();
+        slot[base + TABLE_HEADER + 4..base + TABLE_HEADER + 8].copy_from_slice(&0x0000_4000u32.to_be_bytes());
+        assert_eq!(parse_code(&slot), (0, 0));
+        let mut rec_len = d.clone();
+        let rec = base + TABLE_HEADER + 8;
+        rec_len[rec..rec + 4].copy_from_slice(&0x0000_4000u32.to_be_bytes());
+        assert_eq!(parse_code(&rec_len), (0, 0));
+    }
+
+    #[test]
+    fn record_count_past_file() {
+        let d = keychain(&[table(KEYCHAIN_CLASS_GENERIC_PASSWORD, &[generic(1), generic(2)])]);
+        let count = HEADER_SIZE + 12 + 8;
+        let mut many = d.clone();
+        many[count..count + 4].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert_eq!(parse_code(&many), (0, 2)); // stops at the end of the slot list
+        let mut fewer = d.clone();
+        fewer[count..count + 4].copy_from_slice(&1u32.to_be_bytes());
+        assert_eq!(parse_code(&fewer), (0, 1));
+    }
+}
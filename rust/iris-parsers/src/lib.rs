@@ -5,3 +5,90 @@ mod dns;
 mod der;
 mod batch;
 mod tlsh;
+mod ethernet;
+mod ip;
+mod icmp;
+mod arp;
+mod dhcp;
+mod hash;
+mod ssh;
+mod base64;
+mod smtp;
+mod ftp;
+mod kerberos;
+mod smb;
+mod snmp;
+mod vpn;
+mod identify;
+mod flow;
+mod dnssd;
+mod bencode;
+mod bittorrent;
+mod sip;
+mod tls;
+mod plist;
+mod x509;
+mod cms;
+mod provisioning;
+mod lz4;
+mod tracev3;
+mod dmg;
+mod inflate;
+mod xml;
+mod cpio;
+mod xar;
+mod sqlite;
+mod quarantine;
+mod requirement;
+mod tcc;
+mod history;
+mod bsm;
+mod zip;
+mod tar;
+mod archive;
+mod json;
+mod rsa;
+mod jwt;
+mod bom;
+mod notarization;
+mod loginitems;
+mod asep;
+mod bookmark;
+mod lzma;
+mod lzfse;
+mod payload;
+mod aar;
+mod scpt;
+mod keychain;
+mod extension;
+mod hpack;
+mod h2;
+mod url;
+mod websocket;
+mod auth;
+mod secheaders;
+mod grpc;
+mod useragent;
+mod httpstream;
+mod httpconn;
+mod httpdiff;
+mod idn;
+mod tsig;
+mod dnscookie;
+mod dnscorr;
+mod dnsupdate;
+mod dnscanon;
+mod dnscheck;
+mod dns64;
+mod tlsrecord;
+mod tlsalert;
+mod tlsrisk;
+mod aes;
+mod quic;
+mod tlsresume;
+mod certcheck;
+mod tlsfp;
+mod tlsclientauth;
+mod certaudit;
+mod tlsversion;
+mod bytes;
@@ -0,0 +1,206 @@
+//! Login and background items: the BackgroundTaskManagement store
+//! (/private/var/db/com.apple.backgroundtaskmanagement/BackgroundItems-v*.btm, an
+//! NSKeyedArchiver plist), the older per-user backgrounditems.btm, and the legacy
+//! com.apple.loginitems.plist lists.
+
+use crate::ffi::{alloc_array, alloc_bytes, free_array, free_c_string_array, free_cstr, to_cstr, vec_to_c_string_array, IrisCStringArray};
+use crate::plist::{self, Plist};
+use std::ffi::c_char;
+
+const MAX_DEPTH: usize = 32;
+
+pub const LOGIN_SOURCE_BTM: u8 = 1;     // BackgroundItems-v*.btm item record
+pub const LOGIN_SOURCE_LEGACY: u8 = 2;  // loginitems plist / backgrounditems.btm entry
+
+pub const BTM_DISPOSITION_ENABLED: u32 = 0x1;
+pub const BTM_DISPOSITION_ALLOWED: u32 = 0x2;
+
+#[repr(C)]
+pub struct IrisLoginItem {
+    pub source: u8,                      // LOGIN_SOURCE_*
+    pub user: *mut c_char,               // owning user's UUID (BTM), "" otherwise
+    pub uuid: *mut c_char,
+    pub name: *mut c_char,
+    pub developer_name: *mut c_char,
+    pub team_id: *mut c_char,
+    pub identifier: *mut c_char,         // BTM identifier, e.g. "2.com.example.helper"
+    pub bundle_id: *mut c_char,
+    pub url: *mut c_char,                // target URL ("file:///Applications/Example.app/")
+    pub executable_path: *mut c_char,
+    pub parent_identifier: *mut c_char,  // owning app for embedded agents/daemons
+    pub associated_bundle_ids: IrisCStringArray,
+    pub item_type: u32,                  // BTM type bits (login item, agent, daemon, ...)
+    pub disposition: u32,                // BTM disposition bits
+    pub enabled: bool,
+    pub allowed: bool,                   // approved in Login Items settings
+    pub bookmark: *mut u8,               // alias/bookmark data when the target is stored that way
+    pub bookmark_len: usize,
+}
+
+#[repr(C)]
+pub struct IrisLoginItems {
+    pub items: *mut IrisLoginItem,
+    pub count: usize,
+}
+
+fn str_field(p: &Plist, keys: &[&str]) -> String {
+    keys.iter().find_map(|k| p.get(k).and_then(Plist::as_str)).unwrap_or("").to_string()
+}
+
+fn item(p: &Plist, source: u8, user: &str) -> IrisLoginItem {
+    let int = |k: &str| p.get(k).and_then(Plist::as_int).unwrap_or(0) as u32;
+    let disposition = int("disposition");
+    let associated = p.get("associatedBundleIdentifiers").and_then(Plist::as_array).unwrap_or(&[])
+        .iter().filter_map(Plist::as_str).map(str::to_string).collect();
+    let bookmark = ["bookmark", "Bookmark", "Alias"].iter().find_map(|k| p.get(k).and_then(Plist::as_data)).unwrap_or(&[]);
+    let (bookmark, bookmark_len) = alloc_bytes(bookmark);
+    IrisLoginItem {
+        source,
+        user: to_cstr(user),
+        uuid: to_cstr(&str_field(p, &["uuid"])),
+        name: to_cstr(&str_field(p, &["name", "Name"])),
+        developer_name: to_cstr(&str_field(p, &["developerName"])),
+        team_id: to_cstr(&str_field(p, &["teamIdentifier"])),
+        identifier: to_cstr(&str_field(p, &["identifier"])),
+        bundle_id: to_cstr(&str_field(p, &["bundleIdentifier"])),
+        url: to_cstr(&str_field(p, &["url", "URL"])),
+        executable_path: to_cstr(&str_field(p, &["executablePath"])),
+        parent_identifier: to_cstr(&str_field(p, &["parentIdentifier"])),
+        associated_bundle_ids: vec_to_c_string_array(associated),
+        item_type: int("type"),
+        disposition,
+        // Legacy lists only hold items that run at login
+        enabled: source == LOGIN_SOURCE_LEGACY || disposition & BTM_DISPOSITION_ENABLED != 0,
+        allowed: source == LOGIN_SOURCE_LEGACY || disposition & BTM_DISPOSITION_ALLOWED != 0,
+        bookmark,
+        bookmark_len,
+    }
+}
+
+/// Collect item records anywhere in the tree. BTM records carry "uuid" and "type";
+/// legacy entries a name plus alias or bookmark data. Keys of "itemsByUserIdentifier"
+/// name the owning user.
+fn collect(p: &Plist, user: &str, out: &mut Vec<IrisLoginItem>, depth: usize) {
+    if depth > MAX_DEPTH { return; }
+    match p {
+        Plist::Dict(d) => {
+            if p.get("uuid").is_some() && p.get("type").and_then(Plist::as_int).is_some() {
+                out.push(item(p, LOGIN_SOURCE_BTM, user));
+                return;
+            }
+            let named = p.get("name").or_else(|| p.get("Name")).is_some();
+            if named && ["bookmark", "Bookmark", "Alias"].iter().any(|k| p.get(k).is_some()) {
+                out.push(item(p, LOGIN_SOURCE_LEGACY, user));
+                return;
+            }
+            for (k, v) in d {
+                match v {
+                    Plist::Dict(users) if k == "itemsByUserIdentifier" => {
+                        for (u, items) in users { collect(items, u, out, depth + 1); }
+                    }
+                    _ => collect(v, user, out, depth + 1),
+                }
+            }
+        }
+        Plist::Array(a) => a.iter().for_each(|v| collect(v, user, out, depth + 1)),
+        _ => {}
+    }
+}
+
+/// Login items in a .btm store or loginitems plist; None if the data is not a plist.
+pub fn parse(data: &[u8]) -> Option<Vec<IrisLoginItem>> {
+    let p = plist::parse(data)?;
+    let root = plist::unarchive(&p).unwrap_or(p);
+    let mut items = Vec::new();
+    collect(&root, "", &mut items, 0);
+    Some(items)
+}
+
+pub fn free_item(i: &IrisLoginItem) {
+    for p in [i.user, i.uuid, i.name, i.developer_name, i.team_id, i.identifier, i.bundle_id, i.url,
+              i.executable_path, i.parent_identifier] { free_cstr(p); }
+    free_c_string_array(&i.associated_bundle_ids);
+    free_array(i.bookmark, i.bookmark_len);
+}
+
+// --- FFI entry points ---
+
+/// Enumerate login/background items from a BackgroundItems-v*.btm file, an older
+/// backgrounditems.btm, or a com.apple.loginitems.plist (format detected).
+/// Returns 0=ok, -2=arg error or not a plist. Free with iris_login_items_free.
+#[no_mangle]
+pub extern "C" fn iris_login_items_parse(data: *const u8, len: usize, out: *mut IrisLoginItems) -> i32 {
+    if data.is_null() || out.is_null() { return -2; }
+    let Some(items) = parse(unsafe { std::slice::from_raw_parts(data, len) }) else { return -2 };
+    let (items, count) = alloc_array(items);
+    unsafe { out.write(IrisLoginItems { items, count }); }
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn iris_login_items_free(items: *mut IrisLoginItems) {
+    if items.is_null() { return; }
+    let items = unsafe { &*items };
+    for i in 0..items.count { free_item(unsafe { &*items.items.add(i) }); }
+    free_array(items.items, items.count);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+
+    fn s(p: *mut c_char) -> String {
+        unsafe { CStr::from_ptr(p) }.to_string_lossy().into_owned()
+    }
+
+    fn parse(doc: &str) -> IrisLoginItems {
+        let mut out = std::mem::MaybeUninit::<IrisLoginItems>::uninit();
+        assert_eq!(iris_login_items_parse(doc.as_ptr(), doc.len(), out.as_mut_ptr()), 0);
+        unsafe { out.assume_init() }
+    }
+
+    #[test]
+    fn btm_store() {
+        let uid = |i: u32| format!("<dict><key>CF$UID</key><integer>{}</integer></dict>", i);
+        let doc = format!(r#"<plist version="1.0"><dict>
+<key>$top</key><dict><key>root</key>{u1}</dict>
+<key>$objects</key><array>
+  <string>$null</string>
+  <dict><key>store</key>{u2}<key>$class</key>{u9}</dict>
+  <dict><key>itemsByUserIdentifier</key>{u3}<key>$class</key>{u9}</dict>
+  <dict><key>NS.keys</key><array>{u4}</array><key>NS.objects</key><array>{u5}</array><key>$class</key>{u9}</dict>
+  <string>5A1E0C2B-0000-4000-8000-000000000001</string>
+  <dict><key>NS.objects</key><array>{u6}</array><key>$class</key>{u9}</dict>
+  <dict><key>uuid</key>{u7}<key>type</key><integer>8</integer><key>disposition</key><integer>11</integer>
+        <key>name</key>{u8}<key>teamIdentifier</key>{u0}<key>url</key>{u10}<key>$class</key>{u9}</dict>
+  <string>9F1C-ITEM</string>
+  <string>updater</string>
+  <dict><key>$classname</key><string>ItemRecord</string></dict>
+  <dict><key>NS.base</key>{u0}<key>NS.relative</key>{u11}<key>$class</key>{u9}</dict>
+  <string>file:///Library/LaunchAgents/com.example.updater.plist</string>
+</array></dict></plist>"#,
+            u0 = uid(0), u1 = uid(1), u2 = uid(2), u3 = uid(3), u4 = uid(4), u5 = uid(5), u6 = uid(6),
+            u7 = uid(7), u8 = uid(8), u9 = uid(9), u10 = uid(10), u11 = uid(11));
+        let mut items = parse(&doc);
+        assert_eq!(items.count, 1);
+        let i = unsafe { &*items.items };
+        assert_eq!((i.source, s(i.user), s(i.uuid), s(i.name)), (LOGIN_SOURCE_BTM, "5A1E0C2B-0000-4000-8000-000000000001".into(), "9F1C-ITEM".into(), "updater".into()));
+        assert_eq!((i.item_type, i.enabled, i.allowed, s(i.team_id)), (8, true, true, String::new()));
+        assert_eq!(s(i.url), "file:///Library/LaunchAgents/com.example.updater.plist");
+        iris_login_items_free(&mut items);
+    }
+
+    #[test]
+    fn legacy_loginitems_plist() {
+        let doc = r#"<plist version="1.0"><dict><key>SessionItems</key><dict><key>CustomListItems</key><array>
+  <dict><key>Name</key><string>Helper</string><key>Alias</key><data>Ym9vaw==</data></dict>
+</array></dict></dict></plist>"#;
+        let mut items = parse(doc);
+        assert_eq!(items.count, 1);
+        let i = unsafe { &*items.items };
+        assert_eq!((i.source, s(i.name), i.enabled), (LOGIN_SOURCE_LEGACY, "Helper".into(), true));
+        assert_eq!(unsafe { std::slice::from_raw_parts(i.bookmark, i.bookmark_len) }, b"book");
+        iris_login_items_free(&mut items);
+    }
+}
@@ -0,0 +1,51 @@
+//! LZ4 block decompression (raw block format, no frame header).
+
+/// Decompress one LZ4 block whose uncompressed size is known up front.
+/// None on malformed input or if the output would exceed `size`.
+pub fn decompress_block(src: &[u8], size: usize) -> Option<Vec<u8>> {
+    let mut out: Vec<u8> = Vec::with_capacity(size);
+    let mut p = 0;
+    let read_len = |p: &mut usize, mut n: usize| -> Option<usize> {
+        if n == 15 {
+            loop {
+                let b = *src.get(*p)?;
+                *p += 1;
+                n = n.checked_add(b as usize)?;
+                if b != 255 { break; }
+            }
+        }
+        Some(n)
+    };
+    loop {
+        let token = *src.get(p)?;
+        p += 1;
+        let lit = read_len(&mut p, (token >> 4) as usize)?;
+        out.extend_from_slice(src.get(p..p.checked_add(lit)?)?);
+        p += lit;
+        if out.len() > size { return None; }
+        // The last sequence carries literals only
+        if p == src.len() { break; }
+        let offset = u16::from_le_bytes([*src.get(p)?, *src.get(p + 1)?]) as usize;
+        p += 2;
+        if offset == 0 || offset > out.len() { return None; }
+        let len = read_len(&mut p, (token & 0x0f) as usize)? + 4;
+        if out.len() + len > size { return None; }
+        let start = out.len() - offset;
+        for i in 0..len { out.push(out[start + i]); }
+    }
+    (out.len() == size).then_some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn literals_and_overlapping_match() {
+        // "abc" then a match of 9 at offset 3, then literal "!"
+        let block = [0x35, b'a', b'b', b'c', 3, 0, 0x10, b'!'];
+        assert_eq!(decompress_block(&block, 13).unwrap(), b"abcabcabcabc!");
+        assert!(decompress_block(&block, 12).is_none());
+        assert!(decompress_block(&[0x15, b'a', 9, 0], 10).is_none());
+    }
+}
@@ -0,0 +1,286 @@
+//! LZFSE decoding (Apple's compression library format, used by Apple Archive and
+//! pbze payloads): a sequence of "bvx" blocks that are raw, LZVN-coded, or LZ77
+//! matches with finite-state-entropy coded literals and lengths. Version 1 blocks,
+//! which the encoder never emits, are not supported.
+
+use crate::bytes::{le32, le64};
+
+const BLOCK_END: &[u8] = b"bvx$";
+const BLOCK_RAW: &[u8] = b"bvx-";
+const BLOCK_V2: &[u8] = b"bvx2";
+const BLOCK_LZVN: &[u8] = b"bvxn";
+
+const V2_HEADER_SIZE: usize = 32;
+const LITERALS_PER_BLOCK: usize = 4 * 10000;
+const MATCHES_PER_BLOCK: usize = 10000;
+const L_SYMBOLS: usize = 20;
+const M_SYMBOLS: usize = 20;
+const D_SYMBOLS: usize = 64;
+const LITERAL_SYMBOLS: usize = 256;
+const L_STATES: usize = 64;
+const M_STATES: usize = 64;
+const D_STATES: usize = 256;
+const LITERAL_STATES: usize = 1024;
+
+const L_EXTRA_BITS: [u8; L_SYMBOLS] = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 3, 5, 8];
+const L_BASE: [u32; L_SYMBOLS] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 20, 28, 60];
+const M_EXTRA_BITS: [u8; M_SYMBOLS] = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 3, 5, 8, 11];
+const M_BASE: [u32; M_SYMBOLS] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 24, 56, 312];
+const D_EXTRA_BITS: [u8; D_SYMBOLS] = [
+    0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 6, 6, 6, 6, 7, 7, 7, 7,
+    8, 8, 8, 8, 9, 9, 9, 9, 10, 10, 10, 10, 11, 11, 11, 11, 12, 12, 12, 12, 13, 13, 13, 13, 14, 14, 14, 14, 15, 15, 15, 15,
+];
+const D_BASE: [u32; D_SYMBOLS] = [
+    0, 1, 2, 3, 4, 6, 8, 10, 12, 16, 20, 24, 28, 36, 44, 52, 60, 76, 92, 108, 124, 156, 188, 220, 252, 316, 380, 444,
+    508, 636, 764, 892, 1020, 1276, 1532, 1788, 2044, 2556, 3068, 3580, 4092, 5116, 6140, 7164, 8188, 10236, 12284,
+    14332, 16380, 20476, 24572, 28668, 32764, 40956, 49148, 57340, 65532, 81916, 98300, 114684, 131068, 163836,
+    196604, 229372,
+];
+
+fn field(v: u64, offset: u32, bits: u32) -> u64 { (v >> offset) & ((1 << bits) - 1) }
+
+/// FSE bit stream, read backwards: the payload is one little-endian integer whose top
+/// `-n` bits (0..=7) are padding, consumed from the most significant end.
+struct BackBits<'a> {
+    d: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BackBits<'a> {
+    fn new(d: &'a [u8], n: i32) -> Option<Self> {
+        if !(-7..=0).contains(&n) || d.len() < if n == 0 { 7 } else { 8 } { return None; }
+        if n < 0 && d[d.len() - 1] >> (8 + n) != 0 { return None; }
+        Some(BackBits { d, pos: 8 * d.len() - (-n) as usize })
+    }
+
+    fn pull(&mut self, k: u32) -> Option<u32> {
+        let k = k as usize;
+        if k == 0 { return Some(0); }
+        let start = self.pos.checked_sub(k)?;
+        let (lo, hi) = (start / 8, self.pos.div_ceil(8));
+        let v = self.d[lo..hi].iter().rev().fold(0u64, |v, &b| v << 8 | b as u64);
+        self.pos = start;
+        Some(((v >> (start - 8 * lo)) & ((1 << k) - 1)) as u32)
+    }
+}
+
+/// (bits to read, symbol, state delta) per state.
+fn literal_table(freq: &[u16], nstates: usize) -> Option<Vec<(u32, u8, usize)>> {
+    if freq.iter().map(|&f| f as usize).sum::<usize>() > nstates { return None; }
+    let mut t = Vec::with_capacity(nstates);
+    for (sym, &f) in freq.iter().enumerate() {
+        let f = f as usize;
+        if f == 0 { continue; }
+        let k = f.leading_zeros() - nstates.leading_zeros();
+        let j0 = ((2 * nstates) >> k) - f;
+        for j in 0..f {
+            t.push(if j < j0 { (k, sym as u8, ((f + j) << k) - nstates) } else { (k - 1, sym as u8, (j - j0) << (k - 1)) });
+        }
+    }
+    Some(t)
+}
+
+/// (state bits, value bits, state delta, value base) per state.
+fn value_table(freq: &[u16], nstates: usize, extra: &[u8], base: &[u32]) -> Option<Vec<(u32, u32, usize, u32)>> {
+    Some(literal_table(freq, nstates)?.into_iter().map(|(k, sym, delta)| (k, extra[sym as usize] as u32, delta, base[sym as usize])).collect())
+}
+
+fn decode_value(t: &[(u32, u32, usize, u32)], state: &mut usize, bits: &mut BackBits) -> Option<u32> {
+    let &(k, vbits, delta, base) = t.get(*state)?;
+    let v = bits.pull(k + vbits)?;
+    *state = delta + (v >> vbits) as usize;
+    Some(base + (v & ((1 << vbits) - 1)))
+}
+
+/// Frequency tables of a v2 header: a variable-length code, 2 to 14 bits per value.
+fn read_freqs(d: &[u8]) -> Option<Vec<u16>> {
+    let mut out = Vec::with_capacity(L_SYMBOLS + M_SYMBOLS + D_SYMBOLS + LITERAL_SYMBOLS);
+    let (mut acc, mut nbits, mut p) = (0u32, 0u32, 0);
+    for _ in 0..out.capacity() {
+        while p < d.len() && nbits + 8 <= 32 {
+            acc |= (d[p] as u32) << nbits;
+            nbits += 8;
+            p += 1;
+        }
+        let (n, v) = match acc & 31 {
+            b if b & 3 == 0 => (2, 0),
+            b if b & 3 == 2 => (2, 1),
+            b if b & 7 == 1 => (3, 2),
+            b if b & 7 == 5 => (3, 3),
+            b if b & 0xf == 7 => (8, 8 + ((acc >> 4) & 0xf)),
+            b if b & 0xf == 15 => (14, 24 + ((acc >> 4) & 0x3ff)),
+            b => (5, 4 + (b >> 3)),
+        };
+        if n > nbits { return None; }
+        out.push(v as u16);
+        acc >>= n;
+        nbits -= n;
+    }
+    (nbits < 8 && p == d.len()).then_some(out)
+}
+
+fn copy_match(out: &mut Vec<u8>, d: usize, m: usize) -> Option<()> {
+    if m == 0 { return Some(()); }
+    let start = out.len().checked_sub(d).filter(|_| d > 0)?;
+    for i in 0..m { out.push(out[start + i]); }
+    Some(())
+}
+
+/// A "bvx2" block; `out` holds everything decoded so far (matches may reach into it).
+fn decode_v2(b: &[u8], out: &mut Vec<u8>, limit: usize) -> Option<usize> {
+    let n_raw = le32(b, 4)? as usize;
+    let (v0, v1, v2) = (le64(b, 8)?, le64(b, 16)?, le64(b, 24)?);
+    let n_literals = field(v0, 0, 20) as usize;
+    let n_lit_payload = field(v0, 20, 20) as usize;
+    let n_matches = field(v0, 40, 20) as usize;
+    let literal_bits = field(v0, 60, 3) as i32 - 7;
+    let lit_states = [field(v1, 0, 10), field(v1, 10, 10), field(v1, 20, 10), field(v1, 30, 10)].map(|s| s as usize);
+    let n_lmd_payload = field(v1, 40, 20) as usize;
+    let lmd_bits = field(v1, 60, 3) as i32 - 7;
+    let header_size = field(v2, 0, 32) as usize;
+    let (mut l_state, mut m_state, mut d_state) = (field(v2, 32, 10) as usize, field(v2, 42, 10) as usize, field(v2, 52, 10) as usize);
+    if n_literals > LITERALS_PER_BLOCK || n_matches > MATCHES_PER_BLOCK || header_size < V2_HEADER_SIZE { return None; }
+    if out.len().checked_add(n_raw)? > limit { return None; }
+
+    let freqs = read_freqs(b.get(V2_HEADER_SIZE..header_size)?)?;
+    let (lf, rest) = freqs.split_at(L_SYMBOLS);
+    let (mf, rest) = rest.split_at(M_SYMBOLS);
+    let (df, litf) = rest.split_at(D_SYMBOLS);
+    let lit_table = literal_table(litf, LITERAL_STATES)?;
+    let l_table = value_table(lf, L_STATES, &L_EXTRA_BITS, &L_BASE)?;
+    let m_table = value_table(mf, M_STATES, &M_EXTRA_BITS, &M_BASE)?;
+    let d_table = value_table(df, D_STATES, &D_EXTRA_BITS, &D_BASE)?;
+
+    let payload = b.get(header_size..header_size.checked_add(n_lit_payload)?.checked_add(n_lmd_payload)?)?;
+    let mut bits = BackBits::new(&payload[..n_lit_payload], literal_bits)?;
+    let mut states = lit_states;
+    let mut literals = Vec::with_capacity(n_literals + 3);
+    // Literals are coded four at a time with interleaved states
+    while literals.len() < n_literals {
+        for s in states.iter_mut() {
+            let &(k, sym, delta) = lit_table.get(*s)?;
+            literals.push(sym);
+            *s = delta + bits.pull(k)? as usize;
+        }
+    }
+
+    let mut bits = BackBits::new(&payload[n_lit_payload..], lmd_bits)?;
+    let start = out.len();
+    let mut lit = 0;
+    let mut dist = 0usize;
+    for _ in 0..n_matches {
+        let l = decode_value(&l_table, &mut l_state, &mut bits)? as usize;
+        let m = decode_value(&m_table, &mut m_state, &mut bits)? as usize;
+        let new_d = decode_value(&d_table, &mut d_state, &mut bits)? as usize;
+        if new_d != 0 { dist = new_d; }
+        if out.len() + l + m > start + n_raw { return None; }
+        out.extend_from_slice(literals.get(lit..lit + l)?);
+        lit += l;
+        copy_match(out, dist, m)?;
+    }
+    (out.len() == start + n_raw).then_some(header_size + payload.len())
+}
+
+/// LZVN: byte-aligned opcodes carrying a literal length, match length and distance.
+fn decode_lzvn(src: &[u8], out: &mut Vec<u8>, n_raw: usize) -> Option<()> {
+    let end = out.len().checked_add(n_raw)?;
+    let (mut p, mut dist) = (0, 0usize);
+    while out.len() < end {
+        let op = *src.get(p)?;
+        let byte = |i: usize| src.get(p + i).map(|&b| b as usize);
+        let (len, l, m, d) = match op {
+            0x06 => break, // end of stream
+            0x0e | 0x16 => (1, 0, 0, dist), // nop
+            0x70..=0x7f | 0xd0..=0xdf | 0x1e | 0x26 | 0x2e | 0x36 | 0x3e => return None,
+            0xa0..=0xbf => {
+                let (b1, b2) = (byte(1)?, byte(2)?);
+                let m = (((op as usize & 7) << 2) | (b1 & 3)) + 3;
+                (3, (op as usize >> 3) & 3, m, (b2 << 8 | b1) >> 2)
+            }
+            0xe0 => (2, byte(1)? + 16, 0, dist),
+            0xe1..=0xef => (1, op as usize & 0xf, 0, dist),
+            0xf0 => (2, 0, byte(1)? + 16, dist),
+            0xf1..=0xff => (1, 0, op as usize & 0xf, dist),
+            _ => {
+                let (l, m) = ((op as usize >> 6) & 3, ((op as usize >> 3) & 7) + 3);
+                match op & 7 {
+                    6 => (1, l, m, dist),
+                    7 => (3, l, m, byte(2)? << 8 | byte(1)?),
+                    _ => (2, l, m, (op as usize & 7) << 8 | byte(1)?),
+                }
+            }
+        };
+        p += len;
+        if out.len() + l + m > end { return None; }
+        out.extend_from_slice(src.get(p..p + l)?);
+        p += l;
+        dist = d;
+        copy_match(out, dist, m)?;
+    }
+    (out.len() == end).then_some(())
+}
+
+/// Decode an LZFSE stream up to its end-of-stream block. None if malformed,
+/// truncated, or larger than `limit`.
+pub fn decompress(d: &[u8], limit: usize) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut p = 0;
+    loop {
+        let b = d.get(p..)?;
+        let magic = b.get(..4)?;
+        if magic == BLOCK_END { return Some(out); }
+        let n_raw = le32(b, 4)? as usize;
+        if out.len().checked_add(n_raw)? > limit { return None; }
+        if magic == BLOCK_RAW {
+            out.extend_from_slice(b.get(8..8 + n_raw)?);
+            p += 8 + n_raw;
+        } else if magic == BLOCK_LZVN {
+            let n_payload = le32(b, 8)? as usize;
+            decode_lzvn(b.get(12..12usize.checked_add(n_payload)?)?, &mut out, n_raw)?;
+            p += 12 + n_payload;
+        } else if magic == BLOCK_V2 {
+            p += decode_v2(b, &mut out, limit)?;
+        } else {
+            return None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap()).collect()
+    }
+
+    // One bvx2 block holding three copies of this line, followed by bvx$
+    const SAMPLE_LINE: &[u8] = b"the cat sat on the mat; the cat sat on the hat.\n";
+    const SAMPLE: &str = "62767832900000001800a00000050060bedbb960290800109b000000346c90044f0070740400c011c02170740400c0111c0100bf\
+        01bf01fc06f04f0000000000000000000000000000002f0100000000002f090000002f010000c04b000000000000000000c04bc2\
+        4bc04b00df03c04bf012bc04c04bf0c5000000000000000000000000000000000000000000000000000000000000000000000000\
+        60b5a7ae072fa0a10200048a02183b370362767824";
+
+    #[test]
+    fn fse_block() {
+        let data = hex(SAMPLE);
+        assert_eq!(decompress(&data, 1 << 20).unwrap(), SAMPLE_LINE.repeat(3));
+        assert!(decompress(&data, 100).is_none());
+        assert!(decompress(&data[..data.len() - 4], 1 << 20).is_none());
+        let mut bad = data.clone();
+        bad[40] ^= 0xff; // frequency table no longer sums to the state count
+        assert!(decompress(&bad, 1 << 20).is_none());
+    }
+
+    #[test]
+    fn raw_and_lzvn_blocks() {
+        let mut data = b"bvx-\x03\0\0\0xyz".to_vec();
+        // 3 literals "abc", then an 8-byte match at distance 3, then end-of-stream
+        data.extend_from_slice(b"bvxn\x0b\0\0\0\x0e\0\0\0\xe3abc\x28\x03\x06\0\0\0\0\0\0\0");
+        data.extend_from_slice(b"bvx$");
+        assert_eq!(decompress(&data, 1 << 20).unwrap(), b"xyzabcabcabcab");
+        data[22] = 0x70; // undefined opcode
+        assert!(decompress(&data, 1 << 20).is_none());
+        assert!(decompress(b"bvx1\0\0\0\0", 1 << 20).is_none());
+    }
+}
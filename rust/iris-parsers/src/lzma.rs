@@ -0,0 +1,370 @@
+//! LZMA decoding for .xz streams (the chunks of pbzx payloads and LZMA-compressed
+//! Apple Archives): the xz container, LZMA2 chunking and the LZMA range decoder.
+//! Only the LZMA2 filter is supported; block and stream checks are not verified.
+
+const XZ_MAGIC: &[u8] = &[0xfd, b'7', b'z', b'X', b'Z', 0];
+const XZ_FOOTER_MAGIC: &[u8] = b"YZ";
+const FILTER_LZMA2: u64 = 0x21;
+
+const PROB_INIT: u16 = 1024;
+const MOVE_BITS: u32 = 5;
+const TOP: u32 = 1 << 24;
+const STATES: usize = 12;
+const POS_STATES: usize = 16;
+const LEN_TO_POS_STATES: usize = 4;
+const END_POS_MODEL: usize = 14;
+const FULL_DISTANCES: usize = 128;
+const ALIGN_BITS: u32 = 4;
+const LITERAL_CODER_SIZE: usize = 0x300;
+
+struct RangeDecoder<'a> {
+    d: &'a [u8],
+    pos: usize,
+    range: u32,
+    code: u32,
+}
+
+impl<'a> RangeDecoder<'a> {
+    fn new(d: &'a [u8]) -> Option<Self> {
+        if d.len() < 5 || d[0] != 0 { return None; }
+        Some(RangeDecoder { d, pos: 5, range: 0xffff_ffff, code: u32::from_be_bytes(d[1..5].try_into().ok()?) })
+    }
+
+    fn normalize(&mut self) -> Option<()> {
+        if self.range < TOP {
+            self.range <<= 8;
+            self.code = self.code << 8 | *self.d.get(self.pos)? as u32;
+            self.pos += 1;
+        }
+        Some(())
+    }
+
+    fn bit(&mut self, p: &mut u16) -> Option<u32> {
+        self.normalize()?;
+        let bound = (self.range >> 11) * *p as u32;
+        if self.code < bound {
+            self.range = bound;
+            *p += (2048 - *p) >> MOVE_BITS;
+            Some(0)
+        } else {
+            self.range -= bound;
+            self.code -= bound;
+            *p -= *p >> MOVE_BITS;
+            Some(1)
+        }
+    }
+
+    fn tree(&mut self, probs: &mut [u16], bits: u32) -> Option<u32> {
+        let mut m = 1usize;
+        for _ in 0..bits { m = m << 1 | self.bit(&mut probs[m])? as usize; }
+        Some(m as u32 - (1 << bits))
+    }
+
+    fn reverse_tree(&mut self, probs: &mut [u16], bits: u32) -> Option<u32> {
+        let (mut m, mut sym) = (1usize, 0u32);
+        for i in 0..bits {
+            let b = self.bit(&mut probs[m])?;
+            m = m << 1 | b as usize;
+            sym |= b << i;
+        }
+        Some(sym)
+    }
+
+    fn direct(&mut self, bits: u32) -> Option<u32> {
+        let mut v = 0u32;
+        for _ in 0..bits {
+            self.normalize()?;
+            self.range >>= 1;
+            let b = (self.code >= self.range) as u32;
+            if b == 1 { self.code -= self.range; }
+            v = v << 1 | b;
+        }
+        Some(v)
+    }
+}
+
+#[derive(Clone)]
+struct LenDecoder {
+    choice: [u16; 2],
+    low: [[u16; 8]; POS_STATES],
+    mid: [[u16; 8]; POS_STATES],
+    high: [u16; 256],
+}
+
+impl LenDecoder {
+    fn new() -> Self {
+        LenDecoder { choice: [PROB_INIT; 2], low: [[PROB_INIT; 8]; POS_STATES], mid: [[PROB_INIT; 8]; POS_STATES], high: [PROB_INIT; 256] }
+    }
+
+    /// Match length minus 2.
+    fn decode(&mut self, rc: &mut RangeDecoder, pos_state: usize) -> Option<u32> {
+        if rc.bit(&mut self.choice[0])? == 0 { return rc.tree(&mut self.low[pos_state], 3); }
+        if rc.bit(&mut self.choice[1])? == 0 { return Some(8 + rc.tree(&mut self.mid[pos_state], 3)?); }
+        Some(16 + rc.tree(&mut self.high, 8)?)
+    }
+}
+
+/// LZMA model state; survives across LZMA2 chunks unless a state reset is signalled.
+struct Lzma {
+    lc: u32,
+    lp: u32,
+    pb: u32,
+    state: usize,
+    reps: [u32; 4],
+    is_match: [[u16; POS_STATES]; STATES],
+    is_rep: [u16; STATES],
+    is_rep0: [u16; STATES],
+    is_rep1: [u16; STATES],
+    is_rep2: [u16; STATES],
+    is_rep0_long: [[u16; POS_STATES]; STATES],
+    dist_slot: [[u16; 64]; LEN_TO_POS_STATES],
+    dist_special: [u16; FULL_DISTANCES - END_POS_MODEL + 1],
+    align: [u16; 1 << ALIGN_BITS],
+    len: LenDecoder,
+    rep_len: LenDecoder,
+    literal: Vec<u16>,
+}
+
+impl Lzma {
+    fn new(props: u8) -> Option<Self> {
+        if props >= 9 * 5 * 5 { return None; }
+        let p = props as u32;
+        Some(Lzma::fresh(p % 9, p / 9 % 5, p / 45))
+    }
+
+    fn fresh(lc: u32, lp: u32, pb: u32) -> Self {
+        Lzma {
+            lc, lp, pb, state: 0, reps: [0; 4],
+            is_match: [[PROB_INIT; POS_STATES]; STATES],
+            is_rep: [PROB_INIT; STATES], is_rep0: [PROB_INIT; STATES], is_rep1: [PROB_INIT; STATES], is_rep2: [PROB_INIT; STATES],
+            is_rep0_long: [[PROB_INIT; POS_STATES]; STATES],
+            dist_slot: [[PROB_INIT; 64]; LEN_TO_POS_STATES],
+            dist_special: [PROB_INIT; FULL_DISTANCES - END_POS_MODEL + 1],
+            align: [PROB_INIT; 1 << ALIGN_BITS],
+            len: LenDecoder::new(), rep_len: LenDecoder::new(),
+            literal: vec![PROB_INIT; LITERAL_CODER_SIZE << (lc + lp)],
+        }
+    }
+
+    fn reset(&mut self) {
+        *self = Lzma::fresh(self.lc, self.lp, self.pb);
+    }
+
+    fn distance(&mut self, rc: &mut RangeDecoder, len: u32) -> Option<u32> {
+        let slot = rc.tree(&mut self.dist_slot[(len as usize).min(LEN_TO_POS_STATES - 1)], 6)?;
+        if slot < 4 { return Some(slot); }
+        let direct = (slot >> 1) - 1;
+        let base = (2 | (slot & 1)) << direct;
+        if (slot as usize) < END_POS_MODEL {
+            let off = (base - slot) as usize;
+            return Some(base + rc.reverse_tree(&mut self.dist_special[off..], direct)?);
+        }
+        let hi = rc.direct(direct - ALIGN_BITS)? << ALIGN_BITS;
+        Some(base.wrapping_add(hi).wrapping_add(rc.reverse_tree(&mut self.align, ALIGN_BITS)?))
+    }
+
+    /// Decode `size` bytes onto `out`; matches may not reach before `dict_start`.
+    fn decode(&mut self, rc: &mut RangeDecoder, out: &mut Vec<u8>, dict_start: usize, size: usize) -> Option<()> {
+        let end = out.len() + size;
+        let pb_mask = (1usize << self.pb) - 1;
+        let lp_mask = (1usize << self.lp) - 1;
+        while out.len() < end {
+            let pos = out.len();
+            let ps = pos & pb_mask;
+            if rc.bit(&mut self.is_match[self.state][ps])? == 0 {
+                let prev = if pos > dict_start { out[pos - 1] as usize } else { 0 };
+                let base = LITERAL_CODER_SIZE * (((pos & lp_mask) << self.lc) + (prev >> (8 - self.lc)));
+                let probs = &mut self.literal[base..base + LITERAL_CODER_SIZE];
+                let mut sym = 1usize;
+                if self.state >= 7 {
+                    let at = pos.checked_sub(self.reps[0] as usize + 1).filter(|&a| a >= dict_start)?;
+                    let mut match_byte = out[at] as usize;
+                    while sym < 0x100 {
+                        let mb = (match_byte >> 7) & 1;
+                        match_byte <<= 1;
+                        let b = rc.bit(&mut probs[((1 + mb) << 8) + sym])? as usize;
+                        sym = sym << 1 | b;
+                        if mb != b { break; }
+                    }
+                }
+                while sym < 0x100 { sym = sym << 1 | rc.bit(&mut probs[sym])? as usize; }
+                out.push(sym as u8);
+                self.state = match self.state { 0..=3 => 0, 4..=9 => self.state - 3, _ => self.state - 6 };
+                continue;
+            }
+            let len;
+            if rc.bit(&mut self.is_rep[self.state])? == 0 {
+                len = self.len.decode(rc, ps)?;
+                self.state = if self.state < 7 { 7 } else { 10 };
+                let dist = self.distance(rc, len)?;
+                if dist == u32::MAX { return None; } // end marker: not allowed in LZMA2
+                self.reps = [dist, self.reps[0], self.reps[1], self.reps[2]];
+            } else {
+                if rc.bit(&mut self.is_rep0[self.state])? == 0 {
+                    if rc.bit(&mut self.is_rep0_long[self.state][ps])? == 0 {
+                        self.state = if self.state < 7 { 9 } else { 11 };
+                        let at = pos.checked_sub(self.reps[0] as usize + 1).filter(|&a| a >= dict_start)?;
+                        out.push(out[at]);
+                        continue;
+                    }
+                } else {
+                    let dist;
+                    if rc.bit(&mut self.is_rep1[self.state])? == 0 {
+                        dist = self.reps[1];
+                    } else {
+                        if rc.bit(&mut self.is_rep2[self.state])? == 0 {
+                            dist = self.reps[2];
+                        } else {
+                            dist = self.reps[3];
+                            self.reps[3] = self.reps[2];
+                        }
+                        self.reps[2] = self.reps[1];
+                    }
+                    self.reps[1] = self.reps[0];
+                    self.reps[0] = dist;
+                }
+                len = self.rep_len.decode(rc, ps)?;
+                self.state = if self.state < 7 { 8 } else { 11 };
+            }
+            let n = len as usize + 2;
+            let start = pos.checked_sub(self.reps[0] as usize + 1).filter(|&a| a >= dict_start)?;
+            if pos + n > end { return None; }
+            for i in 0..n { out.push(out[start + i]); }
+        }
+        Some(())
+    }
+}
+
+/// Decode LZMA2 chunks; returns the bytes consumed including the end marker.
+pub fn lzma2_decompress(d: &[u8], out: &mut Vec<u8>, limit: usize) -> Option<usize> {
+    let mut p = 0;
+    let mut dict_start = out.len();
+    let mut lzma: Option<Lzma> = None;
+    loop {
+        let control = *d.get(p)?;
+        p += 1;
+        match control {
+            0x00 => return Some(p),
+            0x01 | 0x02 => {
+                if control == 0x01 { dict_start = out.len(); }
+                let size = u16::from_be_bytes(d.get(p..p + 2)?.try_into().ok()?) as usize + 1;
+                let chunk = d.get(p + 2..p + 2 + size)?;
+                if out.len() + size > limit { return None; }
+                out.extend_from_slice(chunk);
+                p += 2 + size;
+            }
+            0x80.. => {
+                let h = d.get(p..p + 4)?;
+                let unpacked = (((control & 0x1f) as usize) << 16 | (h[0] as usize) << 8 | h[1] as usize) + 1;
+                let packed = ((h[2] as usize) << 8 | h[3] as usize) + 1;
+                p += 4;
+                let reset = (control >> 5) & 3;
+                if reset == 3 { dict_start = out.len(); }
+                if reset >= 2 {
+                    let props = *d.get(p)?;
+                    p += 1;
+                    let l = Lzma::new(props)?;
+                    if l.lc + l.lp > 4 { return None; }
+                    lzma = Some(l);
+                } else if reset == 1 {
+                    lzma.as_mut()?.reset();
+                }
+                let l = lzma.as_mut()?;
+                if out.len() + unpacked > limit { return None; }
+                let mut rc = RangeDecoder::new(d.get(p..p + packed)?)?;
+                l.decode(&mut rc, out, dict_start, unpacked)?;
+                p += packed;
+            }
+            _ => return None,
+        }
+    }
+}
+
+fn varint(d: &[u8], p: &mut usize) -> Option<u64> {
+    let mut v = 0u64;
+    for i in 0..9 {
+        let b = *d.get(*p)?;
+        *p += 1;
+        v |= ((b & 0x7f) as u64) << (7 * i);
+        if b & 0x80 == 0 { return Some(v); }
+    }
+    None
+}
+
+fn check_size(check: u8) -> usize {
+    match check { 0 => 0, 1..=3 => 4, 4..=6 => 8, 7..=9 => 16, 10..=12 => 32, _ => 64 }
+}
+
+fn pad4(n: usize) -> usize { (n + 3) & !3 }
+
+/// Decompress one or more concatenated .xz streams.
+pub fn xz_decompress(d: &[u8], limit: usize) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut p = 0;
+    while p < d.len() {
+        if d[p..].iter().take(4).all(|&b| b == 0) && p > 0 { p += 4; continue; } // stream padding
+        if !d[p..].starts_with(XZ_MAGIC) { return None; }
+        let check = *d.get(p + 7)? & 0x0f;
+        p += 12;
+        loop {
+            let hdr = *d.get(p)? as usize;
+            if hdr == 0 { break; } // index indicator
+            let header = d.get(p..p + (hdr + 1) * 4)?;
+            let flags = header[1];
+            let mut q = 2;
+            if flags & 0x40 != 0 { varint(header, &mut q)?; }
+            if flags & 0x80 != 0 { varint(header, &mut q)?; }
+            if flags & 0x03 != 0 { return None; } // filter chains (BCJ, delta) are not supported
+            if varint(header, &mut q)? != FILTER_LZMA2 || varint(header, &mut q)? != 1 { return None; }
+            p += header.len();
+            let start = p;
+            p += lzma2_decompress(&d[p..], &mut out, limit)?;
+            p = start + pad4(p - start) + check_size(check);
+        }
+        // Index: records of (unpadded size, uncompressed size), padded, CRC32
+        let start = p;
+        p += 1;
+        let records = varint(d, &mut p)?;
+        for _ in 0..records { varint(d, &mut p)?; varint(d, &mut p)?; }
+        p = start + pad4(p - start) + 4;
+        if d.get(p + 10..p + 12)? != XZ_FOOTER_MAGIC { return None; }
+        p += 12;
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    pub fn hex(s: &str) -> Vec<u8> {
+        let s: String = s.split_whitespace().collect();
+        (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap()).collect()
+    }
+
+    /// The text compressed in XZ_SAMPLE.
+    pub fn sample_text() -> Vec<u8> {
+        (0..40).flat_map(|i| format!("line {} of the payload\n", i % 7).into_bytes()).collect()
+    }
+
+    #[test]
+    fn xz_streams() {
+        let xz = hex(XZ_SAMPLE);
+        let out = xz_decompress(&xz, 1 << 20).unwrap();
+        let want = sample_text();
+        assert_eq!(out, want);
+        assert_eq!(xz_decompress(&[xz.clone(), xz.clone()].concat(), 1 << 20).unwrap().len(), 2 * want.len());
+        assert!(xz_decompress(&xz, 100).is_none());
+        assert!(xz_decompress(&xz[..xz.len() - 20], 1 << 20).is_none());
+        // Stored LZMA2 chunk followed by the end marker
+        let mut out = Vec::new();
+        assert_eq!(lzma2_decompress(&[1, 0, 2, b'a', b'b', b'c', 0], &mut out, 10), Some(7));
+        assert_eq!(out, b"abc");
+    }
+
+    // `xz -9 --check=crc64` of the expected text
+    pub const XZ_SAMPLE: &str = "\
+        fd377a585a000004e6d6b446020021011c00000010cf58cce0036f00335d00361a4a1f08a026034d069df8b2a5acb03d\
+        ff98034f57900b05789fbb275009f7f98fc70363177d7c0218d9863b9c0f15780600000011df4502539da5f000014ff0\
+        06000000e5630450b1c467fb020000000004595a";
+}
@@ -71,7 +71,7 @@ fn parse_file(path: &str) -> Result<ParseResult, i32> {
         if arches.is_empty() { return Err(-2); }
         let off = arches[0].offset as usize;
         let sz = arches[0].size as usize;
-        if off.checked_add(sz).map_or(true, |end| end > bytes.len()) { return Err(-2); }
+        if off.checked_add(sz).is_none_or(|end| end > bytes.len()) { return Err(-2); }
         let slice = &bytes[off..off + sz];
         let macho = MachO::parse_lossy(slice, 0).map_err(|_| -2)?;
         return Ok(extract_info(&macho, slice));
@@ -0,0 +1,307 @@
+//! Stapled notarization tickets: CMS SignedData whose DER content lists the code
+//! directory hashes Apple notarized. Tickets are found in Contents/CodeResources of a
+//! bundle, in the ticket slot of a disk image's code signature, or after a flat
+//! package's "t8lr" trailer. The CMS signature is not verified.
+
+use crate::bytes::{be32, be64};
+use crate::cms;
+use crate::der::{read_tlv, Tlv};
+use crate::ffi::{alloc_array, free_array, free_c_string_array, vec_to_c_string_array, IrisCStringArray};
+use crate::hash::to_hex;
+use crate::x509::{alloc_cert_array, free_cert_array, IrisX509Cert};
+use std::ffi::{c_char, CStr};
+
+const SUPERBLOB_MAGIC: u32 = 0xfade_0cc0;
+const BLOB_WRAPPER_MAGIC: u32 = 0xfade_0b01;
+const CSSLOT_TICKET: u32 = 0x10002;
+const KOLY_SIZE: usize = 512;
+const PKG_TRAILER_MAGIC: &[u8] = b"t8lr";
+const PKG_TRAILER_SIZE: usize = 12;
+const CDHASH_LEN: usize = 20;
+const MAX_HEADER_SCAN: usize = 64;
+const MAX_DEPTH: usize = 16;
+
+pub const TICKET_SOURCE_RAW: u8 = 0;     // ticket bytes passed directly
+pub const TICKET_SOURCE_BUNDLE: u8 = 1;  // Contents/CodeResources
+pub const TICKET_SOURCE_DMG: u8 = 2;     // code signature ticket slot
+pub const TICKET_SOURCE_PKG: u8 = 3;     // t8lr trailer
+
+#[repr(C)]
+pub struct IrisNotarizationTicket {
+    pub source: u8,                    // TICKET_SOURCE_*
+    pub cdhashes: IrisCStringArray,    // hex, 20-byte (truncated) code directory hashes
+    pub signing_time: i64,             // CMS signingTime, 0 if absent
+    pub timestamps: *mut i64,          // unix seconds of the times in the ticket content
+    pub timestamp_count: usize,
+    pub digest_valid: bool,            // messageDigest matches the ticket content
+    pub signer_chain: *mut IrisX509Cert,
+    pub signer_chain_count: usize,
+}
+
+/// Collect 20-byte OCTET STRINGs (cdhashes) and UTCTime/GeneralizedTime values.
+fn walk(t: &Tlv, hashes: &mut Vec<String>, times: &mut Vec<i64>, depth: usize) {
+    if depth > MAX_DEPTH { return; }
+    if t.constructed {
+        for c in t.children() { walk(&c, hashes, times, depth + 1); }
+    } else if t.is(0, 4) && t.content.len() == CDHASH_LEN {
+        hashes.push(to_hex(t.content));
+    } else if let Some(ts) = t.as_time() {
+        times.push(ts);
+    }
+}
+
+/// Decode a ticket. A short vendor header before the CMS ContentInfo is skipped.
+fn parse(data: &[u8], source: u8) -> Option<IrisNotarizationTicket> {
+    let sd = (0..data.len().min(MAX_HEADER_SCAN))
+        .filter(|&i| data[i] == 0x30)
+        .find_map(|i| cms::parse_signed_data(&data[i..]))?;
+    let mut cdhashes = Vec::new();
+    let mut times = Vec::new();
+    let mut p = 0;
+    while let Some((t, used)) = read_tlv(&sd.content[p..]) {
+        walk(&t, &mut cdhashes, &mut times, 0);
+        p += used;
+    }
+    cdhashes.dedup();
+    let (signer_chain, signer_chain_count) = alloc_cert_array(&sd.signer_chain());
+    let (timestamps, timestamp_count) = alloc_array(times);
+    Some(IrisNotarizationTicket {
+        source,
+        cdhashes: vec_to_c_string_array(cdhashes),
+        signing_time: sd.signers.first().and_then(|s| s.signing_time).unwrap_or(0),
+        timestamps,
+        timestamp_count,
+        digest_valid: sd.digest_matches(),
+        signer_chain,
+        signer_chain_count,
+    })
+}
+
+/// The CSSLOT_TICKET blob of a code signature SuperBlob, unwrapped.
+fn superblob_ticket(sig: &[u8]) -> Option<&[u8]> {
+    if be32(sig, 0)? != SUPERBLOB_MAGIC { return None; }
+    let sig = sig.get(..be32(sig, 4)? as usize)?;
+    let count = be32(sig, 8)? as usize;
+    let off = (0..count.min(sig.len() / 8)).find(|i| be32(sig, 12 + 8 * i) == Some(CSSLOT_TICKET))
+        .and_then(|i| be32(sig, 16 + 8 * i))? as usize;
+    let blob = sig.get(off..)?;
+    if be32(blob, 0)? == BLOB_WRAPPER_MAGIC {
+        let len = be32(blob, 4)? as usize;
+        return blob.get(8..len);
+    }
+    Some(blob)
+}
+
+/// Ticket stapled to a disk image: the koly trailer's code signature offset/length.
+fn dmg_ticket(d: &[u8]) -> Option<&[u8]> {
+    let koly = d.get(d.len().checked_sub(KOLY_SIZE)?..)?;
+    if !koly.starts_with(b"koly") { return None; }
+    let (off, len) = (usize::try_from(be64(koly, 296)?).ok()?, usize::try_from(be64(koly, 304)?).ok()?);
+    superblob_ticket(d.get(off..off.checked_add(len)?)?)
+}
+
+/// Ticket stapled to a flat package: ticket, then "t8lr", version, type, ticket length.
+fn pkg_ticket(d: &[u8]) -> Option<&[u8]> {
+    let trailer = d.get(d.len().checked_sub(PKG_TRAILER_SIZE)?..)?;
+    if !trailer.starts_with(PKG_TRAILER_MAGIC) || !d.starts_with(b"xar!") { return None; }
+    let len = u32::from_le_bytes(trailer[8..12].try_into().ok()?) as usize;
+    let end = d.len() - PKG_TRAILER_SIZE;
+    d.get(end.checked_sub(len)?..end)
+}
+
+fn write_ticket(t: Option<IrisNotarizationTicket>, out: *mut IrisNotarizationTicket) -> i32 {
+    match t {
+        Some(t) => {
+            unsafe { out.write(t); }
+            0
+        }
+        None => -2,
+    }
+}
+
+// --- FFI entry points ---
+
+/// Parse raw ticket bytes (e.g. a .ticket file or Contents/CodeResources).
+/// Returns 0=ok, -2=arg error or not a CMS-wrapped ticket.
+/// Free with iris_notarization_ticket_free.
+#[no_mangle]
+pub extern "C" fn iris_notarization_ticket_parse(data: *const u8, len: usize, out: *mut IrisNotarizationTicket) -> i32 {
+    if data.is_null() || out.is_null() { return -2; }
+    write_ticket(parse(unsafe { std::slice::from_raw_parts(data, len) }, TICKET_SOURCE_RAW), out)
+}
+
+/// Find and parse the ticket stapled to an app bundle directory, a .dmg or a flat .pkg.
+/// Returns 0=ok, -1=unreadable, -2=arg error or malformed ticket, -3=nothing stapled.
+/// Free with iris_notarization_ticket_free.
+#[no_mangle]
+pub extern "C" fn iris_notarization_ticket_find(path: *const c_char, out: *mut IrisNotarizationTicket) -> i32 {
+    if path.is_null() || out.is_null() { return -2; }
+    let Ok(path) = unsafe { CStr::from_ptr(path) }.to_str() else { return -2 };
+    let p = std::path::Path::new(path);
+    if p.is_dir() {
+        let Ok(d) = std::fs::read(p.join("Contents/CodeResources")) else { return -3 };
+        return write_ticket(parse(&d, TICKET_SOURCE_BUNDLE), out);
+    }
+    let Ok(d) = std::fs::read(p) else { return -1 };
+    if let Some(t) = dmg_ticket(&d) { return write_ticket(parse(t, TICKET_SOURCE_DMG), out); }
+    if let Some(t) = pkg_ticket(&d) { return write_ticket(parse(t, TICKET_SOURCE_PKG), out); }
+    -3
+}
+
+#[no_mangle]
+pub extern "C" fn iris_notarization_ticket_free(t: *mut IrisNotarizationTicket) {
+    if t.is_null() { return; }
+    let t = unsafe { &*t };
+    free_c_string_array(&t.cdhashes);
+    free_array(t.timestamps, t.timestamp_count);
+    free_cert_array(t.signer_chain, t.signer_chain_count);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cms::tests::signed_data;
+    use crate::x509::tests::tlv;
+
+    fn ticket() -> Vec<u8> {
+        let hashes = [tlv(0x04, &[0xaa; 20]), tlv(0x04, &[0xbb; 20]), tlv(0x04, &[0xcc; 32])].concat();
+        let content = tlv(0x30, &[tlv(0x02, &[1]), tlv(0x31, &hashes), tlv(0x18, b"20240301120000Z")].concat());
+        [&b"s8ch\0\0\0\x01"[..], &signed_data(&content, b"240301120005Z")].concat()
+    }
+
+    fn cdhash(t: &IrisNotarizationTicket, i: usize) -> String {
+        unsafe { CStr::from_ptr(*t.cdhashes.items.add(i)) }.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn raw_dmg_and_pkg() {
+        let tk = ticket();
+        let mut out = std::mem::MaybeUninit::<IrisNotarizationTicket>::uninit();
+        assert_eq!(iris_notarization_ticket_parse(tk.as_ptr(), tk.len(), out.as_mut_ptr()), 0);
+        let mut t = unsafe { out.assume_init() };
+        assert_eq!((t.cdhashes.count, cdhash(&t, 1).as_str()), (2, "bb".repeat(20).as_str()));
+        assert_eq!((t.signing_time, t.timestamp_count, t.digest_valid, t.signer_chain_count), (1_709_294_405, 1, true, 2));
+        assert_eq!(unsafe { *t.timestamps }, 1_709_294_400);
+        iris_notarization_ticket_free(&mut t);
+
+        // Disk image: data, code signature SuperBlob with a wrapped ticket slot, koly
+        let wrapped = [&BLOB_WRAPPER_MAGIC.to_be_bytes()[..], &(8 + tk.len() as u32).to_be_bytes(), &tk].concat();
+        let sig = [&SUPERBLOB_MAGIC.to_be_bytes()[..], &(28 + wrapped.len() as u32).to_be_bytes(), &2u32.to_be_bytes(),
+                   &0u32.to_be_bytes(), &28u32.to_be_bytes(), &CSSLOT_TICKET.to_be_bytes(), &28u32.to_be_bytes(), &wrapped].concat();
+        let mut koly = vec![0u8; KOLY_SIZE];
+        koly[..4].copy_from_slice(b"koly");
+        koly[296..304].copy_from_slice(&4096u64.to_be_bytes());
+        koly[304..312].copy_from_slice(&(sig.len() as u64).to_be_bytes());
+        let dmg = [vec![0u8; 4096], sig, koly].concat();
+        assert_eq!(dmg_ticket(&dmg), Some(&tk[..]));
+
+        let pkg = [&b"xar!"[..], &[0; 60], &tk, PKG_TRAILER_MAGIC, &[1, 0, 1, 0], &(tk.len() as u32).to_le_bytes()].concat();
+        assert_eq!(pkg_ticket(&pkg), Some(&tk[..]));
+
+        let dir = std::env::temp_dir().join(format!("iris-ticket-{}.app", std::process::id()));
+        std::fs::create_dir_all(dir.join("Contents")).unwrap();
+        let path = std::ffi::CString::new(dir.to_str().unwrap()).unwrap();
+        let mut out = std::mem::MaybeUninit::<IrisNotarizationTicket>::uninit();
+        assert_eq!(iris_notarization_ticket_find(path.as_ptr(), out.as_mut_ptr()), -3);
+        std::fs::write(dir.join("Contents/CodeResources"), &tk).unwrap();
+        assert_eq!(iris_notarization_ticket_find(path.as_ptr(), out.as_mut_ptr()), 0);
+        let mut t = unsafe { out.assume_init() };
+        assert_eq!((t.source, cdhash(&t, 0)), (TICKET_SOURCE_BUNDLE, "aa".repeat(20)));
+        iris_notarization_ticket_free(&mut t);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn sig_with(slot_offset: u32, tk: &[u8]) -> Vec<u8> {
+        let wrapped = [&BLOB_WRAPPER_MAGIC.to_be_bytes()[..], &(8 + tk.len() as u32).to_be_bytes(), tk].concat();
+        [&SUPERBLOB_MAGIC.to_be_bytes()[..], &(20 + wrapped.len() as u32).to_be_bytes(), &1u32.to_be_bytes(),
+         &CSSLOT_TICKET.to_be_bytes(), &slot_offset.to_be_bytes(), &wrapped].concat()
+    }
+
+    fn dmg_with(sig: &[u8], off: u64, len: u64) -> Vec<u8> {
+        let mut koly = vec![0u8; KOLY_SIZE];
+        koly[..4].copy_from_slice(b"koly");
+        koly[296..304].copy_from_slice(&off.to_be_bytes());
+        koly[304..312].copy_from_slice(&len.to_be_bytes());
+        [&[0u8; 64][..], sig, &koly].concat()
+    }
+
+    fn parse_code(d: &[u8]) -> i32 {
+        let mut out = std::mem::MaybeUninit::<IrisNotarizationTicket>::uninit();
+        let rc = iris_notarization_ticket_parse(d.as_ptr(), d.len(), out.as_mut_ptr());
+        if rc == 0 { iris_notarization_ticket_free(out.as_mut_ptr()); }
+        rc
+    }
+
+    #[test]
+    fn truncated_and_malformed_tickets() {
+        let tk = ticket();
+        assert_eq!(parse_code(&tk[..tk.len() - 1]), -2);
+        assert_eq!(parse_code(&tk[..40]), -2);
+        assert_eq!(parse_code(&tk[8..]), 0); // no vendor header
+        assert_eq!(parse_code(&[&[0u8; MAX_HEADER_SCAN][..], &tk[8..]].concat()), -2); // header too long to skip
+        assert_eq!(parse_code(b"s8ch"), -2);
+        assert_eq!(parse_code(&[]), -2);
+        let mut out = std::mem::MaybeUninit::<IrisNotarizationTicket>::uninit();
+        assert_eq!(iris_notarization_ticket_parse(std::ptr::null(), 0, out.as_mut_ptr()), -2);
+        assert_eq!(iris_notarization_ticket_parse(tk.as_ptr(), tk.len(), std::ptr::null_mut()), -2);
+
+        // Content altered after signing still parses, with the digest flagged
+        let mut tampered = tk.clone();
+        let at = tampered.windows(20).position(|w| w == [0xaa; 20]).unwrap();
+        tampered[at] = 0xab;
+        assert_eq!(iris_notarization_ticket_parse(tampered.as_ptr(), tampered.len(), out.as_mut_ptr()), 0);
+        let mut t = unsafe { out.assume_init_read() };
+        assert!(!t.digest_valid);
+        assert_eq!(cdhash(&t, 0), format!("ab{}", "aa".repeat(19)));
+        iris_notarization_ticket_free(&mut t);
+    }
+
+    #[test]
+    fn malformed_staples() {
+        let tk = ticket();
+        let sig = sig_with(20, &tk);
+        assert_eq!(dmg_ticket(&dmg_with(&sig, 64, sig.len() as u64)), Some(&tk[..]));
+        assert!(dmg_ticket(&dmg_with(&sig, 64, sig.len() as u64 + 1000)).is_none()); // past the end of the file
+        assert!(dmg_ticket(&dmg_with(&sig, 1 << 40, 16)).is_none());
+        assert!(dmg_ticket(&dmg_with(&sig, 64, u64::MAX)).is_none());
+        assert!(dmg_ticket(&dmg_with(&sig_with(1 << 20, &tk), 64, sig.len() as u64)).is_none()); // slot outside the blob
+        let mut long = sig.clone();
+        long[4..8].copy_from_slice(&(sig.len() as u32 + 1).to_be_bytes()); // SuperBlob longer than its slice
+        assert!(dmg_ticket(&dmg_with(&long, 64, sig.len() as u64)).is_none());
+        let mut wrapper = sig.clone();
+        wrapper[24..28].copy_from_slice(&4u32.to_be_bytes()); // wrapper shorter than its header
+        assert!(dmg_ticket(&dmg_with(&wrapper, 64, sig.len() as u64)).is_none());
+        assert!(dmg_ticket(&sig).is_none()); // no koly trailer
+
+        let pkg = |len: u32| [&b"xar!"[..], &tk, PKG_TRAILER_MAGIC, &[1, 0, 1, 0], &len.to_le_bytes()].concat();
+        assert_eq!(pkg_ticket(&pkg(tk.len() as u32)), Some(&tk[..]));
+        assert!(pkg_ticket(&pkg(tk.len() as u32 + 5)).is_none());
+        assert!(pkg_ticket(&pkg(tk.len() as u32)[4..]).is_none()); // not a xar archive
+        assert!(pkg_ticket(b"t8lr").is_none());
+
+        // Found by path: a stapled but truncated ticket, nothing stapled, no file
+        let dir = std::env::temp_dir().join(format!("iris-ticket-bad-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let find = |name: &str, data: Option<&[u8]>| {
+            let p = dir.join(name);
+            if let Some(d) = data { std::fs::write(&p, d).unwrap(); }
+            let path = std::ffi::CString::new(p.to_str().unwrap()).unwrap();
+            let mut out = std::mem::MaybeUninit::<IrisNotarizationTicket>::uninit();
+            let rc = iris_notarization_ticket_find(path.as_ptr(), out.as_mut_ptr());
+            if rc == 0 { iris_notarization_ticket_free(out.as_mut_ptr()); }
+            rc
+        };
+        let cut = &tk[..tk.len() - 10];
+        let bad_pkg = [&b"xar!"[..], cut, PKG_TRAILER_MAGIC, &[1, 0, 1, 0], &(cut.len() as u32).to_le_bytes()].concat();
+        assert_eq!(find("cut.pkg", Some(&bad_pkg)), -2);
+        let cut_sig = sig_with(20, cut);
+        assert_eq!(find("cut.dmg", Some(&dmg_with(&cut_sig, 64, cut_sig.len() as u64))), -2);
+        assert_eq!(find("ok.pkg", Some(&pkg(tk.len() as u32))), 0);
+        assert_eq!(find("plain.bin", Some(b"not stapled")), -3);
+        assert_eq!(find("missing.dmg", None), -1);
+        std::fs::remove_dir_all(&dir).unwrap();
+        let mut out = std::mem::MaybeUninit::<IrisNotarizationTicket>::uninit();
+        assert_eq!(iris_notarization_ticket_find(std::ptr::null(), out.as_mut_ptr()), -2);
+        assert_eq!(iris_notarization_ticket_find(c"/\xff".as_ptr(), out.as_mut_ptr()), -2);
+    }
+}
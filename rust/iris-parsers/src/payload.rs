@@ -0,0 +1,184 @@
+//! Compressed payload streams: pbzx (installer package Payload files, OTA and IPSW
+//! components) and the Apple Archive block framing "pbz" + algorithm around LZFSE,
+//! LZMA, LZ4 or zlib chunks, plus bare LZFSE and xz streams.
+
+use crate::bytes::{be64, le32};
+use crate::ffi::alloc_bytes;
+use crate::{inflate, lz4, lzfse, lzma};
+
+pub const DEFAULT_LIMIT: usize = 1 << 30;
+
+pub const PAYLOAD_PBZX: u8 = 1;   // "pbzx": xz chunks (also Apple Archive LZMA)
+pub const PAYLOAD_PBZE: u8 = 2;   // Apple Archive, LZFSE chunks
+pub const PAYLOAD_PBZ4: u8 = 3;   // Apple Archive, LZ4 chunks
+pub const PAYLOAD_PBZZ: u8 = 4;   // Apple Archive, zlib (raw deflate) chunks
+pub const PAYLOAD_LZFSE: u8 = 5;  // bare "bvx" block stream
+pub const PAYLOAD_XZ: u8 = 6;     // bare xz stream
+
+const BLOCK_HEADER: usize = 12;
+const CHUNK_HEADER: usize = 16;
+
+/// Stream type of `d`: PAYLOAD_*, or None.
+pub fn format(d: &[u8]) -> Option<u8> {
+    match d.get(..4)? {
+        b"pbzx" => Some(PAYLOAD_PBZX),
+        b"pbze" => Some(PAYLOAD_PBZE),
+        b"pbz4" => Some(PAYLOAD_PBZ4),
+        b"pbzz" => Some(PAYLOAD_PBZZ),
+        b"bvx2" | b"bvxn" | b"bvx-" | b"bvx$" => Some(PAYLOAD_LZFSE),
+        _ if d.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0]) => Some(PAYLOAD_XZ),
+        _ => None,
+    }
+}
+
+/// LZ4 chunk in Apple's "bv41" block framing, as written by the compression library.
+fn lz4_frames(d: &[u8], limit: usize) -> Option<Vec<u8>> {
+    let (mut out, mut p) = (Vec::new(), 0);
+    loop {
+        let b = d.get(p..)?;
+        match b.get(..4)? {
+            b"bv4$" => return Some(out),
+            b"bv4-" => {
+                let n = le32(b, 4)? as usize;
+                if out.len() + n > limit { return None; }
+                out.extend_from_slice(b.get(8..8usize.checked_add(n)?)?);
+                p += 8 + n;
+            }
+            b"bv41" => {
+                let (n, c) = (le32(b, 4)? as usize, le32(b, 8)? as usize);
+                if out.len() + n > limit { return None; }
+                let block = lz4::decompress_block(b.get(12..12usize.checked_add(c)?)?, n)?;
+                if block.len() != n { return None; }
+                out.extend_from_slice(&block);
+                p += 12 + c;
+            }
+            _ => return None,
+        }
+    }
+}
+
+fn chunk(kind: u8, c: &[u8], size: usize) -> Option<Vec<u8>> {
+    let out = match kind {
+        PAYLOAD_PBZX => lzma::xz_decompress(c, size)?,
+        PAYLOAD_PBZE => lzfse::decompress(c, size)?,
+        PAYLOAD_PBZ4 => lz4_frames(c, size)?,
+        _ => inflate::inflate(c, size)?.0,
+    };
+    (out.len() == size).then_some(out)
+}
+
+/// Chunked pbz* stream: magic, u64 block size, then (u64 raw size, u64 stored size,
+/// data) per chunk, all big-endian. A chunk whose sizes match is stored uncompressed.
+fn blocks(d: &[u8], kind: u8, limit: usize) -> Result<Vec<u8>, i32> {
+    let block_size = be64(d, 4).ok_or(-1)?;
+    let (mut out, mut p) = (Vec::new(), BLOCK_HEADER);
+    while p < d.len() {
+        let (raw, stored) = (be64(d, p).ok_or(-1)?, be64(d, p + 8).ok_or(-1)?);
+        if raw > block_size.max(1 << 24) { return Err(-2); }
+        let (raw, stored) = (raw as usize, usize::try_from(stored).map_err(|_| -2)?);
+        if out.len() + raw > limit { return Err(-1); }
+        let data = d.get(p + CHUNK_HEADER..).and_then(|c| c.get(..stored)).ok_or(-1)?;
+        if raw == stored {
+            out.extend_from_slice(data);
+        } else {
+            out.extend_from_slice(&chunk(kind, data, raw).ok_or(-2)?);
+        }
+        p += CHUNK_HEADER + stored;
+    }
+    Ok(out)
+}
+
+/// Decompress any PAYLOAD_* stream, producing at most `limit` bytes.
+/// Err(-1) truncated or over the limit, Err(-2) corrupt, Err(-3) not recognised.
+pub fn decompress(d: &[u8], limit: usize) -> Result<Vec<u8>, i32> {
+    match format(d).ok_or(-3)? {
+        PAYLOAD_LZFSE => lzfse::decompress(d, limit).ok_or(-2),
+        PAYLOAD_XZ => lzma::xz_decompress(d, limit).ok_or(-2),
+        kind => blocks(d, kind, limit),
+    }
+}
+
+// --- FFI entry points ---
+
+/// Stream type of a buffer (PAYLOAD_*), 0 if it is not a recognised payload stream.
+#[no_mangle]
+pub extern "C" fn iris_payload_format(data: *const u8, len: usize) -> u8 {
+    if data.is_null() { return 0; }
+    format(unsafe { std::slice::from_raw_parts(data, len) }).unwrap_or(0)
+}
+
+/// Decompress a pbzx stream (pkg Payload, OTA/IPSW component), an Apple Archive pbz*
+/// stream (.aar/.yaa, LZFSE/LZMA/LZ4/zlib chunks) or a bare LZFSE or xz stream,
+/// producing at most `limit` bytes (0 = 1 GiB). The output can be handed to the cpio,
+/// Apple Archive, Mach-O, hashing or strings parsers.
+/// Returns 0=ok, -1=truncated or larger than limit, -2=arg error or corrupt data,
+/// -3=not a recognised stream. Free *out with iris_free_bytes(*out, *out_len).
+#[no_mangle]
+pub extern "C" fn iris_payload_decompress(data: *const u8, len: usize, limit: usize, out: *mut *mut u8, out_len: *mut usize) -> i32 {
+    if data.is_null() || out.is_null() || out_len.is_null() { return -2; }
+    let limit = if limit == 0 { DEFAULT_LIMIT } else { limit };
+    match decompress(unsafe { std::slice::from_raw_parts(data, len) }, limit) {
+        Ok(v) => {
+            let (p, n) = alloc_bytes(&v);
+            unsafe { out.write(p); out_len.write(n); }
+            0
+        }
+        Err(e) => e,
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::lzma::tests::XZ_SAMPLE;
+
+    /// Wrap (raw size, data) chunks as a pbz* stream; stored chunks have equal sizes.
+    pub fn pbz(magic: &[u8], chunks: &[(usize, &[u8])]) -> Vec<u8> {
+        let mut d = magic.to_vec();
+        d.extend_from_slice(&(1u64 << 20).to_be_bytes());
+        for (raw, c) in chunks {
+            d.extend_from_slice(&(*raw as u64).to_be_bytes());
+            d.extend_from_slice(&(c.len() as u64).to_be_bytes());
+            d.extend_from_slice(c);
+        }
+        d
+    }
+
+    fn run(d: &[u8], limit: usize) -> (i32, Vec<u8>) {
+        let (mut p, mut n) = (std::ptr::null_mut(), 0);
+        let rc = iris_payload_decompress(d.as_ptr(), d.len(), limit, &mut p, &mut n);
+        if p.is_null() { return (rc, Vec::new()); }
+        let v = unsafe { std::slice::from_raw_parts(p, n) }.to_vec();
+        crate::ffi::iris_free_bytes(p, n);
+        (rc, v)
+    }
+
+    #[test]
+    fn pbzx_chunks() {
+        let xz = crate::lzma::tests::hex(XZ_SAMPLE);
+        let text = crate::lzma::tests::sample_text();
+        let d = pbz(b"pbzx", &[(text.len(), &xz), (5, b"tail\n")]);
+        assert_eq!(iris_payload_format(d.as_ptr(), d.len()), PAYLOAD_PBZX);
+        let (rc, out) = run(&d, 0);
+        assert_eq!(rc, 0);
+        assert_eq!(out, [text.as_slice(), b"tail\n"].concat());
+        assert_eq!(run(&d, 100).0, -1);
+        assert_eq!(run(&d[..d.len() - 2], 0).0, -1);
+        assert_eq!(run(&pbz(b"pbzx", &[(text.len() + 1, &xz)]), 0).0, -2);
+        assert_eq!(run(b"PK\x03\x04", 0).0, -3);
+    }
+
+    #[test]
+    fn apple_archive_chunks() {
+        // "abc", a 5-byte match at distance 3, then "!"
+        let lz4 = [b"bv41".as_slice(), &9u32.to_le_bytes(), &8u32.to_le_bytes(), &[0x31, b'a', b'b', b'c', 3, 0, 0x10, b'!'], b"bv4$"].concat();
+        let d = pbz(b"pbz4", &[(9, &lz4)]);
+        assert_eq!(decompress(&d, 1 << 20), Ok(b"abcabcab!".to_vec()));
+        let lzfse = [b"bvx-".as_slice(), &3u32.to_le_bytes(), b"xyz", b"bvx$"].concat();
+        assert_eq!(decompress(&pbz(b"pbze", &[(3, &lzfse)]), 1 << 20), Ok(b"xyz".to_vec()));
+        assert_eq!(decompress(&lzfse, 1 << 20), Ok(b"xyz".to_vec()));
+        // raw deflate stored block
+        let deflate = [1, 4, 0, 0xfb, 0xff, b'd', b'a', b't', b'a'];
+        assert_eq!(decompress(&pbz(b"pbzz", &[(4, &deflate)]), 1 << 20), Ok(b"data".to_vec()));
+    }
+}
@@ -0,0 +1,641 @@
+//! Property lists: bplist00 binary and XML (Apple DTD) formats parsed into one typed
+//! tree. The FFI exposes the tree as opaque node pointers owned by the root.
+
+use crate::der::components_to_unix;
+use crate::ffi::IrisSlice;
+use crate::xml::{decode_entities, find};
+use std::ffi::{c_char, CStr};
+
+pub const PLIST_BOOL: u8 = 1;
+pub const PLIST_INT: u8 = 2;
+pub const PLIST_REAL: u8 = 3;
+pub const PLIST_DATE: u8 = 4;
+pub const PLIST_DATA: u8 = 5;
+pub const PLIST_STRING: u8 = 6;
+pub const PLIST_UID: u8 = 7;
+pub const PLIST_ARRAY: u8 = 8;
+pub const PLIST_DICT: u8 = 9;
+
+/// Seconds between the Unix epoch and the Core Foundation epoch (2001-01-01).
+pub const CF_EPOCH: f64 = 978_307_200.0;
+const MAX_DEPTH: usize = 128;
+const MAX_NODES: usize = 1 << 20;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Plist {
+    Bool(bool),
+    Int(i64),      // bplist 128-bit ints keep the low 64 bits
+    Real(f64),
+    Date(f64),     // Unix seconds
+    Data(Vec<u8>),
+    String(String),
+    Uid(u64),      // NSKeyedArchiver object reference
+    Array(Vec<Plist>),
+    Dict(Vec<(String, Plist)>),
+}
+
+impl Plist {
+    pub fn get(&self, key: &str) -> Option<&Plist> {
+        match self {
+            Plist::Dict(d) => d.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self { Plist::String(s) => Some(s), _ => None }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self { Plist::Bool(b) => Some(*b), _ => None }
+    }
+
+    pub fn as_int(&self) -> Option<i64> {
+        match self { Plist::Int(i) => Some(*i), Plist::Uid(u) => Some(*u as i64), _ => None }
+    }
+
+    pub fn as_data(&self) -> Option<&[u8]> {
+        match self { Plist::Data(d) => Some(d), _ => None }
+    }
+
+    pub fn as_array(&self) -> Option<&[Plist]> {
+        match self { Plist::Array(a) => Some(a), _ => None }
+    }
+
+    fn type_code(&self) -> u8 {
+        match self {
+            Plist::Bool(_) => PLIST_BOOL, Plist::Int(_) => PLIST_INT, Plist::Real(_) => PLIST_REAL,
+            Plist::Date(_) => PLIST_DATE, Plist::Data(_) => PLIST_DATA, Plist::String(_) => PLIST_STRING,
+            Plist::Uid(_) => PLIST_UID, Plist::Array(_) => PLIST_ARRAY, Plist::Dict(_) => PLIST_DICT,
+        }
+    }
+}
+
+/// Parse a binary or XML plist, detected by its leading bytes.
+pub fn parse(data: &[u8]) -> Option<Plist> {
+    if data.starts_with(b"bplist00") { return parse_binary(data); }
+    parse_xml(data)
+}
+
+// --- bplist00 ---
+
+struct Binary<'a> {
+    data: &'a [u8],
+    offsets: Vec<usize>,
+    ref_size: usize,
+    nodes: usize,
+    in_progress: Vec<bool>,
+}
+
+fn be_uint(b: &[u8]) -> u64 {
+    b.iter().fold(0u64, |acc, &x| acc << 8 | x as u64)
+}
+
+impl Binary<'_> {
+    /// Length nibble of a marker; 0xF means an int object follows with the real count.
+    fn length(&self, marker: u8, p: usize) -> Option<(usize, usize)> {
+        let n = marker & 0x0f;
+        if n != 0x0f { return Some((n as usize, p + 1)); }
+        let m = *self.data.get(p + 1)?;
+        if m & 0xf0 != 0x10 { return None; }
+        let size = 1usize << (m & 0x0f);
+        let v = be_uint(self.data.get(p + 2..p + 2 + size)?);
+        Some((usize::try_from(v).ok()?, p + 2 + size))
+    }
+
+    fn refs(&self, p: usize, count: usize) -> Option<Vec<usize>> {
+        let bytes = self.data.get(p..p.checked_add(count.checked_mul(self.ref_size)?)?)?;
+        Some(bytes.chunks_exact(self.ref_size).map(|c| be_uint(c) as usize).collect())
+    }
+
+    fn object(&mut self, idx: usize, depth: usize) -> Option<Plist> {
+        self.nodes += 1;
+        if depth > MAX_DEPTH || self.nodes > MAX_NODES || *self.in_progress.get(idx)? { return None; }
+        let p = self.offsets[idx];
+        let marker = *self.data.get(p)?;
+        let d = self.data;
+        let v = match marker >> 4 {
+            0x0 => match marker {
+                0x08 => Plist::Bool(false),
+                0x09 => Plist::Bool(true),
+                _ => return None,
+            },
+            0x1 => {
+                let size = 1usize << (marker & 0x0f);
+                let b = d.get(p + 1..p + 1 + size)?;
+                // 8-byte ints are signed; 16-byte ints are unsigned with the value in the low half
+                Plist::Int(match size { 16 => be_uint(&b[8..]) as i64, s if s <= 8 => be_uint(b) as i64, _ => return None })
+            }
+            0x2 => match marker & 0x0f {
+                2 => Plist::Real(f32::from_be_bytes(d.get(p + 1..p + 5)?.try_into().ok()?) as f64),
+                3 => Plist::Real(f64::from_be_bytes(d.get(p + 1..p + 9)?.try_into().ok()?)),
+                _ => return None,
+            },
+            0x3 if marker == 0x33 => Plist::Date(f64::from_be_bytes(d.get(p + 1..p + 9)?.try_into().ok()?) + CF_EPOCH),
+            0x4 => {
+                let (n, s) = self.length(marker, p)?;
+                Plist::Data(d.get(s..s.checked_add(n)?)?.to_vec())
+            }
+            0x5 => {
+                let (n, s) = self.length(marker, p)?;
+                Plist::String(String::from_utf8_lossy(d.get(s..s.checked_add(n)?)?).into_owned())
+            }
+            0x6 => {
+                let (n, s) = self.length(marker, p)?;
+                let units: Vec<u16> = d.get(s..s.checked_add(n.checked_mul(2)?)?)?
+                    .chunks_exact(2).map(|c| u16::from_be_bytes([c[0], c[1]])).collect();
+                Plist::String(String::from_utf16_lossy(&units))
+            }
+            0x8 => Plist::Uid(be_uint(d.get(p + 1..p + 2 + (marker & 0x0f) as usize)?)),
+            0xa | 0xc => {
+                // Sets (0xC) have no plist XML form; they surface as arrays
+                let (n, s) = self.length(marker, p)?;
+                let refs = self.refs(s, n)?;
+                self.in_progress[idx] = true;
+                let items = refs.into_iter().map(|r| self.child(r, depth)).collect::<Option<Vec<_>>>();
+                self.in_progress[idx] = false;
+                Plist::Array(items?)
+            }
+            0xd => {
+                let (n, s) = self.length(marker, p)?;
+                let keys = self.refs(s, n)?;
+                let vals = self.refs(s + n * self.ref_size, n)?;
+                self.in_progress[idx] = true;
+                let entries = keys.into_iter().zip(vals).map(|(k, v)| {
+                    match self.child(k, depth)? {
+                        Plist::String(k) => Some((k, self.child(v, depth)?)),
+                        _ => None,
+                    }
+                }).collect::<Option<Vec<_>>>();
+                self.in_progress[idx] = false;
+                Plist::Dict(entries?)
+            }
+            _ => return None,
+        };
+        Some(v)
+    }
+
+    fn child(&mut self, r: usize, depth: usize) -> Option<Plist> {
+        if r >= self.offsets.len() { return None; }
+        self.object(r, depth + 1)
+    }
+}
+
+fn parse_binary(data: &[u8]) -> Option<Plist> {
+    if data.len() < 8 + 32 { return None; }
+    let t = &data[data.len() - 32..];
+    let offset_size = t[6] as usize;
+    let ref_size = t[7] as usize;
+    let num_objects = usize::try_from(be_uint(&t[8..16])).ok()?;
+    let top = usize::try_from(be_uint(&t[16..24])).ok()?;
+    let table = usize::try_from(be_uint(&t[24..32])).ok()?;
+    if !(1..=8).contains(&offset_size) || !(1..=8).contains(&ref_size) || top >= num_objects { return None; }
+    let table_end = table.checked_add(num_objects.checked_mul(offset_size)?)?;
+    if table_end > data.len() - 32 { return None; }
+    let offsets: Vec<usize> = data[table..table_end].chunks_exact(offset_size).map(|c| be_uint(c) as usize).collect();
+    let mut b = Binary { data, offsets, ref_size, nodes: 0, in_progress: vec![false; num_objects] };
+    b.object(top, 0)
+}
+
+// --- XML ---
+
+struct Xml<'a> {
+    s: &'a [u8],
+    p: usize,
+}
+
+struct Tag<'a> {
+    name: &'a [u8],
+    closing: bool,
+    empty: bool,
+}
+
+/// "2024-01-15T10:30:00Z" -> Unix seconds.
+fn parse_iso_date(s: &str) -> Option<f64> {
+    let s = s.trim();
+    let n = |r: std::ops::Range<usize>| -> Option<u32> { s.get(r)?.parse().ok() };
+    if s.len() < 10 || s.as_bytes()[4] != b'-' || s.as_bytes()[7] != b'-' { return None; }
+    let (hh, mm, ss) = if s.len() >= 19 { (n(11..13)?, n(14..16)?, n(17..19)?) } else { (0, 0, 0) };
+    Some(components_to_unix(n(0..4)? as i64, n(5..7)?, n(8..10)?, hh, mm, ss) as f64)
+}
+
+impl<'a> Xml<'a> {
+    fn rest(&self) -> &'a [u8] { &self.s[self.p..] }
+
+    /// Skip whitespace, XML declarations, DOCTYPE and comments.
+    fn skip_misc(&mut self) -> Option<()> {
+        loop {
+            while self.p < self.s.len() && self.s[self.p].is_ascii_whitespace() { self.p += 1; }
+            let r = self.rest();
+            let end: &[u8] = if r.starts_with(b"<?") { b"?>" } else if r.starts_with(b"<!--") { b"-->" } else if r.starts_with(b"<!") { b">" } else { return Some(()) };
+            self.p += find(r, end)? + end.len();
+        }
+    }
+
+    fn tag(&mut self) -> Option<Tag<'a>> {
+        self.skip_misc()?;
+        let r = self.rest();
+        if r.first() != Some(&b'<') { return None; }
+        let end = find(r, b">")?;
+        let inner = &r[1..end];
+        self.p += end + 1;
+        let closing = inner.first() == Some(&b'/');
+        let empty = inner.last() == Some(&b'/');
+        let body = inner.get(closing as usize..inner.len().saturating_sub(empty as usize))?;
+        let name_end = body.iter().position(|b| b.is_ascii_whitespace()).unwrap_or(body.len());
+        Some(Tag { name: &body[..name_end], closing, empty })
+    }
+
+    fn expect_close(&mut self, name: &[u8]) -> Option<()> {
+        let t = self.tag()?;
+        (t.closing && t.name == name).then_some(())
+    }
+
+    /// Character data up to `</name>`, with CDATA sections and entities resolved.
+    fn text(&mut self, name: &[u8]) -> Option<String> {
+        let mut out = String::new();
+        loop {
+            let r = self.rest();
+            let lt = r.iter().position(|&b| b == b'<')?;
+            out.push_str(&decode_entities(&r[..lt]));
+            self.p += lt;
+            if self.rest().starts_with(b"<![CDATA[") {
+                let r = &self.rest()[9..];
+                let end = find(r, b"]]>")?;
+                out.push_str(&String::from_utf8_lossy(&r[..end]));
+                self.p += 9 + end + 3;
+                continue;
+            }
+            self.expect_close(name)?;
+            return Some(out);
+        }
+    }
+
+    fn at_close(&mut self) -> Option<bool> {
+        self.skip_misc()?;
+        Some(self.rest().starts_with(b"</"))
+    }
+
+    fn value(&mut self, depth: usize) -> Option<Plist> {
+        if depth > MAX_DEPTH { return None; }
+        let t = self.tag()?;
+        if t.closing { return None; }
+        let text = |x: &mut Self| if t.empty { Some(String::new()) } else { x.text(t.name) };
+        Some(match t.name {
+            b"dict" => {
+                let mut entries = Vec::new();
+                if !t.empty {
+                    while !self.at_close()? {
+                        let k = self.tag()?;
+                        if k.name != b"key" || k.closing { return None; }
+                        let key = if k.empty { String::new() } else { self.text(b"key")? };
+                        entries.push((key, self.value(depth + 1)?));
+                    }
+                    self.expect_close(b"dict")?;
+                }
+                Plist::Dict(entries)
+            }
+            b"array" => {
+                let mut items = Vec::new();
+                if !t.empty {
+                    while !self.at_close()? { items.push(self.value(depth + 1)?); }
+                    self.expect_close(b"array")?;
+                }
+                Plist::Array(items)
+            }
+            b"true" | b"false" => {
+                if !t.empty { self.expect_close(t.name)?; }
+                Plist::Bool(t.name == b"true")
+            }
+            b"string" => Plist::String(text(self)?),
+            b"integer" => {
+                let s = text(self)?;
+                let s = s.trim();
+                Plist::Int(s.parse::<i64>().ok().or_else(|| s.parse::<u64>().ok().map(|u| u as i64))?)
+            }
+            b"real" => Plist::Real(text(self)?.trim().parse().ok()?),
+            b"date" => Plist::Date(parse_iso_date(&text(self)?)?),
+            b"data" => Plist::Data(crate::base64::decode(text(self)?.as_bytes())?),
+            _ => return None,
+        })
+    }
+}
+
+fn parse_xml(data: &[u8]) -> Option<Plist> {
+    let data = data.strip_prefix(b"\xef\xbb\xbf").unwrap_or(data);
+    let mut x = Xml { s: data, p: 0 };
+    x.skip_misc()?;
+    if !x.rest().starts_with(b"<plist") { return x.value(0); }
+    let t = x.tag()?;
+    if t.empty { return None; }
+    let v = x.value(0)?;
+    x.expect_close(b"plist")?;
+    Some(v)
+}
+
+// --- NSKeyedArchiver ---
+
+/// 16 bytes as an uppercase hyphenated UUID, as CFUUID prints it.
+pub fn uuid_string(b: &[u8]) -> String {
+    let h = crate::hash::to_hex(b).to_uppercase();
+    format!("{}-{}-{}-{}-{}", &h[..8], &h[8..12], &h[12..16], &h[16..20], &h[20..])
+}
+
+struct Unarchiver<'a> {
+    objects: &'a [Plist],
+    nodes: usize,
+}
+
+/// Object reference: a bplist UID, or its XML spelling <dict><key>CF$UID</key>...
+fn uid(p: &Plist) -> Option<usize> {
+    match p {
+        Plist::Uid(u) => Some(*u as usize),
+        Plist::Dict(d) if d.len() == 1 && d[0].0 == "CF$UID" => d[0].1.as_int().map(|i| i as usize),
+        _ => None,
+    }
+}
+
+impl Unarchiver<'_> {
+    /// None for $null and unresolvable references.
+    fn value(&mut self, p: &Plist, depth: usize) -> Option<Plist> {
+        self.nodes += 1;
+        if depth > MAX_DEPTH || self.nodes > MAX_NODES { return None; }
+        if let Some(i) = uid(p) {
+            let obj = self.objects.get(i)?;
+            return if obj.as_str() == Some("$null") { None } else { self.value(obj, depth + 1) };
+        }
+        match p {
+            Plist::Array(a) => Some(Plist::Array(a.iter().filter_map(|v| self.value(v, depth + 1)).collect())),
+            Plist::Dict(d) => match p.get("$class") {
+                Some(class) => self.instance(p, class, depth),
+                None => Some(Plist::Dict(d.iter().filter_map(|(k, v)| Some((k.clone(), self.value(v, depth + 1)?))).collect())),
+            },
+            _ => Some(p.clone()),
+        }
+    }
+
+    fn instance(&mut self, p: &Plist, class: &Plist, depth: usize) -> Option<Plist> {
+        let class_name = uid(class).and_then(|i| self.objects.get(i)).and_then(|c| c.get("$classname")).and_then(Plist::as_str).unwrap_or("");
+        if let (Some(keys), Some(values)) = (p.get("NS.keys").and_then(Plist::as_array), p.get("NS.objects").and_then(Plist::as_array)) {
+            let mut d = Vec::new();
+            for (k, v) in keys.iter().zip(values) {
+                let Some(Plist::String(k)) = self.value(k, depth + 1) else { continue };
+                if let Some(v) = self.value(v, depth + 1) { d.push((k, v)); }
+            }
+            return Some(Plist::Dict(d));
+        }
+        if let Some(items) = p.get("NS.objects") { return self.value(items, depth + 1); }
+        if let Some(s) = p.get("NS.string") { return self.value(s, depth + 1); }
+        if let Some(b) = p.get("NS.bytes").or_else(|| p.get("NS.data")) { return self.value(b, depth + 1); }
+        if let Some(Plist::Real(t)) = p.get("NS.time") { return Some(Plist::Date(t + CF_EPOCH)); }
+        if let Some(rel) = p.get("NS.relative") {
+            let rel = self.value(rel, depth + 1)?;
+            return match p.get("NS.base").and_then(|b| self.value(b, depth + 1)) {
+                Some(Plist::String(base)) => Some(Plist::String(format!("{}{}", base, rel.as_str()?))),
+                _ => Some(rel),
+            };
+        }
+        if let Some(b) = p.get("NS.uuidbytes").and_then(Plist::as_data).filter(|b| b.len() == 16) {
+            return Some(Plist::String(uuid_string(b)));
+        }
+        // Any other class: its encoded fields, plus the class name under "$class"
+        let Plist::Dict(fields) = p else { return None };
+        let mut d: Vec<(String, Plist)> = fields.iter().filter(|(k, _)| k != "$class")
+            .filter_map(|(k, v)| Some((k.clone(), self.value(v, depth + 1)?))).collect();
+        d.push(("$class".into(), Plist::String(class_name.into())));
+        Some(Plist::Dict(d))
+    }
+}
+
+/// Resolve an NSKeyedArchiver plist into a plain tree: dictionaries, arrays and sets
+/// become Dict/Array, NSString/NSData/NSDate/NSURL/NSUUID their plain values, and other
+/// objects a Dict of their encoded fields with the class name under "$class".
+/// Returns the root object, or None if this is not a keyed archive.
+pub fn unarchive(archive: &Plist) -> Option<Plist> {
+    let objects = archive.get("$objects")?.as_array()?;
+    let root = archive.get("$top")?.get("root")?;
+    Unarchiver { objects, nodes: 0 }.value(root, 0)
+}
+
+// --- FFI entry points ---
+
+fn node<'a>(n: *const Plist) -> Option<&'a Plist> {
+    if n.is_null() { None } else { Some(unsafe { &*n }) }
+}
+
+/// Parse a binary or XML plist. Returns the root node (null on parse/arg error);
+/// every node pointer obtained from it stays valid until iris_plist_free(root).
+#[no_mangle]
+pub extern "C" fn iris_plist_parse(data: *const u8, len: usize) -> *mut Plist {
+    if data.is_null() || len == 0 { return std::ptr::null_mut(); }
+    let buf = unsafe { std::slice::from_raw_parts(data, len) };
+    match parse(buf) {
+        Some(p) => Box::into_raw(Box::new(p)),
+        None => std::ptr::null_mut(),
+    }
+}
+
+/// Free a tree returned by iris_plist_parse. Pass only the root.
+#[no_mangle]
+pub extern "C" fn iris_plist_free(root: *mut Plist) {
+    if root.is_null() { return; }
+    unsafe { drop(Box::from_raw(root)); }
+}
+
+/// PLIST_* type of a node, 0 for null.
+#[no_mangle]
+pub extern "C" fn iris_plist_type(n: *const Plist) -> u8 {
+    node(n).map_or(0, Plist::type_code)
+}
+
+/// Element count of an array or dict, else 0.
+#[no_mangle]
+pub extern "C" fn iris_plist_count(n: *const Plist) -> usize {
+    match node(n) {
+        Some(Plist::Array(a)) => a.len(),
+        Some(Plist::Dict(d)) => d.len(),
+        _ => 0,
+    }
+}
+
+/// Array element or dict value at `index`; null if out of range or not a container.
+#[no_mangle]
+pub extern "C" fn iris_plist_item(n: *const Plist, index: usize) -> *const Plist {
+    let item = match node(n) {
+        Some(Plist::Dict(d)) => d.get(index).map(|(_, v)| v),
+        Some(p) => p.as_array().and_then(|a| a.get(index)),
+        None => None,
+    };
+    item.map_or(std::ptr::null(), |v| v as *const Plist)
+}
+
+/// Dict key at `index` (UTF-8, not NUL-terminated); empty slice if absent.
+#[no_mangle]
+pub extern "C" fn iris_plist_key(n: *const Plist, index: usize) -> IrisSlice {
+    match node(n) {
+        Some(Plist::Dict(d)) => IrisSlice::from_bytes(d.get(index).map_or(&b""[..], |(k, _)| k.as_bytes())),
+        _ => IrisSlice::from_bytes(b""),
+    }
+}
+
+/// Dict value for a NUL-terminated key; null if missing or not a dict.
+#[no_mangle]
+pub extern "C" fn iris_plist_get(n: *const Plist, key: *const c_char) -> *const Plist {
+    if key.is_null() { return std::ptr::null(); }
+    let key = unsafe { CStr::from_ptr(key) }.to_string_lossy();
+    node(n).and_then(|p| p.get(&key)).map_or(std::ptr::null(), |v| v as *const Plist)
+}
+
+/// String contents (UTF-8) or data bytes; empty slice for other types.
+#[no_mangle]
+pub extern "C" fn iris_plist_bytes(n: *const Plist) -> IrisSlice {
+    let bytes = node(n).and_then(|p| p.as_str().map(str::as_bytes).or_else(|| p.as_data()));
+    IrisSlice::from_bytes(bytes.unwrap_or(b""))
+}
+
+/// Integer, UID or bool (0/1) value; 0 for other types.
+#[no_mangle]
+pub extern "C" fn iris_plist_int(n: *const Plist) -> i64 {
+    node(n).and_then(|p| p.as_int().or_else(|| p.as_bool().map(i64::from))).unwrap_or(0)
+}
+
+/// Real value, or a date as Unix seconds; 0.0 for other types.
+#[no_mangle]
+pub extern "C" fn iris_plist_real(n: *const Plist) -> f64 {
+    match node(n) {
+        Some(Plist::Real(r)) | Some(Plist::Date(r)) => *r,
+        Some(Plist::Int(i)) => *i as f64,
+        _ => 0.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn xml_launch_daemon() {
+        let xml = br#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key><string>com.example.agent &amp; co</string>
+    <!-- comment -->
+    <key>ProgramArguments</key>
+    <array><string>/usr/local/bin/agent</string><string><![CDATA[--flag <x>]]></string></array>
+    <key>RunAtLoad</key><true/>
+    <key>StartInterval</key><integer>3600</integer>
+    <key>Weight</key><real>0.5</real>
+    <key>Created</key><date>2001-01-01T00:00:00Z</date>
+    <key>Blob</key><data>
+        aGVsbG8=
+    </data>
+    <key>Empty</key><dict/>
+</dict>
+</plist>"#;
+        let p = parse(xml).unwrap();
+        assert_eq!(p.get("Label").and_then(Plist::as_str), Some("com.example.agent & co"));
+        let args = p.get("ProgramArguments").and_then(Plist::as_array).unwrap();
+        assert_eq!(args[1].as_str(), Some("--flag <x>"));
+        assert_eq!(p.get("RunAtLoad").and_then(Plist::as_bool), Some(true));
+        assert_eq!(p.get("StartInterval").and_then(Plist::as_int), Some(3600));
+        assert_eq!(p.get("Weight"), Some(&Plist::Real(0.5)));
+        assert_eq!(p.get("Created"), Some(&Plist::Date(CF_EPOCH)));
+        assert_eq!(p.get("Blob").and_then(Plist::as_data), Some(&b"hello"[..]));
+        assert_eq!(p.get("Empty"), Some(&Plist::Dict(vec![])));
+        assert!(parse(b"<plist><dict><key>a</key></dict></plist>").is_none());
+    }
+
+    /// { "Name": "Iris", "List": [1, true, "\u{e9}"], "When": date(0) }
+    fn sample_bplist() -> Vec<u8> {
+        let mut d = b"bplist00".to_vec();
+        let mut offsets = Vec::new();
+        let mut obj = |d: &mut Vec<u8>, bytes: &[u8]| { offsets.push(d.len() as u8); d.extend_from_slice(bytes); };
+        obj(&mut d, &[0xd3, 1, 2, 3, 4, 5, 6]);          // 0: dict, keys 1..3, values 4..6
+        obj(&mut d, b"\x54Name");                         // 1
+        obj(&mut d, b"\x54List");                         // 2
+        obj(&mut d, b"\x54When");                         // 3
+        obj(&mut d, b"\x54Iris");                         // 4
+        obj(&mut d, &[0xa3, 7, 8, 9]);                    // 5: array
+        obj(&mut d, &[0x33, 0, 0, 0, 0, 0, 0, 0, 0]);     // 6: date
+        obj(&mut d, &[0x10, 1]);                          // 7
+        obj(&mut d, &[0x09]);                             // 8
+        obj(&mut d, &[0x61, 0x00, 0xe9]);                 // 9: UTF-16 string
+        let table = d.len() as u64;
+        d.extend_from_slice(&offsets);
+        d.extend_from_slice(&[0, 0, 0, 0, 0, 0, 1, 1]);
+        d.extend_from_slice(&(offsets.len() as u64).to_be_bytes());
+        d.extend_from_slice(&0u64.to_be_bytes());
+        d.extend_from_slice(&table.to_be_bytes());
+        d
+    }
+
+    #[test]
+    fn binary_and_ffi_accessors() {
+        let d = sample_bplist();
+        let root = iris_plist_parse(d.as_ptr(), d.len());
+        assert!(!root.is_null());
+        assert_eq!((iris_plist_type(root), iris_plist_count(root)), (PLIST_DICT, 3));
+        let k = iris_plist_key(root, 0);
+        assert_eq!(unsafe { std::slice::from_raw_parts(k.ptr, k.len) }, b"Name");
+        let name = iris_plist_get(root, c"Name".as_ptr());
+        let s = iris_plist_bytes(name);
+        assert_eq!(unsafe { std::slice::from_raw_parts(s.ptr, s.len) }, b"Iris");
+        let list = iris_plist_get(root, c"List".as_ptr());
+        assert_eq!(iris_plist_int(iris_plist_item(list, 0)), 1);
+        assert_eq!(iris_plist_type(iris_plist_item(list, 1)), PLIST_BOOL);
+        assert_eq!(unsafe { &*iris_plist_item(list, 2) }.as_str(), Some("\u{e9}"));
+        assert!(iris_plist_item(list, 3).is_null());
+        assert_eq!(iris_plist_real(iris_plist_get(root, c"When".as_ptr())), CF_EPOCH);
+        iris_plist_free(root);
+    }
+
+    #[test]
+    fn xml_empty_closing_tag_rejected() {
+        let xml = b"<plist></></plist>";
+        assert!(parse(xml).is_none());
+        assert!(iris_plist_parse(xml.as_ptr(), xml.len()).is_null());
+    }
+
+    #[test]
+    fn binary_cycle_rejected() {
+        let mut d = sample_bplist();
+        d[36] = 0; // array's first element -> the root dict
+        assert!(parse(&d).is_none());
+    }
+
+    #[test]
+    fn keyed_archive() {
+        let uid = |i: u32| format!("<dict><key>CF$UID</key><integer>{}</integer></dict>", i);
+        let xml = format!(r#"<plist version="1.0"><dict>
+<key>$archiver</key><string>NSKeyedArchiver</string>
+<key>$top</key><dict><key>root</key>{u1}</dict>
+<key>$objects</key><array>
+  <string>$null</string>
+  <dict><key>NS.keys</key><array>{u2}{u3}{u9}</array><key>NS.objects</key><array>{u4}{u6}{u0}</array><key>$class</key>{u5}</dict>
+  <string>items</string>
+  <string>when</string>
+  <dict><key>NS.objects</key><array>{u7}{u0}</array><key>$class</key>{u8}</dict>
+  <dict><key>$classname</key><string>NSDictionary</string></dict>
+  <dict><key>NS.time</key><real>1.5</real><key>$class</key>{u8}</dict>
+  <dict><key>name</key>{u10}<key>url</key>{u11}<key>$class</key>{u12}</dict>
+  <dict><key>$classname</key><string>NSArray</string></dict>
+  <string>gone</string>
+  <string>Agent</string>
+  <dict><key>NS.base</key>{u0}<key>NS.relative</key>{u13}<key>$class</key>{u8}</dict>
+  <dict><key>$classname</key><string>ItemRecord</string></dict>
+  <string>file:///Applications/Agent.app/</string>
+</array></dict></plist>"#,
+            u0 = uid(0), u1 = uid(1), u2 = uid(2), u3 = uid(3), u4 = uid(4), u5 = uid(5), u6 = uid(6), u7 = uid(7),
+            u8 = uid(8), u9 = uid(9), u10 = uid(10), u11 = uid(11), u12 = uid(12), u13 = uid(13));
+        let root = unarchive(&parse(xml.as_bytes()).unwrap()).unwrap();
+        let items = root.get("items").and_then(Plist::as_array).unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].get("name").and_then(Plist::as_str), Some("Agent"));
+        assert_eq!(items[0].get("url").and_then(Plist::as_str), Some("file:///Applications/Agent.app/"));
+        assert_eq!(items[0].get("$class").and_then(Plist::as_str), Some("ItemRecord"));
+        assert_eq!(root.get("when"), Some(&Plist::Date(CF_EPOCH + 1.5)));
+        assert_eq!(root.get("gone"), None);
+        assert_eq!(unarchive(&Plist::Dict(vec![])), None);
+    }
+}
@@ -0,0 +1,215 @@
+//! Provisioning profiles (.mobileprovision / .provisionprofile): a CMS SignedData
+//! envelope around an XML plist describing team, entitlements and allowed devices.
+
+use crate::cms;
+use crate::ffi::{free_c_string_array, free_cstr, to_cstr, vec_to_c_string_array, IrisCStringArray};
+use crate::plist::{self, Plist};
+use crate::x509::{self, alloc_cert_array, free_cert_array, IrisX509Cert};
+use std::ffi::c_char;
+
+pub const PROVISION_TYPE_DEVELOPMENT: u8 = 1; // device list + get-task-allow
+pub const PROVISION_TYPE_AD_HOC: u8 = 2;      // device list, no get-task-allow
+pub const PROVISION_TYPE_ALL_DEVICES: u8 = 3; // ProvisionsAllDevices (enterprise / Developer ID)
+pub const PROVISION_TYPE_STORE: u8 = 4;       // no devices: App Store / TestFlight
+
+#[repr(C)]
+pub struct IrisProvisioningProfile {
+    pub name: *mut c_char,
+    pub uuid: *mut c_char,
+    pub team_id: *mut c_char,
+    pub team_name: *mut c_char,
+    pub app_id_name: *mut c_char,
+    pub application_identifier: *mut c_char, // "TEAMID.com.example.app", from the entitlements
+    pub platforms: IrisCStringArray,         // "iOS", "OSX", ...
+    pub profile_type: u8,                    // PROVISION_TYPE_*
+    pub creation_date: i64,                  // unix seconds
+    pub expiration_date: i64,
+    pub signing_time: i64,                   // CMS signingTime, 0 if absent
+    pub device_count: usize,                 // ProvisionedDevices entries
+    pub provisions_all_devices: bool,
+    pub get_task_allow: bool,
+    pub cms_digest_valid: bool,              // messageDigest matches the embedded plist
+    pub entitlements: *mut Plist,            // walk with iris_plist_*; owned by the profile, null if absent
+    pub signer_chain: *mut IrisX509Cert,     // CMS signer first, then its issuers
+    pub signer_chain_count: usize,
+    pub developer_certificates: *mut IrisX509Cert,
+    pub developer_certificates_count: usize,
+}
+
+fn string(p: &Plist, key: &str) -> String {
+    p.get(key).and_then(Plist::as_str).unwrap_or("").to_string()
+}
+
+fn date(p: &Plist, key: &str) -> i64 {
+    match p.get(key) { Some(Plist::Date(d)) => *d as i64, _ => 0 }
+}
+
+// --- FFI entry points ---
+
+/// Parse a provisioning profile. Returns 0=ok, -2=arg error or not a CMS-wrapped plist.
+/// Free with iris_provisioning_free.
+#[no_mangle]
+pub extern "C" fn iris_provisioning_parse(data: *const u8, len: usize, out: *mut IrisProvisioningProfile) -> i32 {
+    if data.is_null() || out.is_null() || len == 0 { return -2; }
+    let buf = unsafe { std::slice::from_raw_parts(data, len) };
+    let Some(sd) = cms::parse_signed_data(buf).filter(|sd| sd.content_type == cms::OID_DATA) else { return -2 };
+    let Some(mut root) = plist::parse(&sd.content) else { return -2 };
+
+    let team_id = root.get("TeamIdentifier").and_then(Plist::as_array)
+        .and_then(|a| a.first()).and_then(Plist::as_str).unwrap_or("").to_string();
+    let platforms = root.get("Platform").and_then(Plist::as_array).unwrap_or_default()
+        .iter().filter_map(Plist::as_str).map(str::to_string).collect();
+    let device_count = root.get("ProvisionedDevices").and_then(Plist::as_array).map_or(0, |d| d.len());
+    let provisions_all_devices = root.get("ProvisionsAllDevices").and_then(Plist::as_bool).unwrap_or(false);
+    let dev_der: Vec<&[u8]> = root.get("DeveloperCertificates").and_then(Plist::as_array).unwrap_or_default()
+        .iter().filter_map(Plist::as_data).collect();
+    let developer: Vec<_> = dev_der.into_iter().filter_map(x509::parse).collect();
+    let (developer_certificates, developer_certificates_count) = alloc_cert_array(&developer.iter().collect::<Vec<_>>());
+
+    let entitlements = match &mut root {
+        Plist::Dict(d) => d.iter().position(|(k, _)| k == "Entitlements").map(|i| d.remove(i).1),
+        _ => None,
+    };
+    let ent = entitlements.as_ref();
+    let application_identifier = ent.map(|e| {
+        let id = string(e, "application-identifier");
+        if id.is_empty() { string(e, "com.apple.application-identifier") } else { id }
+    }).unwrap_or_default();
+    let get_task_allow = ent.and_then(|e| e.get("get-task-allow").or_else(|| e.get("com.apple.security.get-task-allow")))
+        .and_then(Plist::as_bool).unwrap_or(false);
+    let profile_type = if provisions_all_devices {
+        PROVISION_TYPE_ALL_DEVICES
+    } else if device_count > 0 {
+        if get_task_allow { PROVISION_TYPE_DEVELOPMENT } else { PROVISION_TYPE_AD_HOC }
+    } else {
+        PROVISION_TYPE_STORE
+    };
+
+    let (signer_chain, signer_chain_count) = alloc_cert_array(&sd.signer_chain());
+
+    unsafe {
+        out.write(IrisProvisioningProfile {
+            name: to_cstr(&string(&root, "Name")),
+            uuid: to_cstr(&string(&root, "UUID")),
+            team_id: to_cstr(&team_id),
+            team_name: to_cstr(&string(&root, "TeamName")),
+            app_id_name: to_cstr(&string(&root, "AppIDName")),
+            application_identifier: to_cstr(&application_identifier),
+            platforms: vec_to_c_string_array(platforms),
+            profile_type,
+            creation_date: date(&root, "CreationDate"),
+            expiration_date: date(&root, "ExpirationDate"),
+            signing_time: sd.signers.first().and_then(|s| s.signing_time).unwrap_or(0),
+            device_count,
+            provisions_all_devices,
+            get_task_allow,
+            cms_digest_valid: sd.digest_matches(),
+            entitlements: entitlements.map_or(std::ptr::null_mut(), |e| Box::into_raw(Box::new(e))),
+            signer_chain, signer_chain_count,
+            developer_certificates, developer_certificates_count,
+        });
+    }
+    0
+}
+
+/// Free a profile filled by iris_provisioning_parse, including its entitlements tree.
+#[no_mangle]
+pub extern "C" fn iris_provisioning_free(p: *mut IrisProvisioningProfile) {
+    if p.is_null() { return; }
+    unsafe {
+        let p = &*p;
+        for s in [p.name, p.uuid, p.team_id, p.team_name, p.app_id_name, p.application_identifier] { free_cstr(s); }
+        free_c_string_array(&p.platforms);
+        if !p.entitlements.is_null() { drop(Box::from_raw(p.entitlements)); }
+        free_cert_array(p.signer_chain, p.signer_chain_count);
+        free_cert_array(p.developer_certificates, p.developer_certificates_count);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base64::encode;
+    use crate::hash::sha256_digest;
+    use crate::x509::tests::{cert, name, tlv, CN, OU};
+
+    fn cstr(p: *mut c_char) -> String {
+        unsafe { std::ffi::CStr::from_ptr(p) }.to_string_lossy().into_owned()
+    }
+
+    /// BER indefinite-length wrapper, as Apple's signing tools emit.
+    fn indef(tag: u8, content: &[u8]) -> Vec<u8> {
+        [&[tag, 0x80][..], content, &[0, 0]].concat()
+    }
+
+    fn profile(xml: &[u8], digest: [u8; 32]) -> Vec<u8> {
+        let root_name = name(&[(CN, "Apple Root CA")]);
+        let wwdr_name = name(&[(CN, "Apple iPhone Certification Authority")]);
+        let signer_name = name(&[(CN, "Apple iPhone OS Provisioning Profile Signing")]);
+        let certs = [cert(1, &root_name, &root_name), cert(7, &signer_name, &wwdr_name), cert(3, &wwdr_name, &root_name)].concat();
+        let sha256 = tlv(0x30, &tlv(0x06, &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01]));
+        let oid_data = tlv(0x06, &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 1, 7, 1]);
+        let attrs = [
+            tlv(0x30, &[tlv(0x06, &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 1, 9, 5]), tlv(0x31, &tlv(0x17, b"240301120000Z"))].concat()),
+            tlv(0x30, &[tlv(0x06, &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 1, 9, 4]), tlv(0x31, &tlv(0x04, &digest))].concat()),
+        ].concat();
+        let sid = tlv(0x30, &[wwdr_name.clone(), tlv(0x02, &[7])].concat());
+        let signer = tlv(0x30, &[tlv(0x02, &[1]), sid, sha256.clone(), tlv(0xa0, &attrs), tlv(0x30, &[]), tlv(0x04, &[0; 4])].concat());
+        // eContent as a constructed OCTET STRING split into two chunks
+        let (a, b) = xml.split_at(xml.len() / 2);
+        let encap = indef(0x30, &[oid_data, indef(0xa0, &indef(0x24, &[tlv(0x04, a), tlv(0x04, b)].concat()))].concat());
+        let sd = indef(0x30, &[tlv(0x02, &[1]), tlv(0x31, &sha256), encap, tlv(0xa0, &certs), tlv(0x31, &signer)].concat());
+        indef(0x30, &[tlv(0x06, &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 1, 7, 2]), indef(0xa0, &sd)].concat())
+    }
+
+    #[test]
+    fn development_profile() {
+        let dev = cert(9, &name(&[(CN, "Apple Development: Jane Doe (ABCDE12345)"), (OU, "TEAM123456")]),
+                       &name(&[(CN, "Apple Worldwide Developer Relations")]));
+        let xml = format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0"><dict>
+<key>AppIDName</key><string>Example</string>
+<key>CreationDate</key><date>2024-03-01T12:00:00Z</date>
+<key>DeveloperCertificates</key><array><data>{}</data></array>
+<key>Entitlements</key><dict>
+  <key>application-identifier</key><string>TEAM123456.com.example.app</string>
+  <key>get-task-allow</key><true/>
+</dict>
+<key>ExpirationDate</key><date>2025-03-01T12:00:00Z</date>
+<key>Name</key><string>Example Dev</string>
+<key>Platform</key><array><string>iOS</string></array>
+<key>ProvisionedDevices</key><array><string>00008030-001A</string><string>00008101-002B</string></array>
+<key>TeamIdentifier</key><array><string>TEAM123456</string></array>
+<key>TeamName</key><string>Example Corp</string>
+<key>UUID</key><string>0f1e2d3c-aaaa-bbbb-cccc-123456789abc</string>
+</dict></plist>"#, encode(&dev));
+        let data = profile(xml.as_bytes(), sha256_digest(xml.as_bytes()));
+
+        let mut out = std::mem::MaybeUninit::<IrisProvisioningProfile>::uninit();
+        assert_eq!(iris_provisioning_parse(data.as_ptr(), data.len(), out.as_mut_ptr()), 0);
+        let mut p = unsafe { out.assume_init() };
+        assert_eq!(cstr(p.team_id), "TEAM123456");
+        assert_eq!(cstr(p.application_identifier), "TEAM123456.com.example.app");
+        assert_eq!((cstr(p.name), cstr(p.team_name)), ("Example Dev".into(), "Example Corp".into()));
+        assert_eq!((p.profile_type, p.device_count, p.get_task_allow), (PROVISION_TYPE_DEVELOPMENT, 2, true));
+        assert_eq!((p.creation_date, p.expiration_date, p.signing_time), (1709294400, 1740830400, 1709294400));
+        assert!(p.cms_digest_valid);
+        assert_eq!(unsafe { &*p.entitlements }.get("get-task-allow"), Some(&Plist::Bool(true)));
+        let chain = unsafe { std::slice::from_raw_parts(p.signer_chain, p.signer_chain_count) };
+        let serials: Vec<String> = chain.iter().map(|c| cstr(c.serial)).collect();
+        assert_eq!(serials, ["07", "03", "01"]);
+        assert!(chain[2].self_signed);
+        assert_eq!(p.developer_certificates_count, 1);
+        assert_eq!(cstr(unsafe { &*p.developer_certificates }.organizational_unit), "TEAM123456");
+        iris_provisioning_free(&mut p);
+
+        let tampered = profile(xml.as_bytes(), [0; 32]);
+        let mut out = std::mem::MaybeUninit::<IrisProvisioningProfile>::uninit();
+        assert_eq!(iris_provisioning_parse(tampered.as_ptr(), tampered.len(), out.as_mut_ptr()), 0);
+        let mut p = unsafe { out.assume_init() };
+        assert!(!p.cms_digest_valid);
+        iris_provisioning_free(&mut p);
+        let mut out = std::mem::MaybeUninit::<IrisProvisioningProfile>::uninit();
+        assert_eq!(iris_provisioning_parse(xml.as_ptr(), xml.len(), out.as_mut_ptr()), -2);
+    }
+}
@@ -0,0 +1,115 @@
+//! LaunchServices quarantine database (~/Library/Preferences/
+//! com.apple.LaunchServices.QuarantineEventsV2): one row per quarantined download.
+
+use crate::ffi::{alloc_array, free_array, free_cstr, to_cstr};
+use crate::plist::CF_EPOCH;
+use crate::sqlite::{Database, Row, Table};
+use std::ffi::{c_char, CStr};
+
+const EVENTS_TABLE: &str = "LSQuarantineEvent";
+
+#[repr(C)]
+pub struct IrisQuarantineEvent {
+    pub uuid: *mut c_char,             // LSQuarantineEventIdentifier, matches the xattr's UUID field
+    pub timestamp: f64,                // unix seconds
+    pub agent_bundle_id: *mut c_char,  // downloading application, e.g. "com.apple.Safari"
+    pub agent_name: *mut c_char,
+    pub data_url: *mut c_char,         // URL the file itself came from
+    pub origin_url: *mut c_char,       // referring page
+    pub origin_title: *mut c_char,
+    pub sender_name: *mut c_char,      // mail / message attachments
+    pub sender_address: *mut c_char,
+    pub type_number: i32,              // LSQuarantineType*: 0 web download, 2 email attachment, ...
+}
+
+#[repr(C)]
+pub struct IrisQuarantineEvents {
+    pub events: *mut IrisQuarantineEvent,
+    pub count: usize,
+}
+
+fn event(t: &Table, r: &Row) -> IrisQuarantineEvent {
+    let s = |col: &str| to_cstr(r.get(t.column(col)).as_str());
+    IrisQuarantineEvent {
+        uuid: s("LSQuarantineEventIdentifier"),
+        timestamp: r.get(t.column("LSQuarantineTimeStamp")).as_real().map_or(0.0, |ts| ts + CF_EPOCH),
+        agent_bundle_id: s("LSQuarantineAgentBundleIdentifier"),
+        agent_name: s("LSQuarantineAgentName"),
+        data_url: s("LSQuarantineDataURLString"),
+        origin_url: s("LSQuarantineOriginURLString"),
+        origin_title: s("LSQuarantineOriginTitle"),
+        sender_name: s("LSQuarantineSenderName"),
+        sender_address: s("LSQuarantineSenderAddress"),
+        type_number: r.get(t.column("LSQuarantineTypeNumber")).as_int().unwrap_or(-1) as i32,
+    }
+}
+
+// --- FFI entry points ---
+
+/// Read all download events from a QuarantineEventsV2 database file.
+/// Returns 0=ok, -1=file error, -2=arg error or not a quarantine database.
+/// Free with iris_quarantine_free.
+#[no_mangle]
+pub extern "C" fn iris_quarantine_parse(path: *const c_char, out: *mut IrisQuarantineEvents) -> i32 {
+    if path.is_null() || out.is_null() { return -2; }
+    let Ok(path) = unsafe { CStr::from_ptr(path) }.to_str() else { return -2 };
+    let db = match Database::open_path(path) { Ok(db) => db, Err(e) => return e };
+    let Some(t) = db.table(EVENTS_TABLE) else { return -2 };
+    let events: Vec<_> = db.rows(&t).iter().map(|r| event(&t, r)).collect();
+    let (events, count) = alloc_array(events);
+    unsafe { out.write(IrisQuarantineEvents { events, count }); }
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn iris_quarantine_free(events: *mut IrisQuarantineEvents) {
+    if events.is_null() { return; }
+    let e = unsafe { &*events };
+    for i in 0..e.count {
+        let ev = unsafe { &*e.events.add(i) };
+        for p in [ev.uuid, ev.agent_bundle_id, ev.agent_name, ev.data_url, ev.origin_url,
+                  ev.origin_title, ev.sender_name, ev.sender_address] { free_cstr(p); }
+    }
+    free_array(e.events, e.count);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sqlite::tests::database;
+    use crate::sqlite::Value;
+
+    #[test]
+    fn download_events() {
+        let sql = "CREATE TABLE LSQuarantineEvent (  LSQuarantineEventIdentifier TEXT PRIMARY KEY NOT NULL,  \
+                   LSQuarantineTimeStamp REAL,  LSQuarantineAgentBundleIdentifier TEXT,  LSQuarantineAgentName TEXT,  \
+                   LSQuarantineDataURLString TEXT,  LSQuarantineSenderName TEXT,  LSQuarantineSenderAddress TEXT,  \
+                   LSQuarantineTypeNumber INTEGER,  LSQuarantineOriginTitle TEXT,  LSQuarantineOriginURLString TEXT,  \
+                   LSQuarantineOriginAlias BLOB )";
+        let t = |s: &str| Value::Text(s.into());
+        let row = vec![
+            t("5D3A1B2C-0000-4000-8000-000000000001"), Value::Real(700_000_000.5), t("com.google.Chrome"), t("Chrome"),
+            t("https://cdn.example.invalid/Installer.dmg"), Value::Null, Value::Null, Value::Int(0),
+            Value::Null, t("https://example.invalid/download"), Value::Null,
+        ];
+        let db = database(&[("CREATE TABLE other (x)", vec![]), (sql, vec![row])]);
+        let path = std::env::temp_dir().join(format!("iris-quarantine-{}.db", std::process::id()));
+        std::fs::write(&path, &db).unwrap();
+        let cpath = std::ffi::CString::new(path.to_str().unwrap()).unwrap();
+
+        let mut out = std::mem::MaybeUninit::<IrisQuarantineEvents>::uninit();
+        assert_eq!(iris_quarantine_parse(cpath.as_ptr(), out.as_mut_ptr()), 0);
+        std::fs::remove_file(&path).unwrap();
+        let mut events = unsafe { out.assume_init() };
+        assert_eq!(events.count, 1);
+        let e = unsafe { &*events.events };
+        let cs = |p: *mut c_char| unsafe { CStr::from_ptr(p) }.to_string_lossy().into_owned();
+        assert_eq!(cs(e.agent_bundle_id), "com.google.Chrome");
+        assert_eq!((cs(e.data_url), cs(e.origin_url)), ("https://cdn.example.invalid/Installer.dmg".into(), "https://example.invalid/download".into()));
+        assert_eq!((e.timestamp, e.type_number, cs(e.sender_name)), (1_678_307_200.5, 0, String::new()));
+        iris_quarantine_free(&mut events);
+
+        let mut out = std::mem::MaybeUninit::<IrisQuarantineEvents>::uninit();
+        assert_eq!(iris_quarantine_parse(cpath.as_ptr(), out.as_mut_ptr()), -1);
+    }
+}
@@ -0,0 +1,406 @@
+//! QUIC Initial packets (RFC 9000 §17.2.2, RFC 9001 §5): derive the Initial keys
+//! from the client's Destination Connection ID, remove header protection, decrypt
+//! the payload and reassemble the CRYPTO frames into the TLS ClientHello. Initial
+//! keys are public, so this is what lets a monitor see the SNI and ALPN of HTTP/3.
+
+use crate::aes::{gcm_open, Aes128};
+use crate::ffi::{alloc_bytes, iris_free_bytes};
+use crate::hash::{hkdf_expand, hkdf_extract};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+pub const QUIC_V1: u32 = 1;
+pub const QUIC_V2: u32 = 0x6b33_43cf;
+
+const V1_SALT: [u8; 20] = [
+    0x38, 0x76, 0x2c, 0xf7, 0xf5, 0x59, 0x34, 0xb3, 0x4d, 0x17, 0x9a, 0xe6, 0xa4, 0xc8, 0x0c, 0xad, 0xcc, 0xbb, 0x7f, 0x0a,
+];
+const V2_SALT: [u8; 20] = [
+    0x0d, 0xed, 0xe3, 0xde, 0xf7, 0x00, 0xa6, 0xdb, 0x81, 0x93, 0x81, 0xbe, 0x6e, 0x26, 0x9d, 0xcb, 0xf9, 0xbd, 0x2e, 0xd9,
+];
+
+const MAX_CID: usize = 20;
+/// CRYPTO stream bytes buffered per connection; post-quantum key shares push a
+/// ClientHello past one datagram, but nowhere near this.
+const MAX_CRYPTO: u64 = 1 << 16;
+
+const FRAME_PADDING: u64 = 0x00;
+const FRAME_PING: u64 = 0x01;
+const FRAME_ACK: u64 = 0x02;
+const FRAME_ACK_ECN: u64 = 0x03;
+const FRAME_CRYPTO: u64 = 0x06;
+const FRAME_CLOSE: u64 = 0x1c;
+const FRAME_CLOSE_APP: u64 = 0x1d;
+
+#[repr(C)]
+pub struct IrisQuicInitial {
+    pub version: u32,
+    pub dcid: *mut u8,          // Destination Connection ID of the first packet
+    pub dcid_len: usize,
+    pub scid: *mut u8,
+    pub scid_len: usize,
+    pub packets: usize,         // Initial packets decrypted from this datagram
+    pub client_hello: *mut u8,  // whole handshake message, NULL until its last byte arrives
+    pub client_hello_len: usize,
+}
+
+pub struct IrisQuicInitials {
+    inner: Mutex<Initials>,
+}
+
+struct Keys {
+    aead: Aes128,
+    iv: [u8; 12],
+    hp: Aes128,
+}
+
+/// Decrypted Initial packets of one datagram.
+pub struct Datagram {
+    pub version: u32,
+    pub dcid: Vec<u8>,
+    pub scid: Vec<u8>,
+    pub packets: usize,
+    pub client_hello: Option<Vec<u8>>,
+}
+
+/// The client's Initial packets on one connection. The keys come from the first
+/// packet's Destination Connection ID and stay fixed even after the client adopts
+/// the server's choice of connection ID.
+#[derive(Default)]
+pub struct Initials {
+    keys: Option<(u32, Keys)>,
+    chunks: BTreeMap<u64, Vec<u8>>,
+    crypto: Vec<u8>,   // contiguous CRYPTO stream from offset 0
+    done: bool,
+}
+
+/// QUIC variable-length integer (RFC 9000 §16).
+fn varint(d: &[u8], p: &mut usize) -> Option<u64> {
+    let first = *d.get(*p)?;
+    let len = 1 << (first >> 6);
+    let bytes = d.get(*p..*p + len)?;
+    *p += len;
+    Some(bytes[1..].iter().fold((first & 0x3f) as u64, |v, &b| v << 8 | b as u64))
+}
+
+/// HKDF-Expand-Label (RFC 8446 §7.1) with an empty context.
+fn expand_label(secret: &[u8], label: &str, len: usize) -> Vec<u8> {
+    let full = format!("tls13 {}", label);
+    let info = [&(len as u16).to_be_bytes()[..], &[full.len() as u8], full.as_bytes(), &[0]].concat();
+    hkdf_expand(secret, &info, len)
+}
+
+fn client_keys(version: u32, dcid: &[u8]) -> Option<Keys> {
+    let (salt, prefix) = match version {
+        QUIC_V1 => (&V1_SALT, "quic"),
+        QUIC_V2 => (&V2_SALT, "quicv2"),
+        _ => return None,
+    };
+    let secret = expand_label(&hkdf_extract(salt, dcid), "client in", 32);
+    let key = |l: &str, n| expand_label(&secret, &format!("{} {}", prefix, l), n);
+    Some(Keys {
+        aead: Aes128::new(&key("key", 16).try_into().ok()?),
+        iv: key("iv", 12).try_into().ok()?,
+        hp: Aes128::new(&key("hp", 16).try_into().ok()?),
+    })
+}
+
+/// Long header packet types are numbered differently in each version.
+fn is_initial(version: u32, ty: u8) -> bool {
+    ty == if version == QUIC_V2 { 1 } else { 0 }
+}
+
+fn is_retry(version: u32, ty: u8) -> bool {
+    ty == if version == QUIC_V2 { 0 } else { 3 }
+}
+
+/// The CRYPTO frames of a decrypted Initial payload. None on a malformed frame or
+/// one not allowed in Initial packets.
+fn crypto_frames(d: &[u8]) -> Option<Vec<(u64, &[u8])>> {
+    let mut frames = Vec::new();
+    let mut p = 0;
+    while p < d.len() {
+        match varint(d, &mut p)? {
+            FRAME_PADDING | FRAME_PING => {}
+            ty @ (FRAME_ACK | FRAME_ACK_ECN) => {
+                varint(d, &mut p)?; // largest acknowledged
+                varint(d, &mut p)?; // delay
+                let ranges = varint(d, &mut p)?;
+                varint(d, &mut p)?; // first range
+                let extra = if ty == FRAME_ACK_ECN { 3 } else { 0 };
+                for _ in 0..ranges.checked_mul(2)?.checked_add(extra)? { varint(d, &mut p)?; }
+            }
+            FRAME_CRYPTO => {
+                let offset = varint(d, &mut p)?;
+                let len = varint(d, &mut p)? as usize;
+                frames.push((offset, d.get(p..p.checked_add(len)?)?));
+                p += len;
+            }
+            ty @ (FRAME_CLOSE | FRAME_CLOSE_APP) => {
+                varint(d, &mut p)?; // error code
+                if ty == FRAME_CLOSE { varint(d, &mut p)?; } // frame type
+                let len = varint(d, &mut p)? as usize;
+                p = p.checked_add(len).filter(|&e| e <= d.len())?;
+            }
+            _ => return None,
+        }
+    }
+    Some(frames)
+}
+
+impl Initials {
+    /// Decrypt the Initial packets of a client datagram (coalesced Handshake and
+    /// 0-RTT packets are skipped) and add their CRYPTO data. Err(-2) if malformed,
+    /// Err(-3) if it holds no Initial packet of a supported version or one fails
+    /// to decrypt.
+    pub fn feed(&mut self, d: &[u8]) -> Result<Datagram, i32> {
+        let mut out = Datagram { version: 0, dcid: Vec::new(), scid: Vec::new(), packets: 0, client_hello: None };
+        let mut p = 0;
+        while p < d.len() {
+            let pkt = &d[p..];
+            // Short header (1-RTT) packets run to the end of the datagram
+            if pkt[0] & 0x80 == 0 { break; }
+            let v = pkt.get(1..5).ok_or(-2)?;
+            let version = u32::from_be_bytes([v[0], v[1], v[2], v[3]]);
+            if !matches!(version, QUIC_V1 | QUIC_V2) { break; }
+            let mut o = 5;
+            let cid = |o: &mut usize| -> Result<&[u8], i32> {
+                let n = *pkt.get(*o).ok_or(-2)? as usize;
+                if n > MAX_CID { return Err(-2); }
+                let c = pkt.get(*o + 1..*o + 1 + n).ok_or(-2)?;
+                *o += 1 + n;
+                Ok(c)
+            };
+            let (dcid, scid) = (cid(&mut o)?, cid(&mut o)?);
+            let ty = pkt[0] >> 4 & 3;
+            if is_retry(version, ty) { break; }
+            if is_initial(version, ty) {
+                let token = varint(pkt, &mut o).ok_or(-2)? as usize;
+                o = o.checked_add(token).ok_or(-2)?;
+            }
+            let len = varint(pkt, &mut o).ok_or(-2)? as usize;
+            let end = o.checked_add(len).filter(|&e| e <= pkt.len()).ok_or(-2)?;
+            p += end;
+            if !is_initial(version, ty) { continue; }
+
+            if self.keys.is_none() { self.keys = client_keys(version, dcid).map(|k| (version, k)); }
+            let keys = match &self.keys {
+                Some((v, k)) if *v == version => k,
+                _ => return Err(-3),
+            };
+            let frames = decrypt(keys, &pkt[..end], o).ok_or(-3)?;
+            for (offset, data) in crypto_frames(&frames).ok_or(-2)? {
+                if offset + data.len() as u64 > MAX_CRYPTO { return Err(-2); }
+                let chunk = self.chunks.entry(offset).or_default();
+                if data.len() > chunk.len() { *chunk = data.to_vec(); }
+            }
+            if out.packets == 0 {
+                (out.version, out.dcid, out.scid) = (version, dcid.to_vec(), scid.to_vec());
+            }
+            out.packets += 1;
+        }
+        if out.packets == 0 { return Err(-3); }
+        self.assemble();
+        if !self.done {
+            if let Some(len) = self.message_len().filter(|&n| self.crypto.len() >= n) {
+                self.done = true;
+                out.client_hello = Some(self.crypto[..len].to_vec());
+            }
+        }
+        Ok(out)
+    }
+
+    /// Move chunks that now touch the contiguous prefix into it.
+    fn assemble(&mut self) {
+        while let Some(e) = self.chunks.first_entry() {
+            let have = self.crypto.len();
+            if *e.key() > have as u64 { break; }
+            let (offset, data) = e.remove_entry();
+            let skip = have - offset as usize;
+            if skip < data.len() { self.crypto.extend_from_slice(&data[skip..]); }
+        }
+    }
+
+    /// Size of the first handshake message, header included, once its header is in.
+    fn message_len(&self) -> Option<usize> {
+        let h = self.crypto.get(..4)?;
+        Some(4 + ((h[1] as usize) << 16 | (h[2] as usize) << 8 | h[3] as usize))
+    }
+}
+
+/// Unprotect and decrypt one Initial packet whose packet number starts at
+/// `pn_offset`. None if it is too short to sample or fails authentication.
+fn decrypt(keys: &Keys, pkt: &[u8], pn_offset: usize) -> Option<Vec<u8>> {
+    let mut mask: [u8; 16] = pkt.get(pn_offset + 4..pn_offset + 20)?.try_into().ok()?;
+    keys.hp.encrypt_block(&mut mask);
+    let first = pkt[0] ^ (mask[0] & 0x0f);
+    let pn_len = (first & 3) as usize + 1;
+    let mut header = pkt[..pn_offset + pn_len].to_vec();
+    header[0] = first;
+    for i in 0..pn_len { header[pn_offset + i] ^= mask[1 + i]; }
+    // The first Initial packets number from 0, so the truncated number is the full one
+    let pn = header[pn_offset..].iter().fold(0u64, |v, &b| v << 8 | b as u64);
+    let mut nonce = keys.iv;
+    for (n, b) in nonce[4..].iter_mut().zip(pn.to_be_bytes()) { *n ^= b; }
+    gcm_open(&keys.aead, &nonce, &header, &pkt[pn_offset + pn_len..])
+}
+
+fn write_initial(out: *mut IrisQuicInitial, d: Datagram) {
+    let (dcid, dcid_len) = alloc_bytes(&d.dcid);
+    let (scid, scid_len) = alloc_bytes(&d.scid);
+    let (client_hello, client_hello_len) = match &d.client_hello {
+        Some(h) => alloc_bytes(h),
+        None => (std::ptr::null_mut(), 0),
+    };
+    unsafe {
+        out.write(IrisQuicInitial {
+            version: d.version, dcid, dcid_len, scid, scid_len, packets: d.packets, client_hello, client_hello_len,
+        });
+    }
+}
+
+// --- FFI ---
+
+/// New Initial packet decryptor for the client side of one QUIC connection.
+/// Free with iris_quic_initials_free.
+#[no_mangle]
+pub extern "C" fn iris_quic_initials_new() -> *mut IrisQuicInitials {
+    Box::into_raw(Box::new(IrisQuicInitials { inner: Mutex::new(Initials::default()) }))
+}
+
+/// Feed a UDP datagram from the client. out.client_hello is set by the feed that
+/// completes the ClientHello, which can span datagrams; hand it to
+/// iris_tls_parse_client_hello. Returns 0=ok, -2=arg error or malformed, -3=no
+/// QUIC v1/v2 Initial packet, or one that fails to decrypt. Free with
+/// iris_quic_initial_free.
+#[no_mangle]
+pub extern "C" fn iris_quic_initials_feed(q: *mut IrisQuicInitials, data: *const u8, len: usize, out: *mut IrisQuicInitial) -> i32 {
+    if q.is_null() || data.is_null() || out.is_null() { return -2; }
+    let d = unsafe { std::slice::from_raw_parts(data, len) };
+    match unsafe { &*q }.inner.lock().unwrap_or_else(|e| e.into_inner()).feed(d) {
+        Ok(r) => { write_initial(out, r); 0 }
+        Err(e) => e,
+    }
+}
+
+/// Decrypt the Initial packets of a single client datagram. Returns 0=ok, with
+/// out.client_hello set, -1=the ClientHello continues in a later datagram (use
+/// iris_quic_initials_new), -2=arg error or malformed, -3=no QUIC v1/v2 Initial
+/// packet, or one that fails to decrypt. Free with iris_quic_initial_free.
+#[no_mangle]
+pub extern "C" fn iris_quic_parse_initial(data: *const u8, len: usize, out: *mut IrisQuicInitial) -> i32 {
+    if data.is_null() || out.is_null() { return -2; }
+    match Initials::default().feed(unsafe { std::slice::from_raw_parts(data, len) }) {
+        Ok(r) if r.client_hello.is_none() => -1,
+        Ok(r) => { write_initial(out, r); 0 }
+        Err(e) => e,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn iris_quic_initial_free(i: *mut IrisQuicInitial) {
+    if i.is_null() { return; }
+    let i = unsafe { &*i };
+    iris_free_bytes(i.dcid, i.dcid_len);
+    iris_free_bytes(i.scid, i.scid_len);
+    iris_free_bytes(i.client_hello, i.client_hello_len);
+}
+
+#[no_mangle]
+pub extern "C" fn iris_quic_initials_free(q: *mut IrisQuicInitials) {
+    if q.is_null() { return; }
+    drop(unsafe { Box::from_raw(q) });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aes::tests::gcm_seal;
+    use crate::hash::to_hex;
+
+    const DCID: [u8; 8] = [0x83, 0x94, 0xc8, 0xf0, 0x3e, 0x51, 0x57, 0x08];
+
+    /// RFC 9001 Appendix A.2: the CRYPTO frame carrying the client's ClientHello.
+    const RFC_CRYPTO: &str = "060040f1010000ed0303ebf8fa56f12939b9584a3896472ec40bb863cfd3e86804fe3a47f06a2b69484c0000041301130201\
+        0000c000000010000e00000b6578616d706c652e636f6dff01000100000a00080006001d0017001800100007000504616c706e0005000501\
+        00000000003300260024001d00209370b2c9caa47fbabaf4559fedba753de171fa71f50f1ce15d43e994ec74d748002b0003020304000d00\
+        10000e0403050306030203080408050806002d00020101001c00024001003900320408ffffffffffffffff05048000ffff07048000ffff08\
+        01100104800075300901100f088394c8f03e51570806048000ffff";
+
+    fn unhex(s: &str) -> Vec<u8> {
+        let s: String = s.split_whitespace().collect();
+        (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap()).collect()
+    }
+
+    /// A protected client Initial with a 4-byte packet number, padded to `size`.
+    fn initial(version: u32, pn: u32, frames: &[u8], size: usize) -> Vec<u8> {
+        let ty = if version == QUIC_V2 { 0xd3 } else { 0xc3 };
+        let head = [&[ty][..], &version.to_be_bytes(), &[DCID.len() as u8], &DCID, &[0], &[0]].concat();
+        let len = size - head.len() - 2;
+        let header = [&head[..], &(0x4000 | len as u16).to_be_bytes(), &pn.to_be_bytes()].concat();
+        let mut plain = frames.to_vec();
+        plain.resize(len - 4 - 16, 0);
+        let keys = client_keys(version, &DCID).unwrap();
+        let mut nonce = keys.iv;
+        for (n, b) in nonce[8..].iter_mut().zip(pn.to_be_bytes()) { *n ^= b; }
+        let mut pkt = [header.clone(), gcm_seal(&keys.aead, &nonce, &header, &plain)].concat();
+        let pn_offset = header.len() - 4;
+        let mut mask: [u8; 16] = pkt[pn_offset + 4..pn_offset + 20].try_into().unwrap();
+        keys.hp.encrypt_block(&mut mask);
+        pkt[0] ^= mask[0] & 0x0f;
+        for i in 0..4 { pkt[pn_offset + i] ^= mask[1 + i]; }
+        pkt
+    }
+
+    #[test]
+    fn rfc9001_client_initial() {
+        let keys = client_keys(QUIC_V1, &DCID).unwrap();
+        assert_eq!(to_hex(&keys.iv), "fa044b2f42a3fd3b46fb255c");
+        let pkt = initial(QUIC_V1, 2, &unhex(RFC_CRYPTO), 1200);
+        assert_eq!(to_hex(&pkt[..22]), "c000000001088394c8f03e5157080000449e7b9aec34");
+        assert_eq!(to_hex(&pkt[22..38]), "d1b1c98dd7689fb8ec11d242b123dc9b");
+
+        let mut out = std::mem::MaybeUninit::<IrisQuicInitial>::uninit();
+        assert_eq!(iris_quic_parse_initial(pkt.as_ptr(), pkt.len(), out.as_mut_ptr()), 0);
+        let mut r = unsafe { out.assume_init_read() };
+        let hello = unsafe { std::slice::from_raw_parts(r.client_hello, r.client_hello_len) };
+        assert_eq!(hello, &unhex(RFC_CRYPTO)[4..]);
+        assert_eq!(crate::tls::offered_version(hello), Some(0x0304));
+        assert_eq!((r.version, r.packets, unsafe { std::slice::from_raw_parts(r.dcid, r.dcid_len) }), (QUIC_V1, 1, &DCID[..]));
+        iris_quic_initial_free(&mut r);
+
+        let mut bad = pkt.clone();
+        bad[500] ^= 1;
+        assert_eq!(iris_quic_parse_initial(bad.as_ptr(), bad.len(), out.as_mut_ptr()), -3);
+        assert_eq!(iris_quic_parse_initial(pkt.as_ptr(), 100, out.as_mut_ptr()), -2);
+    }
+
+    #[test]
+    fn client_hello_across_datagrams() {
+        // A v2 ClientHello in two CRYPTO frames, the second arriving first and
+        // coalesced with a 0-RTT packet
+        let hello = unhex(RFC_CRYPTO)[4..].to_vec();
+        let frame = |offset: u8, data: &[u8]| [&[0x06, offset, 0x40, data.len() as u8][..], data].concat();
+        let zero_rtt = [&[0xe3][..], &QUIC_V2.to_be_bytes(), &[8], &DCID, &[0], &[3], &[1, 2, 3]].concat();
+        let second = [initial(QUIC_V2, 1, &frame(50, &hello[50..]), 1200), zero_rtt].concat();
+        let first = initial(QUIC_V2, 0, &[&[0x02, 0, 0, 0, 0][..], &frame(0, &hello[..50])].concat(), 1200);
+
+        let mut out = std::mem::MaybeUninit::<IrisQuicInitial>::uninit();
+        assert_eq!(iris_quic_parse_initial(second.as_ptr(), second.len(), out.as_mut_ptr()), -1);
+        let q = iris_quic_initials_new();
+        assert_eq!(iris_quic_initials_feed(q, second.as_ptr(), second.len(), out.as_mut_ptr()), 0);
+        let mut r = unsafe { out.assume_init_read() };
+        assert_eq!((r.version, r.packets), (QUIC_V2, 1));
+        assert!(r.client_hello.is_null());
+        iris_quic_initial_free(&mut r);
+
+        assert_eq!(iris_quic_initials_feed(q, first.as_ptr(), first.len(), out.as_mut_ptr()), 0);
+        let mut r = unsafe { out.assume_init_read() };
+        assert_eq!(unsafe { std::slice::from_raw_parts(r.client_hello, r.client_hello_len) }, &hello[..]);
+        iris_quic_initial_free(&mut r);
+
+        // A short-header packet alone holds no Initial
+        assert_eq!(iris_quic_initials_feed(q, [0x40, 1, 2, 3].as_ptr(), 4, out.as_mut_ptr()), -3);
+        iris_quic_initials_free(q);
+    }
+}
@@ -0,0 +1,611 @@
+//! Code signing requirement blobs (0xfade0c00): decompiles the binary expression
+//! form found in code signatures and TCC csreq columns into requirement language,
+//! and compiles requirement language back into a blob.
+
+use crate::der::{decode_oid, encode_oid};
+use crate::ffi::alloc_bytes;
+use crate::hash::to_hex;
+use std::ffi::{c_char, CStr, CString};
+
+const REQUIREMENT_MAGIC: u32 = 0xfade_0c00;
+const KIND_EXPRESSION: u32 = 1;
+const MAX_DEPTH: usize = 64;
+
+const OP_FLAG_MASK: u32 = 0xff00_0000;
+
+const OP_FALSE: u32 = 0;
+const OP_TRUE: u32 = 1;
+const OP_IDENT: u32 = 2;
+const OP_APPLE_ANCHOR: u32 = 3;
+const OP_ANCHOR_HASH: u32 = 4;
+const OP_INFO_KEY_VALUE: u32 = 5;
+const OP_AND: u32 = 6;
+const OP_OR: u32 = 7;
+const OP_CDHASH: u32 = 8;
+const OP_NOT: u32 = 9;
+const OP_INFO_KEY_FIELD: u32 = 10;
+const OP_CERT_FIELD: u32 = 11;
+const OP_TRUSTED_CERT: u32 = 12;
+const OP_TRUSTED_CERTS: u32 = 13;
+const OP_CERT_GENERIC: u32 = 14;
+const OP_APPLE_GENERIC_ANCHOR: u32 = 15;
+const OP_ENTITLEMENT_FIELD: u32 = 16;
+const OP_CERT_POLICY: u32 = 17;
+const OP_NAMED_ANCHOR: u32 = 18;
+const OP_NAMED_CODE: u32 = 19;
+const OP_PLATFORM: u32 = 20;
+const OP_NOTARIZED: u32 = 21;
+const OP_CERT_FIELD_DATE: u32 = 22;
+const OP_LEGACY_DEV_ID: u32 = 23;
+
+const MATCH_EXISTS: u32 = 0;
+const MATCH_EQUAL: u32 = 1;
+const MATCH_CONTAINS: u32 = 2;
+const MATCH_BEGINS_WITH: u32 = 3;
+const MATCH_ENDS_WITH: u32 = 4;
+const MATCH_LESS_THAN: u32 = 5;
+const MATCH_GREATER_THAN: u32 = 6;
+const MATCH_LESS_EQUAL: u32 = 7;
+const MATCH_GREATER_EQUAL: u32 = 8;
+const MATCH_ON: u32 = 9;
+const MATCH_BEFORE: u32 = 10;
+const MATCH_AFTER: u32 = 11;
+const MATCH_ON_OR_BEFORE: u32 = 12;
+const MATCH_ON_OR_AFTER: u32 = 13;
+const MATCH_ABSENT: u32 = 14;
+
+// Binding strength, used to decide where parentheses are needed
+const PREC_OR: u8 = 1;
+const PREC_AND: u8 = 2;
+const PREC_PRIMARY: u8 = 3;
+
+struct Reader<'a> {
+    d: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn u32(&mut self) -> Option<u32> {
+        let v = u32::from_be_bytes(self.d.get(self.pos..self.pos + 4)?.try_into().ok()?);
+        self.pos += 4;
+        Some(v)
+    }
+
+    fn i64(&mut self) -> Option<i64> {
+        let v = i64::from_be_bytes(self.d.get(self.pos..self.pos + 8)?.try_into().ok()?);
+        self.pos += 8;
+        Some(v)
+    }
+
+    /// Length-prefixed data, padded to a 4-byte boundary.
+    fn data(&mut self) -> Option<&'a [u8]> {
+        let len = self.u32()? as usize;
+        let v = self.d.get(self.pos..self.pos.checked_add(len)?)?;
+        self.pos += len.next_multiple_of(4);
+        Some(v)
+    }
+
+    fn string(&mut self) -> Option<String> {
+        self.data().map(quote)
+    }
+
+    fn slot(&mut self) -> Option<String> {
+        Some(match self.u32()? as i32 {
+            0 => "leaf".into(),
+            -1 => "root".into(),
+            n => n.to_string(),
+        })
+    }
+
+    fn match_suffix(&mut self) -> Option<String> {
+        let op = self.u32()?;
+        Some(match op {
+            MATCH_EXISTS => " exists".into(),
+            MATCH_ABSENT => " absent".into(),
+            MATCH_EQUAL => format!(" = {}", self.string()?),
+            MATCH_CONTAINS => format!(" ~ {}", self.string()?),
+            MATCH_BEGINS_WITH => format!(" = {}", quote(&[self.data()?, b"*"].concat())),
+            MATCH_ENDS_WITH => format!(" = {}", quote(&[b"*", self.data()?].concat())),
+            MATCH_LESS_THAN => format!(" < {}", self.string()?),
+            MATCH_GREATER_THAN => format!(" > {}", self.string()?),
+            MATCH_LESS_EQUAL => format!(" <= {}", self.string()?),
+            MATCH_GREATER_EQUAL => format!(" >= {}", self.string()?),
+            MATCH_ON => format!(" = timestamp \"{}\"", self.i64()?),
+            MATCH_BEFORE => format!(" < timestamp \"{}\"", self.i64()?),
+            MATCH_AFTER => format!(" > timestamp \"{}\"", self.i64()?),
+            MATCH_ON_OR_BEFORE => format!(" <= timestamp \"{}\"", self.i64()?),
+            MATCH_ON_OR_AFTER => format!(" >= timestamp \"{}\"", self.i64()?),
+            _ => return None,
+        })
+    }
+
+    /// One expression, returned with the binding strength of its top-level operator.
+    fn expr(&mut self, depth: usize) -> Option<(String, u8)> {
+        if depth > MAX_DEPTH { return None; }
+        let op = self.u32()? & !OP_FLAG_MASK;
+        let primary = |s: String| Some((s, PREC_PRIMARY));
+        match op {
+            OP_FALSE => primary("never".into()),
+            OP_TRUE => primary("always".into()),
+            OP_IDENT => primary(format!("identifier {}", self.string()?)),
+            OP_APPLE_ANCHOR => primary("anchor apple".into()),
+            OP_APPLE_GENERIC_ANCHOR => primary("anchor apple generic".into()),
+            OP_TRUSTED_CERTS => primary("anchor trusted".into()),
+            OP_NOTARIZED => primary("notarized".into()),
+            OP_LEGACY_DEV_ID => primary("legacy".into()),
+            OP_NAMED_ANCHOR => primary(format!("anchor apple {}", String::from_utf8_lossy(self.data()?))),
+            OP_NAMED_CODE => primary(format!("({})", String::from_utf8_lossy(self.data()?))),
+            OP_PLATFORM => primary(format!("platform = {}", self.u32()? as i32)),
+            OP_CDHASH => primary(format!("cdhash H\"{}\"", to_hex(self.data()?))),
+            OP_ANCHOR_HASH => {
+                let slot = self.slot()?;
+                primary(format!("certificate {} = H\"{}\"", slot, to_hex(self.data()?)))
+            }
+            OP_TRUSTED_CERT => primary(format!("certificate {} trusted", self.slot()?)),
+            OP_INFO_KEY_VALUE => {
+                let key = self.string()?;
+                primary(format!("info[{}] = {}", key, self.string()?))
+            }
+            OP_INFO_KEY_FIELD => {
+                let key = self.string()?;
+                primary(format!("info[{}]{}", key, self.match_suffix()?))
+            }
+            OP_ENTITLEMENT_FIELD => {
+                let key = self.string()?;
+                primary(format!("entitlement[{}]{}", key, self.match_suffix()?))
+            }
+            OP_CERT_FIELD => {
+                let slot = self.slot()?;
+                let field = String::from_utf8_lossy(self.data()?).into_owned();
+                primary(format!("certificate {}[{}]{}", slot, field, self.match_suffix()?))
+            }
+            OP_CERT_GENERIC | OP_CERT_POLICY | OP_CERT_FIELD_DATE => {
+                let slot = self.slot()?;
+                let oid = decode_oid(self.data()?)?;
+                let kind = match op { OP_CERT_GENERIC => "field", OP_CERT_POLICY => "policy", _ => "timestamp" };
+                primary(format!("certificate {}[{}.{}]{}", slot, kind, oid, self.match_suffix()?))
+            }
+            OP_NOT => {
+                let (e, prec) = self.expr(depth + 1)?;
+                primary(format!("! {}", paren(e, prec, PREC_PRIMARY)))
+            }
+            OP_AND | OP_OR => {
+                let prec = if op == OP_AND { PREC_AND } else { PREC_OR };
+                let (l, lp) = self.expr(depth + 1)?;
+                let (r, rp) = self.expr(depth + 1)?;
+                let word = if op == OP_AND { "and" } else { "or" };
+                Some((format!("{} {} {}", paren(l, lp, prec), word, paren(r, rp, prec + 1)), prec))
+            }
+            _ => None,
+        }
+    }
+}
+
+fn paren(e: String, prec: u8, min: u8) -> String {
+    if prec < min { format!("({})", e) } else { e }
+}
+
+fn quote(s: &[u8]) -> String {
+    let mut out = String::from("\"");
+    for c in String::from_utf8_lossy(s).chars() {
+        if c == '"' || c == '\\' { out.push('\\'); }
+        out.push(c);
+    }
+    out.push('"');
+    out
+}
+
+/// Requirement language text for a requirement blob, e.g.
+/// `identifier "com.example.app" and anchor apple generic`.
+pub fn decompile(blob: &[u8]) -> Option<String> {
+    let mut r = Reader { d: blob, pos: 0 };
+    if r.u32()? != REQUIREMENT_MAGIC { return None; }
+    let len = r.u32()? as usize;
+    if len < 12 || len > blob.len() { return None; }
+    if r.u32()? != KIND_EXPRESSION { return None; }
+    r.d = &blob[..len];
+    r.expr(0).map(|(s, _)| s)
+}
+
+// --- Compiler ---
+
+enum Token {
+    Word(String),        // keyword or unquoted value
+    Str(String),         // "quoted", escapes removed
+    Hash(Vec<u8>),       // H"hex"
+    Punct(&'static str),
+}
+
+const PUNCT: [&str; 14] = ["<=", ">=", "==", "&&", "||", "(", ")", "[", "]", "!", "=", "<", ">", "~"];
+
+fn bare(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "._-/*+:$@".contains(c)
+}
+
+fn tokenize(s: &str) -> Option<Vec<Token>> {
+    let mut out = Vec::new();
+    let mut chars = s.char_indices().peekable();
+    while let Some(&(i, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '"' || (c == 'H' && s[i + 1..].starts_with('"')) {
+            if c == 'H' { chars.next(); }
+            chars.next();
+            let mut v = String::new();
+            loop {
+                match chars.next()?.1 {
+                    '"' => break,
+                    '\\' => v.push(chars.next()?.1),
+                    ch => v.push(ch),
+                }
+            }
+            out.push(if c == 'H' {
+                if !v.len().is_multiple_of(2) { return None; }
+                Token::Hash((0..v.len()).step_by(2).map(|j| u8::from_str_radix(v.get(j..j + 2)?, 16).ok()).collect::<Option<_>>()?)
+            } else {
+                Token::Str(v)
+            });
+        } else if let Some(p) = PUNCT.iter().find(|p| s[i..].starts_with(**p)) {
+            for _ in 0..p.len() { chars.next(); }
+            out.push(Token::Punct(p));
+        } else if bare(c) {
+            let mut w = String::new();
+            while let Some(&(_, ch)) = chars.peek().filter(|(_, ch)| bare(*ch)) { w.push(ch); chars.next(); }
+            out.push(Token::Word(w));
+        } else {
+            return None;
+        }
+    }
+    Some(out)
+}
+
+struct Compiler {
+    t: Vec<Token>,
+    pos: usize,
+}
+
+fn emit(v: &mut Vec<u8>, x: u32) { v.extend_from_slice(&x.to_be_bytes()); }
+
+fn emit_data(v: &mut Vec<u8>, d: &[u8]) {
+    emit(v, d.len() as u32);
+    v.extend_from_slice(d);
+    v.resize(v.len().next_multiple_of(4), 0);
+}
+
+impl Compiler {
+    fn next(&mut self) -> Option<&Token> {
+        let t = self.t.get(self.pos);
+        self.pos += t.is_some() as usize;
+        t
+    }
+
+    fn punct(&mut self, p: &str) -> bool {
+        let hit = matches!(self.t.get(self.pos), Some(Token::Punct(q)) if *q == p);
+        self.pos += hit as usize;
+        hit
+    }
+
+    fn word(&mut self, w: &str) -> bool {
+        let hit = matches!(self.t.get(self.pos), Some(Token::Word(x)) if x == w);
+        self.pos += hit as usize;
+        hit
+    }
+
+    fn value(&mut self) -> Option<String> {
+        match self.next()? {
+            Token::Word(w) | Token::Str(w) => Some(w.clone()),
+            _ => None,
+        }
+    }
+
+    fn hash(&mut self) -> Option<Vec<u8>> {
+        match self.next()? {
+            Token::Hash(h) => Some(h.clone()),
+            _ => None,
+        }
+    }
+
+    fn bracketed(&mut self) -> Option<String> {
+        if !self.punct("[") { return None; }
+        let v = self.value()?;
+        self.punct("]").then_some(v)
+    }
+
+    fn slot(&mut self) -> Option<u32> {
+        match self.value()?.as_str() {
+            "leaf" => Some(0),
+            "root" | "anchor" => Some(-1i32 as u32),
+            n => n.parse::<i32>().ok().map(|n| n as u32),
+        }
+    }
+
+    fn timestamp(&mut self) -> Option<i64> {
+        self.value()?.parse().ok()
+    }
+
+    /// Match operator and operand, as appended to info/entitlement/certificate fields.
+    fn matcher(&mut self, v: &mut Vec<u8>) -> Option<()> {
+        if self.word("exists") { emit(v, MATCH_EXISTS); return Some(()); }
+        if self.word("absent") { emit(v, MATCH_ABSENT); return Some(()); }
+        let (op, date_op) = if self.punct("=") || self.punct("==") {
+            (MATCH_EQUAL, MATCH_ON)
+        } else if self.punct("~") {
+            (MATCH_CONTAINS, MATCH_ON)
+        } else if self.punct("<=") {
+            (MATCH_LESS_EQUAL, MATCH_ON_OR_BEFORE)
+        } else if self.punct(">=") {
+            (MATCH_GREATER_EQUAL, MATCH_ON_OR_AFTER)
+        } else if self.punct("<") {
+            (MATCH_LESS_THAN, MATCH_BEFORE)
+        } else if self.punct(">") {
+            (MATCH_GREATER_THAN, MATCH_AFTER)
+        } else {
+            return None;
+        };
+        if self.word("timestamp") {
+            if op == MATCH_CONTAINS { return None; }
+            emit(v, date_op);
+            v.extend_from_slice(&self.timestamp()?.to_be_bytes());
+            return Some(());
+        }
+        let s = self.value()?;
+        let (op, s) = match (op, s.strip_prefix('*'), s.strip_suffix('*')) {
+            (MATCH_EQUAL, Some(inner), Some(_)) if s.len() >= 2 => (MATCH_CONTAINS, &inner[..inner.len() - 1]),
+            (MATCH_EQUAL, None, Some(prefix)) => (MATCH_BEGINS_WITH, prefix),
+            (MATCH_EQUAL, Some(suffix), None) => (MATCH_ENDS_WITH, suffix),
+            _ => (op, s.as_str()),
+        };
+        emit(v, op);
+        emit_data(v, s.as_bytes());
+        Some(())
+    }
+
+    /// What follows "certificate <slot>" (or a bare "anchor"): a hash, "trusted", or a field match.
+    fn certificate(&mut self, slot: u32, v: &mut Vec<u8>) -> Option<()> {
+        if self.punct("=") {
+            emit(v, OP_ANCHOR_HASH);
+            emit(v, slot);
+            emit_data(v, &self.hash()?);
+        } else if self.word("trusted") {
+            emit(v, OP_TRUSTED_CERT);
+            emit(v, slot);
+        } else {
+            let field = self.bracketed()?;
+            let typed = [("field.", OP_CERT_GENERIC), ("policy.", OP_CERT_POLICY), ("timestamp.", OP_CERT_FIELD_DATE)];
+            match typed.iter().find_map(|(p, op)| field.strip_prefix(p).map(|oid| (oid, *op))) {
+                Some((oid, op)) => {
+                    emit(v, op);
+                    emit(v, slot);
+                    emit_data(v, &encode_oid(oid)?);
+                }
+                None => {
+                    emit(v, OP_CERT_FIELD);
+                    emit(v, slot);
+                    emit_data(v, field.as_bytes());
+                }
+            }
+            self.matcher(v)?;
+        }
+        Some(())
+    }
+
+    fn primary(&mut self, depth: usize) -> Option<Vec<u8>> {
+        if depth > MAX_DEPTH { return None; }
+        let mut v = Vec::new();
+        if self.punct("!") {
+            emit(&mut v, OP_NOT);
+            v.extend(self.primary(depth + 1)?);
+            return Some(v);
+        }
+        if self.punct("(") {
+            let e = self.or_expr(depth + 1)?;
+            return self.punct(")").then_some(e);
+        }
+        let Some(Token::Word(w)) = self.next() else { return None };
+        match w.clone().as_str() {
+            "always" | "true" => emit(&mut v, OP_TRUE),
+            "never" | "false" => emit(&mut v, OP_FALSE),
+            "notarized" => emit(&mut v, OP_NOTARIZED),
+            "legacy" => emit(&mut v, OP_LEGACY_DEV_ID),
+            "identifier" => {
+                self.punct("=");
+                emit(&mut v, OP_IDENT);
+                emit_data(&mut v, self.value()?.as_bytes());
+            }
+            "anchor" => {
+                if self.word("apple") {
+                    if self.word("generic") {
+                        emit(&mut v, OP_APPLE_GENERIC_ANCHOR);
+                    } else if let Some(Token::Word(name)) = self.t.get(self.pos).filter(|t| !matches!(t, Token::Word(w) if w == "and" || w == "or")) {
+                        let name = name.clone();
+                        self.pos += 1;
+                        emit(&mut v, OP_NAMED_ANCHOR);
+                        emit_data(&mut v, name.as_bytes());
+                    } else {
+                        emit(&mut v, OP_APPLE_ANCHOR);
+                    }
+                } else if self.word("trusted") {
+                    emit(&mut v, OP_TRUSTED_CERTS);
+                } else {
+                    self.certificate(-1i32 as u32, &mut v)?;
+                }
+            }
+            "certificate" | "cert" => {
+                let slot = self.slot()?;
+                self.certificate(slot, &mut v)?;
+            }
+            "info" | "entitlement" => {
+                emit(&mut v, if w == "info" { OP_INFO_KEY_FIELD } else { OP_ENTITLEMENT_FIELD });
+                emit_data(&mut v, self.bracketed()?.as_bytes());
+                self.matcher(&mut v)?;
+            }
+            "cdhash" => {
+                self.punct("=");
+                emit(&mut v, OP_CDHASH);
+                emit_data(&mut v, &self.hash()?);
+            }
+            "platform" => {
+                if !self.punct("=") { return None; }
+                emit(&mut v, OP_PLATFORM);
+                emit(&mut v, self.value()?.parse::<i32>().ok()? as u32);
+            }
+            _ => return None,
+        }
+        Some(v)
+    }
+
+    fn and_expr(&mut self, depth: usize) -> Option<Vec<u8>> {
+        let mut l = self.primary(depth)?;
+        while self.word("and") || self.punct("&&") {
+            let r = self.primary(depth)?;
+            l = [&OP_AND.to_be_bytes()[..], &l, &r].concat();
+        }
+        Some(l)
+    }
+
+    fn or_expr(&mut self, depth: usize) -> Option<Vec<u8>> {
+        let mut l = self.and_expr(depth)?;
+        while self.word("or") || self.punct("||") {
+            let r = self.and_expr(depth)?;
+            l = [&OP_OR.to_be_bytes()[..], &l, &r].concat();
+        }
+        Some(l)
+    }
+}
+
+/// Requirement blob for requirement language text. A leading "designated =>" (as
+/// printed by `codesign -d -r-`) is accepted. None on syntax errors.
+pub fn compile(text: &str) -> Option<Vec<u8>> {
+    let text = text.trim();
+    let text = text.strip_prefix("designated").and_then(|r| r.trim_start().strip_prefix("=>")).unwrap_or(text);
+    let mut c = Compiler { t: tokenize(text)?, pos: 0 };
+    let expr = c.or_expr(0)?;
+    if c.pos != c.t.len() { return None; }
+    let mut v = Vec::with_capacity(12 + expr.len());
+    emit(&mut v, REQUIREMENT_MAGIC);
+    emit(&mut v, 12 + expr.len() as u32);
+    emit(&mut v, KIND_EXPRESSION);
+    v.extend(expr);
+    Some(v)
+}
+
+// --- FFI entry points ---
+
+/// Requirement language text for a requirement blob (0xfade0c00), or null if the
+/// blob is not a well-formed expression. Free with iris_free_string.
+#[no_mangle]
+pub extern "C" fn iris_requirement_decompile(data: *const u8, len: usize) -> *mut c_char {
+    if data.is_null() { return std::ptr::null_mut(); }
+    match decompile(unsafe { std::slice::from_raw_parts(data, len) }) {
+        Some(text) => CString::new(text).map_or(std::ptr::null_mut(), CString::into_raw),
+        None => std::ptr::null_mut(),
+    }
+}
+
+/// Compile requirement language text into a blob. Returns 0=ok, -2=arg or syntax
+/// error. Free with iris_free_bytes.
+#[no_mangle]
+pub extern "C" fn iris_requirement_compile(text: *const c_char, out: *mut *mut u8, out_len: *mut usize) -> i32 {
+    if text.is_null() || out.is_null() || out_len.is_null() { return -2; }
+    let Ok(text) = unsafe { CStr::from_ptr(text) }.to_str() else { return -2 };
+    let Some(blob) = compile(text) else { return -2 };
+    let (ptr, len) = alloc_bytes(&blob);
+    unsafe { *out = ptr; *out_len = len; }
+    0
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    pub fn op(v: &mut Vec<u8>, x: u32) { v.extend_from_slice(&x.to_be_bytes()); }
+
+    pub fn data(v: &mut Vec<u8>, d: &[u8]) {
+        op(v, d.len() as u32);
+        v.extend_from_slice(d);
+        v.resize(v.len().next_multiple_of(4), 0);
+    }
+
+    pub fn blob(expr: &[u8]) -> Vec<u8> {
+        let mut v = Vec::new();
+        op(&mut v, REQUIREMENT_MAGIC);
+        op(&mut v, 12 + expr.len() as u32);
+        op(&mut v, KIND_EXPRESSION);
+        v.extend_from_slice(expr);
+        v
+    }
+
+    /// `identifier "<id>" and anchor apple generic and certificate leaf[subject.OU] = "<team>"`
+    pub fn developer_id(id: &str, team: &str) -> Vec<u8> {
+        let mut e = Vec::new();
+        op(&mut e, OP_AND);
+        op(&mut e, OP_AND);
+        op(&mut e, OP_IDENT);
+        data(&mut e, id.as_bytes());
+        op(&mut e, OP_APPLE_GENERIC_ANCHOR);
+        op(&mut e, OP_CERT_FIELD);
+        op(&mut e, 0);
+        data(&mut e, b"subject.OU");
+        op(&mut e, MATCH_EQUAL);
+        data(&mut e, team.as_bytes());
+        blob(&e)
+    }
+
+    #[test]
+    fn decompile_expressions() {
+        assert_eq!(decompile(&developer_id("com.example.app", "TEAM123456")).as_deref(),
+                   Some("identifier \"com.example.app\" and anchor apple generic and certificate leaf[subject.OU] = \"TEAM123456\""));
+
+        // (anchor apple or cdhash H"..") and ! certificate 1[field.1.2.840.113635.100.6.2.6] exists
+        let mut e = Vec::new();
+        op(&mut e, OP_AND);
+        op(&mut e, OP_OR);
+        op(&mut e, OP_APPLE_ANCHOR);
+        op(&mut e, OP_CDHASH);
+        data(&mut e, &[0xab, 0xcd]);
+        op(&mut e, OP_NOT);
+        op(&mut e, OP_CERT_GENERIC);
+        op(&mut e, 1);
+        data(&mut e, &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x63, 0x64, 0x06, 0x02, 0x06]);
+        op(&mut e, MATCH_EXISTS);
+        assert_eq!(decompile(&blob(&e)).as_deref(),
+                   Some("(anchor apple or cdhash H\"abcd\") and ! certificate 1[field.1.2.840.113635.100.6.2.6] exists"));
+
+        let mut b = developer_id("x", "y");
+        let n = b.len();
+        b.truncate(n - 4);
+        assert_eq!(decompile(&b), None);
+        assert_eq!(decompile(b"\xfa\xde\x0c\x01\0\0\0\x0c\0\0\0\0"), None);
+    }
+
+    #[test]
+    fn compile_round_trips() {
+        let dev = "identifier \"com.example.app\" and anchor apple generic and certificate leaf[subject.OU] = \"TEAM123456\"";
+        assert_eq!(compile(dev), Some(developer_id("com.example.app", "TEAM123456")));
+        assert_eq!(compile(&format!("designated => {}", dev.replace('"', ""))), Some(developer_id("com.example.app", "TEAM123456")));
+
+        for text in [
+            "(anchor apple or cdhash H\"abcd\") and ! certificate 1[field.1.2.840.113635.100.6.2.6] exists",
+            "anchor apple generic and (certificate leaf[field.1.2.840.113635.100.6.1.9] exists or certificate 1[field.1.2.840.113635.100.6.2.6] exists and notarized)",
+            "entitlement[\"com.apple.security.get-task-allow\"] absent and info[\"CFBundleVersion\"] >= \"2.0\"",
+            "certificate root = H\"00ff\" or certificate -2 trusted or anchor trusted or platform = 1",
+            "info[\"CFBundleIdentifier\"] = \"com.example.*\" and certificate leaf[timestamp.1.2.3] < timestamp \"1700000000\"",
+            "! (always and never) or anchor apple Developer",
+        ] {
+            let blob = compile(text).unwrap_or_else(|| panic!("{}", text));
+            assert_eq!(decompile(&blob).as_deref(), Some(text));
+        }
+
+        for bad in ["", "anchor", "identifier", "certificate leaf", "info[\"x\"] =", "(always", "always and", "always never", "cdhash H\"abc\"", "platform 1"] {
+            assert_eq!(compile(bad), None, "{}", bad);
+        }
+
+        let text = std::ffi::CString::new("anchor apple").unwrap();
+        let (mut p, mut n) = (std::ptr::null_mut(), 0);
+        assert_eq!(iris_requirement_compile(text.as_ptr(), &mut p, &mut n), 0);
+        let s = iris_requirement_decompile(p, n);
+        assert_eq!(unsafe { CStr::from_ptr(s) }.to_str(), Ok("anchor apple"));
+        crate::batch::iris_free_string(s);
+        crate::ffi::iris_free_bytes(p, n);
+    }
+}
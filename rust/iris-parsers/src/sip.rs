@@ -0,0 +1,324 @@
+//! SIP (RFC 3261) and RTSP (RFC 2326/7826) messages: start line, dialog headers and
+//! the SDP body's media streams. Headers go through the same httparse path as HTTP.
+
+use crate::ffi::{alloc_array, free_array, IrisSlice};
+use crate::http::{alloc_headers, free_headers, IrisHttpHeader, MAX_HEADERS};
+use std::slice;
+
+pub const SIP_PROTO_SIP: u8 = 1;
+pub const SIP_PROTO_RTSP: u8 = 2;
+
+#[repr(C)]
+pub struct IrisSdpMedia {
+    pub media: IrisSlice,      // "audio", "video", "application"
+    pub port: u16,
+    pub proto: IrisSlice,      // "RTP/AVP", "RTP/SAVP", "udp"
+    pub formats: IrisSlice,    // "0 8 101"
+    pub connection: IrisSlice, // media-level c= address, else the session-level one
+}
+
+#[repr(C)]
+pub struct IrisSipMessage {
+    pub protocol: u8,          // SIP_PROTO_*
+    pub is_request: bool,
+    pub method: IrisSlice,     // requests: "INVITE", "DESCRIBE", ...
+    pub uri: IrisSlice,
+    pub status_code: u16,      // responses
+    pub reason: IrisSlice,
+    pub version: IrisSlice,    // "SIP/2.0", "RTSP/1.0"
+    pub cseq: u32,
+    pub cseq_method: IrisSlice, // SIP only; RTSP CSeq carries just the number
+    pub call_id: IrisSlice,    // SIP Call-ID or RTSP Session (without ";timeout=")
+    pub from: IrisSlice,
+    pub to: IrisSlice,
+    pub contact: IrisSlice,
+    pub user_agent: IrisSlice, // User-Agent, else Server
+    pub transport: IrisSlice,  // RTSP Transport
+    pub vias: *mut IrisSlice,  // one entry per hop, comma-joined Via values split
+    pub vias_count: usize,
+    pub header_end_index: usize,
+    pub content_length: i64,   // -1 = absent
+    pub sdp_session_name: IrisSlice,
+    pub sdp_connection: IrisSlice,
+    pub media: *mut IrisSdpMedia,
+    pub media_count: usize,
+    pub headers: *mut IrisHttpHeader,
+    pub headers_count: usize,
+}
+
+struct StartLine<'a> {
+    protocol: u8,
+    method: &'a [u8],
+    uri: &'a [u8],
+    status_code: u16,
+    reason: &'a [u8],
+    version: &'a [u8],
+}
+
+fn trim(s: &[u8]) -> &[u8] {
+    let start = s.iter().position(|b| !b.is_ascii_whitespace()).unwrap_or(s.len());
+    let end = s.iter().rposition(|b| !b.is_ascii_whitespace()).map_or(start, |e| e + 1);
+    &s[start..end]
+}
+
+fn version_protocol(v: &[u8]) -> Option<u8> {
+    match v {
+        b"SIP/2.0" => Some(SIP_PROTO_SIP),
+        b"RTSP/1.0" | b"RTSP/2.0" => Some(SIP_PROTO_RTSP),
+        _ => None,
+    }
+}
+
+fn start_line(line: &[u8]) -> Option<StartLine<'_>> {
+    let mut parts = line.splitn(3, |&b| b == b' ');
+    let (a, b, c) = (parts.next()?, parts.next()?, parts.next().unwrap_or(b""));
+    if let Some(protocol) = version_protocol(a) {
+        let code = std::str::from_utf8(b).ok().filter(|s| s.len() == 3)?.parse().ok()?;
+        return Some(StartLine { protocol, method: b"", uri: b"", status_code: code, reason: c, version: a });
+    }
+    let protocol = version_protocol(c)?;
+    if a.is_empty() || !a.iter().all(|b| b.is_ascii_uppercase() || *b == b'_' || *b == b'-') || b.is_empty() { return None; }
+    Some(StartLine { protocol, method: a, uri: b, status_code: 0, reason: b"", version: c })
+}
+
+/// Map RFC 3261 §7.3.3 compact forms to the full header name.
+fn canonical(name: &str) -> &str {
+    match name {
+        "v" | "V" => "via",
+        "f" | "F" => "from",
+        "t" | "T" => "to",
+        "m" | "M" => "contact",
+        "i" | "I" => "call-id",
+        "l" | "L" => "content-length",
+        "c" | "C" => "content-type",
+        _ => name,
+    }
+}
+
+/// Split a Via value at commas outside quotes and angle brackets.
+fn split_hops(v: &[u8]) -> Vec<&[u8]> {
+    let (mut out, mut start, mut quoted, mut angle) = (Vec::new(), 0, false, false);
+    for (i, &b) in v.iter().enumerate() {
+        match b {
+            b'"' => quoted = !quoted,
+            b'<' if !quoted => angle = true,
+            b'>' if !quoted => angle = false,
+            b',' if !quoted && !angle => { out.push(trim(&v[start..i])); start = i + 1; }
+            _ => {}
+        }
+    }
+    out.push(trim(&v[start..]));
+    out.retain(|h| !h.is_empty());
+    out
+}
+
+struct Sdp<'a> {
+    session_name: &'a [u8],
+    connection: &'a [u8],
+    media: Vec<IrisSdpMedia>,
+}
+
+/// "IN IP4 192.0.2.1[/ttl]" -> "192.0.2.1"
+fn connection_addr(v: &[u8]) -> &[u8] {
+    let addr = v.split(|&b| b == b' ').filter(|t| !t.is_empty()).nth(2).unwrap_or(b"");
+    addr.split(|&b| b == b'/').next().unwrap_or(b"")
+}
+
+fn parse_sdp(body: &[u8]) -> Option<Sdp<'_>> {
+    if !body.starts_with(b"v=") { return None; }
+    let mut sdp = Sdp { session_name: b"", connection: b"", media: Vec::new() };
+    for line in body.split(|&b| b == b'\n') {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        if line.len() < 2 || line[1] != b'=' { continue; }
+        let v = &line[2..];
+        match line[0] {
+            b's' => sdp.session_name = v,
+            b'c' => match sdp.media.last_mut() {
+                Some(m) => m.connection = IrisSlice::from_bytes(connection_addr(v)),
+                None => sdp.connection = connection_addr(v),
+            },
+            b'm' => {
+                let mut f = v.splitn(4, |&b| b == b' ');
+                let media = f.next().unwrap_or(b"");
+                // "49170/2" carries a port count; only the base port matters here
+                let port = f.next().and_then(|p| p.split(|&b| b == b'/').next())
+                    .and_then(|p| std::str::from_utf8(p).ok()).and_then(|p| p.parse().ok()).unwrap_or(0);
+                sdp.media.push(IrisSdpMedia {
+                    media: IrisSlice::from_bytes(media),
+                    port,
+                    proto: IrisSlice::from_bytes(f.next().unwrap_or(b"")),
+                    formats: IrisSlice::from_bytes(f.next().unwrap_or(b"")),
+                    connection: IrisSlice::from_bytes(sdp.connection),
+                });
+            }
+            _ => {}
+        }
+    }
+    Some(sdp)
+}
+
+// --- FFI entry points ---
+
+/// Parse a SIP or RTSP request/response. Returns 0=ok, -1=incomplete (headers or
+/// Content-Length body), -2=parse/arg error, -3=not SIP/RTSP.
+/// Slices point into `data` — keep it alive. Free with iris_sip_free.
+#[no_mangle]
+pub extern "C" fn iris_sip_parse(data: *const u8, len: usize, out: *mut IrisSipMessage) -> i32 {
+    if data.is_null() || out.is_null() || len == 0 { return -2; }
+    let buf = unsafe { slice::from_raw_parts(data, len) };
+    let Some(nl) = buf.iter().position(|&b| b == b'\n') else {
+        return if buf.len() < 256 { -1 } else { -3 };
+    };
+    let line = &buf[..nl];
+    let Some(sl) = start_line(line.strip_suffix(b"\r").unwrap_or(line)) else { return -3 };
+
+    let mut hdr_buf = [httparse::EMPTY_HEADER; MAX_HEADERS];
+    let (offset, headers) = match httparse::parse_headers(&buf[nl + 1..], &mut hdr_buf) {
+        Ok(httparse::Status::Complete((n, h))) => (nl + 1 + n, h),
+        Ok(httparse::Status::Partial) => return -1,
+        Err(_) => return -2,
+    };
+
+    let mut msg = IrisSipMessage {
+        protocol: sl.protocol,
+        is_request: sl.status_code == 0,
+        method: IrisSlice::from_bytes(sl.method),
+        uri: IrisSlice::from_bytes(sl.uri),
+        status_code: sl.status_code,
+        reason: IrisSlice::from_bytes(sl.reason),
+        version: IrisSlice::from_bytes(sl.version),
+        cseq: 0, cseq_method: IrisSlice::from_bytes(b""),
+        call_id: IrisSlice::from_bytes(b""),
+        from: IrisSlice::from_bytes(b""), to: IrisSlice::from_bytes(b""), contact: IrisSlice::from_bytes(b""),
+        user_agent: IrisSlice::from_bytes(b""), transport: IrisSlice::from_bytes(b""),
+        vias: std::ptr::null_mut(), vias_count: 0,
+        header_end_index: offset,
+        content_length: -1,
+        sdp_session_name: IrisSlice::from_bytes(b""), sdp_connection: IrisSlice::from_bytes(b""),
+        media: std::ptr::null_mut(), media_count: 0,
+        headers: std::ptr::null_mut(), headers_count: 0,
+    };
+    let mut vias = Vec::new();
+    let mut is_sdp = false;
+    let mut server: &[u8] = b"";
+    for h in headers.iter() {
+        let v = trim(h.value);
+        match canonical(&h.name.to_ascii_lowercase()) {
+            "via" => vias.extend(split_hops(v).into_iter().map(IrisSlice::from_bytes)),
+            "from" => msg.from = IrisSlice::from_bytes(v),
+            "to" => msg.to = IrisSlice::from_bytes(v),
+            "contact" => msg.contact = IrisSlice::from_bytes(v),
+            "call-id" => msg.call_id = IrisSlice::from_bytes(v),
+            "session" => msg.call_id = IrisSlice::from_bytes(trim(v.split(|&b| b == b';').next().unwrap_or(b""))),
+            "user-agent" => msg.user_agent = IrisSlice::from_bytes(v),
+            "server" => server = v,
+            "transport" => msg.transport = IrisSlice::from_bytes(v),
+            "cseq" => {
+                let mut p = v.splitn(2, |&b| b == b' ');
+                msg.cseq = p.next().and_then(|n| std::str::from_utf8(n).ok()).and_then(|n| n.parse().ok()).unwrap_or(0);
+                msg.cseq_method = IrisSlice::from_bytes(trim(p.next().unwrap_or(b"")));
+            }
+            "content-length" => match std::str::from_utf8(v).ok().and_then(|s| s.parse::<i64>().ok()) {
+                Some(n) if (0..=65_535).contains(&n) => msg.content_length = n,
+                _ => return -2,
+            },
+            "content-type" => is_sdp = v.len() >= 15 && v[..15].eq_ignore_ascii_case(b"application/sdp"),
+            _ => {}
+        }
+    }
+    if msg.user_agent.len == 0 { msg.user_agent = IrisSlice::from_bytes(server); }
+
+    // Without Content-Length (SIP over UDP) the body runs to the end of the datagram
+    let body = match msg.content_length {
+        n if n >= 0 => match buf.get(offset..offset + n as usize) { Some(b) => b, None => return -1 },
+        _ => &buf[offset..],
+    };
+    if is_sdp {
+        if let Some(sdp) = parse_sdp(body) {
+            msg.sdp_session_name = IrisSlice::from_bytes(sdp.session_name);
+            msg.sdp_connection = IrisSlice::from_bytes(sdp.connection);
+            (msg.media, msg.media_count) = alloc_array(sdp.media);
+        }
+    }
+    (msg.vias, msg.vias_count) = alloc_array(vias);
+    (msg.headers, msg.headers_count) = alloc_headers(headers);
+    unsafe { out.write(msg); }
+    0
+}
+
+/// Free arrays allocated by iris_sip_parse.
+#[no_mangle]
+pub extern "C" fn iris_sip_free(msg: *mut IrisSipMessage) {
+    if msg.is_null() { return; }
+    unsafe {
+        let m = &*msg;
+        free_array(m.vias, m.vias_count);
+        free_array(m.media, m.media_count);
+        free_headers(m.headers, m.headers_count);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn s(sl: &IrisSlice) -> &str {
+        if sl.len == 0 { return ""; }
+        unsafe { std::str::from_utf8_unchecked(slice::from_raw_parts(sl.ptr, sl.len)) }
+    }
+
+    fn parse(data: &[u8]) -> (i32, IrisSipMessage) {
+        let mut out = std::mem::MaybeUninit::<IrisSipMessage>::uninit();
+        let rc = iris_sip_parse(data.as_ptr(), data.len(), out.as_mut_ptr());
+        (rc, if rc == 0 { unsafe { out.assume_init() } } else { unsafe { std::mem::zeroed() } })
+    }
+
+    #[test]
+    fn sip_invite_with_sdp() {
+        let sdp = "v=0\r\no=alice 2890844526 2890844526 IN IP4 10.0.0.5\r\ns=Call\r\nc=IN IP4 10.0.0.5\r\nt=0 0\r\n\
+                   m=audio 49170 RTP/AVP 0 8 101\r\nm=video 51372 RTP/AVP 99\r\nc=IN IP4 10.0.0.6/127\r\n";
+        let msg = format!("INVITE sip:bob@example.com SIP/2.0\r\n\
+            Via: SIP/2.0/UDP pc33.example.com;branch=z9hG4bK776asdhds, SIP/2.0/UDP proxy.example.com\r\n\
+            v: SIP/2.0/TCP edge.example.com\r\n\
+            f: \"Alice, A.\" <sip:alice@example.com>;tag=1928301774\r\nTo: <sip:bob@example.com>\r\n\
+            i: a84b4c76e66710@pc33\r\nCSeq: 314159 INVITE\r\nContact: <sip:alice@10.0.0.5>\r\n\
+            User-Agent: Linphone/5.0\r\nc: application/sdp\r\nl: {}\r\n\r\n{}", sdp.len(), sdp);
+        let (rc, mut m) = parse(msg.as_bytes());
+        assert_eq!(rc, 0);
+        assert_eq!((m.protocol, m.is_request), (SIP_PROTO_SIP, true));
+        assert_eq!((s(&m.method), s(&m.uri), s(&m.version)), ("INVITE", "sip:bob@example.com", "SIP/2.0"));
+        assert_eq!(s(&m.from), "\"Alice, A.\" <sip:alice@example.com>;tag=1928301774");
+        assert_eq!(s(&m.call_id), "a84b4c76e66710@pc33");
+        assert_eq!((m.cseq, s(&m.cseq_method)), (314159, "INVITE"));
+        assert_eq!(s(&m.user_agent), "Linphone/5.0");
+        let vias = unsafe { slice::from_raw_parts(m.vias, m.vias_count) };
+        assert_eq!(vias.iter().map(s).collect::<Vec<_>>(), ["SIP/2.0/UDP pc33.example.com;branch=z9hG4bK776asdhds",
+            "SIP/2.0/UDP proxy.example.com", "SIP/2.0/TCP edge.example.com"]);
+        assert_eq!((s(&m.sdp_session_name), s(&m.sdp_connection)), ("Call", "10.0.0.5"));
+        let media = unsafe { slice::from_raw_parts(m.media, m.media_count) };
+        assert_eq!((s(&media[0].media), media[0].port, s(&media[0].proto), s(&media[0].formats), s(&media[0].connection)),
+                   ("audio", 49170, "RTP/AVP", "0 8 101", "10.0.0.5"));
+        assert_eq!((s(&media[1].media), media[1].port, s(&media[1].connection)), ("video", 51372, "10.0.0.6"));
+        iris_sip_free(&mut m);
+    }
+
+    #[test]
+    fn rtsp_response() {
+        let data = b"RTSP/1.0 200 OK\r\nCSeq: 3\r\nSession: 12345678;timeout=60\r\n\
+            Transport: RTP/AVP;unicast;client_port=8000-8001;server_port=9000-9001\r\nServer: GStreamer RTSP server\r\n\r\n";
+        let (rc, mut m) = parse(data);
+        assert_eq!(rc, 0);
+        assert_eq!((m.protocol, m.is_request, m.status_code, s(&m.reason)), (SIP_PROTO_RTSP, false, 200, "OK"));
+        assert_eq!((m.cseq, s(&m.call_id)), (3, "12345678"));
+        assert_eq!(s(&m.transport), "RTP/AVP;unicast;client_port=8000-8001;server_port=9000-9001");
+        assert_eq!(s(&m.user_agent), "GStreamer RTSP server");
+        iris_sip_free(&mut m);
+    }
+
+    #[test]
+    fn rejects_other_protocols_and_waits_for_body() {
+        assert_eq!(parse(b"GET / HTTP/1.1\r\nHost: x\r\n\r\n").0, -3);
+        assert_eq!(parse(b"OPTIONS rtsp://cam/stream RTSP/1.0\r\nCSeq: 1\r\n").0, -1);
+        assert_eq!(parse(b"ANNOUNCE rtsp://cam RTSP/1.0\r\nContent-Length: 10\r\n\r\nv=0").0, -1);
+    }
+}
@@ -0,0 +1,453 @@
+//! SMB2/3 parser (MS-SMB2): sync/async header, SMB3 transform header, and the
+//! NEGOTIATE / SESSION_SETUP / TREE_CONNECT bodies useful for spotting lateral movement.
+
+use crate::ffi::{alloc_array, free_array, to_cstr, free_cstr};
+use std::ffi::c_char;
+
+const SMB2_HEADER_LEN: usize = 64;
+const TRANSFORM_HEADER_LEN: usize = 52;
+
+pub const SMB2_NEGOTIATE: u16 = 0x0000;
+pub const SMB2_SESSION_SETUP: u16 = 0x0001;
+pub const SMB2_TREE_CONNECT: u16 = 0x0003;
+
+const FLAG_SERVER_TO_REDIR: u32 = 0x0000_0001;
+const FLAG_ASYNC: u32 = 0x0000_0002;
+const FLAG_SIGNED: u32 = 0x0000_0008;
+
+const CTX_ENCRYPTION: u16 = 0x0002;
+const CTX_NETNAME: u16 = 0x0005;
+
+pub const SMB_AUTH_NTLM: u8 = 1;
+pub const SMB_AUTH_KERBEROS: u8 = 2;
+
+const KRB5_OID: &[u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x12, 0x01, 0x02, 0x02];
+const MS_KRB5_OID: &[u8] = &[0x2A, 0x86, 0x48, 0x82, 0xF7, 0x12, 0x01, 0x02, 0x02];
+
+#[repr(C)]
+pub struct IrisSmb2Message {
+    pub encrypted: bool,            // SMB3 transform header; only session_id is meaningful
+    pub command: u16,
+    pub is_response: bool,
+    pub is_signed: bool,
+    pub status: u32,                // NTSTATUS (responses)
+    pub flags: u32,
+    pub credits: u16,
+    pub message_id: u64,
+    pub async_id: u64,              // async header only
+    pub tree_id: u32,               // sync header only
+    pub session_id: u64,
+    pub next_command: u32,          // offset of the next compounded message, 0 = last
+    // NEGOTIATE
+    pub security_mode: u16,
+    pub capabilities: u32,
+    pub guid: [u8; 16],             // client GUID (request) / server GUID (response)
+    pub dialects: *mut u16,         // offered dialects (request) or the selected one (response)
+    pub dialects_count: usize,
+    pub ciphers: *mut u16,          // SMB 3.1.1 encryption context
+    pub ciphers_count: usize,
+    pub netname: *mut c_char,       // SMB 3.1.1 NETNAME context (target server name)
+    // SESSION_SETUP
+    pub session_flags: u16,         // response: 0x1 guest, 0x2 null, 0x4 encrypt
+    pub auth_mech: u8,              // SMB_AUTH_*, 0 = unknown
+    pub ntlm_message_type: u8,      // 1 NEGOTIATE, 2 CHALLENGE, 3 AUTHENTICATE
+    pub ntlm_domain: *mut c_char,   // AUTHENTICATE only
+    pub ntlm_user: *mut c_char,
+    pub ntlm_workstation: *mut c_char,
+    pub ntlm_v1: bool,              // 24-byte NT response
+    // TREE_CONNECT
+    pub tree_path: *mut c_char,     // "\\server\share"
+    pub admin_share: bool,          // ADMIN$, C$ (any drive letter) or IPC$
+}
+
+#[derive(Default)]
+struct Smb2 {
+    encrypted: bool,
+    command: u16,
+    status: u32,
+    flags: u32,
+    credits: u16,
+    message_id: u64,
+    async_id: u64,
+    tree_id: u32,
+    session_id: u64,
+    next_command: u32,
+    security_mode: u16,
+    capabilities: u32,
+    guid: [u8; 16],
+    dialects: Vec<u16>,
+    ciphers: Vec<u16>,
+    netname: String,
+    session_flags: u16,
+    auth_mech: u8,
+    ntlm_type: u8,
+    ntlm_domain: String,
+    ntlm_user: String,
+    ntlm_workstation: String,
+    ntlm_v1: bool,
+    tree_path: String,
+}
+
+fn r16(d: &[u8], o: usize) -> u16 { u16::from_le_bytes([d[o], d[o + 1]]) }
+fn r32(d: &[u8], o: usize) -> u32 { u32::from_le_bytes([d[o], d[o + 1], d[o + 2], d[o + 3]]) }
+fn r64(d: &[u8], o: usize) -> u64 { r32(d, o) as u64 | (r32(d, o + 4) as u64) << 32 }
+
+fn utf16(d: &[u8]) -> String {
+    let units: Vec<u16> = d.chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect();
+    String::from_utf16_lossy(&units)
+}
+
+/// Sub-slice at an offset/length pair taken from the message, bounds-checked.
+fn field(d: &[u8], off: usize, len: usize) -> Option<&[u8]> {
+    d.get(off..off.checked_add(len)?)
+}
+
+fn negotiate_contexts(msg: &[u8], mut off: usize, count: u16, s: &mut Smb2) {
+    for _ in 0..count {
+        if off + 8 > msg.len() { return; }
+        let ctype = r16(msg, off);
+        let dlen = r16(msg, off + 2) as usize;
+        let data = match field(msg, off + 8, dlen) { Some(d) => d, None => return };
+        match ctype {
+            CTX_ENCRYPTION if data.len() >= 2 => {
+                let n = r16(data, 0) as usize;
+                s.ciphers = data[2..].chunks_exact(2).take(n).map(|c| u16::from_le_bytes([c[0], c[1]])).collect();
+            }
+            CTX_NETNAME => s.netname = utf16(data),
+            _ => {}
+        }
+        off = (off + 8 + dlen + 7) & !7;
+    }
+}
+
+fn negotiate(msg: &[u8], response: bool, s: &mut Smb2) {
+    let b = SMB2_HEADER_LEN;
+    if !response {
+        if msg.len() < b + 36 { return; }
+        let count = r16(msg, b + 2) as usize;
+        s.security_mode = r16(msg, b + 4);
+        s.capabilities = r32(msg, b + 8);
+        s.guid.copy_from_slice(&msg[b + 12..b + 28]);
+        s.dialects = msg[b + 36..].chunks_exact(2).take(count).map(|c| u16::from_le_bytes([c[0], c[1]])).collect();
+        if s.dialects.contains(&0x0311) {
+            negotiate_contexts(msg, r32(msg, b + 28) as usize, r16(msg, b + 32), s);
+        }
+    } else {
+        if msg.len() < b + 64 { return; }
+        s.security_mode = r16(msg, b + 2);
+        let dialect = r16(msg, b + 4);
+        s.dialects = vec![dialect];
+        s.guid.copy_from_slice(&msg[b + 8..b + 24]);
+        s.capabilities = r32(msg, b + 24);
+        if let Some(blob) = field(msg, r16(msg, b + 56) as usize, r16(msg, b + 58) as usize) {
+            security_blob(blob, s);
+        }
+        if dialect == 0x0311 {
+            negotiate_contexts(msg, r32(msg, b + 60) as usize, r16(msg, b + 6), s);
+        }
+    }
+}
+
+fn ntlm_string(m: &[u8], at: usize, unicode: bool) -> String {
+    if at + 8 > m.len() { return String::new(); }
+    match field(m, r32(m, at + 4) as usize, r16(m, at) as usize) {
+        Some(d) if unicode => utf16(d),
+        Some(d) => String::from_utf8_lossy(d).into_owned(),
+        None => String::new(),
+    }
+}
+
+#[derive(Default)]
+pub struct Ntlm {
+    pub message_type: u8,   // 1 NEGOTIATE, 2 CHALLENGE, 3 AUTHENTICATE
+    pub domain: String,     // AUTHENTICATE only
+    pub user: String,
+    pub workstation: String,
+    pub v1: bool,           // 24-byte NT response
+}
+
+/// Decode the NTLMSSP message found anywhere in `blob` (bare, or inside SPNEGO).
+pub fn ntlm_message(blob: &[u8]) -> Option<Ntlm> {
+    let p = blob.windows(8).position(|w| w == b"NTLMSSP\0")?;
+    let m = &blob[p..];
+    if m.len() < 12 { return None; }
+    let mut n = Ntlm { message_type: m[8], ..Default::default() };
+    if n.message_type == 3 && m.len() >= 64 {
+        let unicode = r32(m, 60) & 0x1 != 0;
+        n.v1 = r16(m, 20) == 24;
+        n.domain = ntlm_string(m, 28, unicode);
+        n.user = ntlm_string(m, 36, unicode);
+        n.workstation = ntlm_string(m, 44, unicode);
+    }
+    Some(n)
+}
+
+/// True if a GSS/SPNEGO blob names the Kerberos mechanism.
+pub fn is_kerberos_blob(blob: &[u8]) -> bool {
+    blob.windows(KRB5_OID.len()).any(|w| w == KRB5_OID || w == MS_KRB5_OID)
+}
+
+/// SPNEGO/GSS blob: classify the mechanism and decode an embedded NTLMSSP message.
+fn security_blob(blob: &[u8], s: &mut Smb2) {
+    if blob.windows(8).any(|w| w == b"NTLMSSP\0") {
+        s.auth_mech = SMB_AUTH_NTLM;
+        let Some(n) = ntlm_message(blob) else { return };
+        (s.ntlm_type, s.ntlm_domain, s.ntlm_user, s.ntlm_workstation, s.ntlm_v1) = (n.message_type, n.domain, n.user, n.workstation, n.v1);
+    } else if is_kerberos_blob(blob) {
+        s.auth_mech = SMB_AUTH_KERBEROS;
+    }
+}
+
+fn session_setup(msg: &[u8], response: bool, s: &mut Smb2) {
+    let b = SMB2_HEADER_LEN;
+    let (off_at, len_at) = if response { (b + 4, b + 6) } else { (b + 12, b + 14) };
+    if msg.len() < len_at + 2 { return; }
+    if response { s.session_flags = r16(msg, b + 2); }
+    if let Some(blob) = field(msg, r16(msg, off_at) as usize, r16(msg, len_at) as usize) {
+        security_blob(blob, s);
+    }
+}
+
+fn tree_connect(msg: &[u8], s: &mut Smb2) {
+    let b = SMB2_HEADER_LEN;
+    if msg.len() < b + 8 { return; }
+    if let Some(p) = field(msg, r16(msg, b + 4) as usize, r16(msg, b + 6) as usize) {
+        s.tree_path = utf16(p);
+    }
+}
+
+fn is_admin_share(path: &str) -> bool {
+    let share = path.rsplit('\\').next().unwrap_or("").to_ascii_uppercase();
+    share == "ADMIN$" || share == "IPC$"
+        || (share.len() == 2 && share.ends_with('$') && share.as_bytes()[0].is_ascii_alphabetic())
+}
+
+fn parse_smb2(msg: &[u8]) -> Result<Smb2, i32> {
+    if msg.len() < 4 { return Err(-1); }
+    match &msg[..4] {
+        b"\xFDSMB" => {
+            if msg.len() < TRANSFORM_HEADER_LEN { return Err(-1); }
+            return Ok(Smb2 { encrypted: true, session_id: r64(msg, 44), ..Default::default() });
+        }
+        b"\xFFSMB" => return Err(-3),
+        b"\xFESMB" => {}
+        _ => return Err(-2),
+    }
+    if msg.len() < SMB2_HEADER_LEN { return Err(-1); }
+    if r16(msg, 4) != 64 { return Err(-2); }
+    let flags = r32(msg, 16);
+    let mut s = Smb2 {
+        command: r16(msg, 12),
+        status: r32(msg, 8),
+        flags,
+        credits: r16(msg, 14),
+        next_command: r32(msg, 20),
+        message_id: r64(msg, 24),
+        session_id: r64(msg, 40),
+        ..Default::default()
+    };
+    if flags & FLAG_ASYNC != 0 { s.async_id = r64(msg, 32); } else { s.tree_id = r32(msg, 36); }
+    // Bodies are parsed within this message only; offsets are relative to its header.
+    let end = if s.next_command as usize >= SMB2_HEADER_LEN { (s.next_command as usize).min(msg.len()) } else { msg.len() };
+    let body = &msg[..end];
+    let response = flags & FLAG_SERVER_TO_REDIR != 0;
+    match s.command {
+        SMB2_NEGOTIATE => negotiate(body, response, &mut s),
+        SMB2_SESSION_SETUP => session_setup(body, response, &mut s),
+        SMB2_TREE_CONNECT if !response => tree_connect(body, &mut s),
+        _ => {}
+    }
+    Ok(s)
+}
+
+// --- FFI entry points ---
+
+/// Parse one SMB2/3 message, optionally preceded by the 4-byte NetBIOS session header.
+/// For compounded requests, call again at `next_command`.
+/// Returns 0=ok, -1=truncated, -2=not SMB2/arg error, -3=SMB1 header. Free with iris_smb2_free.
+#[no_mangle]
+pub extern "C" fn iris_smb2_parse(data: *const u8, len: usize, out: *mut IrisSmb2Message) -> i32 {
+    if data.is_null() || out.is_null() || len == 0 { return -2; }
+    let mut buf = unsafe { std::slice::from_raw_parts(data, len) };
+    if buf.len() >= 8 && buf[0] == 0 && &buf[5..8] == b"SMB" { buf = &buf[4..]; }
+    let s = match parse_smb2(buf) { Ok(s) => s, Err(e) => return e };
+    let admin_share = is_admin_share(&s.tree_path);
+    let (dialects, dialects_count) = alloc_array(s.dialects);
+    let (ciphers, ciphers_count) = alloc_array(s.ciphers);
+    unsafe {
+        out.write(IrisSmb2Message {
+            encrypted: s.encrypted,
+            command: s.command,
+            is_response: s.flags & FLAG_SERVER_TO_REDIR != 0,
+            is_signed: s.flags & FLAG_SIGNED != 0,
+            status: s.status,
+            flags: s.flags,
+            credits: s.credits,
+            message_id: s.message_id,
+            async_id: s.async_id,
+            tree_id: s.tree_id,
+            session_id: s.session_id,
+            next_command: s.next_command,
+            security_mode: s.security_mode,
+            capabilities: s.capabilities,
+            guid: s.guid,
+            dialects, dialects_count,
+            ciphers, ciphers_count,
+            netname: to_cstr(&s.netname),
+            session_flags: s.session_flags,
+            auth_mech: s.auth_mech,
+            ntlm_message_type: s.ntlm_type,
+            ntlm_domain: to_cstr(&s.ntlm_domain),
+            ntlm_user: to_cstr(&s.ntlm_user),
+            ntlm_workstation: to_cstr(&s.ntlm_workstation),
+            ntlm_v1: s.ntlm_v1,
+            tree_path: to_cstr(&s.tree_path),
+            admin_share,
+        });
+    }
+    0
+}
+
+/// Free allocations in an IrisSmb2Message.
+#[no_mangle]
+pub extern "C" fn iris_smb2_free(msg: *mut IrisSmb2Message) {
+    if msg.is_null() { return; }
+    unsafe {
+        let m = &*msg;
+        for p in [m.netname, m.ntlm_domain, m.ntlm_user, m.ntlm_workstation, m.tree_path] {
+            free_cstr(p);
+        }
+        free_array(m.dialects, m.dialects_count);
+        free_array(m.ciphers, m.ciphers_count);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(command: u16, flags: u32) -> Vec<u8> {
+        let mut h = vec![0u8; 64];
+        h[..4].copy_from_slice(b"\xFESMB");
+        h[4] = 64;
+        h[12..14].copy_from_slice(&command.to_le_bytes());
+        h[16..20].copy_from_slice(&flags.to_le_bytes());
+        h[24..32].copy_from_slice(&7u64.to_le_bytes());
+        h[36..40].copy_from_slice(&5u32.to_le_bytes());
+        h[40..48].copy_from_slice(&0x1122u64.to_le_bytes());
+        h
+    }
+
+    fn parse(data: &[u8]) -> (i32, Option<IrisSmb2Message>) {
+        let mut out = std::mem::MaybeUninit::<IrisSmb2Message>::uninit();
+        let rc = iris_smb2_parse(data.as_ptr(), data.len(), out.as_mut_ptr());
+        (rc, if rc == 0 { Some(unsafe { out.assume_init() }) } else { None })
+    }
+
+    fn cstr(p: *mut c_char) -> String {
+        unsafe { std::ffi::CStr::from_ptr(p) }.to_string_lossy().into_owned()
+    }
+
+    fn le16(s: &str) -> Vec<u8> { s.encode_utf16().flat_map(|u| u.to_le_bytes()).collect() }
+
+    #[test]
+    fn negotiate_request_with_contexts() {
+        let mut m = header(SMB2_NEGOTIATE, 0);
+        let dialects = [0x0202u16, 0x0210, 0x0300, 0x0302, 0x0311];
+        let mut body = vec![0u8; 36];
+        body[0] = 36;
+        body[2..4].copy_from_slice(&(dialects.len() as u16).to_le_bytes());
+        body[4] = 1;
+        body[8..12].copy_from_slice(&0x7fu32.to_le_bytes());
+        body[12..28].copy_from_slice(&[0xAB; 16]);
+        for d in dialects { body.extend_from_slice(&d.to_le_bytes()); }
+        while !(64 + body.len()).is_multiple_of(8) { body.push(0); }
+        let ctx_off = 64 + body.len();
+        body[28..32].copy_from_slice(&(ctx_off as u32).to_le_bytes());
+        body[32..34].copy_from_slice(&2u16.to_le_bytes());
+        // encryption: AES-128-GCM, AES-128-CCM
+        body.extend_from_slice(&[2, 0, 6, 0, 0, 0, 0, 0, 2, 0, 2, 0, 1, 0, 0, 0]);
+        let name = le16("fileserver");
+        body.extend_from_slice(&[5, 0, name.len() as u8, 0, 0, 0, 0, 0]);
+        body.extend_from_slice(&name);
+        m.extend_from_slice(&body);
+
+        let mut framed = vec![0, 0, 0, m.len() as u8];
+        framed.extend_from_slice(&m);
+        let (rc, msg) = parse(&framed);
+        assert_eq!(rc, 0);
+        let mut msg = msg.unwrap();
+        assert!(!msg.is_response);
+        assert_eq!((msg.message_id, msg.tree_id, msg.session_id), (7, 5, 0x1122));
+        assert_eq!(msg.capabilities, 0x7f);
+        assert_eq!(msg.guid, [0xAB; 16]);
+        let d = unsafe { std::slice::from_raw_parts(msg.dialects, msg.dialects_count) };
+        assert_eq!(d, dialects);
+        let c = unsafe { std::slice::from_raw_parts(msg.ciphers, msg.ciphers_count) };
+        assert_eq!(c, [2, 1]);
+        assert_eq!(cstr(msg.netname), "fileserver");
+        iris_smb2_free(&mut msg);
+    }
+
+    #[test]
+    fn session_setup_ntlm_authenticate() {
+        let mut m = header(SMB2_SESSION_SETUP, FLAG_SIGNED);
+        let (dom, user, ws) = (le16("CORP"), le16("alice"), le16("MAC01"));
+        let mut ntlm = b"NTLMSSP\0".to_vec();
+        ntlm.extend_from_slice(&3u32.to_le_bytes());
+        ntlm.resize(64, 0);
+        let mut payload = vec![0u8; 24]; // NTLMv1-sized NT response
+        let nt_off = 64;
+        let put = |at: usize, len: usize, off: usize, n: &mut Vec<u8>| {
+            n[at..at + 2].copy_from_slice(&(len as u16).to_le_bytes());
+            n[at + 2..at + 4].copy_from_slice(&(len as u16).to_le_bytes());
+            n[at + 4..at + 8].copy_from_slice(&(off as u32).to_le_bytes());
+        };
+        put(20, 24, nt_off, &mut ntlm);
+        put(28, dom.len(), nt_off + 24, &mut ntlm);
+        put(36, user.len(), nt_off + 24 + dom.len(), &mut ntlm);
+        put(44, ws.len(), nt_off + 24 + dom.len() + user.len(), &mut ntlm);
+        ntlm[60] = 0x01;
+        payload.extend_from_slice(&dom);
+        payload.extend_from_slice(&user);
+        payload.extend_from_slice(&ws);
+        ntlm.extend_from_slice(&payload);
+
+        let mut body = vec![0u8; 24];
+        body[0] = 25;
+        body[12..14].copy_from_slice(&88u16.to_le_bytes());
+        body[14..16].copy_from_slice(&(ntlm.len() as u16).to_le_bytes());
+        m.extend_from_slice(&body);
+        m.extend_from_slice(&ntlm);
+
+        let (rc, msg) = parse(&m);
+        assert_eq!(rc, 0);
+        let mut msg = msg.unwrap();
+        assert!(msg.is_signed);
+        assert_eq!(msg.auth_mech, SMB_AUTH_NTLM);
+        assert_eq!(msg.ntlm_message_type, 3);
+        assert_eq!(cstr(msg.ntlm_domain), "CORP");
+        assert_eq!(cstr(msg.ntlm_user), "alice");
+        assert_eq!(cstr(msg.ntlm_workstation), "MAC01");
+        assert!(msg.ntlm_v1);
+        iris_smb2_free(&mut msg);
+    }
+
+    #[test]
+    fn tree_connect_admin_share_and_smb1() {
+        let mut m = header(SMB2_TREE_CONNECT, 0);
+        let path = le16("\\\\10.0.0.5\\C$");
+        let mut body = vec![9, 0, 0, 0, 72, 0, path.len() as u8, 0];
+        body.extend_from_slice(&path);
+        m.extend_from_slice(&body);
+        let (rc, msg) = parse(&m);
+        assert_eq!(rc, 0);
+        let mut msg = msg.unwrap();
+        assert_eq!(cstr(msg.tree_path), "\\\\10.0.0.5\\C$");
+        assert!(msg.admin_share);
+        iris_smb2_free(&mut msg);
+
+        assert_eq!(parse(b"\xFFSMBr\0\0\0\0").0, -3);
+        assert_eq!(parse(b"\xFESMB").0, -1);
+    }
+}
@@ -0,0 +1,438 @@
+//! Incremental SMTP dialogue parser (RFC 5321): commands, multi-line replies,
+//! STARTTLS/AUTH tracking, and DATA/BDAT message boundaries with header extraction.
+
+use crate::base64;
+use crate::ffi::{IrisCStringArray, vec_to_c_string_array, free_c_string_array, to_cstr, free_cstr};
+use std::ffi::c_char;
+
+const MAX_LINE: usize = 16 * 1024;
+const MAX_HEADER_BYTES: usize = 64 * 1024;
+
+pub const SMTP_EVENT_COMMAND: u8 = 1;
+pub const SMTP_EVENT_REPLY: u8 = 2;
+pub const SMTP_EVENT_AUTH: u8 = 3;
+pub const SMTP_EVENT_MESSAGE: u8 = 4;
+pub const SMTP_EVENT_TLS_STARTED: u8 = 5;
+
+#[repr(C)]
+pub struct IrisSmtpEvent {
+    pub kind: u8,                  // SMTP_EVENT_*
+    pub reply_code: u16,           // REPLY only
+    pub verb: *mut c_char,         // uppercase command verb / AUTH mechanism
+    pub argument: *mut c_char,     // command argument / reply text (lines joined by \n)
+    pub address: *mut c_char,      // MAIL FROM / RCPT TO path without <>
+    pub username: *mut c_char,     // decoded AUTH identity
+    pub cleartext_auth: bool,      // credentials sent before STARTTLS
+    pub header_names: IrisCStringArray,  // MESSAGE only
+    pub header_values: IrisCStringArray,
+    pub message_size: usize,       // MESSAGE only, after dot-unstuffing
+}
+
+#[repr(C)]
+pub struct IrisSmtpEvents {
+    pub events: *mut IrisSmtpEvent,
+    pub count: usize,
+}
+
+#[derive(Default)]
+struct Event {
+    kind: u8,
+    code: u16,
+    verb: String,
+    argument: String,
+    address: String,
+    username: String,
+    cleartext: bool,
+    headers: Vec<(String, String)>,
+    size: usize,
+}
+
+#[derive(PartialEq)]
+enum AuthStep { None, PlainResponse, LoginUser, LoginPassword, CramResponse }
+
+/// Opaque per-connection state. Create with iris_smtp_new.
+pub struct IrisSmtpSession {
+    client_buf: Vec<u8>,
+    server_buf: Vec<u8>,
+    reply_lines: Vec<String>,
+    in_data: bool,
+    bdat_remaining: usize,
+    bdat_last: bool,
+    message: Vec<u8>,
+    message_size: usize,
+    starttls_pending: bool,
+    tls: bool,
+    auth: AuthStep,
+    auth_mech: String,
+    events: Vec<Event>,
+}
+
+fn take_line(buf: &mut Vec<u8>) -> Option<Vec<u8>> {
+    let nl = buf.iter().position(|&b| b == b'\n')?;
+    let mut line: Vec<u8> = buf.drain(..=nl).collect();
+    line.pop();
+    if line.last() == Some(&b'\r') { line.pop(); }
+    Some(line)
+}
+
+fn strip_path(arg: &str) -> String {
+    let a = arg.trim();
+    match (a.find('<'), a.find('>')) {
+        (Some(s), Some(e)) if e > s => a[s + 1..e].to_string(),
+        _ => a.split_whitespace().next().unwrap_or("").to_string(),
+    }
+}
+
+/// Unfold and split RFC 5322 headers from the start of a message.
+fn parse_headers(msg: &[u8]) -> Vec<(String, String)> {
+    let text = String::from_utf8_lossy(msg);
+    let mut out: Vec<(String, String)> = Vec::new();
+    for line in text.split('\n') {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        if line.is_empty() { break; }
+        if line.starts_with(' ') || line.starts_with('\t') {
+            if let Some(last) = out.last_mut() {
+                last.1.push(' ');
+                last.1.push_str(line.trim());
+            }
+            continue;
+        }
+        if let Some((n, v)) = line.split_once(':') {
+            out.push((n.trim().to_string(), v.trim().to_string()));
+        }
+    }
+    out
+}
+
+fn decode_text(b64: &str) -> String {
+    base64::decode(b64.as_bytes()).map(|v| String::from_utf8_lossy(&v).into_owned()).unwrap_or_default()
+}
+
+impl IrisSmtpSession {
+    fn new() -> Self {
+        IrisSmtpSession {
+            client_buf: Vec::new(), server_buf: Vec::new(), reply_lines: Vec::new(),
+            in_data: false, bdat_remaining: 0, bdat_last: false,
+            message: Vec::new(), message_size: 0,
+            starttls_pending: false, tls: false,
+            auth: AuthStep::None, auth_mech: String::new(), events: Vec::new(),
+        }
+    }
+
+    fn append_message(&mut self, data: &[u8]) {
+        self.message_size += data.len();
+        let room = MAX_HEADER_BYTES.saturating_sub(self.message.len());
+        self.message.extend_from_slice(&data[..data.len().min(room)]);
+    }
+
+    fn finish_message(&mut self) {
+        let headers = parse_headers(&self.message);
+        self.events.push(Event {
+            kind: SMTP_EVENT_MESSAGE, headers, size: self.message_size, ..Default::default()
+        });
+        self.message.clear();
+        self.message_size = 0;
+    }
+
+    fn auth_event(&mut self, username: String) {
+        self.events.push(Event {
+            kind: SMTP_EVENT_AUTH, verb: self.auth_mech.clone(), username,
+            cleartext: !self.tls, ..Default::default()
+        });
+    }
+
+    fn client_line(&mut self, line: &[u8]) {
+        let text = String::from_utf8_lossy(line).into_owned();
+        match std::mem::replace(&mut self.auth, AuthStep::None) {
+            AuthStep::PlainResponse => {
+                let decoded = decode_text(&text);
+                let user = decoded.split('\0').nth(1).unwrap_or("").to_string();
+                return self.auth_event(user);
+            }
+            AuthStep::LoginUser => {
+                self.auth = AuthStep::LoginPassword;
+                return self.auth_event(decode_text(&text));
+            }
+            AuthStep::CramResponse => {
+                let decoded = decode_text(&text);
+                let user = decoded.split(' ').next().unwrap_or("").to_string();
+                return self.auth_event(user);
+            }
+            AuthStep::LoginPassword => return, // never surface the password
+            AuthStep::None => {}
+        }
+
+        let (verb, arg) = match text.split_once(' ') {
+            Some((v, a)) => (v.to_ascii_uppercase(), a.trim().to_string()),
+            None => (text.trim().to_ascii_uppercase(), String::new()),
+        };
+        let mut ev = Event { kind: SMTP_EVENT_COMMAND, verb: verb.clone(), ..Default::default() };
+        match verb.as_str() {
+            "MAIL" | "RCPT" => {
+                if let Some((_, path)) = arg.split_once(':') { ev.address = strip_path(path); }
+            }
+            "DATA" => { self.in_data = true; self.message.clear(); self.message_size = 0; }
+            "BDAT" => {
+                let mut parts = arg.split_whitespace();
+                self.bdat_remaining = parts.next().and_then(|n| n.parse().ok()).unwrap_or(0);
+                self.bdat_last = parts.next().is_some_and(|p| p.eq_ignore_ascii_case("LAST"));
+                // An empty last chunk ends the message with no octets to wait for
+                if self.bdat_remaining == 0 && self.bdat_last { self.finish_message(); }
+            }
+            "STARTTLS" => self.starttls_pending = true,
+            "AUTH" => {
+                let mut parts = arg.split_whitespace();
+                self.auth_mech = parts.next().unwrap_or("").to_ascii_uppercase();
+                let initial = parts.next();
+                match (self.auth_mech.as_str(), initial) {
+                    ("PLAIN", Some(ir)) => {
+                        let decoded = decode_text(ir);
+                        let user = decoded.split('\0').nth(1).unwrap_or("").to_string();
+                        self.events.push(ev);
+                        return self.auth_event(user);
+                    }
+                    ("PLAIN", None) => self.auth = AuthStep::PlainResponse,
+                    ("LOGIN", Some(ir)) => {
+                        self.auth = AuthStep::LoginPassword;
+                        self.events.push(ev);
+                        return self.auth_event(decode_text(ir));
+                    }
+                    ("LOGIN", None) => self.auth = AuthStep::LoginUser,
+                    ("CRAM-MD5", _) => self.auth = AuthStep::CramResponse,
+                    _ => {}
+                }
+                ev.argument = self.auth_mech.clone(); // never echo initial responses
+                self.events.push(ev);
+                return;
+            }
+            _ => {}
+        }
+        ev.argument = arg;
+        self.events.push(ev);
+    }
+
+    fn client_text(&mut self, line: &[u8]) {
+        if !self.in_data { return self.client_line(line); }
+        if line == b"." {
+            self.in_data = false;
+            return self.finish_message();
+        }
+        let body = if line.starts_with(b"..") { &line[1..] } else { line };
+        self.append_message(body);
+        self.append_message(b"\r\n");
+    }
+
+    /// One pass over `data`, switching between BDAT octets and lines; only a
+    /// trailing partial line is buffered.
+    fn feed_client(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            // BDAT chunks are raw octets, not lines
+            if self.bdat_remaining > 0 {
+                let n = self.bdat_remaining.min(data.len());
+                self.append_message(&data[..n]);
+                self.bdat_remaining -= n;
+                data = &data[n..];
+                if self.bdat_remaining == 0 && self.bdat_last { self.finish_message(); }
+                continue;
+            }
+            let Some(nl) = data.iter().position(|&b| b == b'\n') else {
+                self.client_buf.extend_from_slice(data);
+                break;
+            };
+            let mut buf = std::mem::take(&mut self.client_buf);
+            let line = if buf.is_empty() {
+                &data[..nl]
+            } else {
+                buf.extend_from_slice(&data[..nl]);
+                &buf[..]
+            };
+            self.client_text(line.strip_suffix(b"\r").unwrap_or(line));
+            buf.clear();
+            self.client_buf = buf;
+            data = &data[nl + 1..];
+        }
+        if self.client_buf.len() > MAX_LINE { self.client_buf.clear(); }
+    }
+
+    fn feed_server(&mut self, data: &[u8]) {
+        self.server_buf.extend_from_slice(data);
+        while let Some(line) = take_line(&mut self.server_buf) {
+            if line.len() < 3 { continue; }
+            let code: u16 = match std::str::from_utf8(&line[..3]).ok().and_then(|s| s.parse().ok()) {
+                Some(c) => c, None => continue,
+            };
+            let text = String::from_utf8_lossy(line.get(4..).unwrap_or(&[])).into_owned();
+            self.reply_lines.push(text);
+            if line.get(3) == Some(&b'-') { continue; } // multi-line continuation
+
+            let lines = std::mem::take(&mut self.reply_lines);
+            if self.in_data && code >= 400 {
+                self.in_data = false; // DATA rejected; nothing follows
+                self.message.clear();
+            }
+            if code >= 400 && self.auth != AuthStep::None { self.auth = AuthStep::None; }
+            let tls_now = self.starttls_pending && code == 220;
+            if self.starttls_pending && code != 220 { self.starttls_pending = false; }
+            self.events.push(Event {
+                kind: SMTP_EVENT_REPLY, code, argument: lines.join("\n"), ..Default::default()
+            });
+            if tls_now {
+                self.starttls_pending = false;
+                self.tls = true;
+                self.events.push(Event { kind: SMTP_EVENT_TLS_STARTED, ..Default::default() });
+                self.server_buf.clear();
+                self.client_buf.clear();
+                return;
+            }
+        }
+        if self.server_buf.len() > MAX_LINE { self.server_buf.clear(); }
+    }
+}
+
+fn alloc_events(evs: Vec<Event>) -> (*mut IrisSmtpEvent, usize) {
+    let count = evs.len();
+    if count == 0 { return (std::ptr::null_mut(), 0); }
+    let layout = std::alloc::Layout::array::<IrisSmtpEvent>(count).unwrap();
+    let ptr = unsafe { std::alloc::alloc(layout) as *mut IrisSmtpEvent };
+    if ptr.is_null() { return (std::ptr::null_mut(), 0); }
+    for (i, e) in evs.into_iter().enumerate() {
+        let (names, values): (Vec<String>, Vec<String>) = e.headers.into_iter().unzip();
+        unsafe {
+            ptr.add(i).write(IrisSmtpEvent {
+                kind: e.kind, reply_code: e.code,
+                verb: to_cstr(&e.verb), argument: to_cstr(&e.argument),
+                address: to_cstr(&e.address), username: to_cstr(&e.username),
+                cleartext_auth: e.cleartext,
+                header_names: vec_to_c_string_array(names),
+                header_values: vec_to_c_string_array(values),
+                message_size: e.size,
+            });
+        }
+    }
+    (ptr, count)
+}
+
+// --- FFI entry points ---
+
+/// Create an SMTP session tracker for one TCP connection. Free with iris_smtp_free.
+#[no_mangle]
+pub extern "C" fn iris_smtp_new() -> *mut IrisSmtpSession {
+    Box::into_raw(Box::new(IrisSmtpSession::new()))
+}
+
+/// Feed bytes from one direction. Events completed by this chunk are returned in
+/// `out` (free with iris_smtp_free_events). After STARTTLS succeeds, input is ignored.
+/// Returns 0=ok, -2=arg error.
+#[no_mangle]
+pub extern "C" fn iris_smtp_feed(
+    session: *mut IrisSmtpSession, data: *const u8, len: usize, from_client: bool,
+    out: *mut IrisSmtpEvents,
+) -> i32 {
+    if session.is_null() || out.is_null() || (data.is_null() && len > 0) { return -2; }
+    let s = unsafe { &mut *session };
+    if !s.tls && len > 0 {
+        let buf = unsafe { std::slice::from_raw_parts(data, len) };
+        if from_client { s.feed_client(buf); } else { s.feed_server(buf); }
+    }
+    let (events, count) = alloc_events(std::mem::take(&mut s.events));
+    unsafe { out.write(IrisSmtpEvents { events, count }); }
+    0
+}
+
+/// Free events returned by iris_smtp_feed.
+#[no_mangle]
+pub extern "C" fn iris_smtp_free_events(evs: *mut IrisSmtpEvents) {
+    if evs.is_null() { return; }
+    unsafe {
+        let e = &*evs;
+        if e.events.is_null() || e.count == 0 { return; }
+        for i in 0..e.count {
+            let ev = &*e.events.add(i);
+            free_cstr(ev.verb);
+            free_cstr(ev.argument);
+            free_cstr(ev.address);
+            free_cstr(ev.username);
+            free_c_string_array(&ev.header_names);
+            free_c_string_array(&ev.header_values);
+        }
+        let layout = std::alloc::Layout::array::<IrisSmtpEvent>(e.count).unwrap();
+        std::alloc::dealloc(e.events as *mut u8, layout);
+    }
+}
+
+/// Free a session created by iris_smtp_new.
+#[no_mangle]
+pub extern "C" fn iris_smtp_free(session: *mut IrisSmtpSession) {
+    if session.is_null() { return; }
+    unsafe { drop(Box::from_raw(session)); }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dialogue_with_auth_and_data() {
+        let mut s = IrisSmtpSession::new();
+        s.feed_server(b"220 mx.example.com ESMTP\r\n");
+        s.feed_client(b"EHLO laptop\r\n");
+        s.feed_server(b"250-mx.example.com\r\n250-AUTH PLAIN LOGIN\r\n250 STARTTLS\r\n");
+        s.feed_client(b"AUTH PLAIN AGFsaWNlAHNlY3JldA==\r\n"); // \0alice\0secret
+        s.feed_client(b"MAIL FROM:<alice@example.com> SIZE=100\r\nRCPT TO:<bob@example.org>\r\nDATA\r\n");
+        s.feed_client(b"From: alice@example.com\r\nSubject: hi\r\n there\r\n\r\n..dot\r\n.\r\n");
+        let evs = std::mem::take(&mut s.events);
+        let reply = evs.iter().find(|e| e.kind == SMTP_EVENT_REPLY && e.code == 250).unwrap();
+        assert_eq!(reply.argument, "mx.example.com\nAUTH PLAIN LOGIN\nSTARTTLS");
+        let auth = evs.iter().find(|e| e.kind == SMTP_EVENT_AUTH).unwrap();
+        assert_eq!(auth.verb, "PLAIN");
+        assert_eq!(auth.username, "alice");
+        assert!(auth.cleartext);
+        assert!(evs.iter().all(|e| !e.argument.contains("AGFsaWNl")));
+        let rcpt = evs.iter().find(|e| e.verb == "RCPT").unwrap();
+        assert_eq!(rcpt.address, "bob@example.org");
+        let msg = evs.iter().find(|e| e.kind == SMTP_EVENT_MESSAGE).unwrap();
+        assert_eq!(msg.headers[1], ("Subject".to_string(), "hi there".to_string()));
+        assert_eq!(msg.size, "From: alice@example.com\r\nSubject: hi\r\n there\r\n\r\n.dot\r\n".len());
+    }
+
+    #[test]
+    fn starttls_stops_parsing() {
+        let mut s = IrisSmtpSession::new();
+        s.feed_client(b"STARTTLS\r\n");
+        s.feed_server(b"220 Ready to start TLS\r\n");
+        assert!(s.tls);
+        assert_eq!(s.events.last().unwrap().kind, SMTP_EVENT_TLS_STARTED);
+    }
+
+    #[test]
+    fn pipelined_bdat_burst() {
+        // Once recursed per command; 20,000 of these overflowed the stack
+        let n = 20_000;
+        let mut s = IrisSmtpSession::new();
+        s.feed_client(&b"BDAT 1\r\nx".repeat(n));
+        s.feed_client(b"BDAT 2 LAST\r\nyz");
+        let evs = std::mem::take(&mut s.events);
+        assert_eq!(evs.iter().filter(|e| e.verb == "BDAT").count(), n + 1);
+        let msgs: Vec<&Event> = evs.iter().filter(|e| e.kind == SMTP_EVENT_MESSAGE).collect();
+        assert_eq!(msgs.len(), 1);
+        assert_eq!(msgs[0].size, n + 2);
+        assert!(s.client_buf.is_empty());
+
+        // A chunk split across feeds, then a command in the same segment
+        s.feed_client(b"BDAT 5 LAST\r\nab");
+        s.feed_client(b"cdeQUIT\r\n");
+        let evs = std::mem::take(&mut s.events);
+        assert_eq!(evs.iter().find(|e| e.kind == SMTP_EVENT_MESSAGE).unwrap().size, 5);
+        assert_eq!(evs.last().unwrap().verb, "QUIT");
+    }
+
+    #[test]
+    fn bdat_zero_last() {
+        let mut s = IrisSmtpSession::new();
+        s.feed_client(b"BDAT 20\r\nSubject: chunked\r\n\r\n");
+        s.feed_client(b"BDAT 0 LAST\r\n");
+        let msg = s.events.iter().find(|e| e.kind == SMTP_EVENT_MESSAGE).unwrap();
+        assert_eq!(msg.size, 20);
+        assert_eq!(msg.headers, [("Subject".to_string(), "chunked".to_string())]);
+    }
+}
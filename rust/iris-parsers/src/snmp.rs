@@ -0,0 +1,351 @@
+//! SNMP v1/v2c/v3 message parser (RFC 1157, RFC 3416, RFC 3412/3414) built on the
+//! BER decoder in der.rs. Flags default communities, writes and bulk-walk patterns.
+
+use crate::der::{read_tlv, DerReader, Tlv};
+use crate::ffi::{alloc_array, free_array, to_cstr, free_cstr};
+use crate::hash::to_hex;
+use std::ffi::c_char;
+
+/// PDU types are the context tag number (0=Get .. 8=Report).
+pub const SNMP_PDU_GETNEXT: u8 = 1;
+pub const SNMP_PDU_SET: u8 = 3;
+pub const SNMP_PDU_TRAP_V1: u8 = 4;
+pub const SNMP_PDU_GETBULK: u8 = 5;
+pub const SNMP_PDU_ENCRYPTED: u8 = 0xFF; // v3 with privacy: PDU not visible
+
+pub const SNMP_FLAG_DEFAULT_COMMUNITY: u32 = 1 << 0; // "public", "private", ...
+pub const SNMP_FLAG_WRITE: u32 = 1 << 1;             // SetRequest
+pub const SNMP_FLAG_BULK_WALK: u32 = 1 << 2;         // GetBulk with large max-repetitions
+pub const SNMP_FLAG_ROOT_WALK: u32 = 1 << 3;         // GetNext/GetBulk from a top-level subtree
+pub const SNMP_FLAG_V3_NOAUTH: u32 = 1 << 4;         // v3 message without authentication
+
+/// Varbind value types (BER tag for the value).
+pub const SNMP_VALUE_INTEGER: u8 = 0x02;
+pub const SNMP_VALUE_OCTETS: u8 = 0x04;
+pub const SNMP_VALUE_NULL: u8 = 0x05;
+pub const SNMP_VALUE_OID: u8 = 0x06;
+pub const SNMP_VALUE_IPADDR: u8 = 0x40;
+pub const SNMP_VALUE_COUNTER32: u8 = 0x41;
+pub const SNMP_VALUE_GAUGE32: u8 = 0x42;
+pub const SNMP_VALUE_TIMETICKS: u8 = 0x43;
+pub const SNMP_VALUE_COUNTER64: u8 = 0x46;
+pub const SNMP_VALUE_NO_SUCH_OBJECT: u8 = 0x80;
+pub const SNMP_VALUE_NO_SUCH_INSTANCE: u8 = 0x81;
+pub const SNMP_VALUE_END_OF_MIB_VIEW: u8 = 0x82;
+
+const DEFAULT_COMMUNITIES: &[&str] = &[
+    "public", "private", "community", "manager", "admin", "cisco", "default", "snmp", "secret",
+];
+const BULK_WALK_MIN_REPETITIONS: i32 = 10;
+const ROOT_WALK_MAX_ARCS: usize = 6; // 1.3.6.1.2.1 and above
+
+#[repr(C)]
+pub struct IrisSnmpVarbind {
+    pub oid: *mut c_char,        // dotted decimal
+    pub value_type: u8,          // SNMP_VALUE_*
+    pub value: *mut c_char,      // display form; octet strings are hex unless printable
+}
+
+#[repr(C)]
+pub struct IrisSnmpMessage {
+    pub version: u8,             // 0=v1, 1=v2c, 3=v3
+    pub community: *mut c_char,  // v1/v2c only
+    pub pdu_type: u8,            // context tag 0..8, or SNMP_PDU_ENCRYPTED
+    pub request_id: i32,
+    pub error_status: i32,       // GetBulk: non-repeaters
+    pub error_index: i32,        // GetBulk: max-repetitions
+    pub varbinds: *mut IrisSnmpVarbind,
+    pub varbinds_count: usize,
+    // v1 Trap
+    pub trap_enterprise: *mut c_char,
+    pub trap_generic: i32,
+    pub trap_specific: i32,
+    // v3
+    pub msg_flags: u8,           // 0x1 auth, 0x2 priv, 0x4 reportable
+    pub security_model: i32,     // 3 = USM
+    pub engine_id: *mut c_char,  // hex
+    pub user_name: *mut c_char,
+    pub context_name: *mut c_char,
+    pub flags: u32,              // SNMP_FLAG_*
+}
+
+struct Varbind { oid: String, value_type: u8, value: String }
+
+#[derive(Default)]
+struct Snmp {
+    version: u8,
+    community: String,
+    pdu_type: u8,
+    request_id: i32,
+    error_status: i32,
+    error_index: i32,
+    varbinds: Vec<Varbind>,
+    enterprise: String,
+    generic: i32,
+    specific: i32,
+    msg_flags: u8,
+    security_model: i32,
+    engine_id: String,
+    user_name: String,
+    context_name: String,
+}
+
+fn unsigned(c: &[u8]) -> u64 {
+    c.iter().skip_while(|&&b| b == 0).take(8).fold(0u64, |v, &b| (v << 8) | b as u64)
+}
+
+fn display(v: &Tlv) -> (u8, String) {
+    let tag = (v.class << 6) | v.number as u8;
+    let text = match tag {
+        SNMP_VALUE_INTEGER => v.as_i64().map(|i| i.to_string()).unwrap_or_default(),
+        SNMP_VALUE_OCTETS => match std::str::from_utf8(v.content) {
+            Ok(s) if s.chars().all(|c| !c.is_control() || c == '\n' || c == '\r' || c == '\t') => s.to_string(),
+            _ => to_hex(v.content),
+        },
+        SNMP_VALUE_OID => v.as_oid().unwrap_or_default(),
+        SNMP_VALUE_IPADDR if v.content.len() == 4 => {
+            let c = v.content;
+            format!("{}.{}.{}.{}", c[0], c[1], c[2], c[3])
+        }
+        SNMP_VALUE_COUNTER32 | SNMP_VALUE_GAUGE32 | SNMP_VALUE_TIMETICKS | SNMP_VALUE_COUNTER64 => {
+            unsigned(v.content).to_string()
+        }
+        SNMP_VALUE_NULL | SNMP_VALUE_NO_SUCH_OBJECT | SNMP_VALUE_NO_SUCH_INSTANCE
+            | SNMP_VALUE_END_OF_MIB_VIEW => String::new(),
+        _ => to_hex(v.content),
+    };
+    (tag, text)
+}
+
+fn int(t: Option<Tlv>) -> i32 { t.and_then(|t| t.as_i64()).unwrap_or(0) as i32 }
+
+fn varbinds(list: Option<Tlv>) -> Vec<Varbind> {
+    let list = match list { Some(l) => l, None => return Vec::new() };
+    list.children().filter_map(|vb| {
+        let mut it = vb.children();
+        let oid = it.next()?.as_oid()?;
+        let (value_type, value) = it.next().map(|v| display(&v)).unwrap_or((SNMP_VALUE_NULL, String::new()));
+        Some(Varbind { oid, value_type, value })
+    }).collect()
+}
+
+fn pdu(p: &Tlv, s: &mut Snmp) -> Option<()> {
+    if p.class != 2 || p.number > 8 { return None; }
+    s.pdu_type = p.number as u8;
+    let mut it = p.children();
+    if s.pdu_type == SNMP_PDU_TRAP_V1 {
+        s.enterprise = it.next()?.as_oid().unwrap_or_default();
+        it.next(); // agent-addr
+        s.generic = int(it.next());
+        s.specific = int(it.next());
+        it.next(); // time-stamp
+    } else {
+        s.request_id = int(it.next());
+        s.error_status = int(it.next());
+        s.error_index = int(it.next());
+    }
+    s.varbinds = varbinds(it.next());
+    Some(())
+}
+
+/// msgGlobalData, USM security parameters, then a ScopedPDU or encrypted OCTET STRING.
+fn v3(mut it: DerReader, s: &mut Snmp) -> Option<()> {
+    let global = it.next()?;
+    let mut g = global.children();
+    g.next(); // msgID
+    g.next(); // msgMaxSize
+    s.msg_flags = g.next().and_then(|f| f.content.first().copied()).unwrap_or(0);
+    s.security_model = int(g.next());
+    if let Some(sec) = it.next().and_then(|o| read_tlv(o.content).map(|(t, _)| t)) {
+        let mut u = sec.children();
+        s.engine_id = u.next().map(|e| to_hex(e.content)).unwrap_or_default();
+        u.next(); // boots
+        u.next(); // time
+        s.user_name = u.next().map(|n| n.as_string()).unwrap_or_default();
+    }
+    let data = it.next()?;
+    if data.is(0, 4) {
+        s.pdu_type = SNMP_PDU_ENCRYPTED;
+        return Some(());
+    }
+    let mut sc = data.children();
+    sc.next(); // contextEngineID
+    s.context_name = sc.next().map(|n| n.as_string()).unwrap_or_default();
+    pdu(&sc.next()?, s)
+}
+
+fn parse_snmp(data: &[u8]) -> Option<Snmp> {
+    let (msg, _) = read_tlv(data)?;
+    if !msg.is(0, 16) { return None; }
+    let mut it = msg.children();
+    let version = it.next()?.as_i64()?;
+    let mut s = Snmp { version: version as u8, ..Default::default() };
+    match version {
+        0 | 1 => {
+            let community = it.next()?;
+            if !community.is(0, 4) { return None; }
+            s.community = community.as_string();
+            pdu(&it.next()?, &mut s)?;
+        }
+        3 => v3(it, &mut s)?,
+        _ => return None,
+    }
+    Some(s)
+}
+
+pub fn is_snmp(data: &[u8]) -> bool { parse_snmp(data).is_some() }
+
+fn flags(s: &Snmp) -> u32 {
+    let mut f = 0;
+    if s.version < 3 && DEFAULT_COMMUNITIES.iter().any(|c| c.eq_ignore_ascii_case(&s.community)) {
+        f |= SNMP_FLAG_DEFAULT_COMMUNITY;
+    }
+    if s.pdu_type == SNMP_PDU_SET { f |= SNMP_FLAG_WRITE; }
+    if s.pdu_type == SNMP_PDU_GETBULK && s.error_index >= BULK_WALK_MIN_REPETITIONS { f |= SNMP_FLAG_BULK_WALK; }
+    if matches!(s.pdu_type, SNMP_PDU_GETNEXT | SNMP_PDU_GETBULK)
+        && s.varbinds.iter().any(|v| v.oid.split('.').count() <= ROOT_WALK_MAX_ARCS)
+    {
+        f |= SNMP_FLAG_ROOT_WALK;
+    }
+    if s.version == 3 && s.msg_flags & 0x1 == 0 { f |= SNMP_FLAG_V3_NOAUTH; }
+    f
+}
+
+// --- FFI entry points ---
+
+/// Parse an SNMP message (UDP payload). Returns 0=ok, -1=not SNMP/truncated,
+/// -2=arg error. Free with iris_snmp_free.
+#[no_mangle]
+pub extern "C" fn iris_snmp_parse(data: *const u8, len: usize, out: *mut IrisSnmpMessage) -> i32 {
+    if data.is_null() || out.is_null() || len == 0 { return -2; }
+    let buf = unsafe { std::slice::from_raw_parts(data, len) };
+    let s = match parse_snmp(buf) { Some(s) => s, None => return -1 };
+    let fl = flags(&s);
+    let vbs = s.varbinds.iter().map(|v| IrisSnmpVarbind {
+        oid: to_cstr(&v.oid), value_type: v.value_type, value: to_cstr(&v.value),
+    }).collect();
+    let (varbinds, varbinds_count) = alloc_array(vbs);
+    unsafe {
+        out.write(IrisSnmpMessage {
+            version: s.version,
+            community: to_cstr(&s.community),
+            pdu_type: s.pdu_type,
+            request_id: s.request_id,
+            error_status: s.error_status,
+            error_index: s.error_index,
+            varbinds, varbinds_count,
+            trap_enterprise: to_cstr(&s.enterprise),
+            trap_generic: s.generic,
+            trap_specific: s.specific,
+            msg_flags: s.msg_flags,
+            security_model: s.security_model,
+            engine_id: to_cstr(&s.engine_id),
+            user_name: to_cstr(&s.user_name),
+            context_name: to_cstr(&s.context_name),
+            flags: fl,
+        });
+    }
+    0
+}
+
+/// Free allocations in an IrisSnmpMessage.
+#[no_mangle]
+pub extern "C" fn iris_snmp_free(msg: *mut IrisSnmpMessage) {
+    if msg.is_null() { return; }
+    unsafe {
+        let m = &*msg;
+        for p in [m.community, m.trap_enterprise, m.engine_id, m.user_name, m.context_name] {
+            free_cstr(p);
+        }
+        if !m.varbinds.is_null() {
+            for i in 0..m.varbinds_count {
+                let v = &*m.varbinds.add(i);
+                free_cstr(v.oid);
+                free_cstr(v.value);
+            }
+        }
+        free_array(m.varbinds, m.varbinds_count);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+        let mut v = vec![tag, content.len() as u8];
+        v.extend_from_slice(content);
+        v
+    }
+
+    fn parse(data: &[u8]) -> (i32, Option<IrisSnmpMessage>) {
+        let mut out = std::mem::MaybeUninit::<IrisSnmpMessage>::uninit();
+        let rc = iris_snmp_parse(data.as_ptr(), data.len(), out.as_mut_ptr());
+        (rc, if rc == 0 { Some(unsafe { out.assume_init() }) } else { None })
+    }
+
+    fn cstr(p: *mut c_char) -> String {
+        unsafe { std::ffi::CStr::from_ptr(p) }.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn v2c_getbulk_walk_with_public() {
+        // 1.3.6.1.2.1 → 2b 06 01 02 01
+        let vb = tlv(0x30, &[tlv(0x06, &[0x2b, 6, 1, 2, 1]), tlv(0x05, &[])].concat());
+        let pdu = tlv(0xA5, &[tlv(0x02, &[0x11]), tlv(0x02, &[0]), tlv(0x02, &[50]), tlv(0x30, &vb)].concat());
+        let msg = tlv(0x30, &[tlv(0x02, &[1]), tlv(0x04, b"public"), pdu].concat());
+        let (rc, m) = parse(&msg);
+        assert_eq!(rc, 0);
+        let mut m = m.unwrap();
+        assert_eq!((m.version, m.pdu_type, m.request_id, m.error_index), (1, SNMP_PDU_GETBULK, 0x11, 50));
+        assert_eq!(cstr(m.community), "public");
+        assert_eq!(m.varbinds_count, 1);
+        assert_eq!(cstr(unsafe { (*m.varbinds).oid }), "1.3.6.1.2.1");
+        let want = SNMP_FLAG_DEFAULT_COMMUNITY | SNMP_FLAG_BULK_WALK | SNMP_FLAG_ROOT_WALK;
+        assert_eq!(m.flags, want);
+        iris_snmp_free(&mut m);
+    }
+
+    #[test]
+    fn response_values() {
+        // sysUpTime.0 = TimeTicks 0x01020304, sysName.0 = "mac-mini", ifInOctets = Counter32 0xFFFFFFFF
+        let oid = |last: &[u8]| tlv(0x06, &[&[0x2b, 6, 1, 2, 1][..], last].concat());
+        let vbs = [
+            tlv(0x30, &[oid(&[1, 3, 0]), tlv(0x43, &[1, 2, 3, 4])].concat()),
+            tlv(0x30, &[oid(&[1, 5, 0]), tlv(0x04, b"mac-mini")].concat()),
+            tlv(0x30, &[oid(&[2, 2, 1, 10, 1]), tlv(0x41, &[0, 0xFF, 0xFF, 0xFF, 0xFF])].concat()),
+            tlv(0x30, &[oid(&[1, 9, 9]), tlv(0x81, &[])].concat()),
+        ].concat();
+        let pdu = tlv(0xA2, &[tlv(0x02, &[1]), tlv(0x02, &[0]), tlv(0x02, &[0]), tlv(0x30, &vbs)].concat());
+        let msg = tlv(0x30, &[tlv(0x02, &[0]), tlv(0x04, b"n0tDefault"), pdu].concat());
+        let (rc, m) = parse(&msg);
+        assert_eq!(rc, 0);
+        let mut m = m.unwrap();
+        assert_eq!(m.flags, 0);
+        let vb = unsafe { std::slice::from_raw_parts(m.varbinds, m.varbinds_count) };
+        let got: Vec<(String, u8, String)> = vb.iter().map(|v| (cstr(v.oid), v.value_type, cstr(v.value))).collect();
+        assert_eq!(got[0], ("1.3.6.1.2.1.1.3.0".into(), SNMP_VALUE_TIMETICKS, "16909060".into()));
+        assert_eq!(got[1].2, "mac-mini");
+        assert_eq!(got[2].2, "4294967295");
+        assert_eq!(got[3].1, SNMP_VALUE_NO_SUCH_INSTANCE);
+        iris_snmp_free(&mut m);
+    }
+
+    #[test]
+    fn v3_encrypted_and_noauth() {
+        let global = tlv(0x30, &[tlv(0x02, &[7]), tlv(0x02, &[0x05, 0xDC]), tlv(0x04, &[0x03]), tlv(0x02, &[3])].concat());
+        let usm = tlv(0x30, &[tlv(0x04, &[0x80, 0, 0x1f]), tlv(0x02, &[1]), tlv(0x02, &[2]),
+            tlv(0x04, b"ops"), tlv(0x04, &[]), tlv(0x04, &[])].concat());
+        let msg = tlv(0x30, &[tlv(0x02, &[3]), global, tlv(0x04, &usm), tlv(0x04, &[0xAA; 8])].concat());
+        let (rc, m) = parse(&msg);
+        assert_eq!(rc, 0);
+        let mut m = m.unwrap();
+        assert_eq!((m.version, m.pdu_type, m.security_model), (3, SNMP_PDU_ENCRYPTED, 3));
+        assert_eq!(cstr(m.user_name), "ops");
+        assert_eq!(cstr(m.engine_id), "80001f");
+        assert_eq!(m.flags & SNMP_FLAG_V3_NOAUTH, 0);
+        iris_snmp_free(&mut m);
+
+        assert_eq!(parse(b"\x30\x03\x02\x01\x02").0, -1);
+    }
+}
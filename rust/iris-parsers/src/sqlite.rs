@@ -0,0 +1,692 @@
+//! Read-only SQLite database file reader: walks table b-trees of a copied database
+//! and decodes records, without libsqlite or file locks. Committed frames from a
+//! "-wal" file are overlaid on the main file. Index b-trees are not used.
+
+use crate::bytes::{be16, be32};
+use crate::ffi::{alloc_array, alloc_bytes, free_array, free_c_string_array, vec_to_c_string_array, IrisCStringArray};
+use std::collections::{HashMap, HashSet};
+use std::ffi::{c_char, CStr};
+
+const MAGIC: &[u8] = b"SQLite format 3\0";
+const HEADER_SIZE: usize = 100;
+const PAGE_INTERIOR_TABLE: u8 = 0x05;
+const PAGE_LEAF_TABLE: u8 = 0x0d;
+const MAX_PAYLOAD: usize = 1 << 30;
+const ENC_UTF16LE: u32 = 2;
+const ENC_UTF16BE: u32 = 3;
+const WAL_MAGIC_LE: u32 = 0x377f_0682;
+const WAL_MAGIC_BE: u32 = 0x377f_0683;
+const WAL_HEADER_SIZE: usize = 32;
+const WAL_FRAME_HEADER_SIZE: usize = 24;
+
+pub const SQLITE_NULL: u8 = 0;
+pub const SQLITE_INT: u8 = 1;
+pub const SQLITE_REAL: u8 = 2;
+pub const SQLITE_TEXT: u8 = 3;
+pub const SQLITE_BLOB: u8 = 4;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Int(i64),
+    Real(f64),
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+impl Value {
+    pub fn as_int(&self) -> Option<i64> {
+        match self { Value::Int(i) => Some(*i), Value::Real(r) => Some(*r as i64), _ => None }
+    }
+
+    pub fn as_real(&self) -> Option<f64> {
+        match self { Value::Real(r) => Some(*r), Value::Int(i) => Some(*i as f64), _ => None }
+    }
+
+    /// Text value, or "" for anything else.
+    pub fn as_str(&self) -> &str {
+        match self { Value::Text(s) => s, _ => "" }
+    }
+}
+
+pub struct Row {
+    pub values: Vec<Value>,
+}
+
+impl Row {
+    /// Value of a column index from Table::column; Null when absent.
+    pub fn get(&self, col: Option<usize>) -> &Value {
+        col.and_then(|i| self.values.get(i)).unwrap_or(&Value::Null)
+    }
+}
+
+pub struct Table {
+    pub name: String,
+    pub root: u32,
+    pub columns: Vec<String>,
+    rowid_alias: Option<usize>, // INTEGER PRIMARY KEY column, stored as NULL in records
+}
+
+impl Table {
+    pub fn column(&self, name: &str) -> Option<usize> {
+        self.columns.iter().position(|c| c.eq_ignore_ascii_case(name))
+    }
+}
+
+pub struct Database {
+    data: Vec<u8>,
+    page_size: usize,
+    usable: usize,
+    encoding: u32,
+    page_count: u32,
+    wal: Vec<u8>,
+    wal_pages: HashMap<u32, usize>, // page number -> offset of its latest committed frame
+    wal_frames: u32,
+}
+
+#[repr(C)]
+pub struct IrisSqliteInfo {
+    pub page_size: u32,
+    pub page_count: u32,          // including pages added by committed WAL frames
+    pub text_encoding: u32,       // 1=UTF-8, 2=UTF-16le, 3=UTF-16be
+    pub user_version: u32,
+    pub application_id: u32,
+    pub schema_cookie: u32,
+    pub freelist_pages: u32,
+    pub wal_mode: bool,           // journal_mode=WAL (file format versions 2)
+    pub wal_frames: u32,          // committed WAL frames overlaid on the main file
+    pub table_count: u32,
+}
+
+#[repr(C)]
+pub struct IrisSqliteValue {
+    pub kind: u8,                 // SQLITE_*
+    pub int_value: i64,
+    pub real_value: f64,
+    pub data: *mut u8,            // UTF-8 text or blob bytes, not NUL-terminated
+    pub len: usize,
+}
+
+#[repr(C)]
+pub struct IrisSqliteTable {
+    pub columns: IrisCStringArray,
+    pub values: *mut IrisSqliteValue, // row-major, row_count * columns.count
+    pub row_count: usize,
+}
+
+/// SQLite varint: 1-9 bytes, big-endian 7-bit groups, full 8 bits in the ninth.
+fn varint(d: &[u8]) -> Option<(i64, usize)> {
+    let mut v = 0u64;
+    for i in 0..9 {
+        let b = *d.get(i)?;
+        if i == 8 { return Some((((v << 8) | b as u64) as i64, 9)); }
+        v = (v << 7) | (b & 0x7f) as u64;
+        if b < 0x80 { return Some((v as i64, i + 1)); }
+    }
+    None
+}
+
+fn int_be(d: &[u8]) -> i64 {
+    let v = d.iter().fold(0u64, |a, &b| a << 8 | b as u64);
+    let shift = 64 - 8 * d.len() as u32;
+    ((v << shift) as i64) >> shift
+}
+
+fn text(raw: &[u8], encoding: u32) -> String {
+    let units = |be: bool| raw.chunks_exact(2).map(move |c| if be { u16::from_be_bytes([c[0], c[1]]) } else { u16::from_le_bytes([c[0], c[1]]) });
+    match encoding {
+        ENC_UTF16LE => String::from_utf16_lossy(&units(false).collect::<Vec<_>>()),
+        ENC_UTF16BE => String::from_utf16_lossy(&units(true).collect::<Vec<_>>()),
+        _ => String::from_utf8_lossy(raw).into_owned(),
+    }
+}
+
+/// Decode a record: a header of serial types followed by the column values.
+fn record(d: &[u8], encoding: u32) -> Option<Vec<Value>> {
+    let (hlen, mut p) = varint(d)?;
+    let hlen = usize::try_from(hlen).ok()?;
+    let mut body = hlen;
+    let mut out = Vec::new();
+    while p < hlen {
+        let (t, n) = varint(d.get(p..hlen)?)?;
+        p += n;
+        let (v, size) = match t {
+            0 => (Value::Null, 0),
+            1..=4 => (Value::Int(int_be(d.get(body..body + t as usize)?)), t as usize),
+            5 => (Value::Int(int_be(d.get(body..body + 6)?)), 6),
+            6 => (Value::Int(int_be(d.get(body..body + 8)?)), 8),
+            7 => (Value::Real(f64::from_bits(int_be(d.get(body..body + 8)?) as u64)), 8),
+            8 => (Value::Int(0), 0),
+            9 => (Value::Int(1), 0),
+            t if t >= 12 => {
+                let len = ((t - 12) / 2) as usize;
+                let raw = d.get(body..body.checked_add(len)?)?;
+                if t % 2 == 0 { (Value::Blob(raw.to_vec()), len) } else { (Value::Text(text(raw, encoding)), len) }
+            }
+            _ => return None,
+        };
+        body += size;
+        out.push(v);
+    }
+    Some(out)
+}
+
+/// WAL checksum over 8-byte words, continuing from `s`.
+fn wal_checksum(d: &[u8], big_endian: bool, mut s: (u32, u32)) -> (u32, u32) {
+    for w in d.chunks_exact(8) {
+        let word = |b: &[u8]| if big_endian { u32::from_be_bytes(b.try_into().unwrap()) } else { u32::from_le_bytes(b.try_into().unwrap()) };
+        s.0 = s.0.wrapping_add(word(&w[..4])).wrapping_add(s.1);
+        s.1 = s.1.wrapping_add(word(&w[4..])).wrapping_add(s.0);
+    }
+    s
+}
+
+/// Column names from a CREATE TABLE statement, and the INTEGER PRIMARY KEY column if any.
+fn columns(sql: &str) -> (Vec<String>, Option<usize>) {
+    let (Some(open), Some(close)) = (sql.find('('), sql.rfind(')')) else { return (Vec::new(), None) };
+    let body = sql.get(open + 1..close).unwrap_or("");
+    let mut defs = Vec::new();
+    let (mut depth, mut quote, mut start) = (0i32, None, 0);
+    for (i, c) in body.char_indices() {
+        match (quote, c) {
+            (Some(q), _) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"' | '`') => quote = Some(c),
+            (None, '[') => quote = Some(']'),
+            (None, '(') => depth += 1,
+            (None, ')') => depth -= 1,
+            (None, ',') if depth == 0 => { defs.push(&body[start..i]); start = i + 1; }
+            _ => {}
+        }
+    }
+    defs.push(&body[start..]);
+    let mut cols = Vec::new();
+    let mut alias = None;
+    for def in defs {
+        let def = def.trim();
+        let mut words = def.split_whitespace();
+        let first = words.next().unwrap_or("");
+        let keyword = first.split('(').next().unwrap_or("");
+        if ["CONSTRAINT", "PRIMARY", "UNIQUE", "CHECK", "FOREIGN"].iter().any(|k| keyword.eq_ignore_ascii_case(k)) { continue; }
+        let name = match def.chars().next() {
+            Some(q @ ('"' | '`' | '[')) => {
+                let end = if q == '[' { ']' } else { q };
+                def[1..].split(end).next().unwrap_or("").to_string()
+            }
+            _ => first.to_string(),
+        };
+        let upper = def.to_ascii_uppercase();
+        if words.next().is_some_and(|t| t.eq_ignore_ascii_case("INTEGER")) && upper.contains("PRIMARY KEY") {
+            alias = Some(cols.len());
+        }
+        cols.push(name);
+    }
+    (cols, alias)
+}
+
+impl Database {
+    /// Open a database image, overlaying the committed frames of its WAL file if given.
+    pub fn open(data: Vec<u8>, wal: Option<Vec<u8>>) -> Option<Database> {
+        if data.len() < HEADER_SIZE || !data.starts_with(MAGIC) { return None; }
+        let page_size = match be16(&data, 16)? { 1 => 65536, n if n >= 512 && n.is_power_of_two() => n as usize, _ => return None };
+        let usable = page_size.checked_sub(data[20] as usize).filter(|&u| u >= 480)?;
+        let mut db = Database {
+            encoding: be32(&data, 56)?,
+            page_count: (data.len() / page_size) as u32,
+            data, page_size, usable,
+            wal: Vec::new(), wal_pages: HashMap::new(), wal_frames: 0,
+        };
+        if let Some(wal) = wal { db.apply_wal(wal); }
+        Some(db)
+    }
+
+    /// Open a database file and its "-wal" sibling if present.
+    /// Err(-1) if the file cannot be read, Err(-2) if it is not SQLite.
+    pub fn open_path(path: &str) -> Result<Database, i32> {
+        let data = std::fs::read(path).map_err(|_| -1)?;
+        let wal = std::fs::read(format!("{}-wal", path)).ok();
+        Database::open(data, wal).ok_or(-2)
+    }
+
+    /// Index frames up to the last valid commit. Frames with stale salts or a broken
+    /// checksum chain end the log, as they do for SQLite itself.
+    fn apply_wal(&mut self, wal: Vec<u8>) {
+        let Some(magic) = be32(&wal, 0) else { return };
+        if magic != WAL_MAGIC_LE && magic != WAL_MAGIC_BE { return; }
+        let big_endian = magic == WAL_MAGIC_BE;
+        if be32(&wal, 8) != Some(self.page_size as u32) { return; }
+        let Some(header) = wal.get(..WAL_HEADER_SIZE) else { return };
+        let mut sum = wal_checksum(&header[..24], big_endian, (0, 0));
+        if (be32(header, 24), be32(header, 28)) != (Some(sum.0), Some(sum.1)) { return; }
+        let salts = &header[16..24];
+        let mut pending = Vec::new();
+        let mut p = WAL_HEADER_SIZE;
+        let frame_size = WAL_FRAME_HEADER_SIZE + self.page_size;
+        while let Some(frame) = wal.get(p..p + frame_size) {
+            if &frame[8..16] != salts { break; }
+            sum = wal_checksum(&frame[..8], big_endian, sum);
+            sum = wal_checksum(&frame[WAL_FRAME_HEADER_SIZE..], big_endian, sum);
+            if (be32(frame, 16), be32(frame, 20)) != (Some(sum.0), Some(sum.1)) { break; }
+            pending.push((be32(frame, 0).unwrap_or(0), p + WAL_FRAME_HEADER_SIZE));
+            let commit_size = be32(frame, 4).unwrap_or(0);
+            if commit_size != 0 {
+                self.wal_frames += pending.len() as u32;
+                self.wal_pages.extend(pending.drain(..));
+                self.page_count = commit_size;
+            }
+            p += frame_size;
+        }
+        self.wal = wal;
+    }
+
+    fn page(&self, n: u32) -> Option<&[u8]> {
+        if let Some(&off) = self.wal_pages.get(&n) { return self.wal.get(off..off + self.page_size); }
+        if n > self.page_count { return None; }
+        let start = (n as usize).checked_sub(1)?.checked_mul(self.page_size)?;
+        self.data.get(start..start + self.page_size)
+    }
+
+    /// Full payload of a table leaf cell, following overflow pages.
+    fn payload(&self, cell: &[u8]) -> Option<(i64, Vec<u8>)> {
+        let (size, n1) = varint(cell)?;
+        let (rowid, n2) = varint(cell.get(n1..)?)?;
+        let size = usize::try_from(size).ok().filter(|&s| s <= MAX_PAYLOAD)?;
+        let body = cell.get(n1 + n2..)?;
+        let u = self.usable;
+        let max_local = u - 35;
+        let local = if size <= max_local {
+            size
+        } else {
+            let min_local = (u - 12) * 32 / 255 - 23;
+            let k = min_local + (size - min_local) % (u - 4);
+            if k <= max_local { k } else { min_local }
+        };
+        let mut out = body.get(..local)?.to_vec();
+        let mut next = if local < size { be32(body, local)? } else { 0 };
+        let mut hops = 0;
+        while out.len() < size {
+            hops += 1;
+            if hops > self.page_count { return None; }
+            let page = self.page(next)?;
+            let take = (u - 4).min(size - out.len());
+            out.extend_from_slice(page.get(4..4 + take)?);
+            next = be32(page, 0)?;
+        }
+        Some((rowid, out))
+    }
+
+    fn walk(&self, n: u32, seen: &mut HashSet<u32>, out: &mut Vec<(i64, Vec<Value>)>) {
+        if !seen.insert(n) { return; }
+        let Some(page) = self.page(n) else { return };
+        let h = if n == 1 { HEADER_SIZE } else { 0 };
+        let (Some(&kind), Some(cells)) = (page.get(h), be16(page, h + 3)) else { return };
+        let ptrs = h + if kind == PAGE_INTERIOR_TABLE { 12 } else { 8 };
+        for i in 0..cells as usize {
+            let Some(cell) = be16(page, ptrs + 2 * i).and_then(|o| page.get(o as usize..)) else { return };
+            match kind {
+                PAGE_LEAF_TABLE => {
+                    if let Some((rowid, values)) = self.payload(cell).and_then(|(r, p)| Some((r, record(&p, self.encoding)?))) {
+                        out.push((rowid, values));
+                    }
+                }
+                PAGE_INTERIOR_TABLE => { if let Some(child) = be32(cell, 0) { self.walk(child, seen, out); } }
+                _ => return,
+            }
+        }
+        if kind == PAGE_INTERIOR_TABLE {
+            if let Some(right) = be32(page, h + 8) { self.walk(right, seen, out); }
+        }
+    }
+
+    fn raw_rows(&self, root: u32) -> Vec<(i64, Vec<Value>)> {
+        let mut out = Vec::new();
+        self.walk(root, &mut HashSet::new(), &mut out);
+        out
+    }
+
+    /// Tables listed in sqlite_schema, in schema order.
+    pub fn tables(&self) -> Vec<Table> {
+        self.raw_rows(1).into_iter().filter_map(|(_, v)| {
+            if v.first()?.as_str() != "table" { return None; }
+            let (columns, rowid_alias) = columns(v.get(4)?.as_str());
+            Some(Table { name: v.get(1)?.as_str().to_string(), root: v.get(3)?.as_int()? as u32, columns, rowid_alias })
+        }).collect()
+    }
+
+    /// A rowid table by name (case-insensitive).
+    pub fn table(&self, name: &str) -> Option<Table> {
+        self.tables().into_iter().find(|t| t.name.eq_ignore_ascii_case(name))
+    }
+
+    /// All rows of a table in rowid order. Malformed cells are skipped.
+    pub fn rows(&self, t: &Table) -> Vec<Row> {
+        self.raw_rows(t.root).into_iter().map(|(rowid, mut values)| {
+            if values.len() < t.columns.len() { values.resize(t.columns.len(), Value::Null); }
+            if let Some(v) = t.rowid_alias.and_then(|i| values.get_mut(i)) { *v = Value::Int(rowid); }
+            Row { values }
+        }).collect()
+    }
+}
+
+// --- FFI entry points ---
+
+fn sqlite_value(v: &Value) -> IrisSqliteValue {
+    let mut out = IrisSqliteValue { kind: SQLITE_NULL, int_value: 0, real_value: 0.0, data: std::ptr::null_mut(), len: 0 };
+    match v {
+        Value::Null => {}
+        Value::Int(i) => { out.kind = SQLITE_INT; out.int_value = *i; out.real_value = *i as f64; }
+        Value::Real(r) => { out.kind = SQLITE_REAL; out.real_value = *r; out.int_value = *r as i64; }
+        Value::Text(t) => { out.kind = SQLITE_TEXT; (out.data, out.len) = alloc_bytes(t.as_bytes()); }
+        Value::Blob(b) => { out.kind = SQLITE_BLOB; (out.data, out.len) = alloc_bytes(b); }
+    }
+    out
+}
+
+fn db_ref<'a>(db: *const Database) -> Option<&'a Database> {
+    if db.is_null() { None } else { Some(unsafe { &*db }) }
+}
+
+/// Open a database file read-only (plus its "-wal" file if present). The file is read
+/// into memory; nothing is locked. Returns null if unreadable or not SQLite.
+#[no_mangle]
+pub extern "C" fn iris_sqlite_open(path: *const c_char) -> *mut Database {
+    if path.is_null() { return std::ptr::null_mut(); }
+    let Ok(path) = unsafe { CStr::from_ptr(path) }.to_str() else { return std::ptr::null_mut() };
+    match Database::open_path(path) {
+        Ok(db) => Box::into_raw(Box::new(db)),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn iris_sqlite_close(db: *mut Database) {
+    if db.is_null() { return; }
+    unsafe { drop(Box::from_raw(db)); }
+}
+
+/// Header summary. Returns 0=ok, -2=arg error.
+#[no_mangle]
+pub extern "C" fn iris_sqlite_info(db: *const Database, out: *mut IrisSqliteInfo) -> i32 {
+    let Some(db) = db_ref(db) else { return -2 };
+    if out.is_null() { return -2; }
+    let h = |o: usize| be32(&db.data, o).unwrap_or(0);
+    unsafe {
+        out.write(IrisSqliteInfo {
+            page_size: db.page_size as u32,
+            page_count: db.page_count,
+            text_encoding: db.encoding,
+            user_version: h(60),
+            application_id: h(68),
+            schema_cookie: h(40),
+            freelist_pages: h(36),
+            wal_mode: db.data[18] == 2 && db.data[19] == 2,
+            wal_frames: db.wal_frames,
+            table_count: db.tables().len() as u32,
+        });
+    }
+    0
+}
+
+/// Names of the tables in the schema. Returns 0=ok, -2=arg error.
+/// Free with iris_sqlite_tables_free.
+#[no_mangle]
+pub extern "C" fn iris_sqlite_tables(db: *const Database, out: *mut IrisCStringArray) -> i32 {
+    let Some(db) = db_ref(db) else { return -2 };
+    if out.is_null() { return -2; }
+    unsafe { out.write(vec_to_c_string_array(db.tables().into_iter().map(|t| t.name).collect())); }
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn iris_sqlite_tables_free(names: *mut IrisCStringArray) {
+    if names.is_null() { return; }
+    unsafe { free_c_string_array(&*names); }
+}
+
+/// Every row of a rowid table. Returns 0=ok, -2=arg error, -3=no such table.
+/// Free with iris_sqlite_table_free.
+#[no_mangle]
+pub extern "C" fn iris_sqlite_read_table(db: *const Database, name: *const c_char, out: *mut IrisSqliteTable) -> i32 {
+    let Some(db) = db_ref(db) else { return -2 };
+    if name.is_null() || out.is_null() { return -2; }
+    let Ok(name) = unsafe { CStr::from_ptr(name) }.to_str() else { return -2 };
+    let Some(t) = db.table(name) else { return -3 };
+    let rows = db.rows(&t);
+    let values: Vec<_> = rows.iter()
+        .flat_map(|r| (0..t.columns.len()).map(|i| sqlite_value(r.get(Some(i)))))
+        .collect();
+    let (values, _) = alloc_array(values);
+    unsafe {
+        out.write(IrisSqliteTable { columns: vec_to_c_string_array(t.columns), values, row_count: rows.len() });
+    }
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn iris_sqlite_table_free(t: *mut IrisSqliteTable) {
+    if t.is_null() { return; }
+    let t = unsafe { &*t };
+    let n = t.row_count * t.columns.count;
+    for i in 0..n {
+        let v = unsafe { &*t.values.add(i) };
+        free_array(v.data, v.len);
+    }
+    free_array(t.values, n);
+    free_c_string_array(&t.columns);
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    pub const PAGE_SIZE: usize = 4096;
+
+    fn put_varint(v: &mut Vec<u8>, x: u64) {
+        if x > 0x00ff_ffff_ffff_ffff {
+            let mut b = [0u8; 9];
+            b[8] = x as u8;
+            let mut y = x >> 8;
+            for i in (0..8).rev() { b[i] = (y & 0x7f) as u8 | 0x80; y >>= 7; }
+            v.extend_from_slice(&b);
+            return;
+        }
+        let mut groups = vec![(x & 0x7f) as u8];
+        let mut y = x >> 7;
+        while y > 0 { groups.push((y & 0x7f) as u8 | 0x80); y >>= 7; }
+        v.extend(groups.iter().rev());
+    }
+
+    pub fn encode_record(values: &[Value]) -> Vec<u8> {
+        let mut types = Vec::new();
+        let mut body = Vec::new();
+        for v in values {
+            match v {
+                Value::Null => put_varint(&mut types, 0),
+                Value::Int(i) => { put_varint(&mut types, 6); body.extend_from_slice(&i.to_be_bytes()); }
+                Value::Real(r) => { put_varint(&mut types, 7); body.extend_from_slice(&r.to_bits().to_be_bytes()); }
+                Value::Text(s) => { put_varint(&mut types, 13 + 2 * s.len() as u64); body.extend_from_slice(s.as_bytes()); }
+                Value::Blob(b) => { put_varint(&mut types, 12 + 2 * b.len() as u64); body.extend_from_slice(b); }
+            }
+        }
+        let mut v = Vec::new();
+        put_varint(&mut v, types.len() as u64 + 1); // header sizes stay below 128 in tests
+        v.extend_from_slice(&types);
+        v.extend_from_slice(&body);
+        v
+    }
+
+    /// A b-tree page with the given cells packed at the end.
+    fn page(kind: u8, cells: &[Vec<u8>], right: Option<u32>, first: bool) -> Vec<u8> {
+        let mut p = vec![0u8; PAGE_SIZE];
+        let h = if first { HEADER_SIZE } else { 0 };
+        p[h] = kind;
+        p[h + 3..h + 5].copy_from_slice(&(cells.len() as u16).to_be_bytes());
+        if let Some(r) = right { p[h + 8..h + 12].copy_from_slice(&r.to_be_bytes()); }
+        let mut ptr = h + if right.is_some() { 12 } else { 8 };
+        let mut end = PAGE_SIZE;
+        for c in cells {
+            end -= c.len();
+            p[end..end + c.len()].copy_from_slice(c);
+            p[ptr..ptr + 2].copy_from_slice(&(end as u16).to_be_bytes());
+            ptr += 2;
+        }
+        p[h + 5..h + 7].copy_from_slice(&(end as u16).to_be_bytes());
+        p
+    }
+
+    fn leaf_cell(rowid: i64, rec: &[u8]) -> Vec<u8> {
+        let mut c = Vec::new();
+        put_varint(&mut c, rec.len() as u64);
+        put_varint(&mut c, rowid as u64);
+        c.extend_from_slice(rec);
+        c
+    }
+
+    /// Database with one single-leaf table per (CREATE TABLE sql, rows) entry.
+    pub fn database(tables: &[(&str, Vec<Vec<Value>>)]) -> Vec<u8> {
+        let mut schema = Vec::new();
+        let mut pages = Vec::new();
+        for (i, (sql, rows)) in tables.iter().enumerate() {
+            let name = sql.split_whitespace().nth(2).unwrap().split('(').next().unwrap().trim_matches('"');
+            let root = i as i64 + 2;
+            schema.push(leaf_cell(i as i64 + 1, &encode_record(&[
+                Value::Text("table".into()), Value::Text(name.into()), Value::Text(name.into()), Value::Int(root), Value::Text(sql.to_string()),
+            ])));
+            let cells: Vec<_> = rows.iter().enumerate().map(|(r, v)| leaf_cell(r as i64 + 1, &encode_record(v))).collect();
+            pages.push(page(PAGE_LEAF_TABLE, &cells, None, false));
+        }
+        let mut first = page(PAGE_LEAF_TABLE, &schema, None, true);
+        first[..16].copy_from_slice(MAGIC);
+        first[16..18].copy_from_slice(&(PAGE_SIZE as u16).to_be_bytes());
+        first[18] = 1;
+        first[19] = 1;
+        first[28..32].copy_from_slice(&(tables.len() as u32 + 1).to_be_bytes());
+        first[56..60].copy_from_slice(&1u32.to_be_bytes());
+        [vec![first], pages].concat().concat()
+    }
+
+    #[test]
+    fn varints_and_columns() {
+        for x in [0u64, 127, 128, 16383, 1 << 40, u64::MAX] {
+            let mut v = Vec::new();
+            put_varint(&mut v, x);
+            assert_eq!(varint(&v), Some((x as i64, v.len())));
+        }
+        let (cols, alias) = columns("CREATE TABLE t (id INTEGER PRIMARY KEY AUTOINCREMENT, \"a b\" TEXT, c REAL DEFAULT (1, 2), UNIQUE(c))");
+        assert_eq!((cols, alias), (vec!["id".to_string(), "a b".into(), "c".into()], Some(0)));
+    }
+
+    #[test]
+    fn interior_pages_and_overflow() {
+        let big = "x".repeat(10000);
+        let rec = encode_record(&[Value::Null, Value::Text(big.clone())]);
+        // Spill all but the minimum local payload into two overflow pages (pages 5, 6)
+        let u = PAGE_SIZE;
+        let min_local = (u - 12) * 32 / 255 - 23;
+        let k = min_local + (rec.len() - min_local) % (u - 4);
+        let local = if k <= u - 35 { k } else { min_local };
+        let mut cell = Vec::new();
+        put_varint(&mut cell, rec.len() as u64);
+        put_varint(&mut cell, 7);
+        cell.extend_from_slice(&rec[..local]);
+        cell.extend_from_slice(&5u32.to_be_bytes());
+        let rest = &rec[local..];
+        let mut ov1 = 6u32.to_be_bytes().to_vec();
+        ov1.extend_from_slice(&rest[..u - 4]);
+        let mut ov2 = vec![0u8; 4];
+        ov2.extend_from_slice(&rest[u - 4..]);
+        ov2.resize(PAGE_SIZE, 0);
+
+        let db = database(&[("CREATE TABLE t (id INTEGER PRIMARY KEY, body TEXT)", vec![])]);
+        let small = leaf_cell(3, &encode_record(&[Value::Null, Value::Text("small".into())]));
+        let mut interior_cell = 3u32.to_be_bytes().to_vec();
+        put_varint(&mut interior_cell, 3);
+        let pages = [
+            page(PAGE_INTERIOR_TABLE, &[interior_cell], Some(4), false), // page 2: root
+            page(PAGE_LEAF_TABLE, &[small], None, false),                 // page 3
+            page(PAGE_LEAF_TABLE, &[cell], None, false),                  // page 4
+            ov1, ov2,
+        ];
+        let data = [db[..PAGE_SIZE].to_vec(), pages.concat()].concat();
+        let db = Database::open(data, None).unwrap();
+        let t = db.table("T").unwrap();
+        let rows = db.rows(&t);
+        assert_eq!(rows.len(), 2);
+        assert_eq!((rows[0].get(t.column("id")), rows[0].get(t.column("body")).as_str()), (&Value::Int(3), "small"));
+        assert_eq!((rows[1].get(t.column("id")), rows[1].get(t.column("body")).as_str()), (&Value::Int(7), big.as_str()));
+        assert!(Database::open(b"SQLite format 2\0".repeat(8), None).is_none());
+    }
+
+    fn wal_frame(wal: &mut Vec<u8>, sum: &mut (u32, u32), pgno: u32, commit: u32, page: &[u8]) {
+        let mut h = pgno.to_be_bytes().to_vec();
+        h.extend_from_slice(&commit.to_be_bytes());
+        h.extend_from_slice(&wal[16..24]);
+        *sum = wal_checksum(&h[..8], true, *sum);
+        *sum = wal_checksum(page, true, *sum);
+        h.extend_from_slice(&sum.0.to_be_bytes());
+        h.extend_from_slice(&sum.1.to_be_bytes());
+        wal.extend_from_slice(&h);
+        wal.extend_from_slice(page);
+    }
+
+    #[test]
+    fn wal_overlay_and_ffi() {
+        let sql = "CREATE TABLE t (id INTEGER PRIMARY KEY, v TEXT)";
+        let mut db = database(&[(sql, vec![vec![Value::Null, Value::Text("old".into())]])]);
+        db[18] = 2;
+        db[19] = 2;
+        let updated = |v: &str| page(PAGE_LEAF_TABLE, &[leaf_cell(1, &encode_record(&[Value::Null, Value::Text(v.into())]))], None, false);
+        let mut wal = WAL_MAGIC_BE.to_be_bytes().to_vec();
+        for w in [3_007_000u32, PAGE_SIZE as u32, 0, 0x1111_1111, 0x2222_2222] { wal.extend_from_slice(&w.to_be_bytes()); }
+        let mut sum = wal_checksum(&wal, true, (0, 0));
+        wal.extend_from_slice(&sum.0.to_be_bytes());
+        wal.extend_from_slice(&sum.1.to_be_bytes());
+        wal_frame(&mut wal, &mut sum, 2, 2, &updated("new"));
+        wal_frame(&mut wal, &mut sum, 2, 0, &updated("uncommitted"));
+
+        let path = std::env::temp_dir().join(format!("iris-sqlite-{}.db", std::process::id()));
+        let wal_path = format!("{}-wal", path.display());
+        std::fs::write(&path, &db).unwrap();
+        std::fs::write(&wal_path, &wal).unwrap();
+        let cpath = std::ffi::CString::new(path.to_str().unwrap()).unwrap();
+        let handle = iris_sqlite_open(cpath.as_ptr());
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&wal_path).unwrap();
+        assert!(!handle.is_null());
+
+        let mut info = std::mem::MaybeUninit::<IrisSqliteInfo>::uninit();
+        assert_eq!(iris_sqlite_info(handle, info.as_mut_ptr()), 0);
+        let info = unsafe { info.assume_init() };
+        assert_eq!((info.page_size, info.page_count, info.text_encoding), (PAGE_SIZE as u32, 2, 1));
+        assert_eq!((info.wal_mode, info.wal_frames, info.table_count), (true, 1, 1));
+        let mut names = std::mem::MaybeUninit::<IrisCStringArray>::uninit();
+        assert_eq!(iris_sqlite_tables(handle, names.as_mut_ptr()), 0);
+        let mut names = unsafe { names.assume_init() };
+        assert_eq!(unsafe { CStr::from_ptr(*names.items) }.to_str(), Ok("t"));
+        iris_sqlite_tables_free(&mut names);
+
+        let mut out = std::mem::MaybeUninit::<IrisSqliteTable>::uninit();
+        assert_eq!(iris_sqlite_read_table(handle, c"T".as_ptr(), out.as_mut_ptr()), 0);
+        let mut t = unsafe { out.assume_init() };
+        assert_eq!((t.row_count, t.columns.count), (1, 2));
+        let v = unsafe { std::slice::from_raw_parts(t.values, 2) };
+        assert_eq!((v[0].kind, v[0].int_value, v[1].kind), (SQLITE_INT, 1, SQLITE_TEXT));
+        assert_eq!(unsafe { std::slice::from_raw_parts(v[1].data, v[1].len) }, b"new");
+        iris_sqlite_table_free(&mut t);
+        let mut out = std::mem::MaybeUninit::<IrisSqliteTable>::uninit();
+        assert_eq!(iris_sqlite_read_table(handle, c"missing".as_ptr(), out.as_mut_ptr()), -3);
+        iris_sqlite_close(handle);
+
+        // A broken checksum chain discards the frame: the main file's row is read
+        wal[WAL_HEADER_SIZE + 30] ^= 1;
+        let db = Database::open(db, Some(wal)).unwrap();
+        let t = db.table("t").unwrap();
+        assert_eq!(db.rows(&t)[0].get(t.column("v")).as_str(), "old");
+    }
+
+    #[test]
+    fn utf16_text() {
+        assert_eq!(record(&[2, 21, b'h', 0, b'i', 0], ENC_UTF16LE), Some(vec![Value::Text("hi".into())]));
+        assert_eq!(record(&[2, 21, 0, b'h', 0, b'i'], ENC_UTF16BE), Some(vec![Value::Text("hi".into())]));
+    }
+}
@@ -0,0 +1,285 @@
+//! SSH identification string and KEXINIT parser (RFC 4253) with HASSH /
+//! HASSHServer fingerprints (MD5 of kex;enc;mac;comp name-lists).
+
+use crate::ffi::{to_cstr, free_cstr};
+use crate::hash::{md5_digest, to_hex};
+use std::ffi::c_char;
+
+const MAX_BANNER_LEN: usize = 255; // RFC 4253 §4.2
+const MAX_PREAMBLE_LINES: usize = 32;
+const MAX_PACKET_LEN: usize = 35_000;
+const SSH_MSG_KEXINIT: u8 = 20;
+
+#[repr(C)]
+pub struct IrisSshInfo {
+    pub has_banner: bool,
+    pub proto_version: *mut c_char,   // "2.0", "1.99"
+    pub software: *mut c_char,        // "OpenSSH_9.6"
+    pub comments: *mut c_char,
+    pub has_kexinit: bool,
+    pub kex_algorithms: *mut c_char,  // comma-separated name-lists
+    pub host_key_algorithms: *mut c_char,
+    pub enc_c2s: *mut c_char,
+    pub enc_s2c: *mut c_char,
+    pub mac_c2s: *mut c_char,
+    pub mac_s2c: *mut c_char,
+    pub comp_c2s: *mut c_char,
+    pub comp_s2c: *mut c_char,
+    pub first_kex_follows: bool,
+    pub hassh: *mut c_char,           // MD5 over the client-to-server lists
+    pub hassh_algorithms: *mut c_char, // pre-hash string
+    pub hassh_server: *mut c_char,    // MD5 over the server-to-client lists
+    pub hassh_server_algorithms: *mut c_char,
+    pub bytes_consumed: usize,
+}
+
+#[derive(Default)]
+struct Banner { proto: String, software: String, comments: String }
+
+#[derive(Default)]
+struct KexInit { lists: [String; 10], first_kex_follows: bool }
+
+enum Step<T> { Done(T, usize), Incomplete, Invalid }
+
+/// Parse the identification line, skipping any pre-banner lines a server may send.
+fn parse_banner(data: &[u8]) -> Step<Banner> {
+    let mut off = 0;
+    for _ in 0..MAX_PREAMBLE_LINES {
+        let rest = &data[off..];
+        let nl = match rest.iter().position(|&b| b == b'\n') {
+            Some(p) => p,
+            None if rest.len() > MAX_BANNER_LEN => return Step::Invalid,
+            None => return Step::Incomplete,
+        };
+        let line = &rest[..nl];
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        off += nl + 1;
+        if !line.starts_with(b"SSH-") { continue; }
+        if line.len() > MAX_BANNER_LEN { return Step::Invalid; }
+        let text = String::from_utf8_lossy(&line[4..]);
+        let (ident, comments) = match text.split_once(' ') {
+            Some((i, c)) => (i.to_string(), c.to_string()),
+            None => (text.to_string(), String::new()),
+        };
+        let (proto, software) = match ident.split_once('-') {
+            Some((p, s)) => (p.to_string(), s.to_string()),
+            None => return Step::Invalid,
+        };
+        return Step::Done(Banner { proto, software, comments }, off);
+    }
+    Step::Invalid
+}
+
+fn read_name_list(p: &[u8], off: &mut usize) -> Option<String> {
+    if *off + 4 > p.len() { return None; }
+    let len = u32::from_be_bytes([p[*off], p[*off + 1], p[*off + 2], p[*off + 3]]) as usize;
+    *off += 4;
+    if *off + len > p.len() { return None; }
+    let s = String::from_utf8_lossy(&p[*off..*off + len]).into_owned();
+    *off += len;
+    Some(s)
+}
+
+/// Parse an unencrypted binary packet carrying SSH_MSG_KEXINIT.
+fn parse_kexinit(data: &[u8]) -> Step<KexInit> {
+    if data.len() < 6 { return Step::Incomplete; }
+    let pkt_len = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+    if !(12..=MAX_PACKET_LEN).contains(&pkt_len) { return Step::Invalid; }
+    if data[5] != SSH_MSG_KEXINIT { return Step::Invalid; }
+    if data.len() < 4 + pkt_len { return Step::Incomplete; }
+    let pad = data[4] as usize;
+    if pad + 1 > pkt_len { return Step::Invalid; }
+    let payload = &data[5..4 + pkt_len - pad];
+    let mut off = 1 + 16; // message code + cookie
+    let mut k = KexInit::default();
+    for slot in k.lists.iter_mut() {
+        match read_name_list(payload, &mut off) {
+            Some(s) => *slot = s,
+            None => return Step::Invalid,
+        }
+    }
+    k.first_kex_follows = payload.get(off).is_some_and(|&b| b != 0);
+    Step::Done(k, 4 + pkt_len)
+}
+
+fn hassh_strings(k: &KexInit) -> (String, String) {
+    let client = format!("{};{};{};{}", k.lists[0], k.lists[2], k.lists[4], k.lists[6]);
+    let server = format!("{};{};{};{}", k.lists[0], k.lists[3], k.lists[5], k.lists[7]);
+    (client, server)
+}
+
+// --- FFI entry points ---
+
+/// Parse the start of an SSH stream: identification line, KEXINIT, or both in sequence.
+/// Returns 0=ok, -1=incomplete, -2=not SSH/error. Free with iris_ssh_free.
+#[no_mangle]
+pub extern "C" fn iris_ssh_parse(data: *const u8, len: usize, out: *mut IrisSshInfo) -> i32 {
+    if data.is_null() || out.is_null() || len == 0 { return -2; }
+    let buf = unsafe { std::slice::from_raw_parts(data, len) };
+
+    let mut off = 0;
+    let mut banner = None;
+    if !(buf.len() >= 6 && buf[5] == SSH_MSG_KEXINIT && buf[0] == 0) {
+        match parse_banner(buf) {
+            Step::Done(b, n) => { banner = Some(b); off = n; }
+            Step::Incomplete => return -1,
+            Step::Invalid => return -2,
+        }
+    }
+    let mut kex = None;
+    if off < buf.len() {
+        match parse_kexinit(&buf[off..]) {
+            Step::Done(k, n) => { kex = Some(k); off += n; }
+            Step::Incomplete if banner.is_none() => return -1,
+            Step::Invalid if banner.is_none() => return -2,
+            _ => {}
+        }
+    }
+
+    let b = banner.as_ref();
+    let k = kex.as_ref();
+    let list = |i: usize| to_cstr(k.map(|k| k.lists[i].as_str()).unwrap_or(""));
+    let (hc, hs) = k.map(hassh_strings).unwrap_or_default();
+    let digest = |s: &str| if s.is_empty() { String::new() } else { to_hex(&md5_digest(s.as_bytes())) };
+    unsafe {
+        out.write(IrisSshInfo {
+            has_banner: b.is_some(),
+            proto_version: to_cstr(b.map(|b| b.proto.as_str()).unwrap_or("")),
+            software: to_cstr(b.map(|b| b.software.as_str()).unwrap_or("")),
+            comments: to_cstr(b.map(|b| b.comments.as_str()).unwrap_or("")),
+            has_kexinit: k.is_some(),
+            kex_algorithms: list(0),
+            host_key_algorithms: list(1),
+            enc_c2s: list(2),
+            enc_s2c: list(3),
+            mac_c2s: list(4),
+            mac_s2c: list(5),
+            comp_c2s: list(6),
+            comp_s2c: list(7),
+            first_kex_follows: k.is_some_and(|k| k.first_kex_follows),
+            hassh: to_cstr(&digest(&hc)),
+            hassh_algorithms: to_cstr(&hc),
+            hassh_server: to_cstr(&digest(&hs)),
+            hassh_server_algorithms: to_cstr(&hs),
+            bytes_consumed: off,
+        });
+    }
+    0
+}
+
+/// Free strings in an IrisSshInfo.
+#[no_mangle]
+pub extern "C" fn iris_ssh_free(info: *mut IrisSshInfo) {
+    if info.is_null() { return; }
+    unsafe {
+        let i = &*info;
+        for p in [i.proto_version, i.software, i.comments, i.kex_algorithms,
+                  i.host_key_algorithms, i.enc_c2s, i.enc_s2c, i.mac_c2s, i.mac_s2c,
+                  i.comp_c2s, i.comp_s2c, i.hassh, i.hassh_algorithms,
+                  i.hassh_server, i.hassh_server_algorithms] {
+            free_cstr(p);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+
+    const KEX: &str = "curve25519-sha256,curve25519-sha256@libssh.org,ecdh-sha2-nistp256,diffie-hellman-group14-sha256,ext-info-c";
+    const LISTS: [&str; 10] = [
+        KEX, "ssh-ed25519,rsa-sha2-512",
+        "chacha20-poly1305@openssh.com,aes128-ctr,aes256-gcm@openssh.com", "aes256-gcm@openssh.com,aes128-ctr",
+        "umac-64-etm@openssh.com,hmac-sha2-256-etm@openssh.com,hmac-sha2-256", "hmac-sha2-256",
+        "none,zlib@openssh.com", "none", "", "",
+    ];
+
+    fn kexinit(lists: &[&str; 10], first_kex_follows: bool) -> Vec<u8> {
+        let mut payload = vec![SSH_MSG_KEXINIT];
+        payload.extend_from_slice(&[0x42; 16]);
+        for l in lists {
+            payload.extend_from_slice(&(l.len() as u32).to_be_bytes());
+            payload.extend_from_slice(l.as_bytes());
+        }
+        payload.extend_from_slice(&[first_kex_follows as u8, 0, 0, 0, 0]);
+        let pad = 8 - (payload.len() + 5) % 8 + 4;
+        let pkt_len = (1 + payload.len() + pad) as u32;
+        [&pkt_len.to_be_bytes()[..], &[pad as u8], &payload, &vec![0; pad]].concat()
+    }
+
+    fn parse(d: &[u8]) -> (i32, Option<IrisSshInfo>) {
+        let mut out = std::mem::MaybeUninit::<IrisSshInfo>::uninit();
+        let rc = iris_ssh_parse(d.as_ptr(), d.len(), out.as_mut_ptr());
+        (rc, (rc == 0).then(|| unsafe { out.assume_init() }))
+    }
+
+    fn s(p: *mut c_char) -> String {
+        unsafe { CStr::from_ptr(p) }.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn banner_and_hassh() {
+        let banner = b"Welcome to example\r\nSSH-2.0-OpenSSH_9.6p1 Ubuntu-3ubuntu13\r\n";
+        let d = [&banner[..], &kexinit(&LISTS, true), b"trailing"].concat();
+        let (rc, info) = parse(&d);
+        assert_eq!(rc, 0);
+        let mut i = info.unwrap();
+        assert!(i.has_banner && i.has_kexinit && i.first_kex_follows);
+        assert_eq!((s(i.proto_version), s(i.software), s(i.comments)),
+                   ("2.0".into(), "OpenSSH_9.6p1".into(), "Ubuntu-3ubuntu13".into()));
+        assert_eq!(s(i.host_key_algorithms), LISTS[1]);
+        assert_eq!(s(i.hassh_algorithms), format!("{KEX};{};{};{}", LISTS[2], LISTS[4], LISTS[6]));
+        // Reference values from Python's hashlib.md5 over the pre-hash strings
+        assert_eq!(s(i.hassh), "3dd00477ea72b82ec7166c901a2ee2f1");
+        assert_eq!(s(i.hassh_server), "7ef7f8048413d3373b9cd93182f2d1bf");
+        assert_eq!(i.bytes_consumed, d.len() - b"trailing".len());
+        iris_ssh_free(&mut i);
+
+        // KEXINIT on its own, e.g. the next segment after the banner
+        let (rc, info) = parse(&kexinit(&LISTS, false));
+        let mut i = info.unwrap();
+        assert!(rc == 0 && !i.has_banner && i.has_kexinit && !i.first_kex_follows);
+        assert_eq!(s(i.hassh), "3dd00477ea72b82ec7166c901a2ee2f1");
+        iris_ssh_free(&mut i);
+    }
+
+    #[test]
+    fn truncated_and_invalid() {
+        let k = kexinit(&LISTS, false);
+        assert_eq!(parse(&k[..k.len() - 1]).0, -1);
+        assert_eq!(parse(b"SSH-2.0-OpenSSH_9.6").0, -1);
+        // The banner alone is usable while the KEXINIT is still arriving
+        let d = [&b"SSH-2.0-dropbear\r\n"[..], &k[..40]].concat();
+        let (rc, info) = parse(&d);
+        let mut i = info.unwrap();
+        assert!(rc == 0 && i.has_banner && !i.has_kexinit);
+        assert_eq!((s(i.software), i.bytes_consumed), ("dropbear".into(), 18));
+        iris_ssh_free(&mut i);
+
+        // Packet lengths outside 12..=MAX_PACKET_LEN
+        let mut big = k.clone();
+        big[..4].copy_from_slice(&(MAX_PACKET_LEN as u32 + 1).to_be_bytes());
+        assert_eq!(parse(&big).0, -2);
+        let mut small = k.clone();
+        small[..4].copy_from_slice(&11u32.to_be_bytes());
+        assert_eq!(parse(&small).0, -2);
+        // A name list running past the packet
+        let mut bad = k;
+        bad[22..26].copy_from_slice(&0xffffu32.to_be_bytes());
+        assert_eq!(parse(&bad).0, -2);
+
+        assert_eq!(parse(&[b'x'; MAX_BANNER_LEN + 1]).0, -2);
+        assert_eq!(parse(b"SSH-2.0\r\n").0, -2);
+        assert_eq!(parse(b"").0, -2);
+    }
+
+    #[test]
+    fn preamble_limit() {
+        let preamble = |n: usize| [b"notice\r\n".repeat(n), b"SSH-2.0-srv\r\n".to_vec()].concat();
+        let (rc, info) = parse(&preamble(MAX_PREAMBLE_LINES - 1));
+        assert_eq!(rc, 0);
+        iris_ssh_free(&mut info.unwrap());
+        assert_eq!(parse(&preamble(MAX_PREAMBLE_LINES)).0, -2);
+    }
+}
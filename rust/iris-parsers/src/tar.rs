@@ -0,0 +1,187 @@
+//! tar archives: v7, POSIX ustar/pax and GNU (long names, base-256 numbers).
+//! Pax extended headers override the fields of the entry that follows.
+
+const BLOCK: usize = 512;
+const MAX_EXTENDED: usize = 1 << 20;
+
+pub const TAR_V7: u8 = 1;
+pub const TAR_USTAR: u8 = 2;
+pub const TAR_PAX: u8 = 3;
+pub const TAR_GNU: u8 = 4;
+
+pub struct Entry<'a> {
+    pub name: String,
+    pub typeflag: u8,  // b'0' file, b'1' hard link, b'2' symlink, b'5' directory, ...
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub mtime: i64,
+    pub link: String,
+    pub offset: usize, // of the entry data within the archive
+    pub data: &'a [u8],
+}
+
+#[derive(Default)]
+struct Overrides {
+    path: Option<String>,
+    link: Option<String>,
+    size: Option<u64>,
+    mtime: Option<i64>,
+    uid: Option<u32>,
+    gid: Option<u32>,
+}
+
+fn cstr(b: &[u8]) -> String {
+    let end = b.iter().position(|&c| c == 0).unwrap_or(b.len());
+    String::from_utf8_lossy(&b[..end]).into_owned()
+}
+
+/// Octal number padded with spaces/NULs, or GNU base-256 when the high bit is set.
+fn number(b: &[u8]) -> Option<u64> {
+    if b.first().is_some_and(|&c| c & 0x80 != 0) {
+        if b[0] & 0x40 != 0 { return None; } // negative
+        return b[1..].iter().try_fold((b[0] & 0x3f) as u64, |v, &c| v.checked_mul(256).map(|v| v | c as u64));
+    }
+    let s = std::str::from_utf8(b).ok()?.trim_matches(|c| c == ' ' || c == '\0');
+    if s.is_empty() { Some(0) } else { u64::from_str_radix(s, 8).ok() }
+}
+
+fn checksum_ok(h: &[u8]) -> bool {
+    let Some(stored) = number(&h[148..156]) else { return false };
+    let sum: u64 = h.iter().enumerate().map(|(i, &b)| if (148..156).contains(&i) { b' ' as u64 } else { b as u64 }).sum();
+    sum == stored
+}
+
+/// Whether a block looks like a tar header (checksum matches and it is not all zero).
+pub fn is_header(d: &[u8]) -> bool {
+    d.get(..BLOCK).is_some_and(|h| h.iter().any(|&b| b != 0) && checksum_ok(h))
+}
+
+/// Format of the first header: TAR_*.
+pub fn format(d: &[u8]) -> u8 {
+    match d.get(257..265) {
+        Some(b"ustar  \0") => TAR_GNU,
+        Some(m) if m.starts_with(b"ustar\0") => if d.get(156) == Some(&b'x') || d.get(156) == Some(&b'g') { TAR_PAX } else { TAR_USTAR },
+        _ => TAR_V7,
+    }
+}
+
+fn pax(records: &[u8], o: &mut Overrides) {
+    let mut p = 0;
+    while p < records.len() {
+        let Some(sp) = records[p..].iter().position(|&b| b == b' ') else { break };
+        let Some(len) = std::str::from_utf8(&records[p..p + sp]).ok().and_then(|s| s.parse::<usize>().ok()) else { break };
+        let Some(rec) = records.get(p + sp + 1..p + len) else { break };
+        let rec = rec.strip_suffix(b"\n").unwrap_or(rec);
+        if let Some(eq) = rec.iter().position(|&b| b == b'=') {
+            let v = String::from_utf8_lossy(&rec[eq + 1..]).into_owned();
+            match &rec[..eq] {
+                b"path" => o.path = Some(v),
+                b"linkpath" => o.link = Some(v),
+                b"size" => o.size = v.parse().ok(),
+                b"mtime" => o.mtime = v.split('.').next().and_then(|s| s.parse().ok()),
+                b"uid" => o.uid = v.parse().ok(),
+                b"gid" => o.gid = v.parse().ok(),
+                _ => {}
+            }
+        }
+        p += len.max(1);
+    }
+}
+
+/// Entries up to the end-of-archive marker. Stops quietly at the first header with a
+/// bad checksum or data running past the end.
+pub fn entries(data: &[u8]) -> Vec<Entry<'_>> {
+    let mut out = Vec::new();
+    let mut next = Overrides::default();
+    let mut p = 0;
+    while let Some(h) = data.get(p..p + BLOCK) {
+        if !is_header(h) { break; }
+        let typeflag = h[156];
+        let Some(mut size) = number(&h[124..136]) else { break };
+        if matches!(typeflag, b'1' | b'2' | b'5') { size = 0; }
+        let start = p + BLOCK;
+        let Some(body) = usize::try_from(next.size.filter(|_| !matches!(typeflag, b'x' | b'g' | b'L' | b'K')).unwrap_or(size)).ok()
+            .and_then(|s| data.get(start..start.checked_add(s)?)) else { break };
+        p = start + body.len().next_multiple_of(BLOCK);
+        match typeflag {
+            b'x' if body.len() <= MAX_EXTENDED => { pax(body, &mut next); continue; }
+            b'g' => continue,
+            b'L' => { next.path = Some(cstr(body)); continue; }
+            b'K' => { next.link = Some(cstr(body)); continue; }
+            _ => {}
+        }
+        let mut name = cstr(&h[..100]);
+        if h[257..263] == *b"ustar\0" && h[345] != 0 { name = format!("{}/{}", cstr(&h[345..500]), name); }
+        let o = std::mem::take(&mut next);
+        out.push(Entry {
+            name: o.path.unwrap_or(name),
+            typeflag: if typeflag == 0 { b'0' } else { typeflag },
+            mode: number(&h[100..108]).unwrap_or(0) as u32,
+            uid: o.uid.unwrap_or_else(|| number(&h[108..116]).unwrap_or(0) as u32),
+            gid: o.gid.unwrap_or_else(|| number(&h[116..124]).unwrap_or(0) as u32),
+            mtime: o.mtime.unwrap_or_else(|| number(&h[136..148]).unwrap_or(0) as i64),
+            link: o.link.unwrap_or_else(|| cstr(&h[157..257])),
+            offset: start,
+            data: body,
+        });
+    }
+    out
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    /// ustar header block for `name` followed by its data, padded.
+    pub fn member(name: &str, typeflag: u8, link: &str, data: &[u8]) -> Vec<u8> {
+        let mut h = vec![0u8; BLOCK];
+        h[..name.len()].copy_from_slice(name.as_bytes());
+        h[100..108].copy_from_slice(b"0000755\0");
+        h[108..116].copy_from_slice(b"0000765\0");
+        h[116..124].copy_from_slice(b"0000024\0");
+        h[124..136].copy_from_slice(format!("{:011o}\0", data.len()).as_bytes());
+        h[136..148].copy_from_slice(b"14524770400\0");
+        h[156] = typeflag;
+        h[157..157 + link.len()].copy_from_slice(link.as_bytes());
+        h[257..263].copy_from_slice(b"ustar\0");
+        h[263..265].copy_from_slice(b"00");
+        h[148..156].copy_from_slice(b"        ");
+        let sum: u32 = h.iter().map(|&b| b as u32).sum();
+        h[148..156].copy_from_slice(format!("{:06o}\0 ", sum).as_bytes());
+        h.extend_from_slice(data);
+        h.resize(h.len().next_multiple_of(BLOCK), 0);
+        h
+    }
+
+    pub fn end() -> Vec<u8> { vec![0; 2 * BLOCK] }
+
+    #[test]
+    fn ustar_pax_and_gnu() {
+        let long = format!("{}/deep.sh", "d".repeat(120));
+        let rec = format!(" path={}\n", long);
+        let pax_rec = format!("{}{}", rec.len() + 3, rec);
+        let a = [
+            member("./", b'5', "", b""),
+            member("././@PaxHeader", b'x', "", pax_rec.as_bytes()),
+            member("short", b'0', "", b"echo hi\n"),
+            member("link", b'2', "/etc/passwd", b""),
+            member("././@LongLink", b'L', "", b"gnu/very/long/name\0"),
+            member("gnu/very/lo", b'0', "", b"x"),
+            end(),
+        ].concat();
+        assert_eq!(format(&a), TAR_USTAR);
+        let e = entries(&a);
+        assert_eq!(e.len(), 4);
+        assert_eq!((e[0].typeflag, e[0].uid, e[0].gid, e[0].mtime), (b'5', 501, 20, 1_700_000_000));
+        assert_eq!((e[1].name.as_str(), e[1].data), (long.as_str(), &b"echo hi\n"[..]));
+        assert_eq!(&a[e[1].offset..e[1].offset + 8], b"echo hi\n");
+        assert_eq!((e[2].typeflag, e[2].link.as_str()), (b'2', "/etc/passwd"));
+        assert_eq!((e[3].name.as_str(), e[3].data), ("gnu/very/long/name", &b"x"[..]));
+
+        assert_eq!(number(&[0x80, 0, 0, 0, 0, 0, 0, 1, 0]), Some(256));
+        let mut bad = a.clone();
+        bad[BLOCK * 3] ^= 1;
+        assert_eq!(entries(&bad).len(), 1);
+    }
+}
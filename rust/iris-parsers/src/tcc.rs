@@ -0,0 +1,197 @@
+//! TCC privacy databases (/Library/Application Support/com.apple.TCC/TCC.db and the
+//! per-user copy): one row per service/client decision, with anomaly flags for audit.
+
+use crate::ffi::{alloc_array, alloc_bytes, free_array, free_cstr, to_cstr};
+use crate::requirement;
+use crate::sqlite::{Database, Row, Table, Value};
+use std::ffi::{c_char, CStr};
+
+const ACCESS_TABLE: &str = "access";
+
+pub const TCC_AUTH_DENIED: i32 = 0;
+pub const TCC_AUTH_ALLOWED: i32 = 2;
+pub const TCC_AUTH_LIMITED: i32 = 3;
+
+const CLIENT_BUNDLE_ID: i32 = 0;
+const CLIENT_PATH: i32 = 1;
+
+pub const TCC_ANOMALY_NO_CSREQ: u32 = 0x01;           // granted without a code requirement
+pub const TCC_ANOMALY_BAD_CSREQ: u32 = 0x02;          // csreq present but undecodable
+pub const TCC_ANOMALY_CSREQ_MISMATCH: u32 = 0x04;     // requirement names a different identifier
+pub const TCC_ANOMALY_WRITABLE_PATH: u32 = 0x08;      // path client in a user-writable or temp location
+pub const TCC_ANOMALY_SENSITIVE: u32 = 0x10;          // granted a high-impact service
+
+const SENSITIVE_SERVICES: &[&str] = &[
+    "kTCCServiceSystemPolicyAllFiles",
+    "kTCCServiceSystemPolicySysAdminFiles",
+    "kTCCServiceAccessibility",
+    "kTCCServiceScreenCapture",
+    "kTCCServiceListenEvent",
+    "kTCCServicePostEvent",
+    "kTCCServiceEndpointSecurityClient",
+    "kTCCServiceDeveloperTool",
+];
+
+const WRITABLE_PREFIXES: &[&str] = &["/tmp/", "/private/tmp/", "/var/tmp/", "/private/var/tmp/", "/var/folders/", "/private/var/folders/", "/Users/Shared/"];
+
+#[repr(C)]
+pub struct IrisTccEntry {
+    pub service: *mut c_char,              // "kTCCServiceSystemPolicyAllFiles", ...
+    pub client: *mut c_char,               // bundle ID or absolute path, per client_type
+    pub client_type: i32,                  // 0=bundle ID, 1=path
+    pub auth_value: i32,                   // 0=denied, 1=unknown, 2=allowed, 3=limited
+    pub auth_reason: i32,                  // 2=user consent, 3=user set, 4=system set, 6=MDM, ...
+    pub auth_version: i32,
+    pub requirement: *mut c_char,          // decompiled csreq, "" if none or undecodable
+    pub csreq: *mut u8,                    // raw csreq blob
+    pub csreq_len: usize,
+    pub indirect_object: *mut c_char,      // Apple Events target, "" if none
+    pub indirect_object_requirement: *mut c_char,
+    pub flags: i64,
+    pub last_modified: i64,                // unix seconds
+    pub last_reminded: i64,                // unix seconds, 0 on older schemas
+    pub anomalies: u32,                    // TCC_ANOMALY_* bits
+}
+
+#[repr(C)]
+pub struct IrisTccEntries {
+    pub entries: *mut IrisTccEntry,
+    pub count: usize,
+}
+
+/// Temp directories, /Users/Shared and a user's Downloads or Desktop.
+pub fn user_writable(path: &str) -> bool {
+    WRITABLE_PREFIXES.iter().any(|p| path.starts_with(p))
+        || path.split('/').nth(3).is_some_and(|d| path.starts_with("/Users/") && (d == "Downloads" || d == "Desktop"))
+}
+
+fn blob<'a>(r: &'a Row, t: &Table, col: &str) -> &'a [u8] {
+    match r.get(t.column(col)) { Value::Blob(b) => b, _ => &[] }
+}
+
+fn decompiled(csreq: &[u8]) -> Option<String> {
+    if csreq.is_empty() { Some(String::new()) } else { requirement::decompile(csreq) }
+}
+
+fn anomalies(service: &str, client: &str, client_type: i32, auth_value: i32, csreq: &[u8], req: Option<&str>) -> u32 {
+    let mut a = 0;
+    if req.is_none() { a |= TCC_ANOMALY_BAD_CSREQ; }
+    if let Some(ident) = req.and_then(|r| r.strip_prefix("identifier \"")).and_then(|r| r.split('"').next()) {
+        if client_type == CLIENT_BUNDLE_ID && ident != client { a |= TCC_ANOMALY_CSREQ_MISMATCH; }
+    }
+    if auth_value != TCC_AUTH_ALLOWED && auth_value != TCC_AUTH_LIMITED { return a; }
+    if csreq.is_empty() { a |= TCC_ANOMALY_NO_CSREQ; }
+    if client_type == CLIENT_PATH && user_writable(client) {
+        a |= TCC_ANOMALY_WRITABLE_PATH;
+    }
+    if SENSITIVE_SERVICES.contains(&service) { a |= TCC_ANOMALY_SENSITIVE; }
+    a
+}
+
+fn entry(t: &Table, r: &Row) -> IrisTccEntry {
+    let text = |col: &str| r.get(t.column(col)).as_str();
+    let int = |col: &str| r.get(t.column(col)).as_int();
+    // Pre-Big Sur schemas have "allowed" (0/1) instead of auth_value
+    let auth_value = int("auth_value").or_else(|| int("allowed").map(|a| if a != 0 { TCC_AUTH_ALLOWED } else { TCC_AUTH_DENIED } as i64)).unwrap_or(-1) as i32;
+    let client_type = int("client_type").unwrap_or(-1) as i32;
+    let csreq = blob(r, t, "csreq");
+    let req = decompiled(csreq);
+    let anomalies = anomalies(text("service"), text("client"), client_type, auth_value, csreq, req.as_deref());
+    let (csreq_ptr, csreq_len) = alloc_bytes(csreq);
+    IrisTccEntry {
+        service: to_cstr(text("service")),
+        client: to_cstr(text("client")),
+        client_type,
+        auth_value,
+        auth_reason: int("auth_reason").unwrap_or(0) as i32,
+        auth_version: int("auth_version").unwrap_or(0) as i32,
+        requirement: to_cstr(req.as_deref().unwrap_or("")),
+        csreq: csreq_ptr, csreq_len,
+        indirect_object: to_cstr(text("indirect_object_identifier").trim_start_matches("UNUSED")),
+        indirect_object_requirement: to_cstr(&decompiled(blob(r, t, "indirect_object_code_identity")).unwrap_or_default()),
+        flags: int("flags").unwrap_or(0),
+        last_modified: int("last_modified").unwrap_or(0),
+        last_reminded: int("last_reminded").unwrap_or(0),
+        anomalies,
+    }
+}
+
+// --- FFI entry points ---
+
+/// Read every row of the access table of a TCC.db file (plus its -wal if present).
+/// Returns 0=ok, -1=file error, -2=arg error or not a TCC database.
+/// Free with iris_tcc_free.
+#[no_mangle]
+pub extern "C" fn iris_tcc_parse(path: *const c_char, out: *mut IrisTccEntries) -> i32 {
+    if path.is_null() || out.is_null() { return -2; }
+    let Ok(path) = unsafe { CStr::from_ptr(path) }.to_str() else { return -2 };
+    let db = match Database::open_path(path) { Ok(db) => db, Err(e) => return e };
+    let Some(t) = db.table(ACCESS_TABLE) else { return -2 };
+    if t.column("service").is_none() || t.column("client").is_none() { return -2; }
+    let entries: Vec<_> = db.rows(&t).iter().map(|r| entry(&t, r)).collect();
+    let (entries, count) = alloc_array(entries);
+    unsafe { out.write(IrisTccEntries { entries, count }); }
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn iris_tcc_free(entries: *mut IrisTccEntries) {
+    if entries.is_null() { return; }
+    let e = unsafe { &*entries };
+    for i in 0..e.count {
+        let t = unsafe { &*e.entries.add(i) };
+        for p in [t.service, t.client, t.requirement, t.indirect_object, t.indirect_object_requirement] { free_cstr(p); }
+        free_array(t.csreq, t.csreq_len);
+    }
+    free_array(e.entries, e.count);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::requirement::tests::developer_id;
+    use crate::sqlite::tests::database;
+
+    #[test]
+    fn access_rows_and_anomalies() {
+        let sql = "CREATE TABLE access (    service        TEXT        NOT NULL,     client         TEXT        NOT NULL,     \
+                   client_type    INTEGER     NOT NULL,     auth_value     INTEGER     NOT NULL,     auth_reason    INTEGER     NOT NULL,     \
+                   auth_version   INTEGER     NOT NULL,     csreq          BLOB,     policy_id      INTEGER,     \
+                   indirect_object_identifier_type    INTEGER,     indirect_object_identifier         TEXT NOT NULL DEFAULT 'UNUSED',     \
+                   indirect_object_code_identity      BLOB,     flags          INTEGER,     last_modified  INTEGER     NOT NULL DEFAULT (CAST(strftime('%s','now') AS INTEGER)),     \
+                   PRIMARY KEY (service, client, client_type, indirect_object_identifier),    FOREIGN KEY (policy_id) REFERENCES policies(id) ON DELETE CASCADE ON UPDATE CASCADE)";
+        let t = |s: &str| Value::Text(s.into());
+        let row = |service: &str, client: &str, ty: i64, auth: i64, csreq: Value| vec![
+            t(service), t(client), Value::Int(ty), Value::Int(auth), Value::Int(2), Value::Int(1), csreq,
+            Value::Null, Value::Int(0), t("UNUSED"), Value::Null, Value::Int(0), Value::Int(1_700_000_000),
+        ];
+        let rows = vec![
+            row("kTCCServiceMicrophone", "us.zoom.xos", 0, 2, Value::Blob(developer_id("us.zoom.xos", "BJ4HAAB9B3"))),
+            row("kTCCServiceSystemPolicyAllFiles", "/private/tmp/agent", 1, 2, Value::Null),
+            row("kTCCServiceAccessibility", "com.example.app", 0, 2, Value::Blob(developer_id("com.evil.app", "TEAM"))),
+            row("kTCCServiceCamera", "com.example.other", 0, 0, Value::Blob(vec![1, 2, 3])),
+        ];
+        let db = database(&[("CREATE TABLE admin (key TEXT PRIMARY KEY NOT NULL, value INTEGER NOT NULL)", vec![]), (sql, rows)]);
+        let path = std::env::temp_dir().join(format!("iris-tcc-{}.db", std::process::id()));
+        std::fs::write(&path, &db).unwrap();
+        let cpath = std::ffi::CString::new(path.to_str().unwrap()).unwrap();
+
+        let mut out = std::mem::MaybeUninit::<IrisTccEntries>::uninit();
+        assert_eq!(iris_tcc_parse(cpath.as_ptr(), out.as_mut_ptr()), 0);
+        std::fs::remove_file(&path).unwrap();
+        let mut entries = unsafe { out.assume_init() };
+        let e = unsafe { std::slice::from_raw_parts(entries.entries, entries.count) };
+        let cs = |p: *mut c_char| unsafe { CStr::from_ptr(p) }.to_string_lossy().into_owned();
+        assert_eq!(e.len(), 4);
+        assert_eq!((cs(e[0].service), cs(e[0].client), e[0].auth_value, e[0].auth_reason), ("kTCCServiceMicrophone".into(), "us.zoom.xos".into(), TCC_AUTH_ALLOWED, 2));
+        assert_eq!(cs(e[0].requirement), "identifier \"us.zoom.xos\" and anchor apple generic and certificate leaf[subject.OU] = \"BJ4HAAB9B3\"");
+        assert_eq!((e[0].anomalies, e[0].last_modified, cs(e[0].indirect_object)), (0, 1_700_000_000, String::new()));
+        assert_eq!(e[1].anomalies, TCC_ANOMALY_NO_CSREQ | TCC_ANOMALY_WRITABLE_PATH | TCC_ANOMALY_SENSITIVE);
+        assert_eq!(e[2].anomalies, TCC_ANOMALY_CSREQ_MISMATCH | TCC_ANOMALY_SENSITIVE);
+        assert_eq!((e[3].auth_value, e[3].anomalies, e[3].csreq_len, cs(e[3].requirement)), (TCC_AUTH_DENIED, TCC_ANOMALY_BAD_CSREQ, 3, String::new()));
+        iris_tcc_free(&mut entries);
+
+        let mut out = std::mem::MaybeUninit::<IrisTccEntries>::uninit();
+        assert_eq!(iris_tcc_parse(cpath.as_ptr(), out.as_mut_ptr()), -1);
+    }
+}
@@ -0,0 +1,650 @@
+//! TLS ClientHello parsing: offered versions, cipher suites, extensions, SNI/ALPN, and
+//! Encrypted Client Hello (and legacy ESNI), which hide the real destination name.
+//! GREASE ECH looks the same on the wire; it is told apart by checking the outer
+//! extension against the ECHConfigList the server publishes in DNS.
+
+use crate::bytes::be16;
+use crate::ffi::{alloc_array, free_array, free_c_string_array, free_cstr, to_cstr, vec_to_c_string_array, IrisCStringArray};
+use crate::hash::{md5_digest, sha256_digest, to_hex};
+use std::ffi::c_char;
+
+const CONTENT_HANDSHAKE: u8 = 22;
+const HS_CLIENT_HELLO: u8 = 1;
+
+/// ServerHello.random of a HelloRetryRequest (RFC 8446 §4.1.3).
+const HRR_RANDOM: [u8; 32] = [
+    0xcf, 0x21, 0xad, 0x74, 0xe5, 0x9a, 0x61, 0x11, 0xbe, 0x1d, 0x8c, 0x02, 0x1e, 0x65, 0xb8, 0x91,
+    0xc2, 0xa2, 0x11, 0x16, 0x7a, 0xbb, 0x8c, 0x5e, 0x07, 0x9e, 0x09, 0xe2, 0xc8, 0xa8, 0x33, 0x9c,
+];
+
+const EXT_SERVER_NAME: u16 = 0;
+const EXT_SUPPORTED_GROUPS: u16 = 10;
+const EXT_EC_POINT_FORMATS: u16 = 11;
+const EXT_SIGNATURE_ALGORITHMS: u16 = 13;
+const EXT_ALPN: u16 = 16;
+const EXT_SESSION_TICKET: u16 = 35;
+const EXT_PRE_SHARED_KEY: u16 = 41;
+const EXT_EARLY_DATA: u16 = 42;
+const EXT_SUPPORTED_VERSIONS: u16 = 43;
+const EXT_POST_HANDSHAKE_AUTH: u16 = 49;
+const EXT_ECH: u16 = 0xfe0d;
+const EXT_ESNI: u16 = 0xffce;
+const EXT_RENEGOTIATION_INFO: u16 = 0xff01;
+/// TLS_EMPTY_RENEGOTIATION_INFO_SCSV (RFC 5746 §3.3).
+const SCSV_RENEGOTIATION: u16 = 0x00ff;
+const ECH_CONFIG_VERSION: u16 = 0xfe0d;
+
+pub const TLS_ECH_OUTER: u8 = 0;
+pub const TLS_ECH_INNER: u8 = 1;
+
+#[repr(C)]
+pub struct IrisTlsClientHello {
+    pub legacy_version: u16,     // ClientHello.legacy_version (0x0303 for TLS 1.2/1.3)
+    pub max_version: u16,        // highest non-GREASE supported_versions entry, else legacy_version
+    pub session_id_len: u8,
+    pub sni: *mut c_char,        // outer SNI; with ECH this is the client-facing server, not the destination
+    pub alpn: IrisCStringArray,
+    pub cipher_suites: *mut u16, // in offered order, GREASE included
+    pub cipher_suites_count: usize,
+    pub extensions: *mut u16,    // extension types in wire order
+    pub extensions_count: usize,
+    pub groups: *mut u16,        // supported_groups
+    pub groups_count: usize,
+    pub ech_present: bool,       // encrypted_client_hello (0xfe0d)
+    pub ech_type: u8,            // TLS_ECH_OUTER / TLS_ECH_INNER
+    pub ech_config_id: u8,       // outer only
+    pub ech_kdf: u16,            // HPKE KDF id, outer only
+    pub ech_aead: u16,           // HPKE AEAD id, outer only
+    pub ech_enc_len: u16,
+    pub ech_payload_len: u16,
+    pub esni_present: bool,      // draft encrypted_server_name (0xffce)
+    pub name_hidden: bool,       // ECH outer or ESNI: the true server name is not on the wire
+}
+
+#[derive(Default)]
+struct ClientHello {
+    legacy_version: u16,
+    max_version: u16,
+    session_id: Vec<u8>,
+    session_ticket: bool,      // session_ticket extension carrying a ticket
+    sni: String,
+    alpn: Vec<String>,
+    cipher_suites: Vec<u16>,
+    extensions: Vec<u16>,
+    groups: Vec<u16>,
+    point_formats: Vec<u8>,
+    signature_algorithms: Vec<u16>,
+    ech: Option<Ech>,
+    esni: bool,
+}
+
+#[derive(Default)]
+struct Ech {
+    kind: u8,
+    config_id: u8,
+    kdf: u16,
+    aead: u16,
+    enc_len: u16,
+    payload_len: u16,
+}
+
+/// The parts of an ECHConfig (draft-ietf-tls-esni §4) a ClientHello must agree with.
+struct EchConfig {
+    config_id: u8,
+    public_key_len: usize,   // equals the encapsulated key size for DHKEMs
+    suites: Vec<(u16, u16)>, // (KDF, AEAD)
+    public_name: String,
+}
+
+/// RFC 8701 GREASE values: 0x?a?a with equal bytes.
+pub fn is_grease(v: u16) -> bool {
+    v & 0x0f0f == 0x0a0a && v >> 8 == v & 0xff
+}
+
+/// Split `d` into a u8/u16-length-prefixed vector at `o` and the offset after it.
+fn vec8(d: &[u8], o: usize) -> Option<(&[u8], usize)> {
+    let n = *d.get(o)? as usize;
+    Some((d.get(o + 1..o + 1 + n)?, o + 1 + n))
+}
+
+fn vec16(d: &[u8], o: usize) -> Option<(&[u8], usize)> {
+    let n = be16(d, o)? as usize;
+    Some((d.get(o + 2..o + 2 + n)?, o + 2 + n))
+}
+
+fn u16_list(d: &[u8]) -> Vec<u16> {
+    d.chunks_exact(2).map(|c| u16::from_be_bytes([c[0], c[1]])).collect()
+}
+
+fn parse_ech(d: &[u8]) -> Option<Ech> {
+    let kind = *d.first()?;
+    if kind == TLS_ECH_INNER { return Some(Ech { kind, ..Default::default() }); }
+    if kind != TLS_ECH_OUTER { return None; }
+    let (enc, next) = vec16(d, 6)?;
+    let (payload, _) = vec16(d, next)?;
+    Some(Ech {
+        kind,
+        kdf: be16(d, 1)?,
+        aead: be16(d, 3)?,
+        config_id: *d.get(5)?,
+        enc_len: enc.len() as u16,
+        payload_len: payload.len() as u16,
+    })
+}
+
+/// ECHConfigList, as in an HTTPS record's ech parameter. Configs of other versions
+/// are skipped.
+fn parse_ech_configs(d: &[u8]) -> Option<Vec<EchConfig>> {
+    let (list, _) = vec16(d, 0)?;
+    let mut configs = Vec::new();
+    let mut p = 0;
+    while p < list.len() {
+        let version = be16(list, p)?;
+        let (c, next) = vec16(list, p + 2)?;
+        p = next;
+        if version != ECH_CONFIG_VERSION { continue; }
+        let (key, o) = vec16(c, 3)?;
+        let (suites, o) = vec16(c, o)?;
+        let (name, _) = vec8(c, o + 1)?;
+        configs.push(EchConfig {
+            config_id: *c.first()?,
+            public_key_len: key.len(),
+            suites: suites.chunks_exact(4).map(|s| (u16::from_be_bytes([s[0], s[1]]), u16::from_be_bytes([s[2], s[3]]))).collect(),
+            public_name: String::from_utf8_lossy(name).into_owned(),
+        });
+    }
+    Some(configs)
+}
+
+/// Whether an outer ECH extension was built from `cfg`: same config id, an
+/// offered cipher suite, a key share of its size (empty after HelloRetryRequest)
+/// and the config's public name as the outer SNI. GREASE ECH fails this.
+fn ech_matches(ech: &Ech, sni: &str, cfg: &EchConfig) -> bool {
+    ech.config_id == cfg.config_id
+        && cfg.suites.contains(&(ech.kdf, ech.aead))
+        && (ech.enc_len == 0 || ech.enc_len as usize == cfg.public_key_len)
+        && sni.trim_end_matches('.').eq_ignore_ascii_case(&cfg.public_name)
+}
+
+fn parse_extension(ch: &mut ClientHello, ty: u16, body: &[u8]) -> Option<()> {
+    match ty {
+        EXT_SERVER_NAME => {
+            let (list, _) = vec16(body, 0)?;
+            let mut o = 0;
+            while o + 3 <= list.len() {
+                let (name, next) = vec16(list, o + 1)?;
+                if list[o] == 0 { ch.sni = String::from_utf8_lossy(name).into_owned(); break; }
+                o = next;
+            }
+        }
+        EXT_ALPN => {
+            let (list, _) = vec16(body, 0)?;
+            let mut o = 0;
+            while o < list.len() {
+                let (proto, next) = vec8(list, o)?;
+                ch.alpn.push(String::from_utf8_lossy(proto).into_owned());
+                o = next;
+            }
+        }
+        EXT_SUPPORTED_GROUPS => ch.groups = u16_list(vec16(body, 0)?.0),
+        EXT_EC_POINT_FORMATS => ch.point_formats = vec8(body, 0)?.0.to_vec(),
+        EXT_SIGNATURE_ALGORITHMS => ch.signature_algorithms = u16_list(vec16(body, 0)?.0),
+        EXT_SUPPORTED_VERSIONS => {
+            let versions = u16_list(vec8(body, 0)?.0);
+            if let Some(v) = versions.into_iter().filter(|&v| !is_grease(v)).max() { ch.max_version = v; }
+        }
+        EXT_SESSION_TICKET => ch.session_ticket = !body.is_empty(),
+        EXT_ECH => ch.ech = Some(parse_ech(body)?),
+        EXT_ESNI => ch.esni = true,
+        _ => {}
+    }
+    Some(())
+}
+
+fn parse_client_hello_body(b: &[u8]) -> Option<ClientHello> {
+    let legacy_version = be16(b, 0)?;
+    let (session_id, o) = vec8(b, 34)?;
+    let (suites, o) = vec16(b, o)?;
+    let (_compression, o) = vec8(b, o)?;
+    let mut ch = ClientHello {
+        legacy_version,
+        max_version: legacy_version,
+        session_id: session_id.to_vec(),
+        cipher_suites: u16_list(suites),
+        ..Default::default()
+    };
+    // Extensions are optional in pre-TLS 1.2 hellos
+    if o == b.len() { return Some(ch); }
+    let (exts, _) = vec16(b, o)?;
+    let mut p = 0;
+    while p + 4 <= exts.len() {
+        let ty = be16(exts, p)?;
+        let (body, next) = vec16(exts, p + 2)?;
+        ch.extensions.push(ty);
+        parse_extension(&mut ch, ty, body)?;
+        p = next;
+    }
+    Some(ch)
+}
+
+/// Accepts a TLS handshake record or a bare handshake message.
+fn parse_client_hello(d: &[u8]) -> Result<ClientHello, i32> {
+    let hs = if d.first() == Some(&CONTENT_HANDSHAKE) {
+        if d.len() < 5 { return Err(-1); }
+        if d[1] != 3 { return Err(-3); }
+        &d[5..]
+    } else {
+        d
+    };
+    if hs.first() != Some(&HS_CLIENT_HELLO) { return Err(-3); }
+    if hs.len() < 4 { return Err(-1); }
+    let len = (hs[1] as usize) << 16 | (hs[2] as usize) << 8 | hs[3] as usize;
+    let body = hs.get(4..4 + len).ok_or(-1)?;
+    parse_client_hello_body(body).ok_or(-2)
+}
+
+/// Highest version a ClientHello record offers (supported_versions, else legacy_version).
+pub fn offered_version(d: &[u8]) -> Option<u16> {
+    parse_client_hello(d).ok().map(|ch| ch.max_version)
+}
+
+/// Whether a ClientHello signals RFC 5746 secure renegotiation, by extension or SCSV.
+pub fn secure_renegotiation_offered(d: &[u8]) -> Option<bool> {
+    let ch = parse_client_hello(d).ok()?;
+    Some(ch.extensions.contains(&EXT_RENEGOTIATION_INFO) || ch.cipher_suites.contains(&SCSV_RENEGOTIATION))
+}
+
+/// Whether a TLS 1.3 ClientHello lets the server ask for a certificate after the
+/// handshake (RFC 8446 §4.6.2), which would then travel encrypted.
+pub fn post_handshake_auth_offered(d: &[u8]) -> Option<bool> {
+    Some(parse_client_hello(d).ok()?.extensions.contains(&EXT_POST_HANDSHAKE_AUTH))
+}
+
+/// What a ClientHello offers for resuming an earlier session.
+pub struct ResumptionOffer {
+    pub session_id: Vec<u8>,
+    pub ticket: bool,            // RFC 5077 session ticket
+    pub psk: bool,               // TLS 1.3 pre_shared_key
+    pub early_data: bool,        // 0-RTT data follows
+}
+
+pub fn resumption_offer(d: &[u8]) -> Option<ResumptionOffer> {
+    let ch = parse_client_hello(d).ok()?;
+    Some(ResumptionOffer {
+        ticket: ch.session_ticket,
+        psk: ch.extensions.contains(&EXT_PRE_SHARED_KEY),
+        early_data: ch.extensions.contains(&EXT_EARLY_DATA),
+        session_id: ch.session_id,
+    })
+}
+
+/// Client fingerprints of a ClientHello.
+pub struct Fingerprints {
+    pub ja3: String,             // "771,4865-4866-...,0-23-...,29-23-24,0"
+    pub ja3_hash: String,        // MD5 of ja3, hex
+    pub ja4: String,             // "t13d1516h2_8daaf6152771_e5627efa2ab1"
+}
+
+fn dash_list<T: ToString>(v: impl Iterator<Item = T>) -> String {
+    v.map(|x| x.to_string()).collect::<Vec<_>>().join("-")
+}
+
+/// JA3: version, cipher suites, extensions, groups and point formats in wire
+/// order, GREASE removed.
+fn ja3(ch: &ClientHello) -> String {
+    let no_grease = |v: &[u16]| dash_list(v.iter().filter(|&&x| !is_grease(x)));
+    format!("{},{},{},{},{}", ch.legacy_version, no_grease(&ch.cipher_suites), no_grease(&ch.extensions),
+            no_grease(&ch.groups), dash_list(ch.point_formats.iter()))
+}
+
+/// First 12 hex digits of the SHA-256 of a JA4 list, zeros if the list is empty.
+fn ja4_hash(list: &str) -> String {
+    if list.is_empty() { return "0".repeat(12); }
+    to_hex(&sha256_digest(list.as_bytes()))[..12].to_string()
+}
+
+fn hex_list(v: impl Iterator<Item = u16>) -> Vec<String> {
+    v.map(|x| format!("{:04x}", x)).collect()
+}
+
+/// JA4 (FoxIO): "t13d1516h2" prefix, then truncated hashes of the sorted cipher
+/// suites and of the sorted extensions (SNI and ALPN dropped) with the signature
+/// algorithms in wire order. Sorting makes it stable under extension shuffling.
+fn ja4(ch: &ClientHello, quic: bool) -> String {
+    let version = match ch.max_version {
+        0x0304 => "13", 0x0303 => "12", 0x0302 => "11", 0x0301 => "10", 0x0300 => "s3",
+        0x0200 => "s2", 0xfeff => "d1", 0xfefd => "d2", 0xfefc => "d3",
+        _ => "00",
+    };
+    let sni = if ch.extensions.contains(&EXT_SERVER_NAME) { 'd' } else { 'i' };
+    let alpn = match ch.alpn.first().map(|a| a.as_bytes()) {
+        Some([first, .., last]) | Some([first @ last]) if first.is_ascii_alphanumeric() && last.is_ascii_alphanumeric() => {
+            format!("{}{}", *first as char, *last as char)
+        }
+        Some(a) if !a.is_empty() => {
+            let hex = to_hex(a);
+            format!("{}{}", &hex[..1], &hex[hex.len() - 1..])
+        }
+        _ => "00".to_string(),
+    };
+    let mut suites = hex_list(ch.cipher_suites.iter().copied().filter(|&c| !is_grease(c)));
+    let exts: Vec<u16> = ch.extensions.iter().copied().filter(|&e| !is_grease(e)).collect();
+    let mut hashed = hex_list(exts.iter().copied().filter(|&e| e != EXT_SERVER_NAME && e != EXT_ALPN));
+    suites.sort();
+    hashed.sort();
+    let mut ext_list = hashed.join(",");
+    if !ext_list.is_empty() && !ch.signature_algorithms.is_empty() {
+        ext_list = format!("{}_{}", ext_list, hex_list(ch.signature_algorithms.iter().copied()).join(","));
+    }
+    format!("{}{}{}{:02}{:02}{}_{}_{}", if quic { 'q' } else { 't' }, version, sni, suites.len().min(99),
+            exts.len().min(99), alpn, ja4_hash(&suites.join(",")), ja4_hash(&ext_list))
+}
+
+/// JA3 and JA4 of a ClientHello record or bare handshake message. `quic` selects
+/// the JA4 transport prefix ('q' rather than 't').
+pub fn fingerprints(d: &[u8], quic: bool) -> Result<Fingerprints, i32> {
+    let ch = parse_client_hello(d)?;
+    let ja3 = ja3(&ch);
+    Ok(Fingerprints { ja3_hash: to_hex(&md5_digest(ja3.as_bytes())), ja4: ja4(&ch, quic), ja3 })
+}
+
+/// A ServerHello (or HelloRetryRequest) body.
+pub struct ServerHello<'a> {
+    pub version: u16,            // negotiated: supported_versions when present, else legacy_version
+    pub legacy_version: u16,     // 0x0303 in TLS 1.3
+    pub random: &'a [u8],
+    pub session_id: &'a [u8],
+    pub cipher_suite: u16,
+    pub extensions: Vec<(u16, &'a [u8])>,
+    pub retry: bool,             // a HelloRetryRequest; the real ServerHello follows
+}
+
+impl ServerHello<'_> {
+    pub fn extension(&self, ty: u16) -> Option<&[u8]> {
+        self.extensions.iter().find(|(t, _)| *t == ty).map(|(_, b)| *b)
+    }
+}
+
+pub fn parse_server_hello(b: &[u8]) -> Option<ServerHello<'_>> {
+    let (session_id, o) = vec8(b, 34)?;
+    let mut sh = ServerHello {
+        version: be16(b, 0)?,
+        legacy_version: be16(b, 0)?,
+        random: b.get(2..34)?,
+        session_id,
+        cipher_suite: be16(b, o)?,
+        extensions: Vec::new(),
+        retry: b.get(2..34)? == HRR_RANDOM,
+    };
+    // Extensions are optional before TLS 1.2; o + 3 skips the compression method
+    if b.len() > o + 3 {
+        let (exts, _) = vec16(b, o + 3)?;
+        let mut p = 0;
+        while p + 4 <= exts.len() {
+            let ty = be16(exts, p)?;
+            let (body, next) = vec16(exts, p + 2)?;
+            if ty == EXT_SUPPORTED_VERSIONS { sh.version = be16(body, 0)?; }
+            sh.extensions.push((ty, body));
+            p = next;
+        }
+    }
+    Some(sh)
+}
+
+/// Split back-to-back handshake messages into (type, whole message). None if the
+/// last one is cut short.
+pub fn handshake_messages(d: &[u8]) -> Option<Vec<(u8, &[u8])>> {
+    let mut msgs = Vec::new();
+    let mut p = 0;
+    while p < d.len() {
+        let h = d.get(p..p + 4)?;
+        let len = (h[1] as usize) << 16 | (h[2] as usize) << 8 | h[3] as usize;
+        msgs.push((h[0], d.get(p..p + 4 + len)?));
+        p += 4 + len;
+    }
+    Some(msgs)
+}
+
+// --- FFI entry points ---
+
+/// Parse a ClientHello from a handshake record (or bare handshake message).
+/// Returns 0=ok, -1=truncated, -2=malformed/arg error, -3=not a ClientHello.
+/// Free with iris_tls_client_hello_free.
+#[no_mangle]
+pub extern "C" fn iris_tls_parse_client_hello(data: *const u8, len: usize, out: *mut IrisTlsClientHello) -> i32 {
+    if data.is_null() || out.is_null() || len == 0 { return -2; }
+    let buf = unsafe { std::slice::from_raw_parts(data, len) };
+    let ch = match parse_client_hello(buf) { Ok(c) => c, Err(rc) => return rc };
+    let ech = ch.ech.unwrap_or_default();
+    let ech_present = ch.extensions.contains(&EXT_ECH);
+    let (cipher_suites, cipher_suites_count) = alloc_array(ch.cipher_suites);
+    let (extensions, extensions_count) = alloc_array(ch.extensions);
+    let (groups, groups_count) = alloc_array(ch.groups);
+    unsafe {
+        out.write(IrisTlsClientHello {
+            legacy_version: ch.legacy_version,
+            max_version: ch.max_version,
+            session_id_len: ch.session_id.len() as u8,
+            sni: to_cstr(&ch.sni),
+            alpn: vec_to_c_string_array(ch.alpn),
+            cipher_suites, cipher_suites_count,
+            extensions, extensions_count,
+            groups, groups_count,
+            ech_present,
+            ech_type: ech.kind,
+            ech_config_id: ech.config_id,
+            ech_kdf: ech.kdf,
+            ech_aead: ech.aead,
+            ech_enc_len: ech.enc_len,
+            ech_payload_len: ech.payload_len,
+            esni_present: ch.esni,
+            name_hidden: (ech_present && ech.kind == TLS_ECH_OUTER) || ch.esni,
+        });
+    }
+    0
+}
+
+/// Check a ClientHello's outer ECH extension against the server's ECHConfigList
+/// (IrisDnsSvcb.ech from its HTTPS record). Returns 1=real ECH: the SNI is the
+/// client-facing server and the destination is hidden; 0=no config matches:
+/// GREASE ECH (or a stale config), so the SNI is likely the real destination;
+/// -1=truncated, -2=arg error or malformed config list, -3=not a ClientHello or
+/// no outer ECH.
+#[no_mangle]
+pub extern "C" fn iris_tls_ech_check(data: *const u8, len: usize, configs: *const u8, configs_len: usize) -> i32 {
+    if data.is_null() || configs.is_null() || len == 0 { return -2; }
+    let buf = unsafe { std::slice::from_raw_parts(data, len) };
+    let ch = match parse_client_hello(buf) { Ok(c) => c, Err(rc) => return rc };
+    let Some(ech) = ch.ech.filter(|e| e.kind == TLS_ECH_OUTER) else { return -3 };
+    let Some(configs) = parse_ech_configs(unsafe { std::slice::from_raw_parts(configs, configs_len) }) else { return -2 };
+    configs.iter().any(|c| ech_matches(&ech, &ch.sni, c)) as i32
+}
+
+/// Free a ClientHello filled by iris_tls_parse_client_hello.
+#[no_mangle]
+pub extern "C" fn iris_tls_client_hello_free(ch: *mut IrisTlsClientHello) {
+    if ch.is_null() { return; }
+    unsafe {
+        let c = &*ch;
+        free_cstr(c.sni);
+        free_c_string_array(&c.alpn);
+        free_array(c.cipher_suites, c.cipher_suites_count);
+        free_array(c.extensions, c.extensions_count);
+        free_array(c.groups, c.groups_count);
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    pub fn ext(ty: u16, body: &[u8]) -> Vec<u8> {
+        [&ty.to_be_bytes()[..], &(body.len() as u16).to_be_bytes(), body].concat()
+    }
+
+    fn client_hello(exts: &[Vec<u8>]) -> Vec<u8> {
+        client_hello_with(0x0303, &[0x3a3a, 0x1301, 0xc02f], exts)
+    }
+
+    pub fn client_hello_with(version: u16, suites: &[u16], exts: &[Vec<u8>]) -> Vec<u8> {
+        let exts = exts.concat();
+        let mut body = version.to_be_bytes().to_vec();
+        body.extend_from_slice(&[0x11; 32]);
+        body.push(32);
+        body.extend_from_slice(&[0x22; 32]);
+        body.extend_from_slice(&(suites.len() as u16 * 2).to_be_bytes());
+        suites.iter().for_each(|c| body.extend_from_slice(&c.to_be_bytes()));
+        body.extend_from_slice(&[1, 0]);
+        body.extend_from_slice(&(exts.len() as u16).to_be_bytes());
+        body.extend_from_slice(&exts);
+        let mut hs = vec![HS_CLIENT_HELLO, 0, (body.len() >> 8) as u8, body.len() as u8];
+        hs.extend_from_slice(&body);
+        let mut rec = vec![CONTENT_HANDSHAKE, 3, 1, (hs.len() >> 8) as u8, hs.len() as u8];
+        rec.extend_from_slice(&hs);
+        rec
+    }
+
+    pub fn sni_ext(name: &str) -> Vec<u8> {
+        let mut entry = vec![0];
+        entry.extend_from_slice(&(name.len() as u16).to_be_bytes());
+        entry.extend_from_slice(name.as_bytes());
+        ext(EXT_SERVER_NAME, &[&(entry.len() as u16).to_be_bytes()[..], &entry].concat())
+    }
+
+    fn parse(d: &[u8]) -> (i32, IrisTlsClientHello) {
+        let mut out = std::mem::MaybeUninit::<IrisTlsClientHello>::uninit();
+        let rc = iris_tls_parse_client_hello(d.as_ptr(), d.len(), out.as_mut_ptr());
+        (rc, if rc == 0 { unsafe { out.assume_init() } } else { unsafe { std::mem::zeroed() } })
+    }
+
+    fn cstr(p: *mut c_char) -> String {
+        unsafe { std::ffi::CStr::from_ptr(p) }.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn plain_client_hello() {
+        let d = client_hello(&[
+            sni_ext("example.com"),
+            ext(EXT_ALPN, &[0, 12, 2, b'h', b'2', 8, b'h', b't', b't', b'p', b'/', b'1', b'.', b'1']),
+            ext(EXT_SUPPORTED_GROUPS, &[0, 4, 0x00, 0x1d, 0x00, 0x17]),
+            ext(EXT_SUPPORTED_VERSIONS, &[6, 0x7a, 0x7a, 0x03, 0x04, 0x03, 0x03]),
+        ]);
+        let (rc, mut ch) = parse(&d);
+        assert_eq!(rc, 0);
+        assert_eq!((ch.legacy_version, ch.max_version, ch.session_id_len), (0x0303, 0x0304, 32));
+        assert_eq!(cstr(ch.sni), "example.com");
+        assert_eq!(ch.alpn.count, 2);
+        assert_eq!(unsafe { std::slice::from_raw_parts(ch.cipher_suites, ch.cipher_suites_count) }, &[0x3a3a, 0x1301, 0xc02f]);
+        assert_eq!(unsafe { std::slice::from_raw_parts(ch.extensions, ch.extensions_count) }, &[0, 16, 10, 43]);
+        assert_eq!(ch.groups_count, 2);
+        assert!(!ch.ech_present && !ch.esni_present && !ch.name_hidden);
+        iris_tls_client_hello_free(&mut ch);
+    }
+
+    #[test]
+    fn ech_outer_hides_name() {
+        let mut ech = vec![TLS_ECH_OUTER, 0x00, 0x01, 0x00, 0x01, 0x42, 0x00, 0x20];
+        ech.extend_from_slice(&[0xaa; 32]);
+        ech.extend_from_slice(&[0x00, 0x10]);
+        ech.extend_from_slice(&[0xbb; 16]);
+        let d = client_hello(&[sni_ext("cloudflare-ech.com"), ext(EXT_ECH, &ech)]);
+        let (rc, mut ch) = parse(&d);
+        assert_eq!(rc, 0);
+        assert_eq!(cstr(ch.sni), "cloudflare-ech.com");
+        assert!(ch.ech_present && ch.name_hidden);
+        assert_eq!((ch.ech_type, ch.ech_config_id, ch.ech_kdf, ch.ech_aead), (TLS_ECH_OUTER, 0x42, 1, 1));
+        assert_eq!((ch.ech_enc_len, ch.ech_payload_len), (32, 16));
+        iris_tls_client_hello_free(&mut ch);
+
+        let (rc, mut ch) = parse(&client_hello(&[sni_ext("a.example"), ext(EXT_ESNI, &[0; 8])]));
+        assert_eq!(rc, 0);
+        assert!(ch.esni_present && ch.name_hidden && !ch.ech_present);
+        iris_tls_client_hello_free(&mut ch);
+    }
+
+    #[test]
+    fn ech_against_published_config() {
+        // ECHConfig: id 0x42, X25519, HKDF-SHA256 with AES-128-GCM or ChaCha20Poly1305
+        let mut cfg = vec![0x42, 0x00, 0x20, 0x00, 0x20];
+        cfg.extend_from_slice(&[0xcc; 32]);
+        cfg.extend_from_slice(&[0x00, 0x08, 0x00, 0x01, 0x00, 0x01, 0x00, 0x01, 0x00, 0x03, 0x00]);
+        cfg.push(18);
+        cfg.extend_from_slice(b"cloudflare-ech.com");
+        cfg.extend_from_slice(&[0x00, 0x00]);
+        let entry = [&ECH_CONFIG_VERSION.to_be_bytes()[..], &(cfg.len() as u16).to_be_bytes(), &cfg].concat();
+        // An unknown version ahead of it is skipped
+        let list = [&[0xfe, 0x09, 0x00, 0x01, 0xff][..], &entry].concat();
+        let list = [&(list.len() as u16).to_be_bytes()[..], &list].concat();
+
+        let outer = |config_id: u8, sni: &str| {
+            let mut ech = vec![TLS_ECH_OUTER, 0x00, 0x01, 0x00, 0x01, config_id, 0x00, 0x20];
+            ech.extend_from_slice(&[0xaa; 32]);
+            ech.extend_from_slice(&[0x00, 0x10]);
+            ech.extend_from_slice(&[0xbb; 16]);
+            client_hello(&[sni_ext(sni), ext(EXT_ECH, &ech)])
+        };
+        let check = |d: &[u8]| iris_tls_ech_check(d.as_ptr(), d.len(), list.as_ptr(), list.len());
+        assert_eq!(check(&outer(0x42, "Cloudflare-ECH.com")), 1);
+        // GREASE ECH: random config id and the real destination as SNI
+        assert_eq!(check(&outer(0x17, "example.com")), 0);
+        assert_eq!(check(&outer(0x42, "example.com")), 0);
+        assert_eq!(check(&client_hello(&[sni_ext("example.com")])), -3);
+        let d = outer(0x42, "cloudflare-ech.com");
+        assert_eq!(iris_tls_ech_check(d.as_ptr(), d.len(), list.as_ptr(), list.len() - 1), -2);
+    }
+
+    #[test]
+    fn truncated_and_foreign() {
+        let d = client_hello(&[sni_ext("example.com")]);
+        assert_eq!(parse(&d[..d.len() - 3]).0, -1);
+        assert_eq!(parse(&[CONTENT_HANDSHAKE, 3, 3, 0, 4, 2, 0, 0, 0]).0, -3);
+        assert_eq!(parse(b"GET / HTTP/1.1\r\n").0, -3);
+        assert!(is_grease(0xdada) && !is_grease(0x1301));
+    }
+
+    fn u16s(v: &[u16]) -> Vec<u8> {
+        v.iter().flat_map(|x| x.to_be_bytes()).collect()
+    }
+
+    /// Chrome's ClientHello from the JA4 documentation, GREASE included.
+    pub fn chrome_hello() -> Vec<u8> {
+        let suites = [0x4a4a, 0x1301, 0x1302, 0x1303, 0xc02b, 0xc02f, 0xc02c, 0xc030, 0xcca9, 0xcca8, 0xc013, 0xc014,
+                      0x009c, 0x009d, 0x002f, 0x0035];
+        let groups = u16s(&[0x2a2a, 0x001d, 0x0017, 0x0018]);
+        let sigs = u16s(&[0x0403, 0x0804, 0x0401, 0x0503, 0x0805, 0x0501, 0x0806, 0x0601]);
+        let exts = [
+            ext(0x1a1a, &[]), sni_ext("example.com"), ext(0x0017, &[]), ext(0xff01, &[0]),
+            ext(EXT_SUPPORTED_GROUPS, &[&(groups.len() as u16).to_be_bytes()[..], &groups].concat()),
+            ext(EXT_EC_POINT_FORMATS, &[1, 0]), ext(EXT_SESSION_TICKET, &[]),
+            ext(EXT_ALPN, &[0, 12, 2, b'h', b'2', 8, b'h', b't', b't', b'p', b'/', b'1', b'.', b'1']),
+            ext(0x0005, &[1, 0, 0, 0, 0]),
+            ext(EXT_SIGNATURE_ALGORITHMS, &[&(sigs.len() as u16).to_be_bytes()[..], &sigs].concat()),
+            ext(0x0012, &[]), ext(0x0033, &[0, 0]), ext(0x002d, &[1, 1]),
+            ext(EXT_SUPPORTED_VERSIONS, &[6, 0x6a, 0x6a, 3, 4, 3, 3]), ext(0x001b, &[2, 0, 2]),
+            ext(0x4469, &[0, 3, 2, b'h', b'2']), ext(0x2a2a, &[0]), ext(0x0015, &[0; 8]),
+        ];
+        client_hello_with(0x0303, &suites, &exts)
+    }
+
+    #[test]
+    fn ja3_and_ja4() {
+        let fp = fingerprints(&chrome_hello(), false).unwrap();
+        assert_eq!(fp.ja4, "t13d1516h2_8daaf6152771_e5627efa2ab1");
+        assert!(fp.ja3.starts_with("771,4865-4866-4867-49195-"));
+        assert!(fp.ja3.ends_with(",29-23-24,0"));
+        assert_eq!(fp.ja3_hash, to_hex(&md5_digest(fp.ja3.as_bytes())));
+        assert!(fingerprints(&chrome_hello(), true).unwrap().ja4.starts_with("q13d"));
+
+        // The example from the JA3 documentation: TLS 1.0, no GREASE, no ALPN
+        let suites = [47, 53, 5, 10, 49161, 49162, 49171, 49172, 50, 56, 19, 4];
+        let exts = [sni_ext("example.com"), ext(EXT_SUPPORTED_GROUPS, &[0, 6, 0, 23, 0, 24, 0, 25]), ext(EXT_EC_POINT_FORMATS, &[1, 0])];
+        let fp = fingerprints(&client_hello_with(0x0301, &suites, &exts), false).unwrap();
+        assert_eq!(fp.ja3, "769,47-53-5-10-49161-49162-49171-49172-50-56-19-4,0-10-11,23-24-25,0");
+        assert_eq!(fp.ja3_hash, "ada70206e40642a3e4461f35503241d5");
+        assert!(fp.ja4.starts_with("t10d120300_"));
+
+        // No extensions at all
+        let fp = fingerprints(&client_hello_with(0x0303, &[0x002f], &[]), false).unwrap();
+        assert!(fp.ja4.starts_with("t12i010000_") && fp.ja4.ends_with("_000000000000"));
+    }
+}
@@ -0,0 +1,120 @@
+//! TLS alert descriptions (RFC 8446 §6, RFC 5246 §7.2) with the failure class and
+//! a plain-language cause for each, so a failed connection can be logged as
+//! "certificate chain does not lead to a trusted CA" rather than "alert 48".
+
+use std::ffi::{c_char, CStr};
+
+pub const TLS_ALERT_WARNING: u8 = 1;
+pub const TLS_ALERT_FATAL: u8 = 2;
+
+pub const TLS_FAILURE_NONE: u8 = 0;          // orderly close
+pub const TLS_FAILURE_NEGOTIATION: u8 = 1;   // no common version, cipher suite, name or protocol
+pub const TLS_FAILURE_CERTIFICATE: u8 = 2;   // a certificate was rejected
+pub const TLS_FAILURE_PROTOCOL: u8 = 3;      // malformed or unverifiable messages
+pub const TLS_FAILURE_ACCESS: u8 = 4;        // refused by policy
+pub const TLS_FAILURE_INTERNAL: u8 = 5;
+pub const TLS_FAILURE_UNKNOWN: u8 = 6;       // unassigned description
+
+struct Alert {
+    description: u8,
+    name: &'static CStr,
+    class: u8,
+    cause: &'static CStr,
+}
+
+const fn alert(description: u8, name: &'static CStr, class: u8, cause: &'static CStr) -> Alert {
+    Alert { description, name, class, cause }
+}
+
+const ALERTS: &[Alert] = &[
+    alert(0, c"close_notify", TLS_FAILURE_NONE, c"connection closed normally"),
+    alert(10, c"unexpected_message", TLS_FAILURE_PROTOCOL, c"a message arrived out of order"),
+    alert(20, c"bad_record_mac", TLS_FAILURE_PROTOCOL, c"a record failed its integrity check (corruption or tampering)"),
+    alert(21, c"decryption_failed", TLS_FAILURE_PROTOCOL, c"a record could not be decrypted"),
+    alert(22, c"record_overflow", TLS_FAILURE_PROTOCOL, c"a record exceeded the size limit"),
+    alert(30, c"decompression_failure", TLS_FAILURE_PROTOCOL, c"a record could not be decompressed"),
+    alert(40, c"handshake_failure", TLS_FAILURE_NEGOTIATION, c"no acceptable versions, cipher suites or groups in common"),
+    alert(41, c"no_certificate", TLS_FAILURE_CERTIFICATE, c"no certificate available (SSL 3.0)"),
+    alert(42, c"bad_certificate", TLS_FAILURE_CERTIFICATE, c"a certificate was corrupt or its signature did not verify"),
+    alert(43, c"unsupported_certificate", TLS_FAILURE_CERTIFICATE, c"a certificate was of an unsupported type"),
+    alert(44, c"certificate_revoked", TLS_FAILURE_CERTIFICATE, c"a certificate was revoked by its issuer"),
+    alert(45, c"certificate_expired", TLS_FAILURE_CERTIFICATE, c"a certificate has expired or is not yet valid"),
+    alert(46, c"certificate_unknown", TLS_FAILURE_CERTIFICATE, c"a certificate was rejected for an unspecified reason"),
+    alert(47, c"illegal_parameter", TLS_FAILURE_PROTOCOL, c"a handshake field was out of range or inconsistent"),
+    alert(48, c"unknown_ca", TLS_FAILURE_CERTIFICATE,
+          c"the certificate chain does not lead to a trusted CA (self-signed, private CA or interception)"),
+    alert(49, c"access_denied", TLS_FAILURE_ACCESS, c"valid credentials, but access was refused by policy"),
+    alert(50, c"decode_error", TLS_FAILURE_PROTOCOL, c"a message could not be decoded"),
+    alert(51, c"decrypt_error", TLS_FAILURE_PROTOCOL, c"a handshake signature or Finished check failed"),
+    alert(60, c"export_restriction", TLS_FAILURE_NEGOTIATION, c"export-grade parameters were refused"),
+    alert(70, c"protocol_version", TLS_FAILURE_NEGOTIATION, c"the offered protocol versions are not supported"),
+    alert(71, c"insufficient_security", TLS_FAILURE_NEGOTIATION, c"the server requires stronger cipher suites than offered"),
+    alert(80, c"internal_error", TLS_FAILURE_INTERNAL, c"the peer hit an internal error unrelated to the protocol"),
+    alert(86, c"inappropriate_fallback", TLS_FAILURE_NEGOTIATION,
+          c"a version fallback retry was refused (possible downgrade attack)"),
+    alert(90, c"user_canceled", TLS_FAILURE_NONE, c"the handshake was canceled by the application"),
+    alert(100, c"no_renegotiation", TLS_FAILURE_NEGOTIATION, c"renegotiation was refused"),
+    alert(109, c"missing_extension", TLS_FAILURE_PROTOCOL, c"a required extension was missing"),
+    alert(110, c"unsupported_extension", TLS_FAILURE_PROTOCOL, c"an extension was sent that was not offered"),
+    alert(111, c"certificate_unobtainable", TLS_FAILURE_CERTIFICATE, c"a certificate could not be fetched from its URL"),
+    alert(112, c"unrecognized_name", TLS_FAILURE_NEGOTIATION, c"the server does not host the requested name (SNI)"),
+    alert(113, c"bad_certificate_status_response", TLS_FAILURE_CERTIFICATE, c"the OCSP status response was invalid"),
+    alert(114, c"bad_certificate_hash_value", TLS_FAILURE_CERTIFICATE, c"a certificate did not match its hash"),
+    alert(115, c"unknown_psk_identity", TLS_FAILURE_ACCESS, c"the pre-shared key identity was not recognized"),
+    alert(116, c"certificate_required", TLS_FAILURE_CERTIFICATE, c"the server requires a client certificate"),
+    alert(120, c"no_application_protocol", TLS_FAILURE_NEGOTIATION, c"no ALPN protocol in common"),
+    alert(121, c"ech_required", TLS_FAILURE_NEGOTIATION, c"the server requires Encrypted Client Hello"),
+];
+
+fn lookup(description: u8) -> Option<&'static Alert> {
+    ALERTS.iter().find(|a| a.description == description)
+}
+
+/// Failure class of an alert description: TLS_FAILURE_*.
+pub fn classify(description: u8) -> u8 {
+    lookup(description).map_or(TLS_FAILURE_UNKNOWN, |a| a.class)
+}
+
+// --- FFI ---
+
+/// RFC name of an alert description ("unknown_ca"), or NULL if unassigned.
+/// Static string; do not free.
+#[no_mangle]
+pub extern "C" fn iris_tls_alert_name(description: u8) -> *const c_char {
+    lookup(description).map_or(std::ptr::null(), |a| a.name.as_ptr())
+}
+
+/// Plain-language cause of an alert, for logs, or NULL if unassigned. Static
+/// string; do not free.
+#[no_mangle]
+pub extern "C" fn iris_tls_alert_cause(description: u8) -> *const c_char {
+    lookup(description).map_or(std::ptr::null(), |a| a.cause.as_ptr())
+}
+
+/// Failure class of an alert (IrisTlsRecord.alert_description): TLS_FAILURE_*.
+/// Any fatal alert ends the connection; warnings other than close_notify and
+/// user_canceled usually precede one.
+#[no_mangle]
+pub extern "C" fn iris_tls_alert_class(description: u8) -> u8 {
+    classify(description)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_classes_and_causes() {
+        let s = |p: *const c_char| unsafe { CStr::from_ptr(p) }.to_str().unwrap();
+        assert_eq!(s(iris_tls_alert_name(48)), "unknown_ca");
+        assert!(s(iris_tls_alert_cause(45)).contains("expired"));
+        assert_eq!(iris_tls_alert_class(40), TLS_FAILURE_NEGOTIATION);
+        assert_eq!(iris_tls_alert_class(0), TLS_FAILURE_NONE);
+        assert_eq!(iris_tls_alert_class(200), TLS_FAILURE_UNKNOWN);
+        assert!(iris_tls_alert_name(200).is_null() && iris_tls_alert_cause(200).is_null());
+        // One entry per description
+        for (i, a) in ALERTS.iter().enumerate() {
+            assert!(ALERTS[i + 1..].iter().all(|b| b.description > a.description));
+        }
+    }
+}
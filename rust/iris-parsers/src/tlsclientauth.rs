@@ -0,0 +1,265 @@
+//! Client certificate (mutual TLS) detection over a connection's handshake
+//! messages. Up to TLS 1.2 the server's CertificateRequest and the client's answer
+//! are in the clear; TLS 1.3 encrypts both, leaving only the client's
+//! post_handshake_auth offer and a certificate_required alert as signals. An
+//! intercepting proxy cannot present the client's key, so a connection that
+//! requires a certificate breaks when intercepted.
+
+use crate::bytes::be16;
+use crate::der::read_tlv;
+use crate::ffi::{alloc_array, free_array, free_c_string_array, vec_to_c_string_array, IrisCStringArray};
+use crate::tls;
+use crate::x509::{self, alloc_cert_array, free_cert_array, IrisX509Cert};
+
+pub const TLS_CLIENT_AUTH_NONE: u8 = 0;       // no CertificateRequest seen
+pub const TLS_CLIENT_AUTH_REQUESTED: u8 = 1;  // requested; the client's answer was not seen
+pub const TLS_CLIENT_AUTH_PROVIDED: u8 = 2;   // the client sent a certificate
+pub const TLS_CLIENT_AUTH_DECLINED: u8 = 3;   // the client sent an empty Certificate
+pub const TLS_CLIENT_AUTH_HIDDEN: u8 = 4;     // TLS 1.3: any request is encrypted
+
+const HS_CLIENT_HELLO: u8 = 1;
+const HS_SERVER_HELLO: u8 = 2;
+const HS_CERTIFICATE: u8 = 11;
+const HS_CERTIFICATE_REQUEST: u8 = 13;
+const HS_SERVER_HELLO_DONE: u8 = 14;
+const HS_CERTIFICATE_VERIFY: u8 = 15;
+
+const TLS12: u16 = 0x0303;
+const TLS13: u16 = 0x0304;
+
+#[repr(C)]
+pub struct IrisTlsClientAuth {
+    pub status: u8,                     // TLS_CLIENT_AUTH_*
+    pub version: u16,                   // negotiated protocol version
+    pub post_handshake_auth: bool,      // TLS 1.3 ClientHello offered post_handshake_auth
+    pub certificate_verify: bool,       // the client proved possession of its key
+    pub certificate_types: *mut u8,     // ClientCertificateType: 1 rsa_sign, 64 ecdsa_sign, ...
+    pub certificate_types_count: usize,
+    pub signature_algorithms: *mut u16, // TLS 1.2 supported_signature_algorithms
+    pub signature_algorithms_count: usize,
+    pub ca_names: IrisCStringArray,     // acceptable issuers, "CN=..., O=..."; empty means any
+    pub client_certificates: *mut IrisX509Cert, // the client's chain, leaf first
+    pub client_certificates_count: usize,
+}
+
+#[derive(Default)]
+pub struct CertificateRequest {
+    pub certificate_types: Vec<u8>,
+    pub signature_algorithms: Vec<u16>,
+    pub ca_names: Vec<String>,
+}
+
+#[derive(Default)]
+pub struct ClientAuth<'a> {
+    pub status: u8,
+    pub version: u16,
+    pub post_handshake_auth: bool,
+    pub certificate_verify: bool,
+    pub request: Option<CertificateRequest>,
+    pub client_certificates: Vec<&'a [u8]>, // DER
+}
+
+fn vec16(d: &[u8], o: usize) -> Option<(&[u8], usize)> {
+    let n = be16(d, o)? as usize;
+    Some((d.get(o + 2..o + 2 + n)?, o + 2 + n))
+}
+
+fn vec24(d: &[u8], o: usize) -> Option<(&[u8], usize)> {
+    let h = d.get(o..o + 3)?;
+    let n = (h[0] as usize) << 16 | (h[1] as usize) << 8 | h[2] as usize;
+    Some((d.get(o + 3..o + 3 + n)?, o + 3 + n))
+}
+
+/// TLS 1.0-1.2 CertificateRequest body (RFC 5246 §7.4.4); the signature
+/// algorithm list only exists from TLS 1.2.
+fn parse_request(b: &[u8], version: u16) -> Option<CertificateRequest> {
+    let n = *b.first()? as usize;
+    let mut r = CertificateRequest { certificate_types: b.get(1..1 + n)?.to_vec(), ..Default::default() };
+    let mut o = 1 + n;
+    if version >= TLS12 {
+        let (algs, next) = vec16(b, o)?;
+        r.signature_algorithms = algs.chunks_exact(2).map(|c| u16::from_be_bytes([c[0], c[1]])).collect();
+        o = next;
+    }
+    let (names, _) = vec16(b, o)?;
+    let mut p = 0;
+    while p < names.len() {
+        let (dn, next) = vec16(names, p)?;
+        let name = read_tlv(dn).and_then(|(t, _)| x509::parse_name(&t));
+        r.ca_names.push(name.map_or_else(String::new, |n| n.display()));
+        p = next;
+    }
+    Some(r)
+}
+
+/// TLS 1.0-1.2 Certificate body: the DER certificates, leaf first.
+fn certificate_list(b: &[u8]) -> Option<Vec<&[u8]>> {
+    let (list, _) = vec24(b, 0)?;
+    let mut certs = Vec::new();
+    let mut p = 0;
+    while p < list.len() {
+        let (c, next) = vec24(list, p)?;
+        certs.push(c);
+        p = next;
+    }
+    Some(certs)
+}
+
+/// Client authentication on one connection from its handshake messages, back to
+/// back in any order as long as each direction keeps its own order. Err(-2) if
+/// malformed, Err(-3) without a ServerHello.
+pub fn detect(msgs: &[u8]) -> Result<ClientAuth<'_>, i32> {
+    let mut a = ClientAuth::default();
+    let (mut hello, mut request, mut client_cert) = (None, None, None);
+    // The server's Certificate precedes ServerHelloDone; the client's follows it
+    let mut server_done = false;
+    for (ty, m) in tls::handshake_messages(msgs).ok_or(-2)? {
+        let body = &m[4..];
+        match ty {
+            HS_CLIENT_HELLO => a.post_handshake_auth = tls::post_handshake_auth_offered(m).ok_or(-2)?,
+            HS_SERVER_HELLO => {
+                let sh = tls::parse_server_hello(body).ok_or(-2)?;
+                if !sh.retry { hello = Some(sh); }
+            }
+            HS_CERTIFICATE_REQUEST => request = Some(body),
+            HS_SERVER_HELLO_DONE => server_done = true,
+            HS_CERTIFICATE if server_done => client_cert = Some(body),
+            HS_CERTIFICATE_VERIFY => a.certificate_verify = true,
+            _ => {}
+        }
+    }
+    a.version = hello.ok_or(-3)?.version;
+    if a.version >= TLS13 {
+        // Plaintext CertificateVerify can only be the server's in a 1.3 capture
+        a.certificate_verify = false;
+        a.status = TLS_CLIENT_AUTH_HIDDEN;
+        return Ok(a);
+    }
+    let Some(request) = request else {
+        (a.status, a.certificate_verify) = (TLS_CLIENT_AUTH_NONE, false);
+        return Ok(a);
+    };
+    a.request = Some(parse_request(request, a.version).ok_or(-2)?);
+    a.status = match client_cert {
+        Some(b) => {
+            a.client_certificates = certificate_list(b).ok_or(-2)?;
+            if a.client_certificates.is_empty() { TLS_CLIENT_AUTH_DECLINED } else { TLS_CLIENT_AUTH_PROVIDED }
+        }
+        None => TLS_CLIENT_AUTH_REQUESTED,
+    };
+    Ok(a)
+}
+
+// --- FFI ---
+
+/// Whether a connection asks for or uses a client certificate, from its handshake
+/// messages concatenated in any order per direction (e.g. the IrisTlsHandshake
+/// data of both directions' streams). Returns 0=ok, -2=arg error or malformed,
+/// -3=no ServerHello. Free with iris_tls_client_auth_free.
+#[no_mangle]
+pub extern "C" fn iris_tls_client_auth(data: *const u8, len: usize, out: *mut IrisTlsClientAuth) -> i32 {
+    if data.is_null() || out.is_null() { return -2; }
+    let a = match detect(unsafe { std::slice::from_raw_parts(data, len) }) {
+        Ok(a) => a,
+        Err(e) => return e,
+    };
+    let r = a.request.unwrap_or_default();
+    let certs: Vec<_> = a.client_certificates.iter().filter_map(|d| x509::parse(d)).collect();
+    let (client_certificates, client_certificates_count) = alloc_cert_array(&certs.iter().collect::<Vec<_>>());
+    let (certificate_types, certificate_types_count) = alloc_array(r.certificate_types);
+    let (signature_algorithms, signature_algorithms_count) = alloc_array(r.signature_algorithms);
+    unsafe {
+        out.write(IrisTlsClientAuth {
+            status: a.status,
+            version: a.version,
+            post_handshake_auth: a.post_handshake_auth,
+            certificate_verify: a.certificate_verify,
+            certificate_types, certificate_types_count,
+            signature_algorithms, signature_algorithms_count,
+            ca_names: vec_to_c_string_array(r.ca_names),
+            client_certificates, client_certificates_count,
+        });
+    }
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn iris_tls_client_auth_free(a: *mut IrisTlsClientAuth) {
+    if a.is_null() { return; }
+    let a = unsafe { &*a };
+    free_array(a.certificate_types, a.certificate_types_count);
+    free_array(a.signature_algorithms, a.signature_algorithms_count);
+    free_c_string_array(&a.ca_names);
+    free_cert_array(a.client_certificates, a.client_certificates_count);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tls::tests::{client_hello_with, ext};
+    use crate::x509::tests::{cert, name, CN};
+    use std::ffi::CStr;
+
+    fn msg(msg_type: u8, body: &[u8]) -> Vec<u8> {
+        [&[msg_type, 0][..], &(body.len() as u16).to_be_bytes(), body].concat()
+    }
+
+    fn server_hello(version: u16) -> Vec<u8> {
+        let versions = if version == TLS13 { ext(43, &TLS13.to_be_bytes()) } else { Vec::new() };
+        let body = [&TLS12.to_be_bytes()[..], &[0x11; 32], &[0], &[0xc0, 0x2f, 0],
+                    &(versions.len() as u16).to_be_bytes(), &versions].concat();
+        msg(HS_SERVER_HELLO, &body)
+    }
+
+    fn certificate(certs: &[Vec<u8>]) -> Vec<u8> {
+        let list: Vec<u8> = certs.iter().flat_map(|c| [&(c.len() as u32).to_be_bytes()[1..], c].concat()).collect();
+        msg(HS_CERTIFICATE, &[&(list.len() as u32).to_be_bytes()[1..], &list].concat())
+    }
+
+    fn cert_request(ca: &[u8]) -> Vec<u8> {
+        let names = [&(ca.len() as u16).to_be_bytes()[..], ca].concat();
+        msg(HS_CERTIFICATE_REQUEST, &[&[2, 1, 64][..], &[0, 4, 4, 3, 8, 4], &(names.len() as u16).to_be_bytes(), &names].concat())
+    }
+
+    #[test]
+    fn tls12_mutual() {
+        let ca = name(&[(CN, "Corp Device CA")]);
+        let server_cert = cert(1, &name(&[(CN, "vpn.example.com")]), &ca);
+        let client_cert = cert(2, &name(&[(CN, "laptop-042")]), &ca);
+        let server = [server_hello(TLS12), certificate(&[server_cert]), cert_request(&ca), msg(HS_SERVER_HELLO_DONE, &[])].concat();
+        let client = [certificate(&[client_cert]), msg(16, &[0; 4]), msg(HS_CERTIFICATE_VERIFY, &[0; 4])].concat();
+
+        let a = detect(&server).unwrap();
+        assert_eq!((a.status, a.certificate_verify), (TLS_CLIENT_AUTH_REQUESTED, false));
+        let r = a.request.unwrap();
+        assert_eq!((r.certificate_types, r.signature_algorithms), (vec![1, 64], vec![0x0403, 0x0804]));
+        assert_eq!(r.ca_names, ["CN=Corp Device CA"]);
+
+        let all = [server.clone(), client].concat();
+        let mut out = std::mem::MaybeUninit::<IrisTlsClientAuth>::uninit();
+        assert_eq!(iris_tls_client_auth(all.as_ptr(), all.len(), out.as_mut_ptr()), 0);
+        let out = unsafe { &mut *out.as_mut_ptr() };
+        assert_eq!((out.status, out.version, out.certificate_verify), (TLS_CLIENT_AUTH_PROVIDED, TLS12, true));
+        assert_eq!((out.ca_names.count, out.client_certificates_count), (1, 1));
+        let leaf = unsafe { &*out.client_certificates };
+        assert_eq!(unsafe { CStr::from_ptr(leaf.common_name) }, c"laptop-042");
+        iris_tls_client_auth_free(out);
+
+        // A client without a certificate answers with an empty list
+        let declined = [server, certificate(&[])].concat();
+        assert_eq!(detect(&declined).unwrap().status, TLS_CLIENT_AUTH_DECLINED);
+    }
+
+    #[test]
+    fn tls13_and_plain() {
+        let ch = client_hello_with(0x0303, &[0x1301], &[ext(49, &[])]);
+        let msgs = [ch[5..].to_vec(), server_hello(TLS13), msg(HS_CERTIFICATE_VERIFY, &[0; 4])].concat();
+        let a = detect(&msgs).unwrap();
+        assert!(a.status == TLS_CLIENT_AUTH_HIDDEN && a.post_handshake_auth && !a.certificate_verify);
+
+        let msgs = [server_hello(TLS12), certificate(&[]), msg(HS_SERVER_HELLO_DONE, &[])].concat();
+        let a = detect(&msgs).unwrap();
+        assert_eq!(a.status, TLS_CLIENT_AUTH_NONE);
+        assert_eq!(detect(&cert_request(&[])).err(), Some(-3));
+    }
+}
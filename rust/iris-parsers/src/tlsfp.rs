@@ -0,0 +1,223 @@
+//! TLS client fingerprint database: JA3 hashes and JA4 strings mapped to a label
+//! and category, loaded from text into a hash index so each ClientHello costs one
+//! fingerprint computation and at most two lookups.
+
+use crate::ffi::{free_cstr, to_cstr};
+use crate::tls;
+use std::collections::hash_map::{Entry, HashMap};
+use std::ffi::{c_char, CString};
+use std::sync::Mutex;
+
+pub const TLS_FP_CATEGORY_UNKNOWN: u8 = 0;
+pub const TLS_FP_CATEGORY_BROWSER: u8 = 1;
+pub const TLS_FP_CATEGORY_LIBRARY: u8 = 2;  // TLS stacks and HTTP clients: OpenSSL, Go, curl, ...
+pub const TLS_FP_CATEGORY_MALWARE: u8 = 3;
+pub const TLS_FP_CATEGORY_TOOL: u8 = 4;     // scanners, proxies and offensive tooling
+pub const TLS_FP_CATEGORY_APPLICATION: u8 = 5;
+
+pub const TLS_FP_NONE: u8 = 0;
+pub const TLS_FP_JA4: u8 = 1;
+pub const TLS_FP_JA3: u8 = 2;
+
+const JA3_HASH_LEN: usize = 32;
+const JA4_LEN: usize = 36;
+
+struct Label {
+    text: CString,
+    category: u8,
+}
+
+/// Opaque fingerprint index. Create with iris_tls_fingerprint_db_new.
+pub struct IrisTlsFingerprintDb {
+    inner: Mutex<HashMap<String, Label>>,
+}
+
+#[repr(C)]
+pub struct IrisTlsFingerprint {
+    pub ja3: *mut c_char,       // "771,4865-4866-...,0-23-...,29-23-24,0"
+    pub ja3_hash: *mut c_char,  // MD5 of ja3, hex
+    pub ja4: *mut c_char,       // "t13d1516h2_8daaf6152771_e5627efa2ab1"
+}
+
+#[repr(C)]
+pub struct IrisTlsFingerprintMatch {
+    pub matched_by: u8,         // TLS_FP_JA4 / TLS_FP_JA3, TLS_FP_NONE without a match
+    pub category: u8,           // TLS_FP_CATEGORY_*
+    pub label: *const c_char,   // owned by the database, valid until it is freed; NULL without a match
+}
+
+fn category(s: &str) -> u8 {
+    match s.to_ascii_lowercase().as_str() {
+        "browser" => TLS_FP_CATEGORY_BROWSER,
+        "library" => TLS_FP_CATEGORY_LIBRARY,
+        "malware" => TLS_FP_CATEGORY_MALWARE,
+        "tool" => TLS_FP_CATEGORY_TOOL,
+        "application" | "app" => TLS_FP_CATEGORY_APPLICATION,
+        _ => TLS_FP_CATEGORY_UNKNOWN,
+    }
+}
+
+/// Normalised key of a JA3 hash or JA4 fingerprint, None for anything else.
+fn key(fp: &str) -> Option<String> {
+    let fp = fp.to_ascii_lowercase();
+    let b = fp.as_bytes();
+    let ja3 = b.len() == JA3_HASH_LEN && b.iter().all(u8::is_ascii_hexdigit);
+    let ja4 = b.len() == JA4_LEN && b[10] == b'_' && b[23] == b'_' && matches!(b[0], b't' | b'q' | b'd');
+    (ja3 || ja4).then_some(fp)
+}
+
+/// Add "fingerprint,category,label" lines (label last, so it may contain commas).
+/// Blank lines and '#' comments are skipped, as are lines without a JA3 hash or
+/// JA4 fingerprint. A fingerprint already present keeps its first entry, so
+/// labels handed out earlier stay valid. Returns the number of entries added.
+fn load(db: &mut HashMap<String, Label>, text: &str) -> usize {
+    let mut added = 0;
+    for line in text.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+        let mut f = line.splitn(3, ',').map(str::trim);
+        let (Some(fp), Some(cat), Some(label)) = (f.next().and_then(key), f.next(), f.next()) else { continue };
+        let Ok(text) = CString::new(label) else { continue };
+        if let Entry::Vacant(v) = db.entry(fp) {
+            v.insert(Label { text, category: category(cat) });
+            added += 1;
+        }
+    }
+    added
+}
+
+/// Look up JA4 first: unlike JA3 it survives the extension order shuffling of
+/// current browsers.
+fn lookup<'a>(db: &'a HashMap<String, Label>, fp: &tls::Fingerprints) -> Option<(u8, &'a Label)> {
+    db.get(&fp.ja4).map(|e| (TLS_FP_JA4, e)).or_else(|| db.get(&fp.ja3_hash).map(|e| (TLS_FP_JA3, e)))
+}
+
+// --- FFI ---
+
+/// Compute the JA3 and JA4 fingerprints of a ClientHello (TLS record or bare
+/// handshake message). `quic` marks a ClientHello from QUIC CRYPTO frames.
+/// Returns 0=ok, -1=truncated, -2=malformed/arg error, -3=not a ClientHello.
+/// Free with iris_tls_fingerprint_free.
+#[no_mangle]
+pub extern "C" fn iris_tls_fingerprint(data: *const u8, len: usize, quic: bool, out: *mut IrisTlsFingerprint) -> i32 {
+    if data.is_null() || out.is_null() { return -2; }
+    match tls::fingerprints(unsafe { std::slice::from_raw_parts(data, len) }, quic) {
+        Ok(fp) => {
+            unsafe {
+                out.write(IrisTlsFingerprint { ja3: to_cstr(&fp.ja3), ja3_hash: to_cstr(&fp.ja3_hash), ja4: to_cstr(&fp.ja4) });
+            }
+            0
+        }
+        Err(e) => e,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn iris_tls_fingerprint_free(fp: *mut IrisTlsFingerprint) {
+    if fp.is_null() { return; }
+    let fp = unsafe { &*fp };
+    for p in [fp.ja3, fp.ja3_hash, fp.ja4] { free_cstr(p); }
+}
+
+/// Create an empty fingerprint database. Free with iris_tls_fingerprint_db_free.
+#[no_mangle]
+pub extern "C" fn iris_tls_fingerprint_db_new() -> *mut IrisTlsFingerprintDb {
+    Box::into_raw(Box::new(IrisTlsFingerprintDb { inner: Mutex::new(HashMap::new()) }))
+}
+
+/// Add entries from UTF-8 text, one "fingerprint,category,label" per line, where
+/// the fingerprint is a JA3 hash or a JA4 string and the category one of browser,
+/// library, malware, tool or application. Comments start with '#'; unparseable
+/// lines and repeated fingerprints are skipped. May be called more than once.
+/// Returns the number of entries added, or -2 on arg error or invalid UTF-8.
+#[no_mangle]
+pub extern "C" fn iris_tls_fingerprint_db_load(db: *mut IrisTlsFingerprintDb, data: *const u8, len: usize) -> i64 {
+    if db.is_null() || data.is_null() { return -2; }
+    let Ok(text) = std::str::from_utf8(unsafe { std::slice::from_raw_parts(data, len) }) else { return -2 };
+    load(&mut unsafe { &*db }.inner.lock().unwrap_or_else(|e| e.into_inner()), text) as i64
+}
+
+/// Number of fingerprints in the database.
+#[no_mangle]
+pub extern "C" fn iris_tls_fingerprint_db_len(db: *mut IrisTlsFingerprintDb) -> usize {
+    if db.is_null() { return 0; }
+    unsafe { &*db }.inner.lock().unwrap_or_else(|e| e.into_inner()).len()
+}
+
+/// Match a ClientHello (TLS record or bare handshake message) against the
+/// database, JA4 first, then JA3. Nothing is allocated; out->label belongs to the
+/// database. Returns 0=match, -1=truncated, -2=malformed/arg error, -3=not a
+/// ClientHello or no match (out->matched_by is TLS_FP_NONE).
+#[no_mangle]
+pub extern "C" fn iris_tls_fingerprint_db_match(
+    db: *mut IrisTlsFingerprintDb, data: *const u8, len: usize, quic: bool, out: *mut IrisTlsFingerprintMatch,
+) -> i32 {
+    if db.is_null() || data.is_null() || out.is_null() { return -2; }
+    let fp = match tls::fingerprints(unsafe { std::slice::from_raw_parts(data, len) }, quic) {
+        Ok(fp) => fp,
+        Err(e) => return e,
+    };
+    let db = unsafe { &*db }.inner.lock().unwrap_or_else(|e| e.into_inner());
+    let (m, rc) = match lookup(&db, &fp) {
+        Some((by, e)) => (IrisTlsFingerprintMatch { matched_by: by, category: e.category, label: e.text.as_ptr() }, 0),
+        None => (IrisTlsFingerprintMatch { matched_by: TLS_FP_NONE, category: TLS_FP_CATEGORY_UNKNOWN, label: std::ptr::null() }, -3),
+    };
+    unsafe { out.write(m); }
+    rc
+}
+
+#[no_mangle]
+pub extern "C" fn iris_tls_fingerprint_db_free(db: *mut IrisTlsFingerprintDb) {
+    if db.is_null() { return; }
+    drop(unsafe { Box::from_raw(db) });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tls::tests::{chrome_hello, client_hello_with};
+    use std::ffi::CStr;
+
+    #[test]
+    fn fingerprint_ffi() {
+        let ch = chrome_hello();
+        let mut out = std::mem::MaybeUninit::<IrisTlsFingerprint>::uninit();
+        assert_eq!(iris_tls_fingerprint(ch.as_ptr(), ch.len(), false, out.as_mut_ptr()), 0);
+        let fp = unsafe { &mut *out.as_mut_ptr() };
+        assert_eq!(unsafe { CStr::from_ptr(fp.ja4) }, c"t13d1516h2_8daaf6152771_e5627efa2ab1");
+        assert_eq!(unsafe { CStr::from_ptr(fp.ja3_hash) }.to_bytes().len(), 32);
+        iris_tls_fingerprint_free(fp);
+        assert_eq!(iris_tls_fingerprint(ch.as_ptr(), 3, false, out.as_mut_ptr()), -1);
+    }
+
+    #[test]
+    fn load_and_match() {
+        let ch = chrome_hello();
+        let ja3 = tls::fingerprints(&ch, false).unwrap().ja3_hash;
+        let db = iris_tls_fingerprint_db_new();
+        let text = format!("# ja4/ja3, category, label\n\
+            T13D1516H2_8DAAF6152771_E5627EFA2AB1, browser, Chrome 120, Windows\n\
+            {ja3},malware,Cobalt Strike\n\
+            not-a-fingerprint,tool,ignored\n\
+            \n\
+            t13d1516h2_8daaf6152771_e5627efa2ab1,tool,duplicate\n");
+        assert_eq!(iris_tls_fingerprint_db_load(db, text.as_ptr(), text.len()), 2);
+        assert_eq!(iris_tls_fingerprint_db_len(db), 2);
+
+        let mut m = std::mem::MaybeUninit::<IrisTlsFingerprintMatch>::uninit();
+        assert_eq!(iris_tls_fingerprint_db_match(db, ch.as_ptr(), ch.len(), false, m.as_mut_ptr()), 0);
+        let r = unsafe { m.assume_init_read() };
+        assert_eq!((r.matched_by, r.category), (TLS_FP_JA4, TLS_FP_CATEGORY_BROWSER));
+        assert_eq!(unsafe { CStr::from_ptr(r.label) }, c"Chrome 120, Windows");
+
+        // Over QUIC the JA4 differs, so only the JA3 hash matches
+        assert_eq!(iris_tls_fingerprint_db_match(db, ch.as_ptr(), ch.len(), true, m.as_mut_ptr()), 0);
+        let r = unsafe { m.assume_init_read() };
+        assert_eq!((r.matched_by, r.category), (TLS_FP_JA3, TLS_FP_CATEGORY_MALWARE));
+
+        let other = client_hello_with(0x0303, &[0x002f], &[]);
+        assert_eq!(iris_tls_fingerprint_db_match(db, other.as_ptr(), other.len(), false, m.as_mut_ptr()), -3);
+        let r = unsafe { m.assume_init_read() };
+        assert!(r.matched_by == TLS_FP_NONE && r.label.is_null());
+        assert_eq!(iris_tls_fingerprint_db_load(db, [0xff].as_ptr(), 1), -2);
+        iris_tls_fingerprint_db_free(db);
+    }
+}
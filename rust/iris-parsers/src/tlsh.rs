@@ -126,7 +126,7 @@ fn distance(h1: &str, h2: &str) -> i32 {
 
     // Length difference (scaled penalty)
     let ldiff = (b1[1] as i32 - b2[1] as i32).unsigned_abs();
-    dist += match ldiff { 0 => 0, 1..=2 => 2, 3..=6 => 6, _ => 12 } as i32;
+    dist += match ldiff { 0 => 0, 1..=2 => 2, 3..=6 => 6, _ => 12 };
 
     // Q-ratio difference
     let (q1a, q2a) = (b1[2] >> 4, b1[2] & 0xF);
@@ -139,7 +139,7 @@ fn distance(h1: &str, h2: &str) -> i32 {
             let c1 = (b1[i] >> shift) & 3;
             let c2 = (b2[i] >> shift) & 3;
             let d = (c1 as i32 - c2 as i32).unsigned_abs();
-            dist += match d { 0 => 0, 1 => 1, _ => 6 } as i32;
+            dist += match d { 0 => 0, 1 => 1, _ => 6 };
         }
     }
     dist
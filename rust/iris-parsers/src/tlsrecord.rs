@@ -0,0 +1,260 @@
+//! TLS record layer (RFC 8446 §5, RFC 5246 §6.2): splits one direction of a TCP
+//! stream into records and reassembles the plaintext handshake messages they
+//! carry, which may be fragmented across records or packed several to a record.
+
+use crate::ffi::{alloc_array, alloc_bytes, free_array, iris_free_bytes};
+use crate::tlsalert::{TLS_ALERT_FATAL, TLS_ALERT_WARNING};
+use std::sync::Mutex;
+
+pub const TLS_CHANGE_CIPHER_SPEC: u8 = 20;
+pub const TLS_ALERT: u8 = 21;
+pub const TLS_HANDSHAKE: u8 = 22;
+pub const TLS_APPLICATION_DATA: u8 = 23;
+pub const TLS_HEARTBEAT: u8 = 24;
+
+/// Largest record fragment: 2^14 plus the 2048 bytes of expansion TLS 1.2 allows.
+const MAX_RECORD: usize = (1 << 14) + 2048;
+/// Largest handshake message reassembled; certificate chains stay well below it.
+const MAX_HANDSHAKE: usize = 1 << 18;
+
+#[repr(C)]
+pub struct IrisTlsRecord {
+    pub content_type: u8,       // TLS_* content type
+    pub version: u16,           // record-layer version (0x0301 in many TLS 1.3 ClientHellos)
+    pub length: u16,
+    pub encrypted: bool,        // application data, or after ChangeCipherSpec in this direction
+    pub alert_level: u8,        // TLS_ALERT_WARNING / TLS_ALERT_FATAL; 0 unless a plaintext alert
+    pub alert_description: u8,  // see iris_tls_alert_name / iris_tls_alert_cause
+}
+
+#[repr(C)]
+pub struct IrisTlsHandshake {
+    pub msg_type: u8,           // 1 ClientHello, 2 ServerHello, 11 Certificate, ...
+    pub data: *mut u8,          // the whole message, 4-byte header included
+    pub len: usize,
+}
+
+#[repr(C)]
+pub struct IrisTlsRecords {
+    pub records: *mut IrisTlsRecord,
+    pub records_count: usize,
+    pub handshakes: *mut IrisTlsHandshake,
+    pub handshakes_count: usize,
+}
+
+pub struct IrisTlsStream {
+    inner: Mutex<TlsStream>,
+}
+
+pub struct Record {
+    pub content_type: u8,
+    pub version: u16,
+    pub length: u16,
+    pub encrypted: bool,
+    pub alert: Option<(u8, u8)>, // (level, description) of a plaintext alert
+}
+
+/// One direction of a TLS connection. After a bad record header the stream is
+/// not TLS (or lost sync) and every later feed fails.
+#[derive(Default)]
+pub struct TlsStream {
+    buf: Vec<u8>,
+    handshake: Vec<u8>,
+    cipher_changed: bool,
+    broken: bool,
+}
+
+fn valid_type(t: u8) -> bool {
+    (TLS_CHANGE_CIPHER_SPEC..=TLS_HEARTBEAT).contains(&t)
+}
+
+impl TlsStream {
+    /// Append stream bytes; returns the complete records and the handshake messages
+    /// they complete. Err(-2) if the stream is not (or no longer) a record stream.
+    pub fn feed(&mut self, d: &[u8]) -> Result<(Vec<Record>, Vec<Vec<u8>>), i32> {
+        if self.broken { return Err(-2); }
+        self.buf.extend_from_slice(d);
+        let (mut records, mut messages) = (Vec::new(), Vec::new());
+        let mut p = 0;
+        while self.buf.len() - p >= 5 {
+            let h = &self.buf[p..p + 5];
+            let len = u16::from_be_bytes([h[3], h[4]]) as usize;
+            if !valid_type(h[0]) || h[1] != 3 || len > MAX_RECORD {
+                self.broken = true;
+                return Err(-2);
+            }
+            if self.buf.len() - p - 5 < len { break; }
+            let (content_type, version) = (h[0], u16::from_be_bytes([h[1], h[2]]));
+            let encrypted = content_type == TLS_APPLICATION_DATA || self.cipher_changed;
+            let fragment = &self.buf[p + 5..p + 5 + len];
+            if content_type == TLS_HANDSHAKE && !encrypted {
+                self.handshake.extend_from_slice(fragment);
+            }
+            let alert = match fragment {
+                [level @ (TLS_ALERT_WARNING | TLS_ALERT_FATAL), description, ..] if content_type == TLS_ALERT && !encrypted => {
+                    Some((*level, *description))
+                }
+                _ => None,
+            };
+            records.push(Record { content_type, version, length: len as u16, encrypted, alert });
+            if content_type == TLS_CHANGE_CIPHER_SPEC { self.cipher_changed = true; }
+            p += 5 + len;
+        }
+        self.buf.drain(..p);
+        while self.handshake.len() >= 4 {
+            let h = &self.handshake;
+            let len = (h[1] as usize) << 16 | (h[2] as usize) << 8 | h[3] as usize;
+            if len > MAX_HANDSHAKE {
+                self.broken = true;
+                return Err(-2);
+            }
+            if h.len() < 4 + len { break; }
+            messages.push(self.handshake.drain(..4 + len).collect());
+        }
+        Ok((records, messages))
+    }
+
+    /// Bytes held for an incomplete record or handshake message.
+    pub fn pending(&self) -> usize {
+        self.buf.len() + self.handshake.len()
+    }
+}
+
+// --- FFI ---
+
+/// New record-layer reassembler for one direction of a TLS connection.
+/// Free with iris_tls_stream_free.
+#[no_mangle]
+pub extern "C" fn iris_tls_stream_new() -> *mut IrisTlsStream {
+    Box::into_raw(Box::new(IrisTlsStream { inner: Mutex::new(TlsStream::default()) }))
+}
+
+/// Feed stream bytes in order. Returns the records they complete and every
+/// plaintext handshake message those finish, ready for parsers such as
+/// iris_tls_parse_client_hello. Returns 0=ok, -2=arg error or not a TLS record
+/// stream (from then on). Free with iris_tls_records_free.
+#[no_mangle]
+pub extern "C" fn iris_tls_stream_feed(s: *mut IrisTlsStream, data: *const u8, len: usize, out: *mut IrisTlsRecords) -> i32 {
+    if s.is_null() || out.is_null() || (data.is_null() && len != 0) { return -2; }
+    let d = if len == 0 { &[][..] } else { unsafe { std::slice::from_raw_parts(data, len) } };
+    let (records, messages) = match unsafe { &*s }.inner.lock().unwrap_or_else(|e| e.into_inner()).feed(d) {
+        Ok(r) => r,
+        Err(e) => return e,
+    };
+    let (records, records_count) = alloc_array(records.into_iter().map(|r| {
+        let (alert_level, alert_description) = r.alert.unwrap_or_default();
+        IrisTlsRecord {
+            content_type: r.content_type, version: r.version, length: r.length, encrypted: r.encrypted,
+            alert_level, alert_description,
+        }
+    }).collect());
+    let (handshakes, handshakes_count) = alloc_array(messages.into_iter().map(|m| {
+        let (data, len) = alloc_bytes(&m);
+        IrisTlsHandshake { msg_type: m[0], data, len }
+    }).collect());
+    unsafe { out.write(IrisTlsRecords { records, records_count, handshakes, handshakes_count }); }
+    0
+}
+
+/// Bytes of a partial record or handshake message waiting for more data.
+#[no_mangle]
+pub extern "C" fn iris_tls_stream_pending(s: *const IrisTlsStream) -> usize {
+    if s.is_null() { return 0; }
+    unsafe { &*s }.inner.lock().unwrap_or_else(|e| e.into_inner()).pending()
+}
+
+#[no_mangle]
+pub extern "C" fn iris_tls_records_free(r: *mut IrisTlsRecords) {
+    if r.is_null() { return; }
+    let r = unsafe { &*r };
+    if !r.handshakes.is_null() {
+        for h in unsafe { std::slice::from_raw_parts(r.handshakes, r.handshakes_count) } { iris_free_bytes(h.data, h.len); }
+    }
+    free_array(r.handshakes, r.handshakes_count);
+    free_array(r.records, r.records_count);
+}
+
+#[no_mangle]
+pub extern "C" fn iris_tls_stream_free(s: *mut IrisTlsStream) {
+    if s.is_null() { return; }
+    drop(unsafe { Box::from_raw(s) });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(content_type: u8, fragment: &[u8]) -> Vec<u8> {
+        [&[content_type, 3, 3][..], &(fragment.len() as u16).to_be_bytes(), fragment].concat()
+    }
+
+    fn handshake(msg_type: u8, body: &[u8]) -> Vec<u8> {
+        [&[msg_type, 0][..], &(body.len() as u16).to_be_bytes(), body].concat()
+    }
+
+    #[test]
+    fn split_and_packed_handshakes() {
+        // A certificate split over two records, then ServerHelloDone packed with a
+        // ServerKeyExchange, all delivered a byte at a time
+        let cert = handshake(11, &[0x5a; 300]);
+        let (ske, done) = (handshake(12, &[1, 2, 3]), handshake(14, &[]));
+        let stream = [
+            record(TLS_HANDSHAKE, &handshake(2, &[3, 3])),
+            record(TLS_HANDSHAKE, &cert[..100]),
+            record(TLS_HANDSHAKE, &cert[100..]),
+            record(TLS_HANDSHAKE, &[ske.clone(), done.clone()].concat()),
+            record(TLS_CHANGE_CIPHER_SPEC, &[1]),
+            record(TLS_HANDSHAKE, &[0xe7; 40]), // encrypted Finished
+            record(TLS_APPLICATION_DATA, &[0x17; 20]),
+        ].concat();
+
+        let mut s = TlsStream::default();
+        let (mut records, mut messages) = (Vec::new(), Vec::new());
+        for b in stream.chunks(1) {
+            let (r, m) = s.feed(b).unwrap();
+            records.extend(r);
+            messages.extend(m);
+        }
+        assert_eq!(s.pending(), 0);
+        let types: Vec<(u8, bool)> = records.iter().map(|r| (r.content_type, r.encrypted)).collect();
+        assert_eq!(types, [
+            (TLS_HANDSHAKE, false), (TLS_HANDSHAKE, false), (TLS_HANDSHAKE, false), (TLS_HANDSHAKE, false),
+            (TLS_CHANGE_CIPHER_SPEC, false), (TLS_HANDSHAKE, true), (TLS_APPLICATION_DATA, true),
+        ]);
+        assert_eq!(messages, [handshake(2, &[3, 3]), cert, ske, done]);
+    }
+
+    #[test]
+    fn ffi_hands_off_client_hello() {
+        let ch = handshake(1, &[0; 40]);
+        let s = iris_tls_stream_new();
+        let data = [record(TLS_HANDSHAKE, &ch[..10]), record(TLS_HANDSHAKE, &ch[10..])].concat();
+        let mut out = std::mem::MaybeUninit::<IrisTlsRecords>::uninit();
+        assert_eq!(iris_tls_stream_feed(s, data.as_ptr(), data.len() - 1, out.as_mut_ptr()), 0);
+        let mut r = unsafe { out.assume_init_read() };
+        assert_eq!((r.records_count, r.handshakes_count), (1, 0));
+        assert_eq!(iris_tls_stream_pending(s), data.len() - 1 - 5);
+        iris_tls_records_free(&mut r);
+
+        assert_eq!(iris_tls_stream_feed(s, data[data.len() - 1..].as_ptr(), 1, out.as_mut_ptr()), 0);
+        let mut r = unsafe { out.assume_init_read() };
+        assert_eq!(r.handshakes_count, 1);
+        let h = unsafe { &*r.handshakes };
+        assert_eq!((h.msg_type, unsafe { std::slice::from_raw_parts(h.data, h.len) }), (1, &ch[..]));
+        iris_tls_records_free(&mut r);
+
+        // A server refusing the handshake
+        let alert = record(TLS_ALERT, &[TLS_ALERT_FATAL, 40]);
+        assert_eq!(iris_tls_stream_feed(s, alert.as_ptr(), alert.len(), out.as_mut_ptr()), 0);
+        let mut r = unsafe { out.assume_init_read() };
+        let rec = unsafe { &*r.records };
+        assert_eq!((rec.content_type, rec.alert_level, rec.alert_description), (TLS_ALERT, TLS_ALERT_FATAL, 40));
+        assert_eq!(crate::tlsalert::classify(rec.alert_description), crate::tlsalert::TLS_FAILURE_NEGOTIATION);
+        iris_tls_records_free(&mut r);
+
+        // Not TLS: the stream stays failed
+        assert_eq!(iris_tls_stream_feed(s, b"GET / HTTP/1.1".as_ptr(), 14, out.as_mut_ptr()), -2);
+        assert_eq!(iris_tls_stream_feed(s, data.as_ptr(), data.len(), out.as_mut_ptr()), -2);
+        iris_tls_stream_free(s);
+    }
+}
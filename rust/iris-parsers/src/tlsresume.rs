@@ -0,0 +1,157 @@
+//! Session resumption on a TLS connection: what the ClientHello offered (session
+//! ID, RFC 5077 ticket, TLS 1.3 PSK) and whether the server took it up. A resumed
+//! handshake sends no Certificate, so certificate checks have nothing to inspect.
+
+use crate::tls;
+
+pub const TLS_RESUME_NONE: u8 = 0;        // full handshake
+pub const TLS_RESUME_SESSION_ID: u8 = 1;  // TLS 1.2 and earlier, server-side session cache
+pub const TLS_RESUME_TICKET: u8 = 2;      // TLS 1.2 session ticket
+pub const TLS_RESUME_PSK: u8 = 3;         // TLS 1.3 pre-shared key
+
+const HS_CLIENT_HELLO: u8 = 1;
+const HS_SERVER_HELLO: u8 = 2;
+const HS_NEW_SESSION_TICKET: u8 = 4;
+const HS_CERTIFICATE: u8 = 11;
+const HS_SERVER_HELLO_DONE: u8 = 14;
+
+const EXT_PRE_SHARED_KEY: u16 = 41;
+const TLS13: u16 = 0x0304;
+
+#[repr(C)]
+#[derive(Default)]
+pub struct IrisTlsResumption {
+    pub resumed: bool,          // abbreviated handshake: no certificate will be sent
+    pub method: u8,             // TLS_RESUME_*
+    pub session_id_offered: bool, // non-empty ClientHello session ID (TLS 1.3 clients send one regardless)
+    pub ticket_offered: bool,
+    pub psk_offered: bool,
+    pub early_data_offered: bool, // 0-RTT data follows the ClientHello
+    pub ticket_issued: bool,    // plaintext NewSessionTicket (TLS 1.2); TLS 1.3 tickets are encrypted
+}
+
+/// Resumption status of one connection from its handshake messages, back to back
+/// in any order. Err(-2) if malformed, Err(-3) without a ServerHello.
+pub fn detect(msgs: &[u8]) -> Result<IrisTlsResumption, i32> {
+    let mut r = IrisTlsResumption::default();
+    let (mut offer, mut hello) = (None, None);
+    let mut full_handshake = false;
+    for (ty, m) in tls::handshake_messages(msgs).ok_or(-2)? {
+        match ty {
+            HS_CLIENT_HELLO => offer = Some(tls::resumption_offer(m).ok_or(-2)?),
+            HS_SERVER_HELLO => {
+                let sh = tls::parse_server_hello(&m[4..]).ok_or(-2)?;
+                if !sh.retry { hello = Some(sh); }
+            }
+            HS_NEW_SESSION_TICKET => r.ticket_issued = true,
+            HS_CERTIFICATE | HS_SERVER_HELLO_DONE => full_handshake = true,
+            _ => {}
+        }
+    }
+    let hello = hello.ok_or(-3)?;
+    if let Some(o) = &offer {
+        r.session_id_offered = !o.session_id.is_empty();
+        (r.ticket_offered, r.psk_offered, r.early_data_offered) = (o.ticket, o.psk, o.early_data);
+    }
+    r.method = if hello.version >= TLS13 {
+        // The server names the PSK identity it accepted
+        if hello.extension(EXT_PRE_SHARED_KEY).is_some() { TLS_RESUME_PSK } else { TLS_RESUME_NONE }
+    } else if offer.as_ref().is_some_and(|o| !o.session_id.is_empty() && o.session_id == hello.session_id) {
+        // An echoed session ID means the server found it (RFC 5246 §7.4.1.2); a
+        // client resuming by ticket may also send one for this
+        if r.ticket_offered { TLS_RESUME_TICKET } else { TLS_RESUME_SESSION_ID }
+    } else if r.ticket_offered && r.ticket_issued && !full_handshake {
+        // Abbreviated ticket handshake: NewSessionTicket straight after the ServerHello
+        TLS_RESUME_TICKET
+    } else {
+        TLS_RESUME_NONE
+    };
+    r.resumed = r.method != TLS_RESUME_NONE;
+    Ok(r)
+}
+
+// --- FFI ---
+
+/// Whether a connection resumed an earlier session, from its handshake messages
+/// concatenated in any order (e.g. the IrisTlsHandshake data from both directions'
+/// streams). Without the ClientHello only TLS 1.3 PSK resumption is recognised.
+/// Returns 0=ok, -2=arg error or malformed, -3=no ServerHello.
+#[no_mangle]
+pub extern "C" fn iris_tls_detect_resumption(data: *const u8, len: usize, out: *mut IrisTlsResumption) -> i32 {
+    if data.is_null() || out.is_null() { return -2; }
+    match detect(unsafe { std::slice::from_raw_parts(data, len) }) {
+        Ok(r) => { unsafe { out.write(r); } 0 }
+        Err(e) => e,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn msg(msg_type: u8, body: &[u8]) -> Vec<u8> {
+        [&[msg_type, 0][..], &(body.len() as u16).to_be_bytes(), body].concat()
+    }
+
+    fn ext(ty: u16, body: &[u8]) -> Vec<u8> {
+        [&ty.to_be_bytes()[..], &(body.len() as u16).to_be_bytes(), body].concat()
+    }
+
+    fn hello(msg_type: u8, session_id: &[u8], tail: &[u8], exts: &[Vec<u8>]) -> Vec<u8> {
+        let exts = exts.concat();
+        let body = [&[3, 3][..], &[0x11; 32], &[session_id.len() as u8], session_id, tail,
+                    &(exts.len() as u16).to_be_bytes(), &exts].concat();
+        msg(msg_type, &body)
+    }
+
+    fn client_hello(session_id: &[u8], exts: &[Vec<u8>]) -> Vec<u8> {
+        hello(HS_CLIENT_HELLO, session_id, &[0, 2, 0x13, 0x01, 1, 0], exts)
+    }
+
+    fn server_hello(session_id: &[u8], exts: &[Vec<u8>]) -> Vec<u8> {
+        hello(HS_SERVER_HELLO, session_id, &[0xc0, 0x2f, 0], exts)
+    }
+
+    #[test]
+    fn tls12_resumption() {
+        let sid = [0x5a; 32];
+        // Session cache hit: the server echoes the ID and skips its certificate
+        let r = detect(&[client_hello(&sid, &[]), server_hello(&sid, &[])].concat()).unwrap();
+        assert!(r.resumed && r.session_id_offered);
+        assert_eq!(r.method, TLS_RESUME_SESSION_ID);
+        // Cache miss: a fresh ID and a full handshake
+        let r = detect(&[client_hello(&sid, &[]), server_hello(&[1; 32], &[]), msg(HS_CERTIFICATE, &[0; 3])].concat()).unwrap();
+        assert!(!r.resumed);
+
+        // Ticket without a session ID, reissued in the abbreviated handshake
+        let ticket = ext(35, &[0xee; 64]);
+        let msgs = [client_hello(&[], std::slice::from_ref(&ticket)), server_hello(&[], &[]), msg(HS_NEW_SESSION_TICKET, &[0; 8])].concat();
+        let r = detect(&msgs).unwrap();
+        assert!(r.resumed && r.ticket_offered && r.ticket_issued && !r.session_id_offered);
+        assert_eq!(r.method, TLS_RESUME_TICKET);
+        // Ticket rejected: full handshake, then a new ticket
+        let msgs = [client_hello(&[], &[ticket]), server_hello(&[], &[]), msg(HS_CERTIFICATE, &[0; 3]),
+                    msg(HS_SERVER_HELLO_DONE, &[]), msg(HS_NEW_SESSION_TICKET, &[0; 8])].concat();
+        assert!(!detect(&msgs).unwrap().resumed);
+    }
+
+    #[test]
+    fn tls13_psk() {
+        let versions = ext(43, &TLS13.to_be_bytes());
+        let sid = [0x33; 32];
+        let ch = client_hello(&sid, &[ext(42, &[]), ext(EXT_PRE_SHARED_KEY, &[0; 40])]);
+        // The legacy session ID is always echoed in TLS 1.3; only the PSK counts
+        let full = [ch.clone(), server_hello(&sid, std::slice::from_ref(&versions))].concat();
+        let r = detect(&full).unwrap();
+        assert!(!r.resumed && r.psk_offered && r.early_data_offered);
+
+        let resumed = [ch, server_hello(&sid, &[versions, ext(EXT_PRE_SHARED_KEY, &[0, 0])])].concat();
+        let mut out = IrisTlsResumption::default();
+        assert_eq!(iris_tls_detect_resumption(resumed.as_ptr(), resumed.len(), &mut out), 0);
+        assert!(out.resumed);
+        assert_eq!(out.method, TLS_RESUME_PSK);
+        assert_eq!(iris_tls_detect_resumption(resumed.as_ptr(), 10, &mut out), -2);
+        let ch = client_hello(&[], &[]);
+        assert_eq!(iris_tls_detect_resumption(ch.as_ptr(), ch.len(), &mut out), -3);
+    }
+}
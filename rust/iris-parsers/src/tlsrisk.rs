@@ -0,0 +1,340 @@
+//! Weak TLS configuration analysis over a connection's handshake messages: old
+//! protocol versions, broken cipher suites, small key-exchange groups and missing
+//! secure renegotiation (RFC 5746), rolled up into one risk level.
+
+use crate::bytes::be16;
+use crate::ffi::{alloc_array, free_array, free_cstr, to_cstr};
+use crate::tls;
+use std::ffi::c_char;
+
+pub const TLS_WEAK_PROTOCOL: u8 = 1;          // SSL 3.0, TLS 1.0 or TLS 1.1 negotiated
+pub const TLS_WEAK_NULL_CIPHER: u8 = 2;       // no encryption
+pub const TLS_WEAK_EXPORT_CIPHER: u8 = 3;     // 40/56-bit export grade
+pub const TLS_WEAK_RC4: u8 = 4;
+pub const TLS_WEAK_DES: u8 = 5;               // single DES, or 3DES (Sweet32)
+pub const TLS_WEAK_ANONYMOUS: u8 = 6;         // unauthenticated key exchange
+pub const TLS_WEAK_SMALL_GROUP: u8 = 7;       // DH prime under 2048 bits or EC curve under 224 bits
+pub const TLS_WEAK_NO_RENEGOTIATION_INFO: u8 = 8;
+
+pub const TLS_RISK_NONE: u8 = 0;
+pub const TLS_RISK_LOW: u8 = 1;
+pub const TLS_RISK_MEDIUM: u8 = 2;
+pub const TLS_RISK_HIGH: u8 = 3;
+
+const HS_CLIENT_HELLO: u8 = 1;
+const HS_SERVER_HELLO: u8 = 2;
+const HS_SERVER_KEY_EXCHANGE: u8 = 12;
+
+const EXT_KEY_SHARE: u16 = 51;
+const EXT_RENEGOTIATION_INFO: u16 = 0xff01;
+
+const TLS13: u16 = 0x0304;
+const CURVE_NAMED: u8 = 3;
+
+#[repr(C)]
+pub struct IrisTlsFinding {
+    pub kind: u8,               // TLS_WEAK_*
+    pub severity: u8,           // TLS_RISK_*
+    pub detail: *mut c_char,    // "TLS 1.0", "cipher suite 0x000a", "1024-bit DH prime", ...
+}
+
+#[repr(C)]
+pub struct IrisTlsRisk {
+    pub version: u16,           // negotiated protocol version
+    pub cipher_suite: u16,
+    pub group: u16,             // named group of the key exchange, 0 if none or explicit DH
+    pub dh_bits: u32,           // finite-field DH prime size, 0 if not DHE
+    pub risk: u8,               // highest finding severity, TLS_RISK_NONE without findings
+    pub findings: *mut IrisTlsFinding,
+    pub findings_count: usize,
+}
+
+pub struct Finding {
+    pub kind: u8,
+    pub severity: u8,
+    pub detail: String,
+}
+
+#[derive(Default)]
+pub struct Assessment {
+    pub version: u16,
+    pub cipher_suite: u16,
+    pub group: u16,
+    pub dh_bits: u32,
+    pub findings: Vec<Finding>,
+}
+
+impl Assessment {
+    pub fn risk(&self) -> u8 {
+        self.findings.iter().map(|f| f.severity).max().unwrap_or(TLS_RISK_NONE)
+    }
+
+    fn flag(&mut self, kind: u8, severity: u8, detail: String) {
+        self.findings.push(Finding { kind, severity, detail });
+    }
+}
+
+fn vec16(d: &[u8], o: usize) -> Option<(&[u8], usize)> {
+    let n = be16(d, o)? as usize;
+    Some((d.get(o + 2..o + 2 + n)?, o + 2 + n))
+}
+
+fn version_name(v: u16) -> String {
+    match v {
+        0x0300 => "SSL 3.0".into(),
+        0x0301..=0x0304 => format!("TLS 1.{}", v - 0x0301),
+        _ => format!("version 0x{:04x}", v),
+    }
+}
+
+fn is_null(cs: u16) -> bool {
+    matches!(cs, 0x0000..=0x0002 | 0x002c..=0x002e | 0x003b | 0x00b0 | 0x00b1 | 0x00b4 | 0x00b5 | 0x00b8 | 0x00b9
+        | 0xc001 | 0xc006 | 0xc00b | 0xc010 | 0xc015 | 0xc039..=0xc03b | 0xc0b4 | 0xc0b5)
+}
+
+fn is_export(cs: u16) -> bool {
+    matches!(cs, 0x0003 | 0x0006 | 0x0008 | 0x000b | 0x000e | 0x0011 | 0x0014 | 0x0017 | 0x0019
+        | 0x0026..=0x002b | 0x0060..=0x0065)
+}
+
+fn is_rc4(cs: u16) -> bool {
+    matches!(cs, 0x0003..=0x0005 | 0x0017 | 0x0018 | 0x0020 | 0x0024 | 0x0028 | 0x002b | 0x0060 | 0x0064..=0x0066
+        | 0x008a | 0x008e | 0x0092 | 0xc002 | 0xc007 | 0xc00c | 0xc011 | 0xc016 | 0xc033)
+}
+
+fn is_single_des(cs: u16) -> bool {
+    matches!(cs, 0x0008 | 0x0009 | 0x000b | 0x000c | 0x000e | 0x000f | 0x0011 | 0x0012 | 0x0014 | 0x0015
+        | 0x0019 | 0x001a | 0x001e | 0x0022 | 0x0026 | 0x0029 | 0x0062 | 0x0063)
+}
+
+fn is_3des(cs: u16) -> bool {
+    matches!(cs, 0x000a | 0x000d | 0x0010 | 0x0013 | 0x0016 | 0x001b | 0x001f | 0x0023 | 0x008b | 0x008f | 0x0093
+        | 0xc003 | 0xc008 | 0xc00d | 0xc012 | 0xc017 | 0xc01a..=0xc01c | 0xc034)
+}
+
+fn is_anonymous(cs: u16) -> bool {
+    matches!(cs, 0x0017..=0x001b | 0x0034 | 0x003a | 0x0046 | 0x006c | 0x006d | 0x0089 | 0x009b | 0x00a6 | 0x00a7
+        | 0x00bf | 0x00c5 | 0xc015..=0xc019 | 0xc046 | 0xc047 | 0xc05a | 0xc05b | 0xc084 | 0xc085)
+}
+
+/// Suites whose ServerKeyExchange carries ECParameters rather than DH parameters.
+fn is_ec_kx(cs: u16) -> bool {
+    matches!(cs, 0xc001..=0xc03b | 0xc048..=0xc04f | 0xc05c..=0xc063 | 0xc072..=0xc079 | 0xc086..=0xc08d
+        | 0xc09a | 0xc09b | 0xc0ac..=0xc0af | 0xcca8 | 0xcca9 | 0xccac | 0xd001..=0xd005)
+}
+
+/// (EC)DHE_PSK suites, whose ServerKeyExchange starts with a PSK identity hint.
+fn has_psk_hint(cs: u16) -> bool {
+    matches!(cs, 0x008e..=0x0091 | 0x00aa | 0x00ab | 0x00b2..=0x00b5 | 0xc033..=0xc03b | 0xc0a6 | 0xc0a7
+        | 0xc0aa | 0xc0ab | 0xccac | 0xccad | 0xd001..=0xd005)
+}
+
+/// sect163*, sect193*, secp160* and secp192* (RFC 8422 deprecated them).
+fn is_small_curve(group: u16) -> bool {
+    matches!(group, 1..=5 | 15..=19)
+}
+
+fn bit_length(n: &[u8]) -> u32 {
+    let n = &n[n.iter().take_while(|&&b| b == 0).count()..];
+    n.first().map_or(0, |&b| (n.len() as u32 - 1) * 8 + (8 - b.leading_zeros()))
+}
+
+/// Key-exchange group or DH prime size from a TLS 1.2 ServerKeyExchange body. Export suites are
+/// skipped: theirs may hold a temporary RSA key, and they are flagged anyway.
+fn server_key_exchange(a: &mut Assessment, b: &[u8]) -> Option<()> {
+    let cs = a.cipher_suite;
+    if is_export(cs) { return Some(()); }
+    let o = if has_psk_hint(cs) { vec16(b, 0)?.1 } else { 0 };
+    if is_ec_kx(cs) {
+        if *b.get(o)? == CURVE_NAMED { a.group = be16(b, o + 1)?; }
+    } else {
+        let (p, o) = vec16(b, o)?;
+        let (g, o) = vec16(b, o)?;
+        let (ys, _) = vec16(b, o)?;
+        if !g.is_empty() && !ys.is_empty() { a.dh_bits = bit_length(p); }
+    }
+    Some(())
+}
+
+/// Assess one connection from its handshake messages, back to back in any order
+/// (e.g. the IrisTlsHandshake data of both directions). Err(-2) if malformed,
+/// Err(-3) without a ServerHello, since nothing was negotiated.
+pub fn assess(msgs: &[u8]) -> Result<Assessment, i32> {
+    let mut a = Assessment::default();
+    let (mut hello, mut ske) = (None, None);
+    let mut client_renegotiation = None;
+    for (ty, m) in tls::handshake_messages(msgs).ok_or(-2)? {
+        match ty {
+            HS_CLIENT_HELLO => client_renegotiation = Some(tls::secure_renegotiation_offered(m).ok_or(-2)?),
+            HS_SERVER_HELLO => {
+                // The ServerHello after a HelloRetryRequest is the one that counts
+                let sh = tls::parse_server_hello(&m[4..]).ok_or(-2)?;
+                if !sh.retry { hello = Some(sh); }
+            }
+            HS_SERVER_KEY_EXCHANGE => ske = Some(&m[4..]),
+            _ => {}
+        }
+    }
+    let hello = hello.ok_or(-3)?;
+    (a.version, a.cipher_suite) = (hello.version, hello.cipher_suite);
+    if let Some(share) = hello.extension(EXT_KEY_SHARE) { a.group = be16(share, 0).ok_or(-2)?; }
+    let server_renegotiation = hello.extension(EXT_RENEGOTIATION_INFO).is_some();
+    // A key exchange this does not recognise (plain PSK, SRP, ...) just leaves the
+    // group unknown
+    if let (Some(b), true) = (ske, a.version < TLS13) { server_key_exchange(&mut a, b); }
+
+    match a.version {
+        0x0300 => a.flag(TLS_WEAK_PROTOCOL, TLS_RISK_HIGH, version_name(a.version)),
+        0x0301 | 0x0302 => a.flag(TLS_WEAK_PROTOCOL, TLS_RISK_MEDIUM, version_name(a.version)),
+        _ => {}
+    }
+    let (cs, suite) = (a.cipher_suite, format!("cipher suite 0x{:04x}", a.cipher_suite));
+    for (weak, kind, severity) in [
+        (is_null(cs), TLS_WEAK_NULL_CIPHER, TLS_RISK_HIGH),
+        (is_export(cs), TLS_WEAK_EXPORT_CIPHER, TLS_RISK_HIGH),
+        (is_rc4(cs), TLS_WEAK_RC4, TLS_RISK_HIGH),
+        (is_single_des(cs), TLS_WEAK_DES, TLS_RISK_HIGH),
+        (is_3des(cs), TLS_WEAK_DES, TLS_RISK_MEDIUM),
+        (is_anonymous(cs), TLS_WEAK_ANONYMOUS, TLS_RISK_HIGH),
+    ] {
+        if weak { a.flag(kind, severity, suite.clone()); }
+    }
+    match a.dh_bits {
+        0 => {}
+        bits if bits < 1024 => a.flag(TLS_WEAK_SMALL_GROUP, TLS_RISK_HIGH, format!("{}-bit DH prime", bits)),
+        bits if bits < 2048 => a.flag(TLS_WEAK_SMALL_GROUP, TLS_RISK_MEDIUM, format!("{}-bit DH prime", bits)),
+        _ => {}
+    }
+    if is_small_curve(a.group) {
+        a.flag(TLS_WEAK_SMALL_GROUP, TLS_RISK_HIGH, format!("named group {}", a.group));
+    }
+    // TLS 1.3 dropped renegotiation. Most servers now refuse it outright, so the
+    // missing protection is a low risk on its own
+    if a.version < TLS13 && !server_renegotiation {
+        let detail = if client_renegotiation == Some(false) {
+            "client offered neither renegotiation_info nor the SCSV"
+        } else {
+            "server did not send renegotiation_info"
+        };
+        a.flag(TLS_WEAK_NO_RENEGOTIATION_INFO, TLS_RISK_LOW, detail.into());
+    }
+    Ok(a)
+}
+
+// --- FFI ---
+
+/// Risk summary for one connection from its handshake messages, concatenated in
+/// any order (e.g. the IrisTlsHandshake data from both directions' streams). The
+/// ClientHello is optional. Returns 0=ok, -2=arg error or malformed, -3=no
+/// ServerHello. Free with iris_tls_risk_free.
+#[no_mangle]
+pub extern "C" fn iris_tls_assess(data: *const u8, len: usize, out: *mut IrisTlsRisk) -> i32 {
+    if data.is_null() || out.is_null() { return -2; }
+    let a = match assess(unsafe { std::slice::from_raw_parts(data, len) }) {
+        Ok(a) => a,
+        Err(e) => return e,
+    };
+    let risk = a.risk();
+    let (findings, findings_count) = alloc_array(a.findings.into_iter().map(|f| IrisTlsFinding {
+        kind: f.kind, severity: f.severity, detail: to_cstr(&f.detail),
+    }).collect());
+    unsafe {
+        out.write(IrisTlsRisk {
+            version: a.version, cipher_suite: a.cipher_suite, group: a.group, dh_bits: a.dh_bits,
+            risk, findings, findings_count,
+        });
+    }
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn iris_tls_risk_free(r: *mut IrisTlsRisk) {
+    if r.is_null() { return; }
+    let r = unsafe { &*r };
+    if !r.findings.is_null() {
+        for f in unsafe { std::slice::from_raw_parts(r.findings, r.findings_count) } { free_cstr(f.detail); }
+    }
+    free_array(r.findings, r.findings_count);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn msg(msg_type: u8, body: &[u8]) -> Vec<u8> {
+        [&[msg_type, 0][..], &(body.len() as u16).to_be_bytes(), body].concat()
+    }
+
+    fn ext(ty: u16, body: &[u8]) -> Vec<u8> {
+        [&ty.to_be_bytes()[..], &(body.len() as u16).to_be_bytes(), body].concat()
+    }
+
+    fn server_hello(version: u16, random: [u8; 32], cs: u16, exts: &[Vec<u8>]) -> Vec<u8> {
+        let exts = exts.concat();
+        let body = [&version.to_be_bytes()[..], &random, &[0], &cs.to_be_bytes(), &[0],
+                    &(exts.len() as u16).to_be_bytes(), &exts].concat();
+        msg(HS_SERVER_HELLO, &body)
+    }
+
+    fn client_hello(suites: &[u16]) -> Vec<u8> {
+        let suites: Vec<u8> = suites.iter().flat_map(|s| s.to_be_bytes()).collect();
+        let body = [&[3, 3][..], &[0; 32], &[0], &(suites.len() as u16).to_be_bytes(), &suites, &[1, 0]].concat();
+        msg(HS_CLIENT_HELLO, &body)
+    }
+
+    const EXT_SUPPORTED_VERSIONS: u16 = 43;
+    /// HelloRetryRequest's fixed ServerHello.random.
+    const HRR: [u8; 32] = [
+        0xcf, 0x21, 0xad, 0x74, 0xe5, 0x9a, 0x61, 0x11, 0xbe, 0x1d, 0x8c, 0x02, 0x1e, 0x65, 0xb8, 0x91,
+        0xc2, 0xa2, 0x11, 0x16, 0x7a, 0xbb, 0x8c, 0x5e, 0x07, 0x9e, 0x09, 0xe2, 0xc8, 0xa8, 0x33, 0x9c,
+    ];
+
+    fn kinds(a: &Assessment) -> Vec<(u8, u8)> {
+        a.findings.iter().map(|f| (f.kind, f.severity)).collect()
+    }
+
+    #[test]
+    fn legacy_connection() {
+        // TLS 1.0, DHE_RSA_WITH_3DES_EDE_CBC_SHA with a 1024-bit prime, no RFC 5746
+        let p = [&[0xff][..], &[0x5a; 127]].concat();
+        let ske = [&(p.len() as u16).to_be_bytes()[..], &p, &[0, 1, 2], &[0, 2, 0x12, 0x34], &[0; 10]].concat();
+        let msgs = [client_hello(&[0x0016]), server_hello(0x0301, [7; 32], 0x0016, &[]), msg(11, &[0; 20]),
+                    msg(HS_SERVER_KEY_EXCHANGE, &ske), msg(14, &[])].concat();
+        let a = assess(&msgs).unwrap();
+        assert_eq!((a.version, a.cipher_suite, a.dh_bits), (0x0301, 0x0016, 1024));
+        assert_eq!(kinds(&a), [
+            (TLS_WEAK_PROTOCOL, TLS_RISK_MEDIUM), (TLS_WEAK_DES, TLS_RISK_MEDIUM),
+            (TLS_WEAK_SMALL_GROUP, TLS_RISK_MEDIUM), (TLS_WEAK_NO_RENEGOTIATION_INFO, TLS_RISK_LOW),
+        ]);
+        assert_eq!(a.findings[3].detail, "client offered neither renegotiation_info nor the SCSV");
+        assert_eq!(a.risk(), TLS_RISK_MEDIUM);
+
+        // RC4 with an ECDHE key exchange on secp192r1
+        let ske = [&[CURVE_NAMED, 0, 19, 4][..], &[0; 4]].concat();
+        let msgs = [server_hello(0x0303, [7; 32], 0xc011, &[ext(EXT_RENEGOTIATION_INFO, &[0])]),
+                    msg(HS_SERVER_KEY_EXCHANGE, &ske)].concat();
+        let a = assess(&msgs).unwrap();
+        assert_eq!(a.group, 19);
+        assert_eq!(kinds(&a), [(TLS_WEAK_RC4, TLS_RISK_HIGH), (TLS_WEAK_SMALL_GROUP, TLS_RISK_HIGH)]);
+        assert_eq!(a.risk(), TLS_RISK_HIGH);
+    }
+
+    #[test]
+    fn modern_connection_via_ffi() {
+        // TLS 1.3 after a HelloRetryRequest
+        let versions = ext(EXT_SUPPORTED_VERSIONS, &TLS13.to_be_bytes());
+        let msgs = [
+            server_hello(0x0303, HRR, 0x1301, &[versions.clone(), ext(EXT_KEY_SHARE, &[0, 23])]),
+            server_hello(0x0303, [9; 32], 0x1301, &[versions, ext(EXT_KEY_SHARE, &[0, 29, 0, 2, 1, 2])]),
+        ].concat();
+        let mut out = std::mem::MaybeUninit::<IrisTlsRisk>::uninit();
+        assert_eq!(iris_tls_assess(msgs.as_ptr(), msgs.len(), out.as_mut_ptr()), 0);
+        let mut r = unsafe { out.assume_init_read() };
+        assert_eq!((r.version, r.cipher_suite, r.group, r.risk, r.findings_count), (TLS13, 0x1301, 29, TLS_RISK_NONE, 0));
+        iris_tls_risk_free(&mut r);
+
+        let ch = client_hello(&[0x1301]);
+        assert_eq!(iris_tls_assess(ch.as_ptr(), ch.len(), out.as_mut_ptr()), -3);
+        assert_eq!(iris_tls_assess(msgs.as_ptr(), msgs.len() - 1, out.as_mut_ptr()), -2);
+    }
+}
@@ -0,0 +1,199 @@
+//! Negotiated protocol version and cipher suite of a TLS connection. TLS 1.3
+//! freezes ServerHello.legacy_version at 0x0303 and names the real version in
+//! supported_versions (RFC 8446 §4.1.3), then encrypts everything after the
+//! ServerHello, so this also reports what a passive observer cannot see.
+
+use crate::bytes::be16;
+use crate::tls;
+
+pub const TLS_VERSION_FROM_LEGACY: u8 = 1;             // ServerHello.legacy_version (TLS 1.2 and earlier)
+pub const TLS_VERSION_FROM_SUPPORTED_VERSIONS: u8 = 2; // ServerHello supported_versions
+pub const TLS_VERSION_FROM_RETRY: u8 = 3;              // HelloRetryRequest only; the real ServerHello was not seen
+
+pub const TLS_HIDDEN_CERTIFICATE: u32 = 0x01;          // server certificate chain
+pub const TLS_HIDDEN_EXTENSIONS: u32 = 0x02;           // EncryptedExtensions: ALPN choice, SNI ack, 0-RTT acceptance
+pub const TLS_HIDDEN_CLIENT_AUTH: u32 = 0x04;          // CertificateRequest and the client's certificate
+pub const TLS_HIDDEN_SIGNATURE: u32 = 0x08;            // CertificateVerify signature algorithm
+pub const TLS_HIDDEN_SESSION_TICKETS: u32 = 0x10;      // NewSessionTicket
+
+/// Everything after the ServerHello is encrypted in TLS 1.3.
+const TLS13_HIDDEN: u32 = TLS_HIDDEN_CERTIFICATE | TLS_HIDDEN_EXTENSIONS | TLS_HIDDEN_CLIENT_AUTH
+    | TLS_HIDDEN_SIGNATURE | TLS_HIDDEN_SESSION_TICKETS;
+
+const HS_CLIENT_HELLO: u8 = 1;
+const HS_SERVER_HELLO: u8 = 2;
+
+const EXT_SUPPORTED_VERSIONS: u16 = 43;
+const EXT_KEY_SHARE: u16 = 51;
+
+const TLS12: u16 = 0x0303;
+const TLS13: u16 = 0x0304;
+
+/// Last 8 bytes of ServerHello.random from a TLS 1.3 server that negotiated
+/// TLS 1.2 (…01) or earlier (…00) (RFC 8446 §4.1.3).
+const DOWNGRADE_SENTINEL: &[u8] = b"DOWNGRD";
+
+#[repr(C)]
+#[derive(Default)]
+pub struct IrisTlsVersion {
+    pub version: u16,           // negotiated; TLS 1.3 drafts count as 0x0304
+    pub wire_version: u16,      // as selected on the wire, e.g. 0x7f17 for draft 23
+    pub legacy_version: u16,    // ServerHello.legacy_version, 0x0303 under TLS 1.3
+    pub source: u8,             // TLS_VERSION_FROM_*
+    pub cipher_suite: u16,
+    pub group: u16,             // TLS 1.3 key_share group, 0 before TLS 1.3
+    pub hello_retry: bool,      // the server sent a HelloRetryRequest
+    pub client_max_version: u16, // highest version the ClientHello offered, 0 without it
+    pub downgrade_sentinel: bool, // a TLS 1.3 server settled for an older version
+    pub inconsistent: bool,     // version, cipher suite and ClientHello contradict each other
+    pub hidden: u32,            // TLS_HIDDEN_*: encrypted, so unknowable from the capture
+}
+
+/// Map TLS 1.3 draft versions (0x7f00 | draft) onto TLS 1.3.
+fn normalise(v: u16) -> u16 {
+    if v >> 8 == 0x7f { TLS13 } else { v }
+}
+
+/// TLS 1.3 cipher suites (RFC 8446 §B.4, RFC 8998), which name no key exchange
+/// and are not valid in earlier versions.
+fn is_tls13_suite(cs: u16) -> bool {
+    matches!(cs, 0x1301..=0x1305 | 0x00c6 | 0x00c7)
+}
+
+/// Version facts of one connection from its handshake messages, back to back in
+/// any order. Err(-2) if malformed, Err(-3) without a ServerHello or
+/// HelloRetryRequest.
+pub fn infer(msgs: &[u8]) -> Result<IrisTlsVersion, i32> {
+    let mut v = IrisTlsVersion::default();
+    let (mut hello, mut retry) = (None, None);
+    for (ty, m) in tls::handshake_messages(msgs).ok_or(-2)? {
+        match ty {
+            HS_CLIENT_HELLO => v.client_max_version = tls::offered_version(m).ok_or(-2)?,
+            HS_SERVER_HELLO => {
+                let sh = tls::parse_server_hello(&m[4..]).ok_or(-2)?;
+                if sh.retry { retry = Some(sh) } else { hello = Some(sh) }
+            }
+            _ => {}
+        }
+    }
+    v.hello_retry = retry.is_some();
+    let (sh, source) = match (hello, retry) {
+        (Some(sh), _) if sh.extension(EXT_SUPPORTED_VERSIONS).is_some() => (sh, TLS_VERSION_FROM_SUPPORTED_VERSIONS),
+        (Some(sh), _) => (sh, TLS_VERSION_FROM_LEGACY),
+        (None, Some(hrr)) => (hrr, TLS_VERSION_FROM_RETRY),
+        (None, None) => return Err(-3),
+    };
+    v.source = source;
+    (v.wire_version, v.legacy_version, v.cipher_suite) = (sh.version, sh.legacy_version, sh.cipher_suite);
+    v.version = normalise(sh.version);
+    if v.version >= TLS13 {
+        // A HelloRetryRequest's key_share is just the group it wants
+        if let Some(share) = sh.extension(EXT_KEY_SHARE) { v.group = be16(share, 0).ok_or(-2)?; }
+        v.hidden = TLS13_HIDDEN;
+    } else {
+        v.downgrade_sentinel = &sh.random[24..31] == DOWNGRADE_SENTINEL && sh.random[31] <= 1;
+    }
+    let offered = normalise(v.client_max_version);
+    v.inconsistent = is_tls13_suite(v.cipher_suite) != (v.version >= TLS13)
+        // supported_versions is only for TLS 1.3 and above, which fix legacy_version
+        || (source != TLS_VERSION_FROM_LEGACY && (v.version < TLS13 || v.legacy_version != TLS12))
+        || (offered != 0 && v.version > offered);
+    Ok(v)
+}
+
+// --- FFI ---
+
+/// Negotiated version and cipher suite of a connection from its handshake
+/// messages concatenated in any order (e.g. the IrisTlsHandshake data from both
+/// directions' streams). Reads supported_versions rather than the frozen
+/// legacy_version, and sets `hidden` to what TLS 1.3 encrypts. The ClientHello is
+/// optional. Returns 0=ok, -2=arg error or malformed, -3=no ServerHello.
+#[no_mangle]
+pub extern "C" fn iris_tls_negotiated_version(data: *const u8, len: usize, out: *mut IrisTlsVersion) -> i32 {
+    if data.is_null() || out.is_null() { return -2; }
+    match infer(unsafe { std::slice::from_raw_parts(data, len) }) {
+        Ok(v) => { unsafe { out.write(v); } 0 }
+        Err(e) => e,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tls::tests::{client_hello_with, ext};
+
+    fn server_hello(version: u16, random: [u8; 32], cs: u16, exts: &[Vec<u8>]) -> Vec<u8> {
+        let exts = exts.concat();
+        let body = [&version.to_be_bytes()[..], &random, &[0], &cs.to_be_bytes(), &[0],
+                    &(exts.len() as u16).to_be_bytes(), &exts].concat();
+        [&[HS_SERVER_HELLO, 0][..], &(body.len() as u16).to_be_bytes(), &body].concat()
+    }
+
+    /// A bare ClientHello message offering `versions` in supported_versions.
+    fn client_hello(versions: &[u16]) -> Vec<u8> {
+        let list: Vec<u8> = versions.iter().flat_map(|v| v.to_be_bytes()).collect();
+        let sv = ext(EXT_SUPPORTED_VERSIONS, &[&[list.len() as u8][..], &list].concat());
+        client_hello_with(TLS12, &[0x1301, 0xc02f], &[sv])[5..].to_vec()
+    }
+
+    const HRR: [u8; 32] = [
+        0xcf, 0x21, 0xad, 0x74, 0xe5, 0x9a, 0x61, 0x11, 0xbe, 0x1d, 0x8c, 0x02, 0x1e, 0x65, 0xb8, 0x91,
+        0xc2, 0xa2, 0x11, 0x16, 0x7a, 0xbb, 0x8c, 0x5e, 0x07, 0x9e, 0x09, 0xe2, 0xc8, 0xa8, 0x33, 0x9c,
+    ];
+
+    #[test]
+    fn tls13_behind_legacy_version() {
+        let sh = server_hello(TLS12, [7; 32], 0x1302, &[ext(EXT_SUPPORTED_VERSIONS, &TLS13.to_be_bytes()),
+                                                     ext(EXT_KEY_SHARE, &[0, 29, 0, 32])]);
+        let v = infer(&[client_hello(&[0x0a0a, TLS13, TLS12]), sh].concat()).unwrap();
+        assert_eq!((v.version, v.legacy_version, v.source), (TLS13, TLS12, TLS_VERSION_FROM_SUPPORTED_VERSIONS));
+        assert_eq!((v.cipher_suite, v.group, v.client_max_version), (0x1302, 29, TLS13));
+        assert_eq!(v.hidden, TLS13_HIDDEN);
+        assert!(!v.inconsistent && !v.hello_retry && !v.downgrade_sentinel);
+
+        // Draft 23, from before RFC 8446
+        let sh = server_hello(TLS12, [7; 32], 0x1301, &[ext(EXT_SUPPORTED_VERSIONS, &[0x7f, 23])]);
+        let v = infer(&sh).unwrap();
+        assert_eq!((v.version, v.wire_version), (TLS13, 0x7f17));
+
+        // Only the HelloRetryRequest made it into the capture
+        let hrr = server_hello(TLS12, HRR, 0x1301, &[ext(EXT_SUPPORTED_VERSIONS, &TLS13.to_be_bytes()), ext(EXT_KEY_SHARE, &[0, 23])]);
+        let v = infer(&hrr).unwrap();
+        assert_eq!((v.version, v.source, v.group), (TLS13, TLS_VERSION_FROM_RETRY, 23));
+        assert!(v.hello_retry);
+    }
+
+    #[test]
+    fn tls12_and_contradictions() {
+        let v = infer(&server_hello(TLS12, [7; 32], 0xc02f, &[])).unwrap();
+        assert_eq!((v.version, v.source, v.hidden, v.group), (TLS12, TLS_VERSION_FROM_LEGACY, 0, 0));
+        assert!(!v.inconsistent);
+
+        // A TLS 1.3 server falling back to 1.2 for a client that offered 1.3
+        let mut random = [7; 32];
+        random[24..].copy_from_slice(b"DOWNGRD\x01");
+        let v = infer(&[client_hello(&[TLS13, TLS12]), server_hello(TLS12, random, 0xc02f, &[])].concat()).unwrap();
+        assert!(v.downgrade_sentinel && !v.inconsistent);
+
+        for msgs in [
+            server_hello(TLS12, [7; 32], 0x1301, &[]), // TLS 1.3 suite without supported_versions
+            server_hello(TLS12, [7; 32], 0xc02f, &[ext(EXT_SUPPORTED_VERSIONS, &TLS12.to_be_bytes())]),
+            server_hello(0x0301, [7; 32], 0x1301, &[ext(EXT_SUPPORTED_VERSIONS, &TLS13.to_be_bytes())]),
+            [client_hello(&[TLS12]), server_hello(TLS12, [7; 32], 0x1301, &[ext(EXT_SUPPORTED_VERSIONS, &TLS13.to_be_bytes())])].concat(),
+        ] {
+            assert!(infer(&msgs).unwrap().inconsistent);
+        }
+    }
+
+    #[test]
+    fn ffi() {
+        let sh = server_hello(TLS12, [7; 32], 0x1303, &[ext(EXT_SUPPORTED_VERSIONS, &TLS13.to_be_bytes())]);
+        let mut out = IrisTlsVersion::default();
+        assert_eq!(iris_tls_negotiated_version(sh.as_ptr(), sh.len(), &mut out), 0);
+        assert_eq!((out.version, out.cipher_suite), (TLS13, 0x1303));
+        assert_eq!(iris_tls_negotiated_version(sh.as_ptr(), 10, &mut out), -2);
+        let ch = client_hello(&[TLS13]);
+        assert_eq!(iris_tls_negotiated_version(ch.as_ptr(), ch.len(), &mut out), -3);
+        assert_eq!(iris_tls_negotiated_version(std::ptr::null(), 0, &mut out), -2);
+    }
+}
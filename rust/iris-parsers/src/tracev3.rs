@@ -0,0 +1,723 @@
+//! Unified logging (tracev3) reader. Walks header, catalog and chunkset chunks, decodes
+//! firehose log entries and formats them with uuidtext/dsc format strings and timesync
+//! wall-clock data. Activity, signpost, state-dump and oversize chunks are skipped.
+
+use crate::bytes::{le16, le32, le64};
+use crate::ffi::{free_cstr, to_cstr};
+use crate::lz4;
+use std::collections::{HashMap, VecDeque};
+use std::ffi::{c_char, CStr};
+
+const CHUNK_HEADER: u32 = 0x1000;
+const CHUNK_CATALOG: u32 = 0x600b;
+const CHUNK_CHUNKSET: u32 = 0x600d;
+const CHUNK_FIREHOSE: u32 = 0x6001;
+const SUBCHUNK_GENERATION: u32 = 0x6102;
+
+const BV41: &[u8] = b"bv41";
+const BV41_UNCOMPRESSED: &[u8] = b"bv4-";
+const UUIDTEXT_MAGIC: u32 = 0x6677_8899;
+const DSC_MAGIC: &[u8] = b"hcsd";
+const TIMESYNC_BOOT: u16 = 0xbbb0;
+const TIMESYNC_RECORD: u32 = 0x0020_7354;
+
+const ACTIVITY_LOG: u8 = 0x4;
+
+const FLAG_CURRENT_AID: u16 = 0x0001;
+const FLAG_FORMATTER_MASK: u16 = 0x000e;
+const FMT_MAIN_EXE: u16 = 0x0002;
+const FMT_SHARED_CACHE: u16 = 0x0004;
+const FMT_ABSOLUTE: u16 = 0x0008;
+const FMT_UUID_RELATIVE: u16 = 0x000a;
+const FMT_LARGE_SHARED_CACHE: u16 = 0x000c;
+const FLAG_LARGE_OFFSET: u16 = 0x0020;
+const FLAG_PRIVATE_DATA: u16 = 0x0100;
+const FLAG_SUBSYSTEM: u16 = 0x0200;
+const FLAG_RULES: u16 = 0x0400;
+const FLAG_OVERSIZE: u16 = 0x0800;
+
+#[repr(C)]
+pub struct IrisLogEntry {
+    pub timestamp_ns: i64,       // unix nanoseconds; 0 without timesync data for the boot
+    pub continuous_time: u64,    // mach continuous ticks
+    pub pid: u32,
+    pub euid: u32,
+    pub thread_id: u64,
+    pub log_type: u8,            // IRIS_LOG_TYPE_*
+    pub process: *mut c_char,    // main executable path from uuidtext, else its UUID
+    pub sender: *mut c_char,     // image that holds the format string (library or executable)
+    pub subsystem: *mut c_char,  // "" when the entry has none
+    pub category: *mut c_char,
+    pub message: *mut c_char,    // formatted; "<private>" for redacted arguments
+    pub format: *mut c_char,     // raw format string, "" if it could not be resolved
+}
+
+fn uuid_at(d: &[u8], o: usize) -> Option<[u8; 16]> { d.get(o..o + 16)?.try_into().ok() }
+fn align8(n: usize) -> usize { (n + 7) & !7 }
+
+fn cstr_at(d: &[u8], o: usize) -> Option<String> {
+    let s = d.get(o..)?;
+    let end = s.iter().position(|&b| b == 0).unwrap_or(s.len());
+    Some(String::from_utf8_lossy(&s[..end]).into_owned())
+}
+
+fn uuid_hex(u: &[u8; 16]) -> String {
+    u.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+fn parse_uuid(s: &str) -> Option<[u8; 16]> {
+    let hex: Vec<u8> = s.bytes().filter(|&b| b != b'-').collect();
+    if hex.len() != 32 { return None; }
+    let mut out = [0u8; 16];
+    for (i, pair) in hex.chunks(2).enumerate() {
+        out[i] = u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()?;
+    }
+    Some(out)
+}
+
+// --- Format string sources ---
+
+/// /var/db/uuidtext/XX/YYYY...: format strings of one executable or library.
+struct UuidText {
+    data: Vec<u8>,
+    ranges: Vec<(u32, u32, usize)>, // (range start, size, file offset)
+    image_path: String,
+}
+
+impl UuidText {
+    fn parse(data: Vec<u8>) -> Option<UuidText> {
+        if le32(&data, 0)? != UUIDTEXT_MAGIC { return None; }
+        let n = le32(&data, 12)? as usize;
+        let mut pos = 16usize.checked_add(n.checked_mul(8)?)?;
+        let mut ranges = Vec::with_capacity(n);
+        for i in 0..n {
+            let (start, size) = (le32(&data, 16 + i * 8)?, le32(&data, 20 + i * 8)?);
+            ranges.push((start, size, pos));
+            pos = pos.checked_add(size as usize)?;
+        }
+        let image_path = cstr_at(&data, pos).unwrap_or_default();
+        Some(UuidText { data, ranges, image_path })
+    }
+
+    fn format(&self, offset: u64) -> Option<String> {
+        let &(start, _, file_off) = self.ranges.iter()
+            .find(|&&(s, size, _)| offset >= s as u64 && offset < s as u64 + size as u64)?;
+        cstr_at(&self.data, file_off + (offset - start as u64) as usize)
+    }
+}
+
+/// /var/db/uuidtext/dsc/<UUID>: format strings of the dyld shared cache (versions 1 and 2).
+struct Dsc {
+    data: Vec<u8>,
+    ranges: Vec<(u64, u32, u32, usize)>, // (virtual offset, data offset, size, uuid index)
+    images: Vec<u32>,                    // path offset per uuid entry
+}
+
+impl Dsc {
+    fn parse(data: Vec<u8>) -> Option<Dsc> {
+        if data.get(..4)? != DSC_MAGIC { return None; }
+        let major = le16(&data, 4)?;
+        let (nr, nu) = (le32(&data, 8)? as usize, le32(&data, 12)? as usize);
+        let (range_len, uuid_len) = match major { 1 => (16, 28), 2 => (24, 32), _ => return None };
+        let mut ranges = Vec::with_capacity(nr.min(1 << 16));
+        let mut p = 16;
+        for _ in 0..nr {
+            ranges.push(match major {
+                1 => (le32(&data, p + 4)? as u64, le32(&data, p + 8)?, le32(&data, p + 12)?, le32(&data, p)? as usize),
+                _ => (le64(&data, p)?, le32(&data, p + 8)?, le32(&data, p + 12)?, le64(&data, p + 16)? as usize),
+            });
+            p += range_len;
+        }
+        let mut images = Vec::with_capacity(nu.min(1 << 16));
+        for _ in 0..nu {
+            images.push(le32(&data, p + uuid_len - 4)?);
+            p += uuid_len;
+        }
+        Some(Dsc { data, ranges, images })
+    }
+
+    fn format(&self, offset: u64) -> Option<(String, String)> {
+        let &(v, d, _, img) = self.ranges.iter().find(|&&(v, _, size, _)| offset >= v && offset < v + size as u64)?;
+        let fmt = cstr_at(&self.data, d as usize + (offset - v) as usize)?;
+        let image = self.images.get(img).and_then(|&o| cstr_at(&self.data, o as usize)).unwrap_or_default();
+        Some((fmt, image))
+    }
+}
+
+struct Boot {
+    uuid: [u8; 16],
+    numer: u32,
+    denom: u32,
+    records: Vec<(u64, i64)>, // (continuous ticks, wall ns)
+}
+
+/// Loaded uuidtext, dsc and timesync data shared by tracev3 iterators.
+pub struct IrisUnifiedLog {
+    uuidtext: HashMap<[u8; 16], UuidText>,
+    dsc: HashMap<[u8; 16], Dsc>,
+    boots: Vec<Boot>,
+}
+
+impl IrisUnifiedLog {
+    fn add_timesync(&mut self, d: &[u8]) -> Option<()> {
+        let mut p = 0;
+        while p + 4 <= d.len() {
+            if le16(d, p)? == TIMESYNC_BOOT {
+                let size = le16(d, p + 2)? as usize;
+                self.boots.push(Boot {
+                    uuid: uuid_at(d, p + 8)?,
+                    numer: le32(d, p + 24)?,
+                    denom: le32(d, p + 28)?,
+                    records: Vec::new(),
+                });
+                p += size.max(48);
+            } else if le32(d, p)? == TIMESYNC_RECORD {
+                let rec = (le64(d, p + 8)?, le64(d, p + 16)? as i64);
+                self.boots.last_mut()?.records.push(rec);
+                p += 32;
+            } else {
+                return None;
+            }
+        }
+        Some(())
+    }
+
+    fn wall_ns(&self, boot: &[u8; 16], ct: u64) -> i64 {
+        let Some(b) = self.boots.iter().rev().find(|b| &b.uuid == boot) else { return 0 };
+        let Some(&(kt, wall)) = b.records.iter().rev().find(|r| r.0 <= ct).or(b.records.first()) else { return 0 };
+        let delta = (ct as i128 - kt as i128) * b.numer as i128 / b.denom.max(1) as i128;
+        wall.saturating_add(delta as i64)
+    }
+}
+
+// --- tracev3 ---
+
+struct Process {
+    pid: u32,
+    euid: u32,
+    main_uuid: Option<[u8; 16]>,
+    dsc_uuid: Option<[u8; 16]>,
+    subsystems: Vec<(u16, String, String)>, // (id, subsystem, category)
+}
+
+struct Entry {
+    continuous_time: u64,
+    pid: u32,
+    euid: u32,
+    thread_id: u64,
+    log_type: u8,
+    process: String,
+    sender: String,
+    subsystem: String,
+    category: String,
+    message: String,
+    format: String,
+}
+
+/// Iterator over one tracev3 file. Holds a copy of the file and borrows the context.
+pub struct IrisTracev3Iter {
+    ctx: *const IrisUnifiedLog,
+    data: Vec<u8>,
+    pos: usize,
+    boot_uuid: [u8; 16],
+    processes: HashMap<(u64, u32), Process>,
+    pending: VecDeque<Entry>,
+}
+
+fn parse_catalog(d: &[u8]) -> Option<HashMap<(u64, u32), Process>> {
+    let strings_off = le16(d, 0)? as usize;
+    let procs_off = le16(d, 2)? as usize;
+    let nprocs = le16(d, 4)? as usize;
+    let uuids: Vec<[u8; 16]> = (0..strings_off / 16).filter_map(|i| uuid_at(d, 24 + i * 16)).collect();
+    let strings = d.get(24 + strings_off..24 + procs_off)?;
+    let string = |o: u16| cstr_at(strings, o as usize).unwrap_or_default();
+    let mut out = HashMap::new();
+    let mut p = 24 + procs_off;
+    for _ in 0..nprocs {
+        let main_idx = le16(d, p + 4)? as usize;
+        let dsc_idx = le16(d, p + 6)? as usize;
+        let key = (le64(d, p + 8)?, le32(d, p + 16)?);
+        let (pid, euid) = (le32(d, p + 20)?, le32(d, p + 24)?);
+        let n_uuid = le32(d, p + 32)? as usize;
+        p += 40 + n_uuid.checked_mul(16)?;
+        let n_sub = le32(d, p)? as usize;
+        p += 8;
+        let subsystems = (0..n_sub).map(|i| {
+            let o = p + i * 6;
+            Some((le16(d, o)?, string(le16(d, o + 2)?), string(le16(d, o + 4)?)))
+        }).collect::<Option<Vec<_>>>()?;
+        p += align8(n_sub * 6);
+        out.insert(key, Process { pid, euid, main_uuid: uuids.get(main_idx).copied(), dsc_uuid: uuids.get(dsc_idx).copied(), subsystems });
+    }
+    Some(out)
+}
+
+enum Arg {
+    Num(u64, usize), // value, byte width
+    Str(String),
+}
+
+/// printf-style expansion, including os_log "%{public}s" annotations.
+fn format_message(fmt: &str, args: &[Arg]) -> String {
+    let mut out = String::with_capacity(fmt.len() + 32);
+    let mut args = args.iter();
+    let mut chars = fmt.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' { out.push(c); continue; }
+        if chars.peek() == Some(&'%') { chars.next(); out.push('%'); continue; }
+        let mut zero = false;
+        let (mut width, mut precision) = (0usize, false);
+        while let Some(&f) = chars.peek() {
+            match f {
+                '-' | '+' | ' ' | '#' | '*' => {}
+                '0' if width == 0 && !precision => zero = true,
+                '0'..='9' if !precision => width = width * 10 + (f as usize - '0' as usize),
+                '0'..='9' => {}
+                '.' => precision = true,
+                '{' => { for a in chars.by_ref() { if a == '}' { break; } } continue; }
+                'h' | 'l' | 'q' | 'z' | 't' | 'j' | 'L' => {}
+                _ => break,
+            }
+            chars.next();
+        }
+        let Some(conv) = chars.next() else { break };
+        let s = match (conv, args.next()) {
+            (_, None) => "<missing>".to_string(),
+            (_, Some(Arg::Str(s))) => s.clone(),
+            ('d' | 'i', Some(Arg::Num(v, w))) => {
+                let shift = 64 - 8 * (*w).clamp(1, 8) as u32;
+                (((*v << shift) as i64) >> shift).to_string()
+            }
+            ('x', Some(Arg::Num(v, _))) => format!("{:x}", v),
+            ('X', Some(Arg::Num(v, _))) => format!("{:X}", v),
+            ('o', Some(Arg::Num(v, _))) => format!("{:o}", v),
+            ('p', Some(Arg::Num(v, _))) => format!("0x{:x}", v),
+            ('c', Some(Arg::Num(v, _))) => char::from_u32(*v as u32).map(String::from).unwrap_or_default(),
+            ('f' | 'F' | 'e' | 'E' | 'g' | 'G', Some(Arg::Num(v, _))) => f64::from_bits(*v).to_string(),
+            (_, Some(Arg::Num(v, _))) => v.to_string(),
+        };
+        let pad = width.saturating_sub(s.chars().count());
+        out.extend(std::iter::repeat_n(if zero { '0' } else { ' ' }, pad));
+        out.push_str(&s);
+    }
+    out
+}
+
+/// Message arguments: item headers, then the string area that string items point into.
+fn parse_items(d: &[u8], mut p: usize) -> Vec<Arg> {
+    let mut args = Vec::new();
+    let Some(&count) = d.get(p + 1) else { return args };
+    p += 2;
+    let mut strings = Vec::new();
+    for _ in 0..count {
+        let (Some(&ty), Some(&size)) = (d.get(p), d.get(p + 1)) else { break };
+        p += 2;
+        match ty {
+            0x20 | 0x22 | 0x40 | 0x42 | 0x30 | 0x32 | 0xf2 => {
+                let (off, len) = (le16(d, p).unwrap_or(0), le16(d, p + 2).unwrap_or(0));
+                strings.push((args.len(), off as usize, len as usize));
+                args.push(Arg::Str(String::new()));
+            }
+            0x21 | 0x25 | 0x31 | 0x35 | 0x41 | 0x45 | 0x85 | 0x01 => args.push(Arg::Str("<private>".into())),
+            0x10 | 0x12 => {} // precision for "%.*s"
+            _ => {
+                let v = d.get(p..p + size as usize).unwrap_or(&[]).iter().rev().fold(0u64, |a, &b| a << 8 | b as u64);
+                args.push(Arg::Num(v, size as usize));
+            }
+        }
+        p += size as usize;
+    }
+    for (i, off, len) in strings {
+        let s = d.get(p + off..p + off + len).unwrap_or(&[]);
+        let s = &s[..s.iter().position(|&b| b == 0).unwrap_or(s.len())];
+        args[i] = Arg::Str(if len == 0 { "(null)".into() } else { String::from_utf8_lossy(s).into_owned() });
+    }
+    args
+}
+
+impl IrisTracev3Iter {
+    fn ctx(&self) -> &IrisUnifiedLog { unsafe { &*self.ctx } }
+
+    /// Decode one log (non-activity) firehose entry.
+    fn log_entry(&self, proc_key: (u64, u32), ct: u64, hdr: &[u8], d: &[u8]) -> Option<Entry> {
+        let flags = le16(hdr, 2)?;
+        let fmt_loc = le32(hdr, 4)? as u64;
+        let mut p = 0;
+        if flags & FLAG_CURRENT_AID != 0 { p += 8; }
+        if flags & FLAG_PRIVATE_DATA != 0 { p += 4; }
+        p += 4; // pc id
+        let mut large = 0u64;
+        if flags & FLAG_LARGE_OFFSET != 0 { large = le16(d, p)? as u64; p += 2; }
+        let fmt_kind = flags & FLAG_FORMATTER_MASK;
+        let mut relative_uuid = None;
+        match fmt_kind {
+            FMT_LARGE_SHARED_CACHE => { large = le16(d, p)? as u64; p += 2; }
+            FMT_ABSOLUTE => p += 2,
+            FMT_UUID_RELATIVE => { relative_uuid = uuid_at(d, p); p += 16; }
+            _ => {}
+        }
+        let subsystem_id = if flags & FLAG_SUBSYSTEM != 0 { p += 2; le16(d, p - 2) } else { None };
+        if flags & FLAG_RULES != 0 { p += 1; }
+        if flags & FLAG_OVERSIZE != 0 { p += 2; }
+
+        let ctx = self.ctx();
+        let proc = self.processes.get(&proc_key);
+        let main = proc.and_then(|pr| pr.main_uuid);
+        let offset = large << 31 | fmt_loc;
+        let (format, sender) = match fmt_kind {
+            FMT_SHARED_CACHE | FMT_LARGE_SHARED_CACHE => proc.and_then(|pr| pr.dsc_uuid)
+                .and_then(|u| ctx.dsc.get(&u)).and_then(|dsc| dsc.format(offset)).unwrap_or_default(),
+            FMT_MAIN_EXE | FMT_UUID_RELATIVE => {
+                let uuid = if fmt_kind == FMT_UUID_RELATIVE { relative_uuid } else { main };
+                let ut = uuid.and_then(|u| ctx.uuidtext.get(&u));
+                (ut.and_then(|t| t.format(offset)).unwrap_or_default(), ut.map(|t| t.image_path.clone()).unwrap_or_default())
+            }
+            _ => (String::new(), String::new()),
+        };
+        let args = parse_items(d, p);
+        let message = if format.is_empty() {
+            format!("<format 0x{:x} not found>", offset)
+        } else {
+            format_message(&format, &args)
+        };
+        let process = main.map(|u| ctx.uuidtext.get(&u).map_or_else(|| uuid_hex(&u), |t| t.image_path.clone())).unwrap_or_default();
+        let (subsystem, category) = subsystem_id
+            .and_then(|id| proc?.subsystems.iter().find(|s| s.0 == id))
+            .map(|s| (s.1.clone(), s.2.clone())).unwrap_or_default();
+        Some(Entry {
+            continuous_time: ct,
+            pid: proc.map_or(0, |p| p.pid),
+            euid: proc.map_or(0, |p| p.euid),
+            thread_id: le64(hdr, 8)?,
+            log_type: hdr[1],
+            process, sender, subsystem, category, message, format,
+        })
+    }
+
+    fn firehose(&mut self, c: &[u8]) -> Option<()> {
+        let key = (le64(c, 0)?, le32(c, 8)?);
+        let public_size = (le16(c, 16)? as usize).checked_sub(16)?;
+        let base = le64(c, 24)?;
+        let public = c.get(32..32 + public_size)?;
+        let mut p = 0;
+        while p + 24 <= public.len() {
+            let hdr = &public[p..p + 24];
+            if hdr[0] == 0 { break; }
+            let size = le16(hdr, 22)? as usize;
+            let body = public.get(p + 24..p + 24 + size)?;
+            if hdr[0] == ACTIVITY_LOG {
+                let ct = base + (le32(hdr, 16)? as u64 | (le16(hdr, 20)? as u64) << 32);
+                if let Some(e) = self.log_entry(key, ct, hdr, body) { self.pending.push_back(e); }
+            }
+            p = align8(p + 24 + size);
+        }
+        Some(())
+    }
+
+    fn chunkset(&mut self, c: &[u8]) -> Option<()> {
+        let magic = c.get(..4)?;
+        let size = le32(c, 4)? as usize;
+        let raw = if magic == BV41 {
+            let csize = le32(c, 8)? as usize;
+            lz4::decompress_block(c.get(12..12 + csize)?, size)?
+        } else if magic == BV41_UNCOMPRESSED {
+            c.get(8..8 + size)?.to_vec()
+        } else {
+            return None;
+        };
+        let mut p = 0;
+        while p + 16 <= raw.len() {
+            let tag = le32(&raw, p)?;
+            let len = le64(&raw, p + 8)? as usize;
+            let body = raw.get(p + 16..(p + 16).checked_add(len)?)?;
+            if tag == CHUNK_FIREHOSE { self.firehose(body); }
+            p = align8(p + 16 + len);
+        }
+        Some(())
+    }
+
+    /// Advance through top-level chunks until entries are pending or the file ends.
+    fn fill(&mut self) {
+        while self.pending.is_empty() && self.pos + 16 <= self.data.len() {
+            let p = self.pos;
+            let (Some(tag), Some(len)) = (le32(&self.data, p), le64(&self.data, p + 8)) else { break };
+            let Some(end) = (p + 16).checked_add(len as usize).filter(|&e| e <= self.data.len()) else { break };
+            self.pos = align8(end);
+            let body = self.data[p + 16..end].to_vec();
+            match tag {
+                CHUNK_CATALOG => { if let Some(procs) = parse_catalog(&body) { self.processes = procs; } }
+                CHUNK_CHUNKSET => { self.chunkset(&body); }
+                _ => {}
+            }
+        }
+    }
+}
+
+fn boot_uuid(header: &[u8]) -> Option<[u8; 16]> {
+    let mut p = 40;
+    while p + 8 <= header.len() {
+        let (tag, size) = (le32(header, p)?, le32(header, p + 4)? as usize);
+        if tag == SUBCHUNK_GENERATION { return uuid_at(header, p + 8); }
+        p += 8 + size;
+    }
+    None
+}
+
+// --- FFI entry points ---
+
+/// Create a context for uuidtext, dsc and timesync data. Free with iris_unifiedlog_free.
+#[no_mangle]
+pub extern "C" fn iris_unifiedlog_new() -> *mut IrisUnifiedLog {
+    Box::into_raw(Box::new(IrisUnifiedLog { uuidtext: HashMap::new(), dsc: HashMap::new(), boots: Vec::new() }))
+}
+
+fn add_file(ctx: *mut IrisUnifiedLog, uuid: *const c_char, data: *const u8, len: usize, dsc: bool) -> i32 {
+    if ctx.is_null() || uuid.is_null() || data.is_null() { return -2; }
+    let Some(uuid) = unsafe { CStr::from_ptr(uuid) }.to_str().ok().and_then(parse_uuid) else { return -2 };
+    let buf = unsafe { std::slice::from_raw_parts(data, len) }.to_vec();
+    let ctx = unsafe { &mut *ctx };
+    if dsc {
+        let Some(d) = Dsc::parse(buf) else { return -2 };
+        ctx.dsc.insert(uuid, d);
+    } else {
+        let Some(t) = UuidText::parse(buf) else { return -2 };
+        ctx.uuidtext.insert(uuid, t);
+    }
+    0
+}
+
+/// Register a uuidtext file under its UUID ("XX" directory + file name, dashes optional).
+/// Returns 0=ok, -2=arg error or not a uuidtext file.
+#[no_mangle]
+pub extern "C" fn iris_unifiedlog_add_uuidtext(ctx: *mut IrisUnifiedLog, uuid: *const c_char, data: *const u8, len: usize) -> i32 {
+    add_file(ctx, uuid, data, len, false)
+}
+
+/// Register a shared-cache strings file from uuidtext/dsc. Returns 0=ok, -2=error.
+#[no_mangle]
+pub extern "C" fn iris_unifiedlog_add_dsc(ctx: *mut IrisUnifiedLog, uuid: *const c_char, data: *const u8, len: usize) -> i32 {
+    add_file(ctx, uuid, data, len, true)
+}
+
+/// Load a .timesync file (boot records and wall-clock samples). Returns 0=ok, -2=error.
+#[no_mangle]
+pub extern "C" fn iris_unifiedlog_add_timesync(ctx: *mut IrisUnifiedLog, data: *const u8, len: usize) -> i32 {
+    if ctx.is_null() || data.is_null() { return -2; }
+    let buf = unsafe { std::slice::from_raw_parts(data, len) };
+    match unsafe { (*ctx).add_timesync(buf) } { Some(()) => 0, None => -2 }
+}
+
+/// Free a context created by iris_unifiedlog_new. Close its iterators first.
+#[no_mangle]
+pub extern "C" fn iris_unifiedlog_free(ctx: *mut IrisUnifiedLog) {
+    if ctx.is_null() { return; }
+    unsafe { drop(Box::from_raw(ctx)); }
+}
+
+/// Start iterating a tracev3 file (copied). `ctx` must outlive the iterator.
+/// Returns null on arg error or if the file does not start with a header chunk.
+#[no_mangle]
+pub extern "C" fn iris_tracev3_open(ctx: *const IrisUnifiedLog, data: *const u8, len: usize) -> *mut IrisTracev3Iter {
+    if ctx.is_null() || data.is_null() || len < 16 { return std::ptr::null_mut(); }
+    let data = unsafe { std::slice::from_raw_parts(data, len) }.to_vec();
+    if le32(&data, 0) != Some(CHUNK_HEADER) { return std::ptr::null_mut(); }
+    let hlen = le64(&data, 8).unwrap_or(0) as usize;
+    let header = data.get(16..16 + hlen).unwrap_or(&[]);
+    let boot_uuid = boot_uuid(header).unwrap_or([0; 16]);
+    Box::into_raw(Box::new(IrisTracev3Iter {
+        ctx, boot_uuid, pos: align8(16 + hlen), data, processes: HashMap::new(), pending: VecDeque::new(),
+    }))
+}
+
+/// Next log entry. Returns 0=entry written (free with iris_log_entry_free), -1=end.
+#[no_mangle]
+pub extern "C" fn iris_tracev3_next(it: *mut IrisTracev3Iter, out: *mut IrisLogEntry) -> i32 {
+    if it.is_null() || out.is_null() { return -2; }
+    let it = unsafe { &mut *it };
+    it.fill();
+    let Some(e) = it.pending.pop_front() else { return -1 };
+    let timestamp_ns = it.ctx().wall_ns(&it.boot_uuid, e.continuous_time);
+    unsafe {
+        out.write(IrisLogEntry {
+            timestamp_ns,
+            continuous_time: e.continuous_time,
+            pid: e.pid, euid: e.euid, thread_id: e.thread_id, log_type: e.log_type,
+            process: to_cstr(&e.process),
+            sender: to_cstr(&e.sender),
+            subsystem: to_cstr(&e.subsystem),
+            category: to_cstr(&e.category),
+            message: to_cstr(&e.message),
+            format: to_cstr(&e.format),
+        });
+    }
+    0
+}
+
+/// Free the strings of an entry returned by iris_tracev3_next.
+#[no_mangle]
+pub extern "C" fn iris_log_entry_free(e: *mut IrisLogEntry) {
+    if e.is_null() { return; }
+    let e = unsafe { &*e };
+    for p in [e.process, e.sender, e.subsystem, e.category, e.message, e.format] { free_cstr(p); }
+}
+
+/// Free an iterator from iris_tracev3_open.
+#[no_mangle]
+pub extern "C" fn iris_tracev3_close(it: *mut IrisTracev3Iter) {
+    if it.is_null() { return; }
+    unsafe { drop(Box::from_raw(it)); }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAIN: [u8; 16] = [0x11; 16];
+    const BOOT: [u8; 16] = [0xb0; 16];
+
+    fn chunk(tag: u32, body: &[u8]) -> Vec<u8> {
+        let mut v = tag.to_le_bytes().to_vec();
+        v.extend_from_slice(&0u32.to_le_bytes());
+        v.extend_from_slice(&(body.len() as u64).to_le_bytes());
+        v.extend_from_slice(body);
+        v.resize(align8(v.len()), 0);
+        v
+    }
+
+    fn uuidtext() -> Vec<u8> {
+        let strings = b"ignored\0pid %d opened %{public}s (%s) 0x%08x\0";
+        let mut v = UUIDTEXT_MAGIC.to_le_bytes().to_vec();
+        for x in [2u32, 1, 1, 0x100, strings.len() as u32] { v.extend_from_slice(&x.to_le_bytes()); }
+        v.extend_from_slice(strings);
+        v.extend_from_slice(b"/usr/libexec/exampled\0");
+        v
+    }
+
+    fn catalog() -> Vec<u8> {
+        let strings = b"com.example.daemon\0network\0";
+        let mut proc = Vec::new();
+        proc.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0]);             // index, unknown, main uuid 0, dsc uuid 0
+        proc.extend_from_slice(&7u64.to_le_bytes());                    // first proc id
+        proc.extend_from_slice(&9u32.to_le_bytes());                    // second proc id
+        for x in [321u32, 501, 0, 0, 0] { proc.extend_from_slice(&x.to_le_bytes()); } // pid, euid, _, n uuids, _
+        proc.extend_from_slice(&1u32.to_le_bytes());                    // subsystems
+        proc.extend_from_slice(&0u32.to_le_bytes());
+        proc.extend_from_slice(&[0x42, 0, 0, 0, 19, 0, 0, 0]);          // id 0x42 -> strings 0 / 19, padded
+        let mut c = Vec::new();
+        c.extend_from_slice(&16u16.to_le_bytes());
+        c.extend_from_slice(&((16 + strings.len()) as u16).to_le_bytes());
+        c.extend_from_slice(&1u16.to_le_bytes());
+        c.extend_from_slice(&[0; 18]);
+        c.extend_from_slice(&MAIN);
+        c.extend_from_slice(strings);
+        c.extend_from_slice(&proc);
+        c
+    }
+
+    fn firehose() -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&0u32.to_le_bytes());                    // pc id
+        data.extend_from_slice(&0x42u16.to_le_bytes());                 // subsystem
+        data.extend_from_slice(&[0x02, 4]);                             // items
+        data.extend_from_slice(&[0x00, 4]); data.extend_from_slice(&321u32.to_le_bytes());
+        data.extend_from_slice(&[0x22, 4, 0, 0, 12, 0]);                // public string @0, 12 bytes
+        data.extend_from_slice(&[0x21, 4, 12, 0, 4, 0]);                // private string
+        data.extend_from_slice(&[0x00, 4]); data.extend_from_slice(&0xbeefu32.to_le_bytes());
+        data.extend_from_slice(b"/etc/hosts\0\0");
+        let mut e = vec![ACTIVITY_LOG, 0x10];                             // error
+        e.extend_from_slice(&(FMT_MAIN_EXE | FLAG_SUBSYSTEM).to_le_bytes());
+        e.extend_from_slice(&0x108u32.to_le_bytes());                   // "pid %d ..." is at 0x100 + 8
+        e.extend_from_slice(&77u64.to_le_bytes());                      // thread
+        e.extend_from_slice(&1000u32.to_le_bytes());
+        e.extend_from_slice(&0u16.to_le_bytes());
+        e.extend_from_slice(&(data.len() as u16).to_le_bytes());
+        e.extend_from_slice(&data);
+        e.resize(align8(e.len()), 0);
+        let mut f = 7u64.to_le_bytes().to_vec();
+        f.extend_from_slice(&9u32.to_le_bytes());
+        f.extend_from_slice(&[0; 4]);
+        f.extend_from_slice(&((e.len() + 16) as u16).to_le_bytes());
+        f.extend_from_slice(&[0; 6]);
+        f.extend_from_slice(&5000u64.to_le_bytes());                    // base continuous time
+        f.extend_from_slice(&e);
+        chunk(CHUNK_FIREHOSE, &f)
+    }
+
+    fn tracev3(compressed: bool) -> Vec<u8> {
+        let mut header = vec![0u8; 40];
+        header.extend_from_slice(&SUBCHUNK_GENERATION.to_le_bytes());
+        header.extend_from_slice(&24u32.to_le_bytes());
+        header.extend_from_slice(&BOOT);
+        header.extend_from_slice(&[0; 8]);
+        let inner = firehose();
+        let set = if compressed {
+            // Literal-only LZ4 block: token 0xF0, length extension bytes, literals
+            let mut block = vec![0xf0];
+            let mut n = inner.len() - 15;
+            while n >= 255 { block.push(255); n -= 255; }
+            block.push(n as u8);
+            block.extend_from_slice(&inner);
+            [BV41, &(inner.len() as u32).to_le_bytes(), &(block.len() as u32).to_le_bytes(), &block, b"bv4$"].concat()
+        } else {
+            [BV41_UNCOMPRESSED, &(inner.len() as u32).to_le_bytes(), &inner].concat()
+        };
+        [chunk(CHUNK_HEADER, &header), chunk(CHUNK_CATALOG, &catalog()), chunk(CHUNK_CHUNKSET, &set)].concat()
+    }
+
+    fn timesync() -> Vec<u8> {
+        let mut v = TIMESYNC_BOOT.to_le_bytes().to_vec();
+        v.extend_from_slice(&48u16.to_le_bytes());
+        v.extend_from_slice(&[0; 4]);
+        v.extend_from_slice(&BOOT);
+        for x in [125u32, 3] { v.extend_from_slice(&x.to_le_bytes()); }
+        v.extend_from_slice(&[0; 16]);
+        v.extend_from_slice(&TIMESYNC_RECORD.to_le_bytes());
+        v.extend_from_slice(&[0; 4]);
+        v.extend_from_slice(&0u64.to_le_bytes());
+        v.extend_from_slice(&1_700_000_000_000_000_000i64.to_le_bytes());
+        v.extend_from_slice(&[0; 8]);
+        v
+    }
+
+    fn cstr(p: *mut c_char) -> String {
+        unsafe { CStr::from_ptr(p) }.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn reads_formatted_entries() {
+        let ctx = iris_unifiedlog_new();
+        let ut = uuidtext();
+        let uuid = std::ffi::CString::new(uuid_hex(&MAIN)).unwrap();
+        assert_eq!(iris_unifiedlog_add_uuidtext(ctx, uuid.as_ptr(), ut.as_ptr(), ut.len()), 0);
+        let ts = timesync();
+        assert_eq!(iris_unifiedlog_add_timesync(ctx, ts.as_ptr(), ts.len()), 0);
+
+        for compressed in [false, true] {
+            let file = tracev3(compressed);
+            let it = iris_tracev3_open(ctx, file.as_ptr(), file.len());
+            assert!(!it.is_null());
+            let mut out = std::mem::MaybeUninit::<IrisLogEntry>::uninit();
+            assert_eq!(iris_tracev3_next(it, out.as_mut_ptr()), 0);
+            let mut e = unsafe { out.assume_init() };
+            assert_eq!(cstr(e.message), "pid 321 opened /etc/hosts (<private>) 0x0000beef");
+            assert_eq!((cstr(e.process), cstr(e.subsystem), cstr(e.category)),
+                       ("/usr/libexec/exampled".into(), "com.example.daemon".into(), "network".into()));
+            assert_eq!((e.pid, e.euid, e.thread_id, e.log_type, e.continuous_time), (321, 501, 77, 0x10, 6000));
+            assert_eq!(e.timestamp_ns, 1_700_000_000_000_000_000 + 250_000);
+            iris_log_entry_free(&mut e);
+            let mut end = std::mem::MaybeUninit::<IrisLogEntry>::uninit();
+            assert_eq!(iris_tracev3_next(it, end.as_mut_ptr()), -1);
+            iris_tracev3_close(it);
+        }
+        iris_unifiedlog_free(ctx);
+    }
+
+    #[test]
+    fn format_specifiers() {
+        let args = [Arg::Num(0xff, 1), Arg::Num(42, 8), Arg::Str("x".into())];
+        assert_eq!(format_message("%hhd %5llu %{private, mask.hash}@ %% %s", &args), "-1    42 x % <missing>");
+        let ctx = iris_unifiedlog_new();
+        assert!(iris_tracev3_open(ctx, b"garbage!garbage!".as_ptr(), 16).is_null());
+        iris_unifiedlog_free(ctx);
+    }
+}
@@ -0,0 +1,259 @@
+//! DNS TSIG (RFC 8945) transaction signatures: the TSIG record (type 250) that
+//! ends a signed message, and HMAC verification of the message with a shared key,
+//! for auditing signed dynamic updates and zone transfers.
+
+use crate::dns::{self, IrisDnsEntry, SECTION_ADDITIONAL};
+use crate::ffi::{alloc_bytes, free_cstr, iris_free_bytes, to_cstr};
+use crate::hash::{hmac_md5, hmac_sha1, hmac_sha256};
+use std::ffi::c_char;
+
+pub const TSIG_SIG_UNCHECKED: u8 = 0;   // no key supplied
+pub const TSIG_SIG_VALID: u8 = 1;
+pub const TSIG_SIG_INVALID: u8 = 2;
+pub const TSIG_SIG_UNSUPPORTED: u8 = 3; // algorithm other than hmac-md5/sha1/sha256
+
+const TYPE_TSIG: u16 = 250;
+
+#[repr(C)]
+pub struct IrisDnsTsig {
+    pub key_name: *mut c_char,
+    pub algorithm: *mut c_char,     // "hmac-sha256", "hmac-md5.sig-alg.reg.int"
+    pub time_signed: u64,           // seconds since the epoch (48-bit on the wire)
+    pub fudge: u16,                 // permitted clock skew, seconds
+    pub mac: *mut u8,
+    pub mac_len: usize,
+    pub original_id: u16,
+    pub error: u16,                 // TSIG RCODE: 16 BADSIG, 17 BADKEY, 18 BADTIME, 22 BADTRUNC
+    pub other: *mut u8,             // BADTIME responses carry the server time here
+    pub other_len: usize,
+    pub signature_status: u8,       // TSIG_SIG_*
+    pub time_skewed: bool,          // time_signed is more than fudge away from now
+}
+
+pub struct Tsig {
+    pub key_name: String,
+    pub algorithm: String,
+    pub time_signed: u64,
+    pub fudge: u16,
+    pub mac: Vec<u8>,
+    pub original_id: u16,
+    pub error: u16,
+    pub other: Vec<u8>,
+    pub signature_status: u8,
+    pub time_skewed: bool,
+}
+
+/// Lowercased, uncompressed wire form of the name at `pos` and its dotted text.
+fn canonical_name(data: &[u8], pos: usize) -> Option<(Vec<u8>, String, usize)> {
+    let (mut wire, mut labels) = (Vec::new(), Vec::new());
+    let end = dns::walk_name(data, pos, |l| {
+        wire.push(l.len() as u8);
+        wire.extend(l.iter().map(u8::to_ascii_lowercase));
+        labels.push(String::from_utf8_lossy(l).to_ascii_lowercase());
+    })?;
+    wire.push(0);
+    Some((wire, labels.join("."), end))
+}
+
+fn mac_for(algorithm: &str, key: &[u8], input: &[u8]) -> Option<Vec<u8>> {
+    match algorithm {
+        "hmac-sha256" => Some(hmac_sha256(key, input).to_vec()),
+        "hmac-sha1" => Some(hmac_sha1(key, input).to_vec()),
+        "hmac-md5.sig-alg.reg.int" => Some(hmac_md5(key, input).to_vec()),
+        _ => None,
+    }
+}
+
+/// Parse the TSIG record that ends `data` and, given the shared key, verify its
+/// MAC. A response is signed over the request's MAC as well, so pass
+/// `request_mac` when checking one. Err(-3) unsigned, Err(-2) malformed.
+pub fn verify(data: &[u8], key: Option<&[u8]>, request_mac: Option<&[u8]>, now: u64) -> Result<Tsig, i32> {
+    let v = dns::view(data, &mut []).ok_or(-2)?;
+    let mut entries: Vec<IrisDnsEntry> = (0..v.count).map(|_| IrisDnsEntry::default()).collect();
+    dns::view(data, &mut entries).ok_or(-2)?;
+    // A truncated additional section would hide the record
+    if v.count != v.counts.iter().map(|&c| c as usize).sum::<usize>() { return Err(-2); }
+    let tsig = match entries.last() {
+        Some(e) if e.record_type == TYPE_TSIG && e.section as usize == SECTION_ADDITIONAL => e,
+        _ if entries.iter().any(|e| e.record_type == TYPE_TSIG) => return Err(-2),
+        _ => return Err(-3),
+    };
+
+    let rd_start = tsig.rdata.ptr as usize - data.as_ptr() as usize;
+    let rd_end = rd_start + tsig.rdata.len;
+    let (key_wire, key_name, _) = canonical_name(data, tsig.name_offset).ok_or(-2)?;
+    let (alg_wire, algorithm, p) = canonical_name(data, rd_start).ok_or(-2)?;
+    let rd = data.get(p..rd_end).ok_or(-2)?;
+    if rd.len() < 10 { return Err(-2); }
+    let be16 = |b: &[u8]| u16::from_be_bytes([b[0], b[1]]);
+    let time_signed = rd[..6].iter().fold(0u64, |t, &b| t << 8 | b as u64);
+    let fudge = be16(&rd[6..]);
+    let mac_len = be16(&rd[8..]) as usize;
+    let rest = rd.get(10 + mac_len..).filter(|r| r.len() >= 6).ok_or(-2)?;
+    let mac = rd[10..10 + mac_len].to_vec();
+    let (original_id, error, other_len) = (be16(rest), be16(&rest[2..]), be16(&rest[4..]) as usize);
+    let other = rest.get(6..6 + other_len).ok_or(-2)?.to_vec();
+
+    let signature_status = match key {
+        None => TSIG_SIG_UNCHECKED,
+        Some(k) => {
+            // Request MAC, then the message as it was before signing (original ID,
+            // TSIG not counted), then the TSIG variables (RFC 8945 §4.3.3)
+            let mut input = Vec::new();
+            if let Some(m) = request_mac {
+                input.extend_from_slice(&(m.len() as u16).to_be_bytes());
+                input.extend_from_slice(m);
+            }
+            input.extend_from_slice(&original_id.to_be_bytes());
+            input.extend_from_slice(&data[2..10]);
+            input.extend_from_slice(&(v.counts[3] - 1).to_be_bytes());
+            input.extend_from_slice(&data[12..tsig.name_offset]);
+            input.extend_from_slice(&key_wire);
+            input.extend_from_slice(&tsig.rrclass.to_be_bytes());
+            input.extend_from_slice(&tsig.ttl.to_be_bytes());
+            input.extend_from_slice(&alg_wire);
+            input.extend_from_slice(&rd[..8]);
+            input.extend_from_slice(&rest[2..6 + other_len]);
+            match mac_for(&algorithm, k, &input) {
+                None => TSIG_SIG_UNSUPPORTED,
+                // Truncated MACs (§5.2.2.1) must keep at least half and 10 bytes
+                Some(full) if mac.len() <= full.len() && mac.len() >= 10.max(full.len() / 2) && full[..mac.len()] == mac[..] => TSIG_SIG_VALID,
+                Some(_) => TSIG_SIG_INVALID,
+            }
+        }
+    };
+    Ok(Tsig {
+        key_name, algorithm, time_signed, fudge, mac, original_id, error, other, signature_status,
+        time_skewed: now.abs_diff(time_signed) > fudge as u64,
+    })
+}
+
+// --- FFI ---
+
+/// Parse the TSIG record of a signed DNS message. With a key (the raw shared
+/// secret) the MAC is verified; for a response also pass the MAC of the request
+/// (NULL otherwise). time_skewed is checked against the current time.
+/// Returns 0=ok, -2=arg error or malformed, -3=no TSIG record. Free with iris_dns_tsig_free.
+#[no_mangle]
+pub extern "C" fn iris_dns_tsig(
+    data: *const u8, len: usize, key: *const u8, key_len: usize,
+    request_mac: *const u8, request_mac_len: usize, out: *mut IrisDnsTsig,
+) -> i32 {
+    if data.is_null() || out.is_null() { return -2; }
+    let data = unsafe { std::slice::from_raw_parts(data, len) };
+    let key = (!key.is_null()).then(|| unsafe { std::slice::from_raw_parts(key, key_len) });
+    let request_mac = (!request_mac.is_null()).then(|| unsafe { std::slice::from_raw_parts(request_mac, request_mac_len) });
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let t = match verify(data, key, request_mac, now) {
+        Ok(t) => t,
+        Err(e) => return e,
+    };
+    let (mac, mac_len) = alloc_bytes(&t.mac);
+    let (other, other_len) = alloc_bytes(&t.other);
+    unsafe {
+        out.write(IrisDnsTsig {
+            key_name: to_cstr(&t.key_name),
+            algorithm: to_cstr(&t.algorithm),
+            time_signed: t.time_signed,
+            fudge: t.fudge,
+            mac, mac_len,
+            original_id: t.original_id,
+            error: t.error,
+            other, other_len,
+            signature_status: t.signature_status,
+            time_skewed: t.time_skewed,
+        });
+    }
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn iris_dns_tsig_free(t: *mut IrisDnsTsig) {
+    if t.is_null() { return; }
+    let t = unsafe { &*t };
+    free_cstr(t.key_name);
+    free_cstr(t.algorithm);
+    iris_free_bytes(t.mac, t.mac_len);
+    iris_free_bytes(t.other, t.other_len);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::{DnsWriter, SECTION_AUTHORITY};
+
+    const NOW: u64 = 1_700_000_100;
+    const SECRET: &[u8] = b"0123456789abcdef";
+
+    fn unhex(s: &str) -> Vec<u8> {
+        (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap()).collect()
+    }
+
+    /// A TSIG-signed message: an UPDATE adding an A record, or its response.
+    fn signed(response: bool, alg: &str, mac: &[u8]) -> Vec<u8> {
+        let mut w = DnsWriter::new(0x1234, if response { 0xA800 } else { 0x2800 });
+        w.question("example.com", 6, 1).unwrap();
+        if !response {
+            w.record(SECTION_AUTHORITY, "host.example.com", 1, 1, 300, |w| { w.bytes(&[192, 0, 2, 7]); Ok(()) }).unwrap();
+        }
+        w.record(SECTION_ADDITIONAL, "update-key", TYPE_TSIG, 255, 0, |w| {
+            w.name_uncompressed(alg)?;
+            w.bytes(&1_700_000_000u64.to_be_bytes()[2..]);
+            w.bytes(&300u16.to_be_bytes());
+            w.bytes(&(mac.len() as u16).to_be_bytes());
+            w.bytes(mac);
+            w.bytes(&[0x12, 0x34, 0, 0, 0, 0]);
+            Ok(())
+        }).unwrap();
+        w.finish()
+    }
+
+    #[test]
+    fn verify_request_and_response() {
+        // MACs computed independently (Python hmac) over the RFC 8945 digest layout
+        let req_mac = unhex("18a75ad9ed7df1e756492eceb359812d260fa723e03f0d622300c3641418920f");
+        let req = signed(false, "hmac-sha256", &req_mac);
+        let t = verify(&req, Some(SECRET), None, NOW).unwrap();
+        assert_eq!((t.key_name.as_str(), t.algorithm.as_str()), ("update-key", "hmac-sha256"));
+        assert_eq!((t.time_signed, t.fudge, t.original_id, t.error), (1_700_000_000, 300, 0x1234, 0));
+        assert_eq!((t.signature_status, t.time_skewed), (TSIG_SIG_VALID, false));
+        assert_eq!(verify(&req, Some(b"wrong"), None, NOW).unwrap().signature_status, TSIG_SIG_INVALID);
+        assert_eq!(verify(&req, None, None, NOW + 1000).map(|t| (t.signature_status, t.time_skewed)), Ok((TSIG_SIG_UNCHECKED, true)));
+
+        // Truncated to 16 bytes is allowed, to 8 is not
+        let short = signed(false, "hmac-sha256", &req_mac[..16]);
+        assert_eq!(verify(&short, Some(SECRET), None, NOW).unwrap().signature_status, TSIG_SIG_VALID);
+        let short = signed(false, "hmac-sha256", &req_mac[..8]);
+        assert_eq!(verify(&short, Some(SECRET), None, NOW).unwrap().signature_status, TSIG_SIG_INVALID);
+
+        // Tampering with the signed content
+        let mut tampered = req.clone();
+        let ip = tampered.iter().rposition(|&b| b == 7).unwrap();
+        tampered[ip] = 8;
+        assert_eq!(verify(&tampered, Some(SECRET), None, NOW).unwrap().signature_status, TSIG_SIG_INVALID);
+
+        let resp = signed(true, "hmac-sha256", &unhex("4cd974fd4b537b53dd1fa8d08467c9ec6cf45c2da674c879c898eaee6a3c21bf"));
+        assert_eq!(verify(&resp, Some(SECRET), Some(&req_mac), NOW).unwrap().signature_status, TSIG_SIG_VALID);
+        assert_eq!(verify(&resp, Some(SECRET), None, NOW).unwrap().signature_status, TSIG_SIG_INVALID);
+
+        let gss = signed(false, "gss-tsig", &[1; 16]);
+        assert_eq!(verify(&gss, Some(SECRET), None, NOW).unwrap().signature_status, TSIG_SIG_UNSUPPORTED);
+    }
+
+    #[test]
+    fn unsigned_and_malformed() {
+        let mut w = DnsWriter::new(1, 0);
+        w.question("example.com", 1, 1).unwrap();
+        let plain = w.finish();
+        assert_eq!(verify(&plain, None, None, NOW).err(), Some(-3));
+        let req = signed(false, "hmac-md5.sig-alg.reg.int", &[0; 16]);
+        assert_eq!(verify(&req[..req.len() - 3], None, None, NOW).err(), Some(-2));
+
+        let mut out = std::mem::MaybeUninit::<IrisDnsTsig>::uninit();
+        assert_eq!(iris_dns_tsig(req.as_ptr(), req.len(), std::ptr::null(), 0, std::ptr::null(), 0, out.as_mut_ptr()), 0);
+        let mut t = unsafe { out.assume_init_read() };
+        assert_eq!((t.mac_len, t.other_len, t.signature_status), (16, 0, TSIG_SIG_UNCHECKED));
+        iris_dns_tsig_free(&mut t);
+        assert_eq!(iris_dns_tsig(plain.as_ptr(), plain.len(), std::ptr::null(), 0, std::ptr::null(), 0, out.as_mut_ptr()), -3);
+    }
+}
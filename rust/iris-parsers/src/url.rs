@@ -0,0 +1,325 @@
+//! URL splitting (RFC 3986 generic syntax) for request targets: absolute-form and
+//! scheme-relative URLs, origin-form paths completed by the Host header, CONNECT's
+//! authority-form and "*". Components are borrowed; nothing is decoded or normalised.
+
+use crate::ffi::{alloc_array, free_array, free_cstr, to_cstr, IrisSlice};
+use std::ffi::c_char;
+use std::slice;
+
+#[repr(C)]
+pub struct IrisUrl {
+    pub scheme: IrisSlice,    // as written ("HTTPS" is not lowercased), empty if none
+    pub userinfo: IrisSlice,  // before '@' in the authority, empty if none
+    pub host: IrisSlice,      // IPv6 literals without brackets
+    pub port: u16,            // explicit port, else the scheme default, else 0
+    pub port_explicit: bool,
+    pub host_is_ipv6: bool,
+    pub path: IrisSlice,      // empty for "http://host" (means "/") and authority-form
+    pub query: IrisSlice,     // after '?', without it
+    pub fragment: IrisSlice,  // after '#', without it
+    pub has_query: bool,      // distinguishes "/p?" from "/p"
+    pub has_fragment: bool,
+}
+
+/// One decoded name/value pair from a query string or form body.
+#[repr(C)]
+pub struct IrisUrlParam {
+    pub name: *mut c_char,
+    pub value: *mut c_char,  // "" for both "k=" and "k"
+    pub has_value: bool,     // an '=' was present
+}
+
+#[repr(C)]
+pub struct IrisUrlParams {
+    pub params: *mut IrisUrlParam,
+    pub count: usize,
+}
+
+#[derive(Default)]
+pub struct Url<'a> {
+    pub scheme: &'a [u8],
+    pub userinfo: &'a [u8],
+    pub host: &'a [u8],
+    pub port: Option<u16>,
+    pub host_is_ipv6: bool,
+    pub path: &'a [u8],
+    pub query: Option<&'a [u8]>,
+    pub fragment: Option<&'a [u8]>,
+}
+
+impl Url<'_> {
+    /// Explicit port, else the scheme's well-known port.
+    pub fn effective_port(&self) -> u16 {
+        self.port.unwrap_or_else(|| default_port(self.scheme))
+    }
+}
+
+pub fn default_port(scheme: &[u8]) -> u16 {
+    match scheme.to_ascii_lowercase().as_slice() {
+        b"http" | b"ws" => 80,
+        b"https" | b"wss" => 443,
+        b"ftp" => 21,
+        _ => 0,
+    }
+}
+
+fn valid_scheme(s: &[u8]) -> bool {
+    s.first().is_some_and(u8::is_ascii_alphabetic)
+        && s.iter().all(|&b| b.is_ascii_alphanumeric() || matches!(b, b'+' | b'-' | b'.'))
+}
+
+/// Split "userinfo@host:port" into the authority fields of a Url.
+pub fn authority(a: &[u8]) -> Option<Url<'_>> {
+    let (userinfo, hostport) = match a.iter().rposition(|&b| b == b'@') {
+        Some(i) => (&a[..i], &a[i + 1..]),
+        None => (&a[..0], a),
+    };
+    let (host, port, ipv6) = if let Some(rest) = hostport.strip_prefix(b"[") {
+        let end = rest.iter().position(|&b| b == b']')?;
+        let host = &rest[..end];
+        if host.is_empty() || !host.iter().all(|&b| b.is_ascii_hexdigit() || matches!(b, b':' | b'.' | b'%')) { return None; }
+        match &rest[end + 1..] {
+            [] => (host, None, true),
+            [b':', port @ ..] => (host, Some(port), true),
+            _ => return None,
+        }
+    } else {
+        match hostport.iter().position(|&b| b == b':') {
+            Some(i) => (&hostport[..i], Some(&hostport[i + 1..]), false),
+            None => (hostport, None, false),
+        }
+    };
+    if host.iter().any(|&b| b <= b' ' || matches!(b, b'/' | b'?' | b'#' | b'[' | b']' | b'@' | 0x7f)) { return None; }
+    let port = match port {
+        // "host:" is allowed and means the default port
+        Some([]) | None => None,
+        Some(p) if p.len() <= 5 && p.iter().all(u8::is_ascii_digit) => {
+            Some(std::str::from_utf8(p).ok()?.parse::<u16>().ok()?)
+        }
+        Some(_) => return None,
+    };
+    Some(Url { userinfo, host, port, host_is_ipv6: ipv6, ..Default::default() })
+}
+
+/// Split "path?query#fragment" into `u`.
+fn path_query_fragment<'a>(mut rest: &'a [u8], u: &mut Url<'a>) {
+    if let Some(i) = rest.iter().position(|&b| b == b'#') {
+        u.fragment = Some(&rest[i + 1..]);
+        rest = &rest[..i];
+    }
+    if let Some(i) = rest.iter().position(|&b| b == b'?') {
+        u.query = Some(&rest[i + 1..]);
+        rest = &rest[..i];
+    }
+    u.path = rest;
+}
+
+/// Split a URL or request target. `host` (the Host header value) supplies the
+/// authority for origin-form targets and "*"; it is ignored when the target has its own.
+pub fn parse<'a>(target: &'a [u8], host: Option<&'a [u8]>) -> Option<Url<'a>> {
+    if target.is_empty() || target.iter().any(|&b| b <= b' ' || b == 0x7f) { return None; }
+    let mut u = Url::default();
+    let colon = target.iter().position(|&b| b == b':');
+    let first_special = target.iter().position(|&b| matches!(b, b'/' | b'?' | b'#'));
+    let rest = match colon {
+        Some(i) if first_special.is_none_or(|s| i < s) && valid_scheme(&target[..i]) && target[i + 1..].starts_with(b"//") => {
+            u.scheme = &target[..i];
+            &target[i + 1..]
+        }
+        _ => target,
+    };
+
+    let path_part = if let Some(after) = rest.strip_prefix(b"//") {
+        let end = after.iter().position(|&b| matches!(b, b'/' | b'?' | b'#')).unwrap_or(after.len());
+        let a = authority(&after[..end])?;
+        if a.host.is_empty() { return None; }
+        (u.userinfo, u.host, u.port, u.host_is_ipv6) = (a.userinfo, a.host, a.port, a.host_is_ipv6);
+        &after[end..]
+    } else if rest.starts_with(b"/") || rest == b"*" {
+        // Origin-form: the authority comes from Host
+        if let Some(h) = host {
+            let a = authority(h)?;
+            (u.host, u.port, u.host_is_ipv6) = (a.host, a.port, a.host_is_ipv6);
+        }
+        rest
+    } else {
+        // Authority-form (CONNECT host:port) has no path
+        let a = authority(rest)?;
+        if !a.userinfo.is_empty() || a.host.is_empty() || a.port.is_none() { return None; }
+        return Some(Url { scheme: u.scheme, ..a });
+    };
+    path_query_fragment(path_part, &mut u);
+    Some(u)
+}
+
+/// Percent-decode; malformed escapes are kept literally. With `plus`, '+' is a space
+/// (application/x-www-form-urlencoded).
+pub fn percent_decode(s: &[u8], plus: bool) -> Vec<u8> {
+    let hex = |b: u8| (b as char).to_digit(16);
+    let mut out = Vec::with_capacity(s.len());
+    let mut i = 0;
+    while i < s.len() {
+        let b = match (s[i], s.get(i + 1).and_then(|&b| hex(b)), s.get(i + 2).and_then(|&b| hex(b))) {
+            (b'%', Some(h), Some(l)) => {
+                i += 2;
+                (h * 16 + l) as u8
+            }
+            (b'+', _, _) if plus => b' ',
+            (b, _, _) => b,
+        };
+        out.push(b);
+        i += 1;
+    }
+    out
+}
+
+/// Split "k=v&k2=v2" (';' is not a separator) into decoded (name, value) pairs,
+/// skipping empty segments. Value is None when the segment has no '='.
+pub fn parse_pairs(d: &[u8], plus: bool) -> Vec<(Vec<u8>, Option<Vec<u8>>)> {
+    d.split(|&b| b == b'&').filter(|seg| !seg.is_empty()).map(|seg| {
+        match seg.iter().position(|&b| b == b'=') {
+            Some(i) => (percent_decode(&seg[..i], plus), Some(percent_decode(&seg[i + 1..], plus))),
+            None => (percent_decode(seg, plus), None),
+        }
+    }).collect()
+}
+
+pub fn alloc_params(pairs: Vec<(Vec<u8>, Option<Vec<u8>>)>) -> IrisUrlParams {
+    let params: Vec<IrisUrlParam> = pairs.into_iter().map(|(n, v)| IrisUrlParam {
+        name: to_cstr(&String::from_utf8_lossy(&n)),
+        value: to_cstr(&String::from_utf8_lossy(v.as_deref().unwrap_or(b""))),
+        has_value: v.is_some(),
+    }).collect();
+    let (params, count) = alloc_array(params);
+    IrisUrlParams { params, count }
+}
+
+// --- FFI entry points ---
+
+/// Split a URL or HTTP request target (e.g. IrisHttpRequest.path). For origin-form
+/// targets ("/a?b"), pass the Host header value as `host` (may be NULL) so host and
+/// port are filled in; `host` is ignored if the target carries an authority.
+/// Slices in `out` point into `data` or `host` — keep them alive.
+/// Returns 0=ok, -2=arg error or malformed URL.
+#[no_mangle]
+pub extern "C" fn iris_url_parse(
+    data: *const u8,
+    len: usize,
+    host: *const u8,
+    host_len: usize,
+    out: *mut IrisUrl,
+) -> i32 {
+    if data.is_null() || out.is_null() { return -2; }
+    let target = unsafe { slice::from_raw_parts(data, len) };
+    let host = if host.is_null() { None } else { Some(unsafe { slice::from_raw_parts(host, host_len) }) };
+    let Some(u) = parse(target, host) else { return -2 };
+    let empty = &target[..0];
+    unsafe {
+        out.write(IrisUrl {
+            scheme: IrisSlice::from_bytes(u.scheme),
+            userinfo: IrisSlice::from_bytes(u.userinfo),
+            host: IrisSlice::from_bytes(u.host),
+            port: u.effective_port(),
+            port_explicit: u.port.is_some(),
+            host_is_ipv6: u.host_is_ipv6,
+            path: IrisSlice::from_bytes(u.path),
+            query: IrisSlice::from_bytes(u.query.unwrap_or(empty)),
+            fragment: IrisSlice::from_bytes(u.fragment.unwrap_or(empty)),
+            has_query: u.query.is_some(),
+            has_fragment: u.fragment.is_some(),
+        });
+    }
+    0
+}
+
+/// Decode a query string (IrisUrl.query, without the '?') into name/value pairs.
+/// '+' is kept as is; use iris_http_parse_urlencoded_body for form bodies.
+/// Invalid UTF-8 is replaced. Returns 0=ok, -2=arg error. Free with iris_url_params_free.
+#[no_mangle]
+pub extern "C" fn iris_url_parse_query(data: *const u8, len: usize, out: *mut IrisUrlParams) -> i32 {
+    if out.is_null() || (data.is_null() && len != 0) { return -2; }
+    let d = if len == 0 { &[][..] } else { unsafe { slice::from_raw_parts(data, len) } };
+    unsafe { out.write(alloc_params(parse_pairs(d, false))); }
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn iris_url_params_free(params: *mut IrisUrlParams) {
+    if params.is_null() { return; }
+    let p = unsafe { &*params };
+    for i in 0..p.count {
+        let e = unsafe { &*p.params.add(i) };
+        free_cstr(e.name);
+        free_cstr(e.value);
+    }
+    free_array(p.params, p.count);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn s(x: &IrisSlice) -> &str {
+        if x.len == 0 { return ""; }
+        std::str::from_utf8(unsafe { slice::from_raw_parts(x.ptr, x.len) }).unwrap()
+    }
+
+    #[test]
+    fn absolute_and_ipv6() {
+        let url = b"https://user:pw@[2001:db8::1]:8443/a/b?x=1&y=2#frag";
+        let mut out = std::mem::MaybeUninit::<IrisUrl>::uninit();
+        assert_eq!(iris_url_parse(url.as_ptr(), url.len(), std::ptr::null(), 0, out.as_mut_ptr()), 0);
+        let u = unsafe { out.assume_init() };
+        assert_eq!((s(&u.scheme), s(&u.userinfo), s(&u.host)), ("https", "user:pw", "2001:db8::1"));
+        assert_eq!((u.port, u.port_explicit, u.host_is_ipv6), (8443, true, true));
+        assert_eq!((s(&u.path), s(&u.query), s(&u.fragment)), ("/a/b", "x=1&y=2", "frag"));
+
+        let u = parse(b"HTTP://Example.com", None).unwrap();
+        assert_eq!((u.host, u.effective_port(), u.path, u.query), (&b"Example.com"[..], 80, &b""[..], None));
+        let u = parse(b"//cdn.example.net:/lib.js?", None).unwrap();
+        assert_eq!((u.scheme, u.port, u.query), (&b""[..], None, Some(&b""[..])));
+        for bad in [&b"http://[::1/"[..], b"http://h:99999/", b"http://h:8o/", b"http:///x", b"http://a b/", b"", b"rel/path"] {
+            assert!(parse(bad, None).is_none(), "{:?}", String::from_utf8_lossy(bad));
+        }
+    }
+
+    #[test]
+    fn origin_and_authority_form() {
+        let u = parse(b"/search?q=iris#top", Some(b"[::1]:8080")).unwrap();
+        assert_eq!((u.host, u.port, u.host_is_ipv6), (&b"::1"[..], Some(8080), true));
+        assert_eq!((u.path, u.query, u.fragment), (&b"/search"[..], Some(&b"q=iris"[..]), Some(&b"top"[..])));
+        let u = parse(b"/", None).unwrap();
+        assert_eq!((u.host, u.effective_port()), (&b""[..], 0));
+        assert!(parse(b"/", Some(b"bad host")).is_none());
+        let u = parse(b"*", Some(b"example.com")).unwrap();
+        assert_eq!((u.host, u.path), (&b"example.com"[..], &b"*"[..]));
+
+        let u = parse(b"example.com:443", None).unwrap();
+        assert_eq!((u.scheme, u.host, u.port, u.path), (&b""[..], &b"example.com"[..], Some(443), &b""[..]));
+        // authority-form requires a port
+        assert!(parse(b"example.com", None).is_none());
+    }
+
+    #[test]
+    fn query_pairs() {
+        assert_eq!(percent_decode(b"a%20b+c%zz%4", false), b"a b+c%zz%4");
+        assert_eq!(percent_decode(b"a+b%2B", true), b"a b+");
+        let q = b"q=caf%C3%A9&&tag=a&tag=b&flag&=x&bad=%FF";
+        let mut out = std::mem::MaybeUninit::<IrisUrlParams>::uninit();
+        assert_eq!(iris_url_parse_query(q.as_ptr(), q.len(), out.as_mut_ptr()), 0);
+        let mut p = unsafe { out.assume_init() };
+        let pairs: Vec<(String, String, bool)> = (0..p.count).map(|i| {
+            let e = unsafe { &*p.params.add(i) };
+            let s = |c| unsafe { std::ffi::CStr::from_ptr(c) }.to_string_lossy().into_owned();
+            (s(e.name), s(e.value), e.has_value)
+        }).collect();
+        assert_eq!(pairs.len(), 6);
+        assert_eq!(pairs[0], ("q".to_string(), "café".to_string(), true));
+        assert_eq!((pairs[3].2, pairs[4].0.as_str(), pairs[5].1.as_str()), (false, "", "\u{fffd}"));
+        iris_url_params_free(&mut p);
+
+        let mut out = std::mem::MaybeUninit::<IrisUrlParams>::uninit();
+        assert_eq!(iris_url_parse_query(std::ptr::null(), 0, out.as_mut_ptr()), 0);
+        assert_eq!(unsafe { out.assume_init() }.count, 0);
+    }
+}
@@ -0,0 +1,223 @@
+//! User-Agent (RFC 9110 §10.1.5) product tokens and a coarse classification of the
+//! client — browser, command-line tool, HTTP library/SDK or bot — so traffic can
+//! be grouped by the software that sent it. Table-driven, no regexes.
+
+use crate::ffi::{free_cstr, to_cstr};
+use crate::url::{self, IrisUrlParams};
+use std::ffi::c_char;
+use std::slice;
+
+pub const UA_UNKNOWN: u8 = 0;
+pub const UA_BROWSER: u8 = 1;
+pub const UA_CLI: u8 = 2;
+pub const UA_SDK: u8 = 3;
+pub const UA_BOT: u8 = 4; // crawlers, monitors and scanners
+
+#[repr(C)]
+pub struct IrisUserAgent {
+    pub category: u8,             // UA_*
+    pub name: *mut c_char,        // "Chrome", "curl", "python-requests", "Googlebot"
+    pub version: *mut c_char,     // "" if none
+    pub products: IrisUrlParams,  // product tokens in order: name -> version
+}
+
+pub struct UserAgent {
+    pub category: u8,
+    pub name: String,
+    pub version: String,
+    pub products: Vec<(String, Option<String>)>,
+    pub comments: Vec<String>,
+}
+
+/// Browsers by the product token that identifies them, most specific first;
+/// Chrome-based browsers also send Chrome/ and Safari/.
+const BROWSERS: &[(&str, &str)] = &[
+    ("Edg", "Edge"), ("EdgA", "Edge"), ("EdgiOS", "Edge"), ("OPR", "Opera"),
+    ("SamsungBrowser", "Samsung Internet"), ("YaBrowser", "Yandex Browser"), ("Vivaldi", "Vivaldi"),
+    ("Firefox", "Firefox"), ("FxiOS", "Firefox"), ("CriOS", "Chrome"), ("Chromium", "Chromium"),
+    ("Chrome", "Chrome"), ("Version", "Safari"),
+];
+
+/// Product names (case-insensitive prefix match) of command-line tools.
+const CLI: &[&str] = &["curl", "wget", "httpie", "aria2", "windowspowershell", "powershell", "lwp-request", "xh"];
+
+/// Product names (case-insensitive prefix match) of HTTP libraries and SDKs.
+const SDK: &[&str] = &[
+    "python-requests", "python-urllib", "python-httpx", "aiohttp", "urllib3", "go-http-client",
+    "okhttp", "java", "apache-httpclient", "axios", "node-fetch", "undici", "node", "libcurl",
+    "dalvik", "cfnetwork", "ruby", "faraday", "reqwest", "dart", "grpc-", "aws-sdk-", "boto3",
+    "botocore", "google-api-", "azsdk-", "postmanruntime", "restsharp", "guzzlehttp", "php",
+];
+
+/// Substrings (lowercase) that mark automated clients.
+const BOT_MARKERS: &[&str] = &[
+    "bot", "crawler", "spider", "slurp", "facebookexternalhit", "headlesschrome", "scan",
+    "sqlmap", "nikto", "nmap", "masscan", "zgrab", "nuclei", "gobuster", "dirbuster", "wpscan",
+];
+
+/// Split into product tokens and (possibly nested) comments.
+fn tokenize(s: &str) -> (Vec<(String, Option<String>)>, Vec<String>) {
+    let (mut products, mut comments) = (Vec::new(), Vec::new());
+    let mut chars = s.char_indices().peekable();
+    while let Some(&(i, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '(' {
+            let mut depth = 0;
+            let mut end = s.len();
+            for (j, c) in chars.by_ref() {
+                match c {
+                    '(' => depth += 1,
+                    ')' => { depth -= 1; if depth == 0 { end = j; break; } }
+                    _ => {}
+                }
+            }
+            comments.push(s[i + 1..end].trim().to_string());
+        } else {
+            let mut end = s.len();
+            while let Some(&(j, c)) = chars.peek() {
+                if c.is_whitespace() || c == '(' { end = j; break; }
+                chars.next();
+            }
+            let tok = &s[i..end];
+            products.push(match tok.split_once('/') {
+                Some((n, v)) => (n.to_string(), Some(v.to_string())),
+                None => (tok.to_string(), None),
+            });
+        }
+    }
+    (products, comments)
+}
+
+fn prefixed(name: &str, table: &[&str]) -> bool {
+    let lower = name.to_ascii_lowercase();
+    table.iter().any(|p| lower.starts_with(p))
+}
+
+/// Name and version of a bot, from a product token or a "compatible; Name/1.0; +url"
+/// comment.
+fn bot(ua: &UserAgent) -> Option<(String, String)> {
+    let is_bot = |s: &str| { let l = s.to_ascii_lowercase(); BOT_MARKERS.iter().any(|m| l.contains(m)) };
+    let from_comments = ua.comments.iter().flat_map(|c| c.split(';')).map(str::trim)
+        .filter(|part| !part.starts_with('+') && is_bot(part))
+        .map(|part| match part.split_once('/') {
+            Some((n, v)) => (n.trim().to_string(), v.trim().to_string()),
+            None => (part.to_string(), String::new()),
+        });
+    let from_products = ua.products.iter().filter(|(n, _)| is_bot(n))
+        .map(|(n, v)| (n.clone(), v.clone().unwrap_or_default()));
+    from_products.chain(from_comments).next()
+}
+
+/// Tokenize and classify a User-Agent value.
+pub fn classify(s: &str) -> UserAgent {
+    let (products, comments) = tokenize(s);
+    let mut ua = UserAgent { category: UA_UNKNOWN, name: String::new(), version: String::new(), products, comments };
+    let product = |name: &str| ua.products.iter().find(|(n, _)| n == name).map(|(_, v)| v.clone().unwrap_or_default());
+
+    let found = if let Some((name, version)) = bot(&ua) {
+        Some((UA_BOT, name, version))
+    } else if ua.products.first().is_some_and(|(n, _)| n == "Mozilla") {
+        let browser = BROWSERS.iter().find_map(|(token, name)| product(token).map(|v| (name.to_string(), v)));
+        // IE 11 dropped "MSIE" and is known by Trident/ plus rv: in the comment
+        let trident = ua.comments.iter().any(|c| c.contains("Trident/"));
+        let msie = ua.comments.iter().flat_map(|c| c.split(';')).map(str::trim)
+            .find_map(|p| p.strip_prefix("MSIE ").or_else(|| p.strip_prefix("rv:").filter(|_| trident)));
+        match (browser, msie) {
+            (Some((name, v)), _) if name != "Safari" || product("Safari").is_some() => Some((UA_BROWSER, name, v)),
+            (_, Some(v)) => Some((UA_BROWSER, "Internet Explorer".into(), v.to_string())),
+            _ => None,
+        }
+    } else {
+        None
+    };
+    let found = found.or_else(|| {
+        let (n, v) = ua.products.first()?;
+        let category = if prefixed(n, CLI) { UA_CLI } else if prefixed(n, SDK) { UA_SDK } else { UA_UNKNOWN };
+        Some((category, n.clone(), v.clone().unwrap_or_default()))
+    });
+    if let Some((category, name, version)) = found {
+        (ua.category, ua.name, ua.version) = (category, name, version);
+    }
+    ua
+}
+
+// --- FFI ---
+
+/// Classify a User-Agent header value. Returns 0=ok, -2=arg error.
+/// Free with iris_user_agent_free.
+#[no_mangle]
+pub extern "C" fn iris_user_agent_parse(data: *const u8, len: usize, out: *mut IrisUserAgent) -> i32 {
+    if data.is_null() || out.is_null() { return -2; }
+    let s = String::from_utf8_lossy(unsafe { slice::from_raw_parts(data, len) });
+    let ua = classify(&s);
+    let products = ua.products.into_iter().map(|(n, v)| (n.into_bytes(), v.map(String::into_bytes))).collect();
+    unsafe {
+        out.write(IrisUserAgent {
+            category: ua.category,
+            name: to_cstr(&ua.name),
+            version: to_cstr(&ua.version),
+            products: url::alloc_params(products),
+        });
+    }
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn iris_user_agent_free(ua: *mut IrisUserAgent) {
+    if ua.is_null() { return; }
+    let ua = unsafe { &mut *ua };
+    free_cstr(ua.name);
+    free_cstr(ua.version);
+    url::iris_url_params_free(&mut ua.products);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(s: &str, category: u8, name: &str, version: &str) {
+        let ua = classify(s);
+        assert_eq!((ua.category, ua.name.as_str(), ua.version.as_str()), (category, name, version), "{}", s);
+    }
+
+    #[test]
+    fn browsers() {
+        check("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36",
+              UA_BROWSER, "Chrome", "120.0.0.0");
+        check("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36 Edg/120.0.2210.91",
+              UA_BROWSER, "Edge", "120.0.2210.91");
+        check("Mozilla/5.0 (Macintosh; Intel Mac OS X 14_2) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.2 Safari/605.1.15",
+              UA_BROWSER, "Safari", "17.2");
+        check("Mozilla/5.0 (X11; Linux x86_64; rv:121.0) Gecko/20100101 Firefox/121.0", UA_BROWSER, "Firefox", "121.0");
+        check("Mozilla/5.0 (Windows NT 6.1; Trident/7.0; rv:11.0) like Gecko", UA_BROWSER, "Internet Explorer", "11.0");
+    }
+
+    #[test]
+    fn tools_sdks_and_bots() {
+        check("curl/8.4.0", UA_CLI, "curl", "8.4.0");
+        check("Wget/1.21.4", UA_CLI, "Wget", "1.21.4");
+        check("python-requests/2.31.0", UA_SDK, "python-requests", "2.31.0");
+        check("Go-http-client/1.1", UA_SDK, "Go-http-client", "1.1");
+        check("aws-sdk-go-v2/1.24.0 os/macos lang/go#1.21.5", UA_SDK, "aws-sdk-go-v2", "1.24.0");
+        check("Mozilla/5.0 (compatible; Googlebot/2.1; +http://www.google.com/bot.html)", UA_BOT, "Googlebot", "2.1");
+        check("Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) HeadlessChrome/119.0.0.0 Safari/537.36",
+              UA_BOT, "HeadlessChrome", "119.0.0.0");
+        check("sqlmap/1.7.2#stable (https://sqlmap.org)", UA_BOT, "sqlmap", "1.7.2#stable");
+        check("MyApp", UA_UNKNOWN, "MyApp", "");
+        check("", UA_UNKNOWN, "", "");
+    }
+
+    #[test]
+    fn ffi_products() {
+        let s = b"Mozilla/5.0 (a (nested) b) Gecko Firefox/1.0";
+        let mut out = std::mem::MaybeUninit::<IrisUserAgent>::uninit();
+        assert_eq!(iris_user_agent_parse(s.as_ptr(), s.len(), out.as_mut_ptr()), 0);
+        let mut ua = unsafe { out.assume_init() };
+        assert_eq!(ua.category, UA_BROWSER);
+        assert_eq!(ua.products.count, 3);
+        let gecko = unsafe { &*ua.products.params.add(1) };
+        assert!(!gecko.has_value);
+        iris_user_agent_free(&mut ua);
+    }
+}
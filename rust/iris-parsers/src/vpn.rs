@@ -0,0 +1,285 @@
+//! VPN handshake detection from the first packets of a flow: WireGuard message
+//! types, OpenVPN control opcodes (UDP and TCP framing), and IKEv1/IKEv2 headers.
+
+use crate::bytes::{be16, be32, be64, le32};
+
+pub const VPN_PROTO_WIREGUARD: u8 = 1;
+pub const VPN_PROTO_OPENVPN: u8 = 2;
+pub const VPN_PROTO_IKEV2: u8 = 3;
+pub const VPN_PROTO_IKEV1: u8 = 4;
+
+pub const VPN_ROLE_UNKNOWN: u8 = 0;
+pub const VPN_ROLE_INITIATOR: u8 = 1;
+pub const VPN_ROLE_RESPONDER: u8 = 2;
+
+const WG_INITIATION_LEN: usize = 148;
+const WG_RESPONSE_LEN: usize = 92;
+const WG_COOKIE_LEN: usize = 64;
+const WG_TRANSPORT_MIN: usize = 32;
+
+const OVPN_HARD_RESET_CLIENT_V1: u8 = 1;
+const OVPN_HARD_RESET_SERVER_V1: u8 = 2;
+const OVPN_CONTROL_V1: u8 = 4;
+const OVPN_ACK_V1: u8 = 5;
+const OVPN_HARD_RESET_CLIENT_V2: u8 = 7;
+const OVPN_HARD_RESET_SERVER_V2: u8 = 8;
+const OVPN_HARD_RESET_CLIENT_V3: u8 = 10;
+const OVPN_CONTROL_WKC_V1: u8 = 11;
+
+const IKE_HEADER_LEN: usize = 28;
+const IKE_FLAG_INITIATOR: u8 = 0x08;
+const IKEV2_PAYLOAD_SA: u8 = 33;
+const IKEV2_PAYLOAD_KE: u8 = 34;
+
+#[repr(C)]
+#[derive(Default)]
+pub struct IrisVpnInfo {
+    pub protocol: u8,          // VPN_PROTO_*
+    pub role: u8,              // VPN_ROLE_*
+    pub message_type: u8,      // WG type, OpenVPN opcode, or IKE exchange type
+    pub handshake: bool,       // session-establishing message (not data/keepalive)
+    pub sender_index: u32,     // WireGuard
+    pub receiver_index: u32,   // WireGuard
+    pub key_id: u8,            // OpenVPN
+    pub session_id: u64,       // OpenVPN local session / IKE initiator SPI
+    pub peer_session_id: u64,  // IKE responder SPI
+    pub plain_control: bool,   // OpenVPN control packet without tls-auth/tls-crypt wrapping
+    pub ike_version: u8,       // major << 4 | minor
+    pub nat_t: bool,           // IKE behind the UDP/4500 non-ESP marker
+    pub ike_encr: u16,         // first transform of each type in the first SA proposal
+    pub ike_prf: u16,
+    pub ike_integ: u16,
+    pub ike_dh_group: u16,     // KE payload group, else SA proposal
+}
+
+fn wireguard(d: &[u8]) -> Option<IrisVpnInfo> {
+    if d.len() < 4 || d[1..4] != [0, 0, 0] { return None; }
+    let mut v = IrisVpnInfo { protocol: VPN_PROTO_WIREGUARD, message_type: d[0], ..Default::default() };
+    match (d[0], d.len()) {
+        (1, WG_INITIATION_LEN) => {
+            v.role = VPN_ROLE_INITIATOR;
+            v.handshake = true;
+            v.sender_index = le32(d, 4)?;
+        }
+        (2, WG_RESPONSE_LEN) => {
+            v.role = VPN_ROLE_RESPONDER;
+            v.handshake = true;
+            v.sender_index = le32(d, 4)?;
+            v.receiver_index = le32(d, 8)?;
+        }
+        (3, WG_COOKIE_LEN) => {
+            v.role = VPN_ROLE_RESPONDER;
+            v.receiver_index = le32(d, 4)?;
+        }
+        (4, n) if n >= WG_TRANSPORT_MIN && n % 16 == 0 => v.receiver_index = le32(d, 4)?,
+        _ => return None,
+    }
+    Some(v)
+}
+
+/// OpenVPN over UDP carries no framing, so only hard-reset opcodes (the first packet of a
+/// session) are accepted there; TCP's 2-byte length prefix allows any control opcode.
+fn openvpn(d: &[u8], is_tcp: bool) -> Option<IrisVpnInfo> {
+    let p = if is_tcp {
+        if be16(d, 0)? as usize != d.len() - 2 { return None; }
+        &d[2..]
+    } else {
+        d
+    };
+    if p.len() < 14 { return None; }
+    let opcode = p[0] >> 3;
+    let key_id = p[0] & 0x07;
+    let reset = matches!(opcode, OVPN_HARD_RESET_CLIENT_V1 | OVPN_HARD_RESET_SERVER_V1
+        | OVPN_HARD_RESET_CLIENT_V2 | OVPN_HARD_RESET_SERVER_V2 | OVPN_HARD_RESET_CLIENT_V3);
+    let control = reset || matches!(opcode, 3 | OVPN_CONTROL_V1 | OVPN_ACK_V1 | OVPN_CONTROL_WKC_V1);
+    if !control || (!is_tcp && !reset) || (reset && key_id != 0) { return None; }
+    let role = match opcode {
+        OVPN_HARD_RESET_CLIENT_V1 | OVPN_HARD_RESET_CLIENT_V2 | OVPN_HARD_RESET_CLIENT_V3 => VPN_ROLE_INITIATOR,
+        OVPN_HARD_RESET_SERVER_V1 | OVPN_HARD_RESET_SERVER_V2 => VPN_ROLE_RESPONDER,
+        _ => VPN_ROLE_UNKNOWN,
+    };
+    // Unwrapped: session_id(8) ack_len(1) [acks + remote session] packet_id(4); a
+    // client reset has no acks and packet_id 0.
+    let plain_control = opcode != OVPN_HARD_RESET_CLIENT_V3 && match p[9] {
+        0 => p.len() >= 14 && (role != VPN_ROLE_INITIATOR || be32(p, 10) == Some(0)),
+        n @ 1..=8 => p.len() >= 10 + 4 * n as usize + 8,
+        _ => false,
+    };
+    Some(IrisVpnInfo {
+        protocol: VPN_PROTO_OPENVPN, role, message_type: opcode, handshake: reset, key_id,
+        session_id: be64(p, 1)?, plain_control, ..Default::default()
+    })
+}
+
+fn ike_transforms(sa: &[u8], v: &mut IrisVpnInfo) {
+    // First proposal: last(1) rsv(1) len(2) num(1) proto(1) spi_size(1) n_transforms(1) spi
+    if sa.len() < 8 { return; }
+    let prop_len = (be16(sa, 2).unwrap_or(0) as usize).min(sa.len());
+    let mut off = 8 + sa[6] as usize;
+    for _ in 0..sa[7] {
+        if off + 8 > prop_len { return; }
+        let (Some(t_len), Some(id)) = (be16(sa, off + 2), be16(sa, off + 6)) else { return };
+        let t_len = t_len as usize;
+        let slot = match sa[off + 4] {
+            1 => &mut v.ike_encr,
+            2 => &mut v.ike_prf,
+            3 => &mut v.ike_integ,
+            4 => &mut v.ike_dh_group,
+            _ => { off += t_len.max(8); continue; }
+        };
+        if *slot == 0 { *slot = id; }
+        off += t_len.max(8);
+    }
+}
+
+fn ike(d: &[u8]) -> Option<IrisVpnInfo> {
+    let (d, nat_t) = if d.len() > 4 && d[..4] == [0, 0, 0, 0] { (&d[4..], true) } else { (d, false) };
+    if d.len() < IKE_HEADER_LEN || be32(d, 24)? as usize != d.len() { return None; }
+    let version = d[17];
+    let flags = d[19];
+    let init_spi = be64(d, 0)?;
+    if init_spi == 0 { return None; }
+    let mut v = IrisVpnInfo {
+        message_type: d[18], session_id: init_spi, peer_session_id: be64(d, 8)?,
+        ike_version: version, nat_t, ..Default::default()
+    };
+    match version >> 4 {
+        2 => {
+            if !(34..=37).contains(&d[18]) { return None; }
+            v.protocol = VPN_PROTO_IKEV2;
+            // Roles follow the original initiator bit, not request/response
+            v.role = if flags & IKE_FLAG_INITIATOR != 0 { VPN_ROLE_INITIATOR } else { VPN_ROLE_RESPONDER };
+            v.handshake = d[18] == 34; // IKE_SA_INIT
+            if v.handshake {
+                let mut next = d[16];
+                let mut off = IKE_HEADER_LEN;
+                while next != 0 {
+                    let Some(plen) = be16(d, off + 2) else { break };
+                    let plen = plen as usize;
+                    if plen < 4 || off + plen > d.len() { break; }
+                    let body = &d[off + 4..off + plen];
+                    match next {
+                        IKEV2_PAYLOAD_SA => ike_transforms(body, &mut v),
+                        IKEV2_PAYLOAD_KE => v.ike_dh_group = be16(body, 0).unwrap_or(v.ike_dh_group),
+                        _ => {}
+                    }
+                    next = d[off];
+                    off += plen;
+                }
+            }
+        }
+        1 => {
+            if !matches!(d[18], 2 | 4 | 5 | 32) { return None; }
+            v.protocol = VPN_PROTO_IKEV1;
+            v.role = if v.peer_session_id == 0 { VPN_ROLE_INITIATOR } else { VPN_ROLE_UNKNOWN };
+            v.handshake = matches!(d[18], 2 | 4); // main / aggressive mode
+        }
+        _ => return None,
+    }
+    Some(v)
+}
+
+pub fn detect(buf: &[u8], is_tcp: bool) -> Option<IrisVpnInfo> {
+    if is_tcp { return openvpn(buf, true); }
+    wireguard(buf).or_else(|| ike(buf)).or_else(|| openvpn(buf, false))
+}
+
+// --- FFI entry points ---
+
+/// Classify the first payload of a UDP or TCP flow as a VPN handshake. Ports are not
+/// consulted, so tunnels on non-standard ports are still recognised.
+/// Returns 0=detected, -2=arg error, -3=no VPN protocol recognised.
+#[no_mangle]
+pub extern "C" fn iris_vpn_detect(
+    data: *const u8, len: usize, is_tcp: bool, out: *mut IrisVpnInfo,
+) -> i32 {
+    if data.is_null() || out.is_null() || len == 0 { return -2; }
+    let buf = unsafe { std::slice::from_raw_parts(data, len) };
+    match detect(buf, is_tcp) {
+        Some(v) => { unsafe { out.write(v); } 0 }
+        None => -3,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detect(d: &[u8], tcp: bool) -> Option<IrisVpnInfo> {
+        let mut out = std::mem::MaybeUninit::<IrisVpnInfo>::uninit();
+        match iris_vpn_detect(d.as_ptr(), d.len(), tcp, out.as_mut_ptr()) {
+            0 => Some(unsafe { out.assume_init() }),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn wireguard_initiation() {
+        let mut p = vec![0u8; WG_INITIATION_LEN];
+        p[0] = 1;
+        p[4..8].copy_from_slice(&0xdeadbeefu32.to_le_bytes());
+        let v = detect(&p, false).unwrap();
+        assert_eq!((v.protocol, v.role, v.sender_index), (VPN_PROTO_WIREGUARD, VPN_ROLE_INITIATOR, 0xdeadbeef));
+        assert!(v.handshake);
+        p.push(0);
+        assert!(detect(&p, false).is_none());
+    }
+
+    #[test]
+    fn openvpn_client_reset_udp_and_tcp() {
+        let mut p = vec![OVPN_HARD_RESET_CLIENT_V2 << 3];
+        p.extend_from_slice(&0x0102030405060708u64.to_be_bytes());
+        p.extend_from_slice(&[0, 0, 0, 0, 0]);
+        let v = detect(&p, false).unwrap();
+        assert_eq!((v.protocol, v.role, v.session_id), (VPN_PROTO_OPENVPN, VPN_ROLE_INITIATOR, 0x0102030405060708));
+        assert!(v.plain_control);
+
+        let mut framed = (p.len() as u16).to_be_bytes().to_vec();
+        framed.extend_from_slice(&p);
+        assert_eq!(detect(&framed, true).unwrap().message_type, OVPN_HARD_RESET_CLIENT_V2);
+        framed[1] += 1;
+        assert!(detect(&framed, true).is_none());
+    }
+
+    #[test]
+    fn ikev2_sa_init_with_proposal() {
+        // SA: one proposal (IKE, no SPI) with ENCR_AES_CBC, PRF_HMAC_SHA2_256, AUTH_HMAC_SHA2_256_128, DH 14
+        let transforms: Vec<u8> = [(1u8, 12u16), (2, 5), (3, 12), (4, 14)].iter().enumerate()
+            .flat_map(|(i, &(t, id))| {
+                let last = if i == 3 { 0 } else { 3 };
+                let mut x = vec![last, 0, 0, 8, t, 0];
+                x.extend_from_slice(&id.to_be_bytes());
+                x
+            }).collect();
+        let mut prop = vec![0, 0, 0, 0, 1, 1, 0, 4];
+        prop.extend_from_slice(&transforms);
+        let pl = prop.len() as u16;
+        prop[2..4].copy_from_slice(&pl.to_be_bytes());
+        let mut sa = vec![IKEV2_PAYLOAD_KE, 0, 0, 0];
+        sa.extend_from_slice(&prop);
+        let sl = sa.len() as u16;
+        sa[2..4].copy_from_slice(&sl.to_be_bytes());
+        let ke = vec![0, 0, 0, 8, 0, 19, 0, 0];
+
+        let mut pkt = vec![0u8; IKE_HEADER_LEN];
+        pkt[..8].copy_from_slice(&0x1122334455667788u64.to_be_bytes());
+        pkt[16] = IKEV2_PAYLOAD_SA;
+        pkt[17] = 0x20;
+        pkt[18] = 34;
+        pkt[19] = IKE_FLAG_INITIATOR;
+        pkt.extend_from_slice(&sa);
+        pkt.extend_from_slice(&ke);
+        let total = pkt.len() as u32;
+        pkt[24..28].copy_from_slice(&total.to_be_bytes());
+
+        let v = detect(&pkt, false).unwrap();
+        assert_eq!((v.protocol, v.role, v.ike_version), (VPN_PROTO_IKEV2, VPN_ROLE_INITIATOR, 0x20));
+        assert_eq!((v.ike_encr, v.ike_prf, v.ike_integ), (12, 5, 12));
+        assert_eq!(v.ike_dh_group, 19); // KE payload wins over the proposal's first group
+        assert_eq!(v.session_id, 0x1122334455667788);
+
+        let mut natt = vec![0, 0, 0, 0];
+        natt.extend_from_slice(&pkt);
+        assert!(detect(&natt, false).unwrap().nat_t);
+    }
+}
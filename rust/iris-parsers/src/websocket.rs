@@ -0,0 +1,219 @@
+//! WebSocket (RFC 6455): spotting the HTTP/1.1 upgrade handshake in parsed
+//! requests and responses, then decoding the frames that follow the 101 switch.
+
+use crate::ffi::{alloc_bytes, iris_free_bytes, IrisSlice};
+use crate::hash::sha1_digest;
+use crate::http::{find_header, find_headers, header_array, IrisHttpHeader, IrisHttpRequest, IrisHttpResponse};
+use std::slice;
+
+const ACCEPT_GUID: &[u8] = b"258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+pub const WS_OP_CONTINUATION: u8 = 0x0;
+pub const WS_OP_TEXT: u8 = 0x1;
+pub const WS_OP_BINARY: u8 = 0x2;
+pub const WS_OP_CLOSE: u8 = 0x8;
+pub const WS_OP_PING: u8 = 0x9;
+pub const WS_OP_PONG: u8 = 0xA;
+
+#[repr(C)]
+pub struct IrisWsFrame {
+    pub fin: bool,
+    pub rsv: u8,              // RSV1-3 as bits 2..0; RSV1 = permessage-deflate compressed
+    pub opcode: u8,           // WS_OP_*
+    pub masked: bool,         // set on every client-to-server frame
+    pub mask_key: [u8; 4],
+    pub header_len: usize,
+    pub frame_len: usize,     // header + payload: bytes to consume
+    pub payload: *mut u8,     // unmasked copy, NULL if empty
+    pub payload_len: usize,
+    pub close_code: u16,      // close frames: status code, 0 if absent
+}
+
+pub struct Frame {
+    pub fin: bool,
+    pub rsv: u8,
+    pub opcode: u8,
+    pub mask_key: Option<[u8; 4]>,
+    pub header_len: usize,
+    pub payload: Vec<u8>,
+}
+
+fn bytes(s: &IrisSlice) -> &[u8] {
+    if s.ptr.is_null() || s.len == 0 { return &[]; }
+    unsafe { slice::from_raw_parts(s.ptr, s.len) }
+}
+
+/// True if the comma-separated header value lists `token` (case-insensitive).
+fn has_token(hs: &[IrisHttpHeader], name: &str, token: &str) -> bool {
+    find_headers(hs, name.as_bytes())
+        .flat_map(|v| v.split(|&b| b == b','))
+        .any(|t| t.trim_ascii().eq_ignore_ascii_case(token.as_bytes()))
+}
+
+fn is_upgrade(hs: &[IrisHttpHeader]) -> bool {
+    has_token(hs, "upgrade", "websocket") && has_token(hs, "connection", "upgrade")
+}
+
+/// Sec-WebSocket-Accept for a Sec-WebSocket-Key: SHA-1 of key + GUID.
+pub fn accept_digest(key: &[u8]) -> [u8; 20] {
+    sha1_digest(&[key.trim_ascii(), ACCEPT_GUID].concat())
+}
+
+/// Decode one frame from the start of `d`. Err(-1) incomplete, Err(-2) malformed.
+pub fn parse_frame(d: &[u8]) -> Result<Frame, i32> {
+    if d.len() < 2 { return Err(-1); }
+    let (b0, b1) = (d[0], d[1]);
+    let (fin, rsv, opcode) = (b0 & 0x80 != 0, (b0 >> 4) & 7, b0 & 0x0f);
+    if !matches!(opcode, WS_OP_CONTINUATION | WS_OP_TEXT | WS_OP_BINARY | WS_OP_CLOSE | WS_OP_PING | WS_OP_PONG) {
+        return Err(-2);
+    }
+    let (len, mut p) = match b1 & 0x7f {
+        126 => (u16::from_be_bytes(d.get(2..4).ok_or(-1)?.try_into().unwrap()) as u64, 4),
+        127 => (u64::from_be_bytes(d.get(2..10).ok_or(-1)?.try_into().unwrap()), 10),
+        n => (n as u64, 2),
+    };
+    if len >> 63 != 0 { return Err(-2); }
+    // Control frames are never fragmented and carry at most 125 bytes
+    if opcode >= WS_OP_CLOSE && (!fin || len > 125) { return Err(-2); }
+    let mask_key = if b1 & 0x80 != 0 {
+        let k: [u8; 4] = d.get(p..p + 4).ok_or(-1)?.try_into().unwrap();
+        p += 4;
+        Some(k)
+    } else {
+        None
+    };
+    let len = usize::try_from(len).map_err(|_| -2)?;
+    let mut payload = d.get(p..).and_then(|r| r.get(..len)).ok_or(-1)?.to_vec();
+    if let Some(k) = mask_key {
+        payload.iter_mut().enumerate().for_each(|(i, b)| *b ^= k[i % 4]);
+    }
+    Ok(Frame { fin, rsv, opcode, mask_key, header_len: p, payload })
+}
+
+// --- FFI entry points ---
+
+/// True if a parsed request is a WebSocket opening handshake (GET with
+/// "Upgrade: websocket", "Connection: upgrade" and a Sec-WebSocket-Key).
+#[no_mangle]
+pub extern "C" fn iris_ws_is_upgrade_request(req: *const IrisHttpRequest) -> bool {
+    if req.is_null() { return false; }
+    let r = unsafe { &*req };
+    let hs = header_array(r.headers, r.headers_count);
+    bytes(&r.method) == b"GET" && is_upgrade(hs) && find_header(hs, b"sec-websocket-key").is_some()
+}
+
+/// True if a parsed response switches to WebSocket (101 with the upgrade headers).
+/// If `req` is non-null, its Sec-WebSocket-Key must also match the response's
+/// Sec-WebSocket-Accept, tying the response to that request.
+#[no_mangle]
+pub extern "C" fn iris_ws_is_upgrade_response(resp: *const IrisHttpResponse, req: *const IrisHttpRequest) -> bool {
+    if resp.is_null() { return false; }
+    let r = unsafe { &*resp };
+    let hs = header_array(r.headers, r.headers_count);
+    if r.status_code != 101 || !is_upgrade(hs) { return false; }
+    if req.is_null() { return true; }
+    let q = unsafe { &*req };
+    let key = find_header(header_array(q.headers, q.headers_count), b"sec-websocket-key");
+    let accept = find_header(hs, b"sec-websocket-accept").and_then(crate::base64::decode);
+    matches!((key, accept), (Some(k), Some(a)) if a == accept_digest(k))
+}
+
+/// Decode the frame at the start of `data`, unmasking the payload. Advance by
+/// frame_len to reach the next frame. Fragmented messages are not reassembled.
+/// Returns 0=ok, -1=incomplete (feed more data), -2=arg error or malformed frame.
+/// Free with iris_ws_frame_free.
+#[no_mangle]
+pub extern "C" fn iris_ws_parse_frame(data: *const u8, len: usize, out: *mut IrisWsFrame) -> i32 {
+    if data.is_null() || out.is_null() { return -2; }
+    let f = match parse_frame(unsafe { slice::from_raw_parts(data, len) }) {
+        Ok(f) => f,
+        Err(e) => return e,
+    };
+    let close_code = match f.payload.get(..2) {
+        Some(c) if f.opcode == WS_OP_CLOSE => u16::from_be_bytes([c[0], c[1]]),
+        _ => 0,
+    };
+    let (payload, payload_len) = alloc_bytes(&f.payload);
+    unsafe {
+        out.write(IrisWsFrame {
+            fin: f.fin,
+            rsv: f.rsv,
+            opcode: f.opcode,
+            masked: f.mask_key.is_some(),
+            mask_key: f.mask_key.unwrap_or([0; 4]),
+            header_len: f.header_len,
+            frame_len: f.header_len + payload_len,
+            payload,
+            payload_len,
+            close_code,
+        });
+    }
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn iris_ws_frame_free(frame: *mut IrisWsFrame) {
+    if frame.is_null() { return; }
+    let f = unsafe { &*frame };
+    iris_free_bytes(f.payload, f.payload_len);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{free_headers, iris_http_parse_request, iris_http_parse_response};
+
+    #[test]
+    fn handshake() {
+        // RFC 6455 section 1.3
+        let req = b"GET /chat HTTP/1.1\r\nHost: server.example.com\r\nUpgrade: websocket\r\nConnection: keep-alive, Upgrade\r\n\
+                    Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n";
+        let resp = b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: WebSocket\r\nConnection: Upgrade\r\n\
+                     Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n\r\n";
+        let mut q = std::mem::MaybeUninit::<IrisHttpRequest>::uninit();
+        let mut r = std::mem::MaybeUninit::<IrisHttpResponse>::uninit();
+        assert_eq!(iris_http_parse_request(req.as_ptr(), req.len(), q.as_mut_ptr()), 0);
+        assert_eq!(iris_http_parse_response(resp.as_ptr(), resp.len(), r.as_mut_ptr()), 0);
+        let (q, r) = unsafe { (q.assume_init(), r.assume_init()) };
+        assert!(iris_ws_is_upgrade_request(&q));
+        assert!(iris_ws_is_upgrade_response(&r, &q) && iris_ws_is_upgrade_response(&r, std::ptr::null()));
+        free_headers(q.headers, q.headers_count);
+
+        let other = b"GET /chat HTTP/1.1\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: AAAAAAAAAAAAAAAAAAAAAA==\r\n\r\n";
+        let mut q = std::mem::MaybeUninit::<IrisHttpRequest>::uninit();
+        assert_eq!(iris_http_parse_request(other.as_ptr(), other.len(), q.as_mut_ptr()), 0);
+        let q = unsafe { q.assume_init() };
+        assert!(iris_ws_is_upgrade_request(&q) && !iris_ws_is_upgrade_response(&r, &q));
+        free_headers(q.headers, q.headers_count);
+        free_headers(r.headers, r.headers_count);
+    }
+
+    #[test]
+    fn frames() {
+        // RFC 6455 section 5.7: masked "Hello", then a close with code 1000
+        let d = [&b"\x81\x85\x37\xfa\x21\x3d\x7f\x9f\x4d\x51\x58"[..], b"\x88\x02\x03\xe8"].concat();
+        let mut out = std::mem::MaybeUninit::<IrisWsFrame>::uninit();
+        assert_eq!(iris_ws_parse_frame(d.as_ptr(), d.len(), out.as_mut_ptr()), 0);
+        let mut f = unsafe { out.assume_init() };
+        assert_eq!((f.fin, f.opcode, f.masked, f.frame_len), (true, WS_OP_TEXT, true, 11));
+        assert_eq!(unsafe { slice::from_raw_parts(f.payload, f.payload_len) }, b"Hello");
+        let next = f.frame_len;
+        iris_ws_frame_free(&mut f);
+        let mut out = std::mem::MaybeUninit::<IrisWsFrame>::uninit();
+        assert_eq!(iris_ws_parse_frame(d[next..].as_ptr(), d.len() - next, out.as_mut_ptr()), 0);
+        let mut f = unsafe { out.assume_init() };
+        assert_eq!((f.opcode, f.close_code, f.masked), (WS_OP_CLOSE, 1000, false));
+        iris_ws_frame_free(&mut f);
+
+        // 256-byte binary frame with a 16-bit length, compressed (RSV1)
+        let big = [&b"\xc2\x7e\x01\x00"[..], &[7; 256]].concat();
+        let f = parse_frame(&big).unwrap();
+        assert_eq!((f.rsv, f.opcode, f.header_len, f.payload.len()), (4, WS_OP_BINARY, 4, 256));
+        assert_eq!(parse_frame(&big[..200]).err(), Some(-1));
+        assert_eq!(parse_frame(b"\x01\x03Hel").map(|f| (f.fin, f.opcode)).ok(), Some((false, WS_OP_TEXT)));
+        // fragmented ping, oversized ping, reserved opcode
+        for bad in [&b"\x09\x00"[..], b"\x89\x7e\x00\x80", b"\x83\x00"] {
+            assert_eq!(parse_frame(bad).err(), Some(-2));
+        }
+    }
+}
@@ -0,0 +1,299 @@
+//! X.509 certificate decoding (RFC 5280): names, serial, validity, fingerprints and
+//! SPKI pins, plus the C summary struct shared by the modules that report certificates.
+
+use crate::base64;
+use crate::der::{read_tlv, Tlv};
+use crate::ffi::{alloc_array, free_array, free_cstr, to_cstr};
+use crate::hash::{sha1_digest, sha256_digest, to_hex};
+use std::ffi::c_char;
+
+const OID_CN: &str = "2.5.4.3";
+const OID_OU: &str = "2.5.4.11";
+const OID_SUBJECT_ALT_NAME: &str = "2.5.29.17";
+const OID_BASIC_CONSTRAINTS: &str = "2.5.29.19";
+
+pub struct Name<'a> {
+    pub raw: &'a [u8],
+    pub attrs: Vec<(String, String)>, // (OID, value) in encoded order
+}
+
+impl Name<'_> {
+    pub fn get(&self, oid: &str) -> Option<&str> {
+        self.attrs.iter().find(|(o, _)| o == oid).map(|(_, v)| v.as_str())
+    }
+
+    /// RFC 4514-style "CN=..., OU=..., O=..." in encoded order.
+    pub fn display(&self) -> String {
+        self.attrs.iter().map(|(oid, v)| {
+            let short = match oid.as_str() {
+                "2.5.4.3" => "CN", "2.5.4.6" => "C", "2.5.4.7" => "L", "2.5.4.8" => "ST",
+                "2.5.4.10" => "O", "2.5.4.11" => "OU", "0.9.2342.19200300.100.1.1" => "UID",
+                "1.2.840.113549.1.9.1" => "emailAddress",
+                other => other,
+            };
+            format!("{}={}", short, v)
+        }).collect::<Vec<_>>().join(", ")
+    }
+}
+
+pub struct Certificate<'a> {
+    pub raw: &'a [u8],
+    pub serial: &'a [u8],
+    pub signature_algorithm: String, // OID; empty if missing
+    pub issuer: Name<'a>,
+    pub subject: Name<'a>,
+    pub not_before: i64,
+    pub not_after: i64,
+    pub spki: &'a [u8],            // SubjectPublicKeyInfo, DER
+    pub san: Vec<String>,          // subjectAltName DNS names and IP addresses; empty if absent
+    pub ca: bool,                  // basicConstraints cA
+}
+
+#[repr(C)]
+pub struct IrisX509Cert {
+    pub subject: *mut c_char,      // "CN=..., OU=..., O=..., C=US"
+    pub issuer: *mut c_char,
+    pub common_name: *mut c_char,
+    pub organizational_unit: *mut c_char, // Apple certificates carry the Team ID here
+    pub serial: *mut c_char,       // hex
+    pub not_before: i64,           // unix seconds
+    pub not_after: i64,
+    pub sha1: *mut c_char,         // fingerprint of the DER encoding, hex
+    pub sha256: *mut c_char,
+    pub spki_pin: *mut c_char,     // base64 SHA-256 of the SubjectPublicKeyInfo (RFC 7469)
+    pub self_signed: bool,         // issuer == subject
+}
+
+fn string_value(t: &Tlv) -> String {
+    match t.number {
+        // BMPString is UTF-16BE; everything else is treated as (lossy) UTF-8
+        30 => {
+            let units: Vec<u16> = t.content.chunks_exact(2).map(|c| u16::from_be_bytes([c[0], c[1]])).collect();
+            String::from_utf16_lossy(&units)
+        }
+        _ => t.as_string(),
+    }
+}
+
+pub fn parse_name<'a>(t: &Tlv<'a>) -> Option<Name<'a>> {
+    let mut attrs = Vec::new();
+    for rdn in t.children() {
+        for atv in rdn.children() {
+            let mut f = atv.children();
+            let oid = f.next()?.as_oid()?;
+            attrs.push((oid, string_value(&f.next()?)));
+        }
+    }
+    Some(Name { raw: t.raw, attrs })
+}
+
+/// subjectAltName entries: dNSName as is, iPAddress in text form. Other name
+/// types are skipped.
+fn parse_san(t: &Tlv, out: &mut Vec<String>) {
+    for gn in t.children() {
+        if gn.is_context(2) {
+            out.push(gn.as_string());
+        } else if gn.is_context(7) {
+            let ip = match gn.content.len() {
+                4 => <[u8; 4]>::try_from(gn.content).map(|a| std::net::IpAddr::from(a).to_string()).ok(),
+                16 => <[u8; 16]>::try_from(gn.content).map(|a| std::net::IpAddr::from(a).to_string()).ok(),
+                _ => None,
+            };
+            out.extend(ip);
+        }
+    }
+}
+
+/// Decode a DER certificate. Of the extensions only subjectAltName and
+/// basicConstraints are read; the signature is not interpreted.
+pub fn parse(der: &[u8]) -> Option<Certificate<'_>> {
+    let (cert, _) = read_tlv(der)?;
+    let tbs = cert.children().next()?;
+    let mut f = tbs.children().peekable();
+    if f.peek()?.is_context(0) { f.next(); } // version
+    let serial = f.next()?.content;
+    let signature_algorithm = f.next()?.children().next().and_then(|t| t.as_oid()).unwrap_or_default();
+    let issuer = parse_name(&f.next()?)?;
+    let mut validity = f.next()?.children();
+    let not_before = validity.next()?.as_time()?;
+    let not_after = validity.next()?.as_time()?;
+    let subject = parse_name(&f.next()?)?;
+    let spki = f.next()?.raw;
+    let mut c = Certificate {
+        raw: cert.raw, serial, signature_algorithm, issuer, subject, not_before, not_after, spki,
+        san: Vec::new(), ca: false,
+    };
+    // issuerUniqueID [1] and subjectUniqueID [2] may precede extensions [3]
+    let Some(exts) = f.find(|t| t.is_context(3)).and_then(|t| t.inner()) else { return Some(c) };
+    for ext in exts.children() {
+        let mut e = ext.children();
+        let Some(oid) = e.next().and_then(|t| t.as_oid()) else { continue };
+        let Some(value) = e.find(|t| t.is(0, 4)).and_then(|t| t.inner()) else { continue };
+        match oid.as_str() {
+            OID_SUBJECT_ALT_NAME => parse_san(&value, &mut c.san),
+            OID_BASIC_CONSTRAINTS => c.ca = value.children().next().is_some_and(|b| b.is(0, 1) && b.content != [0]),
+            _ => {}
+        }
+    }
+    Some(c)
+}
+
+/// HPKP-style pin: base64 of the SHA-256 of the DER SubjectPublicKeyInfo. It
+/// survives reissuing a certificate for the same key, and differs when a TLS
+/// interception proxy substitutes its own.
+pub fn spki_pin(c: &Certificate) -> String {
+    base64::encode(&sha256_digest(c.spki))
+}
+
+pub fn cert_summary(c: &Certificate) -> IrisX509Cert {
+    IrisX509Cert {
+        subject: to_cstr(&c.subject.display()),
+        issuer: to_cstr(&c.issuer.display()),
+        common_name: to_cstr(c.subject.get(OID_CN).unwrap_or("")),
+        organizational_unit: to_cstr(c.subject.get(OID_OU).unwrap_or("")),
+        serial: to_cstr(&to_hex(c.serial)),
+        not_before: c.not_before,
+        not_after: c.not_after,
+        sha1: to_cstr(&to_hex(&sha1_digest(c.raw))),
+        sha256: to_cstr(&to_hex(&sha256_digest(c.raw))),
+        spki_pin: to_cstr(&spki_pin(c)),
+        self_signed: c.issuer.raw == c.subject.raw,
+    }
+}
+
+/// C summaries for decoded certificates. Free with free_cert_array.
+pub fn alloc_cert_array(certs: &[&Certificate]) -> (*mut IrisX509Cert, usize) {
+    alloc_array(certs.iter().map(|c| cert_summary(c)).collect())
+}
+
+pub fn free_cert_array(ptr: *mut IrisX509Cert, count: usize) {
+    if ptr.is_null() { return; }
+    for i in 0..count {
+        let c = unsafe { &*ptr.add(i) };
+        for p in [c.subject, c.issuer, c.common_name, c.organizational_unit, c.serial, c.sha1, c.sha256, c.spki_pin] {
+            free_cstr(p);
+        }
+    }
+    free_array(ptr, count);
+}
+
+// --- FFI ---
+
+/// SPKI SHA-256 pin of a certificate in DER or PEM, e.g. to compare against the
+/// pins expected for a host. NULL if it does not parse. Free with iris_free_string.
+#[no_mangle]
+pub extern "C" fn iris_x509_spki_pin(data: *const u8, len: usize) -> *mut c_char {
+    if data.is_null() { return std::ptr::null_mut(); }
+    let data = unsafe { std::slice::from_raw_parts(data, len) };
+    let der = base64::pem_decode(data);
+    match parse(der.as_deref().unwrap_or(data)) {
+        Some(c) => to_cstr(&spki_pin(&c)),
+        None => std::ptr::null_mut(),
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    pub fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+        let mut v = vec![tag];
+        match content.len() {
+            n if n < 128 => v.push(n as u8),
+            n if n < 256 => v.extend_from_slice(&[0x81, n as u8]),
+            n => v.extend_from_slice(&[0x82, (n >> 8) as u8, n as u8]),
+        }
+        v.extend_from_slice(content);
+        v
+    }
+
+    pub fn name(attrs: &[(&[u8], &str)]) -> Vec<u8> {
+        let rdns: Vec<u8> = attrs.iter().flat_map(|(oid, v)| {
+            tlv(0x31, &tlv(0x30, &[tlv(0x06, oid), tlv(0x0c, v.as_bytes())].concat()))
+        }).collect();
+        tlv(0x30, &rdns)
+    }
+
+    pub const CN: &[u8] = &[0x55, 4, 3];
+    pub const OU: &[u8] = &[0x55, 4, 11];
+
+    /// Minimal certificate: the fields parse() reads plus placeholder key and signature.
+    pub fn cert(serial: u8, subject: &[u8], issuer: &[u8]) -> Vec<u8> {
+        cert_with(serial, subject, issuer, b"20340101000000Z", &[])
+    }
+
+    /// As cert(), valid from 2024-01-01 until `not_after` (GeneralizedTime), with
+    /// the given Extension SEQUENCEs.
+    pub fn cert_with(serial: u8, subject: &[u8], issuer: &[u8], not_after: &[u8], exts: &[Vec<u8>]) -> Vec<u8> {
+        let alg = tlv(0x30, &tlv(0x06, &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 1, 1, 11]));
+        let validity = tlv(0x30, &[tlv(0x17, b"240101000000Z"), tlv(0x18, not_after)].concat());
+        let mut tbs = [tlv(0xa0, &tlv(0x02, &[2])), tlv(0x02, &[serial]), alg.clone(), issuer.to_vec(),
+                       validity, subject.to_vec(), tlv(0x30, &[])].concat();
+        if !exts.is_empty() { tbs.extend(tlv(0xa3, &tlv(0x30, &exts.concat()))); }
+        tlv(0x30, &[tlv(0x30, &tbs), alg, tlv(0x03, &[0])].concat())
+    }
+
+    /// subjectAltName extension with dNSName entries.
+    pub fn san_ext(names: &[&str]) -> Vec<u8> {
+        let gns: Vec<u8> = names.iter().flat_map(|n| tlv(0x82, n.as_bytes())).collect();
+        tlv(0x30, &[tlv(0x06, &[0x55, 29, 17]), tlv(0x04, &tlv(0x30, &gns))].concat())
+    }
+
+    /// Critical basicConstraints extension with cA set.
+    pub fn ca_ext() -> Vec<u8> {
+        tlv(0x30, &[tlv(0x06, &[0x55, 29, 19]), tlv(0x01, &[0xff]), tlv(0x04, &tlv(0x30, &tlv(0x01, &[0xff])))].concat())
+    }
+
+    #[test]
+    fn parse_certificate() {
+        let subj = name(&[(CN, "Apple Development: Jane (ABCDE12345)"), (OU, "TEAM123456")]);
+        let iss = name(&[(CN, "Apple Worldwide Developer Relations")]);
+        let der = cert(0x42, &subj, &iss);
+        let c = parse(&der).unwrap();
+        assert_eq!(c.serial, &[0x42]);
+        assert_eq!(c.signature_algorithm, "1.2.840.113549.1.1.11");
+        assert_eq!(c.subject.display(), "CN=Apple Development: Jane (ABCDE12345), OU=TEAM123456");
+        assert_eq!(c.issuer.get(OID_CN), Some("Apple Worldwide Developer Relations"));
+        assert_eq!((c.not_before, c.not_after), (1704067200, 2019686400));
+        assert!(c.san.is_empty() && !c.ca);
+
+        let (arr, n) = alloc_cert_array(&[&c]);
+        let s = unsafe { &*arr };
+        let cs = |p: *mut c_char| unsafe { std::ffi::CStr::from_ptr(p) }.to_string_lossy().into_owned();
+        assert_eq!((cs(s.organizational_unit), cs(s.serial), s.self_signed), ("TEAM123456".into(), "42".into(), false));
+        assert_eq!(cs(s.sha256), to_hex(&sha256_digest(&der)));
+        assert_eq!(cs(s.spki_pin), spki_pin(&c));
+        free_cert_array(arr, n);
+    }
+
+    #[test]
+    fn san_and_basic_constraints() {
+        let (subj, iss) = (name(&[(CN, "example.com")]), name(&[(CN, "CA")]));
+        let ip = tlv(0x87, &[192, 0, 2, 1]);
+        let email = tlv(0x81, b"admin@example.com");
+        let san = tlv(0x30, &[tlv(0x06, &[0x55, 29, 17]), tlv(0x04, &tlv(0x30, &[tlv(0x82, b"example.com"), ip, email].concat()))].concat());
+        let der = cert_with(1, &subj, &iss, b"20250101000000Z", &[ca_ext(), san]);
+        let c = parse(&der).unwrap();
+        assert_eq!(c.san, ["example.com", "192.0.2.1"]);
+        assert!(c.ca);
+        let der = cert_with(1, &subj, &iss, b"20250101000000Z", &[san_ext(&["a.example", "b.example"])]);
+        let c = parse(&der).unwrap();
+        assert_eq!((c.san.len(), c.ca, c.not_after), (2, false, 1735689600));
+    }
+
+    #[test]
+    fn spki_pin_der_and_pem() {
+        let der = cert(1, &name(&[(CN, "example.com")]), &name(&[(CN, "CA")]));
+        // The placeholder key is an empty SEQUENCE
+        let pin = base64::encode(&sha256_digest(&[0x30, 0]));
+        assert_eq!(spki_pin(&parse(&der).unwrap()), pin);
+
+        let pem = format!("-----BEGIN CERTIFICATE-----\n{}\n-----END CERTIFICATE-----\n", base64::encode(&der));
+        for input in [&der[..], pem.as_bytes()] {
+            let p = iris_x509_spki_pin(input.as_ptr(), input.len());
+            assert_eq!(unsafe { std::ffi::CStr::from_ptr(p) }.to_str(), Ok(pin.as_str()));
+            crate::batch::iris_free_string(p);
+        }
+        assert!(iris_x509_spki_pin(b"junk".as_ptr(), 4).is_null());
+    }
+}
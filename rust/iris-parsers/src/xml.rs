@@ -0,0 +1,172 @@
+//! Minimal XML reading: entity decoding shared with the plist parser, and a small
+//! element tree for documents like XAR tables of contents and installer Distribution files.
+
+const MAX_DEPTH: usize = 64;
+
+pub struct Element {
+    pub name: String,
+    pub attrs: Vec<(String, String)>,
+    pub children: Vec<Element>,
+    pub text: String, // character data directly inside this element, CDATA included
+}
+
+impl Element {
+    pub fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|c| c.name == name)
+    }
+
+    pub fn children_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> {
+        self.children.iter().filter(move |c| c.name == name)
+    }
+
+    pub fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs.iter().find(|(k, _)| k == name).map(|(_, v)| v.as_str())
+    }
+
+    /// Trimmed text of the first child named `name`.
+    pub fn child_text(&self, name: &str) -> Option<&str> {
+        self.child(name).map(|c| c.text.trim())
+    }
+}
+
+pub fn find(hay: &[u8], needle: &[u8]) -> Option<usize> {
+    hay.windows(needle.len()).position(|w| w == needle)
+}
+
+pub fn decode_entities(raw: &[u8]) -> String {
+    let s = String::from_utf8_lossy(raw);
+    if !s.contains('&') { return s.into_owned(); }
+    let mut out = String::with_capacity(s.len());
+    let mut rest = &s[..];
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let Some(semi) = rest.find(';').filter(|&i| i <= 10) else { out.push('&'); rest = &rest[1..]; continue };
+        let ent = &rest[1..semi];
+        let ch = match ent {
+            "lt" => Some('<'), "gt" => Some('>'), "amp" => Some('&'), "quot" => Some('"'), "apos" => Some('\''),
+            _ => ent.strip_prefix("#x").or_else(|| ent.strip_prefix("#X")).map(|h| u32::from_str_radix(h, 16))
+                .or_else(|| ent.strip_prefix('#').map(|d| d.parse::<u32>()))
+                .and_then(|r| r.ok()).and_then(char::from_u32),
+        };
+        match ch {
+            Some(c) => { out.push(c); rest = &rest[semi + 1..]; }
+            None => { out.push('&'); rest = &rest[1..]; }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+struct Reader<'a> {
+    s: &'a [u8],
+    p: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn rest(&self) -> &'a [u8] { &self.s[self.p..] }
+
+    fn skip_ws(&mut self) {
+        while self.p < self.s.len() && self.s[self.p].is_ascii_whitespace() { self.p += 1; }
+    }
+
+    /// Skip past `end`, which must occur in the remaining input.
+    fn skip_past(&mut self, end: &[u8]) -> Option<()> {
+        self.p += find(self.rest(), end)? + end.len();
+        Some(())
+    }
+
+    fn name(&mut self) -> Option<String> {
+        let r = self.rest();
+        let n = r.iter().position(|&b| b.is_ascii_whitespace() || b"/>=".contains(&b)).unwrap_or(r.len());
+        if n == 0 { return None; }
+        self.p += n;
+        Some(String::from_utf8_lossy(&r[..n]).into_owned())
+    }
+
+    fn element(&mut self, depth: usize) -> Option<Element> {
+        if depth > MAX_DEPTH || self.rest().first() != Some(&b'<') { return None; }
+        self.p += 1;
+        let name = self.name()?;
+        let mut el = Element { name, attrs: Vec::new(), children: Vec::new(), text: String::new() };
+        loop {
+            self.skip_ws();
+            match *self.rest().first()? {
+                b'/' => { self.skip_past(b">")?; return Some(el); }
+                b'>' => { self.p += 1; break; }
+                _ => {
+                    let key = self.name()?;
+                    self.skip_ws();
+                    if self.rest().first() != Some(&b'=') { return None; }
+                    self.p += 1;
+                    self.skip_ws();
+                    let quote = *self.rest().first()?;
+                    if quote != b'"' && quote != b'\'' { return None; }
+                    self.p += 1;
+                    let end = self.rest().iter().position(|&b| b == quote)?;
+                    el.attrs.push((key, decode_entities(&self.rest()[..end])));
+                    self.p += end + 1;
+                }
+            }
+        }
+        loop {
+            let r = self.rest();
+            let lt = r.iter().position(|&b| b == b'<')?;
+            el.text.push_str(&decode_entities(&r[..lt]));
+            self.p += lt;
+            let r = self.rest();
+            if r.starts_with(b"<![CDATA[") {
+                self.p += 9;
+                let end = find(self.rest(), b"]]>")?;
+                el.text.push_str(&String::from_utf8_lossy(&self.rest()[..end]));
+                self.p += end + 3;
+            } else if r.starts_with(b"<!--") {
+                self.skip_past(b"-->")?;
+            } else if r.starts_with(b"<?") {
+                self.skip_past(b"?>")?;
+            } else if r.starts_with(b"</") {
+                self.p += 2;
+                if self.name()? != el.name { return None; }
+                self.skip_past(b">")?;
+                return Some(el);
+            } else {
+                el.children.push(self.element(depth + 1)?);
+            }
+        }
+    }
+}
+
+/// Parse a document into its root element. None on malformed or mismatched markup.
+pub fn parse(data: &[u8]) -> Option<Element> {
+    let data = data.strip_prefix(b"\xef\xbb\xbf").unwrap_or(data);
+    let mut r = Reader { s: data, p: 0 };
+    loop {
+        r.skip_ws();
+        let rest = r.rest();
+        if rest.starts_with(b"<?") { r.skip_past(b"?>")?; }
+        else if rest.starts_with(b"<!--") { r.skip_past(b"-->")?; }
+        else if rest.starts_with(b"<!") { r.skip_past(b">")?; }
+        else { return r.element(0); }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn element_tree() {
+        let doc = br#"<?xml version="1.0"?>
+<!-- comment --><installer-gui-script minSpecVersion='2'>
+  <title>Example &amp; Co</title>
+  <pkg-ref id="com.example.pkg" version="1.0"/>
+  <script><![CDATA[function check() { return a < b; }]]></script>
+</installer-gui-script>"#;
+        let root = parse(doc).unwrap();
+        assert_eq!((root.name.as_str(), root.attr("minSpecVersion")), ("installer-gui-script", Some("2")));
+        assert_eq!(root.child_text("title"), Some("Example & Co"));
+        assert_eq!(root.child("pkg-ref").and_then(|p| p.attr("id")), Some("com.example.pkg"));
+        assert_eq!(root.child_text("script"), Some("function check() { return a < b; }"));
+        assert!(parse(b"<a><b></a></b>").is_none());
+    }
+}
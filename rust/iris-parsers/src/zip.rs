@@ -0,0 +1,488 @@
+//! ZIP archive listing from the end-of-central-directory record and central directory
+//! (including ZIP64), without decompressing anything. Entry names that would escape
+//! the extraction directory are flagged.
+
+use crate::bytes::{le16, le32, le64};
+use crate::der::components_to_unix;
+use crate::ffi::{alloc_array, free_array, free_cstr, to_cstr};
+use std::ffi::{c_char, CStr};
+
+const EOCD_SIG: u32 = 0x0605_4b50;
+const ZIP64_EOCD_SIG: u32 = 0x0606_4b50;
+const ZIP64_LOCATOR_SIG: u32 = 0x0706_4b50;
+const CENTRAL_SIG: u32 = 0x0201_4b50;
+const EOCD_SIZE: usize = 22;
+const ZIP64_EOCD_SIZE: usize = 56;
+const ZIP64_LOCATOR_SIZE: usize = 20;
+const CENTRAL_SIZE: usize = 46;
+const MAX_COMMENT: usize = 0xffff;
+
+const EXTRA_ZIP64: u16 = 0x0001;
+const EXTRA_UNIX_TIME: u16 = 0x5455;
+const EXTRA_AES: u16 = 0x9901;
+
+const FLAG_ENCRYPTED: u16 = 0x0001;
+const FLAG_STRONG_ENCRYPTION: u16 = 0x0040;
+const METHOD_AES: u16 = 99;
+const HOST_UNIX: u8 = 3;
+const S_IFMT: u32 = 0o170000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFLNK: u32 = 0o120000;
+
+pub const ZIP_ENC_NONE: u8 = 0;
+pub const ZIP_ENC_ZIPCRYPTO: u8 = 1;
+pub const ZIP_ENC_AES: u8 = 2;
+pub const ZIP_ENC_STRONG: u8 = 3;
+
+#[repr(C)]
+pub struct IrisZipEntry {
+    pub name: *mut c_char,
+    pub compressed_size: u64,
+    pub uncompressed_size: u64,
+    pub crc32: u32,
+    pub method: u16,               // 0=stored, 8=deflate, 12=bzip2, 14=lzma, 93=zstd, ... (AES: the inner method)
+    pub flags: u16,                // general purpose bit flags
+    pub mtime: i64,                // unix seconds; DOS timestamps are taken as UTC
+    pub encryption: u8,            // ZIP_ENC_*
+    pub is_dir: bool,
+    pub is_symlink: bool,
+    pub unix_mode: u32,            // 0 unless created on a unix host
+    pub local_header_offset: u64,  // relative to the start of the archive
+    pub path_traversal: bool,      // absolute path, drive letter or ".." component
+}
+
+#[repr(C)]
+pub struct IrisZipInfo {
+    pub entries: *mut IrisZipEntry,
+    pub entry_count: usize,
+    pub declared_entries: u64,     // count in the end-of-central-directory record
+    pub zip64: bool,
+    pub prefix_len: u64,           // bytes before the archive, e.g. a self-extractor stub
+    pub comment: *mut c_char,
+    pub traversal_count: usize,
+}
+
+struct Directory {
+    entries: u64,
+    size: u64,
+    offset: u64,
+    zip64: bool,
+    end: usize,    // where the central directory should end
+    comment: String,
+}
+
+fn find_eocd(d: &[u8]) -> Option<usize> {
+    let lowest = d.len().saturating_sub(EOCD_SIZE + MAX_COMMENT);
+    (lowest..=d.len().checked_sub(EOCD_SIZE)?).rev().find(|&p| le32(d, p) == Some(EOCD_SIG))
+}
+
+fn directory(d: &[u8], eocd: usize) -> Option<Directory> {
+    let comment_len = le16(d, eocd + 20)? as usize;
+    let comment = String::from_utf8_lossy(d.get(eocd + EOCD_SIZE..)?.get(..comment_len).unwrap_or(&[])).into_owned();
+    let (entries, size, offset) = (le16(d, eocd + 10)?, le32(d, eocd + 12)?, le32(d, eocd + 16)?);
+    if entries != 0xffff && size != 0xffff_ffff && offset != 0xffff_ffff {
+        return Some(Directory { entries: entries as u64, size: size as u64, offset: offset as u64, zip64: false, end: eocd, comment });
+    }
+    let loc = eocd.checked_sub(ZIP64_LOCATOR_SIZE)?;
+    if le32(d, loc)? != ZIP64_LOCATOR_SIG { return None; }
+    // The recorded offset is wrong when data was prepended; the record usually sits
+    // right before the locator
+    let z = usize::try_from(le64(d, loc + 8)?).ok().filter(|&z| le32(d, z) == Some(ZIP64_EOCD_SIG))
+        .or_else(|| loc.checked_sub(ZIP64_EOCD_SIZE).filter(|&z| le32(d, z) == Some(ZIP64_EOCD_SIG)))?;
+    Some(Directory { entries: le64(d, z + 32)?, size: le64(d, z + 40)?, offset: le64(d, z + 48)?, zip64: true, end: z, comment })
+}
+
+fn dos_time(date: u16, time: u16) -> i64 {
+    let (y, m, day) = (1980 + (date >> 9) as i64, ((date >> 5) & 0xf) as u32, (date & 0x1f) as u32);
+    if m == 0 || day == 0 { return 0; }
+    components_to_unix(y, m, day, (time >> 11) as u32, ((time >> 5) & 0x3f) as u32, (time & 0x1f) as u32 * 2)
+}
+
+/// Whether an archive entry name would escape the extraction directory.
+pub fn traversal(name: &str) -> bool {
+    let n = name.replace('\\', "/");
+    let b = n.as_bytes();
+    n.starts_with('/') || (b.len() >= 2 && b[0].is_ascii_alphabetic() && b[1] == b':') || n.split('/').any(|c| c == "..")
+}
+
+struct Entry {
+    name: String,
+    compressed_size: u64,
+    uncompressed_size: u64,
+    crc32: u32,
+    method: u16,
+    flags: u16,
+    mtime: i64,
+    encryption: u8,
+    unix_mode: u32,
+    local_header_offset: u64,
+}
+
+/// One central directory header at `p`; returns the entry and the next offset.
+fn central(d: &[u8], p: usize) -> Option<(Entry, usize)> {
+    if le32(d, p)? != CENTRAL_SIG { return None; }
+    let host = *d.get(p + 5)?;
+    let flags = le16(d, p + 8)?;
+    let mut method = le16(d, p + 10)?;
+    let (name_len, extra_len, comment_len) = (le16(d, p + 28)? as usize, le16(d, p + 30)? as usize, le16(d, p + 32)? as usize);
+    let name = d.get(p + CENTRAL_SIZE..p + CENTRAL_SIZE + name_len)?;
+    let extra = d.get(p + CENTRAL_SIZE + name_len..p + CENTRAL_SIZE + name_len + extra_len)?;
+    let mut e = Entry {
+        name: String::from_utf8_lossy(name).into_owned(),
+        crc32: le32(d, p + 16)?,
+        compressed_size: le32(d, p + 20)? as u64,
+        uncompressed_size: le32(d, p + 24)? as u64,
+        local_header_offset: le32(d, p + 42)? as u64,
+        mtime: dos_time(le16(d, p + 14)?, le16(d, p + 12)?),
+        unix_mode: if host == HOST_UNIX { le32(d, p + 38)? >> 16 } else { 0 },
+        encryption: ZIP_ENC_NONE,
+        method, flags,
+    };
+    if flags & FLAG_ENCRYPTED != 0 {
+        e.encryption = if flags & FLAG_STRONG_ENCRYPTION != 0 { ZIP_ENC_STRONG } else { ZIP_ENC_ZIPCRYPTO };
+    }
+    let mut x = 0;
+    while x + 4 <= extra.len() {
+        let (id, len) = (le16(extra, x)?, le16(extra, x + 2)? as usize);
+        let field = extra.get(x + 4..x + 4 + len)?;
+        match id {
+            EXTRA_ZIP64 => {
+                // Only the fields saturated in the fixed header are present, in this order
+                let mut o = 0;
+                for v in [&mut e.uncompressed_size, &mut e.compressed_size, &mut e.local_header_offset] {
+                    if *v == 0xffff_ffff {
+                        if let Some(w) = le64(field, o) { *v = w; o += 8; }
+                    }
+                }
+            }
+            EXTRA_UNIX_TIME if field.first().is_some_and(|f| f & 1 != 0) => {
+                if let Some(t) = le32(field, 1) { e.mtime = t as i32 as i64; }
+            }
+            EXTRA_AES if method == METHOD_AES => {
+                method = le16(field, 5).unwrap_or(method);
+                e.method = method;
+                e.encryption = ZIP_ENC_AES;
+            }
+            _ => {}
+        }
+        x += 4 + len;
+    }
+    Some((e, p + CENTRAL_SIZE + name_len + extra_len + comment_len))
+}
+
+fn list(d: &[u8], out: *mut IrisZipInfo) -> i32 {
+    let Some(eocd) = find_eocd(d) else { return -3 };
+    let Some(dir) = directory(d, eocd) else { return -2 };
+    // Self-extractors and other prepended data shift every offset: the directory
+    // really ends where the EOCD (or ZIP64 EOCD) begins.
+    let mut start = usize::try_from(dir.offset).unwrap_or(usize::MAX);
+    let mut prefix = 0;
+    if le32(d, start) != Some(CENTRAL_SIG) && dir.entries > 0 {
+        let Some(s) = usize::try_from(dir.size).ok().and_then(|sz| dir.end.checked_sub(sz)) else { return -1 };
+        let Some(shift) = s.checked_sub(start) else { return -1 };
+        if le32(d, s) != Some(CENTRAL_SIG) { return -1; }
+        (start, prefix) = (s, shift);
+    }
+    let mut entries = Vec::with_capacity(dir.entries.min(65536) as usize);
+    let mut p = start;
+    for _ in 0..dir.entries {
+        let Some((e, next)) = central(d, p) else { return -1 };
+        entries.push(e);
+        p = next;
+    }
+    let mut traversal_count = 0;
+    let listed: Vec<_> = entries.into_iter().map(|e| {
+        let path_traversal = traversal(&e.name);
+        traversal_count += path_traversal as usize;
+        IrisZipEntry {
+            is_dir: e.name.ends_with('/') || e.unix_mode & S_IFMT == S_IFDIR,
+            is_symlink: e.unix_mode & S_IFMT == S_IFLNK,
+            name: to_cstr(&e.name),
+            compressed_size: e.compressed_size,
+            uncompressed_size: e.uncompressed_size,
+            crc32: e.crc32,
+            method: e.method,
+            flags: e.flags,
+            mtime: e.mtime,
+            encryption: e.encryption,
+            unix_mode: e.unix_mode,
+            local_header_offset: e.local_header_offset,
+            path_traversal,
+        }
+    }).collect();
+    let (entries, entry_count) = alloc_array(listed);
+    unsafe {
+        out.write(IrisZipInfo {
+            entries, entry_count,
+            declared_entries: dir.entries,
+            zip64: dir.zip64,
+            prefix_len: prefix as u64,
+            comment: to_cstr(&dir.comment),
+            traversal_count,
+        });
+    }
+    0
+}
+
+// --- FFI entry points ---
+
+/// List a ZIP archive held in memory. Returns 0=ok, -1=truncated central directory,
+/// -2=arg error or malformed ZIP64 records, -3=no end-of-central-directory record.
+/// Free with iris_zip_free.
+#[no_mangle]
+pub extern "C" fn iris_zip_list(data: *const u8, len: usize, out: *mut IrisZipInfo) -> i32 {
+    if data.is_null() || out.is_null() { return -2; }
+    list(unsafe { std::slice::from_raw_parts(data, len) }, out)
+}
+
+/// List a ZIP archive file. Same return codes as iris_zip_list, with -1 also
+/// covering an unreadable file.
+#[no_mangle]
+pub extern "C" fn iris_zip_list_path(path: *const c_char, out: *mut IrisZipInfo) -> i32 {
+    if path.is_null() || out.is_null() { return -2; }
+    let Ok(path) = unsafe { CStr::from_ptr(path) }.to_str() else { return -2 };
+    let Ok(data) = std::fs::read(path) else { return -1 };
+    list(&data, out)
+}
+
+#[no_mangle]
+pub extern "C" fn iris_zip_free(info: *mut IrisZipInfo) {
+    if info.is_null() { return; }
+    let info = unsafe { &*info };
+    for i in 0..info.entry_count { free_cstr(unsafe { (*info.entries.add(i)).name }); }
+    free_array(info.entries, info.entry_count);
+    free_cstr(info.comment);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn central_header(name: &str, method: u16, flags: u16, unix_mode: u32, extra: &[u8], offset: u32) -> Vec<u8> {
+        let mut v = CENTRAL_SIG.to_le_bytes().to_vec();
+        v.extend_from_slice(&[20, HOST_UNIX, 20, 0]);
+        v.extend_from_slice(&flags.to_le_bytes());
+        v.extend_from_slice(&method.to_le_bytes());
+        v.extend_from_slice(&0x6000u16.to_le_bytes());              // 12:00:00
+        v.extend_from_slice(&((44 << 9) | (3 << 5) | 15u16).to_le_bytes()); // 2024-03-15
+        v.extend_from_slice(&0xdead_beefu32.to_le_bytes());
+        v.extend_from_slice(&100u32.to_le_bytes());
+        v.extend_from_slice(&300u32.to_le_bytes());
+        v.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        v.extend_from_slice(&(extra.len() as u16).to_le_bytes());
+        v.extend_from_slice(&[0; 6]);
+        v.extend_from_slice(&(unix_mode << 16).to_le_bytes());
+        v.extend_from_slice(&offset.to_le_bytes());
+        v.extend_from_slice(name.as_bytes());
+        v.extend_from_slice(extra);
+        v
+    }
+
+    fn archive(prefix: usize, headers: &[Vec<u8>]) -> Vec<u8> {
+        let cd = headers.concat();
+        let mut d = vec![0u8; prefix + 64]; // local headers and data are not read
+        let cd_offset = 64u32;
+        d.extend_from_slice(&cd);
+        d.extend_from_slice(&EOCD_SIG.to_le_bytes());
+        d.extend_from_slice(&[0; 4]);
+        d.extend_from_slice(&(headers.len() as u16).to_le_bytes());
+        d.extend_from_slice(&(headers.len() as u16).to_le_bytes());
+        d.extend_from_slice(&(cd.len() as u32).to_le_bytes());
+        d.extend_from_slice(&cd_offset.to_le_bytes());
+        d.extend_from_slice(&5u16.to_le_bytes());
+        d.extend_from_slice(b"hello");
+        d
+    }
+
+    fn cs(p: *mut c_char) -> String {
+        unsafe { CStr::from_ptr(p) }.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn list_entries() {
+        let mut aes = EXTRA_AES.to_le_bytes().to_vec();
+        aes.extend_from_slice(&7u16.to_le_bytes());
+        aes.extend_from_slice(&[2, 0, b'A', b'E', 3, 8, 0]);
+        let mut zip64 = EXTRA_ZIP64.to_le_bytes().to_vec();
+        zip64.extend_from_slice(&8u16.to_le_bytes());
+        zip64.extend_from_slice(&(5u64 << 32).to_le_bytes());
+        let mut big = central_header("big.bin", 8, 0, 0o100644, &zip64, 0);
+        big[24..28].copy_from_slice(&0xffff_ffffu32.to_le_bytes());
+        let headers = [
+            central_header("Payload/", 0, 0, 0o040755, &[], 0),
+            central_header("../../Library/LaunchAgents/x.plist", 8, 0, 0o100644, &[], 10),
+            central_header("secret.txt", METHOD_AES, FLAG_ENCRYPTED, 0o100600, &aes, 20),
+            central_header("link", 0, 0, 0o120777, &[], 30),
+            big,
+        ];
+        for prefix in [0, 1000] {
+            let d = archive(prefix, &headers);
+            let mut out = std::mem::MaybeUninit::<IrisZipInfo>::uninit();
+            assert_eq!(iris_zip_list(d.as_ptr(), d.len(), out.as_mut_ptr()), 0);
+            let mut info = unsafe { out.assume_init() };
+            assert_eq!((info.entry_count, info.prefix_len, info.traversal_count, cs(info.comment)), (5, prefix as u64, 1, "hello".into()));
+            let e = unsafe { std::slice::from_raw_parts(info.entries, info.entry_count) };
+            assert!(e[0].is_dir && !e[0].path_traversal);
+            assert!(e[1].path_traversal);
+            assert_eq!((e[1].crc32, e[1].compressed_size, e[1].uncompressed_size, e[1].mtime), (0xdead_beef, 100, 300, 1_710_504_000));
+            assert_eq!((e[2].encryption, e[2].method, e[2].unix_mode), (ZIP_ENC_AES, 8, 0o100600));
+            assert!(e[3].is_symlink);
+            assert_eq!((cs(e[4].name), e[4].uncompressed_size, e[4].compressed_size), ("big.bin".into(), 5 << 32, 100));
+            iris_zip_free(&mut info);
+        }
+
+        let d = archive(0, &headers);
+        let mut out = std::mem::MaybeUninit::<IrisZipInfo>::uninit();
+        assert_eq!(iris_zip_list(d[..d.len() - 60].as_ptr(), d.len() - 60, out.as_mut_ptr()), -3);
+        let mut cut = d.clone();
+        cut.drain(64 + 10..64 + 50);
+        assert_eq!(iris_zip_list(cut.as_ptr(), cut.len(), out.as_mut_ptr()), -1);
+        assert!(traversal("C:\\Windows\\x.dll") && traversal("/etc/passwd") && traversal("a\\..\\..\\b") && !traversal("a/..b/c"));
+    }
+    fn zip64_archive(headers: &[Vec<u8>], record_offset: u64) -> Vec<u8> {
+        let cd = headers.concat();
+        let mut d = vec![0u8; 64];
+        d.extend_from_slice(&cd);
+        let z = d.len() as u64;
+        d.extend_from_slice(&ZIP64_EOCD_SIG.to_le_bytes());
+        d.extend_from_slice(&44u64.to_le_bytes());
+        d.extend_from_slice(&[45, 3, 45, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        d.extend_from_slice(&(headers.len() as u64).to_le_bytes());
+        d.extend_from_slice(&(headers.len() as u64).to_le_bytes());
+        d.extend_from_slice(&(cd.len() as u64).to_le_bytes());
+        d.extend_from_slice(&64u64.to_le_bytes());
+        d.extend_from_slice(&ZIP64_LOCATOR_SIG.to_le_bytes());
+        d.extend_from_slice(&[0; 4]);
+        d.extend_from_slice(&(if record_offset == 0 { z } else { record_offset }).to_le_bytes());
+        d.extend_from_slice(&1u32.to_le_bytes());
+        d.extend_from_slice(&EOCD_SIG.to_le_bytes());
+        d.extend_from_slice(&[0; 4]);
+        d.extend_from_slice(&[0xff; 12]);
+        d.extend_from_slice(&[0, 0]);
+        d
+    }
+
+    fn list_ok(d: &[u8]) -> IrisZipInfo {
+        let mut out = std::mem::MaybeUninit::<IrisZipInfo>::uninit();
+        assert_eq!(iris_zip_list(d.as_ptr(), d.len(), out.as_mut_ptr()), 0);
+        unsafe { out.assume_init() }
+    }
+
+    fn list_err(d: &[u8]) -> i32 {
+        let mut out = std::mem::MaybeUninit::<IrisZipInfo>::uninit();
+        iris_zip_list(d.as_ptr(), d.len(), out.as_mut_ptr())
+    }
+
+    #[test]
+    fn missing_and_truncated_eocd() {
+        let d = archive(0, &[central_header("a.txt", 0, 0, 0o100644, &[], 0)]);
+        let eocd = d.len() - EOCD_SIZE - 5;
+        assert_eq!(list_err(&d[..eocd + EOCD_SIZE - 1]), -3); // record cut short
+        assert_eq!(list_err(&d[..eocd]), -3);
+        assert_eq!(list_err(b"PK\x05\x06"), -3);
+        assert_eq!(list_err(&[]), -3);
+        assert_eq!(iris_zip_list(d.as_ptr(), d.len(), std::ptr::null_mut()), -2);
+
+        // A comment length past the end of the file is tolerated
+        let mut d = d[..d.len() - 5].to_vec();
+        d[eocd + 20] = 200;
+        let mut info = list_ok(&d);
+        assert_eq!((info.entry_count, cs(info.comment)), (1, String::new()));
+        iris_zip_free(&mut info);
+
+        // Empty archive: just the EOCD record
+        let mut info = list_ok(&archive(0, &[])[64..]);
+        assert_eq!((info.entry_count, info.declared_entries), (0, 0));
+        iris_zip_free(&mut info);
+    }
+
+    #[test]
+    fn zip64_records() {
+        let mut ext = EXTRA_ZIP64.to_le_bytes().to_vec();
+        ext.extend_from_slice(&24u16.to_le_bytes());
+        ext.extend_from_slice(&(6u64 << 32).to_le_bytes());
+        ext.extend_from_slice(&(5u64 << 32).to_le_bytes());
+        ext.extend_from_slice(&(7u64 << 32).to_le_bytes());
+        let mut h = central_header("huge.iso", 8, 0, 0o100644, &ext, 0xffff_ffff);
+        h[20..28].copy_from_slice(&[0xff; 8]);
+        let headers = [central_header("small", 0, 0, 0o100644, &[], 0), h];
+
+        let d = zip64_archive(&headers, 0);
+        let mut info = list_ok(&d);
+        assert!(info.zip64);
+        assert_eq!((info.entry_count, info.declared_entries, info.prefix_len), (2, 2, 0));
+        let e = unsafe { std::slice::from_raw_parts(info.entries, info.entry_count) };
+        assert_eq!((e[1].uncompressed_size, e[1].compressed_size, e[1].local_header_offset), (6 << 32, 5 << 32, 7 << 32));
+        iris_zip_free(&mut info);
+
+        // A wrong locator offset (prepended stub) falls back to the record before it
+        let mut info = list_ok(&zip64_archive(&headers, 1 << 40));
+        assert_eq!(info.entry_count, 2);
+        iris_zip_free(&mut info);
+
+        // Saturated EOCD fields without a locator, or a locator to nothing
+        let mut no_locator = d.clone();
+        let loc = d.len() - EOCD_SIZE - ZIP64_LOCATOR_SIZE;
+        no_locator[loc] = 0;
+        assert_eq!(list_err(&no_locator), -2);
+        let mut no_record = d.clone();
+        no_record[loc - ZIP64_EOCD_SIZE] = 0;
+        assert_eq!(list_err(&no_record), -2);
+        // The record itself cut off
+        assert_eq!(list_err(&d[loc - ZIP64_EOCD_SIZE + 40..]), -2);
+    }
+
+    #[test]
+    fn directory_outside_file() {
+        let headers = [central_header("a", 0, 0, 0o100644, &[], 0), central_header("b", 0, 0, 0o100644, &[], 0)];
+        let d = archive(0, &headers);
+        let eocd = d.len() - EOCD_SIZE - 5;
+        let mut far = d.clone();
+        far[eocd + 16..eocd + 20].copy_from_slice(&0x7fff_0000u32.to_le_bytes());
+        assert_eq!(list_err(&far), -1);
+        let mut big = d.clone();
+        big[eocd + 12..eocd + 16].copy_from_slice(&0x0100_0000u32.to_le_bytes()); // size past the start
+        big[eocd + 16..eocd + 20].copy_from_slice(&1u32.to_le_bytes());
+        assert_eq!(list_err(&big), -1);
+        let mut more = d.clone();
+        more[eocd + 10] = 3; // declares an entry past the directory
+        assert_eq!(list_err(&more), -1);
+        let mut name = d.clone();
+        name[64 + 28] = 0xff; // first name runs over the next header and the EOCD
+        name[64 + 29] = 0xff;
+        assert_eq!(list_err(&name), -1);
+    }
+
+    #[test]
+    fn stored_and_deflated_sizes() {
+        // Sizes are reported as recorded; a stored entry should have equal ones
+        let mut stored = central_header("stored.bin", 0, 0, 0o100644, &[], 0);
+        stored[20..24].copy_from_slice(&300u32.to_le_bytes());
+        let mut mismatched = central_header("lying.bin", 0, 0, 0o100644, &[], 0);
+        mismatched[24..28].copy_from_slice(&(1u32 << 30).to_le_bytes());
+        let deflated = central_header("deflated.bin", 8, 0, 0o100644, &[], 0);
+        let mut info = list_ok(&archive(0, &[stored, mismatched, deflated]));
+        let e = unsafe { std::slice::from_raw_parts(info.entries, info.entry_count) };
+        let sizes: Vec<_> = e.iter().map(|e| (e.method, e.compressed_size, e.uncompressed_size)).collect();
+        assert_eq!(sizes, [(0, 300, 300), (0, 100, 1 << 30), (8, 100, 300)]);
+        assert!(e[0].compressed_size == e[0].uncompressed_size && e[1].compressed_size != e[1].uncompressed_size);
+        iris_zip_free(&mut info);
+    }
+
+    #[test]
+    fn traversal_names() {
+        for bad in ["..", "../x", "a/../../b", "a/b/..", "/abs", "\\\\server\\share\\x", "c:relative", "Z:/x", "..\\x"] {
+            assert!(traversal(bad), "{bad}");
+        }
+        for ok in ["", ".", "./x", "a/b", "a..b", "...", "..a/b", "dir/"] {
+            assert!(!traversal(ok), "{ok}");
+        }
+        let names = ["ok/file", "../evil", "/etc/cron.d/x", "C:\\boot.ini"];
+        let headers: Vec<_> = names.iter().map(|n| central_header(n, 0, 0, 0o100644, &[], 0)).collect();
+        let mut info = list_ok(&archive(0, &headers));
+        let e = unsafe { std::slice::from_raw_parts(info.entries, info.entry_count) };
+        assert_eq!(e.iter().map(|e| e.path_traversal).collect::<Vec<_>>(), [false, true, true, true]);
+        assert_eq!(info.traversal_count, 3);
+        iris_zip_free(&mut info);
+    }
+}